@@ -0,0 +1,137 @@
+// Lights the textured vertex colors, with the normals of the instance's normal
+// map if it has one: by a fixed light, and by the clustered lights bound as set 1.
+// Built with BINDLESS defined for devices with descriptor indexing, which sample
+// an array of textures; the others sample the layers of one array image.
+#version 460
+#ifdef BINDLESS
+#extension GL_EXT_nonuniform_qualifier : require
+#endif
+
+layout(location = 0) in vec4 in_color;
+layout(location = 1) in vec2 in_uv;
+layout(location = 2) flat in uint in_texture;
+layout(location = 3) flat in uint in_normal_map;
+layout(location = 4) in vec2 in_position;
+
+layout(location = 0) out vec4 f_color;
+
+#ifdef BINDLESS
+layout(set = 0, binding = 0) uniform sampler2D textures[];
+
+vec4 sample_texture(uint texture_id, vec2 uv) {
+    return texture(textures[nonuniformEXT(texture_id)], uv);
+}
+#else
+layout(set = 0, binding = 0) uniform sampler2DArray textures;
+
+vec4 sample_texture(uint texture_id, vec2 uv) {
+    return texture(textures, vec3(uv, float(texture_id)));
+}
+#endif
+
+// Towards the light, which is up and to the left, in front of the screen: x
+// right, y down and z towards the viewer.
+const vec3 LIGHT = normalize(vec3(-0.5, -0.6, 0.6));
+const float AMBIENT = 0.25;
+
+#define MAX_LIGHTS 256
+#define CLUSTERS 16
+#define CLUSTER_COUNT 256
+#define MAX_LIGHTS_PER_CLUSTER 32
+
+// A point or spot light, in clip space. Point lights' cones take in every
+// direction; unused slots have a radius of 0.
+struct Light {
+    vec2 position;
+    vec2 direction;
+    vec4 color;
+    float radius;
+    float cos_outer;
+    float cos_inner;
+};
+
+// The lights whose radius reaches into a cluster of clip space.
+struct Cluster {
+    uint count;
+    uint lights[MAX_LIGHTS_PER_CLUSTER];
+};
+
+layout(set = 1, binding = 0) readonly buffer Lights {
+    Light lights[MAX_LIGHTS];
+};
+
+layout(set = 1, binding = 1) readonly buffer Clusters {
+    // Whether to show each cluster's light count instead of shading.
+    uint heatmap;
+    Cluster clusters[CLUSTER_COUNT];
+};
+
+// How high the lights hang in front of the screen, in clip space units.
+const float LIGHT_HEIGHT = 0.25;
+
+// The surface's normal, bent by the normal map's texel, which points up the
+// image in green. The scene's UVs are planar, so the tangent frame follows
+// from how they change across the screen, however the instance is turned,
+// stretched or mirrored.
+vec3 bend(vec3 texel) {
+    vec2 du = vec2(dFdx(in_uv.x), dFdy(in_uv.x));
+    vec2 dv = vec2(dFdx(in_uv.y), dFdy(in_uv.y));
+    float det = du.x*dv.y-du.y*dv.x;
+    if (det == 0.0) {
+        return vec3(0.0, 0.0, 1.0);
+    }
+    vec2 tangent = normalize(vec2(dv.y, -dv.x)*det);
+    vec2 bitangent = normalize(vec2(-du.y, du.x)*det);
+    vec3 normal = texel*2.0-1.0;
+    return normalize(vec3(tangent*normal.x-bitangent*normal.y, normal.z));
+}
+
+// The cluster the fragment is in.
+uint cluster() {
+    uvec2 id = uvec2(clamp((in_position*0.5+0.5)*CLUSTERS, vec2(0.0), vec2(CLUSTERS-1)));
+    return id.y*CLUSTERS+id.x;
+}
+
+// Blue for no lights through green to red for a full cluster.
+vec3 heat(uint count) {
+    float t = float(count)/float(MAX_LIGHTS_PER_CLUSTER);
+    return clamp(vec3(1.5-abs(4.0*t-3.0), 1.5-abs(4.0*t-2.0), 1.5-abs(4.0*t-1.0)), 0.0, 1.0);
+}
+
+// What the cluster's lights add, each fading out towards its radius and the
+// edge of its cone.
+vec3 point_lights(vec3 normal, uint cluster) {
+    vec3 sum = vec3(0.0);
+    for (uint i = 0; i < clusters[cluster].count; i++) {
+        Light light = lights[clusters[cluster].lights[i]];
+        vec2 offset = in_position-light.position;
+        float distance = length(offset);
+        if (distance >= light.radius) {
+            continue;
+        }
+        float falloff = 1.0-distance/light.radius;
+        float spot = smoothstep(light.cos_outer, light.cos_inner, dot(offset/max(distance, 1e-4), light.direction));
+        vec3 to_light = normalize(vec3(-offset, LIGHT_HEIGHT));
+        sum += light.color.rgb*max(dot(normal, to_light), 0.0)*falloff*falloff*spot;
+    }
+    return sum;
+}
+
+vec4 shade(vec4 albedo, vec3 normal) {
+    uint cluster = cluster();
+    if (heatmap != 0u) {
+        return vec4(heat(clusters[cluster].count), albedo.a);
+    }
+    float diffuse = max(dot(normal, LIGHT), 0.0);
+    vec3 light = AMBIENT+(1.0-AMBIENT)*diffuse+point_lights(normal, cluster);
+    return vec4(albedo.rgb*light, albedo.a);
+}
+
+void main() {
+    vec4 albedo = in_color*sample_texture(in_texture, in_uv);
+    vec3 normal = vec3(0.0, 0.0, 1.0);
+    if (in_normal_map != 0u) {
+        normal = bend(sample_texture(in_normal_map, in_uv).rgb);
+    }
+    f_color = shade(albedo, normal);
+}
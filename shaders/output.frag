@@ -0,0 +1,99 @@
+// The output pass: grades the scene with the color LUT, if there is one, then
+// encodes it for the swapchain's color space. Built with SAMPLED defined for
+// dynamic rendering.
+#version 460
+
+#ifdef SAMPLED
+// Dynamic rendering has no input attachments, so the scene is a sampled image.
+layout(set = 0, binding = 0) uniform sampler2D scene;
+#else
+layout(input_attachment_index = 0, set = 0, binding = 0) uniform subpassInput scene;
+#endif
+
+layout(location = 0) out vec4 f_color;
+
+layout(push_constant) uniform OutputParams {
+    uint transfer; // 0: unchanged, 1: HDR10 PQ, 2: scRGB linear
+    float paper_white;
+    uint premultiply; // the compositor expects pre-multiplied alpha
+    uint grade; // look the color up in the LUT
+    float dither_step; // one code of the output, 0 to not dither
+    uint srgb_format; // the output encodes to sRGB after the shader
+} params;
+
+// Maps sRGB-encoded colors to graded ones.
+layout(set = 0, binding = 1) uniform sampler3D lut;
+
+// A tile of blue noise.
+layout(set = 0, binding = 2) uniform sampler2D dither_noise;
+
+vec3 srgb_to_linear(vec3 c) {
+    return mix(c/12.92, pow((c+0.055)/1.055, vec3(2.4)), greaterThan(c, vec3(0.04045)));
+}
+
+vec3 linear_to_srgb(vec3 c) {
+    return mix(c*12.92, 1.055*pow(c, vec3(1.0/2.4))-0.055, greaterThan(c, vec3(0.0031308)));
+}
+
+// Offsets the color by up to half a code either way, by the noise at the
+// pixel, so it rounds up or down in proportion to how near it is to each.
+vec3 dither(vec3 c) {
+    ivec2 texel = ivec2(gl_FragCoord.xy)%textureSize(dither_noise, 0);
+    float offset = (texelFetch(dither_noise, texel, 0).r-0.5)*params.dither_step;
+    if (params.srgb_format != 0u) {
+        return srgb_to_linear(max(linear_to_srgb(max(c, 0.0))+offset, 0.0));
+    }
+    return c+offset;
+}
+
+vec3 pq_oetf(vec3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 y = pow(clamp(nits/10000.0, 0.0, 1.0), vec3(m1));
+    return pow((c1+c2*y)/(1.0+c3*y), vec3(m2));
+}
+
+// The texel centers of the LUT's edges are at 0 and 1.
+vec3 grade(vec3 c) {
+    float size = float(textureSize(lut, 0).x);
+    return texture(lut, clamp(c, 0.0, 1.0)*(size-1.0)/size+0.5/size).rgb;
+}
+
+void main() {
+#ifdef SAMPLED
+    vec4 color = texelFetch(scene, ivec2(gl_FragCoord.xy), 0);
+#else
+    vec4 color = subpassLoad(scene);
+#endif
+    if (params.grade != 0u) {
+        color.rgb = grade(color.rgb);
+    }
+    if (params.transfer == 0u) {
+        f_color = color;
+        // The scene is sRGB-encoded already, so it's decoded for the hardware to
+        // encode it back instead of encoding it twice.
+        if (params.srgb_format != 0u) {
+            f_color.rgb = srgb_to_linear(max(color.rgb, 0.0));
+        }
+    } else {
+        vec3 linear = srgb_to_linear(max(color.rgb, 0.0))*params.paper_white;
+        if (params.transfer == 1u) {
+            const mat3 bt709_to_bt2020 = mat3(
+                0.6274, 0.0691, 0.0164,
+                0.3293, 0.9195, 0.0880,
+                0.0433, 0.0114, 0.8956);
+            f_color = vec4(pq_oetf(bt709_to_bt2020*linear), color.a);
+        } else {
+            f_color = vec4(linear/80.0, color.a);
+        }
+    }
+    if (params.premultiply != 0u) {
+        f_color.rgb *= f_color.a;
+    }
+    if (params.dither_step > 0.0) {
+        f_color.rgb = dither(f_color.rgb);
+    }
+}
@@ -0,0 +1,236 @@
+use crate::memory::{AllocationPurpose, MemoryStats};
+use bytemuck::Pod;
+use std::{
+    mem::{size_of, size_of_val},
+    sync::Arc,
+};
+use vulkano::{
+    buffer::{
+        BufferAccess, BufferContents, BufferSlice, BufferUsage, CpuAccessibleBuffer,
+        DeviceLocalBuffer, TypedBufferAccess,
+    },
+    command_buffer::{AutoCommandBufferBuilder, BufferCopy, CopyBufferInfo},
+    device::Device,
+    memory::DeviceMemoryAllocationError,
+    DeviceSize,
+};
+
+pub type FrameChunk<T> = Arc<BufferSlice<[T], DeviceLocalBuffer<[T]>>>;
+
+/// Host-visible memory a frame's dynamic data is written to, for the frame's command
+/// buffer to copy into [`FrameRing`]s. There is one region per frame in flight, which
+/// stays mapped for the ring's lifetime; a frame fills its region front to back, and
+/// the ring wraps around to it again once the frame's fence was waited for. vulkano
+/// refuses the write while the GPU may still read the region. A region that fills up
+/// is replaced by one twice as big.
+pub struct StagingRing {
+    device: Arc<Device>,
+    regions: Vec<Arc<CpuAccessibleBuffer<[u8]>>>,
+    frame_index: usize,
+    /// Frames begun so far; tells frames apart when only one is in flight.
+    frame_number: u64,
+    /// Bytes of the current frame's region in use.
+    head: DeviceSize,
+}
+
+impl StagingRing {
+    /// `per_frame_capacity` is in bytes.
+    pub fn new(
+        device: Arc<Device>,
+        frames_in_flight: usize,
+        per_frame_capacity: DeviceSize,
+        memory_stats: &mut MemoryStats,
+    ) -> Result<Self, DeviceMemoryAllocationError> {
+        let regions = (0..frames_in_flight)
+            .map(|_| create_region(&device, per_frame_capacity, memory_stats))
+            .collect::<Result<_, _>>()?;
+        Ok(StagingRing {
+            device,
+            regions,
+            frame_index: 0,
+            frame_number: 0,
+            head: 0,
+        })
+    }
+
+    /// Starts writing to the region of frame `frame_index`, whose fence has to have
+    /// been waited for.
+    pub fn begin_frame(&mut self, frame_index: usize) {
+        self.frame_index = frame_index;
+        self.frame_number += 1;
+        self.head = 0;
+    }
+
+    /// Copies `data` into the current frame's region, returning the region and the
+    /// offset it starts at.
+    fn stage<T: Pod>(
+        &mut self,
+        data: &[T],
+        memory_stats: &mut MemoryStats,
+    ) -> (Arc<CpuAccessibleBuffer<[u8]>>, DeviceSize) {
+        let size = size_of_val(data) as DeviceSize;
+        let region = &mut self.regions[self.frame_index];
+        if self.head + size > region.size() {
+            // Copies recorded so far keep the old region alive until the frame is done.
+            let capacity = (2 * region.size()).max(size);
+            memory_stats.untrack(AllocationPurpose::Staging, region.size());
+            *region = create_region(&self.device, capacity, memory_stats).unwrap();
+            self.head = 0;
+        }
+        let offset = self.head;
+        region.write().unwrap()[offset as usize..(offset + size) as usize]
+            .copy_from_slice(bytemuck::cast_slice(data));
+        self.head += size;
+        (region.clone(), offset)
+    }
+}
+
+fn create_region(
+    device: &Arc<Device>,
+    capacity: DeviceSize,
+    memory_stats: &mut MemoryStats,
+) -> Result<Arc<CpuAccessibleBuffer<[u8]>>, DeviceMemoryAllocationError> {
+    let capacity = capacity.max(1);
+    // Safe: only the bytes a frame writes are ever copied from.
+    let region = unsafe {
+        CpuAccessibleBuffer::uninitialized_array(
+            device.clone(),
+            capacity,
+            BufferUsage::transfer_src(),
+            false,
+        )
+    }?;
+    memory_stats.track(AllocationPurpose::Staging, capacity);
+    Ok(region)
+}
+
+/// Device-local buffers for data rewritten every frame, one per frame in flight.
+/// Each frame's data goes through the [`StagingRing`] and is copied into chunks
+/// sub-allocated from the frame's buffer, which is reused once the ring comes back
+/// around to the frame. A buffer that fills up is replaced by one twice as big.
+pub struct FrameRing<T>
+where
+    [T]: BufferContents,
+{
+    device: Arc<Device>,
+    usage: BufferUsage,
+    purpose: AllocationPurpose,
+    buffers: Vec<Arc<DeviceLocalBuffer<[T]>>>,
+    /// [`StagingRing`] frame the chunks at the front of the buffer belong to.
+    frame_number: u64,
+    /// Elements of the current frame's buffer in use.
+    head: DeviceSize,
+}
+
+impl<T> FrameRing<T>
+where
+    T: Pod + Send + Sync,
+    [T]: BufferContents,
+{
+    /// `per_frame_capacity` is in elements of `T`.
+    pub fn new(
+        device: Arc<Device>,
+        usage: BufferUsage,
+        purpose: AllocationPurpose,
+        frames_in_flight: usize,
+        per_frame_capacity: usize,
+        memory_stats: &mut MemoryStats,
+    ) -> Result<Self, DeviceMemoryAllocationError> {
+        let usage = BufferUsage {
+            transfer_dst: true,
+            ..usage
+        };
+        let buffers = (0..frames_in_flight)
+            .map(|_| {
+                create_buffer(
+                    &device,
+                    usage,
+                    purpose,
+                    per_frame_capacity as DeviceSize,
+                    memory_stats,
+                )
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(FrameRing {
+            device,
+            usage,
+            purpose,
+            buffers,
+            frame_number: 0,
+            head: 0,
+        })
+    }
+
+    /// Stages `data` and records its copy into the next free chunk of the current
+    /// frame's buffer. Has to be recorded before the render pass that reads it.
+    pub fn upload<L, P>(
+        &mut self,
+        data: &[T],
+        staging: &mut StagingRing,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        memory_stats: &mut MemoryStats,
+    ) -> FrameChunk<T> {
+        if self.frame_number != staging.frame_number {
+            self.frame_number = staging.frame_number;
+            self.head = 0;
+        }
+        let len = data.len() as DeviceSize;
+        let buffer = &mut self.buffers[staging.frame_index];
+        if self.head + len > buffer.len() {
+            let capacity = (2 * buffer.len()).max(len);
+            memory_stats.untrack(self.purpose, buffer.size());
+            *buffer = create_buffer(
+                &self.device,
+                self.usage,
+                self.purpose,
+                capacity,
+                memory_stats,
+            )
+            .unwrap();
+            self.head = 0;
+        }
+        let chunk = buffer.slice(self.head..self.head + len).unwrap();
+        self.head += len;
+
+        if !data.is_empty() {
+            let (region, offset) = staging.stage(data, memory_stats);
+            builder
+                .copy_buffer(CopyBufferInfo {
+                    regions: [BufferCopy {
+                        src_offset: offset,
+                        dst_offset: 0,
+                        size: chunk.size(),
+                        ..Default::default()
+                    }]
+                    .into(),
+                    ..CopyBufferInfo::buffers(region, chunk.clone())
+                })
+                .unwrap();
+        }
+        chunk
+    }
+
+    /// Untracks every buffer, for when the ring is dropped.
+    pub fn untrack_memory(&self, memory_stats: &mut MemoryStats) {
+        for buffer in &self.buffers {
+            memory_stats.untrack(self.purpose, buffer.size());
+        }
+    }
+}
+
+fn create_buffer<T>(
+    device: &Arc<Device>,
+    usage: BufferUsage,
+    purpose: AllocationPurpose,
+    capacity: DeviceSize,
+    memory_stats: &mut MemoryStats,
+) -> Result<Arc<DeviceLocalBuffer<[T]>>, DeviceMemoryAllocationError>
+where
+    T: Send + Sync,
+    [T]: BufferContents,
+{
+    let capacity = capacity.max(1);
+    let buffer = DeviceLocalBuffer::array(device.clone(), capacity, usage, [])?;
+    memory_stats.track(purpose, capacity * size_of::<T>() as DeviceSize);
+    Ok(buffer)
+}
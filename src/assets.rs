@@ -0,0 +1,711 @@
+use crate::{
+    lut::ColorLut,
+    renderer::{Mesh, Renderer, TextureId, Vertex},
+    texture::TextureImage,
+};
+use gltf::{buffer, mesh::Mode, Gltf};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver},
+        Arc,
+    },
+};
+use tracing::{info, warn};
+use vulkano::{device::Device, shader::ShaderModule};
+use winit::event_loop::EventLoopProxy;
+
+/// What a file dropped onto the window is loaded as, decided by its extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AssetKind {
+    Mesh,
+    FragmentShader,
+    Image,
+    ColorLut,
+}
+
+impl AssetKind {
+    fn from_path(path: &Path) -> Option<Self> {
+        match Self::extension(path)?.as_str() {
+            "obj" | "gltf" | "glb" => Some(AssetKind::Mesh),
+            "frag" => Some(AssetKind::FragmentShader),
+            "png" | "jpg" | "jpeg" => Some(AssetKind::Image),
+            "cube" => Some(AssetKind::ColorLut),
+            _ => None,
+        }
+    }
+
+    fn extension(path: &Path) -> Option<String> {
+        Some(path.extension()?.to_str()?.to_lowercase())
+    }
+}
+
+/// The files the textures given on the command line were loaded from, so that an
+/// image replaces the right one.
+pub struct TextureFiles {
+    /// Canonical, in the order of their [`TextureId`]s from 1.
+    paths: Vec<PathBuf>,
+    /// How many of `paths` are the instances' textures; the rest are normal maps.
+    colors: usize,
+}
+
+impl TextureFiles {
+    /// `colors` are the instances' textures, followed by `normal_maps`.
+    pub fn new<'a>(
+        colors: impl IntoIterator<Item = &'a PathBuf>,
+        normal_maps: impl IntoIterator<Item = &'a PathBuf>,
+    ) -> Self {
+        let canonical = |path: &PathBuf| fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+        let mut paths: Vec<PathBuf> = colors.into_iter().map(canonical).collect();
+        let colors = paths.len();
+        paths.extend(normal_maps.into_iter().map(canonical));
+        TextureFiles { paths, colors }
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// The texture `path` was loaded into.
+    fn find(&self, path: &Path) -> Option<TextureId> {
+        let path = fs::canonicalize(path).ok()?;
+        let index = self.paths.iter().position(|known| *known == path)?;
+        Some(TextureId(index as u32 + 1))
+    }
+
+    /// Makes `path` the file of the first of the instances' textures, returning it,
+    /// or `None` if they have none.
+    fn replace_first(&mut self, path: &Path) -> Option<TextureId> {
+        if self.colors == 0 {
+            return None;
+        }
+        self.paths[0] = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        Some(TextureId(1))
+    }
+}
+
+/// Loads a dropped or changed file into the renderer. Failures are logged and leave
+/// the current asset in place. A file shaders included reloads those shaders.
+pub fn load_file(
+    path: &Path,
+    renderer: &mut Renderer,
+    shaders: &mut ShaderLoader,
+    textures: &mut TextureFiles,
+) {
+    let users = shaders.users(path);
+    for user in &users {
+        load_file(user, renderer, shaders, textures);
+    }
+
+    if shaders.is_shadertoy(path) {
+        match shaders
+            .load_shadertoy(renderer.device(), path)
+            .and_then(|module| renderer.set_shadertoy_shader(module))
+        {
+            Ok(()) => info!(path = %path.display(), "loaded ShaderToy shader"),
+            Err(message) => {
+                warn!(path = %path.display(), %message, "failed to load ShaderToy shader")
+            }
+        }
+        return;
+    }
+
+    let kind = match AssetKind::from_path(path) {
+        Some(kind) => kind,
+        None => {
+            if users.is_empty() {
+                warn!(path = %path.display(), "don't know how to load file");
+            }
+            return;
+        }
+    };
+
+    let result = match kind {
+        AssetKind::Mesh => {
+            // Parsed and uploaded on the renderer's upload thread, which logs the result.
+            let owned_path = path.to_owned();
+            renderer.load_mesh(path.display().to_string(), move || load_mesh(&owned_path));
+            info!(path = %path.display(), "loading mesh in the background");
+            return;
+        }
+        AssetKind::FragmentShader => shaders
+            .load_fragment_shader(renderer.device(), path)
+            .and_then(|module| renderer.set_fragment_shader(module)),
+        // The file of a texture replaces it, an image shaped like a LUT strip grades
+        // the scene, and any other replaces the instances' first texture.
+        AssetKind::Image => TextureImage::load(path).and_then(|image| {
+            if let Some(texture) = textures.find(path) {
+                return renderer.set_texture(texture, image);
+            }
+            if ColorLut::is_strip(&image) {
+                let lut = ColorLut::from_strip(&image)?;
+                renderer.set_color_lut(Some(&lut));
+                return Ok(());
+            }
+            let texture = textures
+                .replace_first(path)
+                .ok_or("the instances aren't textured; start with --texture")?;
+            renderer.set_texture(texture, image)
+        }),
+        AssetKind::ColorLut => ColorLut::load(path).map(|lut| renderer.set_color_lut(Some(&lut))),
+    };
+
+    match result {
+        Ok(()) => info!(path = %path.display(), ?kind, "loaded file"),
+        Err(message) => {
+            warn!(path = %path.display(), ?kind, %message, "failed to load file")
+        }
+    }
+}
+
+/// Watches files and directories for `--watch`. Change notifications arrive on
+/// notify's thread, are queued on a channel and wake the event loop with a user
+/// event, so the main thread can reload them. Editors often save in several writes,
+/// so a reload may see a half-written file; it fails, and the next write retries.
+pub struct AssetWatcher {
+    watcher: RecommendedWatcher,
+    changes: Receiver<PathBuf>,
+    files: HashSet<PathBuf>,
+    /// Watched for `files`; editors that save by replacing the file would otherwise
+    /// end a watch on the file itself.
+    parents: HashSet<PathBuf>,
+    directories: Vec<PathBuf>,
+}
+
+impl AssetWatcher {
+    pub fn new(event_loop: EventLoopProxy<()>) -> Result<Self, String> {
+        let (sender, changes) = mpsc::channel();
+        let watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+            match result {
+                Ok(event) => {
+                    if let notify::EventKind::Create(_) | notify::EventKind::Modify(_) = event.kind
+                    {
+                        for path in event.paths {
+                            let _ = sender.send(path);
+                        }
+                        // Fails only once the event loop is gone.
+                        let _ = event_loop.send_event(());
+                    }
+                }
+                Err(e) => warn!(error = %e, "file watcher failed"),
+            }
+        })
+        .map_err(|e| e.to_string())?;
+        Ok(AssetWatcher {
+            watcher,
+            changes,
+            files: HashSet::new(),
+            parents: HashSet::new(),
+            directories: Vec::new(),
+        })
+    }
+
+    /// Reloads `path` when it changes, or every file in it if it's a directory.
+    pub fn watch(&mut self, path: &Path) -> Result<(), String> {
+        let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+        let path = fs::canonicalize(path).map_err(|e| error(&e))?;
+        if path.is_dir() {
+            self.watcher
+                .watch(&path, RecursiveMode::Recursive)
+                .map_err(|e| error(&e))?;
+            self.directories.push(path);
+        } else {
+            let parent = path.parent().ok_or_else(|| error(&"not a file"))?;
+            if !self.parents.contains(parent) {
+                self.watcher
+                    .watch(parent, RecursiveMode::NonRecursive)
+                    .map_err(|e| error(&e))?;
+                self.parents.insert(parent.to_owned());
+            }
+            self.files.insert(path);
+        }
+        Ok(())
+    }
+
+    /// Watched files that changed since the last call, each once.
+    pub fn changed_files(&self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for path in self.changes.try_iter() {
+            let watched = self.files.contains(&path)
+                || self
+                    .directories
+                    .iter()
+                    .any(|directory| path.starts_with(directory));
+            if watched && path.is_file() && !changed.contains(&path) {
+                changed.push(path);
+            }
+        }
+        changed
+    }
+}
+
+fn load_mesh(path: &Path) -> Result<Mesh, String> {
+    match AssetKind::extension(path).as_deref() {
+        Some("obj") => parse_obj(&fs::read_to_string(path).map_err(|e| e.to_string())?),
+        Some("gltf" | "glb") => {
+            let gltf = Gltf::open(path).map_err(|e| e.to_string())?;
+            parse_gltf(gltf, path.parent().unwrap_or_else(|| Path::new("")))
+        }
+        _ => Err("only Wavefront .obj and glTF meshes are supported".to_owned()),
+    }
+}
+
+/// Triangulates the faces of an .obj file into an indexed triangle list, using `x`
+/// and `y` of each vertex and the optional `v x y z r g b` color extension. The mesh
+/// is scaled to fit the default triangle's extent.
+fn parse_obj(source: &str) -> Result<Mesh, String> {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+
+    for (line_number, line) in source.lines().enumerate() {
+        let error = |message: &str| format!("line {}: {}", line_number + 1, message);
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("v") => {
+                let values = fields
+                    .map(|field| field.parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| error("invalid vertex"))?;
+                if values.len() < 3 {
+                    return Err(error("vertex needs x, y and z"));
+                }
+                let color = match values.get(3..6) {
+                    Some(rgb) => [rgb[0], rgb[1], rgb[2], 1.0],
+                    None => [1.0, 1.0, 1.0, 1.0],
+                };
+                // .obj is y-up, Vulkan clip space is y-down.
+                vertices.push(Vertex {
+                    position: [values[0], -values[1]],
+                    color,
+                });
+            }
+            Some("f") => {
+                let corners = fields
+                    .map(|field| {
+                        let index: i64 = field
+                            .split('/')
+                            .next()
+                            .and_then(|index| index.parse().ok())
+                            .ok_or_else(|| error("invalid face index"))?;
+                        let index = if index < 0 {
+                            vertices.len() as i64 + index
+                        } else {
+                            index - 1
+                        };
+                        if (0..vertices.len() as i64).contains(&index) {
+                            Ok(index as u32)
+                        } else {
+                            Err(error("face index out of range"))
+                        }
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if corners.len() < 3 {
+                    return Err(error("face needs at least three vertices"));
+                }
+                for i in 1..corners.len() - 1 {
+                    indices.extend([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if indices.is_empty() {
+        return Err("no faces".to_owned());
+    }
+    fit_to_extent(&mut vertices, &indices);
+    Ok(Mesh::new(vertices, indices))
+}
+
+/// Flattens the triangles of a glTF file's default scene into an indexed triangle
+/// list, with the nodes' transforms applied, using `x` and `y` of each position and
+/// the first vertex colors. The mesh is scaled like an .obj one. External buffers
+/// are read relative to `directory`.
+fn parse_gltf(mut gltf: Gltf, directory: &Path) -> Result<Mesh, String> {
+    let mut blob = gltf.blob.take();
+    let buffers = gltf
+        .buffers()
+        .map(|buffer| {
+            let data = match buffer.source() {
+                buffer::Source::Bin => blob.take().ok_or("missing binary chunk")?,
+                buffer::Source::Uri(uri) => match uri.strip_prefix("data:") {
+                    Some(data) => {
+                        let (_, encoded) = data
+                            .split_once(";base64,")
+                            .ok_or("only base64 data URIs are supported")?;
+                        base64::decode(encoded).map_err(|e| e.to_string())?
+                    }
+                    None => fs::read(directory.join(uri)).map_err(|e| format!("{}: {}", uri, e))?,
+                },
+            };
+            if data.len() < buffer.length() {
+                return Err(format!("buffer {} is truncated", buffer.index()));
+            }
+            Ok(data)
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let scene = gltf
+        .default_scene()
+        .or_else(|| gltf.scenes().next())
+        .ok_or("no scene")?;
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let mut nodes: Vec<_> = scene.nodes().map(|node| (node, IDENTITY)).collect();
+    while let Some((node, parent)) = nodes.pop() {
+        let transform = multiply(&parent, &node.transform().matrix());
+        nodes.extend(node.children().map(|child| (child, transform)));
+        let primitives = node.mesh().into_iter().flat_map(|mesh| mesh.primitives());
+        for primitive in primitives.filter(|primitive| primitive.mode() == Mode::Triangles) {
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+            let colors: Vec<_> = reader
+                .read_colors(0)
+                .map(|colors| colors.into_rgba_f32().collect())
+                .unwrap_or_default();
+            let first = vertices.len() as u32;
+            let positions = reader
+                .read_positions()
+                .ok_or("primitive without positions")?;
+            for (i, [x, y, z]) in positions.enumerate() {
+                let position = [0, 1].map(|row| {
+                    transform[0][row] * x
+                        + transform[1][row] * y
+                        + transform[2][row] * z
+                        + transform[3][row]
+                });
+                // glTF colors are linear, the scene's are sRGB encoded.
+                let color = colors.get(i).map_or([1.0; 4], |&[r, g, b, a]| {
+                    [encode_srgb(r), encode_srgb(g), encode_srgb(b), a]
+                });
+                // glTF is y-up, Vulkan clip space is y-down.
+                vertices.push(Vertex {
+                    position: [position[0], -position[1]],
+                    color,
+                });
+            }
+            let count = vertices.len() as u32 - first;
+            match reader.read_indices() {
+                Some(read) => {
+                    for index in read.into_u32() {
+                        if index >= count {
+                            return Err("vertex index out of range".to_owned());
+                        }
+                        indices.push(first + index);
+                    }
+                }
+                None => indices.extend(first..first + count),
+            }
+        }
+    }
+
+    if indices.is_empty() {
+        return Err("no triangles".to_owned());
+    }
+    fit_to_extent(&mut vertices, &indices);
+    Ok(Mesh::new(vertices, indices))
+}
+
+/// Column-major, like glTF's matrices.
+const IDENTITY: [[f32; 4]; 4] = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+fn multiply(a: &[[f32; 4]; 4], b: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut product = [[0.0; 4]; 4];
+    for (column, b_column) in product.iter_mut().zip(b) {
+        for (row, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b_column[k]).sum();
+        }
+    }
+    product
+}
+
+fn encode_srgb(linear: f32) -> f32 {
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Centers the vertices `indices` uses on the origin and scales them to fit the
+/// default triangle's extent.
+fn fit_to_extent(vertices: &mut [Vertex], indices: &[u32]) {
+    // Vertices no face uses don't count towards the extent.
+    let (mut min, mut max) = ([f32::MAX; 2], [f32::MIN; 2]);
+    for &index in indices {
+        let vertex = &vertices[index as usize];
+        for axis in 0..2 {
+            min[axis] = min[axis].min(vertex.position[axis]);
+            max[axis] = max[axis].max(vertex.position[axis]);
+        }
+    }
+    let center = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0];
+    let extent = (max[0] - min[0]).max(max[1] - min[1]).max(f32::EPSILON);
+    for vertex in vertices {
+        for (position, center) in vertex.position.iter_mut().zip(center) {
+            *position = (*position - center) / extent;
+        }
+    }
+}
+
+/// Compiles shaders loaded from files. `#include "file"` and `#include <file>` are
+/// both resolved relative to the including file, and the `--define` macros are
+/// defined in every shader. Which files each shader included is kept, so that editing
+/// one reloads all the shaders using it.
+pub struct ShaderLoader {
+    defines: Vec<(String, Option<String>)>,
+    /// Files each shader included when it was last compiled, by the shader's
+    /// canonical path.
+    includes: HashMap<PathBuf, HashSet<PathBuf>>,
+    /// Canonical path of the last shader loaded with [`Self::load_shadertoy`].
+    shadertoy: Option<PathBuf>,
+}
+
+impl ShaderLoader {
+    pub fn new(defines: Vec<(String, Option<String>)>) -> Self {
+        ShaderLoader {
+            defines,
+            includes: HashMap::new(),
+            shadertoy: None,
+        }
+    }
+
+    /// Every file the loaded shaders included.
+    pub fn included_files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.includes.values().flatten()
+    }
+
+    /// Shaders that included `path`.
+    fn users(&self, path: &Path) -> Vec<PathBuf> {
+        let path = match fs::canonicalize(path) {
+            Ok(path) => path,
+            Err(_) => return Vec::new(),
+        };
+        self.includes
+            .iter()
+            .filter(|(_, includes)| includes.contains(&path))
+            .map(|(shader, _)| shader.clone())
+            .collect()
+    }
+
+    fn is_shadertoy(&self, path: &Path) -> bool {
+        self.shadertoy.is_some() && fs::canonicalize(path).ok() == self.shadertoy
+    }
+
+    /// Compiles a GLSL fragment shader. It has to read the vertex color at location 0
+    /// and write the output color at location 0, like the built-in one.
+    fn load_fragment_shader(
+        &mut self,
+        device: &Arc<Device>,
+        path: &Path,
+    ) -> Result<Arc<ShaderModule>, String> {
+        self.compile(device, path, |source| source)
+    }
+
+    /// Compiles a shader written for ShaderToy: the file defines `mainImage` and
+    /// reads the `iTime`, `iTimeDelta`, `iFrame`, `iResolution` and `iMouse`
+    /// uniforms, which are declared around it. `iChannel` inputs aren't provided.
+    pub fn load_shadertoy(
+        &mut self,
+        device: &Arc<Device>,
+        path: &Path,
+    ) -> Result<Arc<ShaderModule>, String> {
+        let module = self.compile(device, path, |source| {
+            format!(
+                "{}\n#line 1\n{}\n{}",
+                SHADERTOY_PREAMBLE, source, SHADERTOY_MAIN
+            )
+        })?;
+        self.shadertoy = fs::canonicalize(path).ok();
+        Ok(module)
+    }
+
+    /// Compiles the fragment shader in `path`, after passing its source through `wrap`.
+    fn compile(
+        &mut self,
+        device: &Arc<Device>,
+        path: &Path,
+        wrap: impl FnOnce(String) -> String,
+    ) -> Result<Arc<ShaderModule>, String> {
+        let path = fs::canonicalize(path).map_err(|e| e.to_string())?;
+        let source = wrap(fs::read_to_string(&path).map_err(|e| e.to_string())?);
+        let compiler = shaderc::Compiler::new().ok_or("failed to create the shader compiler")?;
+        let included = RefCell::new(HashSet::new());
+        let mut options =
+            shaderc::CompileOptions::new().ok_or("failed to create the compile options")?;
+        for (name, value) in &self.defines {
+            options.add_macro_definition(name, value.as_deref());
+        }
+        options.set_include_callback(|requested, _, requesting, _| {
+            let resolved = Path::new(requesting)
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .join(requested);
+            let resolved = fs::canonicalize(&resolved)
+                .map_err(|e| format!("{}: {}", resolved.display(), e))?;
+            let content = fs::read_to_string(&resolved)
+                .map_err(|e| format!("{}: {}", resolved.display(), e))?;
+            included.borrow_mut().insert(resolved.clone());
+            Ok(shaderc::ResolvedInclude {
+                resolved_name: resolved.display().to_string(),
+                content,
+            })
+        });
+
+        let result = compiler.compile_into_spirv(
+            &source,
+            shaderc::ShaderKind::Fragment,
+            &path.display().to_string(),
+            "main",
+            Some(&options),
+        );
+        // Kept even when compiling fails, so fixing an included file retries.
+        drop(options);
+        self.includes.insert(path, included.into_inner());
+        let artifact = result.map_err(|e| e.to_string())?;
+        if artifact.get_num_warnings() > 0 {
+            warn!(warnings = %artifact.get_warning_messages(), "shader compiled with warnings");
+        }
+
+        unsafe { ShaderModule::from_words(device.clone(), artifact.as_binary()) }
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Declares what ShaderToy provides. The layout matches `ShaderToyInputs` in the
+/// renderer, which pushes them.
+const SHADERTOY_PREAMBLE: &str = "#version 460
+layout(location = 0) out vec4 shadertoy_color;
+layout(push_constant) uniform ShaderToyInputs {
+    vec4 iMouse;
+    vec3 iResolution;
+    float iTime;
+    float iTimeDelta;
+    int iFrame;
+};";
+
+/// Calls `mainImage` with ShaderToy's pixel coordinates, whose origin is the bottom
+/// left.
+const SHADERTOY_MAIN: &str = "void main() {
+    mainImage(shadertoy_color, vec2(gl_FragCoord.x, iResolution.y-gl_FragCoord.y));
+    shadertoy_color.a = 1.0;
+}";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The indices of a mesh small enough to store them as `u16`.
+    fn indices(mesh: &Mesh) -> Vec<u16> {
+        bytemuck::cast_slice(mesh.indices.bytes()).to_vec()
+    }
+
+    #[test]
+    fn obj_faces_are_triangulated_as_fans() {
+        let mesh = parse_obj(
+            "# quad and a triangle\n\
+             v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0 1 0 0\n\
+             f 1/1/1 2/2/2 3/3/3 4/4/4\n\
+             f -4 -3 -1\n",
+        )
+        .unwrap();
+        assert_eq!(indices(&mesh), [0, 1, 2, 0, 2, 3, 0, 1, 3]);
+        assert_eq!(mesh.vertices[3].color, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(mesh.vertices[0].color, [1.0; 4]);
+        // Centered and flipped to y-down.
+        assert_eq!(mesh.vertices[0].position, [-0.5, 0.5]);
+        assert_eq!(mesh.vertices[2].position, [0.5, -0.5]);
+    }
+
+    #[test]
+    fn obj_face_errors_name_the_line() {
+        let vertices = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+        assert_eq!(
+            parse_obj(&format!("{}f 1 2 4\n", vertices)).unwrap_err(),
+            "line 4: face index out of range"
+        );
+        assert_eq!(
+            parse_obj(&format!("{}f 1 2 -4\n", vertices)).unwrap_err(),
+            "line 4: face index out of range"
+        );
+        assert_eq!(
+            parse_obj(&format!("{}f 1 2\n", vertices)).unwrap_err(),
+            "line 4: face needs at least three vertices"
+        );
+        assert_eq!(
+            parse_obj(&format!("{}f 1 x 3\n", vertices)).unwrap_err(),
+            "line 4: invalid face index"
+        );
+        assert_eq!(parse_obj(vertices).unwrap_err(), "no faces");
+    }
+
+    /// A glTF file with one triangle, drawn by `nodes`, in a data URI.
+    fn gltf(nodes: &str, scene_nodes: &str) -> Gltf {
+        let positions: [f32; 9] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let json = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": {} }}],
+                "nodes": {},
+                "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }} }}] }}],
+                "buffers": [{{
+                    "byteLength": 36,
+                    "uri": "data:application/octet-stream;base64,{}"
+                }}],
+                "bufferViews": [{{ "buffer": 0, "byteLength": 36 }}],
+                "accessors": [{{
+                    "bufferView": 0,
+                    "componentType": 5126,
+                    "count": 3,
+                    "type": "VEC3",
+                    "min": [0, 0, 0],
+                    "max": [1, 1, 0]
+                }}]
+            }}"#,
+            scene_nodes,
+            nodes,
+            base64::encode(bytemuck::cast_slice(&positions))
+        );
+        Gltf::from_slice(json.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn gltf_nodes_are_flattened_with_their_transforms() {
+        let mesh = parse_gltf(
+            gltf(
+                r#"[
+                    { "mesh": 0 },
+                    { "translation": [2, 0, 0], "children": [2] },
+                    { "mesh": 0, "translation": [0, 1, 0] }
+                ]"#,
+                "[0, 1]",
+            ),
+            Path::new(""),
+        )
+        .unwrap();
+        assert_eq!(mesh.vertices.len(), 6);
+        assert_eq!(indices(&mesh).len(), 6);
+        // Both translations apply to the child's copy, y flipped and scaled by the
+        // extent of 3.
+        let [a, b] = [mesh.vertices[0].position, mesh.vertices[3].position];
+        let offset = [(a[0] - b[0]).abs(), (a[1] - b[1]).abs()];
+        assert!((offset[0] - 2.0 / 3.0).abs() < 1e-6, "{:?}", offset);
+        assert!((offset[1] - 1.0 / 3.0).abs() < 1e-6, "{:?}", offset);
+        assert!(mesh.vertices.iter().all(|vertex| vertex.color == [1.0; 4]));
+    }
+
+    #[test]
+    fn gltf_without_triangles_is_rejected() {
+        let gltf = gltf(r#"[{ "translation": [1, 0, 0] }]"#, "[0]");
+        assert_eq!(parse_gltf(gltf, Path::new("")).unwrap_err(), "no triangles");
+    }
+}
@@ -0,0 +1,188 @@
+use crate::renderer::AUDIO_BANDS;
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    Device, Sample, SampleFormat, Stream, StreamConfig,
+};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+/// Samples per FFT. At 48 kHz that's about 21 ms of audio, with bins 47 Hz apart.
+const FFT_SIZE: usize = 1024;
+
+/// Lower edge of the lowest band; the bands are spaced logarithmically up to Nyquist.
+const MIN_FREQUENCY: f32 = 40.0;
+
+/// Band levels at or below this many dB under full scale read as silence.
+const FLOOR_DB: f32 = -60.0;
+
+/// Share of a band's level kept per FFT when the band gets quieter, so the
+/// animation decays smoothly instead of flickering.
+const RELEASE: f32 = 0.85;
+
+/// Captures audio from an input device and keeps the loudness of
+/// [`AUDIO_BANDS`] frequency bands up to date for `--audio`. For system audio,
+/// pick a monitor or loopback device.
+pub struct AudioCapture {
+    /// Capture stops when this is dropped.
+    _stream: Stream,
+    bands: Arc<Mutex<[f32; AUDIO_BANDS]>>,
+}
+
+impl AudioCapture {
+    /// Starts capturing from the input device whose name contains `device_name`, or
+    /// from the default one for `default`.
+    pub fn start(device_name: &str) -> Result<Self, String> {
+        let device = find_device(device_name)?;
+        let name = device.name().unwrap_or_else(|_| "unknown".to_owned());
+        let supported = device.default_input_config().map_err(|e| e.to_string())?;
+        let format = supported.sample_format();
+        let config: StreamConfig = supported.into();
+        info!(device = %name, sample_rate = config.sample_rate.0, channels = config.channels, "capturing audio");
+
+        let bands = Arc::new(Mutex::new([0.0; AUDIO_BANDS]));
+        let analyzer = Analyzer::new(
+            config.sample_rate.0 as f32,
+            config.channels as usize,
+            bands.clone(),
+        );
+        let stream = match format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, analyzer),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, analyzer),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, analyzer),
+        }?;
+        stream.play().map_err(|e| e.to_string())?;
+
+        Ok(AudioCapture {
+            _stream: stream,
+            bands,
+        })
+    }
+
+    /// The latest band levels, in `[0, 1]`.
+    pub fn bands(&self) -> [f32; AUDIO_BANDS] {
+        *self.bands.lock().unwrap()
+    }
+}
+
+fn find_device(name: &str) -> Result<Device, String> {
+    let host = cpal::default_host();
+    if name == "default" {
+        return host
+            .default_input_device()
+            .ok_or_else(|| "no default audio input device".to_owned());
+    }
+    let needle = name.to_lowercase();
+    host.input_devices()
+        .map_err(|e| e.to_string())?
+        .find(|device| {
+            device
+                .name()
+                .is_ok_and(|device_name| device_name.to_lowercase().contains(&needle))
+        })
+        .ok_or_else(|| format!("no audio input device matches '{}'", name))
+}
+
+fn build_stream<T: Sample>(
+    device: &Device,
+    config: &StreamConfig,
+    mut analyzer: Analyzer,
+) -> Result<Stream, String> {
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| analyzer.push(data),
+            |e| warn!(error = %e, "audio capture failed"),
+        )
+        .map_err(|e| e.to_string())
+}
+
+/// Runs on the audio thread: downmixes to mono, and once `FFT_SIZE` samples are in,
+/// transforms them and publishes the band levels.
+struct Analyzer {
+    fft: Arc<dyn Fft<f32>>,
+    channels: usize,
+    window: Vec<f32>,
+    samples: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    /// FFT bins each band ends at, exclusive.
+    band_ends: [usize; AUDIO_BANDS],
+    bands: Arc<Mutex<[f32; AUDIO_BANDS]>>,
+}
+
+impl Analyzer {
+    fn new(sample_rate: f32, channels: usize, bands: Arc<Mutex<[f32; AUDIO_BANDS]>>) -> Self {
+        // Hann window, against leakage between bins.
+        let window = (0..FFT_SIZE)
+            .map(|i| {
+                let x = i as f32 / FFT_SIZE as f32;
+                0.5 - 0.5 * (std::f32::consts::TAU * x).cos()
+            })
+            .collect();
+
+        let nyquist = sample_rate / 2.0;
+        let bin_width = sample_rate / FFT_SIZE as f32;
+        let mut band_ends = [0; AUDIO_BANDS];
+        for (band, end) in band_ends.iter_mut().enumerate() {
+            let t = (band + 1) as f32 / AUDIO_BANDS as f32;
+            let frequency = MIN_FREQUENCY * (nyquist / MIN_FREQUENCY).powf(t);
+            *end = ((frequency / bin_width) as usize).clamp(2, FFT_SIZE / 2);
+        }
+
+        Analyzer {
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            channels: channels.max(1),
+            window,
+            samples: Vec::with_capacity(FFT_SIZE),
+            spectrum: vec![Complex::default(); FFT_SIZE],
+            band_ends,
+            bands,
+        }
+    }
+
+    fn push<T: Sample>(&mut self, data: &[T]) {
+        for frame in data.chunks(self.channels) {
+            let mono = frame.iter().map(Sample::to_f32).sum::<f32>() / frame.len() as f32;
+            self.samples.push(mono);
+            if self.samples.len() == FFT_SIZE {
+                self.analyze();
+                self.samples.clear();
+            }
+        }
+    }
+
+    fn analyze(&mut self) {
+        for ((bin, sample), weight) in self
+            .spectrum
+            .iter_mut()
+            .zip(&self.samples)
+            .zip(&self.window)
+        {
+            *bin = Complex::new(sample * weight, 0.0);
+        }
+        self.fft.process(&mut self.spectrum);
+
+        let mut levels = [0.0; AUDIO_BANDS];
+        // Bin 0 is DC.
+        let mut start = 1;
+        for (level, &end) in levels.iter_mut().zip(&self.band_ends) {
+            let end = end.max(start + 1);
+            let peak = self.spectrum[start..end]
+                .iter()
+                .map(|bin| bin.norm())
+                .fold(0.0, f32::max);
+            // A full-scale sine peaks at a quarter of the window length with Hann.
+            let db = 20.0
+                * (peak / (FFT_SIZE as f32 / 4.0))
+                    .max(f32::MIN_POSITIVE)
+                    .log10();
+            *level = (1.0 - db / FLOOR_DB).clamp(0.0, 1.0);
+            start = end;
+        }
+
+        let mut bands = self.bands.lock().unwrap();
+        for (band, level) in bands.iter_mut().zip(levels) {
+            *band = level.max(*band * RELEASE);
+        }
+    }
+}
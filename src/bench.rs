@@ -0,0 +1,176 @@
+use serde_json::json;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+use vulkano::device::physical::PhysicalDevice;
+
+/// Frames drawn before measuring starts, so pipeline creation, first-use driver
+/// work and swapchain setup don't skew the results.
+const WARMUP_FRAMES: usize = 30;
+
+const CSV_HEADER: &str = "device,device_type,driver_version,driver_name,api_version,frames,fps,\
+cpu_avg_ms,cpu_median_ms,cpu_p99_ms,gpu_avg_ms,gpu_median_ms,gpu_p99_ms";
+
+/// Collects frame timings for `--bench` and reports them once enough frames ran.
+pub struct Benchmark {
+    frames: usize,
+    warmup_left: usize,
+    started: Instant,
+    cpu_times: Vec<Duration>,
+    gpu_times: Vec<Duration>,
+}
+
+impl Benchmark {
+    pub fn new(frames: usize) -> Self {
+        Benchmark {
+            frames,
+            warmup_left: WARMUP_FRAMES,
+            started: Instant::now(),
+            cpu_times: Vec::with_capacity(frames),
+            gpu_times: Vec::with_capacity(frames),
+        }
+    }
+
+    /// Records one frame's CPU time and, when one was read back, a GPU time.
+    /// Returns `true` once all frames were measured.
+    pub fn frame_done(&mut self, cpu_time: Duration, gpu_time: Option<Duration>) -> bool {
+        if self.warmup_left > 0 {
+            self.warmup_left -= 1;
+            self.started = Instant::now();
+            return false;
+        }
+        self.cpu_times.push(cpu_time);
+        self.gpu_times.extend(gpu_time);
+        self.cpu_times.len() >= self.frames
+    }
+
+    /// Prints the results, writes them to `<output>.json` and appends them as a row
+    /// to `<output>.csv`, so runs on different GPUs and drivers can be compared.
+    pub fn report(&self, physical_device: PhysicalDevice, output: &Path) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let fps = self.cpu_times.len() as f64 / elapsed;
+        let cpu = Summary::of(&self.cpu_times);
+        let gpu = Summary::of(&self.gpu_times);
+        let properties = physical_device.properties();
+
+        println!(
+            "{} ({:?}, driver {:#x}), {} frames in {:.2} s, {:.1} fps",
+            properties.device_name,
+            properties.device_type,
+            properties.driver_version,
+            self.cpu_times.len(),
+            elapsed,
+            fps,
+        );
+        for (name, summary) in [("CPU", cpu), ("GPU", gpu)] {
+            match summary {
+                Some(summary) => println!(
+                    "  {} frame time: avg {:.3} ms, median {:.3} ms, p99 {:.3} ms",
+                    name, summary.average_ms, summary.median_ms, summary.p99_ms
+                ),
+                None => println!("  {} frame time: unavailable", name),
+            }
+        }
+
+        let report = json!({
+            "device": {
+                "name": properties.device_name,
+                "type": format!("{:?}", properties.device_type),
+                "vendor_id": properties.vendor_id,
+                "device_id": properties.device_id,
+                "driver_version": properties.driver_version,
+                "driver_name": properties.driver_name,
+                "driver_info": properties.driver_info,
+                "api_version": physical_device.api_version().to_string(),
+            },
+            "frames": self.cpu_times.len(),
+            "seconds": elapsed,
+            "fps": fps,
+            "cpu_ms": cpu.map(Summary::to_json),
+            "gpu_ms": gpu.map(Summary::to_json),
+        });
+        let json_path = output.with_extension("json");
+        if let Err(e) = fs::write(&json_path, format!("{:#}\n", report)) {
+            warn!(path = %json_path.display(), error = %e, "failed to write benchmark results");
+        }
+
+        let summary_fields = |summary: Option<Summary>| match summary {
+            Some(summary) => format!(
+                "{:.4},{:.4},{:.4}",
+                summary.average_ms, summary.median_ms, summary.p99_ms
+            ),
+            None => ",,".to_owned(),
+        };
+        let row = format!(
+            "\"{}\",{:?},{},\"{}\",{},{},{:.2},{},{}",
+            properties.device_name.replace('"', "\"\""),
+            properties.device_type,
+            properties.driver_version,
+            properties
+                .driver_name
+                .as_deref()
+                .unwrap_or_default()
+                .replace('"', "\"\""),
+            physical_device.api_version(),
+            self.cpu_times.len(),
+            fps,
+            summary_fields(cpu),
+            summary_fields(gpu),
+        );
+        let csv_path = output.with_extension("csv");
+        if let Err(e) = append_csv_row(&csv_path, &row) {
+            warn!(path = %csv_path.display(), error = %e, "failed to write benchmark results");
+        }
+
+        info!(json = %json_path.display(), csv = %csv_path.display(), "benchmark results written");
+    }
+}
+
+fn append_csv_row(path: &Path, row: &str) -> std::io::Result<()> {
+    let new_file = !path.exists();
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    if new_file {
+        writeln!(file, "{}", CSV_HEADER)?;
+    }
+    writeln!(file, "{}", row)
+}
+
+#[derive(Clone, Copy)]
+struct Summary {
+    average_ms: f64,
+    median_ms: f64,
+    p99_ms: f64,
+}
+
+impl Summary {
+    fn of(times: &[Duration]) -> Option<Self> {
+        if times.is_empty() {
+            return None;
+        }
+        let mut sorted = times.to_vec();
+        sorted.sort();
+        // Nearest-rank percentile.
+        let percentile = |p: f64| {
+            let rank = ((p * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+            sorted[rank - 1].as_secs_f64() * 1000.0
+        };
+        let total: Duration = sorted.iter().sum();
+        Some(Summary {
+            average_ms: total.as_secs_f64() * 1000.0 / sorted.len() as f64,
+            median_ms: percentile(0.5),
+            p99_ms: percentile(0.99),
+        })
+    }
+
+    fn to_json(self) -> serde_json::Value {
+        json!({
+            "average": self.average_ms,
+            "median": self.median_ms,
+            "p99": self.p99_ms,
+        })
+    }
+}
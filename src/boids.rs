@@ -0,0 +1,294 @@
+use crate::{
+    debug_draw::DebugDraw,
+    scene::{Transform, Velocity},
+};
+use hecs::World;
+use std::collections::HashMap;
+use tracing::info;
+use winit::event::VirtualKeyCode;
+
+/// Neighbors a boid looks at, at most; the rest of a dense flock is ignored, which
+/// keeps ticks cheap once it has bunched up.
+const MAX_NEIGHBORS: usize = 24;
+
+/// Slowest a boid flies, as a fraction of [`FlockParams::max_speed`], so it never
+/// stalls and turns on the spot.
+const MIN_SPEED_FRACTION: f32 = 0.25;
+
+/// Boids beyond this distance from the center of the view turn back in.
+const EDGE: f32 = 0.95;
+
+/// Acceleration turning boids back in at the edge, in clip space units per second
+/// squared.
+const EDGE_PUSH: f32 = 4.0;
+
+/// Where the parameter bars of [`Flock::draw_overlay`] start, and their size.
+const BARS_ORIGIN: [f32; 2] = [-0.95, -0.95];
+const BAR_SIZE: [f32; 2] = [0.4, 0.03];
+const BAR_SPACING: f32 = 0.05;
+
+const SELECTED_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const UNSELECTED_COLOR: [f32; 4] = [0.4, 0.4, 0.4, 1.0];
+const TARGET_COLOR: [f32; 4] = [1.0, 0.5, 0.0, 1.0];
+
+/// Marks an entity as one of the flock of `--demo boids`. The flock steers its
+/// [`Velocity`] and turns its x axis along it.
+#[derive(Clone, Copy, Debug)]
+pub struct Boid;
+
+/// The weights of the steering rules, in clip space units per second squared, and
+/// the distances they act over.
+#[derive(Clone, Copy, Debug)]
+pub struct FlockParams {
+    /// Steering away from neighbors, stronger the closer they are.
+    pub separation: f32,
+    /// Steering toward the average velocity of the neighbors.
+    pub alignment: f32,
+    /// Steering toward the average position of the neighbors.
+    pub cohesion: f32,
+    /// Steering toward the cursor.
+    pub seek: f32,
+    /// Other boids within this distance, in clip space units, are neighbors.
+    pub radius: f32,
+    /// In clip space units per second.
+    pub max_speed: f32,
+}
+
+impl Default for FlockParams {
+    fn default() -> Self {
+        FlockParams {
+            separation: 4.0,
+            alignment: 1.5,
+            cohesion: 1.0,
+            seek: 0.5,
+            radius: 0.15,
+            max_speed: 0.6,
+        }
+    }
+}
+
+/// A parameter the arrow and +/- keys tune.
+struct Tunable {
+    name: &'static str,
+    field: fn(&mut FlockParams) -> &mut f32,
+    min: f32,
+    max: f32,
+    step: f32,
+    /// Of its bar in the overlay.
+    color: [f32; 4],
+}
+
+const TUNABLES: [Tunable; 6] = [
+    Tunable {
+        name: "separation",
+        field: |params| &mut params.separation,
+        min: 0.0,
+        max: 10.0,
+        step: 0.5,
+        color: [1.0, 0.3, 0.3, 1.0],
+    },
+    Tunable {
+        name: "alignment",
+        field: |params| &mut params.alignment,
+        min: 0.0,
+        max: 5.0,
+        step: 0.25,
+        color: [0.3, 1.0, 0.3, 1.0],
+    },
+    Tunable {
+        name: "cohesion",
+        field: |params| &mut params.cohesion,
+        min: 0.0,
+        max: 5.0,
+        step: 0.25,
+        color: [0.3, 0.5, 1.0, 1.0],
+    },
+    Tunable {
+        name: "seek",
+        field: |params| &mut params.seek,
+        min: 0.0,
+        max: 5.0,
+        step: 0.25,
+        color: TARGET_COLOR,
+    },
+    Tunable {
+        name: "radius",
+        field: |params| &mut params.radius,
+        min: 0.02,
+        max: 0.5,
+        step: 0.02,
+        color: [1.0, 1.0, 0.3, 1.0],
+    },
+    Tunable {
+        name: "max_speed",
+        field: |params| &mut params.max_speed,
+        min: 0.1,
+        max: 2.0,
+        step: 0.1,
+        color: [0.3, 1.0, 1.0, 1.0],
+    },
+];
+
+/// Separation, alignment and cohesion for every entity with a [`Boid`], plus a pull
+/// toward a target. Neighbors are found through a spatial hash with cells as large
+/// as the neighbor radius, so only the boids in the 3x3 cells around each one are
+/// looked at.
+#[derive(Default)]
+pub struct Flock {
+    pub params: FlockParams,
+    /// What the boids steer toward, in clip space.
+    pub target: [f32; 2],
+    /// Index of the [`TUNABLES`] entry the +/- keys change.
+    selected: usize,
+    /// Indices into `boids` by cell, kept between ticks for their allocations.
+    grid: HashMap<[i32; 2], Vec<usize>>,
+    /// Position and velocity of each boid as of the start of the tick.
+    boids: Vec<([f32; 2], [f32; 2])>,
+}
+
+impl Flock {
+    /// Steers every boid's velocity for a tick of `dt` seconds; moving them is left
+    /// to the [`Velocity`] system.
+    pub fn steer(&mut self, world: &mut World, dt: f32) {
+        let params = self.params;
+        let cell_of = |position: [f32; 2]| {
+            [
+                (position[0] / params.radius).floor() as i32,
+                (position[1] / params.radius).floor() as i32,
+            ]
+        };
+
+        self.boids.clear();
+        for cell in self.grid.values_mut() {
+            cell.clear();
+        }
+        for (_, (transform, velocity, _)) in world.query_mut::<(&Transform, &Velocity, &Boid)>() {
+            let index = self.boids.len();
+            self.boids.push((transform.translation, velocity.0));
+            self.grid
+                .entry(cell_of(transform.translation))
+                .or_default()
+                .push(index);
+        }
+
+        // The query visits the boids in the same order again.
+        let query = world.query_mut::<(&mut Transform, &mut Velocity, &Boid)>();
+        for (index, (_, (transform, velocity, _))) in query.into_iter().enumerate() {
+            let (position, current) = self.boids[index];
+            let [cell_x, cell_y] = cell_of(position);
+            let mut separation = [0.0; 2];
+            let mut average_velocity = [0.0; 2];
+            let mut center = [0.0; 2];
+            let mut neighbors = 0;
+            let nearby = (-1..=1)
+                .flat_map(|dy| (-1..=1).map(move |dx| [cell_x + dx, cell_y + dy]))
+                .filter_map(|cell| self.grid.get(&cell))
+                .flatten();
+            for &other in nearby {
+                if other == index {
+                    continue;
+                }
+                let (other_position, other_velocity) = self.boids[other];
+                let offset = [
+                    position[0] - other_position[0],
+                    position[1] - other_position[1],
+                ];
+                let distance = offset[0].hypot(offset[1]);
+                if distance >= params.radius || distance == 0.0 {
+                    continue;
+                }
+                let push = (1.0 - distance / params.radius) / distance;
+                for axis in 0..2 {
+                    separation[axis] += offset[axis] * push;
+                    average_velocity[axis] += other_velocity[axis];
+                    center[axis] += other_position[axis];
+                }
+                neighbors += 1;
+                if neighbors == MAX_NEIGHBORS {
+                    break;
+                }
+            }
+
+            let mut acceleration = [0.0; 2];
+            for axis in 0..2 {
+                if neighbors > 0 {
+                    let count = neighbors as f32;
+                    acceleration[axis] += params.separation * separation[axis] / count
+                        + params.alignment * (average_velocity[axis] / count - current[axis])
+                        + params.cohesion * (center[axis] / count - position[axis]);
+                }
+                acceleration[axis] += params.seek * (self.target[axis] - position[axis]);
+                if position[axis] > EDGE {
+                    acceleration[axis] -= EDGE_PUSH;
+                } else if position[axis] < -EDGE {
+                    acceleration[axis] += EDGE_PUSH;
+                }
+            }
+
+            let mut steered = [
+                current[0] + acceleration[0] * dt,
+                current[1] + acceleration[1] * dt,
+            ];
+            let speed = steered[0].hypot(steered[1]);
+            let clamped = speed.clamp(MIN_SPEED_FRACTION * params.max_speed, params.max_speed);
+            if speed > 0.0 {
+                steered = steered.map(|component| component * clamped / speed);
+            }
+            velocity.0 = steered;
+            transform.rotation = steered[1].atan2(steered[0]);
+        }
+    }
+
+    /// Tunes the parameters with a key: Up and Down pick one, Minus and Equals (+)
+    /// lower and raise it. Other keys are ignored.
+    pub fn handle_key(&mut self, key: VirtualKeyCode) {
+        match key {
+            VirtualKeyCode::Up => {
+                self.selected = (self.selected + TUNABLES.len() - 1) % TUNABLES.len();
+            }
+            VirtualKeyCode::Down => self.selected = (self.selected + 1) % TUNABLES.len(),
+            VirtualKeyCode::Minus | VirtualKeyCode::Equals => {
+                let tunable = &TUNABLES[self.selected];
+                let step = if key == VirtualKeyCode::Minus {
+                    -tunable.step
+                } else {
+                    tunable.step
+                };
+                let value = (tunable.field)(&mut self.params);
+                *value = (*value + step).clamp(tunable.min, tunable.max);
+            }
+            _ => return,
+        }
+        let tunable = &TUNABLES[self.selected];
+        let value = *(tunable.field)(&mut self.params);
+        info!(parameter = tunable.name, value, "boids");
+    }
+
+    /// Draws a bar per parameter in the top left corner, filled as far as its value
+    /// is between its bounds, the selected one outlined in white, and the target with
+    /// the neighbor radius around it. There's no text; the parameter names and values
+    /// are logged as they're tuned.
+    pub fn draw_overlay(&self, debug_draw: &mut DebugDraw) {
+        let mut params = self.params;
+        for (i, tunable) in TUNABLES.iter().enumerate() {
+            let value = *(tunable.field)(&mut params);
+            let fill = (value - tunable.min) / (tunable.max - tunable.min);
+            let min = [BARS_ORIGIN[0], BARS_ORIGIN[1] + i as f32 * BAR_SPACING];
+            let max = [min[0] + BAR_SIZE[0], min[1] + BAR_SIZE[1]];
+            let outline = if i == self.selected {
+                SELECTED_COLOR
+            } else {
+                UNSELECTED_COLOR
+            };
+            debug_draw.rect(min, max, outline);
+            // Hatched, as the overlay only draws lines.
+            for line in 1..4 {
+                let y = min[1] + BAR_SIZE[1] * line as f32 / 4.0;
+                let end = min[0] + BAR_SIZE[0] * fill;
+                debug_draw.line([min[0], y], [end, y], tunable.color);
+            }
+        }
+        debug_draw.circle(self.target, 0.02, TARGET_COLOR);
+        debug_draw.circle(self.target, self.params.radius, UNSELECTED_COLOR);
+    }
+}
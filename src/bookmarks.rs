@@ -0,0 +1,78 @@
+use crate::{config, scene::Camera};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use tracing::{debug, warn};
+use winit::event::VirtualKeyCode;
+
+const FILE_NAME: &str = "camera_bookmarks.ron";
+
+const SLOTS: usize = 9;
+
+/// Camera positions saved with Ctrl+1..9 and recalled with 1..9, kept in the config
+/// directory so they survive restarts and repeatable captures can start from them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CameraBookmarks {
+    slots: [Option<Camera>; SLOTS],
+}
+
+impl CameraBookmarks {
+    /// Reads the saved bookmarks; a missing or unreadable file means none.
+    pub fn load() -> Self {
+        let path = match config::config_file(FILE_NAME) {
+            Some(path) => path,
+            None => return CameraBookmarks::default(),
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => return CameraBookmarks::default(),
+        };
+        ron::from_str(&contents).unwrap_or_else(|e| {
+            warn!(path = %path.display(), error = %e, "ignoring unreadable camera bookmarks");
+            CameraBookmarks::default()
+        })
+    }
+
+    pub fn get(&self, slot: usize) -> Option<Camera> {
+        self.slots[slot]
+    }
+
+    /// Sets `slot` and saves every bookmark. Failures to save are logged.
+    pub fn set(&mut self, slot: usize, camera: Camera) {
+        self.slots[slot] = Some(camera);
+        let path = match config::config_file(FILE_NAME) {
+            Some(path) => path,
+            None => return,
+        };
+        let result = ron::ser::to_string_pretty(self, Default::default())
+            .map_err(|e| e.to_string())
+            .and_then(|contents| {
+                path.parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|()| fs::write(&path, contents))
+                    .map_err(|e| e.to_string())
+            });
+        match result {
+            Ok(()) => debug!(path = %path.display(), "saved camera bookmarks"),
+            Err(message) => {
+                warn!(path = %path.display(), %message, "failed to save camera bookmarks")
+            }
+        }
+    }
+}
+
+/// The bookmark slot a number key selects: 1 is the first.
+pub fn slot(key: VirtualKeyCode) -> Option<usize> {
+    let slot = match key {
+        VirtualKeyCode::Key1 => 0,
+        VirtualKeyCode::Key2 => 1,
+        VirtualKeyCode::Key3 => 2,
+        VirtualKeyCode::Key4 => 3,
+        VirtualKeyCode::Key5 => 4,
+        VirtualKeyCode::Key6 => 5,
+        VirtualKeyCode::Key7 => 6,
+        VirtualKeyCode::Key8 => 7,
+        VirtualKeyCode::Key9 => 8,
+        _ => return None,
+    };
+    Some(slot)
+}
@@ -0,0 +1,85 @@
+use crate::{
+    renderer::{self, FrameData, RendererSettings, AUDIO_BANDS},
+    scene::{self, Scene},
+};
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::Path,
+    sync::Arc,
+};
+use tracing::info;
+use vulkano::instance::Instance;
+
+/// Small, so software rasterizers like lavapipe stay fast.
+const CAPTURE_EXTENT: [u32; 2] = [256, 256];
+
+/// What `--capture` renders. Every input is fixed, so the images only change when
+/// the rendering does.
+const CAPTURED_FRAMES: [FrameData; 3] = [
+    FrameData {
+        time: 0.0,
+        mouse: [0.5, 0.5],
+        zoom: 1.0,
+        audio_bands: [0.0; AUDIO_BANDS],
+    },
+    FrameData {
+        time: 1.5,
+        mouse: [0.25, 0.75],
+        zoom: 1.0,
+        audio_bands: [0.0; AUDIO_BANDS],
+    },
+    FrameData {
+        time: 4.0,
+        mouse: [1.0, 0.2],
+        zoom: 2.0,
+        audio_bands: [0.0; AUDIO_BANDS],
+    },
+];
+
+/// Renders the captured frames offscreen and writes them to `directory` as
+/// `frame_<N>.png`.
+pub fn save_frames(
+    instance: &Arc<Instance>,
+    physical_device_index: usize,
+    queue_family_id: u32,
+    settings: &RendererSettings,
+    directory: &Path,
+) -> Result<(), String> {
+    let mut scene = Scene::new(scene::DEFAULT_INSTANCE_COUNT);
+    let frames: Vec<_> = CAPTURED_FRAMES
+        .into_iter()
+        .map(|frame| {
+            let instances = scene.pack_instances(frame.time, 1.0).meshes.owned();
+            (frame, instances)
+        })
+        .collect();
+    let images = renderer::render_offscreen(
+        instance,
+        physical_device_index,
+        queue_family_id,
+        settings,
+        CAPTURE_EXTENT,
+        &frames,
+    )?;
+
+    fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+    for (index, pixels) in images.iter().enumerate() {
+        let path = directory.join(format!("frame_{}.png", index));
+        write_png(&path, CAPTURE_EXTENT, pixels)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    info!(directory = %directory.display(), frames = images.len(), "captured frames");
+    Ok(())
+}
+
+fn write_png(path: &Path, extent: [u32; 2], pixels: &[u8]) -> Result<(), String> {
+    let file = BufWriter::new(File::create(path).map_err(|e| e.to_string())?);
+    let mut encoder = png::Encoder::new(file, extent[0], extent[1]);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels))
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,910 @@
+use crate::{
+    gpu::GpuSelector,
+    hdr::{self, DisplayOutput, SurfaceFormatPolicy},
+    monitor::{FullscreenMode, MonitorSelector},
+    pacing::FPS_RANGE,
+    renderer::{
+        DemoKind, MotionBlurSettings, RasterizerSettings, Stylization, Topology, MAX_LIGHTS,
+        MAX_NBODY_BODIES,
+    },
+    video::VideoSettings,
+    window::{self, WindowSettings},
+};
+use std::{env, path::PathBuf, process};
+use vulkano::Version;
+
+const GPU_ENV_VAR: &str = "COOL_VULKANO_GPU";
+
+const USAGE: &str = "\
+Usage: vulkano_triangle_tutorial [OPTIONS]
+
+Options:
+    --gpu <INDEX|NAME>    Render on the GPU with this index or whose name contains NAME
+                          (defaults to $COOL_VULKANO_GPU, then the most capable GPU)
+    --force-software      Prefer a CPU implementation such as lavapipe or SwiftShader,
+                          with fewer instances, for machines without a GPU; without one
+                          the device is picked as usual
+    --list-gpus           Print the available GPUs and exit
+    --info                Print the selected GPU's properties, limits, enabled features,
+                          memory, queue families and surface formats and exit
+    --device-lost-retries <N>
+                          Recreate the device up to N times in a row after it is lost [default: 3]
+    --crash-dump <FILE>   When the device is lost, write to FILE the GPU and driver and
+                          the passes and draws of the frames in flight
+    --power-save          Sleep between frames instead of redrawing continuously
+    --idle-fps <FPS>      Frame rate while in power-save mode, 0.1 to 10000, or 0 to redraw
+                          on input only [default: 30]
+    --fps-cap <FPS>       Limit the frame rate regardless of the present mode, 0.1 to 10000
+    --frames-in-flight <N>
+                          Frames the CPU may record ahead of the GPU [default: 2]
+    --draw-buckets <N>    Record draws into N secondary command buffers in parallel [default: 1]
+    --prerecord           Record command buffers once per swapchain image and reuse them
+    --depth-prepass       Draw the instances' depth first and shade only the visible
+                          fragments; Z toggles it
+    --occlusion-culling   Skip clusters of 64 instances that were hidden behind others in
+                          the last frames, tested with occlusion queries; implies
+                          --depth-prepass and only culls while it is on
+    --cull-mode <none|back|front|both>
+                          Faces of the scene's triangles to cull [default: none]; C cycles it
+    --front-face <ccw|cw> Winding of the triangles' front faces [default: ccw]; F flips it
+    --depth-bias <CONSTANT[,SLOPE]>
+                          Add CONSTANT plus SLOPE times each triangle's depth slope to its
+                          fragments' depth
+    --line-width <PIXELS> Width of the debug lines, expanded into quads where wide lines
+                          aren't supported, and of meshes drawn as lines [default: 1]; L
+                          cycles it
+    --pipeline-stats      Log vertex and fragment shader invocations and clipped primitives
+                          per frame with the frame stats (at debug level)
+    --quantize-vertices   Store mesh positions as half floats and colors as normalized
+                          bytes, halving the vertex buffers
+    --planar-vertices     Store mesh positions and colors in separate vertex buffers
+                          instead of interleaving them in one
+    --topology <triangle-list|triangle-strip|triangle-fan|line-list|line-strip|point-list>
+                          Assemble every mesh's indices as this instead of its own
+                          topology, e.g. point-list to see the vertices
+    --point-sprites <PIXELS>
+                          Draw every instance and particle as a round point this wide
+                          instead of its mesh, for very large instance counts
+    --outline <SCALE>     Outline the instances picked with Ctrl+click by drawing them
+                          again this many times bigger behind a stencil mask (e.g. 1.1)
+    --stereo <SEPARATION> Draw the instances for two eyes this far apart in clip space
+                          (e.g. 0.05) in one multiview pass, shown side by side
+    --environment-map <SIZE>
+                          Draw the instances into a cube map with faces this many texels
+                          wide and show them reflected in a mirror ball
+    --no-dynamic-rendering
+                          Always record frames with a render pass and framebuffers, even
+                          where dynamic rendering is supported
+    --no-push-descriptors Bind descriptor sets even where descriptors can be pushed
+    --no-synchronization2 Record barriers with the original Vulkan 1.0 calls, even where
+                          VK_KHR_synchronization2 is supported
+    --api-version <MAJOR.MINOR>
+                          Highest Vulkan version to use, to try the paths older devices
+                          take [default: 1.3]
+    --display-output <sdr|hdr10|scrgb>
+                          Color space to present in, falling back to SDR if unsupported [default: sdr]
+    --surface-format <PREFERENCES>
+                          How to pick the SDR swapchain format: a comma separated list of
+                          srgb, 10bit and format names like B8G8R8A8_UNORM, tried in
+                          order, falling back to the surface's first sRGB one
+                          [default: srgb]
+    --paper-white <NITS>  Brightness of white on HDR outputs [default: 200]
+    --lut <FILE>          Grade the final image with a 3D LUT, from a .cube file or a PNG
+                          strip N² pixels wide and N tall; dropping either onto the window
+                          swaps it
+    --dither              Add blue noise to the final image against banding on 8 and 10
+                          bit displays; D toggles it to compare
+    --taa                 Anti-alias with a jittered history of frames; T toggles it to
+                          compare against no anti-aliasing. Needs dynamic rendering
+    --dof                 Blur what's out of focus; G toggles it and Shift+click focuses
+                          on what's under the cursor. Needs dynamic rendering
+    --motion-blur         Streak what moves along its motion vectors; M toggles it.
+                          Needs dynamic rendering
+    --motion-blur-samples <N>
+                          Taps along each pixel's motion [default: 8]
+    --shutter <SCALE>     Fraction of the frame the shutter is open for, scaling the
+                          streaks [default: 0.5]
+    --chromatic-aberration <INTENSITY>
+    --vignette <INTENSITY>
+    --film-grain <INTENSITY>
+                          Split red and blue toward the edges, darken the corners, or add
+                          animated grain, from 0 to 1. F1, F2 and F3 toggle them, and [
+                          and ] tune the last toggled, shown in the overlay. Needs dynamic
+                          rendering
+    --title <TITLE>       Window title
+    --size <WxH>          Initial window size in logical pixels
+    --min-size <WxH>      Smallest size the window can be resized to
+    --max-size <WxH>      Largest size the window can be resized to
+    --fixed-size          Don't let the window be resized
+    --no-decorations      Open the window without a title bar and borders
+    --always-on-top       Keep the window above other windows
+    --transparent         Clear to a transparent background, so the triangles float
+                          over the desktop where the window system supports it
+    --no-icon             Don't set the window icon
+    --monitor <INDEX|NAME>
+                          Open the window centered on the monitor with this index or whose
+                          name contains NAME
+    --list-monitors       Print the available monitors and their video modes and exit
+    --fullscreen          Open as a borderless fullscreen window
+    --exclusive-fullscreen
+                          Open fullscreen in the monitor's largest, fastest video mode
+    --no-restore-window   Don't reopen the window where it was when the last run exited
+    --bench <FRAMES>      Draw FRAMES frames of a fixed workload as fast as possible, then
+                          print CPU and GPU frame time statistics and exit
+    --bench-output <PATH> Write benchmark results to PATH.json and append them to PATH.csv
+                          [default: bench]
+    --perf-log <FILE>     Append a row per frame to FILE with its CPU and GPU time and
+                          how long it waited for earlier frames, to acquire and to
+                          present, and whether the swapchain was recreated. CSV, or JSON
+                          lines if FILE ends in .json or .jsonl. GPU times lag by the
+                          frames in flight
+    --multi-gpu <FRAMES>  Experimental: draw FRAMES frames offscreen on the most capable
+                          GPU, then alternating between it and a second GPU, copying the
+                          second one's frames over through host memory, print the
+                          speedup and exit
+    --capture <DIR>       Render a fixed set of frames offscreen into DIR as PNGs and exit,
+                          as the golden-image test does
+    --export-gif <FILE>   Record what the window shows into FILE as an animated GIF at 20
+                          frames per second and exit. The simulation steps at a fixed
+                          rate, so the GIF plays at full speed however slowly the frames
+                          are drawn
+    --seconds <S>         Length of the --export-gif recording [default: 5]
+    --video-output <FILE> Where F9 starts recording what the window shows, encoded by
+                          ffmpeg, until F9 is pressed again. Later recordings get a
+                          number appended [default: recording.mp4]
+    --video-codec <NAME>  ffmpeg encoder for F9 recordings [default: libx264]
+    --video-bitrate <RATE>
+                          Bitrate of F9 recordings, e.g. 20M [default: 8M]
+    --ffmpeg <PATH>       The ffmpeg executable [default: ffmpeg]
+    --exr-output <FILE>   Where F12 writes the linear HDR image of the next frame, before
+                          grading and encoding for the display, as OpenEXR. Later
+                          exports get a number appended [default: frame.exr]. Needs
+                          dynamic rendering
+    --clusters <N>        Add N spinning clusters of striped squares, drawn with a second
+                          mesh and material; V toggles them [default: 0]
+    --lod-discs           Draw the instances as discs in three levels of detail, fewer
+                          segments the smaller they are on screen
+    --rainbow             Cycle the instances' tints through a scrolling rainbow
+    --physics             Give the instances rigid bodies that fall and pile up inside the
+                          view; middle-click blasts them away from the cursor. Needs a
+                          build with --features physics
+    --particles <RATE>    Add a particle fountain emitting RATE particles per second
+    --gpu-particles <N>   Add a fountain of N particles simulated by a compute shader,
+                          e.g. 1000000
+    --procedural-background
+                          Fill the background with a pattern a compute shader writes into
+                          a storage image every frame
+    --demo <NAME>         Run a showcase: mandelbrot explores the Mandelbrot set instead
+                          of the scene, dragging to pan and scrolling to zoom; nbody
+                          simulates a galaxy of bodies pulling on each other with a
+                          compute shader instead of the scene; life runs the Game of
+                          Life in its place, dragging to paint live cells; vegetation
+                          sways a field of 65536 instanced grass blades in the wind,
+                          which blows toward the cursor; terrain shows a heightmap
+                          landscape, dragging to orbit the camera and scrolling to
+                          zoom; boids flocks the instances toward the cursor, Up and
+                          Down picking a parameter shown in the overlay and - and +
+                          tuning it. Ctrl+Tab cycles through mandelbrot, nbody, life,
+                          vegetation, terrain, the --shadertoy shader if any, and back
+                          to the scene
+    --bodies <N>          Bodies --demo nbody simulates, up to 524288. Each pulls on every
+                          other, so the cost grows with the square [default: 16384]
+    --heightmap <PNG>     Heights of --demo terrain, by the PNG's brightness, instead of
+                          generated hills
+    --shadertoy <GLSL>    Draw a shader written for ShaderToy instead of the scene: GLSL
+                          defines mainImage and reads iTime, iTimeDelta, iFrame,
+                          iResolution and iMouse; iChannel inputs aren't supported. With
+                          --watch, editing it reloads it
+    --tilemap <ATLAS>     Draw a demo tilemap behind the scene, with tiles from the PNG
+                          ATLAS
+    --tile-size <PIXELS>  Side of one tile in the tilemap's atlas [default: 16]
+    --texture <IMAGE>     Draw the instances textured, each with the next of the PNG or JPEG
+                          textures given in turn, all in one draw; may be repeated
+    --normal-map <IMAGE>  Light the instances, bending their shading with this tangent-space
+                          normal map, green up; N switches to flat shading to compare
+    --lights <N>          Light the instances with N point and spot lights circling the
+                          center, up to 256. K adds a light at the cursor, Delete removes
+                          the nearest, J switches it between point and spot, , and .
+                          shrink and grow it, and H shows how many lights reach each
+                          cluster of the screen [default: 0]
+    --no-bindless         Put the textures in the layers of one array image even where
+                          descriptor indexing is supported
+    --scene-file <FILE>   Where Ctrl+S saves the scene and Ctrl+O loads it from
+                          [default: scene.ron]
+    --watch <PATH>        Reload meshes, shaders, textures and the scene file from PATH
+                          when they change; PATH is a file or directory and may be
+                          repeated. The --texture and --normal-map images, the scene file
+                          and files dropped onto the window are watched too
+    --define <NAME[=VALUE]>
+                          Define a preprocessor macro, e.g. MAX_LIGHTS=8, in shaders
+                          loaded from files; may be repeated
+    --audio <DEVICE>      Make the wobble react to audio from the input device whose name
+                          contains DEVICE, or the default one for 'default'. Needs a build
+                          with --features audio
+    --record-input <FILE> Record input and window events to FILE
+    --replay-input <FILE> Replay events recorded with --record-input at one simulation tick
+                          per frame, ignoring live input, and exit when the recording ends
+    -h, --help            Print this help and exit
+";
+
+#[derive(Debug)]
+pub struct Options {
+    pub gpu: Option<GpuSelector>,
+    pub list_gpus: bool,
+    pub info: bool,
+    pub list_monitors: bool,
+    pub device_lost_retries: u32,
+    pub crash_dump: Option<PathBuf>,
+    pub power_save: bool,
+    pub idle_fps: f64,
+    pub fps_cap: Option<f64>,
+    pub frames_in_flight: usize,
+    pub draw_buckets: usize,
+    pub prerecord: bool,
+    pub depth_prepass: bool,
+    pub occlusion_culling: bool,
+    pub pipeline_statistics: bool,
+    pub quantize_vertices: bool,
+    pub planar_vertices: bool,
+    pub topology: Option<Topology>,
+    pub point_sprites: Option<f32>,
+    pub outline: Option<f32>,
+    pub stereo: Option<f32>,
+    pub environment_map: Option<u32>,
+    pub rasterizer: RasterizerSettings,
+    pub no_dynamic_rendering: bool,
+    pub no_push_descriptors: bool,
+    pub no_synchronization2: bool,
+    pub api_version: Version,
+    pub display_output: DisplayOutput,
+    pub surface_format_policy: SurfaceFormatPolicy,
+    pub paper_white: f32,
+    pub lut: Option<PathBuf>,
+    pub dither: bool,
+    pub taa: bool,
+    pub depth_of_field: bool,
+    pub motion_blur: bool,
+    pub motion_blur_settings: MotionBlurSettings,
+    pub stylization: Stylization,
+    pub window: WindowSettings,
+    pub bench_frames: Option<usize>,
+    pub bench_output: PathBuf,
+    pub perf_log: Option<PathBuf>,
+    pub capture_dir: Option<PathBuf>,
+    pub export_gif: Option<PathBuf>,
+    pub gif_seconds: f32,
+    pub video: VideoSettings,
+    pub exr_output: PathBuf,
+    pub multi_gpu_frames: Option<u32>,
+    pub clusters: usize,
+    pub lod_discs: bool,
+    pub rainbow: bool,
+    pub particle_rate: Option<f32>,
+    pub gpu_particles: u32,
+    pub procedural_background: bool,
+    pub demo: Option<DemoKind>,
+    pub nbody_bodies: u32,
+    pub heightmap: Option<PathBuf>,
+    pub shadertoy: Option<PathBuf>,
+    pub tile_atlas: Option<PathBuf>,
+    pub tile_size: u32,
+    pub textures: Vec<PathBuf>,
+    pub normal_map: Option<PathBuf>,
+    pub lights: usize,
+    pub no_bindless: bool,
+    pub scene_file: PathBuf,
+    pub watch: Vec<PathBuf>,
+    pub shader_defines: Vec<(String, Option<String>)>,
+    #[cfg(feature = "audio")]
+    pub audio_device: Option<String>,
+    #[cfg(feature = "physics")]
+    pub physics: bool,
+    pub record_input: Option<PathBuf>,
+    pub replay_input: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            gpu: None,
+            list_gpus: false,
+            info: false,
+            list_monitors: false,
+            device_lost_retries: 3,
+            crash_dump: None,
+            power_save: false,
+            idle_fps: 30.0,
+            fps_cap: None,
+            frames_in_flight: 2,
+            draw_buckets: 1,
+            prerecord: false,
+            depth_prepass: false,
+            occlusion_culling: false,
+            pipeline_statistics: false,
+            quantize_vertices: false,
+            planar_vertices: false,
+            topology: None,
+            point_sprites: None,
+            outline: None,
+            stereo: None,
+            environment_map: None,
+            rasterizer: RasterizerSettings::default(),
+            no_dynamic_rendering: false,
+            no_push_descriptors: false,
+            no_synchronization2: false,
+            api_version: Version::V1_3,
+            display_output: DisplayOutput::Sdr,
+            surface_format_policy: SurfaceFormatPolicy::default(),
+            paper_white: hdr::DEFAULT_PAPER_WHITE_NITS,
+            lut: None,
+            dither: false,
+            taa: false,
+            depth_of_field: false,
+            motion_blur: false,
+            motion_blur_settings: MotionBlurSettings::default(),
+            stylization: Stylization::default(),
+            window: WindowSettings::default(),
+            bench_frames: None,
+            bench_output: PathBuf::from("bench"),
+            perf_log: None,
+            capture_dir: None,
+            export_gif: None,
+            gif_seconds: 5.0,
+            video: VideoSettings {
+                ffmpeg: PathBuf::from("ffmpeg"),
+                codec: "libx264".to_owned(),
+                bitrate: "8M".to_owned(),
+                output: PathBuf::from("recording.mp4"),
+            },
+            exr_output: PathBuf::from("frame.exr"),
+            multi_gpu_frames: None,
+            clusters: 0,
+            lod_discs: false,
+            rainbow: false,
+            particle_rate: None,
+            gpu_particles: 0,
+            procedural_background: false,
+            demo: None,
+            nbody_bodies: 16 * 1024,
+            heightmap: None,
+            shadertoy: None,
+            tile_atlas: None,
+            tile_size: 16,
+            textures: Vec::new(),
+            normal_map: None,
+            lights: 0,
+            no_bindless: false,
+            scene_file: PathBuf::from("scene.ron"),
+            watch: Vec::new(),
+            shader_defines: Vec::new(),
+            #[cfg(feature = "audio")]
+            audio_device: None,
+            #[cfg(feature = "physics")]
+            physics: false,
+            record_input: None,
+            replay_input: None,
+        }
+    }
+}
+
+impl Options {
+    /// Parses the process arguments, printing usage and exiting on `--help` or bad input.
+    pub fn from_env() -> Self {
+        match Self::parse(env::args().skip(1)) {
+            Ok(Some(mut options)) => {
+                if options.gpu.is_none() {
+                    options.gpu = env::var(GPU_ENV_VAR)
+                        .ok()
+                        .filter(|value| !value.trim().is_empty())
+                        .map(|value| GpuSelector::parse(&value));
+                }
+                options
+            }
+            Ok(None) => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            Err(message) => {
+                eprintln!("error: {}\n\n{}", message, USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    /// Returns `Ok(None)` when help was requested.
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut options = Options::default();
+
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_owned(), Some(value.to_owned())),
+                None => (arg, None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{} expects a value", flag))
+            };
+
+            match flag.as_str() {
+                "--gpu" => options.gpu = Some(GpuSelector::parse(&value()?)),
+                "--force-software" => options.gpu = Some(GpuSelector::Software),
+                "--list-gpus" => options.list_gpus = true,
+                "--info" => options.info = true,
+                "--device-lost-retries" => {
+                    options.device_lost_retries = parse_number(&flag, &value()?)?
+                }
+                "--crash-dump" => options.crash_dump = Some(PathBuf::from(value()?)),
+                "--power-save" => options.power_save = true,
+                "--idle-fps" => {
+                    options.idle_fps = parse_number(&flag, &value()?)?;
+                    if options.idle_fps != 0.0 && !FPS_RANGE.contains(&options.idle_fps) {
+                        return Err(format!(
+                            "{} must be 0 or from {} to {}",
+                            flag,
+                            FPS_RANGE.start(),
+                            FPS_RANGE.end()
+                        ));
+                    }
+                }
+                "--fps-cap" => {
+                    let fps: f64 = parse_number(&flag, &value()?)?;
+                    if !FPS_RANGE.contains(&fps) {
+                        return Err(format!(
+                            "{} must be from {} to {}",
+                            flag,
+                            FPS_RANGE.start(),
+                            FPS_RANGE.end()
+                        ));
+                    }
+                    options.fps_cap = Some(fps);
+                }
+                "--frames-in-flight" => {
+                    options.frames_in_flight = parse_number(&flag, &value()?)?;
+                    if options.frames_in_flight == 0 {
+                        return Err(format!("{} must be at least 1", flag));
+                    }
+                }
+                "--draw-buckets" => options.draw_buckets = parse_number(&flag, &value()?)?,
+                "--prerecord" => options.prerecord = true,
+                "--depth-prepass" => options.depth_prepass = true,
+                "--pipeline-stats" => options.pipeline_statistics = true,
+                "--quantize-vertices" => options.quantize_vertices = true,
+                "--planar-vertices" => options.planar_vertices = true,
+                "--topology" => {
+                    let value = value()?;
+                    options.topology = Some(Topology::parse(&value).ok_or_else(|| {
+                        format!(
+                            "{} expects triangle-list, triangle-strip, triangle-fan, line-list, \
+                             line-strip or point-list, got '{}'",
+                            flag, value
+                        )
+                    })?)
+                }
+                "--cull-mode" => {
+                    let value = value()?;
+                    options.rasterizer.cull_mode = RasterizerSettings::parse_cull_mode(&value)
+                        .ok_or_else(|| {
+                            format!(
+                                "{} expects none, back, front or both, got '{}'",
+                                flag, value
+                            )
+                        })?
+                }
+                "--front-face" => {
+                    let value = value()?;
+                    options.rasterizer.front_face = RasterizerSettings::parse_front_face(&value)
+                        .ok_or_else(|| format!("{} expects ccw or cw, got '{}'", flag, value))?
+                }
+                "--depth-bias" => {
+                    let value = value()?;
+                    options.rasterizer.depth_bias =
+                        Some(RasterizerSettings::parse_depth_bias(&value).ok_or_else(|| {
+                            format!(
+                                "{} expects CONSTANT or CONSTANT,SLOPE, got '{}'",
+                                flag, value
+                            )
+                        })?)
+                }
+                "--point-sprites" => {
+                    let size: f32 = parse_number(&flag, &value()?)?;
+                    if size <= 0.0 {
+                        return Err(format!("{} must be positive", flag));
+                    }
+                    options.point_sprites = Some(size);
+                }
+                "--outline" => {
+                    let scale: f32 = parse_number(&flag, &value()?)?;
+                    if scale <= 1.0 {
+                        return Err(format!("{} must be greater than 1", flag));
+                    }
+                    options.outline = Some(scale);
+                }
+                "--stereo" => {
+                    let separation: f32 = parse_number(&flag, &value()?)?;
+                    // Clip space is 2 wide; any further apart and an eye sees nothing.
+                    if !(0.0..2.0).contains(&separation) {
+                        return Err(format!("{} must be at least 0 and less than 2", flag));
+                    }
+                    options.stereo = Some(separation);
+                }
+                "--environment-map" => {
+                    let size: u32 = parse_number(&flag, &value()?)?;
+                    if size == 0 {
+                        return Err(format!("{} must be positive", flag));
+                    }
+                    options.environment_map = Some(size);
+                }
+                "--line-width" => {
+                    options.rasterizer.line_width = parse_number(&flag, &value()?)?;
+                    if options.rasterizer.line_width <= 0.0 {
+                        return Err(format!("{} must be positive", flag));
+                    }
+                }
+                "--no-dynamic-rendering" => options.no_dynamic_rendering = true,
+                "--no-push-descriptors" => options.no_push_descriptors = true,
+                "--no-synchronization2" => options.no_synchronization2 = true,
+                "--api-version" => {
+                    let value = value()?;
+                    options.api_version = value
+                        .parse()
+                        .ok()
+                        .filter(|&version| version >= Version::V1_0)
+                        .ok_or_else(|| {
+                            format!(
+                                "{} expects a Vulkan version like 1.2, got '{}'",
+                                flag, value
+                            )
+                        })?;
+                }
+                "--occlusion-culling" => {
+                    options.occlusion_culling = true;
+                    options.depth_prepass = true;
+                }
+                "--display-output" => {
+                    let value = value()?;
+                    options.display_output = DisplayOutput::parse(&value).ok_or_else(|| {
+                        format!("{} expects sdr, hdr10 or scrgb, got '{}'", flag, value)
+                    })?
+                }
+                "--surface-format" => {
+                    let value = value()?;
+                    options.surface_format_policy =
+                        SurfaceFormatPolicy::parse(&value).ok_or_else(|| {
+                            format!(
+                                "{} expects srgb, 10bit or swapchain format names, got '{}'",
+                                flag, value
+                            )
+                        })?
+                }
+                "--paper-white" => options.paper_white = parse_number(&flag, &value()?)?,
+                "--lut" => options.lut = Some(PathBuf::from(value()?)),
+                "--dither" => options.dither = true,
+                "--taa" => options.taa = true,
+                "--dof" => options.depth_of_field = true,
+                "--motion-blur" => options.motion_blur = true,
+                "--motion-blur-samples" => {
+                    let samples: u32 = parse_number(&flag, &value()?)?;
+                    if samples == 0 {
+                        return Err(format!("{} must be positive", flag));
+                    }
+                    options.motion_blur_settings.samples = samples;
+                }
+                "--shutter" => {
+                    let shutter: f32 = parse_number(&flag, &value()?)?;
+                    if shutter < 0.0 {
+                        return Err(format!("{} can't be negative", flag));
+                    }
+                    options.motion_blur_settings.shutter = shutter;
+                }
+                "--chromatic-aberration" | "--vignette" | "--film-grain" => {
+                    let intensity: f32 = parse_number(&flag, &value()?)?;
+                    if !(0.0..=1.0).contains(&intensity) {
+                        return Err(format!("{} must be between 0 and 1", flag));
+                    }
+                    let field = match flag.as_str() {
+                        "--chromatic-aberration" => &mut options.stylization.chromatic_aberration,
+                        "--vignette" => &mut options.stylization.vignette,
+                        _ => &mut options.stylization.film_grain,
+                    };
+                    *field = Some(intensity);
+                }
+                "--title" => options.window.title = value()?,
+                "--size" => options.window.size = Some(parse_size(&flag, &value()?)?),
+                "--min-size" => options.window.min_size = Some(parse_size(&flag, &value()?)?),
+                "--max-size" => options.window.max_size = Some(parse_size(&flag, &value()?)?),
+                "--fixed-size" => options.window.resizable = false,
+                "--no-decorations" => options.window.decorations = false,
+                "--always-on-top" => options.window.always_on_top = true,
+                "--transparent" => options.window.transparent = true,
+                "--no-icon" => options.window.icon = false,
+                "--monitor" => options.window.monitor = Some(MonitorSelector::parse(&value()?)),
+                "--list-monitors" => options.list_monitors = true,
+                "--fullscreen" => options.window.fullscreen = Some(FullscreenMode::Borderless),
+                "--exclusive-fullscreen" => {
+                    options.window.fullscreen = Some(FullscreenMode::Exclusive)
+                }
+                "--no-restore-window" => options.window.restore_placement = false,
+                "--bench" => {
+                    let frames = parse_number(&flag, &value()?)?;
+                    if frames == 0 {
+                        return Err(format!("{} must be at least 1", flag));
+                    }
+                    options.bench_frames = Some(frames);
+                }
+                "--bench-output" => options.bench_output = PathBuf::from(value()?),
+                "--perf-log" => options.perf_log = Some(PathBuf::from(value()?)),
+                "--capture" => options.capture_dir = Some(PathBuf::from(value()?)),
+                "--export-gif" => options.export_gif = Some(PathBuf::from(value()?)),
+                "--video-output" => options.video.output = PathBuf::from(value()?),
+                "--video-codec" => options.video.codec = value()?,
+                "--video-bitrate" => options.video.bitrate = value()?,
+                "--ffmpeg" => options.video.ffmpeg = PathBuf::from(value()?),
+                "--exr-output" => options.exr_output = PathBuf::from(value()?),
+                "--seconds" => {
+                    let seconds: f32 = parse_number(&flag, &value()?)?;
+                    if seconds <= 0.0 {
+                        return Err(format!("{} must be positive", flag));
+                    }
+                    options.gif_seconds = seconds;
+                }
+                "--multi-gpu" => {
+                    let frames = parse_number(&flag, &value()?)?;
+                    if frames == 0 {
+                        return Err(format!("{} must be at least 1", flag));
+                    }
+                    options.multi_gpu_frames = Some(frames);
+                }
+                "--clusters" => options.clusters = parse_number(&flag, &value()?)?,
+                "--lod-discs" => options.lod_discs = true,
+                "--rainbow" => options.rainbow = true,
+                "--particles" => options.particle_rate = Some(parse_number(&flag, &value()?)?),
+                "--gpu-particles" => options.gpu_particles = parse_number(&flag, &value()?)?,
+                "--procedural-background" => options.procedural_background = true,
+                "--heightmap" => options.heightmap = Some(PathBuf::from(value()?)),
+                "--shadertoy" => {
+                    options.shadertoy = Some(PathBuf::from(value()?));
+                    options.demo = Some(DemoKind::ShaderToy);
+                }
+                "--demo" => {
+                    let value = value()?;
+                    options.demo = Some(DemoKind::parse(&value).ok_or_else(|| {
+                        format!(
+                            "{} expects mandelbrot, nbody, life, vegetation, terrain or boids, got '{}'",
+                            flag, value
+                        )
+                    })?);
+                }
+                "--bodies" => {
+                    options.nbody_bodies = parse_number(&flag, &value()?)?;
+                    if !(1..=MAX_NBODY_BODIES).contains(&options.nbody_bodies) {
+                        return Err(format!("{} must be 1 to {}", flag, MAX_NBODY_BODIES));
+                    }
+                }
+                "--tilemap" => options.tile_atlas = Some(PathBuf::from(value()?)),
+                "--tile-size" => {
+                    options.tile_size = parse_number(&flag, &value()?)?;
+                    if options.tile_size == 0 {
+                        return Err(format!("{} must be at least 1", flag));
+                    }
+                }
+                "--scene-file" => options.scene_file = PathBuf::from(value()?),
+                "--watch" => options.watch.push(PathBuf::from(value()?)),
+                "--define" => {
+                    let definition = value()?;
+                    let (name, value) = match definition.split_once('=') {
+                        Some((name, value)) => (name, Some(value.to_owned())),
+                        None => (definition.as_str(), None),
+                    };
+                    if name.is_empty() {
+                        return Err(format!("{} needs a macro name", flag));
+                    }
+                    options.shader_defines.push((name.to_owned(), value));
+                }
+                "--texture" => options.textures.push(PathBuf::from(value()?)),
+                "--normal-map" => options.normal_map = Some(PathBuf::from(value()?)),
+                "--lights" => {
+                    options.lights = parse_number(&flag, &value()?)?;
+                    if options.lights > MAX_LIGHTS {
+                        return Err(format!("{} must be at most {}", flag, MAX_LIGHTS));
+                    }
+                }
+                "--no-bindless" => options.no_bindless = true,
+                "--audio" => {
+                    let device = value()?;
+                    #[cfg(feature = "audio")]
+                    {
+                        options.audio_device = Some(device);
+                    }
+                    #[cfg(not(feature = "audio"))]
+                    return Err(format!(
+                        "{} {} needs a build with --features audio",
+                        flag, device
+                    ));
+                }
+                "--physics" => {
+                    #[cfg(feature = "physics")]
+                    {
+                        options.physics = true;
+                    }
+                    #[cfg(not(feature = "physics"))]
+                    return Err(format!("{} needs a build with --features physics", flag));
+                }
+                "--record-input" => options.record_input = Some(PathBuf::from(value()?)),
+                "--replay-input" => options.replay_input = Some(PathBuf::from(value()?)),
+                "-h" | "--help" => return Ok(None),
+                _ => return Err(format!("unknown argument '{}'", flag)),
+            }
+        }
+
+        if options.record_input.is_some() && options.replay_input.is_some() {
+            return Err("--record-input and --replay-input can't be combined".to_owned());
+        }
+        if options.export_gif.is_some() && options.bench_frames.is_some() {
+            return Err("--export-gif and --bench can't be combined".to_owned());
+        }
+        if options.export_gif.is_some() && options.replay_input.is_some() {
+            return Err("--export-gif and --replay-input can't be combined".to_owned());
+        }
+        if options.shadertoy.is_some() && options.demo != Some(DemoKind::ShaderToy) {
+            return Err("--shadertoy and --demo can't be combined".to_owned());
+        }
+        #[cfg(feature = "physics")]
+        if options.physics && options.demo == Some(DemoKind::Boids) {
+            return Err("--physics and --demo boids can't be combined".to_owned());
+        }
+        Ok(Some(options))
+    }
+}
+
+fn parse_size(flag: &str, value: &str) -> Result<winit::dpi::LogicalSize<f64>, String> {
+    window::parse_size(value)
+        .ok_or_else(|| format!("{} expects WIDTHxHEIGHT, got '{}'", flag, value))
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, got '{}'", flag, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<Options>, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn parse_ok(args: &[&str]) -> Options {
+        parse(args).unwrap().expect("help was requested")
+    }
+
+    #[test]
+    fn no_arguments_give_the_defaults() {
+        let options = parse_ok(&[]);
+        assert_eq!(options.frames_in_flight, 2);
+        assert_eq!(options.fps_cap, None);
+        assert_eq!(options.stereo, None);
+    }
+
+    #[test]
+    fn help_returns_none() {
+        assert!(parse(&["--help"]).unwrap().is_none());
+        assert!(parse(&["--prerecord", "-h"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn gpu_takes_an_index_or_a_name() {
+        assert_eq!(parse_ok(&["--gpu", "1"]).gpu, Some(GpuSelector::Index(1)));
+        assert_eq!(
+            parse_ok(&["--gpu=GeForce"]).gpu,
+            Some(GpuSelector::Name("geforce".to_owned()))
+        );
+    }
+
+    #[test]
+    fn values_follow_the_flag_or_an_equals_sign() {
+        assert_eq!(parse_ok(&["--fps-cap", "30"]).fps_cap, Some(30.0));
+        assert_eq!(parse_ok(&["--fps-cap=30"]).fps_cap, Some(30.0));
+    }
+
+    #[test]
+    fn switches_take_no_value() {
+        let options = parse_ok(&["--prerecord", "--depth-prepass"]);
+        assert!(options.prerecord);
+        assert!(options.depth_prepass);
+    }
+
+    #[test]
+    fn force_software_selects_the_software_gpu() {
+        assert_eq!(
+            parse_ok(&["--force-software"]).gpu,
+            Some(GpuSelector::Software)
+        );
+    }
+
+    #[test]
+    fn frame_rates_are_validated() {
+        assert_eq!(parse_ok(&["--fps-cap", "0.1"]).fps_cap, Some(0.1));
+        assert_eq!(parse_ok(&["--idle-fps", "0"]).idle_fps, 0.0);
+        assert_eq!(parse_ok(&["--idle-fps", "10000"]).idle_fps, 10000.0);
+        for value in ["0", "-30", "1e-300", "1e300", "NaN", "inf"] {
+            assert_eq!(
+                parse(&["--fps-cap", value]).unwrap_err(),
+                "--fps-cap must be from 0.1 to 10000"
+            );
+        }
+        for value in ["-30", "1e-300", "1e300", "NaN", "inf"] {
+            assert_eq!(
+                parse(&["--idle-fps", value]).unwrap_err(),
+                "--idle-fps must be 0 or from 0.1 to 10000"
+            );
+        }
+    }
+
+    #[test]
+    fn stereo_separation_is_validated() {
+        assert_eq!(parse_ok(&["--stereo", "0.05"]).stereo, Some(0.05));
+        assert_eq!(parse_ok(&["--stereo", "0"]).stereo, Some(0.0));
+        for value in ["-0.1", "2", "NaN"] {
+            assert_eq!(
+                parse(&["--stereo", value]).unwrap_err(),
+                "--stereo must be at least 0 and less than 2"
+            );
+        }
+        assert_eq!(
+            parse(&["--stereo", "wide"]).unwrap_err(),
+            "--stereo expects a number, got 'wide'"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_arguments() {
+        assert_eq!(
+            parse(&["--frobnicate"]).unwrap_err(),
+            "unknown argument '--frobnicate'"
+        );
+    }
+
+    #[test]
+    fn rejects_a_missing_value() {
+        assert_eq!(parse(&["--gpu"]).unwrap_err(), "--gpu expects a value");
+    }
+
+    #[test]
+    fn rejects_out_of_range_numbers() {
+        assert_eq!(
+            parse(&["--frames-in-flight", "0"]).unwrap_err(),
+            "--frames-in-flight must be at least 1"
+        );
+        assert_eq!(
+            parse(&["--outline", "1"]).unwrap_err(),
+            "--outline must be greater than 1"
+        );
+        assert_eq!(
+            parse(&["--environment-map", "0"]).unwrap_err(),
+            "--environment-map must be positive"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        assert!(parse(&["--topology", "quad-list"])
+            .unwrap_err()
+            .contains("got 'quad-list'"));
+        assert_eq!(
+            parse(&["--front-face", "up"]).unwrap_err(),
+            "--front-face expects ccw or cw, got 'up'"
+        );
+    }
+
+    #[test]
+    fn rejects_conflicting_flags() {
+        assert_eq!(
+            parse(&["--record-input", "a", "--replay-input", "b"]).unwrap_err(),
+            "--record-input and --replay-input can't be combined"
+        );
+    }
+}
@@ -0,0 +1,10 @@
+use std::{env, path::PathBuf};
+
+/// `name` in the per-user config directory, where state kept between runs goes.
+pub fn config_file(name: &str) -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .or_else(|| env::var_os("APPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("cool_vulkano_example").join(name))
+}
@@ -0,0 +1,83 @@
+use crate::renderer::{InstanceData, Vertex};
+use std::f32::consts::TAU;
+
+/// Line segments per circle.
+const CIRCLE_SEGMENTS: usize = 32;
+
+const INSTANCE_COLOR: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+const SCENE_COLOR: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+
+/// Immediate-mode overlay in clip space. Shapes added during a frame are drawn as
+/// lines on top of the scene, then cleared for the next one.
+#[derive(Default)]
+pub struct DebugDraw {
+    /// Pairs of line end points.
+    vertices: Vec<Vertex>,
+}
+
+impl DebugDraw {
+    pub fn line(&mut self, a: [f32; 2], b: [f32; 2], color: [f32; 4]) {
+        self.vertices.push(Vertex { position: a, color });
+        self.vertices.push(Vertex { position: b, color });
+    }
+
+    /// Outline of a quadrilateral through `corners` in order.
+    pub fn quad(&mut self, corners: [[f32; 2]; 4], color: [f32; 4]) {
+        for i in 0..4 {
+            self.line(corners[i], corners[(i + 1) % 4], color);
+        }
+    }
+
+    /// Outline of the axis-aligned rectangle from `min` to `max`.
+    pub fn rect(&mut self, min: [f32; 2], max: [f32; 2], color: [f32; 4]) {
+        self.quad([min, [max[0], min[1]], max, [min[0], max[1]]], color);
+    }
+
+    pub fn circle(&mut self, center: [f32; 2], radius: f32, color: [f32; 4]) {
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+            [
+                center[0] + radius * angle.cos(),
+                center[1] + radius * angle.sin(),
+            ]
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// Outlines each instance's `extent`, a half-size in the mesh's space before its
+    /// basis is applied, and the axis-aligned box around all of them.
+    pub fn instance_bounds(&mut self, instances: &[InstanceData], extent: [f32; 2]) {
+        let mut min = [f32::INFINITY; 2];
+        let mut max = [f32::NEG_INFINITY; 2];
+        for instance in instances {
+            let corners = [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]].map(|[x, y]| {
+                let (x, y) = (x * extent[0], y * extent[1]);
+                [
+                    instance.translation[0] + instance.basis_x[0] * x + instance.basis_y[0] * y,
+                    instance.translation[1] + instance.basis_x[1] * x + instance.basis_y[1] * y,
+                ]
+            });
+            for corner in corners {
+                for axis in 0..2 {
+                    min[axis] = min[axis].min(corner[axis]);
+                    max[axis] = max[axis].max(corner[axis]);
+                }
+            }
+            self.quad(corners, INSTANCE_COLOR);
+        }
+        if !instances.is_empty() {
+            self.rect(min, max, SCENE_COLOR);
+        }
+    }
+
+    /// The line list accumulated this frame.
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{renderer::HdrFrame, video};
+use ::exr::{
+    error::UnitResult,
+    prelude::{Image, SpecificChannels, Vec2, WritableImage},
+};
+use half::f16;
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+/// Writes `frame` as an OpenEXR image to `path`, or to the first free path with a
+/// number appended if something's there, returning where it went. The channels are
+/// half floats, compressed losslessly, with the sRGB-encoded values the output pass
+/// read decoded to linear, as OpenEXR's are.
+pub fn save_frame(path: &Path, frame: &HdrFrame) -> Result<PathBuf, String> {
+    let path = video::free_path(path);
+    let file = File::create(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    write(
+        BufWriter::new(file),
+        frame.extent,
+        &to_linear(&frame.to_rgba_f16()),
+    )
+    .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// RGBA half floats, as their bits, with the color decoded from sRGB as the output
+/// pass does for HDR displays: past 1 too, and negatives as 0. Alpha is kept.
+fn to_linear(pixels: &[u16]) -> Vec<u16> {
+    pixels
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let [r, g, b, a] = [pixel[0], pixel[1], pixel[2], pixel[3]];
+            let linear = [r, g, b].map(|c| {
+                let c = f16::from_bits(c).to_f32().max(0.0);
+                let c = if c > 0.04045 {
+                    ((c + 0.055) / 1.055).powf(2.4)
+                } else {
+                    c / 12.92
+                };
+                f16::from_f32(c).to_bits()
+            });
+            [linear[0], linear[1], linear[2], a]
+        })
+        .collect()
+}
+
+/// Writes an image of `extent` from `pixels`, rows of RGBA half floats as their
+/// bits.
+fn write(out: impl Write + Seek, [width, height]: [u32; 2], pixels: &[u16]) -> UnitResult {
+    // exr's own half crate, a newer one than the renderer's.
+    let half = ::exr::prelude::f16::from_bits;
+    let channels = SpecificChannels::rgba(|Vec2(x, y)| {
+        let pixel = &pixels[4 * (y * width as usize + x)..][..4];
+        (
+            half(pixel[0]),
+            half(pixel[1]),
+            half(pixel[2]),
+            half(pixel[3]),
+        )
+    });
+    Image::from_channels((width as usize, height as usize), channels)
+        .write()
+        .to_buffered(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::exr::{
+        meta::attribute::SampleType,
+        prelude::{read, ReadChannels, ReadLayers},
+    };
+    use std::io::Cursor;
+
+    fn half(value: f32) -> u16 {
+        f16::from_f32(value).to_bits()
+    }
+
+    #[test]
+    fn round_trips_through_the_file() {
+        let [width, height] = [3u32, 2];
+        let pixels: Vec<u16> = (0..width * height * 4)
+            .map(|i| half(i as f32 * 0.25))
+            .collect();
+        let mut bytes = Vec::new();
+        write(Cursor::new(&mut bytes), [width, height], &pixels).unwrap();
+
+        let image = read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .rgba_channels(
+                |size, _| (size.width(), vec![0; 4 * size.area()]),
+                |(width, read): &mut (usize, Vec<u16>),
+                 Vec2(x, y),
+                 (r, g, b, a): (
+                    ::exr::prelude::f16,
+                    ::exr::prelude::f16,
+                    ::exr::prelude::f16,
+                    ::exr::prelude::f16,
+                )| {
+                    let offset = 4 * (y * *width + x);
+                    read[offset..offset + 4].copy_from_slice(&[r, g, b, a].map(|c| c.to_bits()));
+                },
+            )
+            .first_valid_layer()
+            .all_attributes()
+            .from_buffered(Cursor::new(bytes))
+            .unwrap();
+
+        let layer = &image.layer_data;
+        assert_eq!(layer.size, Vec2(width as usize, height as usize));
+        let (r, g, b, a) = &layer.channel_data.channels;
+        let alpha = a.as_ref().unwrap();
+        assert!([r, g, b, alpha]
+            .iter()
+            .all(|channel| channel.sample_type == SampleType::F16));
+        assert_eq!(layer.channel_data.pixels.1, pixels);
+    }
+
+    #[test]
+    fn decodes_srgb_to_linear() {
+        let linear = to_linear(&[half(0.0), half(0.5), half(1.0), half(0.5)]);
+        let linear: Vec<f32> = linear
+            .iter()
+            .map(|&bits| f16::from_bits(bits).to_f32())
+            .collect();
+        assert_eq!(linear[0], 0.0);
+        assert!((linear[1] - 0.214).abs() < 1e-3, "{}", linear[1]);
+        assert_eq!(linear[2], 1.0);
+        // Alpha isn't a color.
+        assert_eq!(linear[3], 0.5);
+    }
+
+    #[test]
+    fn decodes_hdr_and_negative_values() {
+        let linear = to_linear(&[half(0.02), half(2.0), half(-0.5), half(1.0)]);
+        let linear: Vec<f32> = linear
+            .iter()
+            .map(|&bits| f16::from_bits(bits).to_f32())
+            .collect();
+        assert!((linear[0] - 0.02 / 12.92).abs() < 1e-5, "{}", linear[0]);
+        assert!(linear[1] > 4.0, "{}", linear[1]);
+        assert_eq!(linear[2], 0.0);
+    }
+}
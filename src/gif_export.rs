@@ -0,0 +1,100 @@
+use crate::{renderer::CapturedFrame, simulation::TICK_RATE};
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+use tracing::info;
+
+/// Frames per second of the GIF. Its delays are in hundredths of a second, so this
+/// has to divide both 100 and the tick rate.
+const FRAME_RATE: u32 = 20;
+
+/// Simulation ticks between two frames of the GIF.
+pub const TICKS_PER_FRAME: u32 = TICK_RATE as u32 / FRAME_RATE;
+
+/// In hundredths of a second.
+const FRAME_DELAY: u16 = (100 / FRAME_RATE) as u16;
+
+/// How finely NeuQuant samples each frame for its palette, from 1, the best colors,
+/// to 30, the fastest.
+const QUANTIZATION_SPEED: i32 = 10;
+
+/// Encodes the frames of `--export-gif` as they're read back, each quantized to a
+/// palette of its own, into a GIF that loops forever.
+pub struct GifExport {
+    path: PathBuf,
+    /// Until the first frame gives the size of the GIF.
+    file: Option<BufWriter<File>>,
+    encoder: Option<gif::Encoder<BufWriter<File>>>,
+    extent: [u16; 2],
+    frames: u32,
+    requested: u32,
+    written: u32,
+}
+
+impl GifExport {
+    /// Creates `path` for a GIF lasting `seconds`.
+    pub fn new(path: &Path, seconds: f32) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(GifExport {
+            path: path.to_owned(),
+            file: Some(BufWriter::new(file)),
+            encoder: None,
+            extent: [0, 0],
+            frames: (seconds * FRAME_RATE as f32).ceil().max(1.0) as u32,
+            requested: 0,
+            written: 0,
+        })
+    }
+
+    /// Whether the frame about to be drawn should be captured, counting it if so.
+    pub fn wants_frame(&mut self) -> bool {
+        let wanted = self.requested < self.frames;
+        if wanted {
+            self.requested += 1;
+        }
+        wanted
+    }
+
+    /// Quantizes and appends `frame`. Later frames are cropped or padded with black
+    /// to the size of the first, should the window be resized midway.
+    pub fn add_frame(&mut self, frame: CapturedFrame) -> Result<(), String> {
+        let encoder = match &mut self.encoder {
+            Some(encoder) => encoder,
+            None => {
+                let extent = frame.extent.map(|side| side.min(u16::MAX as u32) as u16);
+                let file = self.file.take().unwrap();
+                let mut encoder = gif::Encoder::new(file, extent[0], extent[1], &[])
+                    .map_err(|e| e.to_string())?;
+                encoder
+                    .set_repeat(gif::Repeat::Infinite)
+                    .map_err(|e| e.to_string())?;
+                self.extent = extent;
+                self.encoder.insert(encoder)
+            }
+        };
+
+        let [width, height] = self.extent;
+        let mut pixels = frame.to_rgba([width as u32, height as u32]);
+        let mut gif_frame =
+            gif::Frame::from_rgba_speed(width, height, &mut pixels, QUANTIZATION_SPEED);
+        gif_frame.delay = FRAME_DELAY;
+        encoder
+            .write_frame(&gif_frame)
+            .map_err(|e| format!("{}: {}", self.path.display(), e))?;
+
+        self.written += 1;
+        if self.is_done() {
+            // Writes the trailer.
+            self.encoder = None;
+            info!(path = %self.path.display(), frames = self.written, "exported GIF");
+        }
+        Ok(())
+    }
+
+    /// Whether every frame was written and the file is complete.
+    pub fn is_done(&self) -> bool {
+        self.written == self.frames
+    }
+}
@@ -0,0 +1,380 @@
+use crate::renderer::DeviceCapabilities;
+use std::{fmt::Debug, sync::Arc};
+use tracing::warn;
+use vulkano::{
+    device::{
+        physical::{PhysicalDevice, PhysicalDeviceType, QueueFamily},
+        Device, DeviceExtensions,
+    },
+    instance::Instance,
+    swapchain::{Surface, SurfaceInfo},
+};
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// How the user asked for a GPU: by its enumeration index, by part of its name, or
+/// for a CPU implementation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GpuSelector {
+    Index(usize),
+    Name(String),
+    /// The first device that rasterizes on the CPU, such as lavapipe or SwiftShader,
+    /// which draw the same wherever they run. Only preferred: without one, the
+    /// device is picked as if nothing was asked for.
+    Software,
+}
+
+impl GpuSelector {
+    pub fn parse(value: &str) -> Self {
+        match value.trim().parse() {
+            Ok(index) => GpuSelector::Index(index),
+            Err(_) => GpuSelector::Name(value.trim().to_lowercase()),
+        }
+    }
+
+    fn matches(&self, physical_device: &PhysicalDevice) -> bool {
+        match self {
+            GpuSelector::Index(index) => physical_device.index() == *index,
+            GpuSelector::Name(name) => physical_device
+                .properties()
+                .device_name
+                .to_lowercase()
+                .contains(name.as_str()),
+            GpuSelector::Software => {
+                physical_device.properties().device_type == PhysicalDeviceType::Cpu
+            }
+        }
+    }
+}
+
+fn device_type_rank(device_type: PhysicalDeviceType) -> u32 {
+    match device_type {
+        PhysicalDeviceType::DiscreteGpu => 0,
+        PhysicalDeviceType::IntegratedGpu => 1,
+        PhysicalDeviceType::VirtualGpu => 2,
+        PhysicalDeviceType::Cpu => 3,
+        PhysicalDeviceType::Other => 4,
+    }
+}
+
+pub fn print_gpus(instance: &Arc<Instance>) {
+    println!("Available devices:");
+    for physical_device in PhysicalDevice::enumerate(instance) {
+        let properties = physical_device.properties();
+        println!(
+            "  [{}] {} (type: {:?}, Vulkan {})",
+            physical_device.index(),
+            properties.device_name,
+            properties.device_type,
+            physical_device.api_version(),
+        );
+    }
+}
+
+/// Prints what a bug report needs to know about `device`: every property and limit,
+/// the optional capabilities, features and extensions the renderer enabled, the
+/// memory heaps and types, the queue families, and the formats and present modes
+/// `surface` supports.
+pub fn print_info<W>(
+    device: &Arc<Device>,
+    surface: &Arc<Surface<W>>,
+    capabilities: &DeviceCapabilities,
+) {
+    let physical_device = device.physical_device();
+    let properties = physical_device.properties();
+    println!(
+        "[{}] {} (Vulkan {})",
+        physical_device.index(),
+        properties.device_name,
+        physical_device.api_version(),
+    );
+
+    println!("\nProperties:");
+    // Properties the device didn't report are `None`.
+    let properties: Vec<_> = debug_fields(properties)
+        .into_iter()
+        .filter(|(_, value)| value != "None")
+        .map(|(name, value)| {
+            let value = value
+                .strip_prefix("Some(")
+                .and_then(|value| value.strip_suffix(')'))
+                .map_or(value.clone(), str::to_owned);
+            (name, value)
+        })
+        .collect();
+    let width = properties
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    for (name, value) in &properties {
+        println!("  {:<width$}  {}", name, value, width = width);
+    }
+
+    println!("\nCapabilities:");
+    for (name, value) in debug_fields(capabilities) {
+        println!("  {:<20}  {}", name, value);
+    }
+
+    println!("\nEnabled features:");
+    for (name, _) in debug_fields(device.enabled_features())
+        .into_iter()
+        .filter(|(_, value)| value == "true")
+    {
+        println!("  {}", name);
+    }
+
+    println!("\nEnabled extensions:");
+    let extensions = format!("{:?}", device.enabled_extensions());
+    for extension in extensions
+        .trim_matches(|c| c == '[' || c == ']')
+        .split(", ")
+        .filter(|extension| !extension.is_empty())
+    {
+        println!("  {}", extension);
+    }
+
+    println!("\nMemory heaps:");
+    println!("  {:<4}  {:>10}  flags", "heap", "size");
+    for heap in physical_device.memory_heaps() {
+        let flags = [
+            (heap.is_device_local(), "device-local"),
+            (heap.is_multi_instance(), "multi-instance"),
+        ];
+        println!(
+            "  {:<4}  {:>6.0} MiB  {}",
+            heap.id(),
+            heap.size() as f64 / MIB,
+            flag_names(&flags),
+        );
+    }
+
+    println!("\nMemory types:");
+    println!("  {:<4}  {:<4}  flags", "type", "heap");
+    for memory_type in physical_device.memory_types() {
+        let flags = [
+            (memory_type.is_device_local(), "device-local"),
+            (memory_type.is_host_visible(), "host-visible"),
+            (memory_type.is_host_coherent(), "host-coherent"),
+            (memory_type.is_host_cached(), "host-cached"),
+            (memory_type.is_lazily_allocated(), "lazily-allocated"),
+        ];
+        println!(
+            "  {:<4}  {:<4}  {}",
+            memory_type.id(),
+            memory_type.heap().id(),
+            flag_names(&flags),
+        );
+    }
+
+    println!("\nQueue families:");
+    println!(
+        "  {:<6}  {:<6}  {:<14}  {:<11}  capabilities",
+        "family", "queues", "timestamp bits", "granularity"
+    );
+    for family in physical_device.queue_families() {
+        let flags = [
+            (family.supports_graphics(), "graphics"),
+            (family.supports_compute(), "compute"),
+            (family.explicitly_supports_transfers(), "transfer"),
+            (family.supports_sparse_binding(), "sparse-binding"),
+            (family.supports_surface(surface).unwrap_or(false), "present"),
+        ];
+        let [width, height, depth] = family.min_image_transfer_granularity();
+        println!(
+            "  {:<6}  {:<6}  {:<14}  {:<11}  {}",
+            family.id(),
+            family.queues_count(),
+            family
+                .timestamp_valid_bits()
+                .map_or("none".to_owned(), |bits| bits.to_string()),
+            format!("{}x{}x{}", width, height, depth),
+            flag_names(&flags),
+        );
+    }
+
+    println!("\nSurface formats:");
+    match physical_device.surface_formats(surface, SurfaceInfo::default()) {
+        Ok(formats) => {
+            for (format, color_space) in formats {
+                println!("  {:<28}  {:?}", format!("{:?}", format), color_space);
+            }
+        }
+        Err(e) => println!("  unavailable: {}", e),
+    }
+
+    println!("\nPresent modes:");
+    match physical_device.surface_present_modes(surface) {
+        Ok(present_modes) => {
+            for present_mode in present_modes {
+                println!("  {:?}", present_mode);
+            }
+        }
+        Err(e) => println!("  unavailable: {}", e),
+    }
+}
+
+/// The top-level fields of `value`'s pretty-printed `Debug` output, each with its
+/// value on one line.
+fn debug_fields(value: &impl Debug) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in format!("{:#?}", value).lines().skip(1) {
+        let field = line
+            .strip_prefix("    ")
+            .filter(|line| !line.starts_with(' '))
+            .and_then(|line| line.split_once(": "));
+        match (field, fields.last_mut()) {
+            (Some((name, value)), _) => fields.push((name.to_owned(), value.to_owned())),
+            // Continues a multi-line value, unless it closes the struct itself.
+            (None, Some((_, value))) if line != "}" => {
+                value.push_str(line.trim());
+                if value.ends_with(',') || value.ends_with('{') {
+                    value.push(' ');
+                }
+            }
+            (None, _) => {}
+        }
+    }
+    for (_, value) in &mut fields {
+        *value = value
+            .trim_end()
+            .trim_end_matches(',')
+            .replace(", ]", "]")
+            .replace(", )", ")")
+            .replace(", }", " }");
+    }
+    fields
+}
+
+fn flag_names(flags: &[(bool, &str)]) -> String {
+    flags
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Picks the physical device and graphics queue family to render to `surface` with.
+///
+/// When `selector` is `None` the most capable device type wins, as it does for
+/// [`GpuSelector::Software`] without a software device. Otherwise the selected
+/// device is used and an error explains why it can't be.
+pub fn select<'a, W>(
+    instance: &'a Arc<Instance>,
+    surface: &Arc<Surface<W>>,
+    device_extensions: &DeviceExtensions,
+    selector: Option<&GpuSelector>,
+) -> Result<(PhysicalDevice<'a>, QueueFamily<'a>), String> {
+    select_with_queue(
+        instance,
+        device_extensions,
+        selector,
+        |queue_family| {
+            queue_family.supports_graphics()
+                && queue_family.supports_surface(surface).unwrap_or(false)
+        },
+        "can present to the window",
+    )
+}
+
+/// Like [`select`], for rendering without a window.
+pub fn select_headless<'a>(
+    instance: &'a Arc<Instance>,
+    device_extensions: &DeviceExtensions,
+    selector: Option<&GpuSelector>,
+) -> Result<(PhysicalDevice<'a>, QueueFamily<'a>), String> {
+    select_with_queue(
+        instance,
+        device_extensions,
+        selector,
+        |queue_family| queue_family.supports_graphics(),
+        "supports graphics",
+    )
+}
+
+/// Every device with a graphics queue family, most capable type first, with that
+/// family.
+pub fn headless_candidates<'a>(
+    instance: &'a Arc<Instance>,
+) -> Vec<(PhysicalDevice<'a>, QueueFamily<'a>)> {
+    let mut candidates: Vec<_> = PhysicalDevice::enumerate(instance)
+        .filter_map(|physical_device| {
+            physical_device
+                .queue_families()
+                .find(QueueFamily::supports_graphics)
+                .map(|queue_family| (physical_device, queue_family))
+        })
+        .collect();
+    candidates.sort_by_key(|(physical_device, _)| {
+        device_type_rank(physical_device.properties().device_type)
+    });
+    candidates
+}
+
+/// `queue_requirement` completes "has no queue family that ..." in the error.
+fn select_with_queue<'a>(
+    instance: &'a Arc<Instance>,
+    device_extensions: &DeviceExtensions,
+    selector: Option<&GpuSelector>,
+    suitable_queue: impl Fn(&QueueFamily) -> bool,
+    queue_requirement: &str,
+) -> Result<(PhysicalDevice<'a>, QueueFamily<'a>), String> {
+    let graphics_queue_family = |physical_device: PhysicalDevice<'a>| {
+        physical_device
+            .queue_families()
+            .find(|queue_family| suitable_queue(queue_family))
+    };
+
+    let most_capable = || {
+        PhysicalDevice::enumerate(instance)
+            .filter(|&physical_device| {
+                physical_device
+                    .supported_extensions()
+                    .is_superset_of(device_extensions)
+            })
+            .filter_map(|physical_device| {
+                graphics_queue_family(physical_device)
+                    .map(|queue_family| (physical_device, queue_family))
+            })
+            .min_by_key(|(physical_device, _)| {
+                device_type_rank(physical_device.properties().device_type)
+            })
+            .ok_or_else(|| "no suitable Vulkan device found".to_owned())
+    };
+
+    let selector = match selector {
+        None => return most_capable(),
+        Some(selector) => selector,
+    };
+
+    let physical_device = match PhysicalDevice::enumerate(instance)
+        .find(|physical_device| selector.matches(physical_device))
+    {
+        Some(physical_device) => physical_device,
+        None if *selector == GpuSelector::Software => {
+            warn!(
+                "no software Vulkan implementation (lavapipe, SwiftShader) found, \
+                 using the most capable device"
+            );
+            return most_capable();
+        }
+        None => return Err(format!("no Vulkan device matches {:?}", selector)),
+    };
+    let name = &physical_device.properties().device_name;
+
+    if !physical_device
+        .supported_extensions()
+        .is_superset_of(device_extensions)
+    {
+        return Err(format!(
+            "{} does not support the required device extensions",
+            name
+        ));
+    }
+
+    let queue_family = graphics_queue_family(physical_device)
+        .ok_or_else(|| format!("{} has no queue family that {}", name, queue_requirement))?;
+
+    Ok((physical_device, queue_family))
+}
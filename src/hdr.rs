@@ -0,0 +1,330 @@
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+use vulkano::{
+    format::{Format, NumericType},
+    instance::InstanceExtensions,
+    swapchain::{ColorSpace, Surface},
+};
+
+/// Paper white used when the user doesn't pass `--paper-white`.
+pub const DEFAULT_PAPER_WHITE_NITS: f32 = 200.0;
+
+/// The display encoding the output pass writes, and the surface color space it needs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayOutput {
+    /// Picked by the [`SurfaceFormatPolicy`]; the scene is written as it is, decoded
+    /// first for formats the hardware encodes to sRGB.
+    Sdr,
+    /// Rec. 2020 primaries with the SMPTE ST 2084 (PQ) curve.
+    Hdr10,
+    /// Linear extended sRGB, where 1.0 is 80 nits and values may exceed it.
+    ScRgb,
+}
+
+impl DisplayOutput {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "sdr" => Some(DisplayOutput::Sdr),
+            "hdr10" => Some(DisplayOutput::Hdr10),
+            "scrgb" => Some(DisplayOutput::ScRgb),
+            _ => None,
+        }
+    }
+
+    fn color_space(self) -> ColorSpace {
+        match self {
+            DisplayOutput::Sdr => ColorSpace::SrgbNonLinear,
+            DisplayOutput::Hdr10 => ColorSpace::Hdr10St2084,
+            DisplayOutput::ScRgb => ColorSpace::ExtendedSrgbLinear,
+        }
+    }
+
+    /// Formats with enough precision for the color space, best first.
+    fn formats(self) -> &'static [Format] {
+        match self {
+            DisplayOutput::Sdr => &[],
+            DisplayOutput::Hdr10 => &[
+                Format::A2B10G10R10_UNORM_PACK32,
+                Format::A2R10G10B10_UNORM_PACK32,
+                Format::R16G16B16A16_SFLOAT,
+            ],
+            DisplayOutput::ScRgb => &[Format::R16G16B16A16_SFLOAT],
+        }
+    }
+
+    /// The transfer function the output shader applies, see `OutputParams::transfer`.
+    pub fn transfer(color_space: ColorSpace) -> u32 {
+        match color_space {
+            ColorSpace::Hdr10St2084 => 1,
+            ColorSpace::ExtendedSrgbLinear => 2,
+            _ => 0,
+        }
+    }
+}
+
+/// A rule [`SurfaceFormatPolicy`] picks SDR surface formats by.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FormatPreference {
+    /// One of these formats, earlier ones first.
+    Formats(Vec<Format>),
+    /// A format the hardware encodes to sRGB as it's written.
+    Srgb,
+    /// A format with at least 10 bits per color channel, against banding.
+    TenBit,
+}
+
+/// Swapchain formats the surface lists for SDR output.
+const NAMED_FORMATS: [Format; 7] = [
+    Format::B8G8R8A8_SRGB,
+    Format::B8G8R8A8_UNORM,
+    Format::R8G8B8A8_SRGB,
+    Format::R8G8B8A8_UNORM,
+    Format::A2B10G10R10_UNORM_PACK32,
+    Format::A2R10G10B10_UNORM_PACK32,
+    Format::R16G16B16A16_SFLOAT,
+];
+
+impl FormatPreference {
+    /// `srgb`, `10bit`, or a format name like `B8G8R8A8_UNORM`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        match value.to_lowercase().as_str() {
+            "srgb" => Some(FormatPreference::Srgb),
+            "10bit" => Some(FormatPreference::TenBit),
+            _ => NAMED_FORMATS
+                .into_iter()
+                .find(|format| format!("{:?}", format).eq_ignore_ascii_case(value))
+                .map(|format| FormatPreference::Formats(vec![format])),
+        }
+    }
+
+    /// The supported format it picks, if any.
+    fn pick(&self, supported: &[(Format, ColorSpace)]) -> Option<(Format, ColorSpace)> {
+        match self {
+            FormatPreference::Formats(formats) => formats.iter().find_map(|&format| {
+                supported
+                    .iter()
+                    .copied()
+                    .find(|&(candidate, _)| candidate == format)
+            }),
+            FormatPreference::Srgb => supported
+                .iter()
+                .copied()
+                .find(|(format, _)| format.type_color() == Some(NumericType::SRGB)),
+            FormatPreference::TenBit => supported
+                .iter()
+                .copied()
+                .find(|(format, _)| format.components()[0] >= 10),
+        }
+    }
+}
+
+/// How the SDR surface format is picked out of those the surface supports in the
+/// sRGB color space, which HDR outputs fall back to as well. The preferences are
+/// tried in order, and the first a supported format meets picks it; with none met
+/// the surface's first sRGB format is taken, or its first at all if it has none.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SurfaceFormatPolicy {
+    pub preferences: Vec<FormatPreference>,
+}
+
+impl Default for SurfaceFormatPolicy {
+    /// sRGB encoding, as 8 bits then go further in the darks, blending happens on
+    /// linear values, and frames can be captured from 8 bit formats.
+    fn default() -> Self {
+        SurfaceFormatPolicy {
+            preferences: vec![FormatPreference::Srgb],
+        }
+    }
+}
+
+impl SurfaceFormatPolicy {
+    /// A comma separated list of [`FormatPreference::parse`]'s, in order.
+    pub fn parse(value: &str) -> Option<Self> {
+        let preferences = value
+            .split(',')
+            .map(FormatPreference::parse)
+            .collect::<Option<_>>()?;
+        Some(SurfaceFormatPolicy { preferences })
+    }
+}
+
+/// Extensions needed to see and create the extended color spaces, enabled when available.
+pub fn optional_instance_extensions() -> InstanceExtensions {
+    InstanceExtensions {
+        ext_swapchain_colorspace: true,
+        ..InstanceExtensions::none()
+    }
+}
+
+/// Picks the surface format for `output` from what the surface supports, by `policy`
+/// for SDR. Falls back to SDR when the display can't do the requested output, and
+/// logs what was picked and why.
+pub fn select_surface_format<W>(
+    surface: &Arc<Surface<W>>,
+    supported: &[(Format, ColorSpace)],
+    output: DisplayOutput,
+    policy: &SurfaceFormatPolicy,
+) -> (Format, ColorSpace) {
+    for (format, color_space) in supported {
+        debug!(?format, ?color_space, "supported surface format");
+    }
+
+    let wanted = output.color_space();
+    let found = output.formats().iter().find_map(|&format| {
+        supported
+            .iter()
+            .copied()
+            .find(|&candidate| candidate == (format, wanted))
+    });
+
+    if let Some((format, color_space)) = found {
+        info!(
+            ?format,
+            ?color_space,
+            reason = "display output",
+            "picked surface format"
+        );
+        return (format, color_space);
+    }
+    if output != DisplayOutput::Sdr {
+        let extension_enabled = surface
+            .instance()
+            .enabled_extensions()
+            .ext_swapchain_colorspace;
+        warn!(
+            ?output,
+            extension_enabled, "surface doesn't support the display output, using SDR"
+        );
+    }
+
+    let ((format, color_space), preference) = pick_sdr(supported, policy);
+    match preference {
+        Some(preference) => {
+            info!(?format, ?color_space, reason = ?preference, "picked surface format");
+        }
+        None => {
+            info!(
+                ?format,
+                ?color_space,
+                reason = "no preference met, the surface's first sRGB one",
+                "picked surface format"
+            );
+        }
+    }
+    (format, color_space)
+}
+
+/// The SDR format `policy` picks out of `supported`, with the preference that
+/// picked it, or the surface's first format in the sRGB color space and `None`.
+/// Only a surface without any falls back to its first format whatever its color
+/// space, which the output pass then writes unchanged.
+fn pick_sdr<'a>(
+    supported: &[(Format, ColorSpace)],
+    policy: &'a SurfaceFormatPolicy,
+) -> ((Format, ColorSpace), Option<&'a FormatPreference>) {
+    let sdr: Vec<_> = supported
+        .iter()
+        .copied()
+        .filter(|&(_, color_space)| color_space == ColorSpace::SrgbNonLinear)
+        .collect();
+    policy
+        .preferences
+        .iter()
+        .find_map(|preference| Some((preference.pick(&sdr)?, Some(preference))))
+        .unwrap_or((sdr.first().copied().unwrap_or(supported[0]), None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRGB: ColorSpace = ColorSpace::SrgbNonLinear;
+
+    fn pick(policy: &str, supported: &[(Format, ColorSpace)]) -> (Format, ColorSpace) {
+        pick_sdr(supported, &SurfaceFormatPolicy::parse(policy).unwrap()).0
+    }
+
+    #[test]
+    fn parses_preferences_in_order() {
+        assert_eq!(
+            SurfaceFormatPolicy::parse("10bit, b8g8r8a8_unorm,SRGB"),
+            Some(SurfaceFormatPolicy {
+                preferences: vec![
+                    FormatPreference::TenBit,
+                    FormatPreference::Formats(vec![Format::B8G8R8A8_UNORM]),
+                    FormatPreference::Srgb,
+                ]
+            })
+        );
+        assert_eq!(
+            SurfaceFormatPolicy::parse("srgb"),
+            Some(SurfaceFormatPolicy::default())
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_preferences() {
+        assert_eq!(SurfaceFormatPolicy::parse(""), None);
+        assert_eq!(SurfaceFormatPolicy::parse("srgb,"), None);
+        assert_eq!(SurfaceFormatPolicy::parse("12bit"), None);
+        // A real format, but not one surfaces list for SDR.
+        assert_eq!(FormatPreference::parse("R8_UNORM"), None);
+    }
+
+    #[test]
+    fn picks_by_the_first_preference_met() {
+        let supported = [
+            (Format::B8G8R8A8_UNORM, SRGB),
+            (Format::B8G8R8A8_SRGB, SRGB),
+            (Format::A2B10G10R10_UNORM_PACK32, SRGB),
+        ];
+        assert_eq!(pick("srgb", &supported).0, Format::B8G8R8A8_SRGB);
+        assert_eq!(
+            pick("10bit,srgb", &supported).0,
+            Format::A2B10G10R10_UNORM_PACK32
+        );
+        assert_eq!(
+            pick("r8g8b8a8_srgb,b8g8r8a8_unorm", &supported).0,
+            Format::B8G8R8A8_UNORM
+        );
+    }
+
+    #[test]
+    fn only_picks_srgb_color_space_formats() {
+        let supported = [
+            (Format::A2B10G10R10_UNORM_PACK32, ColorSpace::Hdr10St2084),
+            (Format::B8G8R8A8_UNORM, SRGB),
+            (Format::A2B10G10R10_UNORM_PACK32, SRGB),
+        ];
+        assert_eq!(
+            pick("10bit", &supported),
+            (Format::A2B10G10R10_UNORM_PACK32, SRGB)
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_surfaces_first_srgb_format() {
+        let supported = [
+            (Format::R16G16B16A16_SFLOAT, ColorSpace::ExtendedSrgbLinear),
+            (Format::B8G8R8A8_UNORM, SRGB),
+            (Format::R8G8B8A8_UNORM, SRGB),
+        ];
+        let policy = SurfaceFormatPolicy::default();
+        let (picked, preference) = pick_sdr(&supported, &policy);
+        assert_eq!(picked, (Format::B8G8R8A8_UNORM, SRGB));
+        assert_eq!(preference, None);
+    }
+
+    #[test]
+    fn falls_back_to_the_surfaces_first_format_without_srgb_ones() {
+        let supported = [
+            (Format::R16G16B16A16_SFLOAT, ColorSpace::ExtendedSrgbLinear),
+            (Format::A2B10G10R10_UNORM_PACK32, ColorSpace::Hdr10St2084),
+        ];
+        let policy = SurfaceFormatPolicy::default();
+        let (picked, preference) = pick_sdr(&supported, &policy);
+        assert_eq!(picked, supported[0]);
+        assert_eq!(preference, None);
+    }
+}
@@ -0,0 +1,217 @@
+use crate::{simulation::Input, window::WindowMetrics};
+use std::collections::HashMap;
+use winit::{
+    dpi::PhysicalPosition,
+    error::ExternalError,
+    event::{MouseScrollDelta, Touch, TouchPhase},
+    window::Window,
+};
+
+/// Smallest and largest zoom the scroll wheel and pinching can reach.
+pub const ZOOM_RANGE: (f32, f32) = (0.1, 10.0);
+
+/// Logical pixels of touchpad scrolling that count as one wheel line.
+const PIXELS_PER_LINE: f64 = 40.0;
+
+/// How the OS cursor behaves while the window has focus. Cycled with Tab.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CursorMode {
+    /// Visible and free to leave the window.
+    #[default]
+    Free,
+    /// Visible but kept inside the window.
+    Confined,
+    /// Hidden and grabbed; the app moves by raw mouse deltas instead of positions,
+    /// so motion doesn't stop at the window edge. Suited to camera controls.
+    Locked,
+}
+
+impl CursorMode {
+    pub fn next(self) -> Self {
+        match self {
+            CursorMode::Free => CursorMode::Confined,
+            CursorMode::Confined => CursorMode::Locked,
+            CursorMode::Locked => CursorMode::Free,
+        }
+    }
+
+    /// winit only has one grab mode, which confines on some platforms and locks on
+    /// others; `Locked` additionally hides the cursor and relies on raw deltas.
+    pub fn apply(self, window: &Window) -> Result<(), ExternalError> {
+        window.set_cursor_grab(self != CursorMode::Free)?;
+        window.set_cursor_visible(self != CursorMode::Locked);
+        Ok(())
+    }
+
+    pub fn uses_raw_motion(self) -> bool {
+        self == CursorMode::Locked
+    }
+}
+
+/// Moves a normalized cursor position by a raw mouse delta, treating one unit of
+/// motion as one physical pixel and keeping the result inside the window.
+pub fn apply_mouse_delta(mouse: [f32; 2], delta: (f64, f64), metrics: &WindowMetrics) -> [f32; 2] {
+    let size = metrics.physical_size;
+    if size.width == 0 || size.height == 0 {
+        return mouse;
+    }
+    [
+        (mouse[0] + (delta.0 / size.width as f64) as f32).clamp(0.0, 1.0),
+        (mouse[1] + (delta.1 / size.height as f64) as f32).clamp(0.0, 1.0),
+    ]
+}
+
+/// Converts a scroll event to wheel lines, up being positive.
+pub fn scroll_lines(delta: MouseScrollDelta, metrics: &WindowMetrics) -> f32 {
+    match delta {
+        MouseScrollDelta::LineDelta(_, lines) => lines,
+        MouseScrollDelta::PixelDelta(position) => {
+            (position.to_logical::<f64>(metrics.scale_factor).y / PIXELS_PER_LINE) as f32
+        }
+    }
+}
+
+/// Turns touch events into the same input as the mouse: one finger drags the
+/// cursor position, two fingers pinch to zoom.
+#[derive(Default)]
+pub struct TouchGestures {
+    touches: HashMap<u64, PhysicalPosition<f64>>,
+}
+
+impl TouchGestures {
+    pub fn handle(&mut self, touch: &Touch, metrics: &WindowMetrics, input: &mut Input) {
+        let pinch_before = self.pinch_distance();
+        match touch.phase {
+            TouchPhase::Started | TouchPhase::Moved => {
+                self.touches.insert(touch.id, touch.location);
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.touches.remove(&touch.id);
+                return;
+            }
+        }
+
+        match self.touches.len() {
+            1 => input.mouse = metrics.normalize_cursor(touch.location),
+            2 => {
+                if let (Some(before), Some(after)) = (pinch_before, self.pinch_distance()) {
+                    if before > 0.0 {
+                        input.pinch = (input.pinch * (after / before) as f32)
+                            .clamp(ZOOM_RANGE.0, ZOOM_RANGE.1);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Distance between the fingers, when exactly two are down.
+    fn pinch_distance(&self) -> Option<f64> {
+        let mut touches = self.touches.values();
+        match (touches.next(), touches.next(), touches.next()) {
+            (Some(a), Some(b), None) => Some(((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::{dpi::PhysicalSize, event::DeviceId};
+
+    fn touch(id: u64, phase: TouchPhase, x: f64, y: f64) -> Touch {
+        Touch {
+            // The gestures don't look at which device a touch came from.
+            device_id: unsafe { DeviceId::dummy() },
+            phase,
+            location: PhysicalPosition::new(x, y),
+            force: None,
+            id,
+        }
+    }
+
+    fn metrics() -> WindowMetrics {
+        WindowMetrics {
+            physical_size: PhysicalSize::new(400, 200),
+            scale_factor: 1.0,
+        }
+    }
+
+    #[test]
+    fn one_finger_drags_the_cursor() {
+        let mut gestures = TouchGestures::default();
+        let mut input = Input::default();
+        gestures.handle(
+            &touch(0, TouchPhase::Started, 100.0, 50.0),
+            &metrics(),
+            &mut input,
+        );
+        gestures.handle(
+            &touch(0, TouchPhase::Moved, 200.0, 150.0),
+            &metrics(),
+            &mut input,
+        );
+        assert_eq!(input.mouse, [0.5, 0.75]);
+    }
+
+    #[test]
+    fn pinching_zooms_by_the_change_in_finger_distance() {
+        let mut gestures = TouchGestures::default();
+        let mut input = Input::default();
+        let metrics = metrics();
+        gestures.handle(
+            &touch(0, TouchPhase::Started, 100.0, 100.0),
+            &metrics,
+            &mut input,
+        );
+        gestures.handle(
+            &touch(1, TouchPhase::Started, 150.0, 100.0),
+            &metrics,
+            &mut input,
+        );
+        gestures.handle(
+            &touch(1, TouchPhase::Moved, 200.0, 100.0),
+            &metrics,
+            &mut input,
+        );
+        assert_eq!(input.pinch, 2.0);
+        gestures.handle(
+            &touch(0, TouchPhase::Moved, 150.0, 100.0),
+            &metrics,
+            &mut input,
+        );
+        assert_eq!(input.pinch, 1.0);
+
+        // Lifting a finger ends the pinch without zooming.
+        gestures.handle(
+            &touch(1, TouchPhase::Ended, 300.0, 100.0),
+            &metrics,
+            &mut input,
+        );
+        assert_eq!(input.pinch, 1.0);
+    }
+
+    #[test]
+    fn pinching_stops_at_the_zoom_range() {
+        let mut gestures = TouchGestures::default();
+        let mut input = Input::default();
+        let metrics = metrics();
+        gestures.handle(
+            &touch(0, TouchPhase::Started, 0.0, 0.0),
+            &metrics,
+            &mut input,
+        );
+        gestures.handle(
+            &touch(1, TouchPhase::Started, 1.0, 0.0),
+            &metrics,
+            &mut input,
+        );
+        gestures.handle(
+            &touch(1, TouchPhase::Moved, 100.0, 0.0),
+            &metrics,
+            &mut input,
+        );
+        assert_eq!(input.pinch, ZOOM_RANGE.1);
+    }
+}
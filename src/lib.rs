@@ -0,0 +1,7 @@
+//! Android loads apps as shared libraries, so there the whole app is built as one,
+//! started through `android_main`. Elsewhere this is empty and the binary runs.
+#![cfg(target_os = "android")]
+// `main` is only the binary's entry point.
+#![allow(dead_code)]
+
+include!("main.rs");
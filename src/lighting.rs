@@ -0,0 +1,160 @@
+use crate::{
+    debug_draw::DebugDraw,
+    renderer::{Light, Renderer, MAX_LIGHTS},
+};
+use std::f32::consts::{FRAC_PI_6, PI, TAU};
+use tracing::info;
+use winit::event::VirtualKeyCode;
+
+/// Colors lights are given in turn.
+const PALETTE: [[f32; 3]; 4] = [
+    [1.6, 0.9, 0.4],
+    [0.4, 0.8, 1.8],
+    [1.4, 0.3, 1.0],
+    [0.5, 1.6, 0.6],
+];
+
+const DEFAULT_RADIUS: f32 = 0.8;
+const MIN_RADIUS: f32 = 0.1;
+const MAX_RADIUS: f32 = 3.0;
+
+/// Factor the radius changes by per key press.
+const RADIUS_STEP: f32 = 1.25;
+
+/// Half the angle of the cone of spot lights.
+const SPOT_CONE: f32 = FRAC_PI_6;
+
+/// Radians per second the lights circle the origin, the odd ones the other way.
+const ORBIT: f32 = 0.4;
+
+/// Where each light is marked in the overlay and how big.
+const MARKER_SIZE: f32 = 0.02;
+
+/// The lights shone on the lit material, edited with the keyboard in place of a
+/// panel: K adds one at the cursor, Delete removes the nearest, J switches the
+/// nearest between point and spot, Comma and Period shrink and grow its radius, and
+/// H toggles the heatmap of lights per cluster. Changes are logged, and every light
+/// is marked in the overlay.
+#[derive(Default)]
+pub struct LightRig {
+    lights: Vec<Light>,
+    heatmap: bool,
+    /// When the overlay was last drawn; keys edit the lights where they were shown.
+    shown_at: f32,
+}
+
+impl LightRig {
+    /// `count` lights around the origin, alternately point and spot lights, the
+    /// spots facing it.
+    pub fn new(count: usize) -> Self {
+        let mut rig = LightRig::default();
+        for i in 0..count {
+            let angle = i as f32 / count as f32 * TAU;
+            let (sin, cos) = angle.sin_cos();
+            rig.add([0.6 * cos, 0.6 * sin]);
+            if i % 2 == 1 {
+                let light = rig.lights.last_mut().unwrap();
+                light.cone = SPOT_CONE;
+                light.direction = angle + PI;
+            }
+        }
+        rig
+    }
+
+    /// Hands the lights and the heatmap setting to `renderer`.
+    pub fn apply(&self, renderer: &mut Renderer) {
+        renderer.set_lights(&self.lights);
+        renderer.set_light_heatmap(self.heatmap);
+    }
+
+    /// Edits the lights with `key` at `position` in clip space, returning whether
+    /// they changed. Other keys are ignored.
+    pub fn handle_key(&mut self, key: VirtualKeyCode, position: [f32; 2]) -> bool {
+        let time = self.shown_at;
+        if key == VirtualKeyCode::H {
+            self.heatmap = !self.heatmap;
+            info!(heatmap = self.heatmap, "toggled the light heatmap");
+            return true;
+        }
+        if key == VirtualKeyCode::K {
+            if self.lights.len() == MAX_LIGHTS {
+                info!(MAX_LIGHTS, "can't add more lights");
+                return false;
+            }
+            // Placed where it is now, not where it was at time 0.
+            self.add(position);
+            let light = self.lights.last_mut().unwrap();
+            light.position = light.animated(-time).position;
+            info!(lights = self.lights.len(), "added a light");
+            return true;
+        }
+        let nearest = match self.nearest(position, time) {
+            Some(nearest) => nearest,
+            None => return false,
+        };
+        let light = &mut self.lights[nearest];
+        match key {
+            VirtualKeyCode::Delete => {
+                self.lights.remove(nearest);
+                info!(lights = self.lights.len(), "removed a light");
+            }
+            VirtualKeyCode::J => {
+                light.cone = if light.is_spot() { PI } else { SPOT_CONE };
+                info!(spot = light.is_spot(), "switched a light");
+            }
+            VirtualKeyCode::Comma | VirtualKeyCode::Period => {
+                let factor = if key == VirtualKeyCode::Comma {
+                    1.0 / RADIUS_STEP
+                } else {
+                    RADIUS_STEP
+                };
+                light.radius = (light.radius * factor).clamp(MIN_RADIUS, MAX_RADIUS);
+                info!(radius = light.radius, "resized a light");
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Marks each light where it is `time` seconds in, in its color: a spot light
+    /// with a line along its axis, and its radius with a circle.
+    pub fn draw_overlay(&mut self, debug_draw: &mut DebugDraw, time: f32) {
+        self.shown_at = time;
+        for light in &self.lights {
+            let light = light.animated(time);
+            let [r, g, b] = light.color.map(|channel| channel.min(1.0));
+            let color = [r, g, b, 1.0];
+            debug_draw.circle(light.position, MARKER_SIZE, color);
+            debug_draw.circle(light.position, light.radius, [r, g, b, 0.25]);
+            if light.is_spot() {
+                let (sin, cos) = light.direction.sin_cos();
+                let [x, y] = light.position;
+                let tip = [x + 4.0 * MARKER_SIZE * cos, y + 4.0 * MARKER_SIZE * sin];
+                debug_draw.line(light.position, tip, color);
+            }
+        }
+    }
+
+    /// Adds a point light at `position` at time 0, in the next color.
+    fn add(&mut self, position: [f32; 2]) {
+        let i = self.lights.len();
+        self.lights.push(Light {
+            position,
+            color: PALETTE[i % PALETTE.len()],
+            radius: DEFAULT_RADIUS,
+            direction: 0.0,
+            cone: PI,
+            orbit: if i.is_multiple_of(2) { ORBIT } else { -ORBIT },
+        });
+    }
+
+    /// Index of the light nearest to `position` `time` seconds in.
+    fn nearest(&self, position: [f32; 2], time: f32) -> Option<usize> {
+        let distance = |light: &Light| {
+            let [x, y] = light.animated(time).position;
+            (x - position[0]).hypot(y - position[1])
+        };
+        (0..self.lights.len())
+            .min_by(|&a, &b| distance(&self.lights[a]).total_cmp(&distance(&self.lights[b])))
+    }
+}
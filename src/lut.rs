@@ -0,0 +1,248 @@
+use crate::texture::TextureImage;
+use std::{fs, path::Path};
+
+/// Largest side accepted; 256³ texels already take 128 MiB of half floats.
+const MAX_SIZE: u32 = 256;
+
+/// A 3D color lookup table mapping sRGB-encoded colors to graded ones, red varying
+/// fastest, then green, then blue.
+#[derive(Clone, Debug)]
+pub struct ColorLut {
+    pub size: u32,
+    pub texels: Vec<[f32; 3]>,
+}
+
+impl ColorLut {
+    /// Loads a .cube file, or a PNG strip: `size` squares of `size` by `size`
+    /// pixels side by side, one per blue level, with red across and green down.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        let lut = if extension.is_some_and(|extension| extension.eq_ignore_ascii_case("cube")) {
+            let text =
+                fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            Self::parse_cube(&text)
+        } else {
+            Self::from_strip(&TextureImage::load(path)?)
+        };
+        lut.map_err(|message| format!("{}: {}", path.display(), message))
+    }
+
+    /// Whether `image` is shaped like a LUT strip.
+    pub fn is_strip(image: &TextureImage) -> bool {
+        image.height > 1 && image.width == image.height * image.height
+    }
+
+    pub fn from_strip(image: &TextureImage) -> Result<Self, String> {
+        if !Self::is_strip(image) {
+            return Err(format!(
+                "a {}x{} image isn't a LUT strip, which is N² pixels wide and N tall",
+                image.width, image.height
+            ));
+        }
+        let size = image.height;
+        check_size(size)?;
+        let mut texels = Vec::with_capacity((size * size * size) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let offset = ((g * image.width + b * size + r) * 4) as usize;
+                    let pixel = &image.pixels[offset..offset + 3];
+                    texels.push([0, 1, 2].map(|channel| pixel[channel] as f32 / 255.0));
+                }
+            }
+        }
+        Ok(ColorLut { size, texels })
+    }
+
+    /// Parses the Adobe/Resolve .cube format. Only 3D tables over the default
+    /// domain of 0 to 1 are supported.
+    pub fn parse_cube(text: &str) -> Result<Self, String> {
+        let mut size = None;
+        let mut texels = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap();
+            match keyword {
+                "TITLE" => {}
+                "LUT_1D_SIZE" => return Err(error("1D LUTs aren't supported".to_owned())),
+                "LUT_3D_SIZE" => {
+                    if size.is_some() {
+                        return Err(error("LUT_3D_SIZE is given twice".to_owned()));
+                    }
+                    let value = words.next().unwrap_or("");
+                    let parsed: u32 = value
+                        .parse()
+                        .ok()
+                        .filter(|_| words.next().is_none())
+                        .ok_or_else(|| error(format!("bad LUT_3D_SIZE `{}`", line)))?;
+                    check_size(parsed).map_err(error)?;
+                    size = Some(parsed);
+                }
+                "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                    let default = if keyword == "DOMAIN_MIN" { 0.0 } else { 1.0 };
+                    let values: Vec<f32> = words.filter_map(|word| word.parse().ok()).collect();
+                    if values != [default; 3] {
+                        return Err(error(format!(
+                            "only a {} of {} is supported",
+                            keyword, default
+                        )));
+                    }
+                }
+                _ => {
+                    let values: Vec<f32> = line
+                        .split_whitespace()
+                        .map(|word| word.parse())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| error(format!("expected three numbers, got `{}`", line)))?;
+                    if values.len() != 3 {
+                        return Err(error(format!("expected three numbers, got `{}`", line)));
+                    }
+                    texels.push([values[0], values[1], values[2]]);
+                }
+            }
+        }
+        let size = size.ok_or_else(|| "no LUT_3D_SIZE".to_owned())?;
+        let expected = (size * size * size) as usize;
+        if texels.len() != expected {
+            return Err(format!(
+                "LUT_3D_SIZE {} needs {} entries, there are {}",
+                size,
+                expected,
+                texels.len()
+            ));
+        }
+        Ok(ColorLut { size, texels })
+    }
+
+    /// The LUT with sides of `size` that leaves colors unchanged.
+    pub fn identity(size: u32) -> Self {
+        let level = |i: u32| i as f32 / (size - 1) as f32;
+        let mut texels = Vec::with_capacity((size * size * size) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    texels.push([level(r), level(g), level(b)]);
+                }
+            }
+        }
+        ColorLut { size, texels }
+    }
+}
+
+fn check_size(size: u32) -> Result<(), String> {
+    if (2..=MAX_SIZE).contains(&size) {
+        Ok(())
+    } else {
+        Err(format!(
+            "a side of {} isn't between 2 and {}",
+            size, MAX_SIZE
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A .cube file of `size` with the identity's entries, after `header`.
+    fn cube(header: &str, size: u32) -> String {
+        let mut text = format!("{}\nLUT_3D_SIZE {}\n", header, size);
+        for [r, g, b] in ColorLut::identity(size).texels {
+            text += &format!("{} {} {}\n", r, g, b);
+        }
+        text
+    }
+
+    #[test]
+    fn parses_entries_red_fastest() {
+        let lut = ColorLut::parse_cube(
+            "# comment\nTITLE \"test\"\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 1.0 1 1\n\n\
+             LUT_3D_SIZE 2\n\
+             0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n",
+        )
+        .unwrap();
+        assert_eq!(lut.size, 2);
+        assert_eq!(lut.texels, ColorLut::identity(2).texels);
+        assert_eq!(lut.texels[1], [1.0, 0.0, 0.0]);
+        assert_eq!(lut.texels[4], [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn entries_can_come_before_the_size() {
+        let text = cube("", 3);
+        let (size_line, entries) = text.trim_start().split_once('\n').unwrap();
+        let lut = ColorLut::parse_cube(&format!("{}{}\n", entries, size_line)).unwrap();
+        assert_eq!(lut.size, 3);
+        assert_eq!(lut.texels.len(), 27);
+    }
+
+    #[test]
+    fn short_and_long_files_are_rejected() {
+        let text = cube("", 2);
+        let (short, _) = text.rsplit_once("1 1 1").unwrap();
+        assert_eq!(
+            ColorLut::parse_cube(short).unwrap_err(),
+            "LUT_3D_SIZE 2 needs 8 entries, there are 7"
+        );
+        let long = format!("{}0.5 0.5 0.5\n", text);
+        assert_eq!(
+            ColorLut::parse_cube(&long).unwrap_err(),
+            "LUT_3D_SIZE 2 needs 8 entries, there are 9"
+        );
+        assert_eq!(ColorLut::parse_cube("").unwrap_err(), "no LUT_3D_SIZE");
+        assert_eq!(
+            ColorLut::parse_cube("0 0 0\n").unwrap_err(),
+            "no LUT_3D_SIZE"
+        );
+    }
+
+    #[test]
+    fn bad_sizes_are_rejected() {
+        for (size, message) in [
+            ("1", "line 1: a side of 1 isn't between 2 and 256"),
+            ("257", "line 1: a side of 257 isn't between 2 and 256"),
+            ("", "line 1: bad LUT_3D_SIZE `LUT_3D_SIZE`"),
+            ("two", "line 1: bad LUT_3D_SIZE `LUT_3D_SIZE two`"),
+            ("-2", "line 1: bad LUT_3D_SIZE `LUT_3D_SIZE -2`"),
+            ("2 2", "line 1: bad LUT_3D_SIZE `LUT_3D_SIZE 2 2`"),
+        ] {
+            let text = format!("LUT_3D_SIZE {}", size);
+            assert_eq!(ColorLut::parse_cube(&text).unwrap_err(), message);
+        }
+        assert_eq!(
+            ColorLut::parse_cube("LUT_3D_SIZE 2\nLUT_3D_SIZE 2\n").unwrap_err(),
+            "line 2: LUT_3D_SIZE is given twice"
+        );
+        assert_eq!(
+            ColorLut::parse_cube("LUT_1D_SIZE 16\n").unwrap_err(),
+            "line 1: 1D LUTs aren't supported"
+        );
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        for (line, message) in [
+            ("0 0", "expected three numbers, got `0 0`"),
+            ("0 0 0 0", "expected three numbers, got `0 0 0 0`"),
+            ("0 zero 0", "expected three numbers, got `0 zero 0`"),
+            (
+                "LUT_3D_INPUT_RANGE 0 1",
+                "expected three numbers, got `LUT_3D_INPUT_RANGE 0 1`",
+            ),
+            ("DOMAIN_MIN -1 0 0", "only a DOMAIN_MIN of 0 is supported"),
+            ("DOMAIN_MAX 2 2 2", "only a DOMAIN_MAX of 1 is supported"),
+            ("DOMAIN_MAX 1 1", "only a DOMAIN_MAX of 1 is supported"),
+        ] {
+            let text = format!("LUT_3D_SIZE 2\n{}\n", line);
+            assert_eq!(
+                ColorLut::parse_cube(&text).unwrap_err(),
+                format!("line 2: {}", message)
+            );
+        }
+    }
+}
@@ -1,413 +1,1684 @@
-use bytemuck::{Pod, Zeroable};
-use core::cmp::{max, min};
+use assets::{AssetWatcher, ShaderLoader, TextureFiles};
+use bench::Benchmark;
+use bookmarks::CameraBookmarks;
+use debug_draw::DebugDraw;
+use gif_export::GifExport;
+use gpu::GpuSelector;
+use input::{CursorMode, TouchGestures};
+use lighting::LightRig;
+use lut::ColorLut;
+use pacing::{FrameLimiter, LiveResize, PowerSave};
+use perf_log::PerfLog;
+use renderdoc_capture::RenderDocCapture;
+use renderer::{
+    cycle_shader_preset, DemoInput, DemoKind, DeviceConfig, DrawList, FrameData, Instances,
+    MaterialId, MeshId, RenderError, Renderer, RendererSettings, TextureId, VertexEncoding,
+    VertexFormat, VertexLayout, WindowSurface, AUDIO_BANDS, SHADER_PRESETS,
+};
+use replay::{EventKind, InputRecorder, InputReplay};
+use scene::{Scene, SceneFile};
+use simulation::Simulation;
+use stats::FrameStats;
+use std::fs;
+use std::path::Path;
+use std::process;
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 use std::time::Instant;
+use stylization::StyleRig;
+use texture::TextureImage;
+use tracing::{debug, error, info, info_span, warn};
+use tracing_subscriber::EnvFilter;
+use video::VideoRecorder;
 use vulkano::{
-    buffer::{BufferUsage, CpuAccessibleBuffer, TypedBufferAccess},
-    command_buffer::{
-        AutoCommandBufferBuilder, CommandBufferUsage, RenderPassBeginInfo, SubpassContents,
-    },
-    device::{
-        physical::{PhysicalDevice, PhysicalDeviceType},
-        Device, DeviceCreateInfo, DeviceExtensions, QueueCreateInfo,
-    },
-    image::{view::ImageView, ImageAccess, ImageUsage, SwapchainImage},
-    impl_vertex,
-    instance::{Instance, InstanceCreateInfo},
-    pipeline::{
-        graphics::{
-            input_assembly::InputAssemblyState,
-            vertex_input::BuffersDefinition,
-            viewport::{Viewport, ViewportState},
-        },
-        GraphicsPipeline,
-    },
-    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
-    swapchain::{
-        acquire_next_image, AcquireError, Swapchain, SwapchainCreateInfo, SwapchainCreationError,
-    },
-    sync::{self, FlushError, GpuFuture},
+    device::DeviceExtensions,
+    instance::{Instance, InstanceCreateInfo, InstanceExtensions},
 };
-use vulkano_win::VkSurfaceBuild;
+use window::{WindowMetrics, WindowPlacement, WindowSettings};
 use winit::{
-    event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
+    dpi::PhysicalSize,
+    event::{
+        DeviceEvent, ElementState, Event, KeyboardInput, ModifiersState, MouseButton,
+        VirtualKeyCode, WindowEvent,
+    },
+    event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
+    window::Window,
 };
 
+/// A profiler zone until the end of the scope: for Tracy with the `profile-tracy`
+/// feature, and for puffin with `profile`.
+macro_rules! profile_zone {
+    ($name:literal) => {
+        #[cfg(feature = "profile-tracy")]
+        let _zone = tracy_client::span!($name);
+        #[cfg(feature = "profile")]
+        puffin::profile_scope!($name);
+    };
+}
+
+mod allocator;
+mod assets;
+#[cfg(feature = "audio")]
+mod audio;
+mod bench;
+mod boids;
+mod bookmarks;
+mod capture;
+mod cli;
+mod config;
+mod debug_draw;
+mod exr;
+mod gif_export;
+mod gpu;
+mod hdr;
+mod input;
+mod lighting;
+mod lut;
+mod memory;
+mod monitor;
+mod multi_gpu;
+mod pacing;
+mod particles;
+mod perf_log;
+#[cfg(feature = "physics")]
+mod physics;
+#[cfg(feature = "profile")]
+mod profiler;
+mod renderdoc_capture;
+mod renderer;
+mod replay;
+mod scene;
+mod simulation;
+mod stats;
+mod stylization;
+mod texture;
+mod tilemap;
+mod video;
+mod window;
+
+/// Wait before the second attempt at recreating a lost renderer, doubled for each
+/// attempt after it.
+const RECREATION_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Exit code when no usable GPU is found, which the golden-image test reads as "skip".
+const NO_DEVICE_EXIT_CODE: i32 = 3;
+
 fn main() {
-    let app_start = Instant::now();
+    run(cli::Options::from_env());
+}
+
+/// Where Android starts the app instead of [`main`]. There are no arguments, and
+/// nowhere to keep a window placement.
+#[cfg(target_os = "android")]
+#[ndk_glue::main(backtrace = "on")]
+fn android_main() {
+    run(cli::Options {
+        window: WindowSettings {
+            restore_placement: false,
+            ..WindowSettings::default()
+        },
+        ..cli::Options::default()
+    });
+}
+
+fn run(options: cli::Options) {
+    init_logging();
+    // Tracy zones from the renderer go nowhere until the client runs.
+    #[cfg(feature = "profile-tracy")]
+    tracy_client::Client::start();
+    let mut renderdoc = RenderDocCapture::attach();
+    let init_span = info_span!("init").entered();
+
+    let instance_count = if options.gpu == Some(GpuSelector::Software) {
+        scene::SOFTWARE_INSTANCE_COUNT
+    } else {
+        scene::DEFAULT_INSTANCE_COUNT
+    };
+    let mut scene = Scene::new(instance_count);
+    if options.rainbow {
+        scene.add_rainbow();
+    }
+    for i in 0..options.clusters {
+        let angle = i as f32 / options.clusters as f32 * std::f32::consts::TAU;
+        let direction = if i % 2 == 0 { 1.0 } else { -1.0 };
+        scene.spawn_cluster([0.6 * angle.cos(), 0.6 * angle.sin()], direction * 1.5);
+    }
+    if let Some(rate) = options.particle_rate {
+        scene.world.spawn((
+            scene::Transform {
+                translation: [0.0, 0.6],
+                ..Default::default()
+            },
+            particles::Emitter::new(rate),
+        ));
+    }
+    #[cfg(feature = "physics")]
+    if options.physics {
+        scene.enable_physics();
+    }
+    if options.demo == Some(DemoKind::Boids) {
+        scene.enable_boids();
+    }
+
+    let tile_atlas = options.tile_atlas.as_deref().map(|path| {
+        tilemap::TileAtlas::load(path, options.tile_size).unwrap_or_else(|message| {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        })
+    });
+    // The normal map goes after the textures.
+    let textures: Vec<TextureImage> = options
+        .textures
+        .iter()
+        .chain(&options.normal_map)
+        .map(|path| {
+            TextureImage::load(path).unwrap_or_else(|message| {
+                eprintln!("error: {}", message);
+                process::exit(1);
+            })
+        })
+        .collect();
+    let texture_count = options.textures.len() as u32;
+    let lit = options.normal_map.is_some() || options.lights > 0;
+    let heightmap = options.heightmap.as_deref().map(|path| {
+        TextureImage::load(path).unwrap_or_else(|message| {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        })
+    });
+    let color_lut = options.lut.as_deref().map(|path| {
+        ColorLut::load(path).unwrap_or_else(|message| {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        })
+    });
+
+    let tilemap = tile_atlas
+        .as_ref()
+        .map(|atlas| demo_tilemap(atlas.tile_count()));
 
-    let background_color = [0.1, 0.1, 0.1, 1.0];
-    let swapchain_buffers_count = 3; // triple buffering
-    let instance_count = 1000;
+    let mut settings = RendererSettings {
+        background_color: if options.window.transparent {
+            [0.0, 0.0, 0.0, 0.0]
+        } else {
+            [0.1, 0.1, 0.1, 1.0]
+        },
+        swapchain_buffers_count: 3, // triple buffering
+        instance_count: scene.drawable_count() as u32,
+        frames_in_flight: options.frames_in_flight,
+        draw_buckets: options.draw_buckets,
+        prerecord: options.prerecord,
+        display_output: options.display_output,
+        surface_format_policy: options.surface_format_policy.clone(),
+        paper_white: options.paper_white,
+        color_lut,
+        dither: options.dither,
+        taa: options.taa,
+        depth_of_field: options.depth_of_field,
+        motion_blur: options.motion_blur.then_some(options.motion_blur_settings),
+        stylization: options.stylization,
+        uncapped_present: options.bench_frames.is_some() || options.export_gif.is_some(),
+        transparent: options.window.transparent,
+        gpu_timing: options.bench_frames.is_some() || options.perf_log.is_some(),
+        // For --export-gif and F9 recordings.
+        frame_capture: true,
+        crash_breadcrumbs: options.crash_dump.is_some(),
+        gpu_particles: options.gpu_particles,
+        procedural_background: options.procedural_background,
+        demo: options.demo,
+        nbody_bodies: options.nbody_bodies,
+        heightmap,
+        depth_prepass: options.depth_prepass,
+        shader_preset: None,
+        rasterizer: options.rasterizer,
+        occlusion_culling: options.occlusion_culling,
+        pipeline_statistics: options.pipeline_statistics,
+        device: DeviceConfig {
+            api_version: options.api_version,
+            dynamic_rendering: !options.no_dynamic_rendering,
+            bindless: !options.no_bindless,
+            push_descriptors: !options.no_push_descriptors,
+            synchronization2: !options.no_synchronization2,
+            ..DeviceConfig::default()
+        },
+        textures,
+        tile_atlas,
+        vertex_format: VertexFormat {
+            encoding: if options.quantize_vertices {
+                VertexEncoding::Quantized
+            } else {
+                VertexEncoding::Full
+            },
+            layout: if options.planar_vertices {
+                VertexLayout::Planar
+            } else {
+                VertexLayout::Interleaved
+            },
+        },
+        topology: options.topology,
+        point_sprites: options.point_sprites,
+        outline: options.outline,
+        stereo: options.stereo,
+        environment_map: options.environment_map,
+    };
 
-    let required_extensions = vulkano_win::required_extensions();
+    let required_extensions = vulkano_win::required_extensions().union(
+        &hdr::optional_instance_extensions()
+            .intersection(&InstanceExtensions::supported_by_core().unwrap()),
+    );
 
     let instance = Instance::new(InstanceCreateInfo {
         enabled_extensions: required_extensions,
         enumerate_portability: true,
+        max_api_version: Some(settings.device.api_version),
         ..Default::default()
     })
     .unwrap();
 
-    let event_loop = EventLoop::new();
-    let surface = WindowBuilder::new()
-        .build_vk_surface(&event_loop, instance.clone())
-        .unwrap();
+    if options.list_gpus {
+        gpu::print_gpus(&instance);
+        return;
+    }
 
-    let device_extensions = DeviceExtensions {
-        khr_swapchain: true,
-        ..DeviceExtensions::none()
-    };
+    if let Some(capture_dir) = &options.capture_dir {
+        let (physical_device, queue_family) =
+            gpu::select_headless(&instance, &DeviceExtensions::none(), options.gpu.as_ref())
+                .unwrap_or_else(|message| {
+                    eprintln!("error: {}", message);
+                    process::exit(NO_DEVICE_EXIT_CODE);
+                });
+        info!(device = %physical_device.properties().device_name, "capturing frames");
+        if let Err(message) = capture::save_frames(
+            &instance,
+            physical_device.index(),
+            queue_family.id(),
+            &settings,
+            capture_dir,
+        ) {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        }
+        return;
+    }
 
-    let (physical_device, queue_family) = PhysicalDevice::enumerate(&instance)
-        .filter(|&physical_device| {
-            physical_device
-                .supported_extensions()
-                .is_superset_of(&device_extensions)
-        })
-        .filter_map(|physical_device| {
-            physical_device
-                .queue_families()
-                .find(|&queue_family| {
-                    queue_family.supports_graphics()
-                        && queue_family.supports_surface(&surface).unwrap_or(false)
-                })
-                .map(|queue_family| (physical_device, queue_family))
-        })
-        .min_by_key(
-            |(physical_device, _)| match physical_device.properties().device_type {
-                PhysicalDeviceType::DiscreteGpu => 0,
-                PhysicalDeviceType::IntegratedGpu => 1,
-                PhysicalDeviceType::VirtualGpu => 2,
-                PhysicalDeviceType::Cpu => 3,
-                PhysicalDeviceType::Other => 4,
-            },
-        )
-        .unwrap();
+    if let Some(frames) = options.multi_gpu_frames {
+        if let Err(message) = multi_gpu::run(&instance, &settings, frames) {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        }
+        return;
+    }
 
-    println!(
-        "Using device: {} (type: {:?})",
-        physical_device.properties().device_name,
-        physical_device.properties().device_type,
-    );
+    let event_loop = EventLoop::new();
+    #[cfg(target_os = "android")]
+    let event_loop = wait_for_resume(event_loop);
+    if options.list_monitors {
+        monitor::print_monitors(&event_loop);
+        return;
+    }
 
-    let (logical_device, mut queues) = Device::new(
-        physical_device,
-        DeviceCreateInfo {
-            enabled_extensions: device_extensions,
-            queue_create_infos: vec![QueueCreateInfo::family(queue_family)],
-            ..Default::default()
-        },
+    let surface = create_window_surface(&event_loop, &instance, &options.window);
+
+    let (physical_device, queue_family) = gpu::select(
+        &instance,
+        &surface,
+        &renderer::device_extensions(),
+        options.gpu.as_ref(),
     )
-    .unwrap();
+    .unwrap_or_else(|message| {
+        eprintln!("error: {}", message);
+        process::exit(NO_DEVICE_EXIT_CODE);
+    });
 
-    let queue = queues.next().unwrap();
+    info!(
+        device = %physical_device.properties().device_name,
+        device_type = ?physical_device.properties().device_type,
+        "selected device"
+    );
 
-    let (mut swapchain, images) = {
-        let surface_capabilities = physical_device
-            .surface_capabilities(&surface, Default::default())
-            .unwrap();
+    let mut physical_device_index = physical_device.index();
+    let mut queue_family_id = queue_family.id();
 
-        println!(
-            "Swapchain buffers count: {}/{:?}",
-            swapchain_buffers_count,
-            surface_capabilities.max_image_count.unwrap_or(0)
+    let mut renderer = Some(
+        Renderer::new(
+            &instance,
+            surface,
+            physical_device_index,
+            queue_family_id,
+            &settings,
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("error: failed to create the renderer: {}", e);
+            process::exit(1);
+        }),
+    );
+    if options.info {
+        let renderer = renderer.as_ref().unwrap();
+        gpu::print_info(
+            renderer.device(),
+            renderer.surface().unwrap(),
+            renderer.capabilities(),
         );
+        return;
+    }
+    if texture_count > 0 || lit {
+        let material = add_texture_style(renderer.as_mut().unwrap(), lit);
+        // White, if only the normal map is given.
+        let textures: Vec<TextureId> = match texture_count {
+            0 => vec![TextureId::default()],
+            _ => (1..=texture_count).map(TextureId).collect(),
+        };
+        scene.texture_instances(material, &textures);
+    }
+    if options.normal_map.is_some() {
+        scene.normal_map_instances(TextureId(texture_count + 1));
+    }
+    let mut light_rig = LightRig::new(options.lights);
+    light_rig.apply(renderer.as_mut().unwrap());
+    let mut style_rig = StyleRig::new(options.stylization);
+    if options.clusters > 0 {
+        let (mesh, material) = add_cluster_style(renderer.as_mut().unwrap());
+        scene.style_clusters(mesh, material);
+    }
+    if options.lod_discs {
+        scene.mesh_instances(renderer.as_mut().unwrap().add_mesh(renderer::disc_mesh()));
+    }
+    let mut device_lost_count = 0;
+    // A new window's surface the renderer can't draw to, dealt with on the next
+    // frame like a lost device.
+    let mut surface_error = None;
+    drop(init_span);
 
-        let image_format = Some(
-            physical_device
-                .surface_formats(&surface, Default::default())
-                .unwrap()[0]
-                .0,
-        );
+    // Benchmarks run uncapped, whatever the pacing options say.
+    let mut benchmark = options.bench_frames.map(Benchmark::new);
+    let mut gif_export = options.export_gif.as_ref().map(|path| {
+        GifExport::new(path, options.gif_seconds).unwrap_or_else(|message| {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        })
+    });
+    let mut video: Option<VideoRecorder> = None;
+    let mut perf_log = options.perf_log.as_ref().map(|path| {
+        PerfLog::open(path).unwrap_or_else(|message| {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        })
+    });
+    // Power saving would stop drawing the frames a GIF export waits for.
+    let mut power_save = (options.power_save && benchmark.is_none() && gif_export.is_none())
+        .then(|| PowerSave::new(options.idle_fps));
+
+    let mut frame_limiter = options
+        .fps_cap
+        .filter(|_| benchmark.is_none())
+        .map(FrameLimiter::new);
+
+    let mut frame_stats = FrameStats::new();
+    let mut simulation = Simulation::new(scene);
+    let mut clusters_visible = true;
+    let mut modifiers = ModifiersState::empty();
+    let mut camera_bookmarks = CameraBookmarks::load();
+    let mut debug_draw = DebugDraw::default();
+    let mut show_bounds = false;
+    #[cfg(feature = "profile")]
+    let mut flamegraph = profiler::Flamegraph::new();
+    let mut input = simulation::Input::default();
+    let mut window_metrics = WindowMetrics::new(renderer.as_ref().unwrap().window());
+    let mut cursor_mode = CursorMode::default();
+    let mut touch_gestures = TouchGestures::default();
+    let mut live_resize = LiveResize::default();
+    let mut recorder = options.record_input.as_deref().map(|path| {
+        InputRecorder::create(path).unwrap_or_else(|message| {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        })
+    });
+    let mut replay = options.replay_input.as_deref().map(|path| {
+        InputReplay::load(path).unwrap_or_else(|message| {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        })
+    });
+    let mut replayed_input = simulation::Input::default();
+    let mut drawn_this_iteration = false;
+    #[cfg(feature = "audio")]
+    let audio = options.audio_device.as_deref().map(|device| {
+        audio::AudioCapture::start(device).unwrap_or_else(|message| {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        })
+    });
+    let mut texture_files = TextureFiles::new(&options.textures, &options.normal_map);
+    let mut asset_watcher = (!options.watch.is_empty()).then(|| {
+        let mut watcher = AssetWatcher::new(event_loop.create_proxy()).unwrap_or_else(|message| {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        });
+        for path in &options.watch {
+            if let Err(message) = watcher.watch(path) {
+                eprintln!("error: {}", message);
+                process::exit(1);
+            }
+        }
+        // Saving the scene file starts watching it if it isn't there yet.
+        let scene_file = Some(&options.scene_file).filter(|path| path.exists());
+        for path in texture_files.paths().iter().chain(scene_file) {
+            if let Err(message) = watcher.watch(path) {
+                warn!(%message, "can't watch file");
+            }
+        }
+        watcher
+    });
+    let mut shader_loader = ShaderLoader::new(options.shader_defines.clone());
+    if let Some(path) = &options.shadertoy {
+        if let Err(message) = load_shadertoy(path, renderer.as_mut().unwrap(), &mut shader_loader) {
+            eprintln!("error: {}: {}", path.display(), message);
+            process::exit(1);
+        }
+    }
+    let mut dragging = false;
+    let mut demo_scroll = 0.0;
+
+    event_loop.run(move |event, window_target, control_flow| {
+        if let (Event::WindowEvent { .. }, Some(_)) = (&event, &power_save) {
+            renderer.as_ref().unwrap().window().request_redraw();
+        }
 
-        let min_image_count = match surface_capabilities.max_image_count {
-            None => max(
-                swapchain_buffers_count,
-                surface_capabilities.min_image_count,
-            ),
-            Some(limit) => min(
-                max(
-                    swapchain_buffers_count,
-                    surface_capabilities.min_image_count,
+        if let (Some(recorder), Event::WindowEvent { event, .. }) = (recorder.as_mut(), &event) {
+            match *event {
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(key),
+                            state,
+                            ..
+                        },
+                    ..
+                } => recorder.record(simulation.ticks(), EventKind::Key { key, state }),
+                WindowEvent::Resized(size) => recorder.record(
+                    simulation.ticks(),
+                    EventKind::Resized {
+                        width: size.width,
+                        height: size.height,
+                    },
                 ),
-                limit,
-            ),
-        };
+                _ => {}
+            }
+        }
 
-        Swapchain::new(
-            logical_device.clone(),
-            surface.clone(),
-            SwapchainCreateInfo {
-                min_image_count,
-                image_format,
-                image_extent: surface.window().inner_size().into(),
-                image_usage: ImageUsage::color_attachment(),
-                composite_alpha: surface_capabilities
-                    .supported_composite_alpha
-                    .iter()
-                    .next()
-                    .unwrap(),
-                ..Default::default()
+        let mut redraw = false;
+        match event {
+            Event::NewEvents(_) => drawn_this_iteration = false,
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { position, .. },
+                ..
+            } if !cursor_mode.uses_raw_motion() => {
+                input.mouse = window_metrics.normalize_cursor(position);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        state,
+                        button: MouseButton::Left,
+                        ..
+                    },
+                ..
+            } if settings.demo.is_some_and(DemoKind::replaces_scene) => {
+                dragging = state == ElementState::Pressed;
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        state: ElementState::Pressed,
+                        button: MouseButton::Left,
+                        ..
+                    },
+                ..
+            } if modifiers.ctrl() && replay.is_none() => {
+                if let Some(recorder) = recorder.as_mut() {
+                    let cursor = input.mouse;
+                    recorder.record(simulation.ticks(), EventKind::Pick { cursor });
+                }
+                pick(simulation.scene_mut(), input.mouse);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        state: ElementState::Pressed,
+                        button: MouseButton::Left,
+                        ..
+                    },
+                ..
+            } if modifiers.shift() && settings.depth_of_field => {
+                renderer.as_mut().unwrap().focus_at(input.mouse);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        state: ElementState::Pressed,
+                        button:
+                            button @ (MouseButton::Left | MouseButton::Right | MouseButton::Middle),
+                        ..
+                    },
+                ..
+            } if replay.is_none() => {
+                if let Some(recorder) = recorder.as_mut() {
+                    let cursor = input.mouse;
+                    recorder.record(simulation.ticks(), EventKind::Click { button, cursor });
+                }
+                click(simulation.scene_mut(), button, input.mouse);
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Touch(touch),
+                ..
+            } => {
+                touch_gestures.handle(&touch, &window_metrics, &mut input);
+            }
+            Event::WindowEvent {
+                event: WindowEvent::MouseWheel { delta, .. },
+                ..
+            } => {
+                let lines = input::scroll_lines(delta, &window_metrics);
+                if settings.demo.is_some_and(DemoKind::replaces_scene) {
+                    demo_scroll += lines;
+                } else {
+                    input.add_scroll(lines);
+                }
+            }
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } if cursor_mode.uses_raw_motion() => {
+                input.mouse = input::apply_mouse_delta(input.mouse, delta, &window_metrics);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Tab),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } if modifiers.ctrl() => {
+                // The scene's slot keeps the demo that runs in it, if it was started
+                // with one.
+                let scene_demo = options.demo.filter(|demo| !demo.replaces_scene());
+                // Kept in the settings so a recreated renderer keeps it too.
+                settings.demo =
+                    DemoKind::cycle(settings.demo, scene_demo, options.shadertoy.is_some());
+                renderer.as_mut().unwrap().set_demo(settings.demo);
+                dragging = false;
+                info!(demo = ?settings.demo, "switched demo");
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Tab),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                cycle_cursor_mode(&mut cursor_mode, renderer.as_ref().unwrap().window());
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::V),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } if replay.is_none() => {
+                toggle_clusters(simulation.scene_mut(), &mut clusters_visible);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode:
+                                    Some(
+                                        key @ (VirtualKeyCode::Up
+                                        | VirtualKeyCode::Down
+                                        | VirtualKeyCode::Minus
+                                        | VirtualKeyCode::Equals),
+                                    ),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } if replay.is_none() => {
+                tune_flock(simulation.scene_mut(), key);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::B),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                show_bounds = !show_bounds;
+                info!(show_bounds, "toggled instance bounds");
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::N),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                let normal_maps = simulation.scene_mut().toggle_normal_maps();
+                info!(normal_maps, "toggled normal maps");
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode:
+                                    Some(
+                                        key @ (VirtualKeyCode::H
+                                        | VirtualKeyCode::K
+                                        | VirtualKeyCode::Delete
+                                        | VirtualKeyCode::J
+                                        | VirtualKeyCode::Comma
+                                        | VirtualKeyCode::Period),
+                                    ),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } if lit => {
+                let position = [2.0 * input.mouse[0] - 1.0, 2.0 * input.mouse[1] - 1.0];
+                if light_rig.handle_key(key, position) {
+                    light_rig.apply(renderer.as_mut().unwrap());
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::D),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                // Kept in the settings so a recreated renderer keeps it too.
+                settings.dither = !settings.dither;
+                renderer.as_mut().unwrap().set_dither(settings.dither);
+                info!(dither = settings.dither, "toggled dithering");
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::T),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                settings.taa = !settings.taa;
+                renderer.as_mut().unwrap().set_taa(settings.taa);
+                info!(taa = settings.taa, "toggled temporal anti-aliasing");
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::G),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                settings.depth_of_field = !settings.depth_of_field;
+                renderer
+                    .as_mut()
+                    .unwrap()
+                    .set_depth_of_field(settings.depth_of_field);
+                info!(
+                    depth_of_field = settings.depth_of_field,
+                    "toggled the depth of field"
+                );
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::M),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                settings.motion_blur = match settings.motion_blur {
+                    Some(_) => None,
+                    None => Some(options.motion_blur_settings),
+                };
+                renderer
+                    .as_mut()
+                    .unwrap()
+                    .set_motion_blur(settings.motion_blur);
+                info!(
+                    motion_blur = settings.motion_blur.is_some(),
+                    "toggled motion blur"
+                );
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode:
+                                    Some(
+                                        key @ (VirtualKeyCode::F1
+                                        | VirtualKeyCode::F2
+                                        | VirtualKeyCode::F3
+                                        | VirtualKeyCode::LBracket
+                                        | VirtualKeyCode::RBracket),
+                                    ),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } if style_rig.handle_key(key) => {
+                settings.stylization = style_rig.stylization();
+                renderer
+                    .as_mut()
+                    .unwrap()
+                    .set_stylization(settings.stylization);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Z),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                // Kept in the settings so a recreated renderer keeps it too.
+                settings.depth_prepass = !settings.depth_prepass;
+                renderer
+                    .as_mut()
+                    .unwrap()
+                    .set_depth_prepass(settings.depth_prepass);
+                info!(
+                    depth_prepass = settings.depth_prepass,
+                    "toggled depth pre-pass"
+                );
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode:
+                                    Some(key @ (VirtualKeyCode::Left | VirtualKeyCode::Right)),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                let preset =
+                    cycle_shader_preset(settings.shader_preset, key == VirtualKeyCode::Right);
+                match renderer.as_mut().unwrap().set_shader_preset(preset) {
+                    Ok(()) => {
+                        // Kept in the settings so a recreated renderer keeps it too.
+                        settings.shader_preset = preset;
+                        let name = preset.map_or("built-in", |index| SHADER_PRESETS[index].name);
+                        info!(preset = name, "switched shader preset");
+                    }
+                    Err(e) => error!(error = %e, "failed to switch shader preset"),
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode:
+                                    Some(
+                                        key @ (VirtualKeyCode::C
+                                        | VirtualKeyCode::F
+                                        | VirtualKeyCode::L),
+                                    ),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                let rasterizer = &mut settings.rasterizer;
+                match key {
+                    VirtualKeyCode::C => rasterizer.cycle_cull_mode(),
+                    VirtualKeyCode::F => rasterizer.flip_front_face(),
+                    _ => rasterizer.cycle_line_width(),
+                }
+                // Kept in the settings so a recreated renderer keeps it too.
+                renderer.as_mut().unwrap().set_rasterizer(*rasterizer);
+                info!(
+                    cull_mode = ?rasterizer.cull_mode,
+                    front_face = ?rasterizer.front_face,
+                    line_width = rasterizer.line_width,
+                    "changed rasterizer state"
+                );
+            }
+            Event::WindowEvent {
+                event: WindowEvent::ModifiersChanged(state),
+                ..
+            } => modifiers = state,
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::S),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } if modifiers.ctrl() => {
+                let file = SceneFile::new(
+                    simulation.scene(),
+                    settings.background_color,
+                    simulation.camera(),
+                );
+                match file.save(&options.scene_file) {
+                    Ok(()) => info!(path = %options.scene_file.display(), "saved scene"),
+                    Err(message) => error!(%message, "failed to save the scene"),
+                }
+                if let Some(watcher) = asset_watcher.as_mut() {
+                    if let Err(message) = watcher.watch(&options.scene_file) {
+                        warn!(%message, "can't watch the scene file");
+                    }
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::O),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } if modifiers.ctrl() && replay.is_none() => {
+                load_scene(
+                    &options.scene_file,
+                    &mut simulation,
+                    &mut input,
+                    &mut settings,
+                    renderer.as_mut().unwrap(),
+                );
+                clusters_visible = true;
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } if bookmarks::slot(key).is_some() && replay.is_none() => {
+                let slot = bookmarks::slot(key).unwrap();
+                if modifiers.ctrl() {
+                    camera_bookmarks.set(slot, simulation.camera());
+                    info!(bookmark = slot + 1, "saved camera bookmark");
+                } else if let Some(camera) = camera_bookmarks.get(slot) {
+                    // Keep the bookmarked zoom once the flight is over.
+                    input.scroll = 0.0;
+                    input.pinch = camera.zoom;
+                    simulation.fly_to(camera, &input);
+                    info!(bookmark = slot + 1, "recalled camera bookmark");
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::DroppedFile(path),
+                ..
+            } => {
+                assets::load_file(
+                    &path,
+                    renderer.as_mut().unwrap(),
+                    &mut shader_loader,
+                    &mut texture_files,
+                );
+                if let Some(watcher) = asset_watcher.as_mut() {
+                    if let Err(message) = watcher.watch(&path) {
+                        warn!(%message, "can't watch dropped file");
+                    }
+                    watch_includes(watcher, &shader_loader);
+                }
+            }
+            Event::UserEvent(()) => {
+                let changed = asset_watcher
+                    .as_ref()
+                    .map_or_else(Vec::new, AssetWatcher::changed_files);
+                let scene_file = options.scene_file.canonicalize().ok();
+                for path in changed {
+                    if Some(&path) == scene_file.as_ref() {
+                        // Replays stay deterministic: the scene only changes with them.
+                        if replay.is_none() {
+                            load_scene(
+                                &path,
+                                &mut simulation,
+                                &mut input,
+                                &mut settings,
+                                renderer.as_mut().unwrap(),
+                            );
+                            clusters_visible = true;
+                        }
+                    } else {
+                        assets::load_file(
+                            &path,
+                            renderer.as_mut().unwrap(),
+                            &mut shader_loader,
+                            &mut texture_files,
+                        );
+                    }
+                }
+                if let Some(watcher) = asset_watcher.as_mut() {
+                    watch_includes(watcher, &shader_loader);
+                }
+                renderer.as_ref().unwrap().window().request_redraw();
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F9),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } if gif_export.is_none() => match video.take() {
+                Some(recording) => recording.stop(),
+                None => {
+                    let size = window_metrics.physical_size;
+                    match VideoRecorder::start(&options.video, [size.width, size.height]) {
+                        Ok(recording) => video = Some(recording),
+                        Err(message) => error!(%message, "can't record video"),
+                    }
+                }
             },
-        )
-        .unwrap()
-    };
+            #[cfg(feature = "profile")]
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F8),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => flamegraph.toggle(),
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F10),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } if renderdoc.is_some() => renderdoc.as_mut().unwrap().trigger(),
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F12),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                if let Err(message) = renderer.as_mut().unwrap().capture_hdr_frame() {
+                    error!(%message, "can't export the HDR image");
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => {
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.finish(simulation.ticks());
+                }
+                *control_flow = ControlFlow::Exit;
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Destroyed,
+                ..
+            } if *control_flow != ControlFlow::Exit => {
+                warn!("window destroyed, opening a new one");
+                let surface = create_window_surface(window_target, &instance, &options.window);
+                window_metrics = WindowMetrics::new(surface.window());
+                if cursor_mode.apply(surface.window()).is_err() {
+                    cursor_mode = CursorMode::Free;
+                }
+                if let Err(e) = renderer.as_mut().unwrap().replace_surface(surface) {
+                    surface_error = Some(RenderError::Surface(e));
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                ..
+            } => {
+                window_metrics.physical_size = size;
+                renderer.as_mut().unwrap().request_swapchain_recreation();
+                live_resize.resized();
+                redraw = true;
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::ScaleFactorChanged {
+                        scale_factor,
+                        new_inner_size,
+                    },
+                ..
+            } => {
+                window_metrics = WindowMetrics {
+                    physical_size: *new_inner_size,
+                    scale_factor,
+                };
+                debug!(
+                    scale_factor,
+                    physical_size = ?window_metrics.physical_size,
+                    logical_size = ?window_metrics.logical_size(),
+                    "scale factor changed"
+                );
+                renderer.as_mut().unwrap().request_swapchain_recreation();
+            }
+            // Android takes the native window away while the app is in the background.
+            Event::Suspended => renderer.as_mut().unwrap().suspend(),
+            Event::Resumed => renderer.as_mut().unwrap().resume(),
+            Event::LoopDestroyed => {
+                shut_down(&mut video, renderer.as_ref().unwrap().window(), &options)
+            }
+            Event::MainEventsCleared => {
+                if let Some(power_save) = power_save.as_mut() {
+                    power_save.schedule(renderer.as_ref().unwrap().window(), control_flow);
+                }
+            }
+            Event::RedrawRequested(_) => redraw = power_save.is_some() || live_resize.is_active(),
+            Event::RedrawEventsCleared => redraw = power_save.is_none(),
+            _ => (),
+        }
 
-    #[repr(C)]
-    #[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
-    struct Vertex {
-        position: [f32; 2],
-        color: [f32; 4],
-    }
-    impl_vertex!(Vertex, position, color);
+        if redraw && !drawn_this_iteration {
+            drawn_this_iteration = true;
+            #[cfg(feature = "profile")]
+            flamegraph.new_frame();
+            profile_zone!("frame");
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.record_input(simulation.ticks(), &input);
+            }
+            let state = {
+                profile_zone!("simulation");
+                if let Some(replay) = replay.as_mut() {
+                    while let Some(kind) = replay.next_due(simulation.ticks()) {
+                        let window = renderer.as_ref().unwrap().window();
+                        match kind {
+                            EventKind::Input(recorded) => replayed_input = recorded,
+                            EventKind::Key {
+                                key: VirtualKeyCode::Tab,
+                                state: ElementState::Pressed,
+                            } => cycle_cursor_mode(&mut cursor_mode, window),
+                            EventKind::Key {
+                                key: VirtualKeyCode::V,
+                                state: ElementState::Pressed,
+                            } => toggle_clusters(simulation.scene_mut(), &mut clusters_visible),
+                            EventKind::Key {
+                                key,
+                                state: ElementState::Pressed,
+                            } => tune_flock(simulation.scene_mut(), key),
+                            EventKind::Key { .. } => {}
+                            EventKind::Click { button, cursor } => {
+                                click(simulation.scene_mut(), button, cursor)
+                            }
+                            EventKind::Pick { cursor } => pick(simulation.scene_mut(), cursor),
+                            EventKind::Resized { width, height } => {
+                                window.set_inner_size(PhysicalSize::new(width, height))
+                            }
+                            EventKind::Exit => *control_flow = ControlFlow::Exit,
+                        }
+                    }
+                    // One tick per frame, so the replay doesn't depend on the frame rate.
+                    simulation.step(&replayed_input)
+                } else if benchmark.is_some() {
+                    // A fixed timestep and no input make every run draw the same frames.
+                    simulation.step(&simulation::Input::default())
+                } else if gif_export.is_some() {
+                    // A fixed timestep, so the GIF plays at the speed of the simulation
+                    // however long frames take to draw and read back.
+                    for _ in 1..gif_export::TICKS_PER_FRAME {
+                        simulation.step(&input);
+                    }
+                    simulation.step(&input)
+                } else {
+                    simulation.advance(&input)
+                }
+            };
+            let frame = FrameData {
+                time: state.time,
+                mouse: state.mouse,
+                zoom: state.zoom,
+                audio_bands: [0.0; AUDIO_BANDS],
+            };
+            #[cfg(feature = "audio")]
+            let frame = FrameData {
+                audio_bands: audio
+                    .as_ref()
+                    .map_or([0.0; AUDIO_BANDS], audio::AudioCapture::bands),
+                ..frame
+            };
 
-    let vertices = [
-        Vertex {
-            position: [-0.5, -0.25],
-            color: [1.0, 0.0, 0.0, 1.0],
-        },
-        Vertex {
-            position: [0.0, 0.5],
-            color: [0.0, 1.0, 0.0, 1.0],
-        },
-        Vertex {
-            position: [0.25, -0.1],
-            color: [0.0, 0.0, 1.0, 1.0],
-        },
-    ];
-
-    let vertex_buffer =
-        CpuAccessibleBuffer::from_iter(logical_device.clone(), BufferUsage::all(), false, vertices)
-            .unwrap();
-
-    mod vertex_shader {
-        vulkano_shaders::shader! {
-            ty: "vertex",
-            src: "
-            #version 460
-
-            layout(location = 0) in vec2 position;
-            layout(location = 1) in vec4 color;
-            
-            layout(location = 0) out vec4 out_color;
-
-            layout(push_constant) uniform PushConstantData {
-                float time;
-                float x;
-                float y;
-            } pc;
-
-            void main() {
-                out_color = color;
-                float time = pc.time;
-                float mouse_x = pc.x;
-                float mouse_y = pc.y;
-                int x = gl_InstanceIndex;
-                vec2 pos = position*vec2(mouse_x, mouse_y);
-                gl_Position = vec4(pos+vec2(sin(time+position.x+position.y+x)*0.5, sin(time+position.x+position.y+x*2)*0.5), 0.0, 1.0);
-            }
-            "
+            if let Some(tilemap) = &tilemap {
+                renderer.as_mut().unwrap().sync_tilemap(tilemap);
+            }
+            let render_start = Instant::now();
+            let no_draws = DrawList::default();
+            renderer.as_mut().unwrap().set_demo_input(&DemoInput {
+                cursor: input.mouse,
+                pressed: dragging,
+                scroll: demo_scroll,
+            });
+            demo_scroll = 0.0;
+            let capture = match gif_export.as_mut() {
+                Some(gif_export) => gif_export.wants_frame(),
+                None => video.is_some(),
+            };
+            if capture {
+                if let Err(message) = renderer.as_mut().unwrap().capture_frame() {
+                    if gif_export.is_some() {
+                        eprintln!("error: can't export a GIF: {}", message);
+                        process::exit(1);
+                    }
+                    error!(%message, "can't record video");
+                    video.take().unwrap().stop();
+                }
+            }
+            if let Some(flock) = simulation.scene().flock() {
+                flock.draw_overlay(&mut debug_draw);
+            }
+            if lit {
+                light_rig.draw_overlay(&mut debug_draw, state.time);
+            }
+            style_rig.draw_overlay(&mut debug_draw);
+            let instances = if settings.demo.is_some_and(DemoKind::replaces_scene) {
+                // The demo is drawn instead of the scene.
+                scene::PackedInstances {
+                    meshes: Instances::default(),
+                    draw_list: &no_draws,
+                    particles: Instances::default(),
+                    selected: &[],
+                }
+            } else {
+                profile_zone!("pack instances");
+                let scene = simulation.scene_mut();
+                let [x, y] = mesh_extent(state.mouse, state.zoom);
+                scene.set_view_extent(x.max(y));
+                scene.pack_instances(state.time, state.alpha)
+            };
+            #[cfg(feature = "profile")]
+            flamegraph.draw(&mut debug_draw);
+            if show_bounds && !settings.demo.is_some_and(DemoKind::replaces_scene) {
+                let extent = mesh_extent(state.mouse, state.zoom);
+                debug_draw.instance_bounds(instances.meshes.data, extent);
+                debug_draw.circle([0.0, 0.0], 0.02, [1.0, 0.0, 1.0, 1.0]);
+            }
+            let result = match surface_error.take() {
+                Some(error) => Err(error),
+                None => renderer.as_mut().unwrap().render(
+                    &frame,
+                    instances.meshes,
+                    instances.draw_list,
+                    instances.selected,
+                    instances.particles,
+                    debug_draw.vertices(),
+                ),
+            };
+            debug_draw.clear();
+            match result {
+                Ok(()) => {
+                    device_lost_count = 0;
+                    if let Some(renderdoc) = renderdoc.as_mut() {
+                        renderdoc.report_captures();
+                    }
+                    let renderer = renderer.as_mut().unwrap();
+                    if let Some(statistics) = renderer.take_pipeline_statistics() {
+                        frame_stats.pipeline_statistics_read(statistics);
+                    }
+                    frame_stats.frame_presented(renderer.culled_clusters());
+                }
+                Err(error) => {
+                    let device_lost = matches!(error, RenderError::DeviceLost);
+                    if let (true, Some(path), Some(report)) = (
+                        device_lost,
+                        &options.crash_dump,
+                        renderer.as_ref().unwrap().crash_report(),
+                    ) {
+                        match fs::write(path, report) {
+                            Ok(()) => error!(path = %path.display(), "wrote crash dump"),
+                            Err(e) => error!(error = %e, "failed to write crash dump"),
+                        }
+                    }
+                    if gif_export.is_some() {
+                        // The frames in flight are gone, which would leave a gap.
+                        eprintln!("error: the renderer was lost while exporting the GIF");
+                        process::exit(1);
+                    }
+                    // The old swapchain must be gone before a new one can use the surface.
+                    let surface = renderer.take().unwrap().into_surface();
+                    if let RenderError::Surface(e) = error {
+                        warn!(error = %e, "can't draw to the new surface, selecting a device");
+                        match gpu::select(
+                            &instance,
+                            &surface,
+                            &renderer::device_extensions(),
+                            options.gpu.as_ref(),
+                        ) {
+                            Ok((physical_device, queue_family)) => {
+                                physical_device_index = physical_device.index();
+                                queue_family_id = queue_family.id();
+                            }
+                            Err(message) => {
+                                eprintln!("error: {}", message);
+                                shut_down(&mut video, surface.window(), &options);
+                                process::exit(NO_DEVICE_EXIT_CODE);
+                            }
+                        }
+                    }
+                    match recreate_renderer(
+                        &instance,
+                        &surface,
+                        physical_device_index,
+                        queue_family_id,
+                        &settings,
+                        &mut device_lost_count,
+                        options.device_lost_retries,
+                    ) {
+                        Some(recreated) => renderer = Some(recreated),
+                        None => {
+                            shut_down(&mut video, surface.window(), &options);
+                            process::exit(1);
+                        }
+                    }
+                    // Handed out again in the same order, so the scene's ids stay valid.
+                    if texture_count > 0 || lit {
+                        add_texture_style(renderer.as_mut().unwrap(), lit);
+                    }
+                    light_rig.apply(renderer.as_mut().unwrap());
+                    if options.clusters > 0 {
+                        add_cluster_style(renderer.as_mut().unwrap());
+                    }
+                    if options.lod_discs {
+                        renderer.as_mut().unwrap().add_mesh(renderer::disc_mesh());
+                    }
+                    if let Some(path) = &options.shadertoy {
+                        let renderer = renderer.as_mut().unwrap();
+                        if let Err(message) = load_shadertoy(path, renderer, &mut shader_loader) {
+                            error!(%message, "failed to reload the ShaderToy shader");
+                        }
+                    }
+                }
+            }
+
+            if let Some(captured) = renderer.as_mut().unwrap().take_captured_frame() {
+                if let Some(gif_export) = gif_export.as_mut() {
+                    if let Err(message) = gif_export.add_frame(captured) {
+                        eprintln!("error: {}", message);
+                        process::exit(1);
+                    }
+                } else if let Some(recording) = video.as_mut() {
+                    if let Err(message) = recording.push(captured) {
+                        error!(%message, "video recording stopped");
+                        video.take().unwrap().stop();
+                    }
+                }
+            }
+            if let Some(frame) = renderer.as_mut().unwrap().take_captured_hdr_frame() {
+                match exr::save_frame(&options.exr_output, &frame) {
+                    Ok(path) => info!(path = %path.display(), "exported the HDR image"),
+                    Err(message) => error!(%message, "can't export the HDR image"),
+                }
+            }
+            if gif_export.as_ref().is_some_and(GifExport::is_done) {
+                *control_flow = ControlFlow::Exit;
+            }
+            let cpu_time = render_start.elapsed();
+            let gpu_time = renderer.as_mut().unwrap().take_gpu_frame_time();
+            if let Some(log) = perf_log.as_mut() {
+                let timings = renderer.as_ref().unwrap().frame_timings();
+                if let Err(e) = log.frame_done(cpu_time, gpu_time, &timings) {
+                    error!(error = %e, "failed to write the performance log, stopping it");
+                    perf_log = None;
+                }
+            }
+            if let Some(benchmark) = benchmark.as_mut() {
+                let renderer = renderer.as_mut().unwrap();
+                if benchmark.frame_done(cpu_time, gpu_time) {
+                    benchmark.report(renderer.device().physical_device(), &options.bench_output);
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            if let Some(frame_limiter) = frame_limiter.as_mut() {
+                frame_limiter.wait();
+            }
+            if let Some(power_save) = power_save.as_mut() {
+                power_save.frame_drawn(control_flow);
+            }
         }
-    }
+    });
+}
 
-    mod fragment_shader {
-        vulkano_shaders::shader! {
-            ty: "fragment",
-            src: "
-            #version 460
+/// Moves to the next cursor mode, falling back to `Free` when the platform refuses it.
+fn cycle_cursor_mode(cursor_mode: &mut CursorMode, window: &Window) {
+    *cursor_mode = cursor_mode.next();
+    if let Err(e) = cursor_mode.apply(window) {
+        warn!(error = %e, mode = ?cursor_mode, "can't change the cursor mode");
+        *cursor_mode = CursorMode::Free;
+        let _ = cursor_mode.apply(window);
+    }
+    info!(mode = ?cursor_mode, "cursor mode");
+}
 
-            layout(location = 0) out vec4 f_color;
-            layout(location = 0) in vec4 in_color;
+/// Replaces the scene, camera and clear color with the ones saved in `path`. Failures
+/// are logged and leave the current scene in place.
+fn load_scene(
+    path: &Path,
+    simulation: &mut Simulation,
+    input: &mut simulation::Input,
+    settings: &mut RendererSettings,
+    renderer: &mut Renderer,
+) {
+    match SceneFile::load(path) {
+        Ok(file) => {
+            file.restore(simulation.scene_mut());
+            simulation.set_camera(&file.camera);
+            // Keep the restored zoom until the next scroll or pinch.
+            input.scroll = 0.0;
+            input.pinch = file.camera.zoom;
+            settings.background_color = file.clear_color;
+            renderer.set_background_color(file.clear_color);
+            info!(path = %path.display(), "loaded scene");
+        }
+        Err(message) => error!(%message, "failed to load the scene"),
+    }
+}
 
-            void main() {
-                f_color = in_color;
-            }
-            "
+/// Watches the files loaded shaders included, so editing one reloads its shaders.
+fn watch_includes(watcher: &mut AssetWatcher, shaders: &ShaderLoader) {
+    for path in shaders.included_files() {
+        if let Err(message) = watcher.watch(path) {
+            warn!(%message, "can't watch included file");
         }
     }
+}
 
-    let loaded_vertex_shader = vertex_shader::load(logical_device.clone()).unwrap();
-    let loaded_fragment_shader = fragment_shader::load(logical_device.clone()).unwrap();
+/// Compiles the ShaderToy shader in `path` and runs it in the renderer.
+fn load_shadertoy(
+    path: &Path,
+    renderer: &mut Renderer,
+    shaders: &mut ShaderLoader,
+) -> Result<(), String> {
+    let module = shaders.load_shadertoy(renderer.device(), path)?;
+    renderer.set_shadertoy_shader(module)
+}
 
-    let render_pass = vulkano::single_pass_renderpass!(
-        logical_device.clone(),
-        attachments: {
-            color: {
-                load: Clear,
-                store: Store,
-                format: swapchain.image_format(),
-                samples: 1,
+/// A map much larger than the window, so most of its chunks are culled, striped
+/// with every tile of the atlas and dotted with empty holes.
+fn demo_tilemap(tile_count: u32) -> tilemap::Tilemap {
+    let mut tilemap = tilemap::Tilemap::new(256, 256, [0.05, 0.05]);
+    for y in 0..256 {
+        for x in 0..256 {
+            if (x / 8 + y / 8) % 5 != 0 {
+                tilemap.set(x, y, (x + y) / 4 % tile_count);
             }
-        },
-        pass: {
-            color: [color],
-            depth_stencil: {}
         }
-    )
-    .unwrap();
+    }
+    tilemap
+}
 
-    let graphics_pipeline = GraphicsPipeline::start()
-        .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
-        .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
-        .input_assembly_state(InputAssemblyState::new())
-        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
-        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
-        .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
-        .build(logical_device.clone())
+/// Adds the mesh and material cluster children are drawn with.
+fn add_cluster_style(renderer: &mut Renderer) -> (MeshId, MaterialId) {
+    let mesh = renderer.add_mesh(renderer::quad_mesh());
+    let material = renderer
+        .add_material(renderer::stripes_shader(renderer.device()))
         .unwrap();
+    (mesh, material)
+}
 
-    let mut viewport = Viewport {
-        origin: [0.0, 0.0],
-        dimensions: [0.0, 0.0],
-        depth_range: 0.0..1.0,
+/// Adds the material textured instances are drawn with, lit if they're normal
+/// mapped or there are lights.
+fn add_texture_style(renderer: &mut Renderer, lit: bool) -> MaterialId {
+    let shader = if lit {
+        renderer.lit_shader()
+    } else {
+        renderer.textured_shader()
     };
+    renderer.add_material(shader).unwrap()
+}
 
-    let mut framebuffers = window_size_dependent_setup(&images, render_pass.clone(), &mut viewport);
+/// Spawns an instance under `cursor`, normalized to `[0, 1]` across the window, on a
+/// left click, and removes the one nearest to it on a right click. A middle click
+/// blasts the physics bodies away from it.
+fn click(scene: &mut Scene, button: MouseButton, cursor: [f32; 2]) {
+    let position = [2.0 * cursor[0] - 1.0, 2.0 * cursor[1] - 1.0];
+    match button {
+        MouseButton::Left => {
+            scene.spawn_instance(position);
+            info!(?position, "spawned instance");
+        }
+        MouseButton::Right => {
+            let removed = scene.despawn_nearest(position);
+            if removed.is_some() {
+                info!(?position, "removed instance");
+            }
+        }
+        #[cfg(feature = "physics")]
+        MouseButton::Middle => scene.blast(position),
+        _ => {}
+    }
+}
 
-    let mut recreate_swapchain = false;
-    let mut previous_frame_end = Some(sync::now(logical_device.clone()).boxed());
+/// Selects the instance nearest to `cursor`, normalized to `[0, 1]` across the
+/// window, or deselects it if it was selected. `--outline` outlines the selection.
+fn pick(scene: &mut Scene, cursor: [f32; 2]) {
+    let position = [2.0 * cursor[0] - 1.0, 2.0 * cursor[1] - 1.0];
+    if let Some((entity, selected)) = scene.toggle_selection(position) {
+        info!(?entity, selected, "picked instance");
+    }
+}
 
-    use winit::dpi::PhysicalPosition;
-    let mut mouse_pos = [0.0, 0.0];
+/// Tunes the boids of `--demo boids` with `key`, if it's one of theirs.
+fn tune_flock(scene: &mut Scene, key: VirtualKeyCode) {
+    if let Some(flock) = scene.flock_mut() {
+        flock.handle_key(key);
+    }
+}
 
-    event_loop.run(move |event, _, control_flow| match event {
-        Event::WindowEvent {
-            event: WindowEvent::CursorMoved { position, .. },
-            ..
-        } => {
-            let dimensions = surface.window().inner_size();
-            mouse_pos = [position.x/(dimensions.width as f64), position.y/(dimensions.height as f64)];
-        }
-        Event::WindowEvent {
-            event: WindowEvent::CloseRequested,
-            ..
-        } => {
-            *control_flow = ControlFlow::Exit;
-        }
-        Event::WindowEvent {
-            event: WindowEvent::Resized(_),
-            ..
-        } => {
-            recreate_swapchain = true;
-        }
-        Event::RedrawEventsCleared => {
-            let dimensions = surface.window().inner_size();
-            if dimensions.width == 0 || dimensions.height == 0 {
-                return;
-            }
-            previous_frame_end.as_mut().unwrap().cleanup_finished();
-            if recreate_swapchain {
-                let (new_swapchain, new_images) = match swapchain.recreate(SwapchainCreateInfo {
-                    image_extent: dimensions.into(),
-                    ..swapchain.create_info()
-                }) {
-                    Ok(r) => r,
-                    Err(SwapchainCreationError::ImageExtentNotSupported { .. }) => return,
-                    Err(e) => panic!("Failed to recreate swapchain: {:?}", e),
-                };
-                swapchain = new_swapchain;
-                framebuffers =
-                    window_size_dependent_setup(&new_images, render_pass.clone(), &mut viewport);
-                recreate_swapchain = false;
-            }
-
-            let (image_num, suboptimal, acquire_future) =
-                match acquire_next_image(swapchain.clone(), None) {
-                    Ok(r) => r,
-                    Err(AcquireError::OutOfDate) => {
-                        recreate_swapchain = true;
-                        return;
-                    }
-                    Err(e) => panic!("Failed to acquire next image: {:?}", e),
-                };
+/// How far the mesh reaches from an instance's origin along each axis of its space:
+/// about half a unit, scaled like the vertex shader does, plus the wobble at rest.
+fn mesh_extent(mouse: [f32; 2], zoom: f32) -> [f32; 2] {
+    [
+        0.5 * (mouse[0] * zoom).abs() + 0.5,
+        0.5 * (mouse[1] * zoom).abs() + 0.5,
+    ]
+}
 
-            if suboptimal {
-                recreate_swapchain = true;
-            }
+fn toggle_clusters(scene: &mut Scene, visible: &mut bool) {
+    *visible = !*visible;
+    for cluster in scene.group_nodes() {
+        scene.set_visible(cluster, *visible);
+    }
+}
 
-            let mut builder = AutoCommandBufferBuilder::primary(
-                logical_device.clone(),
-                queue.family(),
-                CommandBufferUsage::OneTimeSubmit,
-            )
-            .unwrap();
+/// Logs at `info` by default; set `RUST_LOG` (e.g. `RUST_LOG=debug` for frame
+/// stats or `RUST_LOG=vulkano_triangle_tutorial=trace` for per-frame events) to change it.
+fn init_logging() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        // Android sends stdout to logcat, which shows escape codes as they are.
+        .with_ansi(!cfg!(target_os = "android"))
+        .init();
+}
 
-            let push_constants = vertex_shader::ty::PushConstantData {
-                time: (Instant::now() - app_start).as_secs_f32(),
-                x: mouse_pos[0] as f32,
-                y: mouse_pos[1] as f32
-            };
+/// Android windows have nothing to draw to until the app is first resumed.
+#[cfg(target_os = "android")]
+fn wait_for_resume(mut event_loop: EventLoop<()>) -> EventLoop<()> {
+    use winit::platform::run_return::EventLoopExtRunReturn;
 
-            use vulkano::pipeline::Pipeline;
-            builder
-                .begin_render_pass(
-                    RenderPassBeginInfo {
-                        clear_values: vec![Some(background_color.into())],
-                        ..RenderPassBeginInfo::framebuffer(framebuffers[image_num].clone())
-                    },
-                    SubpassContents::Inline,
-                )
-                .unwrap()
-                .set_viewport(0, [viewport.clone()])
-                .bind_pipeline_graphics(graphics_pipeline.clone())
-                .bind_vertex_buffers(0, vertex_buffer.clone())
-                .push_constants(graphics_pipeline.layout().clone(), 0, push_constants)
-                .draw(vertex_buffer.len() as u32, instance_count, 0, 0)
-                .unwrap()
-                .end_render_pass()
-                .unwrap();
-            let command_buffer = builder.build().unwrap();
-
-            let future = previous_frame_end
-                .take()
-                .unwrap()
-                .join(acquire_future)
-                .then_execute(queue.clone(), command_buffer)
-                .unwrap()
-                .then_swapchain_present(queue.clone(), swapchain.clone(), image_num)
-                .then_signal_fence_and_flush();
-
-            match future {
-                Ok(future) => {
-                    previous_frame_end = Some(future.boxed());
-                }
-                Err(FlushError::OutOfDate) => {
-                    recreate_swapchain = true;
-                    previous_frame_end = Some(sync::now(logical_device.clone()).boxed());
-                }
-                Err(e) => {
-                    println!("Failed to flush future: {:?}", e);
-                    previous_frame_end = Some(sync::now(logical_device.clone()).boxed());
-                }
-            }
-        }
-        _ => (),
+    event_loop.run_return(|event, _, control_flow| {
+        *control_flow = match event {
+            Event::Resumed => ControlFlow::Exit,
+            _ => ControlFlow::Wait,
+        };
     });
+    event_loop
 }
 
-fn window_size_dependent_setup(
-    images: &[Arc<SwapchainImage<Window>>],
-    render_pass: Arc<RenderPass>,
-    viewport: &mut Viewport,
-) -> Vec<Arc<Framebuffer>> {
-    let dimensions = images[0].dimensions().width_height();
-    viewport.dimensions = [dimensions[0] as f32, dimensions[1] as f32];
+fn create_window_surface<T>(
+    window_target: &EventLoopWindowTarget<T>,
+    instance: &Arc<Instance>,
+    window_settings: &WindowSettings,
+) -> Arc<WindowSurface> {
+    let window = Arc::new(
+        window_settings
+            .builder(window_target)
+            .build(window_target)
+            .unwrap(),
+    );
+    vulkano_win::create_surface_from_winit(window, instance.clone()).unwrap()
+}
 
-    images
-        .iter()
-        .map(|image| {
-            let view = ImageView::new_default(image.clone()).unwrap();
-            Framebuffer::new(
-                render_pass.clone(),
-                FramebufferCreateInfo {
-                    attachments: vec![view],
-                    ..Default::default()
-                },
-            )
-            .unwrap()
-        })
-        .collect::<Vec<_>>()
+/// What has to happen however the app exits: ffmpeg gets to finish the file and
+/// the window's placement is kept.
+fn shut_down(video: &mut Option<VideoRecorder>, window: &Window, options: &cli::Options) {
+    if let Some(recording) = video.take() {
+        recording.stop();
+    }
+    if options.window.restore_placement {
+        WindowPlacement::save(window);
+    }
+}
+
+/// Creates the renderer again after the device was lost or can't draw to a new
+/// surface, waiting longer after each failed attempt. `None` once `max_retries`
+/// attempts in a row failed.
+fn recreate_renderer(
+    instance: &Arc<Instance>,
+    surface: &Arc<WindowSurface>,
+    physical_device_index: usize,
+    queue_family_id: u32,
+    settings: &RendererSettings,
+    device_lost_count: &mut u32,
+    max_retries: u32,
+) -> Option<Renderer> {
+    loop {
+        *device_lost_count += 1;
+        if *device_lost_count > max_retries {
+            error!(
+                device_lost_count = *device_lost_count,
+                "renderer lost too many times in a row, giving up"
+            );
+            return None;
+        }
+        if *device_lost_count > 1 {
+            // A driver resetting the GPU needs a moment before a device works again.
+            let backoff = RECREATION_BACKOFF * 2u32.pow((*device_lost_count - 2).min(6));
+            debug!(?backoff, "waiting before recreating the renderer");
+            thread::sleep(backoff);
+        }
+
+        warn!(
+            attempt = *device_lost_count,
+            max_retries, "recreating renderer"
+        );
+        match Renderer::new(
+            instance,
+            surface.clone(),
+            physical_device_index,
+            queue_family_id,
+            settings,
+        ) {
+            Ok(renderer) => return Some(renderer),
+            Err(e) => error!(error = %e, "failed to recreate the renderer"),
+        }
+    }
 }
@@ -0,0 +1,146 @@
+use std::{
+    ffi::c_void,
+    time::{Duration, Instant},
+};
+use tracing::debug;
+use vulkano::{
+    device::{physical::PhysicalDevice, Device, DeviceExtensions},
+    DeviceSize, Version, VulkanObject,
+};
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// What a tracked allocation is used for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AllocationPurpose {
+    Vertex,
+    Instance,
+    Uniform,
+    /// Storage buffers, e.g. state written by compute shaders.
+    Storage,
+    /// Sampled images.
+    Texture,
+    /// Host-visible memory data is written to before it's copied to the device.
+    Staging,
+}
+
+impl AllocationPurpose {
+    const ALL: [AllocationPurpose; 6] = [
+        AllocationPurpose::Vertex,
+        AllocationPurpose::Instance,
+        AllocationPurpose::Uniform,
+        AllocationPurpose::Storage,
+        AllocationPurpose::Texture,
+        AllocationPurpose::Staging,
+    ];
+}
+
+/// Budget and usage of one memory heap, as reported by VK_EXT_memory_budget.
+#[derive(Clone, Copy, Debug)]
+pub struct HeapBudget {
+    pub heap: u32,
+    pub device_local: bool,
+    pub size: DeviceSize,
+    pub budget: DeviceSize,
+    pub usage: DeviceSize,
+}
+
+/// Extensions that make [`MemoryStats`] more useful, enabled when the device has them.
+pub fn optional_device_extensions() -> DeviceExtensions {
+    DeviceExtensions {
+        ext_memory_budget: true,
+        ..DeviceExtensions::none()
+    }
+}
+
+/// Per-heap budgets from the driver plus the bytes this crate allocated, by purpose.
+pub struct MemoryStats {
+    budget_supported: bool,
+    allocated: [DeviceSize; AllocationPurpose::ALL.len()],
+    last_report: Instant,
+}
+
+impl MemoryStats {
+    pub fn new(device: &Device) -> Self {
+        MemoryStats {
+            budget_supported: device.enabled_extensions().ext_memory_budget
+                && device.physical_device().api_version() >= Version::V1_1,
+            allocated: [0; AllocationPurpose::ALL.len()],
+            last_report: Instant::now(),
+        }
+    }
+
+    pub fn track(&mut self, purpose: AllocationPurpose, bytes: DeviceSize) {
+        self.allocated[purpose as usize] += bytes;
+    }
+
+    pub fn untrack(&mut self, purpose: AllocationPurpose, bytes: DeviceSize) {
+        self.allocated[purpose as usize] -= bytes;
+    }
+
+    pub fn allocated(&self, purpose: AllocationPurpose) -> DeviceSize {
+        self.allocated[purpose as usize]
+    }
+
+    /// Returns `None` when the device can't report budgets.
+    pub fn heap_budgets(&self, physical_device: PhysicalDevice) -> Option<Vec<HeapBudget>> {
+        if !self.budget_supported {
+            return None;
+        }
+
+        let mut budget_properties = ash::vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let mut memory_properties = ash::vk::PhysicalDeviceMemoryProperties2 {
+            p_next: &mut budget_properties as *mut _ as *mut c_void,
+            ..Default::default()
+        };
+        unsafe {
+            let fns = physical_device.instance().fns();
+            (fns.v1_1.get_physical_device_memory_properties2)(
+                physical_device.internal_object(),
+                &mut memory_properties,
+            );
+        }
+
+        Some(
+            physical_device
+                .memory_heaps()
+                .map(|heap| HeapBudget {
+                    heap: heap.id(),
+                    device_local: heap.is_device_local(),
+                    size: heap.size(),
+                    budget: budget_properties.heap_budget[heap.id() as usize],
+                    usage: budget_properties.heap_usage[heap.id() as usize],
+                })
+                .collect(),
+        )
+    }
+
+    /// Logs the budgets and tracked allocations, at most once per second.
+    pub fn report_if_due(&mut self, physical_device: PhysicalDevice) {
+        if self.last_report.elapsed() < REPORT_INTERVAL {
+            return;
+        }
+        self.last_report = Instant::now();
+
+        if let Some(heaps) = self.heap_budgets(physical_device) {
+            for heap in heaps {
+                debug!(
+                    heap = heap.heap,
+                    device_local = heap.device_local,
+                    usage_mib = heap.usage as f64 / MIB,
+                    budget_mib = heap.budget as f64 / MIB,
+                    size_mib = heap.size as f64 / MIB,
+                    "memory heap"
+                );
+            }
+        }
+        for purpose in AllocationPurpose::ALL {
+            debug!(
+                ?purpose,
+                mib = self.allocated(purpose) as f64 / MIB,
+                "tracked allocations"
+            );
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use tracing::{debug, warn};
+use winit::{
+    event_loop::EventLoopWindowTarget,
+    monitor::{MonitorHandle, VideoMode},
+    window::Fullscreen,
+};
+
+/// How the user asked for a monitor: by its enumeration index or by part of its name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MonitorSelector {
+    Index(usize),
+    Name(String),
+}
+
+impl MonitorSelector {
+    pub fn parse(value: &str) -> Self {
+        match value.trim().parse() {
+            Ok(index) => MonitorSelector::Index(index),
+            Err(_) => MonitorSelector::Name(value.trim().to_lowercase()),
+        }
+    }
+
+    fn matches(&self, index: usize, monitor: &MonitorHandle) -> bool {
+        match self {
+            MonitorSelector::Index(wanted) => index == *wanted,
+            MonitorSelector::Name(name) => monitor
+                .name()
+                .is_some_and(|monitor_name| monitor_name.to_lowercase().contains(name.as_str())),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FullscreenMode {
+    /// A borderless window covering the monitor, keeping the desktop's video mode.
+    Borderless,
+    /// Takes over the monitor in its largest, fastest video mode.
+    Exclusive,
+}
+
+pub fn print_monitors<T>(window_target: &EventLoopWindowTarget<T>) {
+    let primary = window_target.primary_monitor();
+    println!("Available monitors:");
+    for (index, monitor) in window_target.available_monitors().enumerate() {
+        let size = monitor.size();
+        let position = monitor.position();
+        println!(
+            "  [{}] {} ({}x{} at {},{}, scale {}){}",
+            index,
+            monitor.name().unwrap_or_else(|| "<unnamed>".to_owned()),
+            size.width,
+            size.height,
+            position.x,
+            position.y,
+            monitor.scale_factor(),
+            if Some(&monitor) == primary.as_ref() {
+                " primary"
+            } else {
+                ""
+            },
+        );
+        let mut video_modes: Vec<_> = monitor.video_modes().collect();
+        video_modes.sort_by_key(|mode| std::cmp::Reverse(video_mode_rank(mode)));
+        for mode in video_modes {
+            println!(
+                "        {}x{} @ {} Hz, {} bpp",
+                mode.size().width,
+                mode.size().height,
+                mode.refresh_rate(),
+                mode.bit_depth(),
+            );
+        }
+    }
+}
+
+/// Finds the selected monitor. `None` leaves the choice to the window system, as does a
+/// selector that matches nothing, after a warning.
+pub fn select<T>(
+    window_target: &EventLoopWindowTarget<T>,
+    selector: Option<&MonitorSelector>,
+) -> Option<MonitorHandle> {
+    let selector = selector?;
+    let found = window_target
+        .available_monitors()
+        .enumerate()
+        .find(|(index, monitor)| selector.matches(*index, monitor))
+        .map(|(_, monitor)| monitor);
+    match &found {
+        Some(monitor) => debug!(name = ?monitor.name(), "selected monitor"),
+        None => warn!(?selector, "no monitor matches, see --list-monitors"),
+    }
+    found
+}
+
+/// The fullscreen state to open the window in on `monitor`, or the one it ends up on.
+pub fn fullscreen<T>(
+    window_target: &EventLoopWindowTarget<T>,
+    mode: FullscreenMode,
+    monitor: Option<MonitorHandle>,
+) -> Fullscreen {
+    match mode {
+        FullscreenMode::Borderless => Fullscreen::Borderless(monitor),
+        FullscreenMode::Exclusive => {
+            let video_mode = monitor
+                .clone()
+                .or_else(|| window_target.primary_monitor())
+                .and_then(|monitor| monitor.video_modes().max_by_key(video_mode_rank));
+            match video_mode {
+                Some(video_mode) => {
+                    debug!(
+                        size = ?video_mode.size(),
+                        refresh_rate = video_mode.refresh_rate(),
+                        "exclusive fullscreen video mode"
+                    );
+                    Fullscreen::Exclusive(video_mode)
+                }
+                None => {
+                    warn!("monitor has no video modes, using borderless fullscreen");
+                    Fullscreen::Borderless(monitor)
+                }
+            }
+        }
+    }
+}
+
+/// Larger resolutions first, then higher refresh rates, then deeper colors.
+fn video_mode_rank(mode: &VideoMode) -> (u32, u16, u16) {
+    let size = mode.size();
+    (
+        size.width * size.height,
+        mode.refresh_rate(),
+        mode.bit_depth(),
+    )
+}
@@ -0,0 +1,151 @@
+use crate::{
+    gpu,
+    renderer::{
+        FrameData, Instances, OffscreenRenderer, OwnedInstances, RendererSettings, AUDIO_BANDS,
+    },
+    scene::Scene,
+};
+use std::{
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::info;
+use vulkano::instance::Instance;
+
+/// Large enough that drawing a frame costs more than copying it between devices.
+const EXTENT: [u32; 2] = [1280, 720];
+
+/// Frames drawn on each device before measuring, so pipeline creation and first-use
+/// driver work don't count.
+const WARMUP_FRAMES: u32 = 10;
+
+/// Experimental explicit multi-adapter rendering for `--multi-gpu`: draws `frames`
+/// frames on the most capable device alone, then again alternating between it and
+/// a second device, and reports the speedup.
+///
+/// Alternate-frame rendering: the second device draws every odd frame on a thread
+/// of its own while the first draws the even frame before it. Devices share no
+/// memory, so each odd frame is read back to the host and copied into an image on
+/// the first device, which would present it; that copy is part of the cost. Frames
+/// are drawn offscreen, with the main mesh and material only.
+pub fn run(
+    instance: &Arc<Instance>,
+    settings: &RendererSettings,
+    frames: u32,
+) -> Result<(), String> {
+    let devices = gpu::headless_candidates(instance);
+    let [(primary, primary_family), (secondary, secondary_family)] = match devices[..] {
+        [first, second, ..] => [first, second],
+        _ => {
+            return Err(format!(
+                "--multi-gpu needs two devices that support graphics, found {}",
+                devices.len()
+            ))
+        }
+    };
+    println!(
+        "presenting device: [{}] {}\nsecond device:     [{}] {}",
+        primary.index(),
+        primary.properties().device_name,
+        secondary.index(),
+        secondary.properties().device_name,
+    );
+
+    let mut scene = Scene::new(settings.instance_count);
+    let workload: Vec<_> = (0..WARMUP_FRAMES + frames)
+        .map(|index| {
+            let time = index as f32 / 60.0;
+            scene.pack_instances(time, 1.0).meshes.owned()
+        })
+        .collect();
+
+    let mut presenting = OffscreenRenderer::new(
+        instance,
+        primary.index(),
+        primary_family.id(),
+        settings,
+        EXTENT,
+    )?;
+    let single = time_frames(&workload, |index, instances| {
+        presenting.render(&frame_data(index), instances).map(drop)
+    })?;
+    info!(device = %primary.properties().device_name, ?single, "single-device frames");
+
+    // The second device lives on its thread; odd frames go there and come back as
+    // pixels.
+    let (jobs, job_receiver) = mpsc::channel::<(u32, OwnedInstances)>();
+    let (result_sender, results) = mpsc::channel::<Result<Vec<u8>, String>>();
+    let mut second = OffscreenRenderer::new(
+        instance,
+        secondary.index(),
+        secondary_family.id(),
+        settings,
+        EXTENT,
+    )?;
+    let worker = thread::Builder::new()
+        .name("second_device".to_owned())
+        .spawn(move || {
+            for (index, instances) in job_receiver {
+                let result = second.render(&frame_data(index), instances.as_instances());
+                if result_sender.send(result).is_err() {
+                    break;
+                }
+            }
+        })
+        .unwrap();
+
+    let alternating = time_frames(&workload, |index, instances| {
+        if index % 2 == 1 {
+            // Drawn already, alongside the even frame before it.
+            let pixels = results.recv().map_err(|e| e.to_string())??;
+            return presenting.receive(&pixels);
+        }
+        if let Some(next) = workload.get(index as usize + 1) {
+            jobs.send((index + 1, next.clone()))
+                .map_err(|e| e.to_string())?;
+        }
+        presenting.render(&frame_data(index), instances).map(drop)
+    });
+    drop(jobs);
+    let _ = worker.join();
+    let alternating = alternating?;
+
+    let speedup = single.as_secs_f64() / alternating.as_secs_f64();
+    println!(
+        "{} frames at {}x{}:\n  one device:  {:.3} ms/frame\n  two devices: {:.3} ms/frame\n  speedup:     {:.2}x",
+        frames,
+        EXTENT[0],
+        EXTENT[1],
+        single.as_secs_f64() * 1000.0 / frames as f64,
+        alternating.as_secs_f64() * 1000.0 / frames as f64,
+        speedup,
+    );
+    Ok(())
+}
+
+/// Runs `draw` for every frame of `workload` and returns the time the frames after
+/// the warmup took.
+fn time_frames(
+    workload: &[OwnedInstances],
+    mut draw: impl FnMut(u32, Instances) -> Result<(), String>,
+) -> Result<Duration, String> {
+    let mut started = Instant::now();
+    for (index, instances) in workload.iter().enumerate() {
+        if index as u32 == WARMUP_FRAMES {
+            started = Instant::now();
+        }
+        draw(index as u32, instances.as_instances())?;
+    }
+    Ok(started.elapsed())
+}
+
+/// The animation at frame `index`, at 60 frames per second.
+fn frame_data(index: u32) -> FrameData {
+    FrameData {
+        time: index as f32 / 60.0,
+        mouse: [0.5, 0.5],
+        zoom: 1.0,
+        audio_bands: [0.0; AUDIO_BANDS],
+    }
+}
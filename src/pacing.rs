@@ -0,0 +1,164 @@
+use std::{
+    ops::RangeInclusive,
+    thread,
+    time::{Duration, Instant},
+};
+use winit::{event_loop::ControlFlow, window::Window};
+
+/// Frame rates `--fps-cap` and `--idle-fps` take: from a frame every ten seconds to
+/// far past any display's.
+pub const FPS_RANGE: RangeInclusive<f64> = 0.1..=10_000.0;
+
+/// Event-driven redraw scheduling for `--power-save`: the loop sleeps until
+/// the next idle frame is due or input arrives, instead of polling.
+pub struct PowerSave {
+    /// `None` redraws on input only.
+    idle_interval: Option<Duration>,
+    next_redraw: Instant,
+}
+
+impl PowerSave {
+    /// `idle_fps` is 0 or within [`FPS_RANGE`].
+    pub fn new(idle_fps: f64) -> Self {
+        PowerSave {
+            idle_interval: (idle_fps > 0.0).then(|| Duration::from_secs_f64(1.0 / idle_fps)),
+            next_redraw: Instant::now(),
+        }
+    }
+
+    /// Call once all events of an iteration have been handled.
+    pub fn schedule(&mut self, window: &Window, control_flow: &mut ControlFlow) {
+        if *control_flow == ControlFlow::Exit {
+            return;
+        }
+        match self.idle_interval {
+            Some(_) => {
+                if Instant::now() >= self.next_redraw {
+                    window.request_redraw();
+                }
+                *control_flow = ControlFlow::WaitUntil(self.next_redraw);
+            }
+            None => *control_flow = ControlFlow::Wait,
+        }
+    }
+
+    pub fn frame_drawn(&mut self, control_flow: &mut ControlFlow) {
+        if let Some(interval) = self.idle_interval {
+            self.next_redraw = Instant::now() + interval;
+            if *control_flow != ControlFlow::Exit {
+                *control_flow = ControlFlow::WaitUntil(self.next_redraw);
+            }
+        }
+    }
+}
+
+/// How long after the last `Resized` event the window still counts as being resized.
+const LIVE_RESIZE_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Detects interactive resizing. Some platforms run a modal loop while the user
+/// drags the window edge and only deliver `Resized`/`RedrawRequested`, so frames
+/// have to be drawn from those events for the scene to follow the edge.
+#[derive(Default)]
+pub struct LiveResize {
+    last_resize: Option<Instant>,
+}
+
+impl LiveResize {
+    pub fn resized(&mut self) {
+        self.last_resize = Some(Instant::now());
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.last_resize
+            .is_some_and(|last_resize| last_resize.elapsed() < LIVE_RESIZE_TIMEOUT)
+    }
+}
+
+/// `thread::sleep` routinely overshoots by a fraction of a millisecond, so the
+/// last stretch before a deadline is spent spinning instead.
+const SPIN_THRESHOLD: Duration = Duration::from_micros(500);
+
+/// Caps the frame rate independently of the present mode by waiting after each present.
+pub struct FrameLimiter {
+    frame_time: Duration,
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    /// `max_fps` is within [`FPS_RANGE`].
+    pub fn new(max_fps: f64) -> Self {
+        FrameLimiter {
+            frame_time: Duration::from_secs_f64(1.0 / max_fps),
+            next_frame: Instant::now(),
+        }
+    }
+
+    pub fn wait(&mut self) {
+        sleep_until(self.next_frame);
+        self.schedule_next(Instant::now());
+    }
+
+    fn schedule_next(&mut self, now: Instant) {
+        // Advance from the ideal time to avoid drift, but don't rush to catch up after a hitch.
+        self.next_frame = if now.saturating_duration_since(self.next_frame) > self.frame_time {
+            now + self.frame_time
+        } else {
+            self.next_frame + self.frame_time
+        };
+    }
+}
+
+fn sleep_until(deadline: Instant) {
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return;
+        }
+        if remaining > SPIN_THRESHOLD {
+            thread::sleep(remaining - SPIN_THRESHOLD);
+        } else {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limiter_waits_until_the_next_frame_is_due() {
+        let mut limiter = FrameLimiter::new(100.0);
+        limiter.wait();
+        let deadline = limiter.next_frame;
+        limiter.wait();
+        assert!(Instant::now() >= deadline);
+    }
+
+    #[test]
+    fn limiter_schedules_from_the_ideal_time() {
+        let mut limiter = FrameLimiter::new(100.0);
+        let deadline = limiter.next_frame;
+        // A frame that ran a little late doesn't push the ones after it back.
+        limiter.schedule_next(deadline + Duration::from_millis(3));
+        assert_eq!(limiter.next_frame, deadline + Duration::from_millis(10));
+    }
+
+    #[test]
+    fn limiter_doesnt_catch_up_after_a_hitch() {
+        let mut limiter = FrameLimiter::new(100.0);
+        let late = limiter.next_frame + Duration::from_millis(50);
+        limiter.schedule_next(late);
+        assert_eq!(limiter.next_frame, late + Duration::from_millis(10));
+    }
+
+    #[test]
+    fn resizing_stays_active_until_the_events_stop() {
+        let mut live_resize = LiveResize::default();
+        assert!(!live_resize.is_active());
+        live_resize.resized();
+        assert!(live_resize.is_active());
+        live_resize.last_resize = Some(Instant::now() - LIVE_RESIZE_TIMEOUT);
+        assert!(!live_resize.is_active());
+    }
+}
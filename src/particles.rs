@@ -0,0 +1,123 @@
+use crate::renderer::InstanceData;
+use std::f32::consts::FRAC_PI_2;
+
+/// Live particles per emitter beyond which spawning pauses.
+const MAX_PARTICLES: usize = 20_000;
+
+/// Spawns particles at its entity's translation and simulates them on the CPU. The
+/// particles are drawn as extra instances of the mesh with additive blending.
+/// Emitters don't follow a [`Parent`](crate::scene::Parent).
+#[derive(Clone, Debug)]
+pub struct Emitter {
+    /// Particles spawned per second.
+    pub rate: f32,
+    /// Seconds a particle lives.
+    pub lifetime: f32,
+    /// Clip space units per second at birth.
+    pub speed: f32,
+    /// Radians, counter-clockwise from +x. Clip space is y-down, so up is -π/2.
+    pub direction: f32,
+    /// Full angle of the cone particles are emitted into, in radians.
+    pub spread: f32,
+    /// Added to each particle's velocity every second, e.g. gravity.
+    pub acceleration: [f32; 2],
+    /// Scale of the mesh per particle.
+    pub size: f32,
+    /// Tint at birth; particles fade linearly to `end_color` over their lifetime.
+    /// Blending is additive, so fading to black fades out.
+    pub start_color: [f32; 4],
+    pub end_color: [f32; 4],
+    particles: Vec<Particle>,
+    /// Fraction of a particle owed from previous updates.
+    spawn_debt: f32,
+    /// Fixed seed, so replays and benchmarks see the same particles.
+    rng: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Particle {
+    position: [f32; 2],
+    velocity: [f32; 2],
+    age: f32,
+}
+
+impl Emitter {
+    /// A fountain of sparks shooting up and falling back.
+    pub fn new(rate: f32) -> Self {
+        Emitter {
+            rate,
+            lifetime: 1.5,
+            speed: 0.9,
+            direction: -FRAC_PI_2,
+            spread: 0.6,
+            acceleration: [0.0, 1.2],
+            size: 0.04,
+            start_color: [1.0, 0.6, 0.2, 1.0],
+            end_color: [0.0, 0.0, 0.0, 1.0],
+            particles: Vec::new(),
+            spawn_debt: 0.0,
+            rng: 0x9e37_79b9,
+        }
+    }
+
+    /// Ages, moves and retires the live particles, then spawns this tick's share of
+    /// new ones at `origin`.
+    pub fn update(&mut self, dt: f32, origin: [f32; 2]) {
+        let lifetime = self.lifetime;
+        self.particles
+            .retain(|particle| particle.age + dt < lifetime);
+        for particle in &mut self.particles {
+            particle.age += dt;
+            for axis in 0..2 {
+                particle.velocity[axis] += self.acceleration[axis] * dt;
+                particle.position[axis] += particle.velocity[axis] * dt;
+            }
+        }
+
+        self.spawn_debt += self.rate * dt;
+        while self.spawn_debt >= 1.0 {
+            self.spawn_debt -= 1.0;
+            if self.particles.len() < MAX_PARTICLES {
+                let angle = self.direction + (self.random() - 0.5) * self.spread;
+                let speed = self.speed * (0.75 + 0.5 * self.random());
+                self.particles.push(Particle {
+                    position: origin,
+                    velocity: [angle.cos() * speed, angle.sin() * speed],
+                    age: 0.0,
+                });
+            }
+        }
+    }
+
+    /// Adds an instance for every live particle to `instances`, and its color, faded
+    /// from the start color to the end color over its lifetime, to `colors`.
+    pub fn pack_instances(&self, instances: &mut Vec<InstanceData>, colors: &mut Vec<[f32; 4]>) {
+        for particle in &self.particles {
+            let t = particle.age / self.lifetime;
+            let mut color = [0.0; 4];
+            for (channel, (start, end)) in color
+                .iter_mut()
+                .zip(self.start_color.iter().zip(self.end_color))
+            {
+                *channel = start + (end - start) * t;
+            }
+            instances.push(InstanceData {
+                basis_x: [self.size, 0.0],
+                basis_y: [0.0, self.size],
+                translation: particle.position,
+                phase: [0.0, 0.0],
+                texture: 0,
+                normal_map: 0,
+            });
+            colors.push(color);
+        }
+    }
+
+    /// Uniform in `[0, 1)`, from a xorshift generator.
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng >> 8) as f32 / (1 << 24) as f32
+    }
+}
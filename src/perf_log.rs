@@ -0,0 +1,85 @@
+use crate::renderer::FrameTimings;
+use serde_json::json;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+const CSV_HEADER: &str =
+    "frame,time_s,cpu_ms,gpu_ms,frame_wait_ms,acquire_ms,submit_ms,present_ms,swapchain_recreated";
+
+/// Appends a row per frame to `--perf-log`: as CSV, or as JSON lines when the file
+/// ends in `.json` or `.jsonl`. Each row is written as it comes, so a run that
+/// crashes keeps its last frames.
+pub struct PerfLog {
+    file: File,
+    json: bool,
+    started: Instant,
+    frame: u64,
+}
+
+impl PerfLog {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        let json = matches!(extension, Some("json" | "jsonl"));
+        let open = || {
+            let new_file = !path.exists();
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            if new_file && !json {
+                writeln!(file, "{}", CSV_HEADER)?;
+            }
+            Ok::<_, io::Error>(file)
+        };
+        let file = open().map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(PerfLog {
+            file,
+            json,
+            started: Instant::now(),
+            frame: 0,
+        })
+    }
+
+    /// `gpu_time` is from [`Renderer::take_gpu_frame_time`](crate::renderer::Renderer::take_gpu_frame_time),
+    /// so it belongs to the frame as many frames back as there are in flight; it's
+    /// left empty when none was read back.
+    pub fn frame_done(
+        &mut self,
+        cpu_time: Duration,
+        gpu_time: Option<Duration>,
+        timings: &FrameTimings,
+    ) -> io::Result<()> {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let time = self.started.elapsed().as_secs_f64();
+        let row = if self.json {
+            json!({
+                "frame": self.frame,
+                "time_s": time,
+                "cpu_ms": ms(cpu_time),
+                "gpu_ms": gpu_time.map(ms),
+                "frame_wait_ms": ms(timings.frame_wait),
+                "acquire_ms": ms(timings.acquire),
+                "submit_ms": ms(timings.submit),
+                "present_ms": ms(timings.present),
+                "swapchain_recreated": timings.swapchain_recreated,
+            })
+            .to_string()
+        } else {
+            format!(
+                "{},{:.6},{:.3},{},{:.3},{:.3},{:.3},{:.3},{}",
+                self.frame,
+                time,
+                ms(cpu_time),
+                gpu_time.map_or(String::new(), |gpu_time| format!("{:.3}", ms(gpu_time))),
+                ms(timings.frame_wait),
+                ms(timings.acquire),
+                ms(timings.submit),
+                ms(timings.present),
+                timings.swapchain_recreated,
+            )
+        };
+        self.frame += 1;
+        writeln!(self.file, "{}", row)
+    }
+}
@@ -0,0 +1,148 @@
+use rapier2d::prelude::*;
+
+/// Clip space units per second squared. Clip space y points down, so it's positive.
+const GRAVITY: Real = 2.0;
+
+/// Half the side of an instance's box collider at scale 1, about the default mesh's
+/// extent.
+pub const BODY_HALF_EXTENT: f32 = 0.25;
+
+/// Thickness of the walls around the view, so fast bodies don't tunnel through.
+const WALL_THICKNESS: Real = 0.5;
+
+/// Bodies within this distance of a blast are pushed away.
+const BLAST_RADIUS: f32 = 0.5;
+
+/// Speed a blast gives the bodies at its center, in clip space units per second,
+/// falling off to zero at [`BLAST_RADIUS`].
+const BLAST_SPEED: f32 = 3.0;
+
+/// The rigid body simulating an entity for `--physics`, whose transform follows it.
+#[derive(Clone, Copy, Debug)]
+pub struct Body(pub RigidBodyHandle);
+
+/// A rapier world of box-shaped bodies falling inside the view, which is walled in on
+/// the sides and the bottom. It's stepped once per simulation tick.
+pub struct Physics {
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    integration_parameters: IntegrationParameters,
+    pipeline: PhysicsPipeline,
+    islands: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    impulse_joints: ImpulseJointSet,
+    multibody_joints: MultibodyJointSet,
+    ccd_solver: CCDSolver,
+}
+
+impl Physics {
+    /// Steps `dt` seconds at a time.
+    pub fn new(dt: f32) -> Self {
+        let mut colliders = ColliderSet::new();
+        let half = 1.0 + WALL_THICKNESS;
+        // Floor, then the left and right walls, their inner faces on the view's edges.
+        for (translation, extent) in [
+            (vector![0.0, half], vector![half, WALL_THICKNESS]),
+            (vector![-half, 0.0], vector![WALL_THICKNESS, half]),
+            (vector![half, 0.0], vector![WALL_THICKNESS, half]),
+        ] {
+            colliders.insert(
+                ColliderBuilder::cuboid(extent.x, extent.y)
+                    .translation(translation)
+                    .build(),
+            );
+        }
+        Physics {
+            bodies: RigidBodySet::new(),
+            colliders,
+            integration_parameters: IntegrationParameters {
+                dt,
+                ..IntegrationParameters::default()
+            },
+            pipeline: PhysicsPipeline::new(),
+            islands: IslandManager::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            impulse_joints: ImpulseJointSet::new(),
+            multibody_joints: MultibodyJointSet::new(),
+            ccd_solver: CCDSolver::new(),
+        }
+    }
+
+    /// Adds a box with sides `2 * half_extent` at `translation`, turned by `rotation`.
+    pub fn add_body(&mut self, translation: [f32; 2], rotation: f32, half_extent: f32) -> Body {
+        let body = RigidBodyBuilder::dynamic()
+            .translation(vector![translation[0], translation[1]])
+            .rotation(rotation)
+            .build();
+        let handle = self.bodies.insert(body);
+        let collider = ColliderBuilder::cuboid(half_extent, half_extent)
+            .restitution(0.3)
+            .friction(0.6)
+            .build();
+        self.colliders
+            .insert_with_parent(collider, handle, &mut self.bodies);
+        Body(handle)
+    }
+
+    pub fn remove_body(&mut self, body: Body) {
+        self.bodies.remove(
+            body.0,
+            &mut self.islands,
+            &mut self.colliders,
+            &mut self.impulse_joints,
+            &mut self.multibody_joints,
+            true,
+        );
+    }
+
+    pub fn step(&mut self) {
+        self.pipeline.step(
+            &vector![0.0, GRAVITY],
+            &self.integration_parameters,
+            &mut self.islands,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.impulse_joints,
+            &mut self.multibody_joints,
+            &mut self.ccd_solver,
+            None,
+            &(),
+            &(),
+        );
+    }
+
+    /// Translation and rotation of `body` as of the last step.
+    pub fn pose(&self, body: Body) -> Option<([f32; 2], f32)> {
+        let body = self.bodies.get(body.0)?;
+        let translation = body.translation();
+        Some(([translation.x, translation.y], body.rotation().angle()))
+    }
+
+    /// Pushes the bodies near `center` away from it, the nearest hardest.
+    pub fn blast(&mut self, center: [f32; 2]) {
+        for (_, body) in self.bodies.iter_mut() {
+            let translation = *body.translation();
+            let offset = [translation.x - center[0], translation.y - center[1]];
+            let distance = offset[0].hypot(offset[1]);
+            if distance >= BLAST_RADIUS {
+                continue;
+            }
+            let direction = if distance > f32::EPSILON {
+                [offset[0] / distance, offset[1] / distance]
+            } else {
+                // Straight up, for a body right at the center.
+                [0.0, -1.0]
+            };
+            let speed = BLAST_SPEED * (1.0 - distance / BLAST_RADIUS);
+            let impulse = body.mass() * speed;
+            body.apply_impulse(
+                vector![direction[0] * impulse, direction[1] * impulse],
+                true,
+            );
+        }
+    }
+}
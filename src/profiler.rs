@@ -0,0 +1,172 @@
+use crate::debug_draw::DebugDraw;
+use puffin::{GlobalFrameView, Reader, Stream};
+use tracing::{info, warn};
+
+/// Left and right edges of the flamegraph, in clip space; they span the frame.
+const LEFT: f32 = -0.95;
+const RIGHT: f32 = 0.95;
+/// Top of the first row, and the height of each.
+const TOP: f32 = -0.95;
+const ROW_HEIGHT: f32 = 0.04;
+/// Space between the rows of two threads.
+const THREAD_GAP: f32 = 0.02;
+
+const FRAME_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const TICK_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+/// Scopes are told apart by color, each name always getting the same one.
+const SCOPE_COLORS: [[f32; 4]; 6] = [
+    [1.0, 0.4, 0.4, 1.0],
+    [0.4, 1.0, 0.4, 1.0],
+    [0.4, 0.6, 1.0, 1.0],
+    [1.0, 0.8, 0.2, 1.0],
+    [0.9, 0.4, 1.0, 1.0],
+    [0.2, 0.9, 0.9, 1.0],
+];
+
+/// A flamegraph of the last complete frame's puffin scopes, drawn in the debug
+/// overlay: a row per nesting level and thread, across the frame's time, with a
+/// tick every millisecond. F8 toggles it, and lists the scopes in the log when it
+/// comes on since the overlay has no text.
+pub struct Flamegraph {
+    frames: GlobalFrameView,
+    visible: bool,
+}
+
+impl Flamegraph {
+    /// Turns on the scopes, which cost close to nothing until then.
+    pub fn new() -> Self {
+        puffin::set_scopes_on(true);
+        Flamegraph {
+            // Only frames ended after this are kept.
+            frames: GlobalFrameView::default(),
+            visible: false,
+        }
+    }
+
+    /// Ends the frame, so its scopes show up in the flamegraph.
+    pub fn new_frame(&self) {
+        puffin::GlobalProfiler::lock().new_frame();
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        if self.visible {
+            self.log_latest_frame();
+        }
+    }
+
+    pub fn draw(&self, debug_draw: &mut DebugDraw) {
+        if !self.visible {
+            return;
+        }
+        let frame = match self
+            .frames
+            .lock()
+            .latest_frame()
+            .map(|frame| frame.unpacked())
+        {
+            Some(Ok(frame)) => frame,
+            Some(Err(e)) => {
+                warn!(error = %e, "can't unpack the profiled frame");
+                return;
+            }
+            None => return,
+        };
+        let (start_ns, end_ns) = frame.meta.range_ns;
+        let duration_ns = (end_ns - start_ns).max(1) as f32;
+        let x = |ns: i64| LEFT + (RIGHT - LEFT) * (ns - start_ns) as f32 / duration_ns;
+
+        let mut top = TOP;
+        for stream_info in frame.thread_streams.values() {
+            let mut rows = 0;
+            draw_scopes(
+                &stream_info.stream,
+                Reader::from_start(&stream_info.stream),
+                &x,
+                top,
+                0,
+                &mut rows,
+                debug_draw,
+            );
+            top += rows as f32 * ROW_HEIGHT + THREAD_GAP;
+        }
+        debug_draw.rect([LEFT, TOP], [RIGHT, top - THREAD_GAP], FRAME_COLOR);
+        for ms in 1..(duration_ns / 1e6) as i64 + 1 {
+            let tick = x(start_ns + ms * 1_000_000);
+            debug_draw.line([tick, TOP - ROW_HEIGHT / 2.0], [tick, TOP], TICK_COLOR);
+        }
+    }
+
+    /// Lists the latest frame's scopes with their durations, indented by depth.
+    fn log_latest_frame(&self) {
+        let frame = match self
+            .frames
+            .lock()
+            .latest_frame()
+            .map(|frame| frame.unpacked())
+        {
+            Some(Ok(frame)) => frame,
+            _ => {
+                info!("no profiled frame yet");
+                return;
+            }
+        };
+        for (thread, stream_info) in &frame.thread_streams {
+            info!(thread = %thread.name, "profiled frame {}", frame.meta.frame_index);
+            log_scopes(
+                &stream_info.stream,
+                Reader::from_start(&stream_info.stream),
+                0,
+            );
+        }
+    }
+}
+
+/// Outlines the scopes read by `reader` on the row at `depth`, then their children
+/// below, counting the rows used into `rows`.
+fn draw_scopes(
+    stream: &Stream,
+    reader: Reader,
+    x: &impl Fn(i64) -> f32,
+    top: f32,
+    depth: usize,
+    rows: &mut usize,
+    debug_draw: &mut DebugDraw,
+) {
+    for scope in reader.flatten() {
+        *rows = (*rows).max(depth + 1);
+        let record = &scope.record;
+        let y = top + depth as f32 * ROW_HEIGHT;
+        debug_draw.rect(
+            [x(record.start_ns), y],
+            [x(record.start_ns + record.duration_ns), y + ROW_HEIGHT],
+            scope_color(record.id),
+        );
+        if let Ok(children) = Reader::with_offset(stream, scope.child_begin_position) {
+            draw_scopes(stream, children, x, top, depth + 1, rows, debug_draw);
+        }
+    }
+}
+
+fn log_scopes(stream: &Stream, reader: Reader, depth: usize) {
+    for scope in reader.flatten() {
+        let record = &scope.record;
+        info!(
+            "{:indent$}{} {:.3} ms",
+            "",
+            record.id,
+            record.duration_ns as f64 / 1e6,
+            indent = 2 * depth
+        );
+        if let Ok(children) = Reader::with_offset(stream, scope.child_begin_position) {
+            log_scopes(stream, children, depth + 1);
+        }
+    }
+}
+
+fn scope_color(id: &str) -> [f32; 4] {
+    let hash = id.bytes().fold(0usize, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(byte as usize)
+    });
+    SCOPE_COLORS[hash % SCOPE_COLORS.len()]
+}
@@ -0,0 +1,41 @@
+use renderdoc::{RenderDoc, V141};
+use tracing::info;
+
+/// RenderDoc's in-application API, when the app runs under RenderDoc. F10 captures
+/// the next frame presented, which RenderDoc's own hotkey tends to miss while the
+/// window doesn't have its focus or frames are skipped.
+pub struct RenderDocCapture {
+    api: RenderDoc<V141>,
+    /// Captures already reported.
+    reported: u32,
+}
+
+impl RenderDocCapture {
+    /// `None` unless RenderDoc injected itself into the process.
+    pub fn attach() -> Option<Self> {
+        let api = RenderDoc::<V141>::new().ok()?;
+        let (major, minor, patch) = api.get_api_version();
+        info!(
+            api = %format!("{}.{}.{}", major, minor, patch),
+            "running under RenderDoc, F10 captures a frame"
+        );
+        let reported = api.get_num_captures();
+        Some(RenderDocCapture { api, reported })
+    }
+
+    pub fn trigger(&mut self) {
+        self.api.trigger_capture();
+        info!("capturing the next frame with RenderDoc");
+    }
+
+    /// Logs the captures RenderDoc finished since the last call.
+    pub fn report_captures(&mut self) {
+        let count = self.api.get_num_captures();
+        while self.reported < count {
+            if let Some((path, _)) = self.api.get_capture(self.reported) {
+                info!(path = %path.display(), "RenderDoc captured a frame");
+            }
+            self.reported += 1;
+        }
+    }
+}
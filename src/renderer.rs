@@ -0,0 +1,4453 @@
+use crate::{
+    allocator::{FrameChunk, FrameRing, StagingRing},
+    hdr::{self, DisplayOutput, SurfaceFormatPolicy},
+    lut::ColorLut,
+    memory::{AllocationPurpose, MemoryStats},
+    texture::TextureImage,
+    tilemap::{TileAtlas, Tilemap},
+};
+use bytemuck::{Pod, Zeroable};
+use core::cmp::{max, min};
+use rayon::prelude::*;
+use std::{
+    fmt, iter,
+    mem::size_of,
+    ops::Range,
+    slice,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::{debug, error, info, info_span, trace, warn};
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer, TypedBufferAccess},
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferInheritanceInfo,
+        CommandBufferInheritanceRenderPassInfo, CommandBufferInheritanceRenderPassType,
+        CommandBufferInheritanceRenderingInfo, CommandBufferUsage, CopyImageToBufferInfo,
+        PrimaryAutoCommandBuffer, RenderPassBeginInfo, RenderingAttachmentInfo, RenderingInfo,
+        SubpassContents,
+    },
+    descriptor_set::{
+        layout::DescriptorSetLayoutCreateInfo, DescriptorSet, PersistentDescriptorSet,
+        WriteDescriptorSet,
+    },
+    device::{
+        physical::{PhysicalDevice, SurfacePropertiesError},
+        Device, DeviceCreateInfo, DeviceCreationError, DeviceExtensions, Features, Queue,
+        QueueCreateInfo,
+    },
+    format::{ClearValue, Format},
+    image::{
+        view::{ImageView, ImageViewAbstract},
+        AttachmentImage, ImageAccess, ImageUsage, SwapchainImage,
+    },
+    impl_vertex,
+    instance::Instance,
+    memory::DeviceMemoryAllocationError,
+    pipeline::{
+        graphics::{
+            color_blend::{AttachmentBlend, ColorBlendState, ColorComponents},
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            rasterization::RasterizationState,
+            render_pass::{PipelineRenderPassType, PipelineRenderingCreateInfo},
+            vertex_input::BuffersDefinition,
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreationError,
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint, StateMode,
+    },
+    query::{
+        QueryControlFlags, QueryPipelineStatisticFlags, QueryPool, QueryPoolCreateInfo,
+        QueryPoolCreationError, QueryResultFlags, QueryType,
+    },
+    render_pass::{Framebuffer, LoadOp, StoreOp},
+    sampler::{Sampler, SamplerCreateInfo},
+    shader::{ShaderCreationError, ShaderModule},
+    swapchain::{
+        acquire_next_image, AcquireError, CompositeAlpha, PresentMode, SupportedCompositeAlpha,
+        Surface, Swapchain, SwapchainCreateInfo, SwapchainCreationError,
+    },
+    sync::{self, FenceSignalFuture, FlushError, GpuFuture, PipelineStage},
+    DeviceSize, Version,
+};
+use winit::window::Window;
+
+mod breadcrumbs;
+mod color_grading;
+mod demo;
+mod depth_of_field;
+mod device_config;
+mod dither;
+mod draw_list;
+mod environment;
+mod g_buffer;
+mod gpu_particles;
+mod instance_colors;
+mod life;
+mod lights;
+mod mesh;
+mod motion_blur;
+mod nbody;
+mod occlusion;
+mod offscreen;
+mod outline;
+mod point_sprites;
+mod post;
+mod presets;
+mod procedural;
+#[cfg(feature = "profile-tracy")]
+mod profiling;
+mod rasterizer;
+mod reflection;
+mod render_graph;
+mod stereo;
+mod stylize;
+mod taa;
+mod terrain;
+mod textures;
+mod tile_layer;
+mod topology;
+mod uploader;
+mod vegetation;
+mod vertex_format;
+pub use demo::{DemoInput, DemoKind};
+pub use device_config::{DeviceCapabilities, DeviceConfig};
+pub use draw_list::{DrawList, MaterialId, MeshId};
+pub use lights::{Light, MAX_LIGHTS};
+pub use mesh::Mesh;
+pub use motion_blur::MotionBlurSettings;
+pub use nbody::MAX_NBODY_BODIES;
+pub use offscreen::{render_offscreen, OffscreenRenderer};
+pub use presets::{cycle_shader_preset, SHADER_PRESETS};
+pub use rasterizer::RasterizerSettings;
+pub use stylize::{Style, Stylization};
+pub use textures::TextureId;
+pub use topology::Topology;
+pub use vertex_format::{VertexEncoding, VertexFormat, VertexLayout};
+
+use breadcrumbs::{Breadcrumbs, Trail};
+use color_grading::ColorGrading;
+use demo::{create_demo, Demo, DemoFrame, DemoResources};
+use depth_of_field::DepthOfField;
+use dither::Dither;
+use draw_list::DrawBatch;
+use environment::EnvironmentMap;
+use g_buffer::GBuffer;
+use gpu_particles::GpuParticles;
+use instance_colors::{InstanceColors, COLOR_SET};
+use lights::Lights;
+use mesh::MeshBuffer;
+use motion_blur::MotionBlur;
+use occlusion::{mesh_extent, ClusterBounds, OcclusionCulling};
+use outline::Outline;
+use point_sprites::PointSprites;
+use post::PostEffects;
+use procedural::ProceduralBackground;
+use render_graph::{AttachmentId, CompiledGraph, PassDesc, PassId, RenderGraph};
+use stereo::Stereo;
+use stylize::StylePass;
+use taa::Taa;
+use terrain::Heightmap;
+use textures::Textures;
+use tile_layer::{TileChunk, TileLayer};
+use topology::PipelineVariants;
+use uploader::Uploader;
+use vertex_format::MeshVertices;
+
+pub fn device_extensions() -> DeviceExtensions {
+    DeviceExtensions {
+        khr_swapchain: true,
+        ..DeviceExtensions::none()
+    }
+}
+
+pub type WindowSurface = Surface<Arc<Window>>;
+type WindowSwapchain = Swapchain<Arc<Window>>;
+type WindowImage = SwapchainImage<Arc<Window>>;
+
+pub struct RendererSettings {
+    pub background_color: [f32; 4],
+    pub swapchain_buffers_count: u32,
+    /// Instances the per-frame buffers are sized for up front. They grow when the
+    /// scene does, and pre-recorded command buffers are recorded again to fit.
+    pub instance_count: u32,
+    pub frames_in_flight: usize,
+    /// Above 1, draws are split across this many secondary command buffers
+    /// recorded in parallel.
+    pub draw_buckets: usize,
+    /// Record each swapchain image's command buffer once and only update its
+    /// uniform and instance buffers per frame. Draw buckets are ignored, particles
+    /// aren't drawn and every instance uses the main mesh and material in this mode.
+    pub prerecord: bool,
+    pub display_output: DisplayOutput,
+    /// How the swapchain format is picked for SDR output.
+    pub surface_format_policy: SurfaceFormatPolicy,
+    /// Brightness of scene white on HDR outputs, in nits.
+    pub paper_white: f32,
+    /// Grades the scene with this LUT before encoding it for the display; changed
+    /// with [`Renderer::set_color_lut`].
+    pub color_lut: Option<ColorLut>,
+    /// Add blue noise to the final image against banding on 8 and 10 bit outputs;
+    /// changed with [`Renderer::set_dither`].
+    pub dither: bool,
+    /// Anti-alias with a jittered history of frames; changed with
+    /// [`Renderer::set_taa`]. Needs dynamic rendering.
+    pub taa: bool,
+    /// Blur what's out of focus; changed with [`Renderer::set_depth_of_field`] and
+    /// focused with [`Renderer::focus_at`]. Needs dynamic rendering.
+    pub depth_of_field: bool,
+    /// Streak what moves along its motion; changed with [`Renderer::set_motion_blur`].
+    /// Needs dynamic rendering.
+    pub motion_blur: Option<MotionBlurSettings>,
+    /// Chromatic aberration, vignette and film grain; changed with
+    /// [`Renderer::set_stylization`]. Needs dynamic rendering.
+    pub stylization: Stylization,
+    /// Present without waiting for vertical blank (Immediate, else Mailbox) when supported.
+    pub uncapped_present: bool,
+    /// Let the desktop show through where the scene's alpha is below one, such as a
+    /// background color with zero alpha. The window has to be created transparent,
+    /// and the surface has to support a blended composite alpha mode.
+    pub transparent: bool,
+    /// Measure each frame's GPU time with timestamp queries. Pre-recorded command
+    /// buffers aren't timed.
+    pub gpu_timing: bool,
+    /// Create the swapchain images so they can be copied from, which
+    /// [`Renderer::capture_frame`] needs.
+    pub frame_capture: bool,
+    /// Keep what each frame in flight recorded for [`Renderer::crash_report`].
+    pub crash_breadcrumbs: bool,
+    /// Particles in a fountain simulated by a compute shader and drawn after the
+    /// scene; 0 disables it. Pre-recorded command buffers don't draw it.
+    pub gpu_particles: u32,
+    /// Fill the background with a pattern a compute shader animates every frame,
+    /// instead of the background color. Pre-recorded command buffers don't draw it.
+    pub procedural_background: bool,
+    /// A showcase to draw, changed with [`Renderer::set_demo`]. Those that
+    /// [replace the scene](DemoKind::replaces_scene) are drawn over the background,
+    /// and the caller then leaves the scene empty; frames with one are recorded
+    /// afresh even with pre-recorded command buffers.
+    pub demo: Option<DemoKind>,
+    /// Bodies [`DemoKind::NBody`] simulates, at most [`MAX_NBODY_BODIES`].
+    pub nbody_bodies: u32,
+    /// An image [`DemoKind::Terrain`] takes its heights from, by brightness; without
+    /// one it generates hills.
+    pub heightmap: Option<TextureImage>,
+    /// Draw the instances' depth first, then shade only the fragments that ended up
+    /// in front. Pre-recorded command buffers skip the pre-pass.
+    pub depth_prepass: bool,
+    /// Index into [`SHADER_PRESETS`] of the shaders to draw the main material with
+    /// instead of the built-in ones. The depth pre-pass is skipped while a preset is
+    /// in use, and pre-recorded command buffers keep the built-in shaders.
+    pub shader_preset: Option<usize>,
+    /// Culling, depth bias and line width of the scene's materials and debug lines.
+    /// Shader presets, particles and pre-recorded command buffers keep the defaults.
+    pub rasterizer: RasterizerSettings,
+    /// While the depth pre-pass is on, test clusters of instances against it with
+    /// occlusion queries and leave the hidden ones out of the next frames.
+    pub occlusion_culling: bool,
+    /// Count each frame's shader invocations and clipped primitives with a pipeline
+    /// statistics query. Pre-recorded command buffers aren't counted.
+    pub pipeline_statistics: bool,
+    /// Version and optional capabilities to create the device with.
+    pub device: DeviceConfig,
+    /// Images instances can sample by [`TextureId`] with [`Renderer::textured_shader`].
+    pub textures: Vec<TextureImage>,
+    /// Tiles for [`Renderer::sync_tilemap`]; without an atlas no tilemap is drawn.
+    /// Pre-recorded command buffers don't draw it either.
+    pub tile_atlas: Option<TileAtlas>,
+    /// How every mesh's vertices are stored on the GPU.
+    pub vertex_format: VertexFormat,
+    /// Assemble every mesh's indices as this instead of its own topology, e.g. as
+    /// points or lines to see its vertices or outline. Particles and pre-recorded
+    /// command buffers draw the main mesh as triangles regardless.
+    pub topology: Option<Topology>,
+    /// Draw every instance and particle as a round point this many pixels wide
+    /// instead of its mesh, for very large instance counts. The depth pre-pass is
+    /// skipped, and pre-recorded command buffers still draw meshes.
+    pub point_sprites: Option<f32>,
+    /// Outline the instances [`Renderer::render`] is given as selected by drawing
+    /// them again this many times bigger behind a stencil mask. Needs a depth/stencil
+    /// format; without one, or when `None`, the depth attachment is depth-only.
+    pub outline: Option<f32>,
+    /// Draw the instances for a left and a right eye this far apart, in clip space,
+    /// in one multiview pass, shown side by side. Needs the `multiview` feature.
+    /// Frames aren't pre-recorded meanwhile.
+    pub stereo: Option<f32>,
+    /// Draw the instances into the faces of a cube image this many texels wide
+    /// every frame, and show them reflected in a mirror ball over the scene. Frames
+    /// aren't pre-recorded meanwhile.
+    pub environment_map: Option<u32>,
+}
+
+/// Frequency bands of [`FrameData::audio_bands`]; the shaders read them as two vec4s.
+pub const AUDIO_BANDS: usize = 8;
+
+/// Per-frame values the scene is animated with.
+pub struct FrameData {
+    pub time: f32,
+    pub mouse: [f32; 2],
+    /// Scale applied to every instance.
+    pub zoom: f32,
+    /// Loudness of each band in `[0, 1]`, lowest frequencies first. Each instance's
+    /// wobble grows with one of them; all zero leaves the animation as it is.
+    pub audio_bands: [f32; AUDIO_BANDS],
+}
+
+impl FrameData {
+    fn band_vectors(&self) -> [[f32; 4]; 2] {
+        let bands = &self.audio_bands;
+        [
+            [bands[0], bands[1], bands[2], bands[3]],
+            [bands[4], bands[5], bands[6], bands[7]],
+        ]
+    }
+}
+
+/// A frame [`Renderer::capture_frame`] copied to host memory. Converting its pixels
+/// is left to whoever takes it, which may be another thread.
+pub struct CapturedFrame {
+    pub extent: [u32; 2],
+    buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+    byte_order: ByteOrder,
+}
+
+impl CapturedFrame {
+    /// The pixels as they were presented, as tightly packed RGBA8 rows of an image
+    /// of `extent`: cropped at the right and bottom, or padded there with black.
+    /// Alpha is set to opaque, as opaque swapchains may leave anything there.
+    pub fn to_rgba(&self, extent: [u32; 2]) -> Vec<u8> {
+        let data = self.buffer.read().unwrap();
+        let mut pixels = [0, 0, 0, u8::MAX].repeat((extent[0] * extent[1]) as usize);
+        let row_bytes = 4 * extent[0].min(self.extent[0]) as usize;
+        for y in 0..extent[1].min(self.extent[1]) as usize {
+            let source = 4 * y * self.extent[0] as usize;
+            let destination = 4 * y * extent[0] as usize;
+            let rows = data[source..source + row_bytes].chunks_exact(4);
+            let destination = pixels[destination..destination + row_bytes].chunks_exact_mut(4);
+            for (pixel, out) in rows.zip(destination) {
+                let rgb = match self.byte_order {
+                    ByteOrder::Rgba => [pixel[0], pixel[1], pixel[2]],
+                    ByteOrder::Bgra => [pixel[2], pixel[1], pixel[0]],
+                };
+                out[..3].copy_from_slice(&rgb);
+            }
+        }
+        pixels
+    }
+}
+
+/// The float image a frame's output pass graded and encoded for the display, after
+/// the post effects, copied to host memory by [`Renderer::capture_hdr_frame`]. Its
+/// colors are sRGB-encoded, as the shaders write them, but not clamped to 1.
+pub struct HdrFrame {
+    pub extent: [u32; 2],
+    /// Four half floats per pixel, as their bits.
+    buffer: Arc<CpuAccessibleBuffer<[u16]>>,
+}
+
+impl HdrFrame {
+    /// The pixels as tightly packed rows of RGBA half floats, as their bits, top row
+    /// first.
+    pub fn to_rgba_f16(&self) -> Vec<u16> {
+        self.buffer.read().unwrap().to_vec()
+    }
+}
+
+/// Counts from one frame's pipeline statistics query, covering both subpasses.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PipelineStatistics {
+    pub vertex_shader_invocations: u64,
+    /// Primitives left after clipping, which go on to be rasterized.
+    pub clipping_primitives: u64,
+    pub fragment_shader_invocations: u64,
+}
+
+/// Where [`Renderer::render`] spent the last frame's CPU time blocked.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameTimings {
+    /// Waiting for the GPU to finish the frame whose resources are reused.
+    pub frame_wait: Duration,
+    /// Acquiring the swapchain image.
+    pub acquire: Duration,
+    /// Submitting the commands.
+    pub submit: Duration,
+    /// Queueing the present.
+    pub present: Duration,
+    /// The swapchain was recreated, for a resize or a suboptimal one.
+    pub swapchain_recreated: bool,
+}
+
+#[derive(Debug)]
+pub enum RenderError {
+    /// The logical device is unusable; the renderer has to be created again.
+    DeviceLost,
+    /// The window's new surface can't be drawn to; the renderer has to be created
+    /// again, for a device that can. It holds the new surface, see [`Renderer::into_surface`].
+    Surface(RendererCreationError),
+}
+
+/// Why [`Renderer::new`] or [`Renderer::replace_surface`] failed. A device lost
+/// again while the renderer is being recreated shows up as any of them.
+#[derive(Debug)]
+pub enum RendererCreationError {
+    Device(DeviceCreationError),
+    /// The queue the renderer was created with can't present to the surface.
+    SurfaceUnsupported,
+    SurfaceProperties(SurfacePropertiesError),
+    Swapchain(SwapchainCreationError),
+    Memory(DeviceMemoryAllocationError),
+    Shader(ShaderCreationError),
+    Pipeline(GraphicsPipelineCreationError),
+    QueryPool(QueryPoolCreationError),
+}
+
+impl fmt::Display for RendererCreationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Device(e) => write!(f, "can't create the device: {}", e),
+            Self::SurfaceUnsupported => write!(f, "the queue can't present to the surface"),
+            Self::SurfaceProperties(e) => write!(f, "can't query the surface: {}", e),
+            Self::Swapchain(e) => write!(f, "can't create the swapchain: {}", e),
+            Self::Memory(e) => write!(f, "can't allocate a buffer: {}", e),
+            Self::Shader(e) => write!(f, "can't load a shader: {}", e),
+            Self::Pipeline(e) => write!(f, "can't create a pipeline: {}", e),
+            Self::QueryPool(e) => write!(f, "can't create a query pool: {}", e),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct Vertex {
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+}
+impl_vertex!(Vertex, position, color);
+
+/// Per-instance attributes, packed from the scene every frame.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct InstanceData {
+    /// Columns of the instance's world matrix, applied to the mesh and its wobble.
+    /// Zero collapses the instance to nothing.
+    pub basis_x: [f32; 2],
+    pub basis_y: [f32; 2],
+    pub translation: [f32; 2],
+    /// Phase of the wobble animation on each axis.
+    pub phase: [f32; 2],
+    /// [`TextureId`] that materials sampling textures read.
+    pub texture: u32,
+    /// [`TextureId`] of the normal map [`Renderer::lit_shader`] perturbs the shading
+    /// with; 0, plain white, shades the instance flat.
+    pub normal_map: u32,
+}
+impl_vertex!(
+    InstanceData,
+    basis_x,
+    basis_y,
+    translation,
+    phase,
+    texture,
+    normal_map
+);
+
+/// Instances to draw: their attributes, and the colors they multiply their mesh's
+/// vertex colors with, one for each. The colors go in a storage buffer of their own,
+/// which the vertex shaders index with `gl_InstanceIndex`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Instances<'a> {
+    pub data: &'a [InstanceData],
+    pub colors: &'a [[f32; 4]],
+}
+
+impl Instances<'_> {
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn owned(&self) -> OwnedInstances {
+        OwnedInstances {
+            data: self.data.to_vec(),
+            colors: self.colors.to_vec(),
+        }
+    }
+}
+
+/// [`Instances`] that own what they hold, for frames drawn later or on another
+/// thread.
+#[derive(Clone, Debug, Default)]
+pub struct OwnedInstances {
+    pub data: Vec<InstanceData>,
+    pub colors: Vec<[f32; 4]>,
+}
+
+impl OwnedInstances {
+    pub fn as_instances(&self) -> Instances<'_> {
+        Instances {
+            data: &self.data,
+            colors: &self.colors,
+        }
+    }
+}
+
+/// A square centered on the origin, the size of the default mesh, as a strip of two
+/// triangles.
+pub fn quad_mesh() -> Mesh {
+    let corner = |x: f32, y: f32| Vertex {
+        position: [x * 0.5, y * 0.5],
+        color: [0.5 + 0.5 * x, 0.5 + 0.5 * y, 1.0, 1.0],
+    };
+    Mesh::new(
+        vec![
+            corner(-1.0, -1.0),
+            corner(1.0, -1.0),
+            corner(-1.0, 1.0),
+            corner(1.0, 1.0),
+        ],
+        vec![0, 1, 2, 3],
+    )
+    .with_topology(Topology::TriangleStrip)
+}
+
+/// A disc the size of the default mesh, as a fan in three levels of detail: 64, 24
+/// and 8 segments around.
+pub fn disc_mesh() -> Mesh {
+    const LEVELS: [u32; 3] = [64, 24, 8];
+    let mut vertices = vec![Vertex {
+        position: [0.0, 0.0],
+        color: [1.0, 1.0, 1.0, 1.0],
+    }];
+    let mut levels = Vec::with_capacity(LEVELS.len());
+    for segments in LEVELS {
+        let first = vertices.len() as u32;
+        vertices.extend((0..segments).map(|i| {
+            let (sin, cos) = (i as f32 / segments as f32 * std::f32::consts::TAU).sin_cos();
+            Vertex {
+                position: [0.5 * cos, 0.5 * sin],
+                color: [0.5 + 0.5 * cos, 0.5 + 0.5 * sin, 1.0, 1.0],
+            }
+        }));
+        // Around the center and back to the first rim vertex.
+        let rim = (first..first + segments).chain([first]);
+        levels.push([0].into_iter().chain(rim).collect());
+    }
+    Mesh::from_lods(vertices, levels).with_topology(Topology::TriangleFan)
+}
+
+/// The fragment shader of the demo material [`Renderer::add_material`] is shown
+/// with.
+pub fn stripes_shader(device: &Arc<Device>) -> Arc<ShaderModule> {
+    stripes_fragment_shader::load(device.clone()).unwrap()
+}
+
+/// The triangle drawn until a mesh is loaded.
+fn default_mesh() -> Mesh {
+    Mesh::triangle_list(vec![
+        Vertex {
+            position: [-0.5, -0.25],
+            color: [1.0, 0.0, 0.0, 1.0],
+        },
+        Vertex {
+            position: [0.0, 0.5],
+            color: [0.0, 1.0, 0.0, 1.0],
+        },
+        Vertex {
+            position: [0.25, -0.1],
+            color: [0.0, 0.0, 1.0, 1.0],
+        },
+    ])
+}
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 position;
+        layout(location = 1) in vec4 color;
+        layout(location = 2) in vec2 basis_x;
+        layout(location = 3) in vec2 basis_y;
+        layout(location = 4) in vec2 translation;
+        layout(location = 5) in vec2 phase;
+        layout(location = 6) in uint texture;
+        layout(location = 7) in uint normal_map;
+
+        layout(location = 0) out vec4 out_color;
+        layout(location = 1) out vec2 out_uv;
+        layout(location = 2) flat out uint out_texture;
+        layout(location = 3) flat out uint out_normal_map;
+        layout(location = 4) out vec2 out_position;
+
+        // Sets 0 and 1 are the materials' textures and lights.
+        layout(set = 2, binding = 0) readonly buffer InstanceColors {
+            vec4 colors[];
+        };
+
+        layout(push_constant) uniform PushConstantData {
+            vec4 bands[2];
+            // Moves every vertex a fraction of a pixel, for TAA.
+            vec2 jitter;
+            float x;
+            float y;
+            float zoom;
+        } pc;
+
+        invariant gl_Position;
+
+        void main() {
+            out_color = color*colors[gl_InstanceIndex];
+            // The unit square around the origin maps onto the whole texture.
+            out_uv = position+0.5;
+            out_texture = texture;
+            out_normal_map = normal_map;
+            float mouse_x = pc.x;
+            float mouse_y = pc.y;
+            vec2 pos = position*vec2(mouse_x, mouse_y)*pc.zoom;
+            uint band = uint(gl_InstanceIndex)%8u;
+            float amplitude = 0.5*(1.0+2.0*pc.bands[band/4u][band%4u]);
+            vec2 wobble = vec2(sin(phase.x+position.x+position.y), sin(phase.y+position.x+position.y))*amplitude;
+            // Later instances are nearer, so a depth pre-pass keeps the painter's order.
+            float depth = max(1.0-float(gl_InstanceIndex+1)/65536.0, 0.0);
+            gl_Position = vec4(translation+mat2(basis_x, basis_y)*(pos+wobble), depth, 1.0);
+            out_position = gl_Position.xy;
+            gl_Position.xy += pc.jitter;
+            // Only read when meshes are drawn as points.
+            gl_PointSize = 1.0;
+        }
+        "
+    }
+}
+
+/// [`vertex_shader`] for pre-recorded command buffers: the per-frame values come
+/// from a uniform buffer instead of push constants, and the instances' colors are in
+/// the same set.
+mod static_vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 position;
+        layout(location = 1) in vec4 color;
+        layout(location = 2) in vec2 basis_x;
+        layout(location = 3) in vec2 basis_y;
+        layout(location = 4) in vec2 translation;
+        layout(location = 5) in vec2 phase;
+
+        layout(location = 0) out vec4 out_color;
+
+        layout(set = 0, binding = 0) uniform FrameUniforms {
+            vec4 bands[2];
+            float x;
+            float y;
+            float zoom;
+        } frame;
+
+        layout(set = 0, binding = 1) readonly buffer InstanceColors {
+            vec4 colors[];
+        };
+
+        void main() {
+            out_color = color*colors[gl_InstanceIndex];
+            vec2 pos = position*vec2(frame.x, frame.y)*frame.zoom;
+            uint band = uint(gl_InstanceIndex)%8u;
+            float amplitude = 0.5*(1.0+2.0*frame.bands[band/4u][band%4u]);
+            vec2 wobble = vec2(sin(phase.x+position.x+position.y), sin(phase.y+position.x+position.y))*amplitude;
+            gl_Position = vec4(translation+mat2(basis_x, basis_y)*(pos+wobble), 0.0, 1.0);
+        }
+        ",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) out vec4 f_color;
+        layout(location = 0) in vec4 in_color;
+
+        void main() {
+            f_color = in_color;
+        }
+        "
+    }
+}
+
+/// Material for cluster children: the vertex colors in diagonal stripes.
+mod stripes_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) out vec4 f_color;
+        layout(location = 0) in vec4 in_color;
+
+        void main() {
+            float stripe = step(0.5, fract((gl_FragCoord.x+gl_FragCoord.y)/16.0));
+            f_color = vec4(in_color.rgb*mix(0.4, 1.0, stripe), in_color.a);
+        }
+        "
+    }
+}
+
+/// Debug overlay lines, already in clip space.
+mod debug_line_vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 position;
+        layout(location = 1) in vec4 color;
+
+        layout(location = 0) out vec4 out_color;
+
+        void main() {
+            gl_Position = vec4(position, 0.0, 1.0);
+            out_color = color;
+        }
+        "
+    }
+}
+
+/// Fullscreen triangle for the output pass.
+mod output_vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        void main() {
+            vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+            gl_Position = vec4(uv*2.0-1.0, 0.0, 1.0);
+        }
+        "
+    }
+}
+
+/// Grades the scene with the color LUT, if there is one, then encodes it for the
+/// swapchain's color space. The scene is authored for an sRGB display, so HDR
+/// outputs decode it to linear first.
+mod output_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/output.frag"
+    }
+}
+
+/// [`output_fragment_shader`] for dynamic rendering, which has no input attachments,
+/// so it fetches the scene from a sampled image instead.
+mod sampled_output_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/output.frag",
+        define: [("SAMPLED", "1")]
+    }
+}
+
+/// Minimum time between swapchain recreations caused by resizing. Until it passes
+/// the old swapchain keeps being presented, scaled by the compositor, which is much
+/// smoother than recreating on every step of an interactive resize.
+const SWAPCHAIN_RECREATION_INTERVAL: Duration = Duration::from_millis(33);
+
+/// Format of the intermediate the scene is drawn into before the output pass.
+const SCENE_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// Only written by the depth pre-pass. Every device can render to it, and it tells
+/// apart the first 65536 instances, which is all the vertex shader spreads out. An
+/// outline needs a stencil, so it picks a depth/stencil format instead.
+const DEPTH_FORMAT: Format = Format::D16_UNORM;
+
+/// Debug line vertices per frame the ring starts out sized for; it grows past that.
+const DEBUG_LINE_VERTICES: usize = 4096;
+
+type FrameFence = FenceSignalFuture<Box<dyn GpuFuture + Send + Sync>>;
+
+/// Resources owned by one frame in flight. They are only reused once the frame's
+/// fence has signalled, so the CPU can record the next frame while the GPU is
+/// still rendering this one. Command buffers and semaphores are owned by the
+/// fence's future chain and released with it.
+#[derive(Default)]
+struct FrameContext {
+    fence: Option<Arc<FrameFence>>,
+    /// `None` for pre-recorded frames, which upload into buffers of their own.
+    uploads: Option<FrameUploads>,
+    /// The frame's commands wrote its pair of timestamp queries.
+    timed: bool,
+    /// The frame's commands wrote its pipeline statistics query.
+    counted: bool,
+}
+
+/// Ring buffer chunks a recorded frame draws from. They are only held, so a ring
+/// buffer replaced by a bigger one stays alive until the frame's fence signals.
+struct FrameUploads {
+    /// Handed out once the frame is done.
+    capture: Option<CapturedFrame>,
+    /// Handed out once the frame is done.
+    hdr_capture: Option<HdrFrame>,
+    /// The depth [`Renderer::focus_at`] asked for, focused on once the frame is done.
+    focus_probe: Option<Arc<CpuAccessibleBuffer<[f32]>>>,
+    _tile_chunks: Option<FrameChunk<TileChunk>>,
+    _instances: FrameChunk<InstanceData>,
+    _cluster_bounds: Option<FrameChunk<ClusterBounds>>,
+    _particles: Option<FrameChunk<InstanceData>>,
+    _debug_lines: Option<FrameChunk<Vertex>>,
+}
+
+/// Where the color channels of an 8-bit swapchain format are.
+#[derive(Clone, Copy)]
+enum ByteOrder {
+    Rgba,
+    Bgra,
+}
+
+fn capture_byte_order(format: Format) -> Option<ByteOrder> {
+    match format {
+        Format::R8G8B8A8_SRGB | Format::R8G8B8A8_UNORM => Some(ByteOrder::Rgba),
+        Format::B8G8R8A8_SRGB | Format::B8G8R8A8_UNORM => Some(ByteOrder::Bgra),
+        _ => None,
+    }
+}
+
+type FrameUniforms = static_vertex_shader::ty::FrameUniforms;
+
+/// What pipelines are built for: the two subpasses of a render pass, or dynamic
+/// rendering, which draws the scene and the output pass in two rendering scopes.
+#[derive(Clone)]
+enum RenderTarget {
+    RenderPass(Arc<ScenePasses>),
+    Dynamic {
+        output_format: Format,
+        depth_format: Format,
+    },
+}
+
+impl RenderTarget {
+    /// Dynamic rendering if the device was created with it.
+    fn new(
+        device: &Arc<Device>,
+        output_format: Format,
+        depth_format: Format,
+        dynamic_rendering: bool,
+    ) -> Self {
+        if dynamic_rendering {
+            RenderTarget::Dynamic {
+                output_format,
+                depth_format,
+            }
+        } else {
+            RenderTarget::RenderPass(Arc::new(ScenePasses::new(
+                device,
+                output_format,
+                depth_format,
+            )))
+        }
+    }
+
+    /// Format of the images the output pass writes.
+    fn output_format(&self) -> Format {
+        match self {
+            RenderTarget::RenderPass(passes) => passes.graph.format(passes.output),
+            RenderTarget::Dynamic { output_format, .. } => *output_format,
+        }
+    }
+
+    /// Format of the scene's depth attachment, which may have a stencil too.
+    fn depth_format(&self) -> Format {
+        match self {
+            RenderTarget::RenderPass(passes) => passes.graph.format(passes.depth),
+            RenderTarget::Dynamic { depth_format, .. } => *depth_format,
+        }
+    }
+
+    /// The depth format if it has a stencil.
+    fn stencil_format(&self) -> Option<Format> {
+        let format = self.depth_format();
+        format.aspects().stencil.then_some(format)
+    }
+
+    /// Where the scene is drawn.
+    fn scene(&self) -> PipelineRenderPassType {
+        match self {
+            RenderTarget::RenderPass(passes) => passes.graph.subpass(passes.scene_pass).into(),
+            RenderTarget::Dynamic { depth_format, .. } => PipelineRenderingCreateInfo {
+                color_attachment_formats: vec![Some(SCENE_FORMAT)],
+                depth_attachment_format: Some(*depth_format),
+                stencil_attachment_format: self.stencil_format(),
+                ..Default::default()
+            }
+            .into(),
+        }
+    }
+
+    /// Where the output pass draws the scene to the output image.
+    fn output(&self) -> PipelineRenderPassType {
+        match self {
+            RenderTarget::RenderPass(passes) => passes.graph.subpass(passes.output_pass).into(),
+            RenderTarget::Dynamic { output_format, .. } => PipelineRenderingCreateInfo {
+                color_attachment_formats: vec![Some(*output_format)],
+                ..Default::default()
+            }
+            .into(),
+        }
+    }
+
+    /// For secondary command buffers drawing the scene into `attachments`.
+    fn scene_inheritance(
+        &self,
+        attachments: &ImageAttachments,
+    ) -> CommandBufferInheritanceRenderPassType {
+        match self {
+            RenderTarget::RenderPass(passes) => CommandBufferInheritanceRenderPassInfo {
+                subpass: passes.graph.subpass(passes.scene_pass),
+                framebuffer: attachments
+                    .framebuffer
+                    .as_ref()
+                    .map(|(framebuffer, _)| framebuffer.clone()),
+            }
+            .into(),
+            RenderTarget::Dynamic { depth_format, .. } => CommandBufferInheritanceRenderingInfo {
+                color_attachment_formats: vec![Some(SCENE_FORMAT)],
+                depth_attachment_format: Some(*depth_format),
+                stencil_attachment_format: self.stencil_format(),
+                ..Default::default()
+            }
+            .into(),
+        }
+    }
+}
+
+/// The frames' render pass as a render graph: the scene pass draws the scene into
+/// the scene image and the depth image, then the output pass reads the scene image
+/// and writes the output image. Both of the former are transient.
+struct ScenePasses {
+    graph: CompiledGraph,
+    scene_pass: PassId,
+    output_pass: PassId,
+    scene: AttachmentId,
+    output: AttachmentId,
+    depth: AttachmentId,
+}
+
+impl ScenePasses {
+    fn new(device: &Arc<Device>, output_format: Format, depth_format: Format) -> Self {
+        let mut graph = RenderGraph::default();
+        let scene = graph.transient("scene", SCENE_FORMAT, true);
+        let output = graph.imported("output", output_format);
+        let depth = graph.transient("depth", depth_format, true);
+        let scene_pass = graph.add_pass(PassDesc {
+            name: "scene",
+            color: vec![scene],
+            depth: Some(depth),
+            input: vec![],
+        });
+        let output_pass = graph.add_pass(PassDesc {
+            name: "output",
+            color: vec![output],
+            depth: None,
+            input: vec![scene],
+        });
+        ScenePasses {
+            graph: graph.compile(device).unwrap(),
+            scene_pass,
+            output_pass,
+            scene,
+            output,
+            depth,
+        }
+    }
+
+    fn clear_values(&self, background_color: [f32; 4]) -> Vec<Option<ClearValue>> {
+        let depth = if self.graph.format(self.depth).aspects().stencil {
+            (1.0, 0).into()
+        } else {
+            1.0.into()
+        };
+        self.graph
+            .clear_values(&[(self.scene, background_color.into()), (self.depth, depth)])
+    }
+}
+
+/// What one output image's frames draw into.
+struct ImageAttachments {
+    scene: Arc<ImageView<AttachmentImage>>,
+    output: Arc<dyn ImageViewAbstract>,
+    depth: Arc<ImageView<AttachmentImage>>,
+    /// Of the three, for the render pass of those passes; `None` with dynamic
+    /// rendering.
+    framebuffer: Option<(Arc<Framebuffer>, Arc<ScenePasses>)>,
+}
+
+impl ImageAttachments {
+    fn new(
+        device: &Arc<Device>,
+        target: &RenderTarget,
+        output: Arc<dyn ImageViewAbstract>,
+        dimensions: [u32; 2],
+    ) -> Self {
+        // One scene image per output image, so frames in flight don't share it.
+        match target {
+            RenderTarget::RenderPass(passes) => {
+                let framebuffer = passes.graph.framebuffer(
+                    device,
+                    dimensions,
+                    &[(passes.output, output.clone())],
+                );
+                ImageAttachments {
+                    scene: framebuffer.transient(passes.scene),
+                    output,
+                    depth: framebuffer.transient(passes.depth),
+                    framebuffer: Some((framebuffer.framebuffer, passes.clone())),
+                }
+            }
+            RenderTarget::Dynamic { depth_format, .. } => {
+                let scene = AttachmentImage::with_usage(
+                    device.clone(),
+                    dimensions,
+                    SCENE_FORMAT,
+                    ImageUsage {
+                        color_attachment: true,
+                        sampled: true,
+                        // For capture_hdr_frame.
+                        transfer_src: true,
+                        ..ImageUsage::none()
+                    },
+                )
+                .unwrap();
+                ImageAttachments {
+                    scene: ImageView::new_default(scene).unwrap(),
+                    output,
+                    depth: depth_attachment(device, dimensions, *depth_format),
+                    framebuffer: None,
+                }
+            }
+        }
+    }
+
+    /// Starts drawing the scene, cleared to `background_color`.
+    fn begin_scene(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        background_color: [f32; 4],
+        contents: SubpassContents,
+    ) {
+        match &self.framebuffer {
+            Some((framebuffer, passes)) => builder.begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: passes.clear_values(background_color),
+                    ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
+                },
+                contents,
+            ),
+            None => builder.begin_rendering(RenderingInfo {
+                color_attachments: vec![Some(RenderingAttachmentInfo {
+                    load_op: LoadOp::Clear,
+                    store_op: StoreOp::Store,
+                    clear_value: Some(background_color.into()),
+                    ..RenderingAttachmentInfo::image_view(self.scene.clone())
+                })],
+                depth_attachment: Some(RenderingAttachmentInfo {
+                    load_op: LoadOp::Clear,
+                    store_op: StoreOp::DontCare,
+                    clear_value: Some(1.0.into()),
+                    ..RenderingAttachmentInfo::image_view(self.depth.clone())
+                }),
+                // The same image, when its format has a stencil.
+                stencil_attachment: self
+                    .depth
+                    .format()
+                    .filter(|format| format.aspects().stencil)
+                    .map(|_| RenderingAttachmentInfo {
+                        load_op: LoadOp::Clear,
+                        store_op: StoreOp::DontCare,
+                        clear_value: Some(0u32.into()),
+                        ..RenderingAttachmentInfo::image_view(self.depth.clone())
+                    }),
+                contents,
+                ..Default::default()
+            }),
+        }
+        .unwrap();
+    }
+
+    /// Moves on from the scene to the output pass, recording `post` on the scene in
+    /// between, which only dynamic rendering has. Returns what the output pass shows
+    /// instead of the scene, if anything.
+    fn begin_output(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        post: Option<PostEffects>,
+    ) -> Option<Arc<ImageView<AttachmentImage>>> {
+        match &self.framebuffer {
+            Some(_) => {
+                builder.next_subpass(SubpassContents::Inline).unwrap();
+                None
+            }
+            None => {
+                builder.end_rendering().unwrap();
+                let shown = post.and_then(|post| post.record(builder, &self.scene));
+                builder
+                    .begin_rendering(RenderingInfo {
+                        color_attachments: vec![Some(RenderingAttachmentInfo {
+                            load_op: LoadOp::DontCare,
+                            store_op: StoreOp::Store,
+                            ..RenderingAttachmentInfo::image_view(self.output.clone())
+                        })],
+                        ..Default::default()
+                    })
+                    .unwrap();
+                shown
+            }
+        }
+    }
+
+    fn end(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        match &self.framebuffer {
+            Some(_) => builder.end_render_pass(),
+            None => builder.end_rendering(),
+        }
+        .unwrap();
+    }
+}
+
+/// Reads the scene image and writes it to the output image graded and encoded for
+/// the display: the render pass's second subpass, or with dynamic rendering a scope
+/// of its own.
+struct OutputPass {
+    pipeline: Arc<GraphicsPipeline>,
+    /// One per output image, binding its scene image and the LUT; empty when they
+    /// are pushed instead.
+    descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+    /// Reads the scene with dynamic rendering, which has no input attachments.
+    sampler: Option<Arc<Sampler>>,
+    grading: ColorGrading,
+    dither: Dither,
+    params: output_fragment_shader::ty::OutputParams,
+}
+
+impl OutputPass {
+    fn new(
+        device: &Arc<Device>,
+        target: &RenderTarget,
+        grading: ColorGrading,
+        dither: Dither,
+        transfer: u32,
+        paper_white: f32,
+    ) -> Result<Self, GraphicsPipelineCreationError> {
+        let sampler = match target {
+            RenderTarget::RenderPass(_) => None,
+            RenderTarget::Dynamic { .. } => {
+                Some(Sampler::new(device.clone(), SamplerCreateInfo::default()).unwrap())
+            }
+        };
+        Ok(OutputPass {
+            pipeline: create_output_pipeline(device, target)?,
+            descriptor_sets: Vec::new(),
+            sampler,
+            params: output_fragment_shader::ty::OutputParams {
+                transfer,
+                paper_white,
+                premultiply: 0,
+                grade: grading.enabled(),
+                dither_step: dither.step(),
+                srgb_format: dither.encodes_srgb(),
+            },
+            grading,
+            dither,
+        })
+    }
+
+    fn set_dither(&mut self, enabled: bool) {
+        self.dither.set_enabled(enabled);
+        self.params.dither_step = self.dither.step();
+    }
+
+    /// Dithers for output images of `format` from now on.
+    fn set_format(&mut self, format: Format) {
+        self.dither.set_format(format);
+        self.params.dither_step = self.dither.step();
+        self.params.srgb_format = self.dither.encodes_srgb();
+    }
+
+    fn update_descriptor_sets(&mut self, attachments: &[ImageAttachments]) {
+        let layout = self.pipeline.layout().set_layouts()[0].clone();
+        if layout.push_descriptor() {
+            self.descriptor_sets.clear();
+            return;
+        }
+        self.descriptor_sets = attachments
+            .iter()
+            .map(|attachments| {
+                PersistentDescriptorSet::new(layout.clone(), self.writes(&attachments.scene))
+                    .unwrap()
+            })
+            .collect();
+    }
+
+    fn writes(&self, scene: &Arc<ImageView<AttachmentImage>>) -> [WriteDescriptorSet; 3] {
+        let scene = scene.clone();
+        let scene = match &self.sampler {
+            Some(sampler) => WriteDescriptorSet::image_view_sampler(0, scene, sampler.clone()),
+            None => WriteDescriptorSet::image_view(0, scene),
+        };
+        [scene, self.grading.write(1), self.dither.write(2)]
+    }
+
+    /// Draws the output pass for `attachments`, those of output image `image_num`,
+    /// showing `resolved` instead of their scene image if given. Call after
+    /// [`ImageAttachments::begin_output`].
+    fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image_num: usize,
+        attachments: &ImageAttachments,
+        resolved: Option<&Arc<ImageView<AttachmentImage>>>,
+        viewport: &Viewport,
+    ) {
+        let layout = self.pipeline.layout();
+        builder
+            .set_viewport(0, [viewport.clone()])
+            .bind_pipeline_graphics(self.pipeline.clone());
+        let descriptor_set = match resolved {
+            // Alternates between frames, so it gets a set of its own.
+            Some(resolved) if !layout.set_layouts()[0].push_descriptor() => Some(
+                PersistentDescriptorSet::new(
+                    layout.set_layouts()[0].clone(),
+                    self.writes(resolved),
+                )
+                .unwrap(),
+            ),
+            Some(_) => None,
+            None => self.descriptor_sets.get(image_num).cloned(),
+        };
+        match descriptor_set {
+            Some(descriptor_set) => builder.bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                layout.clone(),
+                0,
+                descriptor_set,
+            ),
+            None => builder.push_descriptor_set(
+                PipelineBindPoint::Graphics,
+                layout.clone(),
+                0,
+                self.writes(resolved.unwrap_or(&attachments.scene)),
+            ),
+        };
+        builder
+            .push_constants(layout.clone(), 0, self.params)
+            .draw(3, 1, 0, 0)
+            .unwrap();
+    }
+}
+
+/// Command buffers recorded once per swapchain image for
+/// [`RendererSettings::prerecord`]. They bind a per-image uniform buffer and
+/// instance buffer, so a frame only writes those.
+struct PrerecordedCommands {
+    pipeline: Arc<GraphicsPipeline>,
+    images: Vec<PrerecordedImage>,
+}
+
+struct PrerecordedImage {
+    uniforms: Arc<CpuAccessibleBuffer<FrameUniforms>>,
+    /// Always [`RendererSettings::instance_count`] long, which is what gets drawn.
+    instances: Arc<CpuAccessibleBuffer<[InstanceData]>>,
+    /// As long as `instances`.
+    colors: Arc<CpuAccessibleBuffer<[[f32; 4]]>>,
+    command_buffer: Arc<PrimaryAutoCommandBuffer>,
+    /// Signalled once the last submission of `command_buffer` is done, after which
+    /// the buffers can be written again.
+    fence: Option<Arc<FrameFence>>,
+}
+
+impl PrerecordedCommands {
+    fn new(
+        device: &Arc<Device>,
+        target: &RenderTarget,
+        vertex_format: VertexFormat,
+        fragment_shader: &Arc<ShaderModule>,
+    ) -> Self {
+        PrerecordedCommands {
+            pipeline: create_static_pipeline(device, target, vertex_format, fragment_shader)
+                .unwrap(),
+            images: Vec::new(),
+        }
+    }
+
+    /// Waits until `image_num`'s command buffer is free, then writes this frame's
+    /// uniforms and instances. Unused instance slots are zeroed, which hides them.
+    fn prepare(
+        &mut self,
+        image_num: usize,
+        frame: &FrameData,
+        instances: Instances,
+    ) -> Result<Arc<PrimaryAutoCommandBuffer>, RenderError> {
+        let image = &mut self.images[image_num];
+        if let Some(fence) = image.fence.take() {
+            match fence.wait(None) {
+                Ok(()) => {}
+                Err(FlushError::DeviceLost) => return Err(RenderError::DeviceLost),
+                Err(e) => error!(error = ?e, "failed to wait for image fence"),
+            }
+        }
+
+        *image.uniforms.write().unwrap() = FrameUniforms {
+            bands: frame.band_vectors(),
+            x: frame.mouse[0],
+            y: frame.mouse[1],
+            zoom: frame.zoom,
+        };
+
+        let mut slots = image.instances.write().unwrap();
+        let padded = instances
+            .data
+            .iter()
+            .copied()
+            .chain(iter::repeat(InstanceData::default()));
+        for (slot, instance) in slots.iter_mut().zip(padded) {
+            *slot = instance;
+        }
+        drop(slots);
+        let mut slots = image.colors.write().unwrap();
+        for (slot, &color) in slots.iter_mut().zip(instances.colors) {
+            *slot = color;
+        }
+        drop(slots);
+
+        Ok(image.command_buffer.clone())
+    }
+}
+
+/// Everything that lives on the logical device. Dropping it and calling
+/// [`Renderer::new`] again is how a lost device is recovered.
+pub struct Renderer {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    window: Arc<Window>,
+    /// `None` while suspended, like the swapchain.
+    surface: Option<Arc<WindowSurface>>,
+    swapchain: Option<Arc<WindowSwapchain>>,
+    swapchain_buffers_count: u32,
+    display_output: DisplayOutput,
+    surface_format_policy: SurfaceFormatPolicy,
+    uncapped_present: bool,
+    transparent: bool,
+    frame_capture: bool,
+    target: RenderTarget,
+    /// By [`MaterialId`]; the first is the main material.
+    pipelines: Vec<PipelineVariants>,
+    /// The main material's pipeline with additive blending.
+    particle_pipeline: Arc<GraphicsPipeline>,
+    /// Writes the instances' depth only, for the depth pre-pass.
+    depth_pipeline: PipelineVariants,
+    /// The topologies [`Self::meshes`] are drawn as, each with a variant of
+    /// [`Self::pipelines`] and [`Self::depth_pipeline`].
+    topologies: Vec<PrimitiveTopology>,
+    /// Replaces the topology of every mesh added or loaded.
+    topology: Option<Topology>,
+    /// [`Self::pipelines`] test for equal depth against the pre-pass.
+    depth_prepass: bool,
+    /// What [`Self::pipelines`], [`Self::depth_pipeline`] and
+    /// [`Self::debug_line_pipeline`] were built with.
+    rasterizer: RasterizerSettings,
+    /// Replaces the main material's pipeline while set.
+    shader_preset: Option<ActivePreset>,
+    /// Line list for the debug overlay, or a triangle list of the quads it's expanded
+    /// into without wide lines; keeps the built-in fragment shader.
+    debug_line_pipeline: Arc<GraphicsPipeline>,
+    /// By [`MaterialId`]. The main one is shared by the particle and pre-recorded
+    /// pipelines and replaceable at runtime.
+    fragment_shaders: Vec<Arc<ShaderModule>>,
+    output_pass: OutputPass,
+    /// By [`MeshId`]; the first is the main mesh.
+    meshes: Vec<MeshBuffer>,
+    vertex_format: VertexFormat,
+    /// By [`MeshId`], for bounding instances.
+    mesh_extents: Vec<[f32; 2]>,
+    /// Every frame's instances, particles, debug lines, visible tile chunks and cluster
+    /// bounds are written here and copied to the device.
+    staging: StagingRing,
+    instance_ring: FrameRing<InstanceData>,
+    instance_colors: InstanceColors,
+    particle_colors: InstanceColors,
+    debug_line_ring: FrameRing<Vertex>,
+    viewport: Viewport,
+    /// By swapchain image.
+    attachments: Vec<ImageAttachments>,
+    background_color: [f32; 4],
+    instance_count: u32,
+    draw_buckets: usize,
+    prerecorded: Option<PrerecordedCommands>,
+    gpu_particles: Option<GpuParticles>,
+    procedural_background: Option<ProceduralBackground>,
+    /// Draws the instances and particles instead of their meshes while set.
+    point_sprites: Option<PointSprites>,
+    /// Outlines the selected instances; needs the depth attachment's stencil.
+    outline: Option<Outline>,
+    /// Draws the instances once per eye instead of once while set.
+    stereo: Option<Stereo>,
+    /// Blends each frame into a history the output pass shows instead while set.
+    taa: Option<Taa>,
+    /// Blurs what's out of focus while set.
+    depth_of_field: Option<DepthOfField>,
+    /// Blurs what moves along its motion while set.
+    motion_blur: Option<MotionBlur>,
+    /// The stylization passes that are on, in the order they're drawn.
+    styles: Vec<StylePass>,
+    /// Where to focus the depth of field next frame, normalized to `[0, 1]`.
+    focus_requested: Option<[f32; 2]>,
+    /// Motion vectors and depth of each pixel, while a post effect reads them.
+    g_buffer: Option<GBuffer>,
+    /// Renders the instances into a cube image and draws them reflected while set.
+    environment_map: Option<EnvironmentMap>,
+    /// The active demo, if it's drawn by the renderer.
+    demo: Option<Box<dyn Demo>>,
+    demo_kind: Option<DemoKind>,
+    nbody_bodies: u32,
+    heightmap: Option<Heightmap>,
+    /// Kept so switching to the ShaderToy demo can run it.
+    shadertoy_module: Option<Arc<ShaderModule>>,
+    tile_layer: Option<TileLayer>,
+    textures: Textures,
+    lights: Lights,
+    occlusion: Option<OcclusionCulling>,
+    recreate_swapchain: bool,
+    /// The swapchain no longer matches the window, but can still be presented.
+    resize_pending: bool,
+    last_swapchain_recreation: Instant,
+    recreate_surface: bool,
+    frames: Vec<FrameContext>,
+    frame_index: usize,
+    /// Two timestamps per frame in flight, bracketing its commands.
+    timestamps: Option<Arc<QueryPool>>,
+    gpu_frame_time: Option<Duration>,
+    /// Sends the timestamps to Tracy too.
+    #[cfg(feature = "profile-tracy")]
+    gpu_profiler: Option<profiling::GpuProfiler>,
+    frame_timings: FrameTimings,
+    breadcrumbs: Option<Breadcrumbs>,
+    /// One pipeline statistics query per frame in flight, around its render pass.
+    statistics_queries: Option<Arc<QueryPool>>,
+    pipeline_statistics: Option<PipelineStatistics>,
+    /// The next recorded frame copies its swapchain image to the host.
+    capture_requested: bool,
+    captured_frame: Option<CapturedFrame>,
+    /// The next recorded frame copies the image its output pass reads to the host.
+    hdr_capture_requested: bool,
+    captured_hdr_frame: Option<HdrFrame>,
+    memory_stats: MemoryStats,
+    capabilities: DeviceCapabilities,
+    uploader: Uploader,
+    /// Signalled once the meshes uploaded since the last submitted frame are in
+    /// place; the next frame waits for it.
+    uploads_ready: Option<Box<dyn GpuFuture + Send + Sync>>,
+}
+
+impl Renderer {
+    pub fn new(
+        instance: &Arc<Instance>,
+        surface: Arc<WindowSurface>,
+        physical_device_index: usize,
+        queue_family_id: u32,
+        settings: &RendererSettings,
+    ) -> Result<Self, RendererCreationError> {
+        let _span = info_span!("create_renderer").entered();
+        let physical_device = PhysicalDevice::from_index(instance, physical_device_index).ok_or(
+            RendererCreationError::Device(DeviceCreationError::InitializationFailed),
+        )?;
+        let queue_family = physical_device.queue_family_by_id(queue_family_id).ok_or(
+            RendererCreationError::Device(DeviceCreationError::InitializationFailed),
+        )?;
+
+        let (capabilities, enabled_features, enabled_extensions) =
+            settings.device.negotiate(physical_device);
+        info!(?capabilities, "device capabilities");
+        if settings.device.bindless && !capabilities.bindless {
+            info!("device doesn't support bindless textures, using a texture array");
+        }
+
+        // Secondary command buffers of draw buckets run inside the query.
+        let supported_features = physical_device.supported_features();
+        let count_pipeline_statistics = settings.pipeline_statistics
+            && supported_features.pipeline_statistics_query
+            && (settings.draw_buckets <= 1 || supported_features.inherited_queries);
+        if settings.pipeline_statistics && !count_pipeline_statistics {
+            warn!("device doesn't support pipeline statistics queries here, they are unavailable");
+        }
+        let multiview = settings.stereo.is_some() && supported_features.multiview;
+        if settings.stereo.is_some() && !multiview {
+            warn!("device doesn't support multiview, drawing one view");
+        }
+        let enabled_features = Features {
+            pipeline_statistics_query: count_pipeline_statistics,
+            inherited_queries: count_pipeline_statistics && settings.draw_buckets > 1,
+            multiview,
+            ..enabled_features
+        };
+        let enabled_extensions = enabled_extensions.union(&DeviceExtensions {
+            // Core in Vulkan 1.1, an extension before.
+            khr_multiview: multiview && capabilities.api_version < Version::V1_1,
+            ..DeviceExtensions::none()
+        });
+
+        // The upload thread's queue: a transfer-only family's, a second one of the
+        // main family's, or the main queue itself.
+        let mut queue_create_infos = vec![QueueCreateInfo::family(queue_family)];
+        match uploader::transfer_family(physical_device) {
+            Some(family) => queue_create_infos.push(QueueCreateInfo::family(family)),
+            None if queue_family.queues_count() > 1 => {
+                queue_create_infos[0].queues = vec![0.5; 2];
+            }
+            None => {}
+        }
+
+        let (device, mut queues) = Device::new(
+            physical_device,
+            DeviceCreateInfo {
+                enabled_extensions,
+                enabled_features,
+                queue_create_infos,
+                ..Default::default()
+            },
+        )
+        .map_err(RendererCreationError::Device)?;
+
+        let queue = queues.next().unwrap();
+        let transfer_queue = queues.next().unwrap_or_else(|| queue.clone());
+        info!(
+            family = transfer_queue.family().id(),
+            shared = transfer_queue == queue,
+            "upload queue"
+        );
+        let uploader = Uploader::new(
+            &device,
+            &transfer_queue,
+            queue_family.id(),
+            settings.vertex_format,
+        );
+
+        let (swapchain, images) = create_swapchain(
+            &device,
+            &surface,
+            settings.swapchain_buffers_count,
+            settings.display_output,
+            &settings.surface_format_policy,
+            settings.uncapped_present,
+            settings.transparent,
+            settings.frame_capture,
+        )?;
+
+        let vertex_format = settings.vertex_format;
+        info!(
+            encoding = ?vertex_format.encoding,
+            layout = ?vertex_format.layout,
+            "mesh vertex format"
+        );
+        let mesh = override_topology(default_mesh(), settings.topology);
+        let main_mesh_extent = mesh_extent(&mesh.vertices);
+
+        let mut memory_stats = MemoryStats::new(&device);
+        let vertex_buffer = vertex_format
+            .create_buffer(&device, &mesh)
+            .map_err(RendererCreationError::Memory)?;
+        memory_stats.track(AllocationPurpose::Vertex, vertex_buffer.size());
+
+        let staging = StagingRing::new(
+            device.clone(),
+            settings.frames_in_flight,
+            (settings.instance_count as usize * (size_of::<InstanceData>() + size_of::<[f32; 4]>())
+                + DEBUG_LINE_VERTICES * size_of::<Vertex>()) as DeviceSize,
+            &mut memory_stats,
+        )
+        .map_err(RendererCreationError::Memory)?;
+
+        let instance_ring = FrameRing::new(
+            device.clone(),
+            BufferUsage::vertex_buffer(),
+            AllocationPurpose::Instance,
+            settings.frames_in_flight,
+            settings.instance_count as usize,
+            &mut memory_stats,
+        )
+        .map_err(RendererCreationError::Memory)?;
+        let instance_colors = InstanceColors::new(
+            &device,
+            settings.frames_in_flight,
+            settings.instance_count as usize,
+            &mut memory_stats,
+        )
+        .map_err(RendererCreationError::Memory)?;
+        let particle_colors = InstanceColors::new(
+            &device,
+            settings.frames_in_flight,
+            settings.instance_count as usize,
+            &mut memory_stats,
+        )
+        .map_err(RendererCreationError::Memory)?;
+
+        let debug_line_ring = FrameRing::new(
+            device.clone(),
+            BufferUsage::vertex_buffer(),
+            AllocationPurpose::Vertex,
+            settings.frames_in_flight,
+            DEBUG_LINE_VERTICES,
+            &mut memory_stats,
+        )
+        .map_err(RendererCreationError::Memory)?;
+
+        let stencil_format = settings.outline.and_then(|_| {
+            let format = outline::depth_stencil_format(device.physical_device());
+            if format.is_none() {
+                warn!("no depth/stencil format can be rendered to, so selections aren't outlined");
+            }
+            format
+        });
+        let target = RenderTarget::new(
+            &device,
+            swapchain.image_format(),
+            stencil_format.unwrap_or(DEPTH_FORMAT),
+            capabilities.dynamic_rendering,
+        );
+        match target {
+            RenderTarget::RenderPass(_) => info!("recording frames with a render pass"),
+            RenderTarget::Dynamic { .. } => info!("recording frames with dynamic rendering"),
+        }
+        let fragment_shader =
+            fragment_shader::load(device.clone()).map_err(RendererCreationError::Shader)?;
+        let topologies = vec![vertex_buffer.topology()];
+        let graphics_pipeline = PipelineVariants::new(&topologies, |topology| {
+            create_pipeline(
+                &device,
+                &target,
+                vertex_format,
+                &fragment_shader,
+                settings.depth_prepass,
+                &settings.rasterizer,
+                topology,
+            )
+        })
+        .map_err(RendererCreationError::Pipeline)?;
+        let depth_pipeline = PipelineVariants::new(&topologies, |topology| {
+            create_depth_pipeline(
+                &device,
+                &target,
+                vertex_format,
+                &settings.rasterizer,
+                topology,
+            )
+        })
+        .map_err(RendererCreationError::Pipeline)?;
+        let particle_pipeline =
+            create_particle_pipeline(&device, &target, vertex_format, &fragment_shader)
+                .map_err(RendererCreationError::Pipeline)?;
+        let debug_line_pipeline =
+            create_debug_line_pipeline(&device, &target, &settings.rasterizer)
+                .map_err(RendererCreationError::Pipeline)?;
+        let grading = ColorGrading::new(
+            &device,
+            &queue,
+            settings.color_lut.as_ref(),
+            &mut memory_stats,
+        );
+        let dither = Dither::new(
+            &device,
+            &queue,
+            settings.dither,
+            swapchain.image_format(),
+            &mut memory_stats,
+        );
+        let mut output_pass = OutputPass::new(
+            &device,
+            &target,
+            grading,
+            dither,
+            DisplayOutput::transfer(swapchain.image_color_space()),
+            settings.paper_white,
+        )
+        .map_err(RendererCreationError::Pipeline)?;
+        output_pass.params.premultiply = premultiplies(&swapchain);
+        let shader_preset = settings.shader_preset.and_then(|index| {
+            ActivePreset::new(&device, &target, vertex_format, &topologies, index)
+                .map_err(
+                    |e| warn!(error = %e, "shader preset doesn't fit, using the built-in shaders"),
+                )
+                .ok()
+        });
+        let prerecorded = settings
+            .prerecord
+            .then(|| PrerecordedCommands::new(&device, &target, vertex_format, &fragment_shader));
+        let gpu_particles = if settings.gpu_particles == 0 {
+            None
+        } else if !queue_family.supports_compute() {
+            warn!("queue family doesn't support compute, GPU particles are disabled");
+            None
+        } else {
+            Some(GpuParticles::new(
+                &device,
+                queue_family,
+                gpu_particles::create_pipeline(&device, &target, vertex_format, &fragment_shader)
+                    .unwrap(),
+                settings.gpu_particles,
+                vertex_buffer.index_count(),
+                &mut memory_stats,
+            ))
+        };
+
+        let procedural_background = if !settings.procedural_background {
+            None
+        } else if !queue_family.supports_compute() {
+            warn!("queue family doesn't support compute, the procedural background is disabled");
+            None
+        } else {
+            Some(ProceduralBackground::new(
+                &device,
+                queue_family,
+                &target,
+                &mut memory_stats,
+            ))
+        };
+
+        let point_sprites = settings
+            .point_sprites
+            .map(|size| PointSprites::new(&device, &target, size));
+        let outline = settings
+            .outline
+            .filter(|_| stencil_format.is_some())
+            .map(|scale| {
+                Outline::new(
+                    &device,
+                    &target,
+                    vertex_format,
+                    &topologies,
+                    &settings.rasterizer,
+                    scale,
+                )
+            });
+
+        let heightmap = settings.heightmap.as_ref().map(Heightmap::from_image);
+        // ShaderToy waits for its shader, in `set_shadertoy_shader`.
+        let demo = settings.demo.and_then(|kind| {
+            create_demo(
+                kind,
+                DemoResources {
+                    device: &device,
+                    queue_family,
+                    target: &target,
+                    memory_stats: &mut memory_stats,
+                    vertex_format,
+                    nbody_bodies: settings.nbody_bodies,
+                    heightmap: heightmap.as_ref(),
+                    shadertoy: None,
+                },
+            )
+        });
+
+        let tile_layer = settings.tile_atlas.as_ref().map(|atlas| {
+            TileLayer::new(
+                &device,
+                &queue,
+                &target,
+                atlas,
+                settings.frames_in_flight,
+                &mut memory_stats,
+            )
+        });
+
+        let anisotropy = capabilities.sampler_anisotropy.then(|| {
+            physical_device
+                .properties()
+                .max_sampler_anisotropy
+                .min(16.0)
+        });
+        let textures = Textures::new(
+            &device,
+            &queue,
+            &settings.textures,
+            anisotropy,
+            &mut memory_stats,
+        );
+        let lights = Lights::new(
+            &device,
+            queue_family,
+            settings.frames_in_flight,
+            &mut memory_stats,
+        );
+
+        let occlusion = settings.occlusion_culling.then(|| {
+            OcclusionCulling::new(
+                &device,
+                &target,
+                settings.frames_in_flight,
+                &mut memory_stats,
+            )
+        });
+
+        let mut viewport = Viewport {
+            origin: [0.0, 0.0],
+            dimensions: [0.0, 0.0],
+            depth_range: 0.0..1.0,
+        };
+
+        let attachments = window_size_dependent_setup(&device, &images, &target, &mut viewport);
+        let stereo = settings.stereo.filter(|_| multiview).map(|separation| {
+            Stereo::new(
+                &device,
+                &target,
+                vertex_format,
+                &topologies,
+                &settings.rasterizer,
+                separation,
+                viewport.dimensions,
+                &mut memory_stats,
+            )
+        });
+        let taa = if settings.taa {
+            create_taa(&device, &target, &viewport, &mut memory_stats)
+        } else {
+            None
+        };
+        let depth_of_field = if settings.depth_of_field {
+            create_depth_of_field(&device, &target, &viewport, &mut memory_stats)
+        } else {
+            None
+        };
+        let motion_blur = settings.motion_blur.and_then(|motion_blur| {
+            create_motion_blur(&device, &target, motion_blur, &viewport, &mut memory_stats)
+        });
+        let environment_map = settings.environment_map.map(|size| {
+            let max_size = device
+                .physical_device()
+                .properties()
+                .max_image_dimension_cube;
+            if size > max_size {
+                warn!(size, max_size, "environment map is too big, shrinking it");
+            }
+            EnvironmentMap::new(
+                &device,
+                &queue,
+                &target,
+                vertex_format,
+                &topologies,
+                &settings.rasterizer,
+                size.min(max_size),
+                &mut memory_stats,
+            )
+        });
+
+        info!(
+            frames_in_flight = settings.frames_in_flight,
+            "frames in flight"
+        );
+        let frames = (0..settings.frames_in_flight)
+            .map(|_| FrameContext::default())
+            .collect();
+
+        // Tracy's GPU zones are made of the same timestamps.
+        let gpu_timing = settings.gpu_timing || cfg!(feature = "profile-tracy");
+        let timestamps = if !gpu_timing {
+            None
+        } else if queue_family.timestamp_valid_bits().is_none() {
+            warn!("queue family doesn't support timestamps, GPU frame times are unavailable");
+            None
+        } else {
+            Some(
+                QueryPool::new(
+                    device.clone(),
+                    QueryPoolCreateInfo {
+                        query_count: 2 * settings.frames_in_flight as u32,
+                        ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+                    },
+                )
+                .map_err(RendererCreationError::QueryPool)?,
+            )
+        };
+        #[cfg(feature = "profile-tracy")]
+        let gpu_profiler = timestamps
+            .is_some()
+            .then(|| profiling::GpuProfiler::new(&device, settings.frames_in_flight))
+            .flatten();
+
+        let statistics_queries = count_pipeline_statistics
+            .then(|| {
+                QueryPool::new(
+                    device.clone(),
+                    QueryPoolCreateInfo {
+                        query_count: settings.frames_in_flight as u32,
+                        ..QueryPoolCreateInfo::query_type(QueryType::PipelineStatistics(
+                            pipeline_statistic_flags(),
+                        ))
+                    },
+                )
+            })
+            .transpose()
+            .map_err(RendererCreationError::QueryPool)?;
+
+        let mut renderer = Renderer {
+            device,
+            queue,
+            window: surface.window().clone(),
+            surface: Some(surface),
+            swapchain: Some(swapchain),
+            swapchain_buffers_count: settings.swapchain_buffers_count,
+            display_output: settings.display_output,
+            surface_format_policy: settings.surface_format_policy.clone(),
+            uncapped_present: settings.uncapped_present,
+            transparent: settings.transparent,
+            frame_capture: settings.frame_capture,
+            target,
+            pipelines: vec![graphics_pipeline],
+            particle_pipeline,
+            depth_pipeline,
+            topologies,
+            topology: settings.topology,
+            depth_prepass: settings.depth_prepass,
+            rasterizer: settings.rasterizer,
+            shader_preset,
+            debug_line_pipeline,
+            fragment_shaders: vec![fragment_shader],
+            output_pass,
+            meshes: vec![vertex_buffer],
+            vertex_format,
+            mesh_extents: vec![main_mesh_extent],
+            staging,
+            instance_ring,
+            instance_colors,
+            particle_colors,
+            debug_line_ring,
+            viewport,
+            attachments,
+            background_color: settings.background_color,
+            instance_count: settings.instance_count,
+            draw_buckets: settings.draw_buckets,
+            prerecorded,
+            gpu_particles,
+            procedural_background,
+            point_sprites,
+            outline,
+            stereo,
+            taa,
+            depth_of_field,
+            motion_blur,
+            styles: Vec::new(),
+            focus_requested: None,
+            g_buffer: None,
+            environment_map,
+            demo,
+            demo_kind: settings.demo,
+            nbody_bodies: settings.nbody_bodies,
+            heightmap,
+            shadertoy_module: None,
+            tile_layer,
+            textures,
+            lights,
+            occlusion,
+            recreate_swapchain: false,
+            resize_pending: false,
+            last_swapchain_recreation: Instant::now(),
+            recreate_surface: false,
+            frames,
+            frame_index: 0,
+            timestamps,
+            gpu_frame_time: None,
+            #[cfg(feature = "profile-tracy")]
+            gpu_profiler,
+            frame_timings: FrameTimings::default(),
+            breadcrumbs: settings
+                .crash_breadcrumbs
+                .then(|| Breadcrumbs::new(settings.frames_in_flight)),
+            statistics_queries,
+            pipeline_statistics: None,
+            capture_requested: false,
+            captured_frame: None,
+            hdr_capture_requested: false,
+            captured_hdr_frame: None,
+            memory_stats,
+            capabilities,
+            uploader,
+            uploads_ready: None,
+        };
+        renderer.update_g_buffer();
+        renderer.set_stylization(settings.stylization);
+        renderer.attachments_changed();
+        Ok(renderer)
+    }
+
+    pub fn window(&self) -> &Arc<Window> {
+        &self.window
+    }
+
+    /// `None` while suspended.
+    pub fn surface(&self) -> Option<&Arc<WindowSurface>> {
+        self.surface.as_ref()
+    }
+
+    /// What the device was created with of [`RendererSettings::device`].
+    pub fn capabilities(&self) -> &DeviceCapabilities {
+        &self.capabilities
+    }
+
+    pub fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+
+    /// Runs `load` on the upload thread and draws the vertices it returns as a
+    /// triangle list instead of the current main mesh, for every instance without a
+    /// mesh of its own, from the first frame after they are uploaded. `name` is
+    /// what the result is logged as.
+    pub fn load_mesh(
+        &self,
+        name: String,
+        load: impl FnOnce() -> Result<Mesh, String> + Send + 'static,
+    ) {
+        let topology = self.topology;
+        self.uploader
+            .load(name, move || Ok(override_topology(load()?, topology)));
+    }
+
+    /// Takes over the meshes the upload thread finished.
+    fn receive_uploads(&mut self) {
+        let finished: Vec<_> = self.uploader.finished().collect();
+        for upload in finished {
+            let mesh = match upload.result {
+                Ok(mesh) => mesh,
+                Err(message) => {
+                    warn!(name = %upload.name, %message, "failed to load mesh");
+                    continue;
+                }
+            };
+            info!(
+                name = %upload.name,
+                vertices = mesh.buffer.vertex_count(),
+                indices = mesh.buffer.index_count(),
+                "loaded mesh"
+            );
+            self.memory_stats
+                .untrack(AllocationPurpose::Vertex, self.meshes[0].size());
+            self.memory_stats
+                .track(AllocationPurpose::Vertex, mesh.size);
+            self.mesh_extents[0] = mesh.extent;
+            self.add_topology(mesh.buffer.topology());
+            self.meshes[0] = mesh.buffer;
+            self.uploads_ready = Some(match self.uploads_ready.take() {
+                Some(ready) => ready.join(mesh.ready).boxed_send_sync(),
+                None => mesh.ready,
+            });
+            if let Some(gpu_particles) = self.gpu_particles.as_mut() {
+                gpu_particles.set_index_count(&self.device, self.meshes[0].index_count());
+            }
+            self.record_prerecorded_commands();
+        }
+    }
+
+    /// Uploads `mesh` for instances to be drawn with.
+    pub fn add_mesh(&mut self, mesh: Mesh) -> MeshId {
+        let mesh = override_topology(mesh, self.topology);
+        self.mesh_extents.push(mesh_extent(&mesh.vertices));
+        let buffer = self.create_mesh(&mesh);
+        self.add_topology(buffer.topology());
+        self.meshes.push(buffer);
+        MeshId(self.meshes.len() - 1)
+    }
+
+    fn create_mesh(&mut self, mesh: &Mesh) -> MeshBuffer {
+        let buffer = self
+            .vertex_format
+            .create_buffer(&self.device, mesh)
+            .unwrap();
+        self.memory_stats
+            .track(AllocationPurpose::Vertex, buffer.size());
+        buffer
+    }
+
+    /// Creates the pipeline variants for meshes drawn as `topology`, unless they
+    /// already exist.
+    fn add_topology(&mut self, topology: PrimitiveTopology) {
+        if self.topologies.contains(&topology) {
+            return;
+        }
+        info!(?topology, "creating pipeline variants");
+        self.topologies.push(topology);
+        self.recreate_material_pipelines();
+        self.depth_pipeline = self.create_depth_pipeline();
+        self.recreate_outline_pipelines();
+        self.recreate_stereo_pipelines();
+        self.recreate_g_buffer_pipelines();
+        self.recreate_environment_map_pipelines();
+        if let Some(shader_preset) = self.shader_preset.as_mut() {
+            *shader_preset = ActivePreset::new(
+                &self.device,
+                &self.target,
+                self.vertex_format,
+                &self.topologies,
+                shader_preset.index,
+            )
+            .unwrap();
+        }
+    }
+
+    /// Adds a material drawing with `module` as its fragment shader. Fails if it
+    /// doesn't fit the scene's vertex shader, listing everything that doesn't.
+    pub fn add_material(&mut self, module: Arc<ShaderModule>) -> Result<MaterialId, String> {
+        self.check_material_shader(&module)?;
+        let pipeline = self
+            .create_material_pipeline(&module)
+            .map_err(pipeline_error)?;
+        self.pipelines.push(pipeline);
+        self.fragment_shaders.push(module);
+        Ok(MaterialId(self.pipelines.len() - 1))
+    }
+
+    /// The fragment shader of a material that multiplies the vertex colors with the
+    /// instance's texture, for [`Self::add_material`].
+    pub fn textured_shader(&self) -> Arc<ShaderModule> {
+        self.textures.shader(&self.device)
+    }
+
+    /// Samples `image` for `texture` from the next frame on, in place of the one given
+    /// in [`RendererSettings::textures`].
+    pub fn set_texture(&mut self, texture: TextureId, image: TextureImage) -> Result<(), String> {
+        self.textures.replace(
+            &self.device,
+            &self.queue,
+            texture,
+            image,
+            &mut self.memory_stats,
+        )
+    }
+
+    /// The fragment shader of a material that lights the textured vertex colors with
+    /// a fixed light and those set with [`Self::set_lights`], bending the instance's
+    /// surface by its normal map, for [`Self::add_material`].
+    pub fn lit_shader(&self) -> Arc<ShaderModule> {
+        self.textures.lit_shader(&self.device)
+    }
+
+    /// Shines `lights` on the materials made with [`Self::lit_shader`] from the next
+    /// frame on, the first [`MAX_LIGHTS`] of them.
+    pub fn set_lights(&mut self, lights: &[Light]) {
+        self.lights.set(lights);
+    }
+
+    /// Grades the scene with `lut` from the next frame on, or stops grading it.
+    pub fn set_color_lut(&mut self, lut: Option<&ColorLut>) {
+        self.output_pass
+            .grading
+            .set(&self.queue, lut, &mut self.memory_stats);
+        self.output_pass.params.grade = self.output_pass.grading.enabled();
+        self.output_pass.update_descriptor_sets(&self.attachments);
+    }
+
+    /// Whether to dither the final image from the next frame on.
+    pub fn set_dither(&mut self, enabled: bool) {
+        self.output_pass.set_dither(enabled);
+    }
+
+    /// Turns temporal anti-aliasing on or off from the next frame on. Without
+    /// dynamic rendering it stays off.
+    pub fn set_taa(&mut self, enabled: bool) {
+        if let Some(taa) = self.taa.take() {
+            taa.untrack_memory(&mut self.memory_stats);
+        }
+        if enabled {
+            self.taa = create_taa(
+                &self.device,
+                &self.target,
+                &self.viewport,
+                &mut self.memory_stats,
+            );
+        }
+        self.update_g_buffer();
+    }
+
+    /// Turns the depth of field on or off from the next frame on. Without dynamic
+    /// rendering it stays off.
+    pub fn set_depth_of_field(&mut self, enabled: bool) {
+        if let Some(depth_of_field) = self.depth_of_field.take() {
+            depth_of_field.untrack_memory(&mut self.memory_stats);
+        }
+        if enabled {
+            self.depth_of_field = create_depth_of_field(
+                &self.device,
+                &self.target,
+                &self.viewport,
+                &mut self.memory_stats,
+            );
+        }
+        self.update_g_buffer();
+    }
+
+    /// Turns motion blur on with `settings` from the next frame on, or off. Without
+    /// dynamic rendering it stays off.
+    pub fn set_motion_blur(&mut self, settings: Option<MotionBlurSettings>) {
+        if let Some(motion_blur) = self.motion_blur.take() {
+            motion_blur.untrack_memory(&mut self.memory_stats);
+        }
+        if let Some(settings) = settings {
+            self.motion_blur = create_motion_blur(
+                &self.device,
+                &self.target,
+                settings,
+                &self.viewport,
+                &mut self.memory_stats,
+            );
+        }
+        self.update_g_buffer();
+    }
+
+    /// Draws the stylization passes `stylization` turns on from the next frame on,
+    /// keeping those that stay on. Without dynamic rendering they stay off.
+    pub fn set_stylization(&mut self, mut stylization: Stylization) {
+        let mut styles = Vec::new();
+        for style in Style::ALL {
+            let existing = self
+                .styles
+                .iter()
+                .position(|pass| pass.style() == style)
+                .map(|i| self.styles.remove(i));
+            match (*style.intensity_mut(&mut stylization), existing) {
+                (Some(intensity), existing) => {
+                    let pass = existing.or_else(|| {
+                        supports_post_effect(&self.target, style.name()).then(|| {
+                            StylePass::new(
+                                &self.device,
+                                style,
+                                self.viewport.dimensions.map(|x| x as u32),
+                                &mut self.memory_stats,
+                            )
+                        })
+                    });
+                    if let Some(mut pass) = pass {
+                        pass.set_intensity(intensity);
+                        styles.push(pass);
+                    }
+                }
+                (None, Some(pass)) => pass.untrack_memory(&mut self.memory_stats),
+                (None, None) => {}
+            }
+        }
+        self.styles = styles;
+    }
+
+    /// Focuses the depth of field on what's under `cursor`, normalized to `[0, 1]`
+    /// across the window, once its depth is read back a few frames from now.
+    pub fn focus_at(&mut self, cursor: [f32; 2]) {
+        self.focus_requested = Some(cursor);
+    }
+
+    /// Creates the G-buffer if something reads it, and drops it if not.
+    fn update_g_buffer(&mut self) {
+        if self.taa.is_none() && self.depth_of_field.is_none() && self.motion_blur.is_none() {
+            if let Some(g_buffer) = self.g_buffer.take() {
+                g_buffer.untrack_memory(&mut self.memory_stats);
+            }
+        } else if self.g_buffer.is_none() {
+            self.g_buffer = Some(GBuffer::new(
+                &self.device,
+                &self.target,
+                self.vertex_format,
+                &self.topologies,
+                &self.rasterizer,
+                self.frames.len(),
+                self.viewport.dimensions,
+                &mut self.memory_stats,
+            ));
+        }
+    }
+
+    /// Whether the materials made with [`Self::lit_shader`] show a heatmap of how
+    /// many lights reach each cluster of the screen instead of shading.
+    pub fn set_light_heatmap(&mut self, enabled: bool) {
+        self.lights.set_heatmap(enabled);
+    }
+
+    /// Draws `tilemap` behind the scene from the next frame on. Call every frame; the
+    /// tiles are only uploaded again after they change.
+    pub fn sync_tilemap(&mut self, tilemap: &Tilemap) {
+        if let Some(tile_layer) = self.tile_layer.as_mut() {
+            tile_layer.sync(&self.device, tilemap, &mut self.memory_stats);
+        }
+    }
+
+    pub fn set_background_color(&mut self, color: [f32; 4]) {
+        self.background_color = color;
+        self.record_prerecorded_commands();
+    }
+
+    /// Swaps the main material's fragment shader, keeping the old one if `module`
+    /// doesn't fit the pipelines, e.g. because its inputs don't match the vertex
+    /// shader's outputs.
+    pub fn set_fragment_shader(&mut self, module: Arc<ShaderModule>) -> Result<(), String> {
+        self.check_material_shader(&module)?;
+        let graphics_pipeline = self
+            .create_material_pipeline(&module)
+            .map_err(pipeline_error)?;
+        let particle_pipeline =
+            create_particle_pipeline(&self.device, &self.target, self.vertex_format, &module)
+                .map_err(pipeline_error)?;
+        let static_pipeline = match self.prerecorded {
+            Some(_) => Some(
+                create_static_pipeline(&self.device, &self.target, self.vertex_format, &module)
+                    .map_err(pipeline_error)?,
+            ),
+            None => None,
+        };
+        let gpu_particle_pipeline = match self.gpu_particles {
+            Some(_) => Some(
+                gpu_particles::create_pipeline(
+                    &self.device,
+                    &self.target,
+                    self.vertex_format,
+                    &module,
+                )
+                .map_err(pipeline_error)?,
+            ),
+            None => None,
+        };
+
+        self.pipelines[0] = graphics_pipeline;
+        if let (Some(gpu_particles), Some(pipeline)) =
+            (self.gpu_particles.as_mut(), gpu_particle_pipeline)
+        {
+            gpu_particles.set_pipeline(pipeline);
+        }
+        self.particle_pipeline = particle_pipeline;
+        if let (Some(prerecorded), Some(pipeline)) = (self.prerecorded.as_mut(), static_pipeline) {
+            prerecorded.pipeline = pipeline;
+        }
+        self.fragment_shaders[0] = module;
+        self.record_prerecorded_commands();
+        Ok(())
+    }
+
+    /// Checks a material's fragment shader against the scene's vertex shader and the
+    /// textures, with every mismatch on a line of its own.
+    fn check_material_shader(&self, module: &Arc<ShaderModule>) -> Result<(), String> {
+        let entry_point = module
+            .entry_point("main")
+            .ok_or("shader has no `main` entry point")?;
+        let loaded_vertex_shader = vertex_shader::load(self.device.clone()).unwrap();
+        reflection::check_stage_interface(
+            &loaded_vertex_shader.entry_point("main").unwrap(),
+            &entry_point,
+        )
+        .map_err(|mismatches| {
+            format!(
+                "inputs don't match the vertex shader's outputs:\n{}",
+                mismatches
+            )
+        })?;
+        reflection::check_descriptors(
+            &entry_point,
+            self.textures.descriptor_set().layout(),
+            self.lights.layout(),
+        )
+        .map_err(|mismatches| format!("descriptors aren't bound:\n{}", mismatches))
+    }
+
+    /// Draws the main material with preset `index` of [`SHADER_PRESETS`] from the next
+    /// frame on, or with the built-in shaders for `None`. Keeps the current shaders
+    /// if the preset's don't fit the pipeline.
+    pub fn set_shader_preset(
+        &mut self,
+        index: Option<usize>,
+    ) -> Result<(), GraphicsPipelineCreationError> {
+        self.shader_preset = match index {
+            Some(index) => Some(ActivePreset::new(
+                &self.device,
+                &self.target,
+                self.vertex_format,
+                &self.topologies,
+                index,
+            )?),
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// Switches to `kind` from the next frame on, dropping the previous demo with
+    /// everything it owned; a demo switched back to starts over.
+    pub fn set_demo(&mut self, kind: Option<DemoKind>) {
+        if let Some(demo) = self.demo.take() {
+            demo.untrack_memory(&mut self.memory_stats);
+        }
+        self.demo_kind = kind;
+        self.demo = kind.and_then(|kind| {
+            create_demo(
+                kind,
+                DemoResources {
+                    device: &self.device,
+                    queue_family: self.queue.family(),
+                    target: &self.target,
+                    memory_stats: &mut self.memory_stats,
+                    vertex_format: self.vertex_format,
+                    nbody_bodies: self.nbody_bodies,
+                    heightmap: self.heightmap.as_ref(),
+                    shadertoy: self.shadertoy_module.as_ref(),
+                },
+            )
+        });
+    }
+
+    /// Runs `module`, a shader the [`ShaderLoader`](crate::assets::ShaderLoader)
+    /// compiled with `load_shadertoy`, as [`DemoKind::ShaderToy`], restarting it if
+    /// it's the active demo. Keeps the current one if it doesn't fit the pipeline.
+    pub fn set_shadertoy_shader(&mut self, module: Arc<ShaderModule>) -> Result<(), String> {
+        demo::create_shadertoy_pipeline(&self.device, &self.target, &module)?;
+        self.shadertoy_module = Some(module);
+        if self.demo_kind == Some(DemoKind::ShaderToy) {
+            self.set_demo(self.demo_kind);
+        }
+        Ok(())
+    }
+
+    /// Hands the active demo this frame's pointer.
+    pub fn set_demo_input(&mut self, input: &DemoInput) {
+        if let Some(demo) = self.demo.as_mut() {
+            demo.handle_input(input, self.viewport.dimensions);
+        }
+    }
+
+    /// Turns the depth pre-pass on or off from the next frame on.
+    pub fn set_depth_prepass(&mut self, enabled: bool) {
+        self.depth_prepass = enabled;
+        self.recreate_material_pipelines();
+    }
+
+    /// Rasterizes the scene's materials, their depth pre-pass and the debug lines with
+    /// `rasterizer` from the next frame on.
+    pub fn set_rasterizer(&mut self, rasterizer: RasterizerSettings) {
+        self.rasterizer = rasterizer;
+        self.recreate_material_pipelines();
+        self.depth_pipeline = self.create_depth_pipeline();
+        self.recreate_outline_pipelines();
+        self.recreate_stereo_pipelines();
+        self.recreate_g_buffer_pipelines();
+        self.recreate_environment_map_pipelines();
+        self.debug_line_pipeline =
+            create_debug_line_pipeline(&self.device, &self.target, &self.rasterizer).unwrap();
+    }
+
+    fn recreate_material_pipelines(&mut self) {
+        self.pipelines = self
+            .fragment_shaders
+            .iter()
+            .map(|module| self.create_material_pipeline(module).unwrap())
+            .collect();
+    }
+
+    /// A material's pipeline with `module`, in a variant for each topology.
+    fn create_material_pipeline(
+        &self,
+        module: &Arc<ShaderModule>,
+    ) -> Result<PipelineVariants, GraphicsPipelineCreationError> {
+        PipelineVariants::new(&self.topologies, |topology| {
+            create_pipeline(
+                &self.device,
+                &self.target,
+                self.vertex_format,
+                module,
+                self.depth_prepass,
+                &self.rasterizer,
+                topology,
+            )
+        })
+    }
+
+    fn create_depth_pipeline(&self) -> PipelineVariants {
+        PipelineVariants::new(&self.topologies, |topology| {
+            create_depth_pipeline(
+                &self.device,
+                &self.target,
+                self.vertex_format,
+                &self.rasterizer,
+                topology,
+            )
+        })
+        .unwrap()
+    }
+
+    fn recreate_outline_pipelines(&mut self) {
+        if let Some(outline) = self.outline.as_mut() {
+            outline.set_pipelines(outline::create_pipelines(
+                &self.device,
+                &self.target,
+                self.vertex_format,
+                &self.topologies,
+                &self.rasterizer,
+            ));
+        }
+    }
+
+    fn recreate_stereo_pipelines(&mut self) {
+        if let Some(stereo) = self.stereo.as_mut() {
+            stereo.recreate_pipelines(
+                &self.device,
+                self.vertex_format,
+                &self.topologies,
+                &self.rasterizer,
+            );
+        }
+    }
+
+    fn recreate_g_buffer_pipelines(&mut self) {
+        if let Some(g_buffer) = self.g_buffer.as_mut() {
+            g_buffer.recreate_pipelines(
+                &self.device,
+                self.vertex_format,
+                &self.topologies,
+                &self.rasterizer,
+            );
+        }
+    }
+
+    fn recreate_environment_map_pipelines(&mut self) {
+        if let Some(environment_map) = self.environment_map.as_mut() {
+            environment_map.recreate_pipelines(
+                &self.device,
+                self.vertex_format,
+                &self.topologies,
+                &self.rasterizer,
+            );
+        }
+    }
+
+    /// Gives the surface back, tearing everything else down, so the device can be recreated.
+    pub fn into_surface(self) -> Arc<WindowSurface> {
+        self.surface.expect("suspended renderers have no surface")
+    }
+
+    /// Releases the swapchain and the surface, for when the window can't be drawn to
+    /// for a while: Android takes the native window away when the app goes to the
+    /// background. Nothing is drawn until [`Self::resume`].
+    pub fn suspend(&mut self) {
+        info!("suspending, releasing the surface");
+        self.release_swapchain();
+        self.surface = None;
+        self.recreate_surface = false;
+    }
+
+    /// Drops the swapchain once the frames drawing to its images are done.
+    fn release_swapchain(&mut self) {
+        // Nothing may use the swapchain images any more once it's gone.
+        for frame in &mut self.frames {
+            if let Some(fence) = frame.fence.take() {
+                if let Err(e) = fence.wait(None) {
+                    error!(error = ?e, "failed to wait for frame fence");
+                }
+            }
+        }
+        self.attachments.clear();
+        self.attachments_changed();
+        self.swapchain = None;
+    }
+
+    /// Creates a new surface for the window on the next frame, after [`Self::suspend`].
+    pub fn resume(&mut self) {
+        if self.swapchain.is_none() {
+            info!("resuming");
+            self.recreate_surface = true;
+        }
+    }
+
+    /// Switches rendering to a new surface, e.g. after the old one or its window was lost.
+    ///
+    /// The old swapchain is dropped first: vulkano can only hand a retired swapchain
+    /// to one for the same surface, and a window takes one swapchain at a time. If
+    /// this fails the renderer draws nothing, but holds on to `surface`; the caller
+    /// takes it back with [`Self::into_surface`] to create a renderer for a device
+    /// that can present to it.
+    pub fn replace_surface(
+        &mut self,
+        surface: Arc<WindowSurface>,
+    ) -> Result<(), RendererCreationError> {
+        info!("replacing surface");
+        self.release_swapchain();
+        self.window = surface.window().clone();
+        self.surface = Some(surface.clone());
+        self.recreate_surface = false;
+        if !self
+            .queue
+            .family()
+            .supports_surface(&surface)
+            .map_err(RendererCreationError::SurfaceProperties)?
+        {
+            return Err(RendererCreationError::SurfaceUnsupported);
+        }
+
+        let (swapchain, images) = create_swapchain(
+            &self.device,
+            &surface,
+            self.swapchain_buffers_count,
+            self.display_output,
+            &self.surface_format_policy,
+            self.uncapped_present,
+            self.transparent,
+            self.frame_capture,
+        )?;
+        let format_changed = swapchain.image_format() != self.target.output_format();
+        if format_changed {
+            self.target = RenderTarget::new(
+                &self.device,
+                swapchain.image_format(),
+                self.target.depth_format(),
+                self.capabilities.dynamic_rendering,
+            );
+            self.output_pass.pipeline = create_output_pipeline(&self.device, &self.target)
+                .map_err(RendererCreationError::Pipeline)?;
+        }
+        // With dynamic rendering only the output pass depends on the output format.
+        if format_changed && matches!(self.target, RenderTarget::RenderPass(_)) {
+            self.recreate_material_pipelines();
+            self.depth_pipeline = self.create_depth_pipeline();
+            self.recreate_outline_pipelines();
+            let main_shader = &self.fragment_shaders[0];
+            self.particle_pipeline = create_particle_pipeline(
+                &self.device,
+                &self.target,
+                self.vertex_format,
+                main_shader,
+            )
+            .map_err(RendererCreationError::Pipeline)?;
+            self.debug_line_pipeline =
+                create_debug_line_pipeline(&self.device, &self.target, &self.rasterizer)
+                    .map_err(RendererCreationError::Pipeline)?;
+            if let Some(tile_layer) = self.tile_layer.as_mut() {
+                tile_layer.set_pipeline(tile_layer::create_pipeline(&self.device, &self.target));
+            }
+            if let Some(procedural_background) = self.procedural_background.as_mut() {
+                procedural_background
+                    .set_pipeline(procedural::create_pipeline(&self.device, &self.target));
+            }
+            if let Some(point_sprites) = self.point_sprites.as_mut() {
+                point_sprites
+                    .set_pipeline(point_sprites::create_pipeline(&self.device, &self.target));
+            }
+            if let Some(stereo) = self.stereo.as_mut() {
+                stereo.set_composite_pipeline(stereo::create_composite_pipeline(
+                    &self.device,
+                    &self.target,
+                ));
+            }
+            if let Some(environment_map) = self.environment_map.as_mut() {
+                environment_map.set_ball_pipeline(environment::create_ball_pipeline(
+                    &self.device,
+                    &self.target,
+                ));
+            }
+            if let Some(demo) = self.demo.as_mut() {
+                demo.recreate_pipelines(&self.device, &self.target);
+            }
+            if let Some(occlusion) = self.occlusion.as_mut() {
+                occlusion.set_pipeline(occlusion::create_pipeline(&self.device, &self.target));
+            }
+            if let Some(shader_preset) = self.shader_preset.as_mut() {
+                *shader_preset = ActivePreset::new(
+                    &self.device,
+                    &self.target,
+                    self.vertex_format,
+                    &self.topologies,
+                    shader_preset.index,
+                )
+                .unwrap();
+            }
+            if let Some(prerecorded) = self.prerecorded.as_mut() {
+                prerecorded.pipeline = create_static_pipeline(
+                    &self.device,
+                    &self.target,
+                    self.vertex_format,
+                    main_shader,
+                )
+                .map_err(RendererCreationError::Pipeline)?;
+            }
+            if let Some(gpu_particles) = self.gpu_particles.as_mut() {
+                gpu_particles.set_pipeline(
+                    gpu_particles::create_pipeline(
+                        &self.device,
+                        &self.target,
+                        self.vertex_format,
+                        main_shader,
+                    )
+                    .unwrap(),
+                );
+            }
+        }
+        self.output_pass.params.transfer = DisplayOutput::transfer(swapchain.image_color_space());
+        self.output_pass.params.premultiply = premultiplies(&swapchain);
+        self.output_pass.set_format(swapchain.image_format());
+
+        self.swapchain = Some(swapchain);
+        self.attachments =
+            window_size_dependent_setup(&self.device, &images, &self.target, &mut self.viewport);
+        self.attachments_changed();
+        self.recreate_swapchain = false;
+        self.resize_pending = false;
+        Ok(())
+    }
+
+    /// Recreates the swapchain for the window's new size, throttled while resizing.
+    pub fn request_swapchain_recreation(&mut self) {
+        self.resize_pending = true;
+    }
+
+    /// What the device is and what the frames in flight were drawing, for when it
+    /// was lost. `None` unless the renderer keeps
+    /// [breadcrumbs](RendererSettings::crash_breadcrumbs).
+    pub fn crash_report(&self) -> Option<String> {
+        let breadcrumbs = self.breadcrumbs.as_ref()?;
+        let properties = self.device.physical_device().properties();
+        let mut report = format!(
+            "device: {} ({:?})\ndriver: {} {}\napi version: {}\n",
+            properties.device_name,
+            properties.device_type,
+            properties.driver_name.as_deref().unwrap_or("unknown"),
+            properties.driver_info.as_deref().unwrap_or(""),
+            self.device.api_version(),
+        );
+        report += "\n";
+        report += &breadcrumbs.report();
+        Some(report)
+    }
+
+    /// GPU time of the most recent frame whose timestamps were read back since the
+    /// last call. Frames are read once their fence is reused, so this lags the
+    /// frame just rendered by the number of frames in flight.
+    pub fn take_gpu_frame_time(&mut self) -> Option<Duration> {
+        self.gpu_frame_time.take()
+    }
+
+    /// Time the last call to [`Self::render`] spent blocked, measured on the CPU.
+    pub fn frame_timings(&self) -> FrameTimings {
+        self.frame_timings
+    }
+
+    /// Pipeline statistics of the most recent frame read back since the last call,
+    /// lagging like [`Self::take_gpu_frame_time`].
+    pub fn take_pipeline_statistics(&mut self) -> Option<PipelineStatistics> {
+        self.pipeline_statistics.take()
+    }
+
+    /// Copies the next frame rendered to the host, for [`Self::take_captured_frame`].
+    /// Fails unless the renderer was created with [`RendererSettings::frame_capture`]
+    /// and the swapchain has an 8-bit RGBA or BGRA format.
+    pub fn capture_frame(&mut self) -> Result<(), String> {
+        if let Some(swapchain) = &self.swapchain {
+            if !swapchain.create_info().image_usage.transfer_src {
+                return Err("the swapchain images can't be copied from".to_owned());
+            }
+            let format = swapchain.image_format();
+            if capture_byte_order(format).is_none() {
+                return Err(format!(
+                    "frames in the {:?} format can't be captured",
+                    format
+                ));
+            }
+        }
+        self.capture_requested = true;
+        Ok(())
+    }
+
+    /// The most recent captured frame read back since the last call, lagging like
+    /// [`Self::take_gpu_frame_time`].
+    pub fn take_captured_frame(&mut self) -> Option<CapturedFrame> {
+        self.captured_frame.take()
+    }
+
+    /// Copies the HDR image the output pass reads in the next frame to the
+    /// host, for [`Self::take_captured_hdr_frame`]. Fails without dynamic rendering,
+    /// where that image is a transient attachment of the render pass.
+    pub fn capture_hdr_frame(&mut self) -> Result<(), String> {
+        if !matches!(self.target, RenderTarget::Dynamic { .. }) {
+            return Err(
+                "the HDR scene image only lives within the render pass; it needs dynamic \
+                 rendering to be copied"
+                    .to_owned(),
+            );
+        }
+        self.hdr_capture_requested = true;
+        Ok(())
+    }
+
+    /// The most recent HDR frame read back since the last call, lagging like
+    /// [`Self::take_gpu_frame_time`].
+    pub fn take_captured_hdr_frame(&mut self) -> Option<HdrFrame> {
+        self.captured_hdr_frame.take()
+    }
+
+    /// Instance clusters the occlusion queries found hidden, while occlusion culling
+    /// and the depth pre-pass are both on. Lags like [`Self::take_gpu_frame_time`].
+    pub fn culled_clusters(&self) -> Option<u32> {
+        self.occlusion
+            .as_ref()
+            .filter(|_| self.draws_depth_prepass())
+            .map(OcclusionCulling::culled)
+    }
+
+    /// Shader presets move the vertices, so the pre-pass is skipped while one is used.
+    fn draws_depth_prepass(&self) -> bool {
+        self.depth_prepass
+            && self.shader_preset.is_none()
+            && self.point_sprites.is_none()
+            && self.stereo.is_none()
+    }
+
+    /// Draws `instances` in the batches of `draw_list`, then `particles` of the main
+    /// mesh blended additively on top, then an outline around the `selected`
+    /// instances, by index, if outlines are on, then `debug_lines` as a line list over
+    /// everything, and presents the frame.
+    pub fn render(
+        &mut self,
+        frame: &FrameData,
+        instances: Instances,
+        draw_list: &DrawList,
+        selected: &[u32],
+        particles: Instances,
+        debug_lines: &[Vertex],
+    ) -> Result<(), RenderError> {
+        profile_zone!("render");
+        let dimensions = self.window().inner_size();
+        if dimensions.width == 0 || dimensions.height == 0 {
+            return Ok(());
+        }
+        // Suspended, and not resumed yet.
+        if self.swapchain.is_none() && !self.recreate_surface {
+            return Ok(());
+        }
+
+        self.frame_timings = FrameTimings::default();
+        // Wait until the GPU is done with the resources of the frame we are about to reuse.
+        let wait_start = Instant::now();
+        let frame_index = self.frame_index;
+        if let Some(fence) = self.frames[frame_index].fence.take() {
+            match fence.wait(None) {
+                Ok(()) => {}
+                Err(FlushError::DeviceLost) => return Err(RenderError::DeviceLost),
+                Err(e) => error!(error = ?e, "failed to wait for frame fence"),
+            }
+        }
+        self.frame_timings.frame_wait = wait_start.elapsed();
+        let capture = self.frames[frame_index]
+            .uploads
+            .as_mut()
+            .and_then(|uploads| uploads.capture.take());
+        if capture.is_some() {
+            self.captured_frame = capture;
+        }
+        let hdr_capture = self.frames[frame_index]
+            .uploads
+            .as_mut()
+            .and_then(|uploads| uploads.hdr_capture.take());
+        if hdr_capture.is_some() {
+            self.captured_hdr_frame = hdr_capture;
+        }
+        let focus_probe = self.frames[frame_index]
+            .uploads
+            .as_mut()
+            .and_then(|uploads| uploads.focus_probe.take());
+        if let (Some(probe), Some(depth_of_field)) = (focus_probe, self.depth_of_field.as_mut()) {
+            let depth = probe.read().unwrap()[0];
+            depth_of_field.set_focus(depth);
+            info!(depth, "focused the depth of field");
+        }
+        self.frames[frame_index].uploads = None;
+        self.staging.begin_frame(frame_index);
+        if std::mem::take(&mut self.frames[frame_index].timed) {
+            let timestamps = self.read_timestamps(frame_index);
+            self.gpu_frame_time = timestamps.and_then(|timestamps| self.gpu_time(timestamps));
+            #[cfg(feature = "profile-tracy")]
+            if let Some(gpu_profiler) = self.gpu_profiler.as_mut() {
+                gpu_profiler.read(frame_index, timestamps);
+            }
+        }
+        if std::mem::take(&mut self.frames[frame_index].counted) {
+            self.pipeline_statistics = self.read_pipeline_statistics(frame_index);
+        }
+        if let Some(occlusion) = self.occlusion.as_mut() {
+            occlusion.read_results(frame_index);
+        }
+
+        if self.recreate_surface {
+            // Some compositors drop surfaces when they restart; the window itself survives.
+            match vulkano_win::create_surface_from_winit(
+                self.window().clone(),
+                self.device.instance().clone(),
+            ) {
+                Ok(surface) => self
+                    .replace_surface(surface)
+                    .map_err(RenderError::Surface)?,
+                Err(e) => {
+                    warn!(error = ?e, "failed to recreate surface");
+                    return Ok(());
+                }
+            }
+        }
+        if self.resize_pending
+            && self.last_swapchain_recreation.elapsed() >= SWAPCHAIN_RECREATION_INTERVAL
+        {
+            self.recreate_swapchain = true;
+        }
+        if self.recreate_swapchain {
+            debug!(
+                width = dimensions.width,
+                height = dimensions.height,
+                "recreating swapchain"
+            );
+            let swapchain = self.swapchain.as_ref().unwrap();
+            let (new_swapchain, new_images) = match swapchain.recreate(SwapchainCreateInfo {
+                image_extent: dimensions.into(),
+                ..swapchain.create_info()
+            }) {
+                Ok(r) => r,
+                Err(SwapchainCreationError::ImageExtentNotSupported { .. }) => return Ok(()),
+                Err(SwapchainCreationError::DeviceLost) => return Err(RenderError::DeviceLost),
+                Err(SwapchainCreationError::SurfaceLost) => {
+                    self.recreate_surface = true;
+                    return Ok(());
+                }
+                Err(e) => panic!("Failed to recreate swapchain: {:?}", e),
+            };
+            self.swapchain = Some(new_swapchain);
+            self.attachments = window_size_dependent_setup(
+                &self.device,
+                &new_images,
+                &self.target,
+                &mut self.viewport,
+            );
+            self.attachments_changed();
+            self.recreate_swapchain = false;
+            self.resize_pending = false;
+            self.last_swapchain_recreation = Instant::now();
+            self.frame_timings.swapchain_recreated = true;
+        }
+
+        let acquire_start = Instant::now();
+        profile_zone!("acquire");
+        let acquired = acquire_next_image(self.swapchain.clone().unwrap(), None);
+        self.frame_timings.acquire = acquire_start.elapsed();
+        let (image_num, suboptimal, acquire_future) = match acquired {
+            Ok(r) => r,
+            Err(AcquireError::OutOfDate) => {
+                self.recreate_swapchain = true;
+                return Ok(());
+            }
+            Err(AcquireError::DeviceLost) => return Err(RenderError::DeviceLost),
+            Err(AcquireError::SurfaceLost) => {
+                self.recreate_surface = true;
+                return Ok(());
+            }
+            Err(e) => panic!("Failed to acquire next image: {:?}", e),
+        };
+
+        trace!(image_num, suboptimal, "acquired swapchain image");
+        if suboptimal {
+            self.resize_pending = true;
+        }
+
+        self.receive_uploads();
+        if self.prerecorded.is_some() {
+            self.fit_prerecorded_instances(instances.len() as u32);
+        }
+        // Pre-recorded command buffers don't draw demos, outlines, stereo views or
+        // environment maps or post effects, or copy frames out.
+        let outlined = self.outline.is_some() && !selected.is_empty();
+        let prerecorded = self.prerecorded.as_mut().filter(|_| {
+            self.demo.is_none()
+                && !self.capture_requested
+                && !self.hdr_capture_requested
+                && !outlined
+                && self.stereo.is_none()
+                && self.g_buffer.is_none()
+                && self.styles.is_empty()
+                && self.environment_map.is_none()
+        });
+        if let (Some(breadcrumbs), Some(_)) = (self.breadcrumbs.as_mut(), &prerecorded) {
+            let mut trail = Trail::new(true);
+            trail.push(format!("pre-recorded commands of image {}", image_num));
+            breadcrumbs.recorded(frame_index, trail);
+        }
+        let (command_buffer, uploads) = match prerecorded {
+            Some(prerecorded) => (prerecorded.prepare(image_num, frame, instances)?, None),
+            None => {
+                profile_zone!("record");
+                let (command_buffer, uploads) = self.record_commands(
+                    image_num,
+                    frame_index,
+                    frame,
+                    (instances, draw_list, selected),
+                    particles,
+                    debug_lines,
+                );
+                (command_buffer, Some(uploads))
+            }
+        };
+
+        // Chain onto the previous frame so submissions and presents stay in order.
+        let previous_index = (frame_index + self.frames.len() - 1) % self.frames.len();
+        let previous_frame_end = match self.frames[previous_index].fence.clone() {
+            Some(fence) => fence.boxed_send_sync(),
+            None => sync::now(self.device.clone()).boxed_send_sync(),
+        };
+        let previous_frame_end = match self.uploads_ready.take() {
+            Some(ready) => previous_frame_end.join(ready).boxed_send_sync(),
+            None => previous_frame_end,
+        };
+
+        // Flushed apart, so each can be timed.
+        let submit_start = Instant::now();
+        let submitted = {
+            profile_zone!("submit");
+            previous_frame_end
+                .join(acquire_future)
+                .then_execute(self.queue.clone(), command_buffer)
+                .unwrap()
+                .then_signal_semaphore_and_flush()
+        };
+        self.frame_timings.submit = submit_start.elapsed();
+        let present_start = Instant::now();
+        let future = submitted.and_then(|submitted| {
+            profile_zone!("present");
+            submitted
+                .then_swapchain_present(
+                    self.queue.clone(),
+                    self.swapchain.clone().unwrap(),
+                    image_num,
+                )
+                .boxed_send_sync()
+                .then_signal_fence_and_flush()
+        });
+        self.frame_timings.present = present_start.elapsed();
+        #[cfg(feature = "profile-tracy")]
+        profiling::frame_mark();
+
+        self.frame_index = (frame_index + 1) % self.frames.len();
+        match future {
+            Ok(future) => {
+                let fence = Arc::new(future);
+                if let Some(prerecorded) = self.prerecorded.as_mut() {
+                    prerecorded.images[image_num].fence = Some(fence.clone());
+                }
+                if let Some(breadcrumbs) = self.breadcrumbs.as_mut() {
+                    breadcrumbs.submitted(frame_index);
+                }
+                self.frames[frame_index] = FrameContext {
+                    fence: Some(fence),
+                    timed: self.timestamps.is_some() && uploads.is_some(),
+                    counted: self.statistics_queries.is_some() && uploads.is_some(),
+                    uploads,
+                };
+            }
+            Err(FlushError::OutOfDate) => self.recreate_swapchain = true,
+            Err(FlushError::DeviceLost) => return Err(RenderError::DeviceLost),
+            Err(FlushError::SurfaceLost) => self.recreate_surface = true,
+            Err(e) => error!(error = ?e, "failed to flush future"),
+        }
+
+        self.memory_stats
+            .report_if_due(self.device.physical_device());
+
+        Ok(())
+    }
+
+    /// Records this frame's draws from scratch, uploading fresh instance, particle
+    /// and debug line data.
+    fn record_commands(
+        &mut self,
+        image_num: usize,
+        frame_index: usize,
+        frame: &FrameData,
+        (instances, draw_list, selected): (Instances, &DrawList, &[u32]),
+        particles: Instances,
+        debug_line_data: &[Vertex],
+    ) -> (Arc<PrimaryAutoCommandBuffer>, FrameUploads) {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.device.clone(),
+            self.queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        let mut trail = Trail::new(self.breadcrumbs.is_some());
+
+        if let Some(query_pool) = &self.timestamps {
+            let first = 2 * frame_index as u32;
+            // Safe: the frame's fence was waited on, so its queries aren't in use.
+            unsafe {
+                builder
+                    .reset_query_pool(query_pool.clone(), first..first + 2)
+                    .unwrap()
+                    .write_timestamp(query_pool.clone(), first, PipelineStage::TopOfPipe)
+                    .unwrap();
+            }
+            #[cfg(feature = "profile-tracy")]
+            if let Some(gpu_profiler) = self.gpu_profiler.as_mut() {
+                gpu_profiler.begin(frame_index);
+            }
+        }
+
+        if let Some(query_pool) = &self.statistics_queries {
+            let query = frame_index as u32;
+            // Safe: the frame's fence was waited on, so its query isn't in use.
+            unsafe {
+                builder
+                    .reset_query_pool(query_pool.clone(), query..query + 1)
+                    .unwrap()
+                    .begin_query(
+                        query_pool.clone(),
+                        query,
+                        QueryControlFlags { precise: false },
+                    )
+                    .unwrap();
+            }
+        }
+
+        if let Some(gpu_particles) = self.gpu_particles.as_mut() {
+            trail.push("simulate gpu particles");
+            gpu_particles.simulate(&mut builder, frame.time);
+        }
+        let demo_frame = DemoFrame {
+            viewport: &self.viewport,
+            time: frame.time,
+            mesh: &self.meshes[0],
+        };
+        if let Some(demo) = self.demo.as_mut() {
+            trail.push("prepare demo");
+            demo.prepare(&mut builder, &demo_frame);
+        }
+        if let Some(procedural_background) = &self.procedural_background {
+            trail.push("generate procedural background");
+            procedural_background.generate(&mut builder, frame.time);
+        }
+        trail.push("uploads");
+
+        let instance_data = instances.data;
+        let instance_buffer = self.instance_ring.upload(
+            instance_data,
+            &mut self.staging,
+            &mut builder,
+            &mut self.memory_stats,
+        );
+        let instance_colors = self.instance_colors.upload(
+            instances.colors,
+            &mut self.staging,
+            &mut builder,
+            &mut self.memory_stats,
+        );
+        let instance_count = instance_data.len() as u32;
+        let depth_prepass = self.draws_depth_prepass();
+        let occlusion = self
+            .occlusion
+            .as_mut()
+            .filter(|_| depth_prepass && instance_count > 0);
+        let all_instances = 0..instance_count;
+        let visible = match &occlusion {
+            Some(occlusion) => occlusion.visible_ranges(instance_count),
+            None => vec![all_instances],
+        };
+        let cluster_bounds = occlusion.map(|occlusion| {
+            let scale = frame.mouse.map(|mouse| (mouse * frame.zoom).abs());
+            let loudest = frame.audio_bands.iter().copied().fold(0.0, f32::max);
+            occlusion.update(
+                instance_data,
+                draw_list.batches(),
+                &self.mesh_extents,
+                scale,
+                // The vertex shader's largest wobble amplitude.
+                0.5 * (1.0 + 2.0 * loudest),
+            );
+            occlusion.reset(&mut builder, frame_index);
+            occlusion.upload(&mut self.staging, &mut builder, &mut self.memory_stats)
+        });
+        let particle_data = particles.data;
+        let particle_buffer = (!particles.is_empty()).then(|| {
+            let buffer = self.instance_ring.upload(
+                particle_data,
+                &mut self.staging,
+                &mut builder,
+                &mut self.memory_stats,
+            );
+            let colors = self.particle_colors.upload(
+                particles.colors,
+                &mut self.staging,
+                &mut builder,
+                &mut self.memory_stats,
+            );
+            (buffer, colors)
+        });
+        let lights = self.lights.upload(
+            frame.time,
+            &mut self.staging,
+            &mut builder,
+            &mut self.memory_stats,
+        );
+        let tile_chunks = self.tile_layer.as_mut().and_then(|tile_layer| {
+            tile_layer.upload(&mut self.staging, &mut builder, &mut self.memory_stats)
+        });
+        let expanded_lines;
+        let debug_line_data = match self.rasterizer.native_line_width(&self.device) {
+            Some(_) => debug_line_data,
+            None => {
+                expanded_lines = rasterizer::expand_lines(
+                    debug_line_data,
+                    self.rasterizer.line_width,
+                    self.viewport.dimensions,
+                );
+                &expanded_lines
+            }
+        };
+        let debug_lines = (!debug_line_data.is_empty()).then(|| {
+            self.debug_line_ring.upload(
+                debug_line_data,
+                &mut self.staging,
+                &mut builder,
+                &mut self.memory_stats,
+            )
+        });
+        let debug_line_inputs = debug_lines.as_ref().map(|vertices| DebugLineInputs {
+            pipeline: &self.debug_line_pipeline,
+            viewport: &self.viewport,
+            vertices,
+        });
+
+        let push_constants = vertex_shader::ty::PushConstantData {
+            bands: frame.band_vectors(),
+            x: frame.mouse[0],
+            y: frame.mouse[1],
+            zoom: frame.zoom,
+            jitter: self.taa.as_ref().map_or([0.0; 2], Taa::jitter),
+        };
+
+        let preset_pipelines: Vec<_>;
+        let (pipelines, preset_push_constants) = match &self.shader_preset {
+            Some(shader_preset) => {
+                preset_pipelines = iter::once(shader_preset.pipeline.clone())
+                    .chain(self.pipelines[1..].iter().cloned())
+                    .collect();
+                (
+                    preset_pipelines.as_slice(),
+                    Some(SHADER_PRESETS[shader_preset.index].push_constants(frame)),
+                )
+            }
+            None => (self.pipelines.as_slice(), None),
+        };
+
+        let inputs = DrawInputs {
+            pipelines,
+            meshes: &self.meshes,
+            batches: draw_list.batches(),
+            viewport: &self.viewport,
+            instance_buffer: &instance_buffer,
+            colors: &instance_colors,
+            push_constants,
+            main_push_constants: preset_push_constants.as_deref(),
+            textures: self.textures.descriptor_set(),
+            lights: Some(&lights),
+            point_sprites: self.point_sprites.as_ref(),
+        };
+        // Every batch falls back to the one depth-only pipeline.
+        let depth_inputs = depth_prepass.then(|| DrawInputs {
+            pipelines: slice::from_ref(&self.depth_pipeline),
+            main_push_constants: None,
+            ..inputs
+        });
+        let particle_list = DrawList::single(0..particle_data.len() as u32);
+        let particle_pipeline = PipelineVariants::single(self.particle_pipeline.clone());
+        let particle_inputs = particle_buffer.as_ref().map(|(buffer, colors)| DrawInputs {
+            pipelines: slice::from_ref(&particle_pipeline),
+            batches: particle_list.batches(),
+            instance_buffer: buffer,
+            colors,
+            main_push_constants: None,
+            ..inputs
+        });
+        let attachments = &self.attachments[image_num];
+        // Drawn in a render pass of its own, so before the scene's; it's composited
+        // inline.
+        if let Some(stereo) = &self.stereo {
+            trail.push(format!("stereo views of instances 0..{}", instance_count));
+            stereo.render(&mut builder, &inputs, 0..instance_count);
+        }
+        if let Some(environment_map) = &self.environment_map {
+            trail.push(format!(
+                "environment map faces of instances 0..{}",
+                instance_count
+            ));
+            environment_map.render(&mut builder, &inputs, 0..instance_count);
+        }
+        if let Some(g_buffer) = self.g_buffer.as_mut() {
+            trail.push(format!("g-buffer of instances 0..{}", instance_count));
+            g_buffer.render(
+                &mut builder,
+                &inputs,
+                instance_data,
+                0..instance_count,
+                &mut self.staging,
+                &mut self.memory_stats,
+            );
+        }
+
+        if self.draw_buckets <= 1 || instance_count == 0 || self.stereo.is_some() {
+            trail.push("scene pass");
+            attachments.begin_scene(&mut builder, self.background_color, SubpassContents::Inline);
+            if let Some(procedural_background) = &self.procedural_background {
+                trail.push("    procedural background");
+                procedural_background.draw(&mut builder, &self.viewport);
+            }
+            if let Some(demo) = self.demo.as_mut() {
+                trail.push("    demo");
+                demo.draw_inline(&mut builder, &demo_frame);
+            }
+            if let (Some(tile_layer), Some(tile_chunks)) = (&self.tile_layer, &tile_chunks) {
+                trail.push("    tilemap");
+                tile_layer.draw(&mut builder, &self.viewport, tile_chunks);
+            }
+            if instance_count > 0 {
+                if let Some(depth_inputs) = &depth_inputs {
+                    trail.push(format!(
+                        "    depth pre-pass of instances 0..{}",
+                        instance_count
+                    ));
+                    depth_inputs.record(&mut builder, 0..instance_count);
+                }
+                if let (Some(occlusion), Some(bounds)) = (&self.occlusion, &cluster_bounds) {
+                    trail.push("    occlusion queries");
+                    occlusion.query(&mut builder, frame_index, &self.viewport, bounds);
+                }
+                match &self.stereo {
+                    Some(stereo) => {
+                        trail.push("    stereo composite");
+                        stereo.draw(&mut builder, &self.viewport);
+                    }
+                    None => {
+                        for range in &visible {
+                            trail.push(format!("    instances {:?}", range));
+                            inputs.record(&mut builder, range.clone());
+                        }
+                    }
+                }
+            }
+            if let Some(particle_inputs) = &particle_inputs {
+                trail.push(format!("    particles 0..{}", particle_data.len()));
+                particle_inputs.record(&mut builder, 0..particle_data.len() as u32);
+            }
+            if let Some(gpu_particles) = &self.gpu_particles {
+                trail.push("    gpu particles");
+                gpu_particles.draw(&mut builder, &self.viewport, &self.meshes[0]);
+            }
+            if let Some(environment_map) = &self.environment_map {
+                trail.push("    environment map ball");
+                environment_map.draw(&mut builder, &self.viewport);
+            }
+            if let Some(outline) = self.outline.as_ref().filter(|_| !selected.is_empty()) {
+                trail.push(format!("    outline of {} instances", selected.len()));
+                outline.draw(&mut builder, &inputs, selected);
+            }
+            if let Some(debug_line_inputs) = &debug_line_inputs {
+                trail.push("    debug lines");
+                debug_line_inputs.record(&mut builder);
+            }
+        } else {
+            let device = &self.device;
+            let queue_family = self.queue.family();
+            let inheritance = self.target.scene_inheritance(attachments);
+            let new_secondary = || {
+                AutoCommandBufferBuilder::secondary(
+                    device.clone(),
+                    queue_family,
+                    CommandBufferUsage::OneTimeSubmit,
+                    CommandBufferInheritanceInfo {
+                        render_pass: Some(inheritance.clone()),
+                        query_statistics_flags: match self.statistics_queries {
+                            Some(_) => pipeline_statistic_flags(),
+                            None => QueryPipelineStatisticFlags::none(),
+                        },
+                        ..Default::default()
+                    },
+                )
+                .unwrap()
+            };
+            let record_secondary = |(inputs, ranges): (&DrawInputs, Vec<Range<u32>>)| {
+                let mut secondary = new_secondary();
+                for range in ranges {
+                    inputs.record(&mut secondary, range);
+                }
+                secondary.build().unwrap()
+            };
+            let particle_range = 0..particle_data.len() as u32;
+            let particle_draw = particle_inputs
+                .as_ref()
+                .map(|particle_inputs| (particle_inputs, vec![particle_range]));
+            // The backgrounds, the demo and the tilemap go first, then the depth
+            // pre-pass and its occlusion queries, and the particles last, so they
+            // blend over every bucket.
+            let mut secondaries = Vec::new();
+            if let Some(procedural_background) = &self.procedural_background {
+                let mut secondary = new_secondary();
+                procedural_background.draw(&mut secondary, &self.viewport);
+                secondaries.push(secondary.build().unwrap());
+            }
+            if let Some(demo) = self.demo.as_mut() {
+                let mut secondary = new_secondary();
+                demo.draw_secondary(&mut secondary, &demo_frame);
+                secondaries.push(secondary.build().unwrap());
+            }
+            if let (Some(tile_layer), Some(tile_chunks)) = (&self.tile_layer, &tile_chunks) {
+                let mut secondary = new_secondary();
+                tile_layer.draw(&mut secondary, &self.viewport, tile_chunks);
+                secondaries.push(secondary.build().unwrap());
+            }
+            let buckets = instance_buckets(instance_count, self.draw_buckets);
+            let depth_draws = depth_inputs.iter().flat_map(|depth_inputs| {
+                buckets
+                    .iter()
+                    .map(move |bucket| (depth_inputs, vec![bucket.clone()]))
+            });
+            secondaries.par_extend(
+                depth_draws
+                    .collect::<Vec<_>>()
+                    .into_par_iter()
+                    .map(record_secondary),
+            );
+            if let (Some(occlusion), Some(bounds)) = (&self.occlusion, &cluster_bounds) {
+                let mut secondary = new_secondary();
+                occlusion.query(&mut secondary, frame_index, &self.viewport, bounds);
+                secondaries.push(secondary.build().unwrap());
+            }
+            secondaries.par_extend(
+                buckets
+                    .iter()
+                    .map(|bucket| (&inputs, clip_ranges(bucket, &visible)))
+                    .chain(particle_draw)
+                    .collect::<Vec<_>>()
+                    .into_par_iter()
+                    .map(record_secondary),
+            );
+            if let Some(gpu_particles) = &self.gpu_particles {
+                let mut secondary = new_secondary();
+                gpu_particles.draw(&mut secondary, &self.viewport, &self.meshes[0]);
+                secondaries.push(secondary.build().unwrap());
+            }
+            if let Some(environment_map) = &self.environment_map {
+                let mut secondary = new_secondary();
+                environment_map.draw(&mut secondary, &self.viewport);
+                secondaries.push(secondary.build().unwrap());
+            }
+            if let Some(outline) = self.outline.as_ref().filter(|_| !selected.is_empty()) {
+                let mut secondary = new_secondary();
+                outline.draw(&mut secondary, &inputs, selected);
+                secondaries.push(secondary.build().unwrap());
+            }
+            if let Some(debug_line_inputs) = &debug_line_inputs {
+                let mut secondary = new_secondary();
+                debug_line_inputs.record(&mut secondary);
+                secondaries.push(secondary.build().unwrap());
+            }
+            // Their draws are recorded on other threads, so only the pass is known.
+            trail.push(format!(
+                "scene pass, {} secondary command buffers",
+                secondaries.len()
+            ));
+            attachments.begin_scene(
+                &mut builder,
+                self.background_color,
+                SubpassContents::SecondaryCommandBuffers,
+            );
+            builder.execute_commands_from_vec(secondaries).unwrap();
+        }
+        trail.push("output pass");
+        let post = (self.g_buffer.is_some() || !self.styles.is_empty()).then(|| PostEffects {
+            g_buffer: self.g_buffer.as_ref(),
+            taa: self.taa.as_mut(),
+            depth_of_field: self.depth_of_field.as_ref(),
+            motion_blur: self.motion_blur.as_ref(),
+            styles: &self.styles,
+            instance_count,
+            time: frame.time,
+            viewport: &self.viewport,
+        });
+        let shown = attachments.begin_output(&mut builder, post);
+        self.output_pass.record(
+            &mut builder,
+            image_num,
+            attachments,
+            shown.as_ref(),
+            &self.viewport,
+        );
+        attachments.end(&mut builder);
+        let hdr_capture = (std::mem::take(&mut self.hdr_capture_requested)
+            && attachments.framebuffer.is_none())
+        .then(|| {
+            trail.push("copy HDR image to host");
+            self.copy_hdr_to_host(&mut builder, shown.as_ref().unwrap_or(&attachments.scene))
+        });
+        if let Some(query_pool) = &self.statistics_queries {
+            builder
+                .end_query(query_pool.clone(), frame_index as u32)
+                .unwrap();
+        }
+        if let Some(query_pool) = &self.timestamps {
+            unsafe {
+                builder
+                    .write_timestamp(
+                        query_pool.clone(),
+                        2 * frame_index as u32 + 1,
+                        PipelineStage::BottomOfPipe,
+                    )
+                    .unwrap();
+            }
+            #[cfg(feature = "profile-tracy")]
+            if let Some(gpu_profiler) = self.gpu_profiler.as_mut() {
+                gpu_profiler.end(frame_index);
+            }
+        }
+        let capture = std::mem::take(&mut self.capture_requested).then(|| {
+            trail.push("copy to host");
+            self.copy_to_host(&mut builder, image_num)
+        });
+        let focus_probe = self
+            .g_buffer
+            .as_ref()
+            .filter(|_| self.depth_of_field.is_some())
+            .zip(self.focus_requested.take())
+            .map(|(g_buffer, cursor)| {
+                trail.push("copy focus depth to host");
+                let [width, height] = self.viewport.dimensions;
+                let pixel = [cursor[0] * width, cursor[1] * height].map(|x| x.max(0.0) as u32);
+                g_buffer.copy_depth_to_host(&mut builder, pixel)
+            });
+        if let Some(breadcrumbs) = self.breadcrumbs.as_mut() {
+            breadcrumbs.recorded(frame_index, trail);
+        }
+        let uploads = FrameUploads {
+            capture,
+            hdr_capture,
+            focus_probe,
+            _tile_chunks: tile_chunks,
+            _instances: instance_buffer,
+            _cluster_bounds: cluster_bounds,
+            _particles: particle_buffer.map(|(buffer, _)| buffer),
+            _debug_lines: debug_lines,
+        };
+        (Arc::new(builder.build().unwrap()), uploads)
+    }
+
+    /// Copies swapchain image `image_num`, once the frame is drawn into it, to a
+    /// buffer the host reads when the frame is done.
+    fn copy_to_host(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image_num: usize,
+    ) -> CapturedFrame {
+        let image = self.attachments[image_num].output.image();
+        let [width, height, _] = image.dimensions().width_height_depth();
+        let buffer = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::transfer_dst(),
+            false,
+            (0..width * height * 4).map(|_| 0u8),
+        )
+        .unwrap();
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                image.clone(),
+                buffer.clone(),
+            ))
+            .unwrap();
+        CapturedFrame {
+            buffer,
+            extent: [width, height],
+            byte_order: capture_byte_order(image.format()).unwrap_or(ByteOrder::Rgba),
+        }
+    }
+
+    /// Copies `image`, an HDR image the frame draws, to a buffer the host reads when
+    /// the frame is done.
+    fn copy_hdr_to_host(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image: &Arc<ImageView<AttachmentImage>>,
+    ) -> HdrFrame {
+        let [width, height, _] = image.image().dimensions().width_height_depth();
+        let buffer = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::transfer_dst(),
+            false,
+            (0..width * height * 4).map(|_| 0u16),
+        )
+        .unwrap();
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                image.image().clone(),
+                buffer.clone(),
+            ))
+            .unwrap();
+        HdrFrame {
+            buffer,
+            extent: [width, height],
+        }
+    }
+
+    /// Reads back the timestamps written by the frame in `frame_index`, whose fence
+    /// has signalled.
+    fn read_timestamps(&self, frame_index: usize) -> Option<[u64; 2]> {
+        let query_pool = self.timestamps.as_ref()?;
+        let first = 2 * frame_index as u32;
+        let mut timestamps = [0u64; 2];
+        match query_pool
+            .queries_range(first..first + 2)
+            .unwrap()
+            .get_results(&mut timestamps, QueryResultFlags::default())
+        {
+            Ok(true) => {}
+            Ok(false) => return None,
+            Err(e) => {
+                warn!(error = ?e, "failed to read timestamp queries");
+                return None;
+            }
+        }
+        Some(timestamps)
+    }
+
+    /// The time between a frame's `timestamps`.
+    fn gpu_time(&self, timestamps: [u64; 2]) -> Option<Duration> {
+        let valid_bits = self.queue.family().timestamp_valid_bits()?;
+        let mask = if valid_bits >= 64 {
+            u64::MAX
+        } else {
+            (1 << valid_bits) - 1
+        };
+        let ticks = timestamps[1].wrapping_sub(timestamps[0]) & mask;
+        let period_ns = self.device.physical_device().properties().timestamp_period as f64;
+        Some(Duration::from_secs_f64(ticks as f64 * period_ns / 1e9))
+    }
+
+    /// Reads back the pipeline statistics counted by the frame in `frame_index`,
+    /// whose fence has signalled.
+    fn read_pipeline_statistics(&self, frame_index: usize) -> Option<PipelineStatistics> {
+        let query_pool = self.statistics_queries.as_ref()?;
+        let query = frame_index as u32;
+        // One value per enabled statistic, in the order of their flag bits.
+        let mut counts = [0u64; 3];
+        match query_pool
+            .queries_range(query..query + 1)
+            .unwrap()
+            .get_results(&mut counts, QueryResultFlags::default())
+        {
+            Ok(true) => Some(PipelineStatistics {
+                vertex_shader_invocations: counts[0],
+                clipping_primitives: counts[1],
+                fragment_shader_invocations: counts[2],
+            }),
+            Ok(false) => None,
+            Err(e) => {
+                warn!(error = ?e, "failed to read pipeline statistics query");
+                None
+            }
+        }
+    }
+
+    /// Rebuilds everything that refers to the attachments after they were recreated.
+    fn attachments_changed(&mut self) {
+        self.output_pass.update_descriptor_sets(&self.attachments);
+        if let Some(stereo) = self.stereo.as_mut() {
+            stereo.resize(
+                &self.device,
+                self.viewport.dimensions,
+                &mut self.memory_stats,
+            );
+        }
+        if let Some(g_buffer) = self.g_buffer.as_mut() {
+            g_buffer.resize(
+                &self.device,
+                self.viewport.dimensions,
+                &mut self.memory_stats,
+            );
+        }
+        if let Some(taa) = self.taa.as_mut() {
+            taa.resize(
+                &self.device,
+                self.viewport.dimensions.map(|x| x as u32),
+                &mut self.memory_stats,
+            );
+        }
+        if let Some(depth_of_field) = self.depth_of_field.as_mut() {
+            depth_of_field.resize(
+                &self.device,
+                self.viewport.dimensions.map(|x| x as u32),
+                &mut self.memory_stats,
+            );
+        }
+        if let Some(motion_blur) = self.motion_blur.as_mut() {
+            motion_blur.resize(
+                &self.device,
+                self.viewport.dimensions.map(|x| x as u32),
+                &mut self.memory_stats,
+            );
+        }
+        for style in &mut self.styles {
+            style.resize(
+                &self.device,
+                self.viewport.dimensions.map(|x| x as u32),
+                &mut self.memory_stats,
+            );
+        }
+        self.record_prerecorded_commands();
+    }
+
+    /// Resizes the pre-recorded instance buffers when `instance_count` instances don't
+    /// fit, doubling them, or fill less than a quarter, halving them, as every slot is
+    /// drawn whether it's used or not. Command buffers still in flight keep the old
+    /// buffers alive.
+    fn fit_prerecorded_instances(&mut self, instance_count: u32) {
+        let capacity = if instance_count > self.instance_count {
+            instance_count.next_power_of_two()
+        } else if instance_count < self.instance_count / 4 {
+            self.instance_count / 2
+        } else {
+            return;
+        };
+        let prerecorded = match self.prerecorded.as_mut() {
+            Some(prerecorded) => prerecorded,
+            None => return,
+        };
+        let uniforms_size = size_of::<FrameUniforms>() as DeviceSize;
+        let instances_size = (self.instance_count as usize
+            * (size_of::<InstanceData>() + size_of::<[f32; 4]>()))
+            as DeviceSize;
+        for _ in prerecorded.images.drain(..) {
+            self.memory_stats
+                .untrack(AllocationPurpose::Uniform, uniforms_size);
+            self.memory_stats
+                .untrack(AllocationPurpose::Instance, instances_size);
+        }
+        debug!(
+            from = self.instance_count,
+            to = capacity,
+            "resizing pre-recorded instance buffers"
+        );
+        self.instance_count = capacity;
+        self.record_prerecorded_commands();
+    }
+
+    /// (Re-)records the pre-recorded command buffers against the current attachments.
+    fn record_prerecorded_commands(&mut self) {
+        let prerecorded = match self.prerecorded.as_mut() {
+            Some(prerecorded) => prerecorded,
+            None => return,
+        };
+
+        let uniforms_size = size_of::<FrameUniforms>() as DeviceSize;
+        let instances_size = (self.instance_count as usize
+            * (size_of::<InstanceData>() + size_of::<[f32; 4]>()))
+            as DeviceSize;
+        while prerecorded.images.len() > self.attachments.len() {
+            prerecorded.images.pop();
+            self.memory_stats
+                .untrack(AllocationPurpose::Uniform, uniforms_size);
+            self.memory_stats
+                .untrack(AllocationPurpose::Instance, instances_size);
+        }
+        let layout = prerecorded.pipeline.layout().set_layouts()[0].clone();
+
+        for (image_num, attachments) in self.attachments.iter().enumerate() {
+            let (uniforms, instances, colors) = match prerecorded.images.get(image_num) {
+                Some(image) => (
+                    image.uniforms.clone(),
+                    image.instances.clone(),
+                    image.colors.clone(),
+                ),
+                None => {
+                    self.memory_stats
+                        .track(AllocationPurpose::Uniform, uniforms_size);
+                    self.memory_stats
+                        .track(AllocationPurpose::Instance, instances_size);
+                    (
+                        CpuAccessibleBuffer::from_data(
+                            self.device.clone(),
+                            BufferUsage::uniform_buffer(),
+                            false,
+                            FrameUniforms::zeroed(),
+                        )
+                        .unwrap(),
+                        CpuAccessibleBuffer::from_iter(
+                            self.device.clone(),
+                            BufferUsage::vertex_buffer(),
+                            false,
+                            (0..self.instance_count).map(|_| InstanceData::default()),
+                        )
+                        .unwrap(),
+                        CpuAccessibleBuffer::from_iter(
+                            self.device.clone(),
+                            BufferUsage::storage_buffer(),
+                            false,
+                            (0..self.instance_count).map(|_| [0.0; 4]),
+                        )
+                        .unwrap(),
+                    )
+                }
+            };
+            let descriptor_set = PersistentDescriptorSet::new(
+                layout.clone(),
+                [
+                    WriteDescriptorSet::buffer(0, uniforms.clone()),
+                    WriteDescriptorSet::buffer(1, colors.clone()),
+                ],
+            )
+            .unwrap();
+
+            let mut builder = AutoCommandBufferBuilder::primary(
+                self.device.clone(),
+                self.queue.family(),
+                CommandBufferUsage::MultipleSubmit,
+            )
+            .unwrap();
+            attachments.begin_scene(&mut builder, self.background_color, SubpassContents::Inline);
+            builder
+                .set_viewport(0, [self.viewport.clone()])
+                .bind_pipeline_graphics(prerecorded.pipeline.clone())
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    prerecorded.pipeline.layout().clone(),
+                    0,
+                    descriptor_set,
+                );
+            self.meshes[0].bind(&mut builder, &instances);
+            builder
+                .draw_indexed(self.meshes[0].index_count(), self.instance_count, 0, 0, 0)
+                .unwrap();
+            attachments.begin_output(&mut builder, None);
+            self.output_pass
+                .record(&mut builder, image_num, attachments, None, &self.viewport);
+            attachments.end(&mut builder);
+            let command_buffer = Arc::new(builder.build().unwrap());
+
+            match prerecorded.images.get_mut(image_num) {
+                Some(image) => image.command_buffer = command_buffer,
+                None => prerecorded.images.push(PrerecordedImage {
+                    uniforms,
+                    instances,
+                    colors,
+                    command_buffer,
+                    fence: None,
+                }),
+            }
+        }
+        debug!(
+            images = prerecorded.images.len(),
+            "recorded command buffers"
+        );
+    }
+}
+
+/// A shader preset's pipeline, standing in for the main material's.
+struct ActivePreset {
+    /// Into [`SHADER_PRESETS`].
+    index: usize,
+    pipeline: PipelineVariants,
+}
+
+impl ActivePreset {
+    fn new(
+        device: &Arc<Device>,
+        target: &RenderTarget,
+        vertex_format: VertexFormat,
+        topologies: &[PrimitiveTopology],
+        index: usize,
+    ) -> Result<Self, GraphicsPipelineCreationError> {
+        Ok(ActivePreset {
+            index,
+            pipeline: PipelineVariants::new(topologies, |topology| {
+                presets::create_pipeline(
+                    device,
+                    target,
+                    vertex_format,
+                    topology,
+                    &SHADER_PRESETS[index],
+                )
+            })?,
+        })
+    }
+}
+
+/// What every draw binds, shared by reference with the recording threads.
+struct DrawInputs<'a> {
+    /// By [`MaterialId`]; ids past the end draw with the first.
+    pipelines: &'a [PipelineVariants],
+    /// By [`MeshId`].
+    meshes: &'a [MeshBuffer],
+    batches: &'a [DrawBatch],
+    viewport: &'a Viewport,
+    instance_buffer: &'a FrameChunk<InstanceData>,
+    /// The instances' colors, bound as [`COLOR_SET`].
+    colors: &'a Arc<PersistentDescriptorSet>,
+    push_constants: vertex_shader::ty::PushConstantData,
+    /// Pushed instead of `push_constants` for the main material's pipeline while a
+    /// shader preset replaces it, as the words of the preset's block.
+    main_push_constants: Option<&'a [u32]>,
+    /// Bound as set 0 to the pipelines that have one.
+    textures: &'a Arc<PersistentDescriptorSet>,
+    /// Bound as set 1 to the pipelines that have one.
+    lights: Option<&'a Arc<PersistentDescriptorSet>>,
+    /// Draws the instances as points instead of the batches' pipelines and meshes.
+    point_sprites: Option<&'a PointSprites>,
+}
+
+impl DrawInputs<'_> {
+    /// Draws the parts of the batches within `instances`, binding a pipeline or mesh
+    /// only when it changes. Secondary command buffers don't inherit dynamic state,
+    /// so everything is set every time.
+    fn record<L, P>(&self, builder: &mut AutoCommandBufferBuilder<L, P>, instances: Range<u32>) {
+        if let Some(point_sprites) = self.point_sprites {
+            point_sprites.draw(
+                builder,
+                self.viewport,
+                self.instance_buffer,
+                self.colors,
+                instances,
+            );
+            return;
+        }
+        builder.set_viewport(0, [self.viewport.clone()]);
+        let mut bound_pipeline = None;
+        let mut bound_mesh = None;
+        for batch in self.batches {
+            let start = max(batch.instances.start, instances.start);
+            let end = min(batch.instances.end, instances.end);
+            if start >= end {
+                continue;
+            }
+            // Ids from a previous device, e.g. after it was lost, fall back to the
+            // main material and mesh.
+            let material = Some(batch.material.0)
+                .filter(|&index| index < self.pipelines.len())
+                .unwrap_or(0);
+            let mesh = Some(batch.mesh.0)
+                .filter(|&index| index < self.meshes.len())
+                .unwrap_or(0);
+            let vertex_buffer = &self.meshes[mesh];
+            let topology = vertex_buffer.topology();
+            let pipeline = self.pipelines[material].get(topology);
+            if bound_pipeline != Some((material, topology)) {
+                builder.bind_pipeline_graphics(pipeline.clone());
+                match self.main_push_constants.filter(|_| material == 0) {
+                    Some(words) => presets::push_words(builder, pipeline.layout(), words),
+                    None => {
+                        builder.push_constants(pipeline.layout().clone(), 0, self.push_constants);
+                    }
+                }
+                let set_layouts = pipeline.layout().set_layouts();
+                // A material reading only the lights has an empty set 0.
+                if set_layouts
+                    .first()
+                    .is_some_and(|set_layout| !set_layout.bindings().is_empty())
+                {
+                    builder.bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        pipeline.layout().clone(),
+                        0,
+                        self.textures.clone(),
+                    );
+                }
+                let reads_lights = set_layouts
+                    .get(1)
+                    .is_some_and(|set_layout| !set_layout.bindings().is_empty());
+                if let Some(lights) = self.lights.filter(|_| reads_lights) {
+                    builder.bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        pipeline.layout().clone(),
+                        1,
+                        lights.clone(),
+                    );
+                }
+                // Every vertex shader drawing instances reads their colors.
+                builder.bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    pipeline.layout().clone(),
+                    COLOR_SET,
+                    self.colors.clone(),
+                );
+                bound_pipeline = Some((material, topology));
+            }
+            if bound_mesh != Some(mesh) {
+                vertex_buffer.bind(builder, self.instance_buffer);
+                bound_mesh = Some(mesh);
+            }
+            let lod = vertex_buffer.lod(batch.lod);
+            builder
+                .draw_indexed(lod.end - lod.start, end - start, lod.start, 0, start)
+                .unwrap();
+        }
+    }
+}
+
+/// The debug overlay's lines for one frame, as the debug line pipeline draws them.
+struct DebugLineInputs<'a> {
+    pipeline: &'a Arc<GraphicsPipeline>,
+    viewport: &'a Viewport,
+    vertices: &'a FrameChunk<Vertex>,
+}
+
+impl DebugLineInputs<'_> {
+    fn record<L, P>(&self, builder: &mut AutoCommandBufferBuilder<L, P>) {
+        builder
+            .set_viewport(0, [self.viewport.clone()])
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .bind_vertex_buffers(0, self.vertices.clone())
+            .draw(self.vertices.len() as u32, 1, 0, 0)
+            .unwrap();
+    }
+}
+
+/// The parts of `visible` within `bucket`.
+fn clip_ranges(bucket: &Range<u32>, visible: &[Range<u32>]) -> Vec<Range<u32>> {
+    visible
+        .iter()
+        .map(|range| max(range.start, bucket.start)..min(range.end, bucket.end))
+        .filter(|range| range.start < range.end)
+        .collect()
+}
+
+/// Splits the instances into at most `buckets` contiguous, similarly sized ranges.
+fn instance_buckets(instance_count: u32, buckets: usize) -> Vec<Range<u32>> {
+    let bucket_size = instance_count.div_ceil(buckets as u32).max(1);
+    (0..instance_count)
+        .step_by(bucket_size as usize)
+        .map(|start| start..min(start + bucket_size, instance_count))
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_swapchain(
+    device: &Arc<Device>,
+    surface: &Arc<WindowSurface>,
+    swapchain_buffers_count: u32,
+    display_output: DisplayOutput,
+    surface_format_policy: &SurfaceFormatPolicy,
+    uncapped_present: bool,
+    transparent: bool,
+    capturable: bool,
+) -> Result<(Arc<WindowSwapchain>, Vec<Arc<WindowImage>>), RendererCreationError> {
+    let physical_device = device.physical_device();
+    let surface_capabilities = physical_device
+        .surface_capabilities(surface, Default::default())
+        .map_err(RendererCreationError::SurfaceProperties)?;
+
+    info!(
+        requested = swapchain_buffers_count,
+        max = surface_capabilities.max_image_count.unwrap_or(0),
+        "swapchain buffers count"
+    );
+
+    let (image_format, image_color_space) = hdr::select_surface_format(
+        surface,
+        &physical_device
+            .surface_formats(surface, Default::default())
+            .map_err(RendererCreationError::SurfaceProperties)?,
+        display_output,
+        surface_format_policy,
+    );
+
+    let present_mode = if uncapped_present {
+        let supported: Vec<_> = physical_device
+            .surface_present_modes(surface)
+            .map_err(RendererCreationError::SurfaceProperties)?
+            .collect();
+        [PresentMode::Immediate, PresentMode::Mailbox]
+            .into_iter()
+            .find(|mode| supported.contains(mode))
+            .unwrap_or(PresentMode::Fifo)
+    } else {
+        PresentMode::Fifo
+    };
+    info!(?present_mode, "present mode");
+
+    let min_image_count = match surface_capabilities.max_image_count {
+        None => max(
+            swapchain_buffers_count,
+            surface_capabilities.min_image_count,
+        ),
+        Some(limit) => min(
+            max(
+                swapchain_buffers_count,
+                surface_capabilities.min_image_count,
+            ),
+            limit,
+        ),
+    };
+
+    Swapchain::new(
+        device.clone(),
+        surface.clone(),
+        SwapchainCreateInfo {
+            min_image_count,
+            image_format: Some(image_format),
+            image_color_space,
+            image_extent: surface.window().inner_size().into(),
+            image_usage: ImageUsage {
+                color_attachment: true,
+                transfer_src: capturable && surface_capabilities.supported_usage_flags.transfer_src,
+                ..ImageUsage::none()
+            },
+            present_mode,
+            composite_alpha: select_composite_alpha(
+                surface_capabilities.supported_composite_alpha,
+                transparent,
+            ),
+            ..Default::default()
+        },
+    )
+    .map_err(RendererCreationError::Swapchain)
+}
+
+/// Opaque swapchain images can use any mode that ignores their alpha. Not every
+/// surface has `Opaque`; Android's often only has `Inherit`, leaving it to the
+/// compositor. The blended modes come last, for surfaces with nothing else.
+///
+/// Transparent ones need a blended mode instead. Which surfaces have one varies:
+/// Wayland and macOS have `PreMultiplied`, X11 has it only for windows with an alpha
+/// visual, and Windows drivers often offer `Opaque` alone. `Inherit` leaves it to
+/// the window system, which may or may not blend.
+fn select_composite_alpha(supported: SupportedCompositeAlpha, transparent: bool) -> CompositeAlpha {
+    let preference = if transparent {
+        [
+            CompositeAlpha::PreMultiplied,
+            CompositeAlpha::PostMultiplied,
+            CompositeAlpha::Inherit,
+            CompositeAlpha::Opaque,
+        ]
+    } else {
+        [
+            CompositeAlpha::Opaque,
+            CompositeAlpha::Inherit,
+            CompositeAlpha::PreMultiplied,
+            CompositeAlpha::PostMultiplied,
+        ]
+    };
+    let composite_alpha = preference
+        .into_iter()
+        .find(|&mode| supported.supports(mode))
+        .expect("surface supports no composite alpha mode");
+    if transparent
+        && !matches!(
+            composite_alpha,
+            CompositeAlpha::PreMultiplied | CompositeAlpha::PostMultiplied
+        )
+    {
+        warn!(
+            ?composite_alpha,
+            "surface has no blended composite alpha, the window may not be transparent"
+        );
+    }
+    composite_alpha
+}
+
+/// Whether the output pass has to pre-multiply the alpha of `swapchain`'s images.
+fn premultiplies(swapchain: &WindowSwapchain) -> u32 {
+    (swapchain.composite_alpha() == CompositeAlpha::PreMultiplied) as u32
+}
+
+/// The scene is drawn into an intermediate attachment in the first subpass, which
+/// the output pass then reads to write the swapchain image.
+fn pipeline_statistic_flags() -> QueryPipelineStatisticFlags {
+    QueryPipelineStatisticFlags {
+        vertex_shader_invocations: true,
+        clipping_primitives: true,
+        fragment_shader_invocations: true,
+        ..QueryPipelineStatisticFlags::none()
+    }
+}
+
+/// `depth_prepass` makes the pipeline shade only fragments whose depth matches what
+/// the pre-pass wrote.
+fn create_pipeline(
+    device: &Arc<Device>,
+    target: &RenderTarget,
+    vertex_format: VertexFormat,
+    fragment_shader: &Arc<ShaderModule>,
+    depth_prepass: bool,
+    rasterizer: &RasterizerSettings,
+    topology: PrimitiveTopology,
+) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    let depth_stencil_state = if depth_prepass {
+        DepthStencilState {
+            depth: Some(DepthState {
+                enable_dynamic: false,
+                write_enable: StateMode::Fixed(false),
+                compare_op: StateMode::Fixed(CompareOp::Equal),
+            }),
+            ..DepthStencilState::disabled()
+        }
+    } else {
+        DepthStencilState::disabled()
+    };
+    build_pipeline(
+        device,
+        target,
+        vertex_format,
+        &loaded_vertex_shader,
+        fragment_shader,
+        topology,
+        ColorBlendState::new(1),
+        depth_stencil_state,
+        rasterizer.mesh_state(device),
+    )
+}
+
+fn create_depth_pipeline(
+    device: &Arc<Device>,
+    target: &RenderTarget,
+    vertex_format: VertexFormat,
+    rasterizer: &RasterizerSettings,
+    topology: PrimitiveTopology,
+) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = fragment_shader::load(device.clone()).unwrap();
+    build_pipeline(
+        device,
+        target,
+        vertex_format,
+        &loaded_vertex_shader,
+        &loaded_fragment_shader,
+        topology,
+        ColorBlendState::new(1).color_write_mask(ColorComponents::none()),
+        DepthStencilState::simple_depth_test(),
+        rasterizer.mesh_state(device),
+    )
+}
+
+fn create_particle_pipeline(
+    device: &Arc<Device>,
+    target: &RenderTarget,
+    vertex_format: VertexFormat,
+    fragment_shader: &Arc<ShaderModule>,
+) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    build_pipeline(
+        device,
+        target,
+        vertex_format,
+        &loaded_vertex_shader,
+        fragment_shader,
+        PrimitiveTopology::TriangleList,
+        ColorBlendState::new(1).blend(AttachmentBlend::additive()),
+        DepthStencilState::disabled(),
+        RasterizationState::new(),
+    )
+}
+
+fn create_static_pipeline(
+    device: &Arc<Device>,
+    target: &RenderTarget,
+    vertex_format: VertexFormat,
+    fragment_shader: &Arc<ShaderModule>,
+) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+    let loaded_vertex_shader = static_vertex_shader::load(device.clone()).unwrap();
+    build_pipeline(
+        device,
+        target,
+        vertex_format,
+        &loaded_vertex_shader,
+        fragment_shader,
+        PrimitiveTopology::TriangleList,
+        ColorBlendState::new(1),
+        DepthStencilState::disabled(),
+        RasterizationState::new(),
+    )
+}
+
+/// Whether `effect` can be recorded between the scene and the output pass, warning
+/// if not: rendering to a render pass, the output pass reads the scene through an
+/// input attachment, without the neighboring pixels the effects need.
+fn supports_post_effect(target: &RenderTarget, effect: &str) -> bool {
+    let supported = matches!(target, RenderTarget::Dynamic { .. });
+    if !supported {
+        warn!("{} needs dynamic rendering, leaving it off", effect);
+    }
+    supported
+}
+
+fn create_taa(
+    device: &Arc<Device>,
+    target: &RenderTarget,
+    viewport: &Viewport,
+    memory_stats: &mut MemoryStats,
+) -> Option<Taa> {
+    supports_post_effect(target, "temporal anti-aliasing")
+        .then(|| Taa::new(device, viewport.dimensions.map(|x| x as u32), memory_stats))
+}
+
+fn create_depth_of_field(
+    device: &Arc<Device>,
+    target: &RenderTarget,
+    viewport: &Viewport,
+    memory_stats: &mut MemoryStats,
+) -> Option<DepthOfField> {
+    supports_post_effect(target, "depth of field")
+        .then(|| DepthOfField::new(device, viewport.dimensions.map(|x| x as u32), memory_stats))
+}
+
+fn create_motion_blur(
+    device: &Arc<Device>,
+    target: &RenderTarget,
+    settings: MotionBlurSettings,
+    viewport: &Viewport,
+    memory_stats: &mut MemoryStats,
+) -> Option<MotionBlur> {
+    supports_post_effect(target, "motion blur").then(|| {
+        MotionBlur::new(
+            device,
+            settings,
+            viewport.dimensions.map(|x| x as u32),
+            memory_stats,
+        )
+    })
+}
+
+fn create_debug_line_pipeline(
+    device: &Arc<Device>,
+    target: &RenderTarget,
+    rasterizer: &RasterizerSettings,
+) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+    let loaded_vertex_shader = debug_line_vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = fragment_shader::load(device.clone()).unwrap();
+    let topology = match rasterizer.native_line_width(device) {
+        Some(_) => PrimitiveTopology::LineList,
+        None => {
+            info!(
+                line_width = rasterizer.line_width,
+                "wide lines aren't supported, expanding debug lines into quads"
+            );
+            PrimitiveTopology::TriangleList
+        }
+    };
+
+    GraphicsPipeline::start()
+        .render_pass(target.scene())
+        .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
+        .input_assembly_state(InputAssemblyState::new().topology(topology))
+        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+        .rasterization_state(rasterizer.line_state(device))
+        .build(device.clone())
+}
+
+fn create_output_pipeline(
+    device: &Arc<Device>,
+    target: &RenderTarget,
+) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+    let loaded_vertex_shader = output_vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = match target {
+        RenderTarget::RenderPass(_) => output_fragment_shader::load(device.clone()),
+        RenderTarget::Dynamic { .. } => sampled_output_fragment_shader::load(device.clone()),
+    }
+    .unwrap();
+
+    GraphicsPipeline::start()
+        .render_pass(target.output())
+        .vertex_input_state(BuffersDefinition::new())
+        .input_assembly_state(InputAssemblyState::new())
+        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+        .with_auto_layout(device.clone(), push_descriptor_layout(device))
+}
+
+/// For `with_auto_layout`: makes set 0 a push descriptor set when `device` has
+/// `khr_push_descriptor` enabled, so its descriptors are pushed while recording
+/// instead of allocated in descriptor sets.
+fn push_descriptor_layout(
+    device: &Arc<Device>,
+) -> impl FnOnce(&mut [DescriptorSetLayoutCreateInfo]) {
+    let push_descriptor = device.enabled_extensions().khr_push_descriptor;
+    move |set_layouts| {
+        if let Some(set_layout) = set_layouts.first_mut() {
+            set_layout.push_descriptor = push_descriptor;
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_pipeline(
+    device: &Arc<Device>,
+    target: &RenderTarget,
+    vertex_format: VertexFormat,
+    loaded_vertex_shader: &Arc<ShaderModule>,
+    loaded_fragment_shader: &Arc<ShaderModule>,
+    topology: PrimitiveTopology,
+    color_blend_state: ColorBlendState,
+    depth_stencil_state: DepthStencilState,
+    rasterization_state: RasterizationState,
+) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+    let vertex_entry_point = loaded_vertex_shader.entry_point("main").unwrap();
+    if let Err(mismatches) = reflection::check_vertex_input(&vertex_entry_point) {
+        panic!(
+            "vertex shader inputs don't match the vertex buffers:\n{}",
+            mismatches
+        );
+    }
+    GraphicsPipeline::start()
+        .render_pass(target.scene())
+        .vertex_input_state(MeshVertices::<InstanceData>::new(vertex_format))
+        .input_assembly_state(InputAssemblyState::new().topology(topology))
+        .vertex_shader(vertex_entry_point, ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+        .color_blend_state(color_blend_state)
+        .depth_stencil_state(depth_stencil_state)
+        .rasterization_state(rasterization_state)
+        .with_auto_layout(device.clone(), textures::adjust_layout)
+}
+
+/// `mesh` with its topology replaced by `topology`, if any.
+fn override_topology(mesh: Mesh, topology: Option<Topology>) -> Mesh {
+    match topology {
+        Some(topology) => mesh.with_topology(topology),
+        None => mesh,
+    }
+}
+
+fn pipeline_error(error: GraphicsPipelineCreationError) -> String {
+    format!("shader doesn't fit the pipeline: {}", error)
+}
+
+/// Transient, so tiled GPUs can keep it in on-chip memory.
+fn depth_attachment(
+    device: &Arc<Device>,
+    dimensions: [u32; 2],
+    format: Format,
+) -> Arc<ImageView<AttachmentImage>> {
+    ImageView::new_default(AttachmentImage::transient(device.clone(), dimensions, format).unwrap())
+        .unwrap()
+}
+
+fn window_size_dependent_setup(
+    device: &Arc<Device>,
+    images: &[Arc<WindowImage>],
+    target: &RenderTarget,
+    viewport: &mut Viewport,
+) -> Vec<ImageAttachments> {
+    let dimensions = images[0].dimensions().width_height();
+    viewport.dimensions = [dimensions[0] as f32, dimensions[1] as f32];
+
+    images
+        .iter()
+        .map(|image| {
+            let view = ImageView::new_default(image.clone()).unwrap();
+            ImageAttachments::new(device, target, view, dimensions)
+        })
+        .collect::<Vec<_>>()
+}
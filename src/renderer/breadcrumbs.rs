@@ -0,0 +1,84 @@
+use std::fmt::Write;
+
+/// The passes and draws each frame in flight recorded, in order, kept until the
+/// frame's slot is reused. When the device is lost, they tell what the GPU was
+/// working on, though not how far it got: any frame submitted may be the one that
+/// hung.
+pub struct Breadcrumbs {
+    /// By frame in flight.
+    frames: Vec<FrameTrail>,
+    /// Frames recorded so far, numbering them.
+    recorded: u64,
+}
+
+#[derive(Default)]
+struct FrameTrail {
+    number: u64,
+    labels: Vec<String>,
+    submitted: bool,
+}
+
+/// Labels of one frame's commands as they're recorded; it keeps nothing when the
+/// renderer has no breadcrumbs.
+pub struct Trail(Option<Vec<String>>);
+
+impl Trail {
+    pub fn new(enabled: bool) -> Self {
+        Trail(enabled.then(Vec::new))
+    }
+
+    pub fn push(&mut self, label: impl Into<String>) {
+        if let Some(labels) = &mut self.0 {
+            labels.push(label.into());
+        }
+    }
+}
+
+impl Breadcrumbs {
+    pub fn new(frames_in_flight: usize) -> Self {
+        Breadcrumbs {
+            frames: (0..frames_in_flight)
+                .map(|_| FrameTrail::default())
+                .collect(),
+            recorded: 0,
+        }
+    }
+
+    /// Keeps `trail` as what the frame in `frame_index` is about to submit.
+    pub fn recorded(&mut self, frame_index: usize, trail: Trail) {
+        self.recorded += 1;
+        self.frames[frame_index] = FrameTrail {
+            number: self.recorded,
+            labels: trail.0.unwrap_or_default(),
+            submitted: false,
+        };
+    }
+
+    /// The frame in `frame_index` was submitted.
+    pub fn submitted(&mut self, frame_index: usize) {
+        self.frames[frame_index].submitted = true;
+    }
+
+    /// The frames in flight, oldest first, and what each recorded.
+    pub fn report(&self) -> String {
+        let mut frames: Vec<_> = self
+            .frames
+            .iter()
+            .filter(|frame| frame.number > 0)
+            .collect();
+        frames.sort_by_key(|frame| frame.number);
+        let mut report = String::new();
+        for frame in frames {
+            let state = if frame.submitted {
+                "submitted"
+            } else {
+                "recorded"
+            };
+            writeln!(report, "frame {} ({}):", frame.number, state).unwrap();
+            for label in &frame.labels {
+                writeln!(report, "    {}", label).unwrap();
+            }
+        }
+        report
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{
+    lut::ColorLut,
+    memory::{AllocationPurpose, MemoryStats},
+};
+use half::f16;
+use std::sync::Arc;
+use vulkano::{
+    descriptor_set::WriteDescriptorSet,
+    device::{Device, Queue},
+    format::Format,
+    image::{view::ImageView, ImageDimensions, ImmutableImage, MipmapsCount},
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    sync::GpuFuture,
+    DeviceSize,
+};
+
+/// The 3D LUT the output pass grades the scene with before encoding it for the
+/// display, as a half float image sampled with trilinear filtering. Without a LUT
+/// a tiny identity one stays bound and grading is skipped.
+pub struct ColorGrading {
+    view: Arc<ImageView<ImmutableImage>>,
+    sampler: Arc<Sampler>,
+    enabled: bool,
+    /// Bytes the image takes, for untracking it once it's replaced.
+    size: DeviceSize,
+}
+
+impl ColorGrading {
+    pub fn new(
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        lut: Option<&ColorLut>,
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let identity = ColorLut::identity(2);
+        let (view, size) = upload(queue, lut.unwrap_or(&identity), memory_stats);
+        ColorGrading {
+            view,
+            sampler,
+            enabled: lut.is_some(),
+            size,
+        }
+    }
+
+    /// Grades with `lut` from now on, or not at all. Waits for the upload.
+    pub fn set(
+        &mut self,
+        queue: &Arc<Queue>,
+        lut: Option<&ColorLut>,
+        memory_stats: &mut MemoryStats,
+    ) {
+        memory_stats.untrack(AllocationPurpose::Texture, self.size);
+        let identity = ColorLut::identity(2);
+        (self.view, self.size) = upload(queue, lut.unwrap_or(&identity), memory_stats);
+        self.enabled = lut.is_some();
+    }
+
+    /// For `OutputParams::grade`.
+    pub fn enabled(&self) -> u32 {
+        self.enabled as u32
+    }
+
+    pub fn write(&self, binding: u32) -> WriteDescriptorSet {
+        WriteDescriptorSet::image_view_sampler(binding, self.view.clone(), self.sampler.clone())
+    }
+}
+
+fn upload(
+    queue: &Arc<Queue>,
+    lut: &ColorLut,
+    memory_stats: &mut MemoryStats,
+) -> (Arc<ImageView<ImmutableImage>>, DeviceSize) {
+    let texels: Vec<u16> = lut
+        .texels
+        .iter()
+        .flat_map(|&[r, g, b]| [r, g, b, 1.0].map(|x| f16::from_f32(x).to_bits()))
+        .collect();
+    let size = (texels.len() * 2) as DeviceSize;
+    let (image, upload) = ImmutableImage::from_iter(
+        texels,
+        ImageDimensions::Dim3d {
+            width: lut.size,
+            height: lut.size,
+            depth: lut.size,
+        },
+        MipmapsCount::One,
+        Format::R16G16B16A16_SFLOAT,
+        queue.clone(),
+    )
+    .unwrap();
+    upload
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+    memory_stats.track(AllocationPurpose::Texture, size);
+    (ImageView::new_default(image).unwrap(), size)
+}
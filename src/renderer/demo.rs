@@ -0,0 +1,682 @@
+use super::{
+    life::LifeDemo,
+    nbody::NBodyDemo,
+    pipeline_error,
+    terrain::{Heightmap, TerrainDemo},
+    vegetation::VegetationDemo,
+    MeshBuffer, RenderTarget, VertexFormat,
+};
+use crate::memory::MemoryStats;
+use bytemuck::{Pod, Zeroable};
+use std::sync::Arc;
+use tracing::warn;
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, SecondaryAutoCommandBuffer,
+    },
+    device::{physical::QueueFamily, Device},
+    pipeline::{
+        graphics::{
+            input_assembly::InputAssemblyState,
+            vertex_input::BuffersDefinition,
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline,
+    },
+    shader::ShaderModule,
+};
+
+/// A showcase picked with `--demo` or `--shadertoy`, or cycled to with Ctrl+Tab.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DemoKind {
+    /// The Mandelbrot set, panned by dragging and zoomed by scrolling.
+    Mandelbrot,
+    /// A shader written for ShaderToy, set with
+    /// [`Renderer::set_shadertoy_shader`](super::Renderer::set_shadertoy_shader).
+    ShaderToy,
+    /// Bodies pulling on each other, simulated by a compute shader and drawn as
+    /// copies of the mesh.
+    NBody,
+    /// Conway's Game of Life, run by a compute shader; dragging paints live cells.
+    Life,
+    /// A field of grass swaying in the wind, a blade per instance; the cursor turns
+    /// the wind.
+    Vegetation,
+    /// A heightmap terrain seen through a camera orbiting it, which dragging turns
+    /// and scrolling moves closer.
+    Terrain,
+    /// The scene's instances flocking toward the cursor. It's simulated with the
+    /// scene, so the renderer draws nothing extra for it.
+    Boids,
+}
+
+/// The demos Ctrl+Tab cycles through after the scene, in order.
+const CYCLE: [DemoKind; 6] = [
+    DemoKind::Mandelbrot,
+    DemoKind::NBody,
+    DemoKind::Life,
+    DemoKind::Vegetation,
+    DemoKind::Terrain,
+    DemoKind::ShaderToy,
+];
+
+impl DemoKind {
+    pub fn parse(name: &str) -> Option<DemoKind> {
+        match name {
+            "mandelbrot" => Some(DemoKind::Mandelbrot),
+            "nbody" => Some(DemoKind::NBody),
+            "life" => Some(DemoKind::Life),
+            "vegetation" => Some(DemoKind::Vegetation),
+            "terrain" => Some(DemoKind::Terrain),
+            "boids" => Some(DemoKind::Boids),
+            _ => None,
+        }
+    }
+
+    /// Whether it's drawn instead of the scene.
+    pub fn replaces_scene(self) -> bool {
+        match self {
+            DemoKind::Mandelbrot
+            | DemoKind::ShaderToy
+            | DemoKind::NBody
+            | DemoKind::Life
+            | DemoKind::Vegetation
+            | DemoKind::Terrain => true,
+            DemoKind::Boids => false,
+        }
+    }
+
+    /// The demo after `current` for Ctrl+Tab: the ones drawn instead of the scene in
+    /// turn, ShaderToy only given a shader, then `scene`, the scene with the demo
+    /// that runs in it if any.
+    pub fn cycle(
+        current: Option<DemoKind>,
+        scene: Option<DemoKind>,
+        shadertoy: bool,
+    ) -> Option<DemoKind> {
+        let position = CYCLE.iter().position(|&kind| Some(kind) == current);
+        let start = position.map_or(0, |position| position + 1);
+        CYCLE[start..]
+            .iter()
+            .copied()
+            .find(|&kind| kind != DemoKind::ShaderToy || shadertoy)
+            .or(scene)
+    }
+}
+
+/// What demos see of the pointer, gathered once per frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DemoInput {
+    /// Normalized to `[0, 1]` across the window, y down.
+    pub cursor: [f32; 2],
+    /// Whether the left button is held.
+    pub pressed: bool,
+    /// Scroll wheel lines since the last frame, up being positive.
+    pub scroll: f32,
+}
+
+/// What a demo draws a frame with.
+pub struct DemoFrame<'a> {
+    pub viewport: &'a Viewport,
+    /// Seconds since the start.
+    pub time: f32,
+    /// The main mesh.
+    pub mesh: &'a MeshBuffer,
+}
+
+/// A showcase the renderer draws instead of the scene. Each owns its pipelines and
+/// buffers and shares the renderer's device, render target and command buffers;
+/// only the active one exists, made by [`create_demo`].
+pub trait Demo {
+    /// Rebuilds the pipelines for the render target, after its format changed.
+    fn recreate_pipelines(&mut self, device: &Arc<Device>, target: &RenderTarget);
+
+    /// Takes this frame's pointer, in a viewport of `dimensions` pixels.
+    fn handle_input(&mut self, _input: &DemoInput, _dimensions: [f32; 2]) {}
+
+    /// Records work that has to happen outside the render pass, such as compute
+    /// dispatches, before the draw.
+    fn prepare(
+        &mut self,
+        _builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        _frame: &DemoFrame,
+    ) {
+    }
+
+    /// Draws first in the scene pass when it's recorded inline.
+    fn draw_inline(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &DemoFrame,
+    );
+
+    /// Draws into a secondary command buffer executed first in the scene pass, when
+    /// it's split into buckets.
+    fn draw_secondary(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &DemoFrame,
+    );
+
+    /// Takes back what it tracked in `memory_stats` when created; called before it's
+    /// dropped.
+    fn untrack_memory(&self, _memory_stats: &mut MemoryStats) {}
+}
+
+/// What [`create_demo`] builds demos from.
+pub struct DemoResources<'a> {
+    pub device: &'a Arc<Device>,
+    pub queue_family: QueueFamily<'a>,
+    pub target: &'a RenderTarget,
+    pub memory_stats: &'a mut MemoryStats,
+    /// Of the meshes demos draw.
+    pub vertex_format: VertexFormat,
+    /// Bodies of [`DemoKind::NBody`].
+    pub nbody_bodies: u32,
+    /// What [`DemoKind::Terrain`] is built from, if not generated.
+    pub heightmap: Option<&'a Heightmap>,
+    /// The shader of [`DemoKind::ShaderToy`], once loaded.
+    pub shadertoy: Option<&'a Arc<ShaderModule>>,
+}
+
+/// The registry of demos: creates the one for `kind`, or `None` for those that run
+/// in the scene and those that can't run, such as ShaderToy before a shader is
+/// loaded.
+pub fn create_demo(kind: DemoKind, resources: DemoResources) -> Option<Box<dyn Demo>> {
+    let needs_compute = matches!(kind, DemoKind::NBody | DemoKind::Life);
+    if needs_compute && !resources.queue_family.supports_compute() {
+        warn!(demo = ?kind, "queue family doesn't support compute, the demo is disabled");
+        return None;
+    }
+    let DemoResources {
+        device,
+        queue_family,
+        target,
+        memory_stats,
+        vertex_format,
+        nbody_bodies,
+        heightmap,
+        shadertoy,
+    } = resources;
+    match kind {
+        DemoKind::Mandelbrot => Some(Box::new(MandelbrotDemo::new(device, target))),
+        // Loading the shader already checked it fits.
+        DemoKind::ShaderToy => shadertoy.map(|module| {
+            Box::new(ShaderToyDemo::new(device, target, module.clone()).unwrap()) as Box<dyn Demo>
+        }),
+        DemoKind::NBody => Some(Box::new(NBodyDemo::new(
+            device,
+            queue_family,
+            target,
+            vertex_format,
+            nbody_bodies,
+            memory_stats,
+        ))),
+        DemoKind::Life => Some(Box::new(LifeDemo::new(
+            device,
+            queue_family,
+            target,
+            memory_stats,
+        ))),
+        DemoKind::Vegetation => Some(Box::new(VegetationDemo::new(device, target, memory_stats))),
+        DemoKind::Terrain => Some(Box::new(TerrainDemo::new(
+            device,
+            target,
+            heightmap,
+            memory_stats,
+        ))),
+        DemoKind::Boids => None,
+    }
+}
+
+/// Zoom factor of one scroll wheel line.
+const ZOOM_PER_LINE: f64 = 1.25;
+
+/// Closest zoom: below this the double-float emulation runs out of precision and
+/// the set turns into blocks.
+const MIN_SCALE: f64 = 1e-13;
+
+const MAX_SCALE: f64 = 4.0;
+
+/// The part of the complex plane [`DemoKind::Mandelbrot`] shows. Kept in `f64` so deep
+/// zooms stay exact until the shader splits it.
+#[derive(Clone, Copy, Debug)]
+pub struct FractalView {
+    pub center: [f64; 2],
+    /// Half the height of the window in the complex plane.
+    pub scale: f64,
+}
+
+impl Default for FractalView {
+    fn default() -> Self {
+        FractalView {
+            center: [-0.5, 0.0],
+            scale: 1.2,
+        }
+    }
+}
+
+impl FractalView {
+    /// The point under `cursor`, normalized to `[0, 1]` across the window with y down,
+    /// in a window `aspect` times wider than tall.
+    pub fn point_at(&self, cursor: [f32; 2], aspect: f64) -> [f64; 2] {
+        [
+            self.center[0] + (2.0 * cursor[0] as f64 - 1.0) * aspect * self.scale,
+            self.center[1] - (2.0 * cursor[1] as f64 - 1.0) * self.scale,
+        ]
+    }
+
+    /// Moves the view so the point under `from` ends up under `to`.
+    pub fn drag(&mut self, from: [f32; 2], to: [f32; 2], aspect: f64) {
+        let (from, to) = (self.point_at(from, aspect), self.point_at(to, aspect));
+        self.center[0] += from[0] - to[0];
+        self.center[1] += from[1] - to[1];
+    }
+
+    /// Zooms in by `lines` scroll wheel lines, or out for negative ones, keeping the
+    /// point under `cursor` in place.
+    pub fn zoom_at(&mut self, cursor: [f32; 2], lines: f32, aspect: f64) {
+        let anchor = self.point_at(cursor, aspect);
+        let scale = (self.scale / ZOOM_PER_LINE.powf(lines as f64)).clamp(MIN_SCALE, MAX_SCALE);
+        let ratio = scale / self.scale;
+        self.center[0] = anchor[0] + (self.center[0] - anchor[0]) * ratio;
+        self.center[1] = anchor[1] + (self.center[1] - anchor[1]) * ratio;
+        self.scale = scale;
+    }
+
+    /// More iterations the deeper the zoom, as the boundary needs them to show its
+    /// detail.
+    fn max_iterations(&self) -> u32 {
+        let depth = (1.0 / self.scale).log2().max(0.0);
+        (128.0 + 48.0 * depth).min(4096.0) as u32
+    }
+}
+
+mod shadertoy_vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        void main() {
+            vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+            gl_Position = vec4(uv*2.0-1.0, 0.0, 1.0);
+        }
+        "
+    }
+}
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) out vec2 out_position;
+
+        void main() {
+            vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+            out_position = uv*2.0-1.0;
+            gl_Position = vec4(out_position, 0.0, 1.0);
+        }
+        "
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 in_position;
+
+        layout(location = 0) out vec4 f_color;
+
+        layout(push_constant) uniform FractalParams {
+            // The center split in two floats whose sum is the double.
+            vec2 center_hi;
+            vec2 center_lo;
+            float scale;
+            float aspect;
+            uint max_iterations;
+        } params;
+
+        // Double-float arithmetic: a number is the unevaluated sum x + y of two floats,
+        // giving about 48 bits of mantissa. `precise` stops the compiler from
+        // simplifying away the rounding errors the algorithms recover.
+
+        vec2 two_sum(float a, float b) {
+            precise float s = a+b;
+            precise float v = s-a;
+            precise float e = (a-(s-v))+(b-v);
+            return vec2(s, e);
+        }
+
+        vec2 quick_two_sum(float a, float b) {
+            precise float s = a+b;
+            precise float e = b-(s-a);
+            return vec2(s, e);
+        }
+
+        vec2 two_product(float a, float b) {
+            precise float p = a*b;
+            precise float e = fma(a, b, -p);
+            return vec2(p, e);
+        }
+
+        vec2 df_add(vec2 a, vec2 b) {
+            vec2 s = two_sum(a.x, b.x);
+            return quick_two_sum(s.x, s.y+a.y+b.y);
+        }
+
+        vec2 df_sub(vec2 a, vec2 b) {
+            return df_add(a, -b);
+        }
+
+        vec2 df_mul(vec2 a, vec2 b) {
+            vec2 p = two_product(a.x, b.x);
+            return quick_two_sum(p.x, p.y+a.x*b.y+a.y*b.x);
+        }
+
+        void main() {
+            vec2 offset = vec2(in_position.x*params.aspect, -in_position.y)*params.scale;
+            vec2 cr = df_add(vec2(params.center_hi.x, params.center_lo.x), vec2(offset.x, 0.0));
+            vec2 ci = df_add(vec2(params.center_hi.y, params.center_lo.y), vec2(offset.y, 0.0));
+
+            vec2 zr = vec2(0.0);
+            vec2 zi = vec2(0.0);
+            uint i = 0;
+            float magnitude = 0.0;
+            for (; i < params.max_iterations; i++) {
+                vec2 zr2 = df_mul(zr, zr);
+                vec2 zi2 = df_mul(zi, zi);
+                magnitude = zr2.x+zi2.x;
+                // Escaping far past 2 keeps the smooth coloring smooth.
+                if (magnitude > 256.0) {
+                    break;
+                }
+                vec2 zri = df_mul(zr, zi);
+                zi = df_add(df_add(zri, zri), ci);
+                zr = df_add(df_sub(zr2, zi2), cr);
+            }
+
+            if (i == params.max_iterations) {
+                f_color = vec4(0.0, 0.0, 0.0, 1.0);
+                return;
+            }
+            float smooth_i = float(i)+1.0-log2(log2(magnitude)*0.5);
+            vec3 color = 0.5+0.5*cos(0.08*smooth_i+vec3(0.0, 0.6, 1.0)*3.14159+vec3(3.5));
+            f_color = vec4(color, 1.0);
+        }
+        "
+    }
+}
+
+/// Draws [`DemoKind::Mandelbrot`] with a full-screen triangle whose fragment shader
+/// iterates every pixel's point of the set in emulated double precision, so zooms
+/// go far deeper than single floats would allow.
+pub struct MandelbrotDemo {
+    pipeline: Arc<GraphicsPipeline>,
+    view: FractalView,
+    /// The cursor as of the last frame, while the button is held.
+    dragged_from: Option<[f32; 2]>,
+}
+
+impl MandelbrotDemo {
+    pub fn new(device: &Arc<Device>, target: &RenderTarget) -> Self {
+        MandelbrotDemo {
+            pipeline: create_pipeline(device, target),
+            view: FractalView::default(),
+            dragged_from: None,
+        }
+    }
+
+    /// Draws the set over the whole viewport.
+    fn draw<L, P>(&self, builder: &mut AutoCommandBufferBuilder<L, P>, viewport: &Viewport) {
+        let view = &self.view;
+        let split = |value: f64| {
+            let hi = value as f32;
+            (hi, (value - hi as f64) as f32)
+        };
+        let (x_hi, x_lo) = split(view.center[0]);
+        let (y_hi, y_lo) = split(view.center[1]);
+        let [width, height] = viewport.dimensions;
+        builder
+            .set_viewport(0, [viewport.clone()])
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                fragment_shader::ty::FractalParams {
+                    center_hi: [x_hi, y_hi],
+                    center_lo: [x_lo, y_lo],
+                    scale: view.scale as f32,
+                    aspect: width / height,
+                    max_iterations: view.max_iterations(),
+                },
+            )
+            .draw(3, 1, 0, 0)
+            .unwrap();
+    }
+}
+
+impl Demo for MandelbrotDemo {
+    fn recreate_pipelines(&mut self, device: &Arc<Device>, target: &RenderTarget) {
+        self.pipeline = create_pipeline(device, target);
+    }
+
+    /// Drags the view and zooms it at the cursor.
+    fn handle_input(&mut self, input: &DemoInput, dimensions: [f32; 2]) {
+        let aspect = (dimensions[0] / dimensions[1]) as f64;
+        if let Some(from) = self.dragged_from {
+            self.view.drag(from, input.cursor, aspect);
+        }
+        self.dragged_from = input.pressed.then_some(input.cursor);
+        if input.scroll != 0.0 {
+            self.view.zoom_at(input.cursor, input.scroll, aspect);
+        }
+    }
+
+    fn draw_inline(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &DemoFrame,
+    ) {
+        self.draw(builder, frame.viewport);
+    }
+
+    fn draw_secondary(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &DemoFrame,
+    ) {
+        self.draw(builder, frame.viewport);
+    }
+}
+
+fn create_pipeline(device: &Arc<Device>, target: &RenderTarget) -> Arc<GraphicsPipeline> {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = fragment_shader::load(device.clone()).unwrap();
+
+    GraphicsPipeline::start()
+        .render_pass(target.scene())
+        .vertex_input_state(BuffersDefinition::new())
+        .input_assembly_state(InputAssemblyState::new())
+        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+        .build(device.clone())
+        .unwrap()
+}
+
+/// The uniforms ShaderToy shaders read, laid out like the block the shader loader
+/// declares them in.
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct ShaderToyInputs {
+    mouse: [f32; 4],
+    resolution: [f32; 3],
+    time: f32,
+    time_delta: f32,
+    frame: i32,
+}
+
+/// Tracks the left mouse button the way ShaderToy reports it in `iMouse`, in pixels
+/// from the bottom left of the window.
+#[derive(Clone, Copy, Debug, Default)]
+struct ShaderToyMouse {
+    /// Where the cursor last was while the button was held.
+    position: [f32; 2],
+    /// Where the button was last pressed.
+    click: [f32; 2],
+    pressed: bool,
+    /// Pressed since the last [`Self::take`].
+    clicked: bool,
+}
+
+impl ShaderToyMouse {
+    fn set_pressed(&mut self, pressed: bool, cursor: [f32; 2]) {
+        if pressed && !self.pressed {
+            self.position = cursor;
+            self.click = cursor;
+            self.clicked = true;
+        }
+        self.pressed = pressed;
+    }
+
+    fn move_to(&mut self, cursor: [f32; 2]) {
+        if self.pressed {
+            self.position = cursor;
+        }
+    }
+
+    /// `iMouse` for the next frame: the position, then the click, whose x is negated
+    /// once the button is released and whose y only on the frame after the click.
+    fn take(&mut self) -> [f32; 4] {
+        let sign = |positive: bool| if positive { 1.0 } else { -1.0 };
+        let mouse = [
+            self.position[0],
+            self.position[1],
+            sign(self.pressed) * self.click[0],
+            sign(self.clicked) * self.click[1],
+        ];
+        self.clicked = false;
+        mouse
+    }
+}
+
+/// Draws [`DemoKind::ShaderToy`] with a full-screen triangle running a shader the
+/// [`ShaderLoader`](crate::assets::ShaderLoader) compiled with `load_shadertoy`.
+pub struct ShaderToyDemo {
+    /// Kept to rebuild the pipeline.
+    module: Arc<ShaderModule>,
+    pipeline: Arc<GraphicsPipeline>,
+    mouse: ShaderToyMouse,
+    /// `iMouse` for the next frame.
+    i_mouse: [f32; 4],
+    /// Time of the last frame drawn, for `iTimeDelta`.
+    last_time: Option<f32>,
+    frame: i32,
+}
+
+impl ShaderToyDemo {
+    /// Fails if `module` doesn't fit the pipeline.
+    pub fn new(
+        device: &Arc<Device>,
+        target: &RenderTarget,
+        module: Arc<ShaderModule>,
+    ) -> Result<Self, String> {
+        Ok(ShaderToyDemo {
+            pipeline: create_shadertoy_pipeline(device, target, &module)?,
+            module,
+            mouse: ShaderToyMouse::default(),
+            i_mouse: [0.0; 4],
+            last_time: None,
+            frame: 0,
+        })
+    }
+
+    /// Draws the shader over the whole viewport at `time`, in seconds.
+    fn draw<L, P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        viewport: &Viewport,
+        time: f32,
+    ) {
+        let [width, height] = viewport.dimensions;
+        let inputs = ShaderToyInputs {
+            mouse: self.i_mouse,
+            resolution: [width, height, 1.0],
+            time,
+            time_delta: self.last_time.map_or(0.0, |last| time - last),
+            frame: self.frame,
+        };
+        self.last_time = Some(time);
+        self.frame += 1;
+        builder
+            .set_viewport(0, [viewport.clone()])
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .push_constants(self.pipeline.layout().clone(), 0, inputs)
+            .draw(3, 1, 0, 0)
+            .unwrap();
+    }
+}
+
+impl Demo for ShaderToyDemo {
+    fn recreate_pipelines(&mut self, device: &Arc<Device>, target: &RenderTarget) {
+        self.pipeline = create_shadertoy_pipeline(device, target, &self.module).unwrap();
+    }
+
+    /// Converts the cursor to the pixels from the bottom left of the window
+    /// ShaderToy reports the mouse in.
+    fn handle_input(&mut self, input: &DemoInput, dimensions: [f32; 2]) {
+        let cursor = [
+            input.cursor[0] * dimensions[0],
+            (1.0 - input.cursor[1]) * dimensions[1],
+        ];
+        self.mouse.set_pressed(input.pressed, cursor);
+        self.mouse.move_to(cursor);
+        self.i_mouse = self.mouse.take();
+    }
+
+    fn draw_inline(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &DemoFrame,
+    ) {
+        self.draw(builder, frame.viewport, frame.time);
+    }
+
+    fn draw_secondary(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &DemoFrame,
+    ) {
+        self.draw(builder, frame.viewport, frame.time);
+    }
+}
+
+pub fn create_shadertoy_pipeline(
+    device: &Arc<Device>,
+    target: &RenderTarget,
+    module: &Arc<ShaderModule>,
+) -> Result<Arc<GraphicsPipeline>, String> {
+    let loaded_vertex_shader = shadertoy_vertex_shader::load(device.clone()).unwrap();
+    let entry_point = module
+        .entry_point("main")
+        .ok_or("the shader has no main entry point")?;
+    if entry_point.descriptor_requirements().next().is_some() {
+        return Err("the shader reads iChannel inputs, which aren't provided".to_owned());
+    }
+
+    GraphicsPipeline::start()
+        .render_pass(target.scene())
+        .vertex_input_state(BuffersDefinition::new())
+        .input_assembly_state(InputAssemblyState::new())
+        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(entry_point, ())
+        .build(device.clone())
+        .map_err(pipeline_error)
+}
@@ -0,0 +1,192 @@
+use super::post;
+use crate::memory::{AllocationPurpose, MemoryStats};
+use std::sync::Arc;
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::WriteDescriptorSet,
+    device::Device,
+    image::{view::ImageView, AttachmentImage},
+    pipeline::{graphics::viewport::Viewport, GraphicsPipeline},
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+};
+
+/// How much deeper each instance is drawn than the one after it, as in the scene's
+/// vertex shader.
+const INSTANCE_DEPTH: f32 = 1.0 / 65536.0;
+
+/// Fraction of the instances between the focus and where the blur is widest.
+const FOCAL_RANGE: f32 = 0.5;
+
+/// Radius of the widest blur, in pixels.
+const MAX_RADIUS: f32 = 8.0;
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        // Samples gathered around each pixel, on a Vogel disc.
+        #define TAPS 32
+
+        layout(set = 0, binding = 0) uniform sampler2D scene;
+        layout(set = 0, binding = 1) uniform sampler2D depth;
+
+        layout(location = 0) out vec4 f_color;
+
+        layout(push_constant) uniform DofParams {
+            float focus;
+            // Depth from the focus the blur is widest at.
+            float range;
+            float max_radius;
+        } params;
+
+        // Radius of the circle of confusion at `pixel` in pixels, negative in front
+        // of the focus.
+        float coc(ivec2 pixel) {
+            float d = texelFetch(depth, pixel, 0).r;
+            return clamp((d-params.focus)/params.range, -1.0, 1.0)*params.max_radius;
+        }
+
+        void main() {
+            ivec2 pixel = ivec2(gl_FragCoord.xy);
+            ivec2 last = textureSize(scene, 0)-1;
+            float center = coc(pixel);
+            vec4 sharp = texelFetch(scene, pixel, 0);
+            vec4 near_sum = vec4(0.0);
+            float near_weight = 0.0;
+            vec4 far_sum = vec4(0.0);
+            float far_weight = 0.0;
+            for (int i = 0; i < TAPS; i++) {
+                float r = sqrt((float(i)+0.5)/float(TAPS))*params.max_radius;
+                float theta = float(i)*2.39996323;
+                ivec2 tap = clamp(pixel+ivec2(round(r*vec2(cos(theta), sin(theta)))), ivec2(0), last);
+                float size = coc(tap);
+                vec4 color = texelFetch(scene, tap, 0);
+                if (size < 0.0) {
+                    // In front of the focus: spreads over whatever is behind it.
+                    float weight = clamp(-size-r+1.0, 0.0, 1.0);
+                    near_sum += color*weight;
+                    near_weight += weight;
+                } else {
+                    // Behind it: spreads no further than this pixel's own blur, so it
+                    // doesn't bleed over what's sharp in front.
+                    float weight = clamp(min(size, max(center, 0.0))-r+1.0, 0.0, 1.0);
+                    far_sum += color*weight;
+                    far_weight += weight;
+                }
+            }
+            vec4 far = far_weight > 0.0 ? far_sum/far_weight : sharp;
+            vec4 near = near_weight > 0.0 ? near_sum/near_weight : sharp;
+            // How much of the pixel the blurred near field covers.
+            float coverage = center < 0.0 ? 1.0 : clamp(4.0*near_weight/float(TAPS), 0.0, 1.0);
+            f_color = mix(far, near, coverage);
+        }
+        "
+    }
+}
+
+/// Depth of field: blurs each pixel by how far its depth is from the focus,
+/// gathering a disc of the frame around it. What's in front of the focus and what's
+/// behind are gathered apart, so a blurred foreground spreads over what's behind it
+/// while a blurred background stays behind what's sharp. As depth is draw order,
+/// the focus is an instance's place in it, and the blur is widest
+/// [`FOCAL_RANGE`] of the instances away. Needs dynamic rendering, as it reads the
+/// frame's neighboring pixels, which an input attachment can't.
+pub struct DepthOfField {
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    image: Arc<ImageView<AttachmentImage>>,
+    dimensions: [u32; 2],
+    /// Depth in focus; starts at the background, behind every instance.
+    focus: f32,
+}
+
+impl DepthOfField {
+    pub fn new(device: &Arc<Device>, dimensions: [u32; 2], memory_stats: &mut MemoryStats) -> Self {
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Nearest,
+                min_filter: Filter::Nearest,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        memory_stats.track(AllocationPurpose::Texture, post::target_size(dimensions));
+        DepthOfField {
+            pipeline: post::create_pipeline(
+                device,
+                &fragment_shader::load(device.clone()).unwrap(),
+            ),
+            sampler,
+            image: post::create_target(device, dimensions),
+            dimensions,
+            focus: 1.0,
+        }
+    }
+
+    /// Recreates the image for a viewport of `dimensions`, if they changed.
+    pub fn resize(
+        &mut self,
+        device: &Arc<Device>,
+        dimensions: [u32; 2],
+        memory_stats: &mut MemoryStats,
+    ) {
+        if dimensions == self.dimensions {
+            return;
+        }
+        memory_stats.untrack(
+            AllocationPurpose::Texture,
+            post::target_size(self.dimensions),
+        );
+        memory_stats.track(AllocationPurpose::Texture, post::target_size(dimensions));
+        self.image = post::create_target(device, dimensions);
+        self.dimensions = dimensions;
+    }
+
+    pub fn untrack_memory(&self, memory_stats: &mut MemoryStats) {
+        memory_stats.untrack(
+            AllocationPurpose::Texture,
+            post::target_size(self.dimensions),
+        );
+    }
+
+    /// Focuses at `depth` from now on.
+    pub fn set_focus(&mut self, depth: f32) {
+        self.focus = depth;
+    }
+
+    /// What the last [`Self::draw`] wrote, for the output pass to show.
+    pub fn image(&self) -> &Arc<ImageView<AttachmentImage>> {
+        &self.image
+    }
+
+    /// Blurs `scene` by `depth`, out of `instance_count` instances, in a rendering
+    /// scope of its own. Call between the scene's and the output pass's.
+    pub fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        scene: &Arc<ImageView<AttachmentImage>>,
+        depth: &Arc<ImageView<AttachmentImage>>,
+        instance_count: u32,
+        viewport: &Viewport,
+    ) {
+        post::draw(
+            builder,
+            &self.pipeline,
+            &self.image,
+            [
+                WriteDescriptorSet::image_view_sampler(0, scene.clone(), self.sampler.clone()),
+                WriteDescriptorSet::image_view_sampler(1, depth.clone(), self.sampler.clone()),
+            ],
+            fragment_shader::ty::DofParams {
+                focus: self.focus,
+                range: instance_count.max(1) as f32 * INSTANCE_DEPTH * FOCAL_RANGE,
+                max_radius: MAX_RADIUS,
+            },
+            viewport,
+        );
+    }
+}
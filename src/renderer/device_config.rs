@@ -0,0 +1,153 @@
+use super::{device_extensions, textures};
+use crate::memory;
+use vulkano::{
+    device::{physical::PhysicalDevice, DeviceExtensions, Features},
+    Version,
+};
+
+// Devices with VK_KHR_portability_subset, such as MoltenVK on macOS and iOS, only
+// implement what the API they're layered on can, and report what they leave out as
+// portability-subset features and properties rather than as core ones. The ones
+// that matter here:
+//
+// - `wide_lines` isn't supported at all, and the core feature says so.
+// - `fill_mode_non_solid` is, but polygons drawn as points also need
+//   `point_polygons`, so that's a capability of its own.
+// - Vertex strides have to be multiples of `min_vertex_input_binding_stride_alignment`
+//   (4 on Metal); all vertex types here are made of 4-byte fields.
+// - The extension has to be enabled whenever it's supported, which vulkano does
+//   too, and the instance has to enumerate portability devices for them to show up.
+
+/// The Vulkan version and optional capabilities to create the device with. Each
+/// capability is only enabled where the physical device supports it, and
+/// [`DeviceCapabilities`] records which were.
+#[derive(Clone, Debug)]
+pub struct DeviceConfig {
+    /// Highest Vulkan version to use; the instance is created with it as its maximum.
+    pub api_version: Version,
+    /// Lines wider than one pixel.
+    pub wide_lines: bool,
+    /// Points larger than one pixel.
+    pub large_points: bool,
+    /// Drawing polygons as lines or points.
+    pub fill_mode_non_solid: bool,
+    pub sampler_anisotropy: bool,
+    /// Record frames with dynamic rendering instead of a render pass and framebuffers.
+    pub dynamic_rendering: bool,
+    /// Sample textures from a variable-count array with descriptor indexing, instead
+    /// of from the layers of one array image.
+    pub bindless: bool,
+    /// Push the descriptors bound while recording, such as the output pass's scene
+    /// image and the tilemap's tiles, instead of keeping descriptor sets for them.
+    pub push_descriptors: bool,
+    /// Record barriers with `vkCmdPipelineBarrier2`, and let render pass dependencies
+    /// wait on no stage.
+    pub synchronization2: bool,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        DeviceConfig {
+            api_version: Version::V1_3,
+            wide_lines: true,
+            large_points: true,
+            fill_mode_non_solid: true,
+            sampler_anisotropy: true,
+            dynamic_rendering: true,
+            bindless: true,
+            push_descriptors: true,
+            synchronization2: true,
+        }
+    }
+}
+
+/// What the device was created with of a [`DeviceConfig`]; optional paths branch on
+/// this rather than on the device's features.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeviceCapabilities {
+    /// The version the device is used at, the lower of the requested one and the
+    /// device's own.
+    pub api_version: Version,
+    /// The device only implements a subset of Vulkan, on top of another API.
+    pub portability_subset: bool,
+    pub wide_lines: bool,
+    pub large_points: bool,
+    pub fill_mode_non_solid: bool,
+    /// Drawing polygons as points; part of `fill_mode_non_solid` except on
+    /// portability-subset devices.
+    pub point_polygons: bool,
+    pub sampler_anisotropy: bool,
+    pub dynamic_rendering: bool,
+    pub bindless: bool,
+    pub push_descriptors: bool,
+    pub synchronization2: bool,
+}
+
+impl DeviceConfig {
+    /// What `physical_device` supports of this, with the features and extensions to
+    /// create the device with for it, on top of the ones the renderer requires.
+    pub fn negotiate(
+        &self,
+        physical_device: PhysicalDevice,
+    ) -> (DeviceCapabilities, Features, DeviceExtensions) {
+        let supported_features = physical_device.supported_features();
+        let supported_extensions = physical_device.supported_extensions();
+        // Already capped at the instance's maximum, which is the requested version.
+        let api_version = physical_device.api_version();
+        let portability_subset = supported_extensions.khr_portability_subset;
+        let fill_mode_non_solid =
+            self.fill_mode_non_solid && supported_features.fill_mode_non_solid;
+        let capabilities = DeviceCapabilities {
+            api_version,
+            portability_subset,
+            wide_lines: self.wide_lines && supported_features.wide_lines,
+            large_points: self.large_points && supported_features.large_points,
+            fill_mode_non_solid,
+            point_polygons: fill_mode_non_solid
+                && (!portability_subset || supported_features.point_polygons),
+            sampler_anisotropy: self.sampler_anisotropy && supported_features.sampler_anisotropy,
+            dynamic_rendering: self.dynamic_rendering && supported_features.dynamic_rendering,
+            bindless: self.bindless && textures::bindless_supported(physical_device),
+            push_descriptors: self.push_descriptors && supported_extensions.khr_push_descriptor,
+            synchronization2: self.synchronization2 && supported_features.synchronization2,
+        };
+
+        let features = Features {
+            wide_lines: capabilities.wide_lines,
+            large_points: capabilities.large_points,
+            fill_mode_non_solid: capabilities.fill_mode_non_solid,
+            // Only exists as a feature on portability-subset devices.
+            point_polygons: capabilities.point_polygons && portability_subset,
+            sampler_anisotropy: capabilities.sampler_anisotropy,
+            dynamic_rendering: capabilities.dynamic_rendering,
+            synchronization2: capabilities.synchronization2,
+            runtime_descriptor_array: capabilities.bindless,
+            shader_sampled_image_array_non_uniform_indexing: capabilities.bindless,
+            descriptor_binding_variable_descriptor_count: capabilities.bindless,
+            ..Features::none()
+        };
+
+        let extensions = device_extensions()
+            .union(&memory::optional_device_extensions().intersection(supported_extensions))
+            .union(&DeviceExtensions {
+                // Core in Vulkan 1.3, an extension before.
+                khr_dynamic_rendering: capabilities.dynamic_rendering
+                    && api_version < Version::V1_3,
+                khr_push_descriptor: capabilities.push_descriptors,
+                khr_synchronization2: capabilities.synchronization2 && api_version < Version::V1_3,
+                khr_portability_subset: portability_subset,
+                ..DeviceExtensions::none()
+            });
+        #[cfg(feature = "profile-tracy")]
+        let extensions = extensions.union(
+            &super::profiling::optional_device_extensions().intersection(supported_extensions),
+        );
+        let extensions = if capabilities.bindless {
+            extensions.union(&textures::bindless_extensions(physical_device))
+        } else {
+            extensions
+        };
+
+        (capabilities, features, extensions)
+    }
+}
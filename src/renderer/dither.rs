@@ -0,0 +1,186 @@
+use super::nbody::hash;
+use crate::memory::{AllocationPurpose, MemoryStats};
+use std::sync::Arc;
+use vulkano::{
+    descriptor_set::WriteDescriptorSet,
+    device::{Device, Queue},
+    format::{Format, NumericType},
+    image::{view::ImageView, ImageDimensions, ImmutableImage, MipmapsCount},
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    sync::GpuFuture,
+    DeviceSize,
+};
+
+/// Side of the tiled noise texture.
+const NOISE_SIZE: u32 = 32;
+
+/// Width of the Gaussian the void-and-cluster method spreads each point with, in
+/// texels.
+const SIGMA: f32 = 1.5;
+
+/// Blue noise the output pass adds to the encoded color before it's quantized,
+/// breaking the bands smooth gradients show on 8 and 10 bit outputs into fine grain
+/// without a pattern the eye picks up. Float outputs don't quantize, so they aren't
+/// dithered.
+pub struct Dither {
+    view: Arc<ImageView<ImmutableImage>>,
+    sampler: Arc<Sampler>,
+    enabled: bool,
+    /// The output's, which decides how far the noise spans.
+    format: Format,
+}
+
+impl Dither {
+    pub fn new(
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        enabled: bool,
+        format: Format,
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        let pixels = blue_noise(NOISE_SIZE);
+        memory_stats.track(AllocationPurpose::Texture, pixels.len() as DeviceSize);
+        let (image, upload) = ImmutableImage::from_iter(
+            pixels,
+            ImageDimensions::Dim2d {
+                width: NOISE_SIZE,
+                height: NOISE_SIZE,
+                array_layers: 1,
+            },
+            MipmapsCount::One,
+            Format::R8_UNORM,
+            queue.clone(),
+        )
+        .unwrap();
+        upload
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Nearest,
+                min_filter: Filter::Nearest,
+                address_mode: [SamplerAddressMode::Repeat; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        Dither {
+            view: ImageView::new_default(image).unwrap(),
+            sampler,
+            enabled,
+            format,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Dithers for `format` from now on.
+    pub fn set_format(&mut self, format: Format) {
+        self.format = format;
+    }
+
+    /// For `OutputParams::dither_step`: one code of the output format, or 0 when not
+    /// dithering.
+    pub fn step(&self) -> f32 {
+        match self.format.type_color() {
+            Some(NumericType::UNORM | NumericType::SRGB) if self.enabled => {
+                1.0 / ((1u32 << self.format.components()[0]) - 1) as f32
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// For `OutputParams::srgb_format`: whether writes to the output are encoded to
+    /// sRGB by the hardware, after the shader.
+    pub fn encodes_srgb(&self) -> u32 {
+        (self.format.type_color() == Some(NumericType::SRGB)) as u32
+    }
+
+    pub fn write(&self, binding: u32) -> WriteDescriptorSet {
+        WriteDescriptorSet::image_view_sampler(binding, self.view.clone(), self.sampler.clone())
+    }
+}
+
+/// A `size` by `size` tile of blue noise by the void-and-cluster method, each of
+/// its values, spread evenly over 0 to 255, as far from its neighbors as can be.
+fn blue_noise(size: u32) -> Vec<u8> {
+    let n = (size * size) as usize;
+    // How much a point at (0, 0) adds to each texel's energy, wrapping around.
+    let kernel: Vec<f32> = (0..n as u32)
+        .map(|i| {
+            let wrap = |d: u32| d.min(size - d) as f32;
+            let (dx, dy) = (wrap(i % size), wrap(i / size));
+            (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+        })
+        .collect();
+    let mut points = vec![false; n];
+    let mut energy = vec![0.0; n];
+    let toggle = |points: &mut Vec<bool>, energy: &mut Vec<f32>, i: usize| {
+        points[i] = !points[i];
+        let sign = if points[i] { 1.0 } else { -1.0 };
+        let (x, y) = (i as u32 % size, i as u32 / size);
+        for (j, energy) in energy.iter_mut().enumerate() {
+            let (dx, dy) = (
+                (j as u32 + size - x) % size,
+                (j as u32 / size + size - y) % size,
+            );
+            *energy += sign * kernel[(dy * size + dx) as usize];
+        }
+    };
+    // The densest point and the emptiest spot.
+    let tightest = |points: &[bool], energy: &[f32]| {
+        (0..n)
+            .filter(|&i| points[i])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+    let largest_void = |points: &[bool], energy: &[f32]| {
+        (0..n)
+            .filter(|&i| !points[i])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+
+    // A tenth of the texels at random, then spread out by moving the densest
+    // point into the emptiest spot until that's where it came from.
+    let initial = n / 10;
+    let mut seed = 0;
+    while points.iter().filter(|&&point| point).count() < initial {
+        let i = (hash(seed) * n as f32) as usize % n;
+        seed += 1;
+        if !points[i] {
+            toggle(&mut points, &mut energy, i);
+        }
+    }
+    loop {
+        let cluster = tightest(&points, &energy);
+        toggle(&mut points, &mut energy, cluster);
+        let void = largest_void(&points, &energy);
+        toggle(&mut points, &mut energy, void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    // The initial points are ranked by removing the densest in turn, the rest by
+    // filling the emptiest spot in turn.
+    let mut rank = vec![0; n];
+    let (initial_points, initial_energy) = (points.clone(), energy.clone());
+    for r in (0..initial).rev() {
+        let cluster = tightest(&points, &energy);
+        toggle(&mut points, &mut energy, cluster);
+        rank[cluster] = r;
+    }
+    let (mut points, mut energy) = (initial_points, initial_energy);
+    for r in initial..n {
+        let void = largest_void(&points, &energy);
+        toggle(&mut points, &mut energy, void);
+        rank[void] = r;
+    }
+    rank.iter().map(|&r| (r * 256 / n) as u8).collect()
+}
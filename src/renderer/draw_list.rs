@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// A mesh added with [`Renderer::add_mesh`](super::Renderer::add_mesh). The default
+/// is the main mesh, which [`Renderer::load_mesh`](super::Renderer::load_mesh) replaces.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct MeshId(pub(super) usize);
+
+/// A pipeline added with [`Renderer::add_material`](super::Renderer::add_material).
+/// The default is the main material, whose fragment shader
+/// [`Renderer::set_fragment_shader`](super::Renderer::set_fragment_shader) replaces.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct MaterialId(pub(super) usize);
+
+/// A run of consecutive instances drawn with one material and mesh, at one level of
+/// detail.
+#[derive(Clone, Debug)]
+pub struct DrawBatch {
+    pub material: MaterialId,
+    pub mesh: MeshId,
+    /// The mesh's most detailed level is 0; levels past its last draw the last.
+    pub lod: u32,
+    pub instances: Range<u32>,
+}
+
+/// The batches a frame draws its instances in. Sorted by material, then mesh, then
+/// level of detail, so recording binds each pipeline and vertex buffer as few times
+/// as possible.
+#[derive(Default)]
+pub struct DrawList {
+    batches: Vec<DrawBatch>,
+}
+
+impl DrawList {
+    /// A single batch drawing `instances` with the main material and mesh.
+    pub fn single(instances: Range<u32>) -> Self {
+        DrawList {
+            batches: vec![DrawBatch {
+                material: MaterialId::default(),
+                mesh: MeshId::default(),
+                lod: 0,
+                instances,
+            }],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.batches.clear();
+    }
+
+    /// Adds the instance at `index`, extending the last batch if it continues it.
+    pub fn push(&mut self, material: MaterialId, mesh: MeshId, lod: u32, index: u32) {
+        if let Some(last) = self.batches.last_mut() {
+            if last.material == material
+                && last.mesh == mesh
+                && last.lod == lod
+                && last.instances.end == index
+            {
+                last.instances.end += 1;
+                return;
+            }
+        }
+        self.batches.push(DrawBatch {
+            material,
+            mesh,
+            lod,
+            instances: index..index + 1,
+        });
+    }
+
+    pub fn sort(&mut self) {
+        self.batches
+            .sort_by_key(|batch| (batch.material, batch.mesh, batch.lod));
+    }
+
+    pub fn batches(&self) -> &[DrawBatch] {
+        &self.batches
+    }
+}
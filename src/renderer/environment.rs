@@ -0,0 +1,504 @@
+use super::{
+    rasterizer::RasterizerSettings, topology::PipelineVariants, vertex_format::MeshVertices,
+    vertex_format::VertexFormat, DrawInputs, InstanceData, RenderTarget, COLOR_SET, SCENE_FORMAT,
+};
+use crate::memory::{AllocationPurpose, MemoryStats};
+use std::{
+    cmp::{max, min},
+    ops::Range,
+    sync::Arc,
+};
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassContents,
+    },
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    device::{Device, Queue},
+    image::{
+        view::{ImageView, ImageViewCreateInfo, ImageViewType},
+        ImageAccess, ImageCreateFlags, ImageDimensions, ImageLayout, ImageSubresourceRange,
+        ImageUsage, SampleCount, StorageImage,
+    },
+    pipeline::{
+        graphics::{
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            vertex_input::BuffersDefinition,
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    render_pass::{
+        AttachmentDescription, AttachmentReference, Framebuffer, FramebufferCreateInfo, LoadOp,
+        RenderPass, RenderPassCreateInfo, StoreOp, Subpass, SubpassDescription,
+    },
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    DeviceSize,
+};
+
+/// What the faces show where the scene isn't, i.e. everything behind it.
+const SKY_COLOR: [f32; 4] = [0.05, 0.06, 0.12, 1.0];
+
+/// Where the reflective ball is drawn, in clip space, and its radius as a fraction
+/// of the viewport's height.
+const BALL_CENTER: [f32; 2] = [0.0, 0.0];
+const BALL_RADIUS: f32 = 0.4;
+
+/// Each face's view of the scene, in the order of a cube image's layers: +X, -X,
+/// +Y, -Y, +Z, -Z. The rows take a direction to the face's s and t axes and the
+/// axis it faces, as a cube is sampled.
+const FACES: [[[f32; 4]; 3]; 6] = [
+    [
+        [0.0, 0.0, -1.0, 0.0],
+        [0.0, -1.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0, 0.0],
+    ],
+    [
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, -1.0, 0.0, 0.0],
+        [-1.0, 0.0, 0.0, 0.0],
+    ],
+    [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+    ],
+    [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 0.0, -1.0, 0.0],
+        [0.0, -1.0, 0.0, 0.0],
+    ],
+    [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, -1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+    ],
+    [
+        [-1.0, 0.0, 0.0, 0.0],
+        [0.0, -1.0, 0.0, 0.0],
+        [0.0, 0.0, -1.0, 0.0],
+    ],
+];
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 position;
+        layout(location = 1) in vec4 color;
+        layout(location = 2) in vec2 basis_x;
+        layout(location = 3) in vec2 basis_y;
+        layout(location = 4) in vec2 translation;
+        layout(location = 5) in vec2 phase;
+
+        layout(location = 0) out vec4 out_color;
+
+        layout(set = 2, binding = 0) readonly buffer InstanceColors {
+            vec4 colors[];
+        };
+
+        layout(push_constant) uniform FaceParams {
+            // The face's rotation, a row each.
+            vec4 face[3];
+            // The rest are the scene's.
+            vec4 bands[2];
+            float x;
+            float y;
+            float zoom;
+        } params;
+
+        void main() {
+            out_color = color*colors[gl_InstanceIndex];
+            vec2 pos = position*vec2(params.x, params.y)*params.zoom;
+            uint band = uint(gl_InstanceIndex)%8u;
+            float amplitude = 0.5*(1.0+2.0*params.bands[band/4u][band%4u]);
+            vec2 wobble = vec2(sin(phase.x+position.x+position.y), sin(phase.y+position.x+position.y))*amplitude;
+            vec2 world = translation+mat2(basis_x, basis_y)*(pos+wobble);
+            // The scene is upright on the plane z = 1, filling the +Z face.
+            vec3 point = vec3(world.x, -world.y, 1.0);
+            vec3 view = vec3(dot(params.face[0].xyz, point), dot(params.face[1].xyz, point), dot(params.face[2].xyz, point));
+            // A 90 degree perspective; depth is within [0, w] only in front of the
+            // face, so what's behind it is clipped.
+            gl_Position = vec4(view.xy, 0.5*view.z, view.z);
+            gl_PointSize = 1.0;
+        }
+        "
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec4 in_color;
+
+        layout(location = 0) out vec4 f_color;
+
+        void main() {
+            f_color = in_color;
+        }
+        "
+    }
+}
+
+mod ball_vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) out vec2 out_offset;
+
+        layout(push_constant) uniform BallParams {
+            // The center in xy, the radius in each axis in zw.
+            vec4 rect;
+        } params;
+
+        void main() {
+            // A strip of 4 vertices around the ball.
+            vec2 corner = vec2((gl_VertexIndex & 1)*2-1, (gl_VertexIndex >> 1)*2-1);
+            out_offset = corner;
+            gl_Position = vec4(params.rect.xy+corner*params.rect.zw, 0.0, 1.0);
+        }
+        "
+    }
+}
+
+mod ball_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 in_offset;
+
+        layout(location = 0) out vec4 f_color;
+
+        layout(set = 0, binding = 0) uniform samplerCube environment;
+
+        void main() {
+            float distance_squared = dot(in_offset, in_offset);
+            if (distance_squared > 1.0) {
+                discard;
+            }
+            // Seen from +z, so its middle reflects the scene behind the viewer; y is
+            // up, as in the faces.
+            vec3 normal = vec3(in_offset.x, -in_offset.y, sqrt(1.0-distance_squared));
+            vec3 reflected = reflect(vec3(0.0, 0.0, -1.0), normal);
+            // Brighter towards the rim, where reflections are stronger.
+            float fresnel = 0.6+0.4*pow(1.0-normal.z, 5.0);
+            f_color = vec4(texture(environment, reflected).rgb*fresnel, 1.0);
+        }
+        "
+    }
+}
+
+/// Draws the instances into the six faces of a cube image, a render pass per face
+/// through a view of its layer, as seen from the origin with the scene on the plane
+/// in front; then draws a mirror ball reflecting them through a cube view of the
+/// whole image in the scene pass. Instances are drawn with their vertex colors
+/// whatever their material.
+pub struct EnvironmentMap {
+    render_pass: Arc<RenderPass>,
+    pipelines: PipelineVariants,
+    /// One for each face, rendering into its layer.
+    framebuffers: Vec<Arc<Framebuffer>>,
+    viewport: Viewport,
+    ball_pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    /// Samples every face through the cube view; rebuilt with the ball pipeline.
+    descriptor_set: Arc<PersistentDescriptorSet>,
+    cube: Arc<ImageView<StorageImage>>,
+}
+
+impl EnvironmentMap {
+    /// `size` is each face's width and height in texels.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        target: &RenderTarget,
+        vertex_format: VertexFormat,
+        topologies: &[PrimitiveTopology],
+        rasterizer: &RasterizerSettings,
+        size: u32,
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        // Storage images stay in the general layout, so the pass keeps them there.
+        let render_pass = RenderPass::new(
+            device.clone(),
+            RenderPassCreateInfo {
+                attachments: vec![AttachmentDescription {
+                    format: Some(SCENE_FORMAT),
+                    samples: SampleCount::Sample1,
+                    load_op: LoadOp::Clear,
+                    store_op: StoreOp::Store,
+                    initial_layout: ImageLayout::General,
+                    final_layout: ImageLayout::General,
+                    ..Default::default()
+                }],
+                subpasses: vec![SubpassDescription {
+                    color_attachments: vec![Some(AttachmentReference {
+                        attachment: 0,
+                        layout: ImageLayout::ColorAttachmentOptimal,
+                        ..Default::default()
+                    })],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let pipelines =
+            create_pipelines(device, &render_pass, vertex_format, topologies, rasterizer);
+
+        let image = StorageImage::with_usage(
+            device.clone(),
+            ImageDimensions::Dim2d {
+                width: size,
+                height: size,
+                array_layers: FACES.len() as u32,
+            },
+            SCENE_FORMAT,
+            ImageUsage {
+                color_attachment: true,
+                sampled: true,
+                ..ImageUsage::none()
+            },
+            ImageCreateFlags {
+                cube_compatible: true,
+                ..ImageCreateFlags::none()
+            },
+            [queue.family()],
+        )
+        .unwrap();
+        memory_stats.track(AllocationPurpose::Texture, cube_size(size));
+        let framebuffers = (0..FACES.len() as u32)
+            .map(|face| {
+                let view = ImageView::new(
+                    image.clone(),
+                    ImageViewCreateInfo {
+                        view_type: ImageViewType::Dim2d,
+                        subresource_range: face_range(&image, face),
+                        ..ImageViewCreateInfo::from_image(&image)
+                    },
+                )
+                .unwrap();
+                Framebuffer::new(
+                    render_pass.clone(),
+                    FramebufferCreateInfo {
+                        attachments: vec![view],
+                        ..Default::default()
+                    },
+                )
+                .unwrap()
+            })
+            .collect();
+        let cube = ImageView::new(
+            image.clone(),
+            ImageViewCreateInfo {
+                view_type: ImageViewType::Cube,
+                ..ImageViewCreateInfo::from_image(&image)
+            },
+        )
+        .unwrap();
+        let viewport = Viewport {
+            origin: [0.0, 0.0],
+            dimensions: [size as f32; 2],
+            depth_range: 0.0..1.0,
+        };
+
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let ball_pipeline = create_ball_pipeline(device, target);
+        let descriptor_set = sampling_descriptor_set(&ball_pipeline, &cube, &sampler);
+        EnvironmentMap {
+            render_pass,
+            pipelines,
+            framebuffers,
+            viewport,
+            ball_pipeline,
+            sampler,
+            descriptor_set,
+            cube,
+        }
+    }
+
+    pub fn set_ball_pipeline(&mut self, pipeline: Arc<GraphicsPipeline>) {
+        self.descriptor_set = sampling_descriptor_set(&pipeline, &self.cube, &self.sampler);
+        self.ball_pipeline = pipeline;
+    }
+
+    /// Recreates the pipelines drawing the faces, e.g. for new topologies.
+    pub fn recreate_pipelines(
+        &mut self,
+        device: &Arc<Device>,
+        vertex_format: VertexFormat,
+        topologies: &[PrimitiveTopology],
+        rasterizer: &RasterizerSettings,
+    ) {
+        self.pipelines = create_pipelines(
+            device,
+            &self.render_pass,
+            vertex_format,
+            topologies,
+            rasterizer,
+        );
+    }
+
+    /// Records a render pass for each face drawing `instances` of `inputs` into it.
+    /// Call outside the render pass, before [`Self::draw`].
+    pub fn render(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        inputs: &DrawInputs,
+        instances: Range<u32>,
+    ) {
+        for (framebuffer, face) in self.framebuffers.iter().zip(FACES) {
+            builder
+                .begin_render_pass(
+                    RenderPassBeginInfo {
+                        clear_values: vec![Some(SKY_COLOR.into())],
+                        ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
+                    },
+                    SubpassContents::Inline,
+                )
+                .unwrap();
+            let params = vertex_shader::ty::FaceParams {
+                face,
+                bands: inputs.push_constants.bands,
+                x: inputs.push_constants.x,
+                y: inputs.push_constants.y,
+                zoom: inputs.push_constants.zoom,
+            };
+            builder.set_viewport(0, [self.viewport.clone()]);
+            for batch in inputs.batches {
+                let start = max(batch.instances.start, instances.start);
+                let end = min(batch.instances.end, instances.end);
+                if start >= end {
+                    continue;
+                }
+                let mesh = inputs.meshes.get(batch.mesh.0).unwrap_or(&inputs.meshes[0]);
+                let pipeline = self.pipelines.get(mesh.topology());
+                builder
+                    .bind_pipeline_graphics(pipeline.clone())
+                    .push_constants(pipeline.layout().clone(), 0, params)
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        pipeline.layout().clone(),
+                        COLOR_SET,
+                        inputs.colors.clone(),
+                    );
+                mesh.bind(builder, inputs.instance_buffer);
+                let lod = mesh.lod(batch.lod);
+                builder
+                    .draw_indexed(lod.end - lod.start, end - start, lod.start, 0, start)
+                    .unwrap();
+            }
+            builder.end_render_pass().unwrap();
+        }
+    }
+
+    /// Draws the mirror ball reflecting the faces written by the last
+    /// [`Self::render`], round whatever the viewport's aspect ratio.
+    pub fn draw<L, P>(&self, builder: &mut AutoCommandBufferBuilder<L, P>, viewport: &Viewport) {
+        let [width, height] = viewport.dimensions;
+        let params = ball_vertex_shader::ty::BallParams {
+            rect: [
+                BALL_CENTER[0],
+                BALL_CENTER[1],
+                BALL_RADIUS * height / width.max(1.0),
+                BALL_RADIUS,
+            ],
+        };
+        builder
+            .set_viewport(0, [viewport.clone()])
+            .bind_pipeline_graphics(self.ball_pipeline.clone())
+            .push_constants(self.ball_pipeline.layout().clone(), 0, params)
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.ball_pipeline.layout().clone(),
+                0,
+                self.descriptor_set.clone(),
+            )
+            .draw(4, 1, 0, 0)
+            .unwrap();
+    }
+}
+
+/// Layer `face` of `image`, with everything else it has.
+fn face_range(image: &StorageImage, face: u32) -> ImageSubresourceRange {
+    let mut range = image.subresource_range();
+    range.array_layers = face..face + 1;
+    range
+}
+
+/// Bytes of every face of `size` texels square, 8 a texel.
+fn cube_size(size: u32) -> DeviceSize {
+    size as DeviceSize * size as DeviceSize * 8 * FACES.len() as DeviceSize
+}
+
+fn sampling_descriptor_set(
+    pipeline: &Arc<GraphicsPipeline>,
+    cube: &Arc<ImageView<StorageImage>>,
+    sampler: &Arc<Sampler>,
+) -> Arc<PersistentDescriptorSet> {
+    PersistentDescriptorSet::new(
+        pipeline.layout().set_layouts()[0].clone(),
+        [WriteDescriptorSet::image_view_sampler(
+            0,
+            cube.clone(),
+            sampler.clone(),
+        )],
+    )
+    .unwrap()
+}
+
+fn create_pipelines(
+    device: &Arc<Device>,
+    render_pass: &Arc<RenderPass>,
+    vertex_format: VertexFormat,
+    topologies: &[PrimitiveTopology],
+    rasterizer: &RasterizerSettings,
+) -> PipelineVariants {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = fragment_shader::load(device.clone()).unwrap();
+    PipelineVariants::new(topologies, |topology| {
+        GraphicsPipeline::start()
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .vertex_input_state(MeshVertices::<InstanceData>::new(vertex_format))
+            .input_assembly_state(InputAssemblyState::new().topology(topology))
+            .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+            .rasterization_state(rasterizer.mesh_state(device))
+            .build(device.clone())
+    })
+    .unwrap()
+}
+
+pub fn create_ball_pipeline(device: &Arc<Device>, target: &RenderTarget) -> Arc<GraphicsPipeline> {
+    let loaded_vertex_shader = ball_vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = ball_fragment_shader::load(device.clone()).unwrap();
+
+    GraphicsPipeline::start()
+        .render_pass(target.scene())
+        .vertex_input_state(BuffersDefinition::new())
+        .input_assembly_state(InputAssemblyState::new().topology(PrimitiveTopology::TriangleStrip))
+        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+        .build(device.clone())
+        .unwrap()
+}
@@ -0,0 +1,528 @@
+use super::{
+    rasterizer::RasterizerSettings, topology::PipelineVariants, vertex_format::MeshVertices,
+    vertex_format::VertexFormat, vertex_shader::ty::PushConstantData, DrawInputs, InstanceData,
+    RenderTarget,
+};
+use crate::{
+    allocator::{FrameRing, StagingRing},
+    memory::{AllocationPurpose, MemoryStats},
+};
+use bytemuck::{Pod, Zeroable};
+use std::{
+    cmp::{max, min},
+    ops::Range,
+    sync::Arc,
+};
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, BufferImageCopy, CopyImageToBufferInfo, PrimaryAutoCommandBuffer,
+        RenderPassBeginInfo, SubpassContents,
+    },
+    device::{Device, DeviceOwned},
+    format::Format,
+    image::{view::ImageView, AttachmentImage, ImageAccess, ImageLayout, ImageUsage, SampleCount},
+    impl_vertex,
+    pipeline::{
+        graphics::{
+            color_blend::ColorBlendState,
+            depth_stencil::DepthStencilState,
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline,
+    },
+    render_pass::{
+        AttachmentDescription, AttachmentReference, Framebuffer, FramebufferCreateInfo, LoadOp,
+        RenderPass, RenderPassCreateInfo, StoreOp, Subpass, SubpassDescription,
+    },
+    DeviceSize,
+};
+
+/// Format of the motion vectors: half floats are plenty for fractions of the screen.
+pub const MOTION_FORMAT: Format = Format::R16G16_SFLOAT;
+
+/// Format of the depth copy: a color format, so it can be sampled and read back the
+/// same whatever the depth attachment's format, in full precision, as each
+/// instance is only 1/65536 deeper than the last.
+pub const DEPTH_COPY_FORMAT: Format = Format::R32_SFLOAT;
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 position;
+        layout(location = 1) in vec2 basis_x;
+        layout(location = 2) in vec2 basis_y;
+        layout(location = 3) in vec2 translation;
+        layout(location = 4) in vec2 phase;
+        layout(location = 5) in vec2 previous_basis_x;
+        layout(location = 6) in vec2 previous_basis_y;
+        layout(location = 7) in vec2 previous_translation;
+        layout(location = 8) in vec2 previous_phase;
+
+        layout(location = 0) out vec2 out_current;
+        layout(location = 1) out vec2 out_previous;
+
+        layout(push_constant) uniform MotionParams {
+            // The scene's this frame, then the last.
+            vec4 bands[2];
+            vec4 previous_bands[2];
+            float x;
+            float y;
+            float zoom;
+            float previous_x;
+            float previous_y;
+            float previous_zoom;
+        } params;
+
+        // Where the scene's vertex shader puts the vertex, given one frame's inputs.
+        vec2 place(vec2 basis_x, vec2 basis_y, vec2 translation, vec2 phase, vec4 bands[2], vec3 camera) {
+            vec2 pos = position*camera.xy*camera.z;
+            uint band = uint(gl_InstanceIndex)%8u;
+            float amplitude = 0.5*(1.0+2.0*bands[band/4u][band%4u]);
+            vec2 wobble = vec2(sin(phase.x+position.x+position.y), sin(phase.y+position.x+position.y))*amplitude;
+            return translation+mat2(basis_x, basis_y)*(pos+wobble);
+        }
+
+        void main() {
+            out_current = place(basis_x, basis_y, translation, phase, params.bands, vec3(params.x, params.y, params.zoom));
+            out_previous = place(
+                previous_basis_x,
+                previous_basis_y,
+                previous_translation,
+                previous_phase,
+                params.previous_bands,
+                vec3(params.previous_x, params.previous_y, params.previous_zoom)
+            );
+            // Sorted like the scene, so the nearest instance's motion is kept.
+            float depth = max(1.0-float(gl_InstanceIndex+1)/65536.0, 0.0);
+            gl_Position = vec4(out_current, depth, 1.0);
+            gl_PointSize = 1.0;
+        }
+        "
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 in_current;
+        layout(location = 1) in vec2 in_previous;
+
+        layout(location = 0) out vec2 f_motion;
+        layout(location = 1) out float f_depth;
+
+        void main() {
+            // From clip space to texture coordinates, which span half as much.
+            f_motion = 0.5*(in_current-in_previous);
+            f_depth = gl_FragCoord.z;
+        }
+        "
+    }
+}
+
+/// An instance's attributes this frame and the last, for the motion vector pass.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct MotionInstance {
+    pub basis_x: [f32; 2],
+    pub basis_y: [f32; 2],
+    pub translation: [f32; 2],
+    pub phase: [f32; 2],
+    pub previous_basis_x: [f32; 2],
+    pub previous_basis_y: [f32; 2],
+    pub previous_translation: [f32; 2],
+    pub previous_phase: [f32; 2],
+}
+impl_vertex!(
+    MotionInstance,
+    basis_x,
+    basis_y,
+    translation,
+    phase,
+    previous_basis_x,
+    previous_basis_y,
+    previous_translation,
+    previous_phase
+);
+
+/// Draws the instances into screen-sized images for post effects: a
+/// [`MOTION_FORMAT`] one, each pixel holding how far what's there moved since the
+/// last frame, in texture coordinates, so where it was is its own coordinates minus
+/// the vector; and a [`DEPTH_COPY_FORMAT`] one with its depth, which the scene pass
+/// only writes with the depth pre-pass. The last frame's instances and camera are
+/// kept to place the vertices where they were. Instances are matched by index, so
+/// one that is new, or took over another's index, moves from wherever that was.
+/// Drawn in a render pass of its own before the scene's, unjittered and with one
+/// pipeline whatever the material.
+pub struct GBuffer {
+    render_pass: Arc<RenderPass>,
+    pipelines: PipelineVariants,
+    instance_ring: FrameRing<MotionInstance>,
+    previous: Vec<InstanceData>,
+    /// The scene's push constants in the last frame, if there was one.
+    previous_frame: Option<PushConstantData>,
+    images: Images,
+}
+
+/// The images drawn into, replaced together when the viewport is resized.
+struct Images {
+    motion: Arc<ImageView<AttachmentImage>>,
+    depth: Arc<ImageView<AttachmentImage>>,
+    framebuffer: Arc<Framebuffer>,
+    viewport: Viewport,
+}
+
+impl GBuffer {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Arc<Device>,
+        target: &RenderTarget,
+        vertex_format: VertexFormat,
+        topologies: &[PrimitiveTopology],
+        rasterizer: &RasterizerSettings,
+        frames_in_flight: usize,
+        dimensions: [f32; 2],
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        let depth_format = target.depth_format();
+        let render_pass = RenderPass::new(
+            device.clone(),
+            RenderPassCreateInfo {
+                attachments: vec![
+                    AttachmentDescription {
+                        format: Some(MOTION_FORMAT),
+                        samples: SampleCount::Sample1,
+                        load_op: LoadOp::Clear,
+                        store_op: StoreOp::Store,
+                        initial_layout: ImageLayout::ColorAttachmentOptimal,
+                        final_layout: ImageLayout::ColorAttachmentOptimal,
+                        ..Default::default()
+                    },
+                    AttachmentDescription {
+                        format: Some(DEPTH_COPY_FORMAT),
+                        samples: SampleCount::Sample1,
+                        load_op: LoadOp::Clear,
+                        store_op: StoreOp::Store,
+                        initial_layout: ImageLayout::ColorAttachmentOptimal,
+                        final_layout: ImageLayout::ColorAttachmentOptimal,
+                        ..Default::default()
+                    },
+                    AttachmentDescription {
+                        format: Some(depth_format),
+                        samples: SampleCount::Sample1,
+                        load_op: LoadOp::Clear,
+                        store_op: StoreOp::DontCare,
+                        stencil_load_op: LoadOp::DontCare,
+                        stencil_store_op: StoreOp::DontCare,
+                        initial_layout: ImageLayout::DepthStencilAttachmentOptimal,
+                        final_layout: ImageLayout::DepthStencilAttachmentOptimal,
+                        ..Default::default()
+                    },
+                ],
+                subpasses: vec![SubpassDescription {
+                    color_attachments: vec![
+                        Some(AttachmentReference {
+                            attachment: 0,
+                            layout: ImageLayout::ColorAttachmentOptimal,
+                            ..Default::default()
+                        }),
+                        Some(AttachmentReference {
+                            attachment: 1,
+                            layout: ImageLayout::ColorAttachmentOptimal,
+                            ..Default::default()
+                        }),
+                    ],
+                    depth_stencil_attachment: Some(AttachmentReference {
+                        attachment: 2,
+                        layout: ImageLayout::DepthStencilAttachmentOptimal,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let pipelines =
+            create_pipelines(device, &render_pass, vertex_format, topologies, rasterizer);
+        let instance_ring = FrameRing::new(
+            device.clone(),
+            BufferUsage::vertex_buffer(),
+            AllocationPurpose::Instance,
+            frames_in_flight,
+            0,
+            memory_stats,
+        )
+        .unwrap();
+        let images = create_images(device, &render_pass, dimensions);
+        memory_stats.track(AllocationPurpose::Texture, images_size(&images.viewport));
+        GBuffer {
+            render_pass,
+            pipelines,
+            instance_ring,
+            previous: Vec::new(),
+            previous_frame: None,
+            images,
+        }
+    }
+
+    /// Recreates the pipelines, e.g. for new topologies.
+    pub fn recreate_pipelines(
+        &mut self,
+        device: &Arc<Device>,
+        vertex_format: VertexFormat,
+        topologies: &[PrimitiveTopology],
+        rasterizer: &RasterizerSettings,
+    ) {
+        self.pipelines = create_pipelines(
+            device,
+            &self.render_pass,
+            vertex_format,
+            topologies,
+            rasterizer,
+        );
+    }
+
+    /// Recreates the images for a viewport of `dimensions`, if they changed.
+    pub fn resize(
+        &mut self,
+        device: &Arc<Device>,
+        dimensions: [f32; 2],
+        memory_stats: &mut MemoryStats,
+    ) {
+        if dimensions.map(|x| x.max(1.0)) == self.images.viewport.dimensions {
+            return;
+        }
+        memory_stats.untrack(
+            AllocationPurpose::Texture,
+            images_size(&self.images.viewport),
+        );
+        self.images = create_images(device, &self.render_pass, dimensions);
+        memory_stats.track(
+            AllocationPurpose::Texture,
+            images_size(&self.images.viewport),
+        );
+    }
+
+    pub fn untrack_memory(&self, memory_stats: &mut MemoryStats) {
+        memory_stats.untrack(
+            AllocationPurpose::Texture,
+            images_size(&self.images.viewport),
+        );
+        self.instance_ring.untrack_memory(memory_stats);
+    }
+
+    /// The motion vectors the last [`Self::render`] wrote.
+    pub fn motion(&self) -> &Arc<ImageView<AttachmentImage>> {
+        &self.images.motion
+    }
+
+    /// The depth the last [`Self::render`] wrote, 1 where there's no instance.
+    pub fn depth(&self) -> &Arc<ImageView<AttachmentImage>> {
+        &self.images.depth
+    }
+
+    /// Records copying the depth at `pixel` to a buffer the host reads once the
+    /// frame is done. Call after [`Self::render`].
+    pub fn copy_depth_to_host(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        pixel: [u32; 2],
+    ) -> Arc<CpuAccessibleBuffer<[f32]>> {
+        let buffer = CpuAccessibleBuffer::from_iter(
+            self.images.depth.device().clone(),
+            BufferUsage::transfer_dst(),
+            false,
+            [1.0f32],
+        )
+        .unwrap();
+        let image = self.images.depth.image();
+        let [width, height, _] = image.dimensions().width_height_depth();
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo {
+                regions: [BufferImageCopy {
+                    image_subresource: image.subresource_layers(),
+                    image_offset: [pixel[0].min(width - 1), pixel[1].min(height - 1), 0],
+                    image_extent: [1, 1, 1],
+                    ..Default::default()
+                }]
+                .into(),
+                ..CopyImageToBufferInfo::image_buffer(image.clone(), buffer.clone())
+            })
+            .unwrap();
+        buffer
+    }
+
+    /// Uploads `instance_data` with where each instance was the last frame, and
+    /// records the pass drawing `instances` of `inputs` into the images. Call
+    /// outside the render pass, before whatever reads them.
+    pub fn render(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        inputs: &DrawInputs,
+        instance_data: &[InstanceData],
+        instances: Range<u32>,
+        staging: &mut StagingRing,
+        memory_stats: &mut MemoryStats,
+    ) {
+        let motion_data: Vec<_> = instance_data
+            .iter()
+            .enumerate()
+            .map(|(i, current)| {
+                let previous = self.previous.get(i).unwrap_or(current);
+                MotionInstance {
+                    basis_x: current.basis_x,
+                    basis_y: current.basis_y,
+                    translation: current.translation,
+                    phase: current.phase,
+                    previous_basis_x: previous.basis_x,
+                    previous_basis_y: previous.basis_y,
+                    previous_translation: previous.translation,
+                    previous_phase: previous.phase,
+                }
+            })
+            .collect();
+        let motion_instances =
+            self.instance_ring
+                .upload(&motion_data, staging, builder, memory_stats);
+        let current = inputs.push_constants;
+        let previous = self.previous_frame.unwrap_or(current);
+        self.previous.clear();
+        self.previous.extend_from_slice(instance_data);
+        self.previous_frame = Some(current);
+
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![
+                        Some([0.0; 4].into()),
+                        Some([1.0, 0.0, 0.0, 0.0].into()),
+                        Some(1.0.into()),
+                    ],
+                    ..RenderPassBeginInfo::framebuffer(self.images.framebuffer.clone())
+                },
+                SubpassContents::Inline,
+            )
+            .unwrap();
+        let params = vertex_shader::ty::MotionParams {
+            bands: current.bands,
+            previous_bands: previous.bands,
+            x: current.x,
+            y: current.y,
+            zoom: current.zoom,
+            previous_x: previous.x,
+            previous_y: previous.y,
+            previous_zoom: previous.zoom,
+        };
+        builder.set_viewport(0, [self.images.viewport.clone()]);
+        for batch in inputs.batches {
+            let start = max(batch.instances.start, instances.start);
+            let end = min(batch.instances.end, instances.end);
+            if start >= end {
+                continue;
+            }
+            let mesh = inputs.meshes.get(batch.mesh.0).unwrap_or(&inputs.meshes[0]);
+            let pipeline = self.pipelines.get(mesh.topology());
+            builder
+                .bind_pipeline_graphics(pipeline.clone())
+                .push_constants(pipeline.layout().clone(), 0, params);
+            mesh.bind(builder, &motion_instances);
+            let lod = mesh.lod(batch.lod);
+            builder
+                .draw_indexed(lod.end - lod.start, end - start, lod.start, 0, start)
+                .unwrap();
+        }
+        builder.end_render_pass().unwrap();
+    }
+}
+
+fn create_images(
+    device: &Arc<Device>,
+    render_pass: &Arc<RenderPass>,
+    dimensions: [f32; 2],
+) -> Images {
+    let dimensions = dimensions.map(|x| x.max(1.0));
+    let extent = dimensions.map(|x| x as u32);
+    let color_image = |format| {
+        let image = AttachmentImage::with_usage(
+            device.clone(),
+            extent,
+            format,
+            ImageUsage {
+                color_attachment: true,
+                sampled: true,
+                transfer_src: true,
+                ..ImageUsage::none()
+            },
+        )
+        .unwrap();
+        ImageView::new_default(image).unwrap()
+    };
+    let motion = color_image(MOTION_FORMAT);
+    let depth = color_image(DEPTH_COPY_FORMAT);
+    let depth_format = render_pass.attachments()[2].format.unwrap();
+    let depth_attachment =
+        AttachmentImage::transient(device.clone(), extent, depth_format).unwrap();
+    let framebuffer = Framebuffer::new(
+        render_pass.clone(),
+        FramebufferCreateInfo {
+            attachments: vec![
+                motion.clone(),
+                depth.clone(),
+                ImageView::new_default(depth_attachment).unwrap(),
+            ],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let viewport = Viewport {
+        origin: [0.0, 0.0],
+        dimensions,
+        depth_range: 0.0..1.0,
+    };
+    Images {
+        motion,
+        depth,
+        framebuffer,
+        viewport,
+    }
+}
+
+/// Bytes of the images at `viewport`: the motion vectors, the depth copy and the
+/// depth attachment, 4 a texel each.
+fn images_size(viewport: &Viewport) -> DeviceSize {
+    let [width, height] = viewport.dimensions;
+    (width * height) as DeviceSize * 12
+}
+
+fn create_pipelines(
+    device: &Arc<Device>,
+    render_pass: &Arc<RenderPass>,
+    vertex_format: VertexFormat,
+    topologies: &[PrimitiveTopology],
+    rasterizer: &RasterizerSettings,
+) -> PipelineVariants {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = fragment_shader::load(device.clone()).unwrap();
+    PipelineVariants::new(topologies, |topology| {
+        GraphicsPipeline::start()
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .vertex_input_state(MeshVertices::<MotionInstance>::new(vertex_format))
+            .input_assembly_state(InputAssemblyState::new().topology(topology))
+            .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+            .color_blend_state(ColorBlendState::new(2))
+            .depth_stencil_state(DepthStencilState::simple_depth_test())
+            .rasterization_state(rasterizer.mesh_state(device))
+            .build(device.clone())
+    })
+    .unwrap()
+}
@@ -0,0 +1,371 @@
+use super::{
+    reflection::assert_shader_layout, vertex_format::MeshVertices, MeshBuffer, RenderTarget,
+    VertexFormat,
+};
+use crate::memory::{AllocationPurpose, MemoryStats};
+use bytemuck::{Pod, Zeroable};
+use std::{mem::size_of, sync::Arc};
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer, DeviceLocalBuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, CopyBufferInfo, DrawIndexedIndirectCommand,
+        PrimaryAutoCommandBuffer,
+    },
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    device::{physical::QueueFamily, Device},
+    impl_vertex,
+    pipeline::{
+        graphics::{
+            color_blend::{AttachmentBlend, ColorBlendState},
+            input_assembly::InputAssemblyState,
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreationError,
+        },
+        ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    shader::ShaderModule,
+    DeviceSize,
+};
+
+/// Invocations per compute workgroup; matches `local_size_x` in the shader.
+const WORKGROUP_SIZE: u32 = 256;
+
+/// Longest step the simulation takes, so a stall doesn't fling every particle away.
+const MAX_STEP: f32 = 0.1;
+
+/// Scale of the mesh per particle at birth; particles shrink to nothing as they age.
+const PARTICLE_SIZE: f32 = 0.01;
+
+/// One particle as laid out in the storage buffers, which are also bound as
+/// instance buffers for drawing.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+struct GpuParticle {
+    particle_position: [f32; 2],
+    velocity: [f32; 2],
+    /// Age and lifetime in seconds. A negative age means not born yet.
+    life: [f32; 2],
+}
+impl_vertex!(GpuParticle, particle_position, velocity, life);
+assert_shader_layout!(GpuParticle, compute_shader::ty::Particle {
+    particle_position => position,
+    velocity => velocity,
+    life => life,
+});
+
+mod compute_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: "
+        #version 460
+
+        layout(local_size_x = 256) in;
+
+        struct Particle {
+            vec2 position;
+            vec2 velocity;
+            vec2 life;
+        };
+
+        layout(set = 0, binding = 0) readonly buffer Source {
+            Particle particles[];
+        } source;
+
+        layout(set = 0, binding = 1) writeonly buffer Destination {
+            Particle particles[];
+        } destination;
+
+        layout(push_constant) uniform EmitterParams {
+            vec2 origin;
+            vec2 gravity;
+            float drag;
+            float speed;
+            float lifetime;
+            float dt;
+            float time;
+            uint count;
+        } emitter;
+
+        float hash(uint x) {
+            x ^= x >> 16;
+            x *= 0x7feb352du;
+            x ^= x >> 15;
+            x *= 0x846ca68bu;
+            x ^= x >> 16;
+            return float(x)/4294967295.0;
+        }
+
+        void main() {
+            uint i = gl_GlobalInvocationID.x;
+            if (i >= emitter.count) {
+                return;
+            }
+
+            Particle p = source.particles[i];
+            p.life.x += emitter.dt;
+            if (p.life.x >= p.life.y) {
+                uint seed = i*3u + floatBitsToUint(emitter.time);
+                float angle = -1.5707963+(hash(seed)-0.5)*0.8;
+                p.position = emitter.origin;
+                p.velocity = vec2(cos(angle), sin(angle))*emitter.speed*(0.5+hash(seed+1u));
+                p.life = vec2(0.0, emitter.lifetime*(0.5+0.5*hash(seed+2u)));
+            } else if (p.life.x >= 0.0) {
+                p.velocity += emitter.gravity*emitter.dt;
+                p.velocity *= exp(-emitter.drag*emitter.dt);
+                p.position += p.velocity*emitter.dt;
+            }
+            destination.particles[i] = p;
+        }
+        "
+    }
+}
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 position;
+        layout(location = 1) in vec4 color;
+        layout(location = 2) in vec2 particle_position;
+        layout(location = 3) in vec2 velocity;
+        layout(location = 4) in vec2 life;
+
+        layout(location = 0) out vec4 out_color;
+
+        layout(push_constant) uniform DrawParams {
+            float size;
+        } params;
+
+        void main() {
+            float t = clamp(life.x/max(life.y, 1e-6), 0.0, 1.0);
+            bool alive = life.x >= 0.0 && life.x < life.y;
+            float size = alive ? params.size*(1.0-t) : 0.0;
+            out_color = color*mix(vec4(0.3, 0.6, 1.0, 1.0), vec4(0.0, 0.0, 0.0, 1.0), t);
+            gl_Position = vec4(particle_position+position*size, 0.0, 1.0);
+        }
+        "
+    }
+}
+
+/// A particle fountain simulated entirely on the GPU for
+/// [`RendererSettings::gpu_particles`](super::RendererSettings::gpu_particles). Each
+/// frame a compute pass reads one storage buffer and writes the other, then the
+/// written one is drawn as the instance buffer of an indirect draw.
+pub struct GpuParticles {
+    count: u32,
+    compute_pipeline: Arc<ComputePipeline>,
+    pipeline: Arc<GraphicsPipeline>,
+    buffers: [Arc<DeviceLocalBuffer<[GpuParticle]>>; 2],
+    /// `descriptor_sets[i]` reads `buffers[i]` and writes the other one.
+    descriptor_sets: [Arc<PersistentDescriptorSet>; 2],
+    /// Index of the buffer holding the latest state.
+    current: usize,
+    /// Copied into both buffers by the first frame, then dropped.
+    initial_state: Option<Arc<CpuAccessibleBuffer<[GpuParticle]>>>,
+    indirect: Arc<CpuAccessibleBuffer<[DrawIndexedIndirectCommand]>>,
+    last_time: Option<f32>,
+}
+
+impl GpuParticles {
+    /// Draws with `pipeline`, made by [`create_pipeline`].
+    pub fn new(
+        device: &Arc<Device>,
+        queue_family: QueueFamily,
+        pipeline: Arc<GraphicsPipeline>,
+        count: u32,
+        index_count: u32,
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        let compute_shader = compute_shader::load(device.clone()).unwrap();
+        let compute_pipeline = ComputePipeline::new(
+            device.clone(),
+            compute_shader.entry_point("main").unwrap(),
+            &(),
+            None,
+            |_| {},
+        )
+        .unwrap();
+
+        let usage = BufferUsage {
+            storage_buffer: true,
+            vertex_buffer: true,
+            transfer_dst: true,
+            ..BufferUsage::none()
+        };
+        let buffers = [(); 2].map(|()| {
+            DeviceLocalBuffer::array(device.clone(), count as DeviceSize, usage, [queue_family])
+                .unwrap()
+        });
+        memory_stats.track(
+            AllocationPurpose::Storage,
+            2 * count as DeviceSize * size_of::<GpuParticle>() as DeviceSize,
+        );
+
+        let layout = compute_pipeline.layout().set_layouts()[0].clone();
+        let descriptor_sets = [0, 1].map(|source| {
+            PersistentDescriptorSet::new(
+                layout.clone(),
+                [
+                    WriteDescriptorSet::buffer(0, buffers[source].clone()),
+                    WriteDescriptorSet::buffer(1, buffers[1 - source].clone()),
+                ],
+            )
+            .unwrap()
+        });
+
+        // Births are staggered over one lifetime, so the fountain starts as a stream
+        // rather than a single burst.
+        let initial_state = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::transfer_src(),
+            false,
+            (0..count).map(|i| GpuParticle {
+                life: [-(i as f32 / count as f32) * EMITTER.lifetime, 0.0],
+                ..GpuParticle::default()
+            }),
+        )
+        .unwrap();
+
+        GpuParticles {
+            count,
+            compute_pipeline,
+            pipeline,
+            buffers,
+            descriptor_sets,
+            current: 0,
+            initial_state: Some(initial_state),
+            indirect: indirect_buffer(device, index_count, count),
+            last_time: None,
+        }
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: Arc<GraphicsPipeline>) {
+        self.pipeline = pipeline;
+    }
+
+    /// Draws the mesh with `index_count` indices per particle from now on.
+    pub fn set_index_count(&mut self, device: &Arc<Device>, index_count: u32) {
+        // A fresh buffer, as frames in flight may still read the old one.
+        self.indirect = indirect_buffer(device, index_count, self.count);
+    }
+
+    /// Records the compute pass advancing the simulation to `time`. Call outside
+    /// the render pass, before [`Self::draw`].
+    pub fn simulate(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        time: f32,
+    ) {
+        if let Some(initial_state) = self.initial_state.take() {
+            for buffer in &self.buffers {
+                builder
+                    .copy_buffer(CopyBufferInfo::buffers(
+                        initial_state.clone(),
+                        buffer.clone(),
+                    ))
+                    .unwrap();
+            }
+        }
+        let dt = self
+            .last_time
+            .map_or(0.0, |last_time| (time - last_time).clamp(0.0, MAX_STEP));
+        self.last_time = Some(time);
+
+        builder
+            .bind_pipeline_compute(self.compute_pipeline.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.compute_pipeline.layout().clone(),
+                0,
+                self.descriptor_sets[self.current].clone(),
+            )
+            .push_constants(
+                self.compute_pipeline.layout().clone(),
+                0,
+                compute_shader::ty::EmitterParams {
+                    dt,
+                    time,
+                    count: self.count,
+                    ..EMITTER
+                },
+            )
+            .dispatch([self.count.div_ceil(WORKGROUP_SIZE), 1, 1])
+            .unwrap();
+        self.current = 1 - self.current;
+    }
+
+    /// Draws the state written by the last [`Self::simulate`], blended additively.
+    pub fn draw<L, P>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        viewport: &Viewport,
+        vertex_buffer: &MeshBuffer,
+    ) {
+        builder
+            .set_viewport(0, [viewport.clone()])
+            .bind_pipeline_graphics(self.pipeline.clone());
+        vertex_buffer.bind(builder, &self.buffers[self.current]);
+        builder
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                vertex_shader::ty::DrawParams {
+                    size: PARTICLE_SIZE,
+                },
+            )
+            .draw_indexed_indirect(self.indirect.clone())
+            .unwrap();
+    }
+}
+
+/// Emitter parameters; `dt`, `time` and `count` are filled in per dispatch.
+const EMITTER: compute_shader::ty::EmitterParams = compute_shader::ty::EmitterParams {
+    origin: [0.0, 0.9],
+    gravity: [0.0, 0.8],
+    drag: 0.3,
+    speed: 1.6,
+    lifetime: 3.0,
+    dt: 0.0,
+    time: 0.0,
+    count: 0,
+};
+
+pub fn create_pipeline(
+    device: &Arc<Device>,
+    target: &RenderTarget,
+    vertex_format: VertexFormat,
+    fragment_shader: &Arc<ShaderModule>,
+) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    GraphicsPipeline::start()
+        .render_pass(target.scene())
+        .vertex_input_state(MeshVertices::<GpuParticle>::new(vertex_format))
+        .input_assembly_state(InputAssemblyState::new())
+        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(fragment_shader.entry_point("main").unwrap(), ())
+        .color_blend_state(ColorBlendState::new(1).blend(AttachmentBlend::additive()))
+        .build(device.clone())
+}
+
+fn indirect_buffer(
+    device: &Arc<Device>,
+    index_count: u32,
+    instance_count: u32,
+) -> Arc<CpuAccessibleBuffer<[DrawIndexedIndirectCommand]>> {
+    CpuAccessibleBuffer::from_iter(
+        device.clone(),
+        BufferUsage::indirect_buffer(),
+        false,
+        [DrawIndexedIndirectCommand {
+            index_count,
+            instance_count,
+            first_index: 0,
+            vertex_offset: 0,
+            first_instance: 0,
+        }],
+    )
+    .unwrap()
+}
@@ -0,0 +1,98 @@
+use crate::{
+    allocator::{FrameRing, StagingRing},
+    memory::{AllocationPurpose, MemoryStats},
+};
+use std::{collections::BTreeMap, sync::Arc};
+use vulkano::{
+    buffer::BufferUsage,
+    command_buffer::AutoCommandBufferBuilder,
+    descriptor_set::{
+        layout::{
+            DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo,
+            DescriptorType,
+        },
+        PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::Device,
+    memory::DeviceMemoryAllocationError,
+    shader::ShaderStages,
+};
+
+/// The set the vertex shaders drawing instances read their colors from, after the
+/// materials' textures and lights. It's declared in each of them as
+/// `layout(set = 2, binding = 0) readonly buffer InstanceColors { vec4 colors[]; }`.
+pub const COLOR_SET: u32 = 2;
+
+/// Color of the instance the set stands in for when there are none: a descriptor
+/// can't cover zero bytes.
+const NO_COLORS: [[f32; 4]; 1] = [[1.0; 4]];
+
+/// The colors instances multiply their mesh's vertex colors with, one per instance in
+/// the instances' order, in a storage buffer the vertex shaders index with
+/// `gl_InstanceIndex`. Batches are drawn from their first instance on, which
+/// `gl_InstanceIndex` counts from, so every instance finds its own.
+pub struct InstanceColors {
+    ring: FrameRing<[f32; 4]>,
+    layout: Arc<DescriptorSetLayout>,
+}
+
+impl InstanceColors {
+    /// `per_frame_capacity` is in colors; the buffers grow past it.
+    pub fn new(
+        device: &Arc<Device>,
+        frames_in_flight: usize,
+        per_frame_capacity: usize,
+        memory_stats: &mut MemoryStats,
+    ) -> Result<Self, DeviceMemoryAllocationError> {
+        Ok(InstanceColors {
+            ring: FrameRing::new(
+                device.clone(),
+                BufferUsage::storage_buffer(),
+                AllocationPurpose::Instance,
+                frames_in_flight,
+                per_frame_capacity,
+                memory_stats,
+            )?,
+            layout: create_layout(device),
+        })
+    }
+
+    /// Uploads `colors` and returns the set they are bound with. Call at most once a
+    /// frame, outside the render pass: the one chunk starts the frame's buffer, so
+    /// it's aligned for any storage buffer offset alignment.
+    pub fn upload<L, P>(
+        &mut self,
+        colors: &[[f32; 4]],
+        staging: &mut StagingRing,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        memory_stats: &mut MemoryStats,
+    ) -> Arc<PersistentDescriptorSet> {
+        let colors = if colors.is_empty() {
+            &NO_COLORS[..]
+        } else {
+            colors
+        };
+        let chunk = self.ring.upload(colors, staging, builder, memory_stats);
+        PersistentDescriptorSet::new(self.layout.clone(), [WriteDescriptorSet::buffer(0, chunk)])
+            .unwrap()
+    }
+}
+
+/// The colors at binding 0, read by the vertex stage only.
+fn create_layout(device: &Arc<Device>) -> Arc<DescriptorSetLayout> {
+    let binding = DescriptorSetLayoutBinding {
+        stages: ShaderStages {
+            vertex: true,
+            ..ShaderStages::none()
+        },
+        ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::StorageBuffer)
+    };
+    DescriptorSetLayout::new(
+        device.clone(),
+        DescriptorSetLayoutCreateInfo {
+            bindings: BTreeMap::from([(0, binding)]),
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
@@ -0,0 +1,376 @@
+use super::{
+    demo::{Demo, DemoFrame, DemoInput},
+    RenderTarget,
+};
+use crate::memory::{AllocationPurpose, MemoryStats};
+use std::sync::Arc;
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, SecondaryAutoCommandBuffer,
+    },
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    device::{physical::QueueFamily, Device},
+    format::Format,
+    image::{view::ImageView, ImageCreateFlags, ImageDimensions, ImageUsage, StorageImage},
+    pipeline::{
+        graphics::{
+            input_assembly::InputAssemblyState,
+            vertex_input::BuffersDefinition,
+            viewport::{Viewport, ViewportState},
+        },
+        ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    DeviceSize,
+};
+
+/// Cells along each side of the grid, which wraps around at the edges and is
+/// stretched over the window.
+const GRID_SIZE: u32 = 256;
+
+/// Invocations per compute workgroup on each axis; matches the shader's local size.
+const WORKGROUP_SIZE: u32 = 8;
+
+const GENERATIONS_PER_SECOND: f32 = 15.0;
+
+/// Radius of the brush painting live cells, in cells.
+const BRUSH_RADIUS: f32 = 3.0;
+
+/// Bytes of both images.
+const IMAGES_SIZE: DeviceSize = 2 * (GRID_SIZE * GRID_SIZE * 4) as DeviceSize;
+
+mod compute_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: "
+        #version 460
+
+        layout(local_size_x = 8, local_size_y = 8) in;
+
+        // Red is whether the cell is alive, green a trail fading after it dies.
+        layout(set = 0, binding = 0, rgba8) uniform readonly image2D current;
+        layout(set = 0, binding = 1, rgba8) uniform writeonly image2D next;
+
+        layout(push_constant) uniform LifeParams {
+            // Cells within brush_radius of brush, in cells, come alive.
+            vec2 brush;
+            float brush_radius;
+            // Non-zero fills the grid with random cells instead.
+            uint seed;
+            // Zero copies the grid, only painting it.
+            uint advance;
+        } params;
+
+        float hash(uvec2 p) {
+            uint x = p.x*1973u+p.y*9277u+params.seed*26699u;
+            x ^= x >> 16;
+            x *= 0x7feb352du;
+            x ^= x >> 15;
+            x *= 0x846ca68bu;
+            x ^= x >> 16;
+            return float(x)/4294967295.0;
+        }
+
+        bool alive_at(ivec2 cell, ivec2 size) {
+            return imageLoad(current, (cell+size)%size).r > 0.5;
+        }
+
+        void main() {
+            ivec2 cell = ivec2(gl_GlobalInvocationID.xy);
+            ivec2 size = imageSize(current);
+            if (any(greaterThanEqual(cell, size))) {
+                return;
+            }
+            bool alive = alive_at(cell, size);
+            float trail = imageLoad(current, cell).g;
+            if (params.seed != 0u) {
+                alive = hash(uvec2(cell)) < 0.25;
+                trail = 0.0;
+            } else if (params.advance != 0u) {
+                int neighbors = 0;
+                for (int y = -1; y <= 1; y++) {
+                    for (int x = -1; x <= 1; x++) {
+                        if ((x != 0 || y != 0) && alive_at(cell+ivec2(x, y), size)) {
+                            neighbors++;
+                        }
+                    }
+                }
+                alive = neighbors == 3 || (alive && neighbors == 2);
+                trail = alive ? 1.0 : trail*0.85;
+            }
+            if (distance(vec2(cell)+0.5, params.brush) < params.brush_radius) {
+                alive = true;
+                trail = 1.0;
+            }
+            imageStore(next, cell, vec4(alive ? 1.0 : 0.0, trail, 0.0, 1.0));
+        }
+        "
+    }
+}
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) out vec2 out_uv;
+
+        void main() {
+            vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+            out_uv = uv;
+            gl_Position = vec4(uv*2.0-1.0, 0.0, 1.0);
+        }
+        "
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 in_uv;
+
+        layout(location = 0) out vec4 f_color;
+
+        layout(set = 0, binding = 0) uniform sampler2D grid;
+
+        void main() {
+            vec4 cell = texture(grid, in_uv);
+            vec3 trail = vec3(0.1, 0.3, 0.6)*cell.g;
+            f_color = vec4(cell.r > 0.5 ? vec3(1.0) : trail, 1.0);
+        }
+        "
+    }
+}
+
+/// Conway's Game of Life for [`DemoKind::Life`](super::DemoKind::Life). The grid lives in
+/// two storage images: each frame a compute pass reads one and writes the other,
+/// advancing a generation when one is due and painting the cells under the brush,
+/// and a full-screen triangle then samples the written one. The images go from
+/// being written to being read and back every frame; vulkano puts the barriers
+/// between the passes.
+pub struct LifeDemo {
+    compute_pipeline: Arc<ComputePipeline>,
+    /// `compute_descriptor_sets[i]` reads `images[i]` and writes the other one.
+    compute_descriptor_sets: [Arc<PersistentDescriptorSet>; 2],
+    pipeline: Arc<GraphicsPipeline>,
+    images: [Arc<ImageView<StorageImage>>; 2],
+    sampler: Arc<Sampler>,
+    /// `descriptor_sets[i]` samples `images[i]`; rebuilt with the pipeline.
+    descriptor_sets: [Arc<PersistentDescriptorSet>; 2],
+    /// Index of the image holding the latest generation.
+    current: usize,
+    /// Generations since the start, as of the last step; `None` until the grid has
+    /// been seeded.
+    generation: Option<u64>,
+    /// Cursor to paint under on the next step, normalized to `[0, 1]` across the
+    /// window.
+    brush: Option<[f32; 2]>,
+}
+
+impl LifeDemo {
+    pub fn new(
+        device: &Arc<Device>,
+        queue_family: QueueFamily,
+        target: &RenderTarget,
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        let compute_shader = compute_shader::load(device.clone()).unwrap();
+        let compute_pipeline = ComputePipeline::new(
+            device.clone(),
+            compute_shader.entry_point("main").unwrap(),
+            &(),
+            None,
+            |_| {},
+        )
+        .unwrap();
+
+        // Every implementation supports RGBA8 storage images.
+        let images = [(); 2].map(|()| {
+            let image = StorageImage::with_usage(
+                device.clone(),
+                ImageDimensions::Dim2d {
+                    width: GRID_SIZE,
+                    height: GRID_SIZE,
+                    array_layers: 1,
+                },
+                Format::R8G8B8A8_UNORM,
+                ImageUsage {
+                    storage: true,
+                    sampled: true,
+                    ..ImageUsage::none()
+                },
+                ImageCreateFlags::none(),
+                [queue_family],
+            )
+            .unwrap();
+            ImageView::new_default(image).unwrap()
+        });
+        memory_stats.track(AllocationPurpose::Texture, IMAGES_SIZE);
+
+        let layout = compute_pipeline.layout().set_layouts()[0].clone();
+        let compute_descriptor_sets = [0, 1].map(|source| {
+            PersistentDescriptorSet::new(
+                layout.clone(),
+                [
+                    WriteDescriptorSet::image_view(0, images[source].clone()),
+                    WriteDescriptorSet::image_view(1, images[1 - source].clone()),
+                ],
+            )
+            .unwrap()
+        });
+
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Nearest,
+                min_filter: Filter::Nearest,
+                address_mode: [SamplerAddressMode::Repeat; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let pipeline = create_pipeline(device, target);
+        let descriptor_sets = sampling_descriptor_sets(&pipeline, &images, &sampler);
+        LifeDemo {
+            compute_pipeline,
+            compute_descriptor_sets,
+            pipeline,
+            images,
+            sampler,
+            descriptor_sets,
+            current: 0,
+            generation: None,
+            brush: None,
+        }
+    }
+
+    /// Draws the grid written by the last compute pass over the whole viewport.
+    fn draw<L, P>(&self, builder: &mut AutoCommandBufferBuilder<L, P>, viewport: &Viewport) {
+        builder
+            .set_viewport(0, [viewport.clone()])
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline.layout().clone(),
+                0,
+                self.descriptor_sets[self.current].clone(),
+            )
+            .draw(3, 1, 0, 0)
+            .unwrap();
+    }
+}
+
+impl Demo for LifeDemo {
+    fn recreate_pipelines(&mut self, device: &Arc<Device>, target: &RenderTarget) {
+        let pipeline = create_pipeline(device, target);
+        self.descriptor_sets = sampling_descriptor_sets(&pipeline, &self.images, &self.sampler);
+        self.pipeline = pipeline;
+    }
+
+    /// Paints live cells under the cursor while the button is held.
+    fn handle_input(&mut self, input: &DemoInput, _dimensions: [f32; 2]) {
+        self.brush = input.pressed.then_some(input.cursor);
+    }
+
+    /// Records the compute pass bringing the grid up to the frame's time, seeding
+    /// it on the first call.
+    fn prepare(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &DemoFrame,
+    ) {
+        let generation = (frame.time * GENERATIONS_PER_SECOND) as u64;
+        let (seed, advance) = match self.generation {
+            None => (1, 0),
+            // However many generations are due, one per frame, so a slow frame only
+            // slows the game down.
+            Some(last) => (0, (generation > last) as u32),
+        };
+        self.generation = Some(generation);
+        let (brush, brush_radius) = match self.brush {
+            Some(cursor) => (cursor.map(|x| x * GRID_SIZE as f32), BRUSH_RADIUS),
+            None => ([0.0, 0.0], 0.0),
+        };
+
+        let groups = GRID_SIZE.div_ceil(WORKGROUP_SIZE);
+        builder
+            .bind_pipeline_compute(self.compute_pipeline.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.compute_pipeline.layout().clone(),
+                0,
+                self.compute_descriptor_sets[self.current].clone(),
+            )
+            .push_constants(
+                self.compute_pipeline.layout().clone(),
+                0,
+                compute_shader::ty::LifeParams {
+                    brush,
+                    brush_radius,
+                    seed,
+                    advance,
+                },
+            )
+            .dispatch([groups, groups, 1])
+            .unwrap();
+        self.current = 1 - self.current;
+    }
+
+    fn draw_inline(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &DemoFrame,
+    ) {
+        self.draw(builder, frame.viewport);
+    }
+
+    fn draw_secondary(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &DemoFrame,
+    ) {
+        self.draw(builder, frame.viewport);
+    }
+
+    fn untrack_memory(&self, memory_stats: &mut MemoryStats) {
+        memory_stats.untrack(AllocationPurpose::Texture, IMAGES_SIZE);
+    }
+}
+
+fn sampling_descriptor_sets(
+    pipeline: &Arc<GraphicsPipeline>,
+    images: &[Arc<ImageView<StorageImage>>; 2],
+    sampler: &Arc<Sampler>,
+) -> [Arc<PersistentDescriptorSet>; 2] {
+    images.clone().map(|image| {
+        PersistentDescriptorSet::new(
+            pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                image,
+                sampler.clone(),
+            )],
+        )
+        .unwrap()
+    })
+}
+
+pub fn create_pipeline(device: &Arc<Device>, target: &RenderTarget) -> Arc<GraphicsPipeline> {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = fragment_shader::load(device.clone()).unwrap();
+
+    GraphicsPipeline::start()
+        .render_pass(target.scene())
+        .vertex_input_state(BuffersDefinition::new())
+        .input_assembly_state(InputAssemblyState::new())
+        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+        .build(device.clone())
+        .unwrap()
+}
@@ -0,0 +1,364 @@
+use super::{reflection::assert_shader_layout, textures::ShaderLight};
+use crate::{
+    allocator::{FrameRing, StagingRing},
+    memory::{AllocationPurpose, MemoryStats},
+};
+use bytemuck::{Pod, Zeroable};
+use std::{collections::BTreeMap, f32::consts::PI, mem::size_of, sync::Arc};
+use tracing::warn;
+use vulkano::{
+    buffer::{BufferUsage, DeviceLocalBuffer},
+    command_buffer::{AutoCommandBufferBuilder, FillBufferInfo},
+    descriptor_set::{
+        layout::{
+            DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo,
+            DescriptorType,
+        },
+        PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::{physical::QueueFamily, Device},
+    pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
+    shader::ShaderStages,
+    DeviceSize,
+};
+
+/// Lights the lit material is shaded with at most. Matches `MAX_LIGHTS` in its
+/// shaders, which always read this many.
+pub const MAX_LIGHTS: usize = 256;
+
+/// Clusters along each side of clip space, which the lights are binned into.
+/// Matches `CLUSTERS` in the shaders.
+const CLUSTERS: u32 = 16;
+
+/// Lights a cluster lists at most; the rest don't reach its pixels. Matches
+/// `MAX_LIGHTS_PER_CLUSTER` in the shaders.
+const MAX_LIGHTS_PER_CLUSTER: u32 = 32;
+
+/// Clusters per compute workgroup on each axis; matches the shader's local size.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// How much of a spot light's cone, from its edge in, fades in.
+const SPOT_SOFTNESS: f32 = 0.2;
+
+/// A point or spot light over the scene, shining on the materials made with
+/// [`Renderer::lit_shader`](super::Renderer::lit_shader). It circles the origin as
+/// time passes, with `orbit` as its speed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    /// In clip space, at time 0.
+    pub position: [f32; 2],
+    /// Linear, so above 1 for lights brighter than white.
+    pub color: [f32; 3],
+    /// How far from it its light fades out, in clip space.
+    pub radius: f32,
+    /// Where a spot light points at time 0, in radians clockwise from the right, as
+    /// y points down.
+    pub direction: f32,
+    /// Half the angle a spot light's cone spans, in radians; from `PI` on it's a
+    /// point light.
+    pub cone: f32,
+    /// Radians per second it circles the origin, turning the cone with it.
+    pub orbit: f32,
+}
+
+impl Light {
+    /// Where it is and points `time` seconds in.
+    pub fn animated(&self, time: f32) -> Light {
+        let angle = self.orbit * time;
+        let (sin, cos) = angle.sin_cos();
+        let [x, y] = self.position;
+        Light {
+            position: [x * cos - y * sin, x * sin + y * cos],
+            direction: self.direction + angle,
+            ..*self
+        }
+    }
+
+    pub fn is_spot(&self) -> bool {
+        self.cone < PI
+    }
+
+    fn to_gpu(self) -> GpuLight {
+        let (cos_outer, cos_inner) = if self.is_spot() {
+            let inner = self.cone * (1.0 - SPOT_SOFTNESS);
+            (self.cone.cos(), inner.cos())
+        } else {
+            // Every direction is past the edge and inside.
+            (-2.0, -1.0)
+        };
+        let (sin, cos) = self.direction.sin_cos();
+        GpuLight {
+            position: self.position,
+            direction: [cos, sin],
+            color: [self.color[0], self.color[1], self.color[2], 1.0],
+            radius: self.radius,
+            cos_outer,
+            cos_inner,
+            _padding: 0.0,
+        }
+    }
+}
+
+/// One light as the lit shaders read it. Unused slots have a radius of 0.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+struct GpuLight {
+    position: [f32; 2],
+    /// A unit vector.
+    direction: [f32; 2],
+    color: [f32; 4],
+    radius: f32,
+    cos_outer: f32,
+    cos_inner: f32,
+    _padding: f32,
+}
+assert_shader_layout!(GpuLight, ShaderLight {
+    position => position,
+    direction => direction,
+    color => color,
+    radius => radius,
+    cos_outer => cos_outer,
+    cos_inner => cos_inner,
+});
+
+mod compute_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: "
+        #version 460
+
+        #define MAX_LIGHTS 256
+        #define CLUSTERS 16
+        #define CLUSTER_COUNT 256
+        #define MAX_LIGHTS_PER_CLUSTER 32
+
+        layout(local_size_x = 8, local_size_y = 8) in;
+
+        struct Light {
+            vec2 position;
+            vec2 direction;
+            vec4 color;
+            float radius;
+            float cos_outer;
+            float cos_inner;
+        };
+
+        struct Cluster {
+            uint count;
+            uint lights[MAX_LIGHTS_PER_CLUSTER];
+        };
+
+        layout(set = 0, binding = 0) readonly buffer Lights {
+            Light lights[MAX_LIGHTS];
+        };
+
+        layout(set = 0, binding = 1) writeonly buffer Clusters {
+            uint heatmap;
+            Cluster clusters[CLUSTER_COUNT];
+        };
+
+        layout(push_constant) uniform BinningParams {
+            uint light_count;
+            uint heatmap;
+        } params;
+
+        // Lists the lights whose radius reaches into this invocation's cluster, in
+        // order, up to the most a cluster holds.
+        void main() {
+            uvec2 id = gl_GlobalInvocationID.xy;
+            if (any(greaterThanEqual(id, uvec2(CLUSTERS)))) {
+                return;
+            }
+            if (id == uvec2(0)) {
+                heatmap = params.heatmap;
+            }
+            vec2 lo = vec2(id)*(2.0/CLUSTERS)-1.0;
+            vec2 hi = lo+2.0/CLUSTERS;
+            uint cluster = id.y*CLUSTERS+id.x;
+            uint count = 0;
+            for (uint i = 0; i < params.light_count && count < MAX_LIGHTS_PER_CLUSTER; i++) {
+                vec2 position = lights[i].position;
+                if (distance(clamp(position, lo, hi), position) < lights[i].radius) {
+                    clusters[cluster].lights[count] = i;
+                    count++;
+                }
+            }
+            clusters[cluster].count = count;
+        }
+        "
+    }
+}
+
+/// The scene's lights, uploaded every frame to a storage buffer in a ring of one
+/// per frame in flight, and bound as set 1 of the materials that read it. A compute
+/// pass then bins them into a grid of clusters over clip space, so each pixel only
+/// shades the lights listed for its cluster.
+pub struct Lights {
+    lights: Vec<Light>,
+    ring: FrameRing<GpuLight>,
+    layout: Arc<DescriptorSetLayout>,
+    /// A flag for the heatmap, then each cluster's count and light indices.
+    clusters: Arc<DeviceLocalBuffer<[u32]>>,
+    /// `None` if the queue family can't run it; the clusters then stay empty.
+    binning: Option<Arc<ComputePipeline>>,
+    /// Whether the clusters were zeroed, which the first frame does.
+    cleared: bool,
+    heatmap: bool,
+}
+
+impl Lights {
+    pub fn new(
+        device: &Arc<Device>,
+        queue_family: QueueFamily,
+        frames_in_flight: usize,
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        let binning = if queue_family.supports_compute() {
+            let shader = compute_shader::load(device.clone()).unwrap();
+            Some(
+                ComputePipeline::new(
+                    device.clone(),
+                    shader.entry_point("main").unwrap(),
+                    &(),
+                    None,
+                    |_| {},
+                )
+                .unwrap(),
+            )
+        } else {
+            warn!("queue family doesn't support compute, the lights are disabled");
+            None
+        };
+        let usage = BufferUsage {
+            storage_buffer: true,
+            transfer_dst: true,
+            ..BufferUsage::none()
+        };
+        let clusters =
+            DeviceLocalBuffer::array(device.clone(), clusters_len(), usage, [queue_family])
+                .unwrap();
+        memory_stats.track(
+            AllocationPurpose::Storage,
+            clusters_len() * size_of::<u32>() as DeviceSize,
+        );
+        Lights {
+            lights: Vec::new(),
+            ring: FrameRing::new(
+                device.clone(),
+                BufferUsage::storage_buffer(),
+                AllocationPurpose::Storage,
+                frames_in_flight,
+                MAX_LIGHTS,
+                memory_stats,
+            )
+            .unwrap(),
+            layout: create_layout(device),
+            clusters,
+            binning,
+            cleared: false,
+            heatmap: false,
+        }
+    }
+
+    /// Replaces the lights, keeping the first [`MAX_LIGHTS`].
+    pub fn set(&mut self, lights: &[Light]) {
+        if lights.len() > MAX_LIGHTS {
+            warn!(
+                lights = lights.len(),
+                MAX_LIGHTS, "too many lights, dropping the last ones"
+            );
+        }
+        self.lights = lights.iter().take(MAX_LIGHTS).copied().collect();
+    }
+
+    /// Whether the lit materials show how many lights each cluster lists, from
+    /// blue for none to red for as many as it holds, instead of shading.
+    pub fn set_heatmap(&mut self, enabled: bool) {
+        self.heatmap = enabled;
+    }
+
+    /// The layout of set 1 of the materials that read the lights.
+    pub fn layout(&self) -> &Arc<DescriptorSetLayout> {
+        &self.layout
+    }
+
+    /// Uploads the lights where they are `time` seconds in, records the pass binning
+    /// them into clusters and returns the set they are bound with. Call once a frame,
+    /// outside the render pass: the one chunk starts the frame's buffer, so it's
+    /// aligned for any storage buffer offset alignment.
+    pub fn upload<L, P>(
+        &mut self,
+        time: f32,
+        staging: &mut StagingRing,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        memory_stats: &mut MemoryStats,
+    ) -> Arc<PersistentDescriptorSet> {
+        let mut data = [GpuLight::default(); MAX_LIGHTS];
+        for (slot, light) in data.iter_mut().zip(&self.lights) {
+            *slot = light.animated(time).to_gpu();
+        }
+        let chunk = self.ring.upload(&data, staging, builder, memory_stats);
+        if !self.cleared {
+            builder
+                .fill_buffer(FillBufferInfo::dst_buffer(self.clusters.clone()))
+                .unwrap();
+            self.cleared = true;
+        }
+        if let Some(binning) = &self.binning {
+            let groups = CLUSTERS.div_ceil(WORKGROUP_SIZE);
+            let set = PersistentDescriptorSet::new(
+                binning.layout().set_layouts()[0].clone(),
+                [
+                    WriteDescriptorSet::buffer(0, chunk.clone()),
+                    WriteDescriptorSet::buffer(1, self.clusters.clone()),
+                ],
+            )
+            .unwrap();
+            builder
+                .bind_pipeline_compute(binning.clone())
+                .bind_descriptor_sets(PipelineBindPoint::Compute, binning.layout().clone(), 0, set)
+                .push_constants(
+                    binning.layout().clone(),
+                    0,
+                    compute_shader::ty::BinningParams {
+                        light_count: self.lights.len() as u32,
+                        heatmap: self.heatmap as u32,
+                    },
+                )
+                .dispatch([groups, groups, 1])
+                .unwrap();
+        }
+        PersistentDescriptorSet::new(
+            self.layout.clone(),
+            [
+                WriteDescriptorSet::buffer(0, chunk),
+                WriteDescriptorSet::buffer(1, self.clusters.clone()),
+            ],
+        )
+        .unwrap()
+    }
+}
+
+/// Length of the clusters buffer in `u32`s.
+fn clusters_len() -> DeviceSize {
+    1 + (CLUSTERS * CLUSTERS * (1 + MAX_LIGHTS_PER_CLUSTER)) as DeviceSize
+}
+
+/// The lights at binding 0 and their clusters at binding 1.
+fn create_layout(device: &Arc<Device>) -> Arc<DescriptorSetLayout> {
+    let binding = DescriptorSetLayoutBinding {
+        stages: ShaderStages {
+            fragment: true,
+            ..ShaderStages::none()
+        },
+        ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::StorageBuffer)
+    };
+    DescriptorSetLayout::new(
+        device.clone(),
+        DescriptorSetLayoutCreateInfo {
+            bindings: BTreeMap::from([(0, binding.clone()), (1, binding)]),
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
@@ -0,0 +1,385 @@
+use super::{Topology, Vertex};
+use std::{borrow::Cow, ops::Range, sync::Arc};
+use vulkano::{
+    buffer::{BufferAccess, BufferAccessObject, TypedBufferAccess},
+    command_buffer::AutoCommandBufferBuilder,
+    pipeline::graphics::input_assembly::PrimitiveTopology,
+    DeviceSize,
+};
+
+/// Indexed vertices, as meshes are added and loaded.
+#[derive(Clone, Debug)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Indices,
+    /// How `indices` make up primitives; a triangle list unless set otherwise.
+    pub topology: Topology,
+    /// Ranges of `indices` drawing the mesh in less and less detail, the first the
+    /// most detailed. Empty when there's a single level, all of `indices`.
+    pub lods: Vec<Range<u32>>,
+}
+
+/// Indices into [`Mesh::vertices`], in the smallest type that can address every
+/// vertex.
+#[derive(Clone, Debug)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Mesh {
+    /// Stores `indices` as `u16` when every vertex fits in one, as on most meshes,
+    /// halving the index buffer.
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        let short = (vertices.len() <= u16::MAX as usize + 1)
+            .then(|| {
+                indices
+                    .iter()
+                    .map(|&index| u16::try_from(index))
+                    .collect::<Result<Vec<_>, _>>()
+                    .ok()
+            })
+            .flatten();
+        let indices = match short {
+            Some(indices) => Indices::U16(indices),
+            None => Indices::U32(indices),
+        };
+        Mesh {
+            vertices,
+            indices,
+            topology: Topology::default(),
+            lods: Vec::new(),
+        }
+    }
+
+    /// A mesh with a level of detail for each of `levels`, the first the most
+    /// detailed, all indexing the same `vertices`.
+    pub fn from_lods(vertices: Vec<Vertex>, levels: Vec<Vec<u32>>) -> Self {
+        let mut lods = Vec::with_capacity(levels.len());
+        let mut indices = Vec::new();
+        for level in levels {
+            let start = indices.len() as u32;
+            indices.extend(level);
+            lods.push(start..indices.len() as u32);
+        }
+        Mesh {
+            lods,
+            ..Mesh::new(vertices, indices)
+        }
+    }
+
+    /// The same vertices and indices, assembled as `topology`.
+    pub fn with_topology(self, topology: Topology) -> Self {
+        Mesh { topology, ..self }
+    }
+
+    /// The indices as the input assembler reads them with
+    /// [`Topology::primitive_topology`]: a triangle fan's are rearranged into a list,
+    /// each level of detail on its own.
+    pub fn assembled_indices(&self) -> Cow<'_, Indices> {
+        let lods = self.levels();
+        match (self.topology, &self.indices) {
+            (Topology::TriangleFan, Indices::U16(fan)) => {
+                Cow::Owned(Indices::U16(fans_to_list(fan, &lods)))
+            }
+            (Topology::TriangleFan, Indices::U32(fan)) => {
+                Cow::Owned(Indices::U32(fans_to_list(fan, &lods)))
+            }
+            _ => Cow::Borrowed(&self.indices),
+        }
+    }
+
+    /// The levels of detail as ranges of [`Self::assembled_indices`], at least one.
+    pub fn assembled_lods(&self) -> Vec<Range<u32>> {
+        let mut start = 0;
+        self.levels()
+            .into_iter()
+            .map(|lod| {
+                let count = match self.topology {
+                    Topology::TriangleFan => 3 * lod.len().saturating_sub(2) as u32,
+                    _ => lod.len() as u32,
+                };
+                start += count;
+                start - count..start
+            })
+            .collect()
+    }
+
+    /// [`Self::lods`], or all the indices when there's a single level.
+    fn levels(&self) -> Vec<Range<u32>> {
+        if self.lods.is_empty() {
+            let all = 0..self.indices.len() as u32;
+            vec![all]
+        } else {
+            self.lods.clone()
+        }
+    }
+
+    /// Each vertex once, in order.
+    pub fn triangle_list(vertices: Vec<Vertex>) -> Self {
+        let indices = (0..vertices.len() as u32).collect();
+        Mesh::new(vertices, indices)
+    }
+
+    /// Fails if there's nothing to draw, an index is past the vertices, a level of
+    /// detail is past the indices or a triangle fan's has no triangle.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.vertices.is_empty() || self.indices.len() == 0 {
+            return Err("no triangles".to_owned());
+        }
+        for lod in &self.lods {
+            if lod.is_empty() || lod.end as usize > self.indices.len() {
+                return Err(format!(
+                    "level of detail {:?} isn't within the {} indices",
+                    lod,
+                    self.indices.len()
+                ));
+            }
+        }
+        if self.topology == Topology::TriangleFan {
+            if let Some(lod) = self.levels().into_iter().find(|lod| lod.len() < 3) {
+                return Err(format!("triangle fan {:?} has fewer than 3 indices", lod));
+            }
+        }
+        let max = match &self.indices {
+            Indices::U16(indices) => indices.iter().map(|&index| index as usize).max(),
+            Indices::U32(indices) => indices.iter().map(|&index| index as usize).max(),
+        };
+        match max {
+            Some(max) if max >= self.vertices.len() => Err(format!(
+                "index {} is past the {} vertices",
+                max,
+                self.vertices.len()
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Indices {
+    pub fn len(&self) -> usize {
+        match self {
+            Indices::U16(indices) => indices.len(),
+            Indices::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        match self {
+            Indices::U16(indices) => bytemuck::cast_slice(indices),
+            Indices::U32(indices) => bytemuck::cast_slice(indices),
+        }
+    }
+}
+
+/// The triangles of each of the fans at `ranges` of `fans`, one after another.
+fn fans_to_list<T: Copy>(fans: &[T], ranges: &[Range<u32>]) -> Vec<T> {
+    ranges
+        .iter()
+        .flat_map(|range| fan_to_list(&fans[range.start as usize..range.end as usize]))
+        .collect()
+}
+
+/// The triangles of the fan around `fan[0]`, one after another.
+fn fan_to_list<T: Copy>(fan: &[T]) -> Vec<T> {
+    match fan.split_first() {
+        Some((&center, rim)) => rim
+            .windows(2)
+            .flat_map(|edge| [center, edge[0], edge[1]])
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// The index buffer of a [`MeshBuffer`], typed as its [`Indices`] were.
+#[derive(Clone)]
+pub enum IndexBuffer {
+    U16(Arc<dyn TypedBufferAccess<Content = [u16]>>),
+    U32(Arc<dyn TypedBufferAccess<Content = [u32]>>),
+}
+
+impl IndexBuffer {
+    fn size(&self) -> DeviceSize {
+        match self {
+            IndexBuffer::U16(buffer) => buffer.size(),
+            IndexBuffer::U32(buffer) => buffer.size(),
+        }
+    }
+}
+
+/// A mesh in the renderer's [`VertexFormat`](super::VertexFormat), in host-visible
+/// memory or uploaded by the [`Uploader`](super::Uploader): a vertex buffer per
+/// stream and the index buffer, with the topology the indices are drawn as.
+#[derive(Clone)]
+pub struct MeshBuffer {
+    streams: Vec<Arc<dyn BufferAccess>>,
+    indices: IndexBuffer,
+    vertex_count: u32,
+    topology: PrimitiveTopology,
+    /// Ranges of `indices`, the first the most detailed and starting at 0.
+    lods: Vec<Range<u32>>,
+}
+
+impl MeshBuffer {
+    /// `indices` and `lods` as [`Mesh::assembled_indices`] and
+    /// [`Mesh::assembled_lods`] return them.
+    pub fn new(
+        streams: Vec<Arc<dyn BufferAccess>>,
+        indices: IndexBuffer,
+        vertex_count: u32,
+        topology: Topology,
+        lods: Vec<Range<u32>>,
+    ) -> Self {
+        MeshBuffer {
+            streams,
+            indices,
+            vertex_count,
+            topology: topology.primitive_topology(),
+            lods,
+        }
+    }
+
+    /// What the pipelines drawing it assemble its indices as.
+    pub fn topology(&self) -> PrimitiveTopology {
+        self.topology
+    }
+
+    pub fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    /// Indices of the most detailed level, which start the index buffer.
+    pub fn index_count(&self) -> u32 {
+        self.lods[0].end
+    }
+
+    /// The indices drawing level of detail `level`, or the least detailed level
+    /// past the last.
+    pub fn lod(&self, level: u32) -> Range<u32> {
+        let last = self.lods.len() - 1;
+        self.lods[(level as usize).min(last)].clone()
+    }
+
+    /// Bytes of all its buffers.
+    pub fn size(&self) -> DeviceSize {
+        self.streams
+            .iter()
+            .map(|stream| stream.size())
+            .sum::<DeviceSize>()
+            + self.indices.size()
+    }
+
+    /// Binds its buffers with `instances` after them, and its index buffer.
+    pub fn bind<L, P>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        instances: &impl BufferAccessObject,
+    ) {
+        let mut buffers = self.streams.clone();
+        buffers.push(instances.as_buffer_access_object());
+        builder.bind_vertex_buffers(0, buffers);
+        // `bind_index_buffer` takes a sized buffer type; the `Arc` is one.
+        match &self.indices {
+            IndexBuffer::U16(buffer) => builder.bind_index_buffer(Arc::new(buffer.clone())),
+            IndexBuffer::U32(buffer) => builder.bind_index_buffer(Arc::new(buffer.clone())),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertices(count: usize) -> Vec<Vertex> {
+        vec![Vertex::default(); count]
+    }
+
+    #[test]
+    fn every_vertex_addressable_by_u16_gets_u16_indices() {
+        let mesh = Mesh::new(vertices(65536), vec![0, 1, 65535]);
+        assert!(matches!(&mesh.indices, Indices::U16(indices) if indices == &[0, 1, 65535]));
+        assert_eq!(mesh.indices.bytes().len(), 6);
+        assert!(mesh.validate().is_ok());
+    }
+
+    #[test]
+    fn more_vertices_than_u16_addresses_get_u32_indices() {
+        // Even though these indices would fit, the mesh has a vertex they can't reach.
+        let mesh = Mesh::new(vertices(65537), vec![0, 1, 2]);
+        assert!(matches!(&mesh.indices, Indices::U32(indices) if indices == &[0, 1, 2]));
+        assert_eq!(mesh.indices.bytes().len(), 12);
+
+        let mesh = Mesh::new(vertices(65537), vec![0, 1, 65536]);
+        assert!(matches!(&mesh.indices, Indices::U32(indices) if indices == &[0, 1, 65536]));
+        assert!(mesh.validate().is_ok());
+    }
+
+    #[test]
+    fn indices_past_u16_stay_u32_and_fail_validation() {
+        let mesh = Mesh::new(vertices(3), vec![0, 1, 65536]);
+        assert!(matches!(mesh.indices, Indices::U32(_)));
+        assert_eq!(
+            mesh.validate().unwrap_err(),
+            "index 65536 is past the 3 vertices"
+        );
+    }
+
+    fn fan(indices: Vec<u32>, lods: Vec<Range<u32>>) -> Mesh {
+        Mesh {
+            lods,
+            ..Mesh::new(vertices(8), indices).with_topology(Topology::TriangleFan)
+        }
+    }
+
+    fn assembled(mesh: &Mesh) -> Vec<u16> {
+        match &*mesh.assembled_indices() {
+            Indices::U16(indices) => indices.clone(),
+            Indices::U32(_) => panic!("expected u16 indices"),
+        }
+    }
+
+    #[test]
+    fn fans_become_lists_wound_the_same_way() {
+        // Each triangle goes center, rim, next rim, as the fan's would.
+        let mesh = fan(vec![0, 1, 2, 3, 4], Vec::new());
+        assert_eq!(assembled(&mesh), [0, 1, 2, 0, 2, 3, 0, 3, 4]);
+        assert_eq!(mesh.assembled_lods(), vec![0..9]);
+        assert_eq!(
+            mesh.topology.primitive_topology(),
+            PrimitiveTopology::TriangleList
+        );
+    }
+
+    #[test]
+    fn each_level_of_detail_is_a_fan_of_its_own() {
+        let mesh = fan(vec![0, 1, 2, 3, 5, 6, 7], vec![0..4, 4..7]);
+        assert_eq!(assembled(&mesh), [0, 1, 2, 0, 2, 3, 5, 6, 7]);
+        assert_eq!(mesh.assembled_lods(), [0..6, 6..9]);
+    }
+
+    #[test]
+    fn other_topologies_are_assembled_as_they_are() {
+        let mesh = Mesh::new(vertices(4), vec![0, 1, 2, 3]).with_topology(Topology::TriangleStrip);
+        assert_eq!(assembled(&mesh), [0, 1, 2, 3]);
+        assert_eq!(mesh.assembled_lods(), vec![0..4]);
+    }
+
+    #[test]
+    fn fans_of_fewer_than_three_indices_have_no_triangles() {
+        for indices in [vec![0], vec![0, 1]] {
+            let mesh = fan(indices, Vec::new());
+            assert!(assembled(&mesh).is_empty());
+            assert_eq!(mesh.assembled_lods(), vec![0..0]);
+            assert!(mesh.validate().is_err());
+        }
+        assert!(fan_to_list::<u16>(&[]).is_empty());
+
+        let mesh = fan(vec![0, 1, 2, 3, 4], vec![0..3, 3..5]);
+        assert_eq!(assembled(&mesh), [0, 1, 2]);
+        assert_eq!(mesh.assembled_lods(), [0..3, 3..3]);
+        assert_eq!(
+            mesh.validate().unwrap_err(),
+            "triangle fan 3..5 has fewer than 3 indices"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use super::post;
+use crate::memory::{AllocationPurpose, MemoryStats};
+use std::sync::Arc;
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::WriteDescriptorSet,
+    device::Device,
+    image::{view::ImageView, AttachmentImage},
+    pipeline::{graphics::viewport::Viewport, GraphicsPipeline},
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+};
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(set = 0, binding = 0) uniform sampler2D scene;
+        layout(set = 0, binding = 1) uniform sampler2D motion;
+
+        layout(location = 0) out vec4 f_color;
+
+        layout(push_constant) uniform MotionBlurParams {
+            uint samples;
+            float shutter;
+        } params;
+
+        void main() {
+            vec2 size = vec2(textureSize(scene, 0));
+            vec2 uv = gl_FragCoord.xy/size;
+            // How far what's at the pixel moved while the shutter was open, centered
+            // on where it is now.
+            vec2 streak = texelFetch(motion, ivec2(gl_FragCoord.xy), 0).xy*params.shutter;
+            vec2 pixels = streak*size;
+            if (dot(pixels, pixels) < 0.25) {
+                f_color = texture(scene, uv);
+                return;
+            }
+            // Shifts the samples along the streak by a fraction of the step that
+            // changes from pixel to pixel, so few samples read as grain instead of
+            // copies.
+            float offset = fract(52.9829189*fract(dot(gl_FragCoord.xy, vec2(0.06711056, 0.00583715))));
+            vec4 sum = vec4(0.0);
+            for (uint i = 0u; i < params.samples; i++) {
+                float t = (float(i)+offset)/float(params.samples)-0.5;
+                sum += texture(scene, uv+streak*t);
+            }
+            f_color = sum/float(params.samples);
+        }
+        "
+    }
+}
+
+/// How [`MotionBlur`] streaks what moves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MotionBlurSettings {
+    /// Taps along each pixel's motion; more make long streaks smoother.
+    pub samples: u32,
+    /// Fraction of the frame the shutter is open for, scaling how long the streaks
+    /// are; 0.5 is a film camera's 180 degree shutter.
+    pub shutter: f32,
+}
+
+impl Default for MotionBlurSettings {
+    fn default() -> Self {
+        MotionBlurSettings {
+            samples: 8,
+            shutter: 0.5,
+        }
+    }
+}
+
+/// Motion blur: averages the frame along each pixel's motion vector, so what moves
+/// streaks by how far it went since the last frame, longer at low frame rates as a
+/// camera's would. Needs dynamic rendering, as it reads the frame's neighboring
+/// pixels, which an input attachment can't.
+pub struct MotionBlur {
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    image: Arc<ImageView<AttachmentImage>>,
+    dimensions: [u32; 2],
+    settings: MotionBlurSettings,
+}
+
+impl MotionBlur {
+    pub fn new(
+        device: &Arc<Device>,
+        settings: MotionBlurSettings,
+        dimensions: [u32; 2],
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        memory_stats.track(AllocationPurpose::Texture, post::target_size(dimensions));
+        MotionBlur {
+            pipeline: post::create_pipeline(
+                device,
+                &fragment_shader::load(device.clone()).unwrap(),
+            ),
+            sampler,
+            image: post::create_target(device, dimensions),
+            dimensions,
+            settings,
+        }
+    }
+
+    /// Recreates the image for a viewport of `dimensions`, if they changed.
+    pub fn resize(
+        &mut self,
+        device: &Arc<Device>,
+        dimensions: [u32; 2],
+        memory_stats: &mut MemoryStats,
+    ) {
+        if dimensions == self.dimensions {
+            return;
+        }
+        memory_stats.untrack(
+            AllocationPurpose::Texture,
+            post::target_size(self.dimensions),
+        );
+        memory_stats.track(AllocationPurpose::Texture, post::target_size(dimensions));
+        self.image = post::create_target(device, dimensions);
+        self.dimensions = dimensions;
+    }
+
+    pub fn untrack_memory(&self, memory_stats: &mut MemoryStats) {
+        memory_stats.untrack(
+            AllocationPurpose::Texture,
+            post::target_size(self.dimensions),
+        );
+    }
+
+    /// What the last [`Self::draw`] wrote, for the output pass to show.
+    pub fn image(&self) -> &Arc<ImageView<AttachmentImage>> {
+        &self.image
+    }
+
+    /// Blurs `scene` along `motion` in a rendering scope of its own. Call between the
+    /// scene's and the output pass's.
+    pub fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        scene: &Arc<ImageView<AttachmentImage>>,
+        motion: &Arc<ImageView<AttachmentImage>>,
+        viewport: &Viewport,
+    ) {
+        post::draw(
+            builder,
+            &self.pipeline,
+            &self.image,
+            [
+                WriteDescriptorSet::image_view_sampler(0, scene.clone(), self.sampler.clone()),
+                WriteDescriptorSet::image_view_sampler(1, motion.clone(), self.sampler.clone()),
+            ],
+            fragment_shader::ty::MotionBlurParams {
+                samples: self.settings.samples,
+                shutter: self.settings.shutter,
+            },
+            viewport,
+        );
+    }
+}
@@ -0,0 +1,419 @@
+use super::{
+    demo::{Demo, DemoFrame},
+    reflection::assert_shader_layout,
+    vertex_format::MeshVertices,
+    MeshBuffer, RenderTarget, VertexFormat,
+};
+use crate::memory::{AllocationPurpose, MemoryStats};
+use bytemuck::{Pod, Zeroable};
+use std::{f32::consts::TAU, mem::size_of, sync::Arc};
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer, DeviceLocalBuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, CopyBufferInfo, PrimaryAutoCommandBuffer,
+        SecondaryAutoCommandBuffer,
+    },
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    device::{physical::QueueFamily, Device},
+    impl_vertex,
+    pipeline::{
+        graphics::{
+            color_blend::{AttachmentBlend, ColorBlendState},
+            input_assembly::InputAssemblyState,
+            viewport::{Viewport, ViewportState},
+        },
+        ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    DeviceSize,
+};
+
+/// Invocations per compute workgroup, and bodies per tile of shared memory; matches
+/// `local_size_x` and `TILE_SIZE` in the shader.
+const WORKGROUP_SIZE: u32 = 256;
+
+/// Most bodies `--bodies` accepts. Every body pulls on every other, so a step costs
+/// the square of the count: at this many it's tens of billions of interactions, a
+/// stress test more than an animation.
+pub const MAX_NBODY_BODIES: u32 = 512 * 1024;
+
+/// Longest step the simulation takes, so a slow frame doesn't fling the bodies
+/// apart. Past a few thousand bodies frames take longer than this, and the
+/// simulation runs slower than real time instead.
+const MAX_STEP: f32 = 1.0 / 30.0;
+
+/// The gravitational constant times the mass of all bodies together, in clip space
+/// units cubed per second squared; each body gets an equal share.
+const GRAVITY: f32 = 0.5;
+
+/// Added to every distance, in clip space units, so close encounters don't sling
+/// bodies out at absurd speeds.
+const SOFTENING: f32 = 0.02;
+
+/// Radius of the initial disk.
+const DISK_RADIUS: f32 = 0.8;
+
+/// Scale of the mesh per body.
+const BODY_SIZE: f32 = 0.005;
+
+/// Bodies at this speed are drawn white-hot; slower ones are bluer.
+const HOT_SPEED: f32 = 1.5;
+
+/// One body as laid out in the storage buffers, which are also bound as instance
+/// buffers for drawing.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+struct GpuBody {
+    body_position: [f32; 2],
+    velocity: [f32; 2],
+}
+impl_vertex!(GpuBody, body_position, velocity);
+assert_shader_layout!(GpuBody, compute_shader::ty::Body {
+    body_position => position,
+    velocity => velocity,
+});
+
+mod compute_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: "
+        #version 460
+
+        #define TILE_SIZE 256
+
+        layout(local_size_x = TILE_SIZE) in;
+
+        struct Body {
+            vec2 position;
+            vec2 velocity;
+        };
+
+        layout(set = 0, binding = 0) readonly buffer Source {
+            Body bodies[];
+        } source;
+
+        layout(set = 0, binding = 1) writeonly buffer Destination {
+            Body bodies[];
+        } destination;
+
+        layout(push_constant) uniform NBodyParams {
+            float gravity;
+            float softening;
+            float dt;
+            uint count;
+        } params;
+
+        // Positions of a tile of bodies, and their mass: 1, or 0 past the end.
+        shared vec3 tile[TILE_SIZE];
+
+        void main() {
+            uint i = gl_GlobalInvocationID.x;
+            uint local = gl_LocalInvocationID.x;
+            // Invocations past the end still load their share of every tile.
+            Body body = source.bodies[min(i, params.count-1u)];
+            float softening = params.softening*params.softening;
+
+            // The workgroup walks all bodies a tile at a time: each invocation loads
+            // one into shared memory, then all of them read the whole tile from
+            // there. That fetches every body from the storage buffer once per
+            // workgroup rather than once per invocation.
+            vec2 acceleration = vec2(0.0);
+            for (uint start = 0u; start < params.count; start += TILE_SIZE) {
+                uint j = start+local;
+                tile[local] = j < params.count ? vec3(source.bodies[j].position, 1.0) : vec3(0.0);
+                barrier();
+                for (uint k = 0u; k < TILE_SIZE; k++) {
+                    vec2 d = tile[k].xy-body.position;
+                    float r2 = dot(d, d)+softening;
+                    // Itself is at distance zero, and pulls nothing.
+                    acceleration += d*(tile[k].z*inversesqrt(r2*r2*r2));
+                }
+                barrier();
+            }
+            if (i >= params.count) {
+                return;
+            }
+
+            // Semi-implicit Euler, which keeps orbits from spiraling outwards.
+            body.velocity += acceleration*params.gravity*params.dt;
+            body.position += body.velocity*params.dt;
+            destination.bodies[i] = body;
+        }
+        "
+    }
+}
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 position;
+        layout(location = 1) in vec4 color;
+        layout(location = 2) in vec2 body_position;
+        layout(location = 3) in vec2 velocity;
+
+        layout(location = 0) out vec4 out_color;
+
+        layout(push_constant) uniform DrawParams {
+            float size;
+            float hot_speed;
+        } params;
+
+        void main() {
+            float heat = clamp(length(velocity)/params.hot_speed, 0.0, 1.0);
+            out_color = color*mix(vec4(0.1, 0.2, 0.6, 1.0), vec4(1.0, 0.9, 0.7, 1.0), heat);
+            gl_Position = vec4(body_position+position*params.size, 0.0, 1.0);
+        }
+        "
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec4 in_color;
+
+        layout(location = 0) out vec4 f_color;
+
+        void main() {
+            f_color = in_color;
+        }
+        "
+    }
+}
+
+/// A disk of bodies pulling on each other, simulated by a compute shader for
+/// [`DemoKind::NBody`](super::DemoKind::NBody) and drawn as instances of the mesh. Like
+/// [`GpuParticles`](super::gpu_particles::GpuParticles), each frame a compute pass
+/// reads one storage buffer and writes the other, and the written one is the
+/// instance buffer of the draw that follows in the same command buffer.
+pub struct NBodyDemo {
+    count: u32,
+    compute_pipeline: Arc<ComputePipeline>,
+    pipeline: Arc<GraphicsPipeline>,
+    /// Of the meshes drawn per body.
+    vertex_format: VertexFormat,
+    buffers: [Arc<DeviceLocalBuffer<[GpuBody]>>; 2],
+    /// `descriptor_sets[i]` reads `buffers[i]` and writes the other one.
+    descriptor_sets: [Arc<PersistentDescriptorSet>; 2],
+    /// Index of the buffer holding the latest state.
+    current: usize,
+    /// Copied into both buffers by the first frame, then dropped.
+    initial_state: Option<Arc<CpuAccessibleBuffer<[GpuBody]>>>,
+    last_time: Option<f32>,
+}
+
+impl NBodyDemo {
+    pub fn new(
+        device: &Arc<Device>,
+        queue_family: QueueFamily,
+        target: &RenderTarget,
+        vertex_format: VertexFormat,
+        count: u32,
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        let compute_shader = compute_shader::load(device.clone()).unwrap();
+        let compute_pipeline = ComputePipeline::new(
+            device.clone(),
+            compute_shader.entry_point("main").unwrap(),
+            &(),
+            None,
+            |_| {},
+        )
+        .unwrap();
+
+        let usage = BufferUsage {
+            storage_buffer: true,
+            vertex_buffer: true,
+            transfer_dst: true,
+            ..BufferUsage::none()
+        };
+        let buffers = [(); 2].map(|()| {
+            DeviceLocalBuffer::array(device.clone(), count as DeviceSize, usage, [queue_family])
+                .unwrap()
+        });
+        memory_stats.track(AllocationPurpose::Storage, buffers_size(count));
+
+        let layout = compute_pipeline.layout().set_layouts()[0].clone();
+        let descriptor_sets = [0, 1].map(|source| {
+            PersistentDescriptorSet::new(
+                layout.clone(),
+                [
+                    WriteDescriptorSet::buffer(0, buffers[source].clone()),
+                    WriteDescriptorSet::buffer(1, buffers[1 - source].clone()),
+                ],
+            )
+            .unwrap()
+        });
+
+        let initial_state = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::transfer_src(),
+            false,
+            (0..count).map(initial_body),
+        )
+        .unwrap();
+
+        NBodyDemo {
+            count,
+            compute_pipeline,
+            pipeline: create_pipeline(device, target, vertex_format),
+            vertex_format,
+            buffers,
+            descriptor_sets,
+            current: 0,
+            initial_state: Some(initial_state),
+            last_time: None,
+        }
+    }
+
+    /// Draws the state written by the last compute pass, a copy of `mesh` per body,
+    /// blended additively.
+    fn draw<L, P>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        viewport: &Viewport,
+        mesh: &MeshBuffer,
+    ) {
+        builder
+            .set_viewport(0, [viewport.clone()])
+            .bind_pipeline_graphics(self.pipeline.clone());
+        mesh.bind(builder, &self.buffers[self.current]);
+        builder
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                vertex_shader::ty::DrawParams {
+                    size: BODY_SIZE,
+                    hot_speed: HOT_SPEED,
+                },
+            )
+            .draw_indexed(mesh.index_count(), self.count, 0, 0, 0)
+            .unwrap();
+    }
+}
+
+impl Demo for NBodyDemo {
+    fn recreate_pipelines(&mut self, device: &Arc<Device>, target: &RenderTarget) {
+        self.pipeline = create_pipeline(device, target, self.vertex_format);
+    }
+
+    /// Records the compute pass advancing the simulation to the frame's time.
+    fn prepare(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &DemoFrame,
+    ) {
+        let time = frame.time;
+        if let Some(initial_state) = self.initial_state.take() {
+            for buffer in &self.buffers {
+                builder
+                    .copy_buffer(CopyBufferInfo::buffers(
+                        initial_state.clone(),
+                        buffer.clone(),
+                    ))
+                    .unwrap();
+            }
+        }
+        let dt = self
+            .last_time
+            .map_or(0.0, |last_time| (time - last_time).clamp(0.0, MAX_STEP));
+        self.last_time = Some(time);
+
+        builder
+            .bind_pipeline_compute(self.compute_pipeline.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.compute_pipeline.layout().clone(),
+                0,
+                self.descriptor_sets[self.current].clone(),
+            )
+            .push_constants(
+                self.compute_pipeline.layout().clone(),
+                0,
+                compute_shader::ty::NBodyParams {
+                    gravity: GRAVITY / self.count as f32,
+                    softening: SOFTENING,
+                    dt,
+                    count: self.count,
+                },
+            )
+            .dispatch([self.count.div_ceil(WORKGROUP_SIZE), 1, 1])
+            .unwrap();
+        self.current = 1 - self.current;
+    }
+
+    fn draw_inline(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &DemoFrame,
+    ) {
+        self.draw(builder, frame.viewport, frame.mesh);
+    }
+
+    fn draw_secondary(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &DemoFrame,
+    ) {
+        self.draw(builder, frame.viewport, frame.mesh);
+    }
+
+    fn untrack_memory(&self, memory_stats: &mut MemoryStats) {
+        memory_stats.untrack(AllocationPurpose::Storage, buffers_size(self.count));
+    }
+}
+
+/// Bytes of both storage buffers for `count` bodies.
+fn buffers_size(count: u32) -> DeviceSize {
+    2 * count as DeviceSize * size_of::<GpuBody>() as DeviceSize
+}
+
+/// Body `i` of `count`, spread evenly over a disk and set on a circular orbit
+/// around the mass inside its radius.
+fn initial_body(i: u32) -> GpuBody {
+    // Square roots spread them by area; the innermost hundredth is left empty.
+    let radius = DISK_RADIUS * (0.01 + 0.99 * hash(2 * i)).sqrt();
+    let angle = TAU * hash(2 * i + 1);
+    let (sin, cos) = angle.sin_cos();
+    // The disk is uniform, so the mass inside grows with the area.
+    let enclosed = (radius / DISK_RADIUS).powi(2);
+    let speed = (GRAVITY * enclosed / radius).sqrt();
+    GpuBody {
+        body_position: [radius * cos, radius * sin],
+        velocity: [-speed * sin, speed * cos],
+    }
+}
+
+/// A pseudo-random number in `[0, 1]`, the same hash the particles' shader uses.
+pub(super) fn hash(mut x: u32) -> f32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x as f32 / u32::MAX as f32
+}
+
+pub fn create_pipeline(
+    device: &Arc<Device>,
+    target: &RenderTarget,
+    vertex_format: VertexFormat,
+) -> Arc<GraphicsPipeline> {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = fragment_shader::load(device.clone()).unwrap();
+    GraphicsPipeline::start()
+        .render_pass(target.scene())
+        .vertex_input_state(MeshVertices::<GpuBody>::new(vertex_format))
+        .input_assembly_state(InputAssemblyState::new())
+        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+        .color_blend_state(ColorBlendState::new(1).blend(AttachmentBlend::additive()))
+        .build(device.clone())
+        .unwrap()
+}
@@ -0,0 +1,318 @@
+use super::{DrawBatch, InstanceData, RenderTarget, Vertex};
+use crate::{
+    allocator::{FrameChunk, FrameRing, StagingRing},
+    memory::{AllocationPurpose, MemoryStats},
+};
+use bytemuck::{Pod, Zeroable};
+use std::{ops::Range, sync::Arc};
+use tracing::warn;
+use vulkano::{
+    buffer::BufferUsage,
+    command_buffer::AutoCommandBufferBuilder,
+    device::Device,
+    impl_vertex,
+    pipeline::{
+        graphics::{
+            color_blend::{ColorBlendState, ColorComponents},
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
+            input_assembly::InputAssemblyState,
+            vertex_input::BuffersDefinition,
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, StateMode,
+    },
+    query::{QueryControlFlags, QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType},
+};
+
+/// Consecutive instances tested and culled together.
+pub const CLUSTER_SIZE: u32 = 64;
+
+/// Clusters per frame that get a query; the instances past them are always drawn.
+const MAX_CLUSTERS: u32 = 4096;
+
+/// Clip space box around a cluster, at the depth of its nearest instance.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct ClusterBounds {
+    bounds_min: [f32; 2],
+    bounds_max: [f32; 2],
+    depth: f32,
+}
+impl_vertex!(ClusterBounds, bounds_min, bounds_max, depth);
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 bounds_min;
+        layout(location = 1) in vec2 bounds_max;
+        layout(location = 2) in float depth;
+
+        const vec2 corners[6] = vec2[](
+            vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0),
+            vec2(0.0, 1.0), vec2(1.0, 0.0), vec2(1.0, 1.0)
+        );
+
+        void main() {
+            gl_Position = vec4(mix(bounds_min, bounds_max, corners[gl_VertexIndex]), depth, 1.0);
+        }
+        "
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) out vec4 f_color;
+
+        void main() {
+            f_color = vec4(0.0);
+        }
+        "
+    }
+}
+
+/// Depth the instance vertex shader gives `instance`; later instances are nearer.
+fn instance_depth(instance: u32) -> f32 {
+    (1.0 - (instance + 1) as f32 / 65536.0).max(0.0)
+}
+
+/// Half the size of the box around `vertices`, centered on the mesh's origin.
+pub fn mesh_extent(vertices: &[Vertex]) -> [f32; 2] {
+    vertices.iter().fold([0.0; 2], |extent, vertex| {
+        [0, 1].map(|axis| extent[axis].max(vertex.position[axis].abs()))
+    })
+}
+
+/// Occlusion queries against the depth pre-pass, one per cluster of instances.
+/// Clusters none of whose box passed the depth test in the last frame read back
+/// are left out of the main pass. The pre-pass still draws them, so they come back
+/// as soon as they are uncovered, a few frames late at most.
+pub struct OcclusionCulling {
+    pipeline: Arc<GraphicsPipeline>,
+    /// [`MAX_CLUSTERS`] queries per frame in flight.
+    query_pool: Arc<QueryPool>,
+    bounds: Vec<ClusterBounds>,
+    bounds_ring: FrameRing<ClusterBounds>,
+    /// Clusters each frame in flight queried, read back once its fence signals.
+    queried: Vec<u32>,
+    /// By cluster, whether any of its box was visible.
+    visible: Vec<bool>,
+}
+
+impl OcclusionCulling {
+    pub fn new(
+        device: &Arc<Device>,
+        target: &RenderTarget,
+        frames_in_flight: usize,
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        let query_pool = QueryPool::new(
+            device.clone(),
+            QueryPoolCreateInfo {
+                query_count: MAX_CLUSTERS * frames_in_flight as u32,
+                ..QueryPoolCreateInfo::query_type(QueryType::Occlusion)
+            },
+        )
+        .unwrap();
+        OcclusionCulling {
+            pipeline: create_pipeline(device, target),
+            query_pool,
+            bounds: Vec::new(),
+            bounds_ring: FrameRing::new(
+                device.clone(),
+                BufferUsage::vertex_buffer(),
+                AllocationPurpose::Instance,
+                frames_in_flight,
+                MAX_CLUSTERS as usize,
+                memory_stats,
+            )
+            .unwrap(),
+            queried: vec![0; frames_in_flight],
+            visible: Vec::new(),
+        }
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: Arc<GraphicsPipeline>) {
+        self.pipeline = pipeline;
+    }
+
+    /// Bounds every cluster of `instances` as the vertex shader will place them:
+    /// each mesh vertex is scaled by `scale` and pushed up to `wobble` along each
+    /// axis before the instance's basis is applied.
+    pub fn update(
+        &mut self,
+        instances: &[InstanceData],
+        batches: &[DrawBatch],
+        mesh_extents: &[[f32; 2]],
+        scale: [f32; 2],
+        wobble: f32,
+    ) {
+        let clusters = instances.len().div_ceil(CLUSTER_SIZE as usize);
+        self.bounds.clear();
+        self.bounds
+            .extend((0..clusters.min(MAX_CLUSTERS as usize)).map(|cluster| {
+                let last = ((cluster + 1) * CLUSTER_SIZE as usize).min(instances.len()) - 1;
+                ClusterBounds {
+                    bounds_min: [f32::INFINITY; 2],
+                    bounds_max: [f32::NEG_INFINITY; 2],
+                    depth: instance_depth(last as u32),
+                }
+            }));
+        for batch in batches {
+            let extent = mesh_extents.get(batch.mesh.0).unwrap_or(&mesh_extents[0]);
+            let half = [0, 1].map(|axis| extent[axis] * scale[axis] + wobble);
+            for index in batch.instances.clone() {
+                let bounds = match self.bounds.get_mut((index / CLUSTER_SIZE) as usize) {
+                    Some(bounds) => bounds,
+                    None => continue,
+                };
+                let instance = &instances[index as usize];
+                for axis in 0..2 {
+                    let reach = instance.basis_x[axis].abs() * half[0]
+                        + instance.basis_y[axis].abs() * half[1];
+                    let translation = instance.translation[axis];
+                    bounds.bounds_min[axis] = bounds.bounds_min[axis].min(translation - reach);
+                    bounds.bounds_max[axis] = bounds.bounds_max[axis].max(translation + reach);
+                }
+            }
+        }
+    }
+
+    /// Resets the queries of the frame in `frame_index` for the clusters of the last
+    /// [`Self::update`]; outside a render pass.
+    pub fn reset<L, P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        frame_index: usize,
+    ) {
+        let first = MAX_CLUSTERS * frame_index as u32;
+        self.queried[frame_index] = self.bounds.len() as u32;
+        // Safe: the frame's fence was waited on, so its queries aren't in use.
+        unsafe {
+            builder
+                .reset_query_pool(self.query_pool.clone(), first..first + MAX_CLUSTERS)
+                .unwrap();
+        }
+    }
+
+    pub fn upload<L, P>(
+        &mut self,
+        staging: &mut StagingRing,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        memory_stats: &mut MemoryStats,
+    ) -> FrameChunk<ClusterBounds> {
+        self.bounds_ring
+            .upload(&self.bounds, staging, builder, memory_stats)
+    }
+
+    /// Draws each cluster's box in `bounds` against the depth pre-pass, within a
+    /// query of its own.
+    pub fn query<L, P>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        frame_index: usize,
+        viewport: &Viewport,
+        bounds: &FrameChunk<ClusterBounds>,
+    ) {
+        builder
+            .set_viewport(0, [viewport.clone()])
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .bind_vertex_buffers(0, bounds.clone());
+        let first = MAX_CLUSTERS * frame_index as u32;
+        for cluster in 0..self.bounds.len() as u32 {
+            // Safe: `reset` reset the frame's queries before the render pass began.
+            unsafe {
+                builder
+                    .begin_query(
+                        self.query_pool.clone(),
+                        first + cluster,
+                        QueryControlFlags { precise: false },
+                    )
+                    .unwrap();
+            }
+            builder
+                .draw(6, 1, 0, cluster)
+                .unwrap()
+                .end_query(self.query_pool.clone(), first + cluster)
+                .unwrap();
+        }
+    }
+
+    /// Reads back the queries of the frame in `frame_index`, whose fence has
+    /// signalled.
+    pub fn read_results(&mut self, frame_index: usize) {
+        let count = std::mem::take(&mut self.queried[frame_index]);
+        if count == 0 {
+            return;
+        }
+        let first = MAX_CLUSTERS * frame_index as u32;
+        let mut samples = vec![0u32; count as usize];
+        match self
+            .query_pool
+            .queries_range(first..first + count)
+            .unwrap()
+            .get_results(&mut samples, QueryResultFlags::default())
+        {
+            Ok(true) => {
+                self.visible.clear();
+                self.visible
+                    .extend(samples.iter().map(|&passed| passed > 0));
+            }
+            Ok(false) => {}
+            Err(e) => warn!(error = ?e, "failed to read occlusion queries"),
+        }
+    }
+
+    /// Clusters found hidden in the last frame read back.
+    pub fn culled(&self) -> u32 {
+        self.visible.iter().filter(|&&visible| !visible).count() as u32
+    }
+
+    /// The runs of the first `instance_count` instances left to draw.
+    pub fn visible_ranges(&self, instance_count: u32) -> Vec<Range<u32>> {
+        let mut ranges: Vec<Range<u32>> = Vec::new();
+        for cluster in 0..instance_count.div_ceil(CLUSTER_SIZE) {
+            if !self.visible.get(cluster as usize).copied().unwrap_or(true) {
+                continue;
+            }
+            let start = cluster * CLUSTER_SIZE;
+            let end = (start + CLUSTER_SIZE).min(instance_count);
+            match ranges.last_mut() {
+                Some(last) if last.end == start => last.end = end,
+                _ => ranges.push(start..end),
+            }
+        }
+        ranges
+    }
+}
+
+/// Tests depth without writing anything.
+pub fn create_pipeline(device: &Arc<Device>, target: &RenderTarget) -> Arc<GraphicsPipeline> {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = fragment_shader::load(device.clone()).unwrap();
+
+    GraphicsPipeline::start()
+        .render_pass(target.scene())
+        .vertex_input_state(BuffersDefinition::new().instance::<ClusterBounds>())
+        .input_assembly_state(InputAssemblyState::new())
+        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+        .color_blend_state(ColorBlendState::new(1).color_write_mask(ColorComponents::none()))
+        .depth_stencil_state(DepthStencilState {
+            depth: Some(DepthState {
+                enable_dynamic: false,
+                write_enable: StateMode::Fixed(false),
+                compare_op: StateMode::Fixed(CompareOp::LessOrEqual),
+            }),
+            ..DepthStencilState::disabled()
+        })
+        .build(device.clone())
+        .unwrap()
+}
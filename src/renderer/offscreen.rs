@@ -0,0 +1,353 @@
+use super::{
+    color_grading::ColorGrading, create_pipeline, default_mesh, dither::Dither, fragment_shader,
+    override_topology, vertex_shader, DrawInputs, DrawList, FrameData, ImageAttachments,
+    InstanceColors, InstanceData, Instances, MeshBuffer, OutputPass, OwnedInstances,
+    PipelineVariants, RenderTarget, RendererSettings, Textures, DEPTH_FORMAT,
+};
+use crate::{
+    allocator::{FrameRing, StagingRing},
+    hdr::DisplayOutput,
+    memory::{AllocationPurpose, MemoryStats},
+};
+use std::{mem::size_of, slice, sync::Arc};
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferToImageInfo, CopyImageToBufferInfo,
+        PrimaryAutoCommandBuffer, PrimaryCommandBuffer, SubpassContents,
+    },
+    device::{physical::PhysicalDevice, Device, DeviceCreateInfo, Queue, QueueCreateInfo},
+    format::Format,
+    image::{
+        view::ImageView, AttachmentImage, ImageCreateFlags, ImageDimensions, ImageUsage,
+        StorageImage,
+    },
+    instance::Instance,
+    pipeline::graphics::viewport::Viewport,
+    swapchain::ColorSpace,
+    sync::GpuFuture,
+    DeviceSize,
+};
+
+/// Stands in for the usual `B8G8R8A8_SRGB` swapchain, in the byte order PNGs use.
+const CAPTURE_FORMAT: Format = Format::R8G8B8A8_SRGB;
+
+/// Draws `frames` with their instances the way the window would, but into an image
+/// of `extent` pixels on a device of its own, and returns each frame as tightly
+/// packed RGBA8 rows.
+pub fn render_offscreen(
+    instance: &Arc<Instance>,
+    physical_device_index: usize,
+    queue_family_id: u32,
+    settings: &RendererSettings,
+    extent: [u32; 2],
+    frames: &[(FrameData, OwnedInstances)],
+) -> Result<Vec<Vec<u8>>, String> {
+    let mut renderer = OffscreenRenderer::new(
+        instance,
+        physical_device_index,
+        queue_family_id,
+        settings,
+        extent,
+    )?;
+    frames
+        .iter()
+        .map(|(frame, instances)| renderer.render(frame, instances.as_instances()))
+        .collect()
+}
+
+/// Draws the instances with the main mesh and material into an image on a device of
+/// its own, reading every frame back to the host. Pipelines, attachments and buffers
+/// are kept between frames.
+pub struct OffscreenRenderer {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    extent: [u32; 2],
+    background_color: [f32; 4],
+    pipeline: PipelineVariants,
+    output_pass: OutputPass,
+    image: Arc<AttachmentImage>,
+    attachments: ImageAttachments,
+    viewport: Viewport,
+    memory_stats: MemoryStats,
+    vertex_buffer: MeshBuffer,
+    staging: StagingRing,
+    instance_ring: FrameRing<InstanceData>,
+    instance_colors: InstanceColors,
+    textures: Textures,
+    readback: Arc<CpuAccessibleBuffer<[u8]>>,
+    /// Where [`Self::receive`] copies frames drawn elsewhere to; created on first use.
+    received: Option<Arc<StorageImage>>,
+}
+
+impl OffscreenRenderer {
+    pub fn new(
+        instance: &Arc<Instance>,
+        physical_device_index: usize,
+        queue_family_id: u32,
+        settings: &RendererSettings,
+        extent: [u32; 2],
+    ) -> Result<Self, String> {
+        let physical_device = PhysicalDevice::from_index(instance, physical_device_index)
+            .ok_or("physical device disappeared")?;
+        let queue_family = physical_device
+            .queue_family_by_id(queue_family_id)
+            .ok_or("queue family disappeared")?;
+        let (device, mut queues) = Device::new(
+            physical_device,
+            DeviceCreateInfo {
+                queue_create_infos: vec![QueueCreateInfo::family(queue_family)],
+                ..Default::default()
+            },
+        )
+        .map_err(|e| e.to_string())?;
+        let queue = queues.next().unwrap();
+
+        // The device enables no features, so this is always the render pass.
+        let target = RenderTarget::new(&device, CAPTURE_FORMAT, DEPTH_FORMAT, false);
+        let fragment_shader = fragment_shader::load(device.clone()).unwrap();
+        let mesh = override_topology(default_mesh(), settings.topology);
+        let pipeline = PipelineVariants::new(&[mesh.topology.primitive_topology()], |topology| {
+            create_pipeline(
+                &device,
+                &target,
+                settings.vertex_format,
+                &fragment_shader,
+                false,
+                &settings.rasterizer,
+                topology,
+            )
+        })
+        .unwrap();
+        let mut memory_stats = MemoryStats::new(&device);
+        let grading = ColorGrading::new(
+            &device,
+            &queue,
+            settings.color_lut.as_ref(),
+            &mut memory_stats,
+        );
+        let dither = Dither::new(
+            &device,
+            &queue,
+            settings.dither,
+            CAPTURE_FORMAT,
+            &mut memory_stats,
+        );
+        let mut output_pass = OutputPass::new(
+            &device,
+            &target,
+            grading,
+            dither,
+            DisplayOutput::transfer(ColorSpace::SrgbNonLinear),
+            settings.paper_white,
+        )
+        .map_err(|e| e.to_string())?;
+
+        let image = AttachmentImage::with_usage(
+            device.clone(),
+            extent,
+            CAPTURE_FORMAT,
+            ImageUsage {
+                color_attachment: true,
+                transfer_src: true,
+                ..ImageUsage::none()
+            },
+        )
+        .unwrap();
+        let attachments = ImageAttachments::new(
+            &device,
+            &target,
+            ImageView::new_default(image.clone()).unwrap(),
+            extent,
+        );
+        output_pass.update_descriptor_sets(slice::from_ref(&attachments));
+        let viewport = Viewport {
+            origin: [0.0, 0.0],
+            dimensions: [extent[0] as f32, extent[1] as f32],
+            depth_range: 0.0..1.0,
+        };
+
+        let vertex_buffer = settings
+            .vertex_format
+            .create_buffer(&device, &mesh)
+            .map_err(|e| e.to_string())?;
+        let staging = StagingRing::new(
+            device.clone(),
+            1,
+            (settings.instance_count as usize * (size_of::<InstanceData>() + size_of::<[f32; 4]>()))
+                as DeviceSize,
+            &mut memory_stats,
+        )
+        .map_err(|e| e.to_string())?;
+        let instance_ring = FrameRing::new(
+            device.clone(),
+            BufferUsage::vertex_buffer(),
+            AllocationPurpose::Instance,
+            1,
+            settings.instance_count as usize,
+            &mut memory_stats,
+        )
+        .map_err(|e| e.to_string())?;
+        let instance_colors = InstanceColors::new(
+            &device,
+            1,
+            settings.instance_count as usize,
+            &mut memory_stats,
+        )
+        .map_err(|e| e.to_string())?;
+        // Only the main material is drawn, which samples no textures.
+        let textures = Textures::new(&device, &queue, &[], None, &mut memory_stats);
+        let readback = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage {
+                transfer_src: true,
+                transfer_dst: true,
+                ..BufferUsage::none()
+            },
+            false,
+            (0..extent[0] * extent[1] * 4).map(|_| 0u8),
+        )
+        .unwrap();
+
+        Ok(OffscreenRenderer {
+            device,
+            queue,
+            extent,
+            background_color: settings.background_color,
+            pipeline,
+            output_pass,
+            image,
+            attachments,
+            viewport,
+            memory_stats,
+            vertex_buffer,
+            staging,
+            instance_ring,
+            instance_colors,
+            textures,
+            readback,
+            received: None,
+        })
+    }
+
+    /// Draws one frame and returns it as tightly packed RGBA8 rows.
+    pub fn render(&mut self, frame: &FrameData, instances: Instances) -> Result<Vec<u8>, String> {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.device.clone(),
+            self.queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        // The previous frame was waited for.
+        self.staging.begin_frame(0);
+        let instance_buffer = self.instance_ring.upload(
+            instances.data,
+            &mut self.staging,
+            &mut builder,
+            &mut self.memory_stats,
+        );
+        let colors = self.instance_colors.upload(
+            instances.colors,
+            &mut self.staging,
+            &mut builder,
+            &mut self.memory_stats,
+        );
+        self.attachments
+            .begin_scene(&mut builder, self.background_color, SubpassContents::Inline);
+        let draw_list = DrawList::single(0..instances.len() as u32);
+        DrawInputs {
+            pipelines: slice::from_ref(&self.pipeline),
+            meshes: slice::from_ref(&self.vertex_buffer),
+            batches: draw_list.batches(),
+            viewport: &self.viewport,
+            instance_buffer: &instance_buffer,
+            colors: &colors,
+            push_constants: vertex_shader::ty::PushConstantData {
+                bands: frame.band_vectors(),
+                x: frame.mouse[0],
+                y: frame.mouse[1],
+                zoom: frame.zoom,
+                jitter: [0.0; 2],
+            },
+            main_push_constants: None,
+            textures: self.textures.descriptor_set(),
+            lights: None,
+            point_sprites: None,
+        }
+        .record(&mut builder, 0..instances.len() as u32);
+        self.attachments.begin_output(&mut builder, None);
+        self.output_pass
+            .record(&mut builder, 0, &self.attachments, None, &self.viewport);
+        self.attachments.end(&mut builder);
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                self.image.clone(),
+                self.readback.clone(),
+            ))
+            .unwrap();
+        self.submit_and_wait(builder)?;
+
+        let pixels = self.readback.read().unwrap().to_vec();
+        Ok(pixels)
+    }
+
+    /// Copies a frame another device drew, as [`Self::render`] returns it, into an
+    /// image on this device, the way a presenting device takes in the frames of the
+    /// others.
+    pub fn receive(&mut self, pixels: &[u8]) -> Result<(), String> {
+        self.readback.write().map_err(|e| e.to_string())?[..pixels.len()].copy_from_slice(pixels);
+        let received = match &self.received {
+            Some(received) => received.clone(),
+            None => {
+                let received = StorageImage::with_usage(
+                    self.device.clone(),
+                    ImageDimensions::Dim2d {
+                        width: self.extent[0],
+                        height: self.extent[1],
+                        array_layers: 1,
+                    },
+                    CAPTURE_FORMAT,
+                    ImageUsage {
+                        transfer_dst: true,
+                        sampled: true,
+                        ..ImageUsage::none()
+                    },
+                    ImageCreateFlags::none(),
+                    [self.queue.family()],
+                )
+                .map_err(|e| e.to_string())?;
+                self.received = Some(received.clone());
+                received
+            }
+        };
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.device.clone(),
+            self.queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        builder
+            .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+                self.readback.clone(),
+                received,
+            ))
+            .unwrap();
+        self.submit_and_wait(builder)
+    }
+
+    fn submit_and_wait(
+        &self,
+        builder: AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), String> {
+        builder
+            .build()
+            .unwrap()
+            .execute(self.queue.clone())
+            .map_err(|e| e.to_string())?
+            .then_signal_fence_and_flush()
+            .map_err(|e| e.to_string())?
+            .wait(None)
+            .map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,234 @@
+use super::{
+    build_pipeline, rasterizer::RasterizerSettings, topology::PipelineVariants,
+    vertex_format::VertexFormat, DrawInputs, RenderTarget,
+};
+use std::sync::Arc;
+use vulkano::{
+    command_buffer::AutoCommandBufferBuilder,
+    device::{physical::PhysicalDevice, Device},
+    format::Format,
+    pipeline::{
+        graphics::{
+            color_blend::{ColorBlendState, ColorComponents},
+            depth_stencil::{
+                CompareOp, DepthStencilState, StencilOp, StencilOpState, StencilOps, StencilState,
+            },
+            input_assembly::PrimitiveTopology,
+        },
+        Pipeline, StateMode,
+    },
+};
+
+/// Color of the outline around selected instances.
+const OUTLINE_COLOR: [f32; 4] = [1.0, 0.6, 0.1, 1.0];
+
+/// Stencil value the selected instances' pixels are marked with.
+const MARK: u32 = 1;
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 position;
+        layout(location = 2) in vec2 basis_x;
+        layout(location = 3) in vec2 basis_y;
+        layout(location = 4) in vec2 translation;
+        layout(location = 5) in vec2 phase;
+
+        layout(location = 0) out vec4 out_color;
+
+        layout(push_constant) uniform OutlineParams {
+            vec4 bands[2];
+            float x;
+            float y;
+            float zoom;
+            float scale;
+            vec4 color;
+        } params;
+
+        void main() {
+            out_color = params.color;
+            // The scene's vertex shader, scaled about the instance's origin.
+            vec2 pos = position*vec2(params.x, params.y)*params.zoom;
+            uint band = uint(gl_InstanceIndex)%8u;
+            float amplitude = 0.5*(1.0+2.0*params.bands[band/4u][band%4u]);
+            vec2 wobble = vec2(sin(phase.x+position.x+position.y), sin(phase.y+position.x+position.y))*amplitude;
+            gl_Position = vec4(translation+mat2(basis_x, basis_y)*(pos+wobble)*params.scale, 0.0, 1.0);
+            gl_PointSize = 1.0;
+        }
+        "
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec4 in_color;
+
+        layout(location = 0) out vec4 f_color;
+
+        void main() {
+            f_color = in_color;
+        }
+        "
+    }
+}
+
+/// The first depth/stencil format `physical_device` can render to, or `None` if it
+/// has none, as some have only depth formats.
+pub fn depth_stencil_format(physical_device: PhysicalDevice) -> Option<Format> {
+    [
+        Format::D24_UNORM_S8_UINT,
+        Format::D32_SFLOAT_S8_UINT,
+        Format::D16_UNORM_S8_UINT,
+    ]
+    .into_iter()
+    .find(|&format| {
+        physical_device
+            .format_properties(format)
+            .optimal_tiling_features
+            .depth_stencil_attachment
+    })
+}
+
+/// Outlines selected instances in two passes over the scene's stencil: the first
+/// marks the pixels the instances cover without drawing anything, the second draws
+/// them again `scale` times bigger in a flat color wherever they aren't marked,
+/// leaving a crisp rim around each. Instances are drawn as the scene's vertex shader
+/// draws them, so shader presets, which move the vertices, aren't followed.
+pub struct Outline {
+    mark: PipelineVariants,
+    outline: PipelineVariants,
+    scale: f32,
+}
+
+impl Outline {
+    /// `target` must have a depth/stencil attachment.
+    pub fn new(
+        device: &Arc<Device>,
+        target: &RenderTarget,
+        vertex_format: VertexFormat,
+        topologies: &[PrimitiveTopology],
+        rasterizer: &RasterizerSettings,
+        scale: f32,
+    ) -> Self {
+        let (mark, outline) =
+            create_pipelines(device, target, vertex_format, topologies, rasterizer);
+        Outline {
+            mark,
+            outline,
+            scale,
+        }
+    }
+
+    pub fn set_pipelines(&mut self, (mark, outline): (PipelineVariants, PipelineVariants)) {
+        self.mark = mark;
+        self.outline = outline;
+    }
+
+    /// Outlines the instances at `selected` indices of `inputs`' instance buffer,
+    /// with the meshes of the batches they're in. Call after the scene's instances
+    /// are drawn; indices past every batch are skipped.
+    pub fn draw<L, P>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        inputs: &DrawInputs,
+        selected: &[u32],
+    ) {
+        builder.set_viewport(0, [inputs.viewport.clone()]);
+        for (pipelines, scale) in [(&self.mark, 1.0), (&self.outline, self.scale)] {
+            let params = vertex_shader::ty::OutlineParams {
+                bands: inputs.push_constants.bands,
+                x: inputs.push_constants.x,
+                y: inputs.push_constants.y,
+                zoom: inputs.push_constants.zoom,
+                scale,
+                color: OUTLINE_COLOR,
+            };
+            for &instance in selected {
+                let batch = inputs
+                    .batches
+                    .iter()
+                    .find(|batch| batch.instances.contains(&instance));
+                let (mesh, lod) = match batch {
+                    Some(batch) => (
+                        inputs.meshes.get(batch.mesh.0).unwrap_or(&inputs.meshes[0]),
+                        batch.lod,
+                    ),
+                    None => continue,
+                };
+                let pipeline = pipelines.get(mesh.topology());
+                builder
+                    .bind_pipeline_graphics(pipeline.clone())
+                    .push_constants(pipeline.layout().clone(), 0, params);
+                mesh.bind(builder, inputs.instance_buffer);
+                let lod = mesh.lod(lod);
+                builder
+                    .draw_indexed(lod.end - lod.start, 1, lod.start, 0, instance)
+                    .unwrap();
+            }
+        }
+    }
+}
+
+/// The marking and the outlining pipeline, in a variant for each of `topologies`.
+pub fn create_pipelines(
+    device: &Arc<Device>,
+    target: &RenderTarget,
+    vertex_format: VertexFormat,
+    topologies: &[PrimitiveTopology],
+    rasterizer: &RasterizerSettings,
+) -> (PipelineVariants, PipelineVariants) {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = fragment_shader::load(device.clone()).unwrap();
+    let create = |color_blend_state: &ColorBlendState, ops: StencilOps| {
+        let stencil = StencilOpState {
+            ops: StateMode::Fixed(ops),
+            reference: StateMode::Fixed(MARK),
+            ..Default::default()
+        };
+        let depth_stencil_state = DepthStencilState {
+            stencil: Some(StencilState {
+                enable_dynamic: false,
+                front: stencil,
+                back: stencil,
+            }),
+            ..DepthStencilState::disabled()
+        };
+        PipelineVariants::new(topologies, |topology| {
+            build_pipeline(
+                device,
+                target,
+                vertex_format,
+                &loaded_vertex_shader,
+                &loaded_fragment_shader,
+                topology,
+                color_blend_state.clone(),
+                depth_stencil_state.clone(),
+                rasterizer.mesh_state(device),
+            )
+        })
+        .unwrap()
+    };
+    let mark = create(
+        &ColorBlendState::new(1).color_write_mask(ColorComponents::none()),
+        StencilOps {
+            pass_op: StencilOp::Replace,
+            compare_op: CompareOp::Always,
+            ..Default::default()
+        },
+    );
+    let outline = create(
+        &ColorBlendState::new(1),
+        StencilOps {
+            compare_op: CompareOp::NotEqual,
+            ..Default::default()
+        },
+    );
+    (mark, outline)
+}
@@ -0,0 +1,152 @@
+use super::{InstanceData, RenderTarget, COLOR_SET};
+use crate::allocator::FrameChunk;
+use std::{ops::Range, sync::Arc};
+use tracing::warn;
+use vulkano::{
+    command_buffer::AutoCommandBufferBuilder,
+    descriptor_set::PersistentDescriptorSet,
+    device::Device,
+    pipeline::{
+        graphics::{
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            vertex_input::BuffersDefinition,
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+};
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 basis_x;
+        layout(location = 1) in vec2 basis_y;
+        layout(location = 2) in vec2 translation;
+
+        layout(location = 0) out vec4 out_color;
+
+        layout(set = 2, binding = 0) readonly buffer InstanceColors {
+            vec4 colors[];
+        };
+
+        layout(push_constant) uniform PointParams {
+            float size;
+        } params;
+
+        void main() {
+            out_color = colors[gl_InstanceIndex];
+            // Matches the scene's vertex shader, so points sort like the meshes.
+            float depth = max(1.0-float(gl_InstanceIndex+1)/65536.0, 0.0);
+            bool collapsed = basis_x == vec2(0.0) && basis_y == vec2(0.0);
+            // Past the far plane, so collapsed instances are clipped like their meshes.
+            gl_Position = collapsed ? vec4(0.0, 0.0, 2.0, 1.0) : vec4(translation, depth, 1.0);
+            gl_PointSize = params.size;
+        }
+        "
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec4 in_color;
+
+        layout(location = 0) out vec4 f_color;
+
+        void main() {
+            // Cut the square the point covers down to its inscribed circle.
+            vec2 offset = gl_PointCoord*2.0-1.0;
+            if (dot(offset, offset) > 1.0) {
+                discard;
+            }
+            f_color = in_color;
+        }
+        "
+    }
+}
+
+/// Draws instances as round points, one vertex each at the instance's translation,
+/// instead of their meshes: far less work per instance, for scenes of very many
+/// small things such as particles or scatter plots. The points are the same size
+/// in pixels whatever the instance's scale.
+pub struct PointSprites {
+    pipeline: Arc<GraphicsPipeline>,
+    size: f32,
+}
+
+impl PointSprites {
+    /// `size` is clamped to the device's range of point sizes, which is 1 pixel
+    /// without `large_points`.
+    pub fn new(device: &Arc<Device>, target: &RenderTarget, size: f32) -> Self {
+        let size = if device.enabled_features().large_points {
+            let [min, max] = device.physical_device().properties().point_size_range;
+            size.clamp(min, max)
+        } else {
+            if size != 1.0 {
+                warn!(
+                    size,
+                    "large points aren't supported, drawing points 1 pixel wide"
+                );
+            }
+            1.0
+        };
+        PointSprites {
+            pipeline: create_pipeline(device, target),
+            size,
+        }
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: Arc<GraphicsPipeline>) {
+        self.pipeline = pipeline;
+    }
+
+    /// Draws `instances` of `instance_buffer`, in their `colors`. Secondary command
+    /// buffers don't inherit the viewport, so it's set every time.
+    pub fn draw<L, P>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        viewport: &Viewport,
+        instance_buffer: &FrameChunk<InstanceData>,
+        colors: &Arc<PersistentDescriptorSet>,
+        instances: Range<u32>,
+    ) {
+        builder
+            .set_viewport(0, [viewport.clone()])
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                vertex_shader::ty::PointParams { size: self.size },
+            )
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline.layout().clone(),
+                COLOR_SET,
+                colors.clone(),
+            )
+            .bind_vertex_buffers(0, instance_buffer.clone())
+            .draw(1, instances.end - instances.start, 0, instances.start)
+            .unwrap();
+    }
+}
+
+pub fn create_pipeline(device: &Arc<Device>, target: &RenderTarget) -> Arc<GraphicsPipeline> {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = fragment_shader::load(device.clone()).unwrap();
+
+    GraphicsPipeline::start()
+        .render_pass(target.scene())
+        .vertex_input_state(BuffersDefinition::new().instance::<InstanceData>())
+        .input_assembly_state(InputAssemblyState::new().topology(PrimitiveTopology::PointList))
+        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+        .build(device.clone())
+        .unwrap()
+}
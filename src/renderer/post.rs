@@ -0,0 +1,170 @@
+use super::{
+    depth_of_field::DepthOfField, g_buffer::GBuffer, motion_blur::MotionBlur, output_vertex_shader,
+    stylize::StylePass, taa::Taa, SCENE_FORMAT,
+};
+use std::sync::Arc;
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderingAttachmentInfo, RenderingInfo,
+    },
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    device::Device,
+    image::{view::ImageView, AttachmentImage, ImageUsage},
+    pipeline::{
+        graphics::{
+            input_assembly::InputAssemblyState,
+            render_pass::PipelineRenderingCreateInfo,
+            vertex_input::BuffersDefinition,
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    render_pass::{LoadOp, StoreOp},
+    shader::ShaderModule,
+    DeviceSize,
+};
+
+/// The passes recorded between the scene's and the output pass's, each on what the
+/// one before wrote.
+pub struct PostEffects<'a> {
+    /// What TAA, the depth of field and motion blur read; they're skipped without it.
+    pub g_buffer: Option<&'a GBuffer>,
+    pub taa: Option<&'a mut Taa>,
+    pub depth_of_field: Option<&'a DepthOfField>,
+    pub motion_blur: Option<&'a MotionBlur>,
+    /// Drawn last, in order.
+    pub styles: &'a [StylePass],
+    /// Drawn this frame, for the depth of field's range.
+    pub instance_count: u32,
+    /// Of the frame, for the film grain.
+    pub time: f32,
+    pub viewport: &'a Viewport,
+}
+
+impl PostEffects<'_> {
+    /// Records the effects on `scene`, returning what the last wrote, if any ran.
+    pub fn record(
+        self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        scene: &Arc<ImageView<AttachmentImage>>,
+    ) -> Option<Arc<ImageView<AttachmentImage>>> {
+        let mut image = None;
+        if let (Some(taa), Some(g_buffer)) = (self.taa, self.g_buffer) {
+            taa.resolve(builder, scene, g_buffer.motion(), self.viewport);
+            image = Some(taa.resolved().clone());
+        }
+        if let (Some(depth_of_field), Some(g_buffer)) = (self.depth_of_field, self.g_buffer) {
+            depth_of_field.draw(
+                builder,
+                image.as_ref().unwrap_or(scene),
+                g_buffer.depth(),
+                self.instance_count,
+                self.viewport,
+            );
+            image = Some(depth_of_field.image().clone());
+        }
+        if let (Some(motion_blur), Some(g_buffer)) = (self.motion_blur, self.g_buffer) {
+            motion_blur.draw(
+                builder,
+                image.as_ref().unwrap_or(scene),
+                g_buffer.motion(),
+                self.viewport,
+            );
+            image = Some(motion_blur.image().clone());
+        }
+        for style in self.styles {
+            style.draw(
+                builder,
+                image.as_ref().unwrap_or(scene),
+                self.time,
+                self.viewport,
+            );
+            image = Some(style.image().clone());
+        }
+        image
+    }
+}
+
+/// An image of `dimensions` a post effect draws into, in the scene's format.
+pub fn create_target(
+    device: &Arc<Device>,
+    dimensions: [u32; 2],
+) -> Arc<ImageView<AttachmentImage>> {
+    let image = AttachmentImage::with_usage(
+        device.clone(),
+        dimensions,
+        SCENE_FORMAT,
+        ImageUsage {
+            color_attachment: true,
+            sampled: true,
+            // For Renderer::capture_hdr_frame, when it's the last pass's.
+            transfer_src: true,
+            ..ImageUsage::none()
+        },
+    )
+    .unwrap();
+    ImageView::new_default(image).unwrap()
+}
+
+/// Bytes of a target of `dimensions`, four half floats per pixel.
+pub fn target_size([width, height]: [u32; 2]) -> DeviceSize {
+    width as DeviceSize * height as DeviceSize * 8
+}
+
+/// A pipeline running `fragment_shader` over the whole of a target.
+pub fn create_pipeline(
+    device: &Arc<Device>,
+    fragment_shader: &Arc<ShaderModule>,
+) -> Arc<GraphicsPipeline> {
+    let loaded_vertex_shader = output_vertex_shader::load(device.clone()).unwrap();
+
+    GraphicsPipeline::start()
+        .render_pass(PipelineRenderingCreateInfo {
+            color_attachment_formats: vec![Some(SCENE_FORMAT)],
+            ..Default::default()
+        })
+        .vertex_input_state(BuffersDefinition::new())
+        .input_assembly_state(InputAssemblyState::new())
+        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(fragment_shader.entry_point("main").unwrap(), ())
+        .build(device.clone())
+        .unwrap()
+}
+
+/// Runs `pipeline` over the whole of `target` in a rendering scope of its own, with
+/// `writes` as set 0.
+pub fn draw<Pc>(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    pipeline: &Arc<GraphicsPipeline>,
+    target: &Arc<ImageView<AttachmentImage>>,
+    writes: impl IntoIterator<Item = WriteDescriptorSet>,
+    push_constants: Pc,
+    viewport: &Viewport,
+) {
+    let descriptor_set =
+        PersistentDescriptorSet::new(pipeline.layout().set_layouts()[0].clone(), writes).unwrap();
+    builder
+        .begin_rendering(RenderingInfo {
+            color_attachments: vec![Some(RenderingAttachmentInfo {
+                load_op: LoadOp::DontCare,
+                store_op: StoreOp::Store,
+                ..RenderingAttachmentInfo::image_view(target.clone())
+            })],
+            ..Default::default()
+        })
+        .unwrap()
+        .set_viewport(0, [viewport.clone()])
+        .bind_pipeline_graphics(pipeline.clone())
+        .bind_descriptor_sets(
+            PipelineBindPoint::Graphics,
+            pipeline.layout().clone(),
+            0,
+            descriptor_set,
+        )
+        .push_constants(pipeline.layout().clone(), 0, push_constants)
+        .draw(3, 1, 0, 0)
+        .unwrap()
+        .end_rendering()
+        .unwrap();
+}
@@ -0,0 +1,416 @@
+use super::{
+    build_pipeline, fragment_shader, reflection::assert_shader_layout, FrameData, RenderTarget,
+    VertexFormat,
+};
+use bytemuck::Pod;
+use std::{slice, sync::Arc};
+use vulkano::{
+    command_buffer::AutoCommandBufferBuilder,
+    device::Device,
+    pipeline::{
+        graphics::{
+            color_blend::ColorBlendState, depth_stencil::DepthStencilState,
+            input_assembly::PrimitiveTopology, rasterization::RasterizationState,
+            GraphicsPipelineCreationError,
+        },
+        layout::PipelineLayout,
+        GraphicsPipeline,
+    },
+    shader::{ShaderCreationError, ShaderModule},
+};
+
+type LoadShader = fn(Arc<Device>) -> Result<Arc<ShaderModule>, ShaderCreationError>;
+
+/// An alternative look for the main material: a vertex and a fragment shader drawing
+/// the same meshes and instances, with a push constant block of their own. The
+/// renderer doesn't know the block's layout, only the 32-bit words `push_constants`
+/// packs it into.
+pub struct ShaderPreset {
+    pub name: &'static str,
+    vertex_shader: LoadShader,
+    fragment_shader: LoadShader,
+    push_constants: fn(&FrameData) -> Vec<u32>,
+}
+
+/// Presets in the order Left and Right cycle through them.
+pub const SHADER_PRESETS: [ShaderPreset; 4] = [
+    ShaderPreset {
+        name: "waves",
+        vertex_shader: waves_vertex_shader::load,
+        fragment_shader: waves_fragment_shader::load,
+        push_constants: waves_push_constants,
+    },
+    ShaderPreset {
+        name: "spiral",
+        vertex_shader: spiral_vertex_shader::load,
+        fragment_shader: fragment_shader::load,
+        push_constants: spiral_push_constants,
+    },
+    ShaderPreset {
+        name: "starfield",
+        vertex_shader: starfield_vertex_shader::load,
+        fragment_shader: starfield_fragment_shader::load,
+        push_constants: starfield_push_constants,
+    },
+    ShaderPreset {
+        name: "plasma",
+        vertex_shader: plasma_vertex_shader::load,
+        fragment_shader: plasma_fragment_shader::load,
+        push_constants: plasma_push_constants,
+    },
+];
+
+/// The preset after `current` in [`SHADER_PRESETS`], or before it going backwards,
+/// where `None` is the built-in shaders and sits between the last and the first.
+pub fn cycle_shader_preset(current: Option<usize>, forward: bool) -> Option<usize> {
+    let positions = SHADER_PRESETS.len() + 1;
+    let position = current.map_or(0, |index| index + 1);
+    let next = if forward {
+        (position + 1) % positions
+    } else {
+        (position + positions - 1) % positions
+    };
+    next.checked_sub(1)
+}
+
+impl ShaderPreset {
+    /// This frame's push constants, as the words of the shaders' block.
+    pub fn push_constants(&self, frame: &FrameData) -> Vec<u32> {
+        (self.push_constants)(frame)
+    }
+}
+
+/// The preset's pipeline for the scene. It doesn't test depth: presets move the
+/// vertices, so nothing they draw matches the depth pre-pass.
+pub fn create_pipeline(
+    device: &Arc<Device>,
+    target: &RenderTarget,
+    vertex_format: VertexFormat,
+    topology: PrimitiveTopology,
+    preset: &ShaderPreset,
+) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+    let loaded_vertex_shader = (preset.vertex_shader)(device.clone()).unwrap();
+    let loaded_fragment_shader = (preset.fragment_shader)(device.clone()).unwrap();
+    build_pipeline(
+        device,
+        target,
+        vertex_format,
+        &loaded_vertex_shader,
+        &loaded_fragment_shader,
+        topology,
+        ColorBlendState::new(1),
+        DepthStencilState::disabled(),
+        RasterizationState::new(),
+    )
+}
+
+/// Pushes `words` from offset 0. vulkano only pushes values of a type known at
+/// compile time, so each word goes on its own.
+pub fn push_words<L, P>(
+    builder: &mut AutoCommandBufferBuilder<L, P>,
+    layout: &Arc<PipelineLayout>,
+    words: &[u32],
+) {
+    for (index, &word) in words.iter().enumerate() {
+        builder.push_constants(layout.clone(), 4 * index as u32, word);
+    }
+}
+
+fn words<T: Pod>(block: T) -> Vec<u32> {
+    bytemuck::cast_slice(slice::from_ref(&block)).to_vec()
+}
+
+/// What the built-in vertex shader scales meshes by.
+fn scale(frame: &FrameData) -> [f32; 2] {
+    frame.mouse.map(|mouse| mouse * frame.zoom)
+}
+
+fn waves_push_constants(frame: &FrameData) -> Vec<u32> {
+    words(waves_vertex_shader::ty::WavesParams {
+        scale: scale(frame),
+        time: frame.time,
+        // The bass makes the waves higher.
+        amplitude: 0.05 + 0.15 * frame.audio_bands[0],
+        wavelength: 0.5,
+    })
+}
+
+fn spiral_push_constants(frame: &FrameData) -> Vec<u32> {
+    words(spiral_vertex_shader::ty::SpiralParams {
+        scale: scale(frame),
+        time: frame.time,
+        twist: 2.0,
+    })
+}
+
+fn starfield_push_constants(frame: &FrameData) -> Vec<u32> {
+    words(starfield_vertex_shader::ty::StarfieldParams {
+        star_size: 0.04 * frame.zoom,
+        time: frame.time,
+        speed: 0.2,
+    })
+}
+
+// Both stages declare the block the words are packed from.
+assert_shader_layout!(plasma_vertex_shader::ty::PlasmaParams, plasma_fragment_shader::ty::PlasmaParams {
+    scale => scale,
+    time => time,
+    frequency => frequency,
+});
+
+fn plasma_push_constants(frame: &FrameData) -> Vec<u32> {
+    words(plasma_vertex_shader::ty::PlasmaParams {
+        scale: scale(frame),
+        time: frame.time,
+        frequency: 6.0,
+    })
+}
+
+/// Instances bob on a wave travelling across the screen, shaded by its height.
+mod waves_vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 position;
+        layout(location = 1) in vec4 color;
+        layout(location = 2) in vec2 basis_x;
+        layout(location = 3) in vec2 basis_y;
+        layout(location = 4) in vec2 translation;
+
+        layout(location = 0) out vec4 out_color;
+        layout(location = 1) out float out_height;
+
+        layout(set = 2, binding = 0) readonly buffer InstanceColors {
+            vec4 colors[];
+        };
+
+        layout(push_constant) uniform WavesParams {
+            vec2 scale;
+            float time;
+            float amplitude;
+            float wavelength;
+        } pc;
+
+        void main() {
+            vec2 world = translation+mat2(basis_x, basis_y)*(position*pc.scale);
+            float height = sin(world.x*6.2831853/pc.wavelength-pc.time*3.0);
+            world.y += height*pc.amplitude;
+            out_color = color*colors[gl_InstanceIndex];
+            out_height = height;
+            gl_Position = vec4(world, 0.0, 1.0);
+            gl_PointSize = 1.0;
+        }
+        ",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
+
+mod waves_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec4 in_color;
+        layout(location = 1) in float in_height;
+
+        layout(location = 0) out vec4 f_color;
+
+        void main() {
+            f_color = vec4(in_color.rgb*mix(0.5, 1.2, 0.5+0.5*in_height), in_color.a);
+        }
+        "
+    }
+}
+
+/// The whole scene swirls around the center, faster further out.
+mod spiral_vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 position;
+        layout(location = 1) in vec4 color;
+        layout(location = 2) in vec2 basis_x;
+        layout(location = 3) in vec2 basis_y;
+        layout(location = 4) in vec2 translation;
+
+        layout(location = 0) out vec4 out_color;
+
+        layout(set = 2, binding = 0) readonly buffer InstanceColors {
+            vec4 colors[];
+        };
+
+        layout(push_constant) uniform SpiralParams {
+            vec2 scale;
+            float time;
+            float twist; // radians per unit of distance from the center
+        } pc;
+
+        void main() {
+            vec2 world = translation+mat2(basis_x, basis_y)*(position*pc.scale);
+            float angle = pc.twist*length(world)-pc.time;
+            world = mat2(cos(angle), sin(angle), -sin(angle), cos(angle))*world;
+            out_color = color*colors[gl_InstanceIndex];
+            gl_Position = vec4(world, 0.0, 1.0);
+            gl_PointSize = 1.0;
+        }
+        ",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
+
+/// Every instance becomes a small star flying out of the center, each at its own
+/// point of the journey, growing as it comes closer.
+mod starfield_vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 position;
+        layout(location = 1) in vec4 color;
+        layout(location = 4) in vec2 translation;
+
+        layout(location = 0) out vec4 out_color;
+        layout(location = 1) out vec2 out_local;
+
+        layout(set = 2, binding = 0) readonly buffer InstanceColors {
+            vec4 colors[];
+        };
+
+        layout(push_constant) uniform StarfieldParams {
+            float star_size;
+            float time;
+            float speed; // journeys per second
+        } pc;
+
+        float hash(uint n) {
+            n = (n^61u)^(n>>16u);
+            n *= 9u;
+            n ^= n>>4u;
+            n *= 0x27d4eb2du;
+            n ^= n>>15u;
+            return float(n)/4294967295.0;
+        }
+
+        void main() {
+            float progress = fract(hash(uint(gl_InstanceIndex))+pc.time*pc.speed);
+            float reach = 0.05+0.95*progress*progress;
+            vec2 direction = length(translation) > 0.0 ? normalize(translation) : vec2(0.0, 1.0);
+            vec2 center = direction*reach*1.5;
+            out_color = vec4((color*colors[gl_InstanceIndex]).rgb, progress);
+            out_local = position;
+            gl_Position = vec4(center+position*pc.star_size*(0.2+progress), 0.0, 1.0);
+            gl_PointSize = 1.0;
+        }
+        ",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
+
+mod starfield_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec4 in_color;
+        layout(location = 1) in vec2 in_local;
+
+        layout(location = 0) out vec4 f_color;
+
+        void main() {
+            // Brightest in the middle and once close; alpha carries the progress.
+            float glow = smoothstep(0.5, 0.0, length(in_local))*in_color.a;
+            f_color = vec4(mix(vec3(1.0), in_color.rgb, 0.5)*glow, 1.0);
+        }
+        "
+    }
+}
+
+/// The instances keep their places but are filled with a plasma pattern computed
+/// from where on the screen each fragment is.
+mod plasma_vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 position;
+        layout(location = 2) in vec2 basis_x;
+        layout(location = 3) in vec2 basis_y;
+        layout(location = 4) in vec2 translation;
+
+        layout(location = 0) out vec2 out_world;
+        layout(location = 1) out float out_alpha;
+
+        layout(set = 2, binding = 0) readonly buffer InstanceColors {
+            vec4 colors[];
+        };
+
+        layout(push_constant) uniform PlasmaParams {
+            vec2 scale;
+            float time;
+            float frequency;
+        } pc;
+
+        void main() {
+            vec2 world = translation+mat2(basis_x, basis_y)*(position*pc.scale);
+            out_world = world;
+            out_alpha = colors[gl_InstanceIndex].a;
+            gl_Position = vec4(world, 0.0, 1.0);
+            gl_PointSize = 1.0;
+        }
+        ",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
+
+mod plasma_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 in_world;
+        layout(location = 1) in float in_alpha;
+
+        layout(location = 0) out vec4 f_color;
+
+        layout(push_constant) uniform PlasmaParams {
+            vec2 scale;
+            float time;
+            float frequency;
+        } pc;
+
+        void main() {
+            vec2 p = in_world*pc.frequency;
+            float t = pc.time;
+            float v = sin(p.x+t)+sin(0.5*(p.y+t))+sin(0.5*(p.x+p.y+t))
+                +sin(length(p+vec2(sin(t/3.0), cos(t/2.0))*4.0)+t);
+            vec3 color = 0.5+0.5*cos(3.14159*v+vec3(0.0, 2.094, 4.188));
+            f_color = vec4(color, in_alpha);
+        }
+        "
+    }
+}
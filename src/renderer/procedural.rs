@@ -0,0 +1,271 @@
+use super::RenderTarget;
+use crate::memory::{AllocationPurpose, MemoryStats};
+use std::sync::Arc;
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    device::{physical::QueueFamily, Device},
+    format::Format,
+    image::{view::ImageView, ImageCreateFlags, ImageDimensions, ImageUsage, StorageImage},
+    pipeline::{
+        graphics::{
+            input_assembly::InputAssemblyState,
+            vertex_input::BuffersDefinition,
+            viewport::{Viewport, ViewportState},
+        },
+        ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    DeviceSize,
+};
+
+/// Side of the pattern in texels; it's stretched over the window.
+const PATTERN_SIZE: u32 = 256;
+
+/// Invocations per compute workgroup on each axis; matches the shader's local size.
+const WORKGROUP_SIZE: u32 = 8;
+
+mod compute_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: "
+        #version 460
+
+        layout(local_size_x = 8, local_size_y = 8) in;
+
+        layout(set = 0, binding = 0, rgba8) uniform writeonly image2D pattern;
+
+        layout(push_constant) uniform PatternParams {
+            float time;
+        } params;
+
+        float hash(vec2 p) {
+            return fract(sin(dot(p, vec2(127.1, 311.7)))*43758.5453);
+        }
+
+        float noise(vec2 p) {
+            vec2 i = floor(p);
+            vec2 f = fract(p);
+            vec2 u = f*f*(3.0-2.0*f);
+            return mix(mix(hash(i), hash(i+vec2(1.0, 0.0)), u.x),
+                mix(hash(i+vec2(0.0, 1.0)), hash(i+vec2(1.0, 1.0)), u.x), u.y);
+        }
+
+        void main() {
+            ivec2 texel = ivec2(gl_GlobalInvocationID.xy);
+            ivec2 size = imageSize(pattern);
+            if (any(greaterThanEqual(texel, size))) {
+                return;
+            }
+            vec2 p = vec2(texel)/vec2(size)*8.0;
+            float t = params.time;
+            // Two octaves of drifting value noise warp a plasma.
+            float n = noise(p+vec2(0.3*t, 0.0))+0.5*noise(2.0*p-vec2(0.0, 0.5*t));
+            float v = sin(p.x+t+3.0*n)+sin(1.3*p.y-0.7*t+2.0*n);
+            vec3 color = 0.5+0.5*cos(3.14159*v+vec3(0.0, 2.094, 4.188));
+            imageStore(pattern, texel, vec4(color, 1.0));
+        }
+        "
+    }
+}
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) out vec2 out_uv;
+
+        void main() {
+            vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+            out_uv = uv;
+            gl_Position = vec4(uv*2.0-1.0, 0.0, 1.0);
+        }
+        "
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 in_uv;
+
+        layout(location = 0) out vec4 f_color;
+
+        layout(set = 0, binding = 0) uniform sampler2D pattern;
+
+        void main() {
+            // Dimmed, so the scene stands out in front of it.
+            f_color = vec4(texture(pattern, in_uv).rgb*0.35, 1.0);
+        }
+        "
+    }
+}
+
+/// An animated pattern filling the background for
+/// [`RendererSettings::procedural_background`](super::RendererSettings::procedural_background).
+/// Each frame a compute pass writes it into a storage image, which a full-screen
+/// triangle then samples behind the scene. The image is written and read in the
+/// same command buffer, with the barrier between the two left to vulkano.
+pub struct ProceduralBackground {
+    compute_pipeline: Arc<ComputePipeline>,
+    /// Writes the pattern through a storage image descriptor.
+    compute_descriptor_set: Arc<PersistentDescriptorSet>,
+    pipeline: Arc<GraphicsPipeline>,
+    image: Arc<ImageView<StorageImage>>,
+    sampler: Arc<Sampler>,
+    /// Samples the pattern; rebuilt with the pipeline.
+    descriptor_set: Arc<PersistentDescriptorSet>,
+}
+
+impl ProceduralBackground {
+    pub fn new(
+        device: &Arc<Device>,
+        queue_family: QueueFamily,
+        target: &RenderTarget,
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        let compute_shader = compute_shader::load(device.clone()).unwrap();
+        let compute_pipeline = ComputePipeline::new(
+            device.clone(),
+            compute_shader.entry_point("main").unwrap(),
+            &(),
+            None,
+            |_| {},
+        )
+        .unwrap();
+
+        // Every implementation supports RGBA8 storage images.
+        let image = StorageImage::with_usage(
+            device.clone(),
+            ImageDimensions::Dim2d {
+                width: PATTERN_SIZE,
+                height: PATTERN_SIZE,
+                array_layers: 1,
+            },
+            Format::R8G8B8A8_UNORM,
+            ImageUsage {
+                storage: true,
+                sampled: true,
+                ..ImageUsage::none()
+            },
+            ImageCreateFlags::none(),
+            [queue_family],
+        )
+        .unwrap();
+        memory_stats.track(
+            AllocationPurpose::Texture,
+            (PATTERN_SIZE * PATTERN_SIZE * 4) as DeviceSize,
+        );
+        let image = ImageView::new_default(image).unwrap();
+
+        let compute_descriptor_set = PersistentDescriptorSet::new(
+            compute_pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::image_view(0, image.clone())],
+        )
+        .unwrap();
+
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let pipeline = create_pipeline(device, target);
+        let descriptor_set = sampling_descriptor_set(&pipeline, &image, &sampler);
+        ProceduralBackground {
+            compute_pipeline,
+            compute_descriptor_set,
+            pipeline,
+            image,
+            sampler,
+            descriptor_set,
+        }
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: Arc<GraphicsPipeline>) {
+        self.descriptor_set = sampling_descriptor_set(&pipeline, &self.image, &self.sampler);
+        self.pipeline = pipeline;
+    }
+
+    /// Records the compute pass writing the pattern at `time`. Call outside the
+    /// render pass, before [`Self::draw`].
+    pub fn generate(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        time: f32,
+    ) {
+        let groups = PATTERN_SIZE.div_ceil(WORKGROUP_SIZE);
+        builder
+            .bind_pipeline_compute(self.compute_pipeline.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.compute_pipeline.layout().clone(),
+                0,
+                self.compute_descriptor_set.clone(),
+            )
+            .push_constants(
+                self.compute_pipeline.layout().clone(),
+                0,
+                compute_shader::ty::PatternParams { time },
+            )
+            .dispatch([groups, groups, 1])
+            .unwrap();
+    }
+
+    /// Draws the pattern written by the last [`Self::generate`] over the whole
+    /// viewport. Call first in the scene pass, as it covers everything before it.
+    pub fn draw<L, P>(&self, builder: &mut AutoCommandBufferBuilder<L, P>, viewport: &Viewport) {
+        builder
+            .set_viewport(0, [viewport.clone()])
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline.layout().clone(),
+                0,
+                self.descriptor_set.clone(),
+            )
+            .draw(3, 1, 0, 0)
+            .unwrap();
+    }
+}
+
+fn sampling_descriptor_set(
+    pipeline: &Arc<GraphicsPipeline>,
+    image: &Arc<ImageView<StorageImage>>,
+    sampler: &Arc<Sampler>,
+) -> Arc<PersistentDescriptorSet> {
+    PersistentDescriptorSet::new(
+        pipeline.layout().set_layouts()[0].clone(),
+        [WriteDescriptorSet::image_view_sampler(
+            0,
+            image.clone(),
+            sampler.clone(),
+        )],
+    )
+    .unwrap()
+}
+
+pub fn create_pipeline(device: &Arc<Device>, target: &RenderTarget) -> Arc<GraphicsPipeline> {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = fragment_shader::load(device.clone()).unwrap();
+
+    GraphicsPipeline::start()
+        .render_pass(target.scene())
+        .vertex_input_state(BuffersDefinition::new())
+        .input_assembly_state(InputAssemblyState::new())
+        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+        .build(device.clone())
+        .unwrap()
+}
@@ -0,0 +1,101 @@
+use ash::vk;
+use std::sync::Arc;
+use tracing::warn;
+use tracy_client::{Client, GpuContext, GpuContextType, GpuSpan};
+use vulkano::{
+    device::{Device, DeviceExtensions},
+    VulkanObject,
+};
+
+/// Lines the GPU zones up with the CPU ones, enabled when the device has it.
+pub fn optional_device_extensions() -> DeviceExtensions {
+    DeviceExtensions {
+        ext_calibrated_timestamps: true,
+        ..DeviceExtensions::none()
+    }
+}
+
+/// Ends a frame in Tracy.
+pub fn frame_mark() {
+    if let Some(client) = Client::running() {
+        client.frame_mark();
+    }
+}
+
+/// Sends each frame's pair of timestamp queries to Tracy as a GPU zone. Zones are
+/// opened and closed as the frame is recorded, and get their times once the frame
+/// is done and its queries are read back.
+pub struct GpuProfiler {
+    context: GpuContext,
+    /// By frame in flight.
+    spans: Vec<Option<GpuSpan>>,
+}
+
+impl GpuProfiler {
+    /// Needs VK_EXT_calibrated_timestamps, which tells where the GPU's clock is now
+    /// so Tracy can place its timestamps next to the CPU's.
+    pub fn new(device: &Arc<Device>, frames_in_flight: usize) -> Option<Self> {
+        if !device.enabled_extensions().ext_calibrated_timestamps {
+            warn!("device doesn't support calibrated timestamps, Tracy won't show GPU zones");
+            return None;
+        }
+        let info = vk::CalibratedTimestampInfoEXT {
+            time_domain: vk::TimeDomainEXT::DEVICE,
+            ..Default::default()
+        };
+        let mut timestamp = 0;
+        let mut max_deviation = 0;
+        unsafe {
+            (device
+                .fns()
+                .ext_calibrated_timestamps
+                .get_calibrated_timestamps_ext)(
+                device.internal_object(),
+                1,
+                &info,
+                &mut timestamp,
+                &mut max_deviation,
+            )
+            .result()
+            .ok()?;
+        }
+        let period = device.physical_device().properties().timestamp_period;
+        let context = Client::running()?
+            .new_gpu_context(
+                Some("graphics queue"),
+                GpuContextType::Vulkan,
+                timestamp as i64,
+                period,
+            )
+            .map_err(|e| warn!(error = %e, "failed to create a Tracy GPU context"))
+            .ok()?;
+        Some(GpuProfiler {
+            context,
+            spans: (0..frames_in_flight).map(|_| None).collect(),
+        })
+    }
+
+    /// Opens the zone of the frame in `frame_index`, where it writes its first
+    /// timestamp.
+    pub fn begin(&mut self, frame_index: usize) {
+        self.spans[frame_index] = self
+            .context
+            .span_alloc("frame", "record_commands", file!(), line!())
+            .ok();
+    }
+
+    /// Closes it, where the frame writes its second timestamp.
+    pub fn end(&mut self, frame_index: usize) {
+        if let Some(span) = self.spans[frame_index].as_mut() {
+            span.end_zone();
+        }
+    }
+
+    /// Hands the zone its `timestamps`, or drops it if they couldn't be read.
+    pub fn read(&mut self, frame_index: usize, timestamps: Option<[u64; 2]>) {
+        let span = self.spans[frame_index].take();
+        if let (Some(span), Some([start, end])) = (span, timestamps) {
+            span.upload_timestamp(start as i64, end as i64);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use super::Vertex;
+use std::sync::Arc;
+use vulkano::{
+    device::Device,
+    pipeline::{
+        graphics::rasterization::{
+            CullMode, DepthBias, DepthBiasState, FrontFace, RasterizationState,
+        },
+        StateMode,
+    },
+};
+
+/// Fixed-function state the scene's materials, their depth pre-pass and the debug
+/// lines are rasterized with. Changing it with
+/// [`Renderer::set_rasterizer`](super::Renderer::set_rasterizer) rebuilds those
+/// pipelines.
+#[derive(Clone, Copy, Debug)]
+pub struct RasterizerSettings {
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    /// Added to the depth of every fragment. The pre-pass and the materials are
+    /// biased alike, so their depths still match.
+    pub depth_bias: Option<DepthBias>,
+    /// Width of the debug lines and of meshes drawn as lines, in pixels, clamped to
+    /// the device's range. Without `wide_lines` the debug lines are expanded into
+    /// quads this wide instead, and meshes are drawn 1 pixel wide.
+    pub line_width: f32,
+}
+
+impl Default for RasterizerSettings {
+    fn default() -> Self {
+        RasterizerSettings {
+            cull_mode: CullMode::None,
+            front_face: FrontFace::CounterClockwise,
+            depth_bias: None,
+            line_width: 1.0,
+        }
+    }
+}
+
+/// Line widths L cycles through.
+const LINE_WIDTHS: [f32; 4] = [1.0, 2.0, 4.0, 8.0];
+
+impl RasterizerSettings {
+    /// Parses `none`, `back`, `front` or `both`.
+    pub fn parse_cull_mode(name: &str) -> Option<CullMode> {
+        match name {
+            "none" => Some(CullMode::None),
+            "back" => Some(CullMode::Back),
+            "front" => Some(CullMode::Front),
+            "both" => Some(CullMode::FrontAndBack),
+            _ => None,
+        }
+    }
+
+    /// Parses `ccw` or `cw`.
+    pub fn parse_front_face(name: &str) -> Option<FrontFace> {
+        match name {
+            "ccw" => Some(FrontFace::CounterClockwise),
+            "cw" => Some(FrontFace::Clockwise),
+            _ => None,
+        }
+    }
+
+    /// Parses `CONSTANT` or `CONSTANT,SLOPE`.
+    pub fn parse_depth_bias(value: &str) -> Option<DepthBias> {
+        let (constant, slope) = value.split_once(',').unwrap_or((value, "0"));
+        Some(DepthBias {
+            constant_factor: constant.trim().parse().ok()?,
+            // Non-zero needs the `depth_bias_clamp` feature.
+            clamp: 0.0,
+            slope_factor: slope.trim().parse().ok()?,
+        })
+    }
+
+    /// No culling, then back faces, then front faces.
+    pub fn cycle_cull_mode(&mut self) {
+        self.cull_mode = match self.cull_mode {
+            CullMode::None => CullMode::Back,
+            CullMode::Back => CullMode::Front,
+            CullMode::Front | CullMode::FrontAndBack => CullMode::None,
+        };
+    }
+
+    pub fn flip_front_face(&mut self) {
+        self.front_face = match self.front_face {
+            FrontFace::CounterClockwise => FrontFace::Clockwise,
+            FrontFace::Clockwise => FrontFace::CounterClockwise,
+        };
+    }
+
+    /// The next of 1, 2, 4 and 8 pixels wider than the current width, or 1 after 8.
+    pub fn cycle_line_width(&mut self) {
+        self.line_width = LINE_WIDTHS
+            .into_iter()
+            .find(|&width| width > self.line_width)
+            .unwrap_or(LINE_WIDTHS[0]);
+    }
+
+    /// The width `device` rasterizes lines at, or `None` if it can't draw them
+    /// [`Self::line_width`] wide.
+    pub fn native_line_width(&self, device: &Arc<Device>) -> Option<f32> {
+        if device.enabled_features().wide_lines {
+            let [min, max] = device.physical_device().properties().line_width_range;
+            Some(self.line_width.clamp(min, max))
+        } else if self.line_width == 1.0 {
+            Some(1.0)
+        } else {
+            None
+        }
+    }
+
+    /// For the scene's meshes, whatever their topology.
+    pub fn mesh_state(&self, device: &Arc<Device>) -> RasterizationState {
+        RasterizationState {
+            depth_bias: self.depth_bias.map(|bias| DepthBiasState {
+                enable_dynamic: false,
+                bias: StateMode::Fixed(bias),
+            }),
+            line_width: StateMode::Fixed(self.native_line_width(device).unwrap_or(1.0)),
+            ..RasterizationState::new()
+                .cull_mode(self.cull_mode)
+                .front_face(self.front_face)
+        }
+    }
+
+    /// For the debug lines, or the quads [`expand_lines`] made of them.
+    pub fn line_state(&self, device: &Arc<Device>) -> RasterizationState {
+        RasterizationState {
+            line_width: StateMode::Fixed(self.native_line_width(device).unwrap_or(1.0)),
+            ..RasterizationState::new()
+        }
+    }
+}
+
+/// Turns a line list in clip space into a triangle list of quads `width` pixels
+/// wide in a viewport of `dimensions`, for devices without wide lines. Each line
+/// becomes two triangles, colored like its ends.
+pub fn expand_lines(vertices: &[Vertex], width: f32, dimensions: [f32; 2]) -> Vec<Vertex> {
+    let mut triangles = Vec::with_capacity(vertices.len() * 3);
+    for line in vertices.chunks_exact(2) {
+        let (a, b) = (line[0], line[1]);
+        // Perpendicular to the line in pixels, half the width long, back in clip space.
+        let pixels = [0, 1].map(|axis| (b.position[axis] - a.position[axis]) * dimensions[axis]);
+        let length = pixels[0].hypot(pixels[1]);
+        if length == 0.0 {
+            continue;
+        }
+        let offset = [
+            -pixels[1] / length * width / dimensions[0],
+            pixels[0] / length * width / dimensions[1],
+        ];
+        let shift = |vertex: Vertex, sign: f32| Vertex {
+            position: [
+                vertex.position[0] + sign * offset[0],
+                vertex.position[1] + sign * offset[1],
+            ],
+            ..vertex
+        };
+        triangles.extend([
+            shift(a, -1.0),
+            shift(a, 1.0),
+            shift(b, 1.0),
+            shift(a, -1.0),
+            shift(b, 1.0),
+            shift(b, -1.0),
+        ]);
+    }
+    triangles
+}
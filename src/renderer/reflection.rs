@@ -0,0 +1,228 @@
+use super::{InstanceData, Vertex};
+use vulkano::{
+    descriptor_set::layout::DescriptorSetLayout,
+    pipeline::graphics::vertex_input::{Vertex as _, VertexMemberInfo, VertexMemberTy},
+    shader::{EntryPoint, ShaderInterfaceEntry, ShaderInterfaceEntryType, ShaderScalarType},
+};
+
+// vulkano reflects the SPIR-V of every shader module it loads. These checks compare
+// what it found with what the renderer binds, and list everything that doesn't fit
+// at once, where pipeline creation would stop at the first mismatch and describe it
+// in terms of formats.
+
+/// Checks that every input of the vertex shader `entry_point` is a field of
+/// [`Vertex`] or [`InstanceData`] of the same type. vulkano pairs inputs with fields
+/// by name, so the names have to match too.
+pub fn check_vertex_input(entry_point: &EntryPoint) -> Result<(), String> {
+    let mismatches = entry_point
+        .input_interface()
+        .elements()
+        .iter()
+        .filter_map(|input| {
+            let name = match input.name.as_deref() {
+                Some(name) => name,
+                None => return Some(format!("{}: has no name to match", describe(input))),
+            };
+            let (buffer, member) = match Vertex::member(name)
+                .map(|member| ("Vertex", member))
+                .or_else(|| InstanceData::member(name).map(|member| ("InstanceData", member)))
+            {
+                Some(found) => found,
+                None => {
+                    return Some(format!(
+                        "{}: neither Vertex nor InstanceData has such a field",
+                        describe(input)
+                    ))
+                }
+            };
+            (!member_matches(&member, &input.ty)).then(|| {
+                format!(
+                    "{}: {}::{} is {}",
+                    describe(input),
+                    buffer,
+                    name,
+                    describe_member(&member)
+                )
+            })
+        })
+        .collect();
+    report(mismatches)
+}
+
+/// Checks that every input of the fragment shader `fragment` is an output of the
+/// vertex shader `vertex` at the same location and of the same type.
+pub fn check_stage_interface(vertex: &EntryPoint, fragment: &EntryPoint) -> Result<(), String> {
+    let outputs = vertex.output_interface().elements();
+    let mismatches = fragment
+        .input_interface()
+        .elements()
+        .iter()
+        .filter_map(|input| {
+            let output = outputs.iter().find(|output| {
+                output.location == input.location && output.component == input.component
+            });
+            match output {
+                None => Some(format!(
+                    "{}: the vertex shader writes nothing there",
+                    describe(input)
+                )),
+                Some(output) if output.ty != input.ty => Some(format!(
+                    "{}: the vertex shader writes {}",
+                    describe(input),
+                    describe(output)
+                )),
+                Some(_) => None,
+            }
+        })
+        .collect();
+    report(mismatches)
+}
+
+/// Checks that the descriptors `entry_point` uses are all in `textures`, as set 0, or
+/// in `lights`, as set 1; materials aren't bound any other set.
+pub fn check_descriptors(
+    entry_point: &EntryPoint,
+    textures: &DescriptorSetLayout,
+    lights: &DescriptorSetLayout,
+) -> Result<(), String> {
+    let mut mismatches: Vec<_> = entry_point
+        .descriptor_requirements()
+        .filter_map(|((set, binding), requirements)| {
+            let error =
+                |message: String| Some(format!("set {} binding {}: {}", set, binding, message));
+            let (name, set_layout) = match set {
+                0 => ("textures", textures),
+                1 => ("lights", lights),
+                _ => {
+                    return error(
+                        "only set 0, the textures, and set 1, the lights, are bound".to_owned(),
+                    )
+                }
+            };
+            let provided = match set_layout.bindings().get(&binding) {
+                Some(provided) => provided,
+                None => return error(format!("the {} have no such binding", name)),
+            };
+            if !requirements
+                .descriptor_types
+                .contains(&provided.descriptor_type)
+            {
+                return error(format!(
+                    "needs one of {:?}, the {} are {:?}",
+                    requirements.descriptor_types, name, provided.descriptor_type
+                ));
+            }
+            if !provided.variable_descriptor_count
+                && requirements.descriptor_count > provided.descriptor_count
+            {
+                return error(format!(
+                    "needs {} descriptors, the {} have {}",
+                    requirements.descriptor_count, name, provided.descriptor_count
+                ));
+            }
+            None
+        })
+        .collect();
+    // Reflection keeps them in a hash map.
+    mismatches.sort();
+    report(mismatches)
+}
+
+fn report(mismatches: Vec<String>) -> Result<(), String> {
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(mismatches.join("\n"))
+    }
+}
+
+/// An input or output as its declaration would read, e.g. "location 1 `color`
+/// (vec4)".
+fn describe(entry: &ShaderInterfaceEntry) -> String {
+    let location = match entry.component {
+        0 => format!("location {}", entry.location),
+        component => format!("location {} component {}", entry.location, component),
+    };
+    match entry.name.as_deref() {
+        Some(name) => format!("{} `{}` ({})", location, name, glsl_type(&entry.ty)),
+        None => format!("{} ({})", location, glsl_type(&entry.ty)),
+    }
+}
+
+fn glsl_type(ty: &ShaderInterfaceEntryType) -> String {
+    let (scalar, vector_prefix) = match (ty.base_type, ty.is_64bit) {
+        (ShaderScalarType::Float, false) => ("float", ""),
+        (ShaderScalarType::Float, true) => ("double", "d"),
+        (ShaderScalarType::Sint, false) => ("int", "i"),
+        (ShaderScalarType::Sint, true) => ("int64_t", "i64"),
+        (ShaderScalarType::Uint, false) => ("uint", "u"),
+        (ShaderScalarType::Uint, true) => ("uint64_t", "u64"),
+    };
+    let element = match ty.num_components {
+        1 => scalar.to_owned(),
+        components => format!("{}vec{}", vector_prefix, components),
+    };
+    match ty.num_elements {
+        1 => element,
+        elements => format!("{}[{}]", element, elements),
+    }
+}
+
+fn describe_member(member: &VertexMemberInfo) -> String {
+    let ty = format!("{:?}", member.ty).to_lowercase();
+    match member.array_size {
+        1 => ty,
+        size => format!("[{}; {}]", ty, size),
+    }
+}
+
+/// Whether a field holds what the shader reads: the same kind of number, and as many
+/// bytes of it.
+fn member_matches(member: &VertexMemberInfo, ty: &ShaderInterfaceEntryType) -> bool {
+    let (base_type, size) = match member.ty {
+        VertexMemberTy::I8 => (ShaderScalarType::Sint, 1),
+        VertexMemberTy::U8 => (ShaderScalarType::Uint, 1),
+        VertexMemberTy::I16 => (ShaderScalarType::Sint, 2),
+        VertexMemberTy::U16 => (ShaderScalarType::Uint, 2),
+        VertexMemberTy::I32 => (ShaderScalarType::Sint, 4),
+        VertexMemberTy::U32 => (ShaderScalarType::Uint, 4),
+        VertexMemberTy::F32 => (ShaderScalarType::Float, 4),
+        VertexMemberTy::F64 => (ShaderScalarType::Float, 8),
+    };
+    let component_size = if ty.is_64bit { 8 } else { 4 };
+    base_type == ty.base_type
+        && member.array_size * size
+            == (ty.num_components * ty.num_elements) as usize * component_size
+}
+
+/// Fails the build unless `$rust`, a struct written by hand to share memory with a
+/// shader, lays out its fields like `$shader` does, the struct vulkano-shaders
+/// generates from the offsets in the shader's SPIR-V. Each field is paired with the
+/// shader's name for it, as the two don't have to agree.
+macro_rules! assert_shader_layout {
+    ($rust:ty, $shader:ty { $($rust_field:ident => $shader_field:ident),* $(,)? }) => {
+        const _: () = {
+            $(
+                assert!(
+                    std::mem::offset_of!($rust, $rust_field)
+                        == std::mem::offset_of!($shader, $shader_field),
+                    concat!(
+                        stringify!($rust), "::", stringify!($rust_field),
+                        " isn't at the offset of `", stringify!($shader_field), "` in ",
+                        stringify!($shader), ". GLSL aligns a vec3 like a vec4, so the \
+                        Rust struct needs padding before it, or the shader a vec4",
+                    )
+                );
+            )*
+            assert!(
+                std::mem::size_of::<$rust>() == std::mem::size_of::<$shader>(),
+                concat!(
+                    stringify!($rust), " isn't the size of ", stringify!($shader),
+                    ". Arrays of it are spaced by the shader's size, which is rounded up \
+                    to its largest member's alignment; pad the end of the Rust struct",
+                )
+            );
+        };
+    };
+}
+pub(super) use assert_shader_layout;
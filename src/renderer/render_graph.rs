@@ -0,0 +1,629 @@
+use std::{collections::HashMap, sync::Arc};
+use vulkano::{
+    device::Device,
+    format::{ClearValue, Format},
+    image::{
+        view::{ImageView, ImageViewAbstract},
+        AttachmentImage, ImageLayout, ImageUsage, SampleCount,
+    },
+    render_pass::{
+        AttachmentDescription, AttachmentReference, Framebuffer, FramebufferCreateInfo, LoadOp,
+        RenderPass, RenderPassCreateInfo, RenderPassCreationError, StoreOp, Subpass,
+        SubpassDependency, SubpassDescription,
+    },
+    sync::{AccessFlags, PipelineStages},
+};
+
+/// An attachment of a [`RenderGraph`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AttachmentId(usize);
+
+/// A pass of a [`RenderGraph`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PassId(usize);
+
+struct Attachment {
+    name: &'static str,
+    format: Format,
+    /// Cleared by the first pass writing it, else that pass overwrites all of it.
+    clear: bool,
+    /// Allocated by the graph and discarded at the end, as opposed to handed in,
+    /// like a swapchain image, and stored.
+    transient: bool,
+}
+
+/// What a pass of a [`RenderGraph`] draws to and reads.
+pub struct PassDesc {
+    pub name: &'static str,
+    pub color: Vec<AttachmentId>,
+    pub depth: Option<AttachmentId>,
+    /// Read as input attachments, at the fragment being shaded.
+    pub input: Vec<AttachmentId>,
+}
+
+/// Passes and the attachments they write and read, compiled into a render pass
+/// with one subpass per pass. Passes are declared in any order: each runs after
+/// every pass writing what it reads, and passes writing the same attachment keep
+/// their order. The graph works out the attachments' load and store ops and
+/// layouts, the dependencies between subpasses and the external ones of the
+/// imported attachments, and allocates the transient attachments for each
+/// framebuffer.
+///
+/// Dynamic rendering doesn't go through the graph; vulkano inserts the barriers
+/// between its rendering scopes itself.
+#[derive(Default)]
+pub struct RenderGraph {
+    attachments: Vec<Attachment>,
+    passes: Vec<PassDesc>,
+}
+
+impl RenderGraph {
+    /// An attachment the graph allocates for every framebuffer and that only lives
+    /// within the render pass, so tiled GPUs can keep it in on-chip memory.
+    pub fn transient(&mut self, name: &'static str, format: Format, clear: bool) -> AttachmentId {
+        self.attachments.push(Attachment {
+            name,
+            format,
+            clear,
+            transient: true,
+        });
+        AttachmentId(self.attachments.len() - 1)
+    }
+
+    /// An attachment handed to [`CompiledGraph::framebuffer`], such as a swapchain
+    /// image. It is stored, and the pass writing it first overwrites all of it.
+    pub fn imported(&mut self, name: &'static str, format: Format) -> AttachmentId {
+        self.attachments.push(Attachment {
+            name,
+            format,
+            clear: false,
+            transient: false,
+        });
+        AttachmentId(self.attachments.len() - 1)
+    }
+
+    pub fn add_pass(&mut self, pass: PassDesc) -> PassId {
+        self.passes.push(pass);
+        PassId(self.passes.len() - 1)
+    }
+
+    /// Orders the passes and creates the render pass. Fails if the passes depend on
+    /// each other in a cycle, or one reads an attachment no pass writes.
+    pub fn compile(self, device: &Arc<Device>) -> Result<CompiledGraph, String> {
+        let order = self.order()?;
+        let mut subpass_of = vec![0; self.passes.len()];
+        for (subpass, &pass) in order.iter().enumerate() {
+            subpass_of[pass] = subpass as u32;
+        }
+
+        // First and last layout of each attachment, and the subpasses using it.
+        let mut layouts: Vec<Option<(ImageLayout, ImageLayout)>> =
+            vec![None; self.attachments.len()];
+        let mut uses: Vec<Vec<u32>> = vec![Vec::new(); self.attachments.len()];
+        let mut usage = vec![ImageUsage::none(); self.attachments.len()];
+        let mut subpasses = Vec::new();
+        for (subpass, &pass) in order.iter().enumerate() {
+            let pass = &self.passes[pass];
+            let mut reference = |attachment: AttachmentId, layout: ImageLayout| {
+                let first = layouts[attachment.0].map_or(layout, |(first, _)| first);
+                layouts[attachment.0] = Some((first, layout));
+                uses[attachment.0].push(subpass as u32);
+                Some(AttachmentReference {
+                    attachment: attachment.0 as u32,
+                    layout,
+                    ..Default::default()
+                })
+            };
+            let color_attachments = pass
+                .color
+                .iter()
+                .map(|&attachment| reference(attachment, ImageLayout::ColorAttachmentOptimal))
+                .collect();
+            let depth_stencil_attachment = pass.depth.and_then(|attachment| {
+                reference(attachment, ImageLayout::DepthStencilAttachmentOptimal)
+            });
+            let input_attachments = pass
+                .input
+                .iter()
+                .map(|&attachment| reference(attachment, ImageLayout::ShaderReadOnlyOptimal))
+                .collect();
+            for &attachment in &pass.input {
+                usage[attachment.0].input_attachment = true;
+            }
+            subpasses.push(SubpassDescription {
+                color_attachments,
+                depth_stencil_attachment,
+                input_attachments,
+                ..Default::default()
+            });
+        }
+        // Attachments used before and after a subpass but not by it have to be kept.
+        for (subpass, description) in subpasses.iter_mut().enumerate() {
+            let subpass = subpass as u32;
+            description.preserve_attachments = uses
+                .iter()
+                .enumerate()
+                .filter(|(_, uses)| {
+                    uses.first().is_some_and(|&first| first < subpass)
+                        && uses.last().is_some_and(|&last| last > subpass)
+                        && !uses.contains(&subpass)
+                })
+                .map(|(attachment, _)| attachment as u32)
+                .collect();
+        }
+
+        let attachments = self
+            .attachments
+            .iter()
+            .zip(&layouts)
+            .map(|(attachment, layouts)| {
+                let (initial_layout, final_layout) = layouts
+                    .ok_or_else(|| format!("no pass uses attachment {:?}", attachment.name))?;
+                let load_op = if attachment.clear {
+                    LoadOp::Clear
+                } else {
+                    LoadOp::DontCare
+                };
+                let store_op = if attachment.transient {
+                    StoreOp::DontCare
+                } else {
+                    StoreOp::Store
+                };
+                Ok(AttachmentDescription {
+                    format: Some(attachment.format),
+                    samples: SampleCount::Sample1,
+                    load_op,
+                    store_op,
+                    stencil_load_op: load_op,
+                    stencil_store_op: store_op,
+                    initial_layout,
+                    final_layout,
+                    ..Default::default()
+                })
+            })
+            .collect::<Result<_, String>>()?;
+
+        let mut dependencies = self.dependencies(&subpass_of);
+        dependencies
+            .extend(self.external_dependencies(&uses, device.enabled_features().synchronization2));
+        let render_pass = RenderPass::new(
+            device.clone(),
+            RenderPassCreateInfo {
+                attachments,
+                subpasses,
+                dependencies,
+                ..Default::default()
+            },
+        )
+        .map_err(|e: RenderPassCreationError| e.to_string())?;
+
+        Ok(CompiledGraph {
+            render_pass,
+            subpass_of,
+            transients: self
+                .attachments
+                .iter()
+                .zip(usage)
+                .map(|(attachment, usage)| {
+                    attachment.transient.then_some(ImageUsage {
+                        transient_attachment: true,
+                        ..usage
+                    })
+                })
+                .collect(),
+        })
+    }
+
+    /// The passes in the order to run them: declaration order, except that readers
+    /// of an attachment come after all of its writers.
+    fn order(&self) -> Result<Vec<usize>, String> {
+        // Neither kind of attachment is loaded, so what no pass writes is undefined.
+        for (pass, desc) in self.passes.iter().enumerate() {
+            if let Some(unwritten) = desc.input.iter().find(|&&attachment| {
+                (0..self.passes.len())
+                    .all(|other| other == pass || !self.writes(other).contains(&attachment))
+            }) {
+                return Err(format!(
+                    "pass {:?} reads attachment {:?}, which no other pass writes",
+                    desc.name, self.attachments[unwritten.0].name
+                ));
+            }
+        }
+        let dependencies: Vec<Vec<usize>> = (0..self.passes.len())
+            .map(|pass| self.predecessors(pass))
+            .collect();
+        let mut order = Vec::with_capacity(self.passes.len());
+        let mut placed = vec![false; self.passes.len()];
+        while order.len() < self.passes.len() {
+            let next = (0..self.passes.len()).find(|&pass| {
+                !placed[pass] && dependencies[pass].iter().all(|&before| placed[before])
+            });
+            match next {
+                Some(pass) => {
+                    placed[pass] = true;
+                    order.push(pass);
+                }
+                None => {
+                    let stuck: Vec<_> = (0..self.passes.len())
+                        .filter(|&pass| !placed[pass])
+                        .map(|pass| self.passes[pass].name)
+                        .collect();
+                    return Err(format!("passes {:?} depend on each other", stuck));
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// The passes that have to run before `pass`: the writers of what it reads, and
+    /// earlier declared writers of what it writes.
+    fn predecessors(&self, pass: usize) -> Vec<usize> {
+        let desc = &self.passes[pass];
+        (0..self.passes.len())
+            .filter(|&other| other != pass)
+            .filter(|&other| {
+                let writes = self.writes(other);
+                desc.input
+                    .iter()
+                    .any(|attachment| writes.contains(attachment))
+                    || (other < pass
+                        && self
+                            .writes(pass)
+                            .iter()
+                            .any(|attachment| writes.contains(attachment)))
+            })
+            .collect()
+    }
+
+    fn writes(&self, pass: usize) -> Vec<AttachmentId> {
+        let desc = &self.passes[pass];
+        desc.color.iter().copied().chain(desc.depth).collect()
+    }
+
+    /// One dependency per pair of subpasses where the later one reads or writes an
+    /// attachment the earlier one wrote, covering just those accesses.
+    fn dependencies(&self, subpass_of: &[u32]) -> Vec<SubpassDependency> {
+        let mut merged: HashMap<(u32, u32), SubpassDependency> = HashMap::new();
+        for (writer, writer_desc) in self.passes.iter().enumerate() {
+            for (reader, reader_desc) in self.passes.iter().enumerate() {
+                let (source, destination) = (subpass_of[writer], subpass_of[reader]);
+                if source >= destination {
+                    continue;
+                }
+                let mut dependency = SubpassDependency {
+                    source_subpass: Some(source),
+                    destination_subpass: Some(destination),
+                    by_region: true,
+                    ..Default::default()
+                };
+                for &attachment in &writer_desc.color {
+                    if reader_desc.input.contains(&attachment) {
+                        dependency.source_stages.color_attachment_output = true;
+                        dependency.source_access.color_attachment_write = true;
+                        dependency.destination_stages.fragment_shader = true;
+                        dependency.destination_access.input_attachment_read = true;
+                    }
+                    if reader_desc.color.contains(&attachment) {
+                        dependency.source_stages.color_attachment_output = true;
+                        dependency.source_access.color_attachment_write = true;
+                        dependency.destination_stages.color_attachment_output = true;
+                        dependency.destination_access.color_attachment_write = true;
+                    }
+                }
+                if let Some(attachment) = writer_desc.depth {
+                    if reader_desc.input.contains(&attachment) {
+                        dependency.source_stages.late_fragment_tests = true;
+                        dependency.source_access.depth_stencil_attachment_write = true;
+                        dependency.destination_stages.fragment_shader = true;
+                        dependency.destination_access.input_attachment_read = true;
+                    }
+                    if reader_desc.depth == Some(attachment) {
+                        dependency.source_stages.late_fragment_tests = true;
+                        dependency.source_access.depth_stencil_attachment_write = true;
+                        dependency.destination_stages.early_fragment_tests = true;
+                        dependency.destination_access.depth_stencil_attachment_read = true;
+                        dependency.destination_access.depth_stencil_attachment_write = true;
+                    }
+                }
+                if dependency.source_stages == PipelineStages::none() {
+                    continue;
+                }
+                merged
+                    .entry((source, destination))
+                    .and_modify(|existing| {
+                        existing.source_stages |= dependency.source_stages;
+                        existing.destination_stages |= dependency.destination_stages;
+                        existing.source_access |= dependency.source_access;
+                        existing.destination_access |= dependency.destination_access;
+                    })
+                    .or_insert(dependency);
+            }
+        }
+        let mut dependencies: Vec<_> = merged.into_values().collect();
+        dependencies
+            .sort_by_key(|dependency| (dependency.source_subpass, dependency.destination_subpass));
+        dependencies
+    }
+
+    /// Explicit dependencies of the imported attachments on what comes before and
+    /// after the render pass, instead of the implicit ones, which wait for the top of
+    /// the pipe and make none of the writes available. An attachment is waited for
+    /// where the acquire semaphore is, and its writes are made available to
+    /// presentation or copies. With synchronization2, nothing after the render
+    /// pass has to wait on a stage, since semaphores and barriers cover it.
+    fn external_dependencies(
+        &self,
+        uses: &[Vec<u32>],
+        synchronization2: bool,
+    ) -> Vec<SubpassDependency> {
+        let mut dependencies = Vec::new();
+        for (index, (attachment, uses)) in self.attachments.iter().zip(uses).enumerate() {
+            let (first, last) = match (uses.first(), uses.last()) {
+                (Some(&first), Some(&last)) if !attachment.transient => (first, last),
+                _ => continue,
+            };
+            let depth = self
+                .passes
+                .iter()
+                .any(|pass| pass.depth == Some(AttachmentId(index)));
+            let (stages, access) = if depth {
+                (
+                    PipelineStages {
+                        early_fragment_tests: true,
+                        late_fragment_tests: true,
+                        ..PipelineStages::none()
+                    },
+                    AccessFlags {
+                        depth_stencil_attachment_read: true,
+                        depth_stencil_attachment_write: true,
+                        ..AccessFlags::none()
+                    },
+                )
+            } else {
+                (
+                    PipelineStages {
+                        color_attachment_output: true,
+                        ..PipelineStages::none()
+                    },
+                    AccessFlags {
+                        color_attachment_write: true,
+                        ..AccessFlags::none()
+                    },
+                )
+            };
+            dependencies.push(SubpassDependency {
+                source_subpass: None,
+                destination_subpass: Some(first),
+                source_stages: stages,
+                destination_stages: stages,
+                destination_access: access,
+                ..Default::default()
+            });
+            dependencies.push(SubpassDependency {
+                source_subpass: Some(last),
+                destination_subpass: None,
+                source_stages: stages,
+                source_access: access,
+                destination_stages: if synchronization2 {
+                    PipelineStages::none()
+                } else {
+                    PipelineStages {
+                        bottom_of_pipe: true,
+                        ..PipelineStages::none()
+                    }
+                },
+                ..Default::default()
+            });
+        }
+        dependencies
+    }
+}
+
+/// A [`RenderGraph`] turned into a render pass.
+pub struct CompiledGraph {
+    render_pass: Arc<RenderPass>,
+    /// By pass, in declaration order.
+    subpass_of: Vec<u32>,
+    /// By attachment: the usage of the transient ones, `None` for imported ones.
+    transients: Vec<Option<ImageUsage>>,
+}
+
+impl CompiledGraph {
+    pub fn subpass(&self, pass: PassId) -> Subpass {
+        Subpass::from(self.render_pass.clone(), self.subpass_of[pass.0]).unwrap()
+    }
+
+    pub fn format(&self, attachment: AttachmentId) -> Format {
+        self.render_pass.attachments()[attachment.0].format.unwrap()
+    }
+
+    /// Clear values to begin the render pass with, from `values` for the attachments
+    /// the graph clears.
+    pub fn clear_values(&self, values: &[(AttachmentId, ClearValue)]) -> Vec<Option<ClearValue>> {
+        (0..self.transients.len())
+            .map(|attachment| {
+                values
+                    .iter()
+                    .find(|(id, _)| id.0 == attachment)
+                    .map(|&(_, value)| value)
+            })
+            .collect()
+    }
+
+    /// Allocates the transient attachments and creates a framebuffer of them and of
+    /// `imported`, which has a view for every imported attachment, by id.
+    pub fn framebuffer(
+        &self,
+        device: &Arc<Device>,
+        dimensions: [u32; 2],
+        imported: &[(AttachmentId, Arc<dyn ImageViewAbstract>)],
+    ) -> GraphFramebuffer {
+        let transients: Vec<_> = self
+            .transients
+            .iter()
+            .enumerate()
+            .map(|(attachment, usage)| {
+                usage.map(|usage| {
+                    let format = self.format(AttachmentId(attachment));
+                    let image =
+                        AttachmentImage::with_usage(device.clone(), dimensions, format, usage)
+                            .unwrap();
+                    ImageView::new_default(image).unwrap()
+                })
+            })
+            .collect();
+        let attachments = transients
+            .iter()
+            .enumerate()
+            .map(|(attachment, transient)| match transient {
+                Some(view) => view.clone() as Arc<dyn ImageViewAbstract>,
+                None => {
+                    let (_, view) = imported
+                        .iter()
+                        .find(|(id, _)| id.0 == attachment)
+                        .expect("every imported attachment needs a view");
+                    view.clone()
+                }
+            })
+            .collect();
+        let framebuffer = Framebuffer::new(
+            self.render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        GraphFramebuffer {
+            framebuffer,
+            transients,
+        }
+    }
+}
+
+/// A framebuffer of a [`CompiledGraph`] and the transient attachments allocated
+/// for it.
+pub struct GraphFramebuffer {
+    pub framebuffer: Arc<Framebuffer>,
+    /// By attachment; `None` for imported ones.
+    transients: Vec<Option<Arc<ImageView<AttachmentImage>>>>,
+}
+
+impl GraphFramebuffer {
+    /// The transient attachment `attachment`.
+    pub fn transient(&self, attachment: AttachmentId) -> Arc<ImageView<AttachmentImage>> {
+        self.transients[attachment.0]
+            .clone()
+            .expect("not a transient attachment")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(passes: &[(&'static str, &[usize], &[usize])]) -> RenderGraph {
+        let mut graph = RenderGraph::default();
+        let count = passes
+            .iter()
+            .flat_map(|(_, color, input)| color.iter().chain(input.iter()))
+            .max()
+            .map_or(0, |&last| last + 1);
+        let attachments: Vec<_> = (0..count)
+            .map(|_| graph.transient("attachment", Format::R8G8B8A8_UNORM, false))
+            .collect();
+        for &(name, color, input) in passes {
+            graph.add_pass(PassDesc {
+                name,
+                color: color.iter().map(|&i| attachments[i]).collect(),
+                depth: None,
+                input: input.iter().map(|&i| attachments[i]).collect(),
+            });
+        }
+        graph
+    }
+
+    #[test]
+    fn independent_passes_keep_their_order() {
+        let graph = graph(&[("a", &[0], &[]), ("b", &[1], &[]), ("c", &[2], &[])]);
+        assert_eq!(graph.order().unwrap(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn readers_run_after_writers() {
+        // The output pass is declared first but reads what the scene pass writes.
+        let graph = graph(&[("output", &[1], &[0]), ("scene", &[0], &[])]);
+        assert_eq!(graph.predecessors(0), [1]);
+        assert!(graph.predecessors(1).is_empty());
+        assert_eq!(graph.order().unwrap(), [1, 0]);
+    }
+
+    #[test]
+    fn readers_wait_for_every_writer() {
+        let graph = graph(&[
+            ("read", &[1], &[0]),
+            ("first", &[0], &[]),
+            ("second", &[0], &[]),
+        ]);
+        assert_eq!(graph.order().unwrap(), [1, 2, 0]);
+    }
+
+    #[test]
+    fn writers_of_one_attachment_keep_their_order() {
+        let graph = graph(&[("first", &[0], &[]), ("second", &[0], &[])]);
+        assert!(graph.predecessors(0).is_empty());
+        assert_eq!(graph.predecessors(1), [0]);
+        assert_eq!(graph.order().unwrap(), [0, 1]);
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let graph = graph(&[
+            ("a", &[1], &[0]),
+            ("b", &[0], &[1]),
+            ("unrelated", &[2], &[]),
+        ]);
+        assert_eq!(
+            graph.order().unwrap_err(),
+            r#"passes ["a", "b"] depend on each other"#
+        );
+    }
+
+    #[test]
+    fn reading_what_no_pass_writes_is_rejected() {
+        let graph = graph(&[("scene", &[0], &[]), ("output", &[2], &[1])]);
+        assert_eq!(
+            graph.order().unwrap_err(),
+            r#"pass "output" reads attachment "attachment", which no other pass writes"#
+        );
+    }
+
+    #[test]
+    fn a_pass_reading_only_what_it_writes_is_rejected() {
+        let graph = graph(&[("feedback", &[0], &[0])]);
+        assert!(graph.order().is_err());
+    }
+
+    #[test]
+    fn input_reads_depend_on_color_writes() {
+        let graph = graph(&[("scene", &[0], &[]), ("output", &[1], &[0])]);
+        let dependencies = graph.dependencies(&[0, 1]);
+        assert_eq!(dependencies.len(), 1);
+        let dependency = &dependencies[0];
+        assert_eq!(
+            (dependency.source_subpass, dependency.destination_subpass),
+            (Some(0), Some(1))
+        );
+        assert!(dependency.source_stages.color_attachment_output);
+        assert!(dependency.source_access.color_attachment_write);
+        assert!(dependency.destination_stages.fragment_shader);
+        assert!(dependency.destination_access.input_attachment_read);
+        assert!(dependency.by_region);
+    }
+
+    #[test]
+    fn unrelated_subpasses_have_no_dependency() {
+        let graph = graph(&[("a", &[0], &[]), ("b", &[1], &[])]);
+        assert!(graph.dependencies(&[0, 1]).is_empty());
+    }
+}
@@ -0,0 +1,453 @@
+use super::{
+    rasterizer::RasterizerSettings, topology::PipelineVariants, vertex_format::MeshVertices,
+    vertex_format::VertexFormat, DrawInputs, InstanceData, RenderTarget, COLOR_SET, SCENE_FORMAT,
+};
+use crate::memory::{AllocationPurpose, MemoryStats};
+use std::{
+    cmp::{max, min},
+    ops::Range,
+    sync::Arc,
+};
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassContents,
+    },
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    device::Device,
+    image::{view::ImageView, AttachmentImage, ImageLayout, ImageUsage, SampleCount},
+    pipeline::{
+        graphics::{
+            color_blend::{AttachmentBlend, ColorBlendState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            vertex_input::BuffersDefinition,
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    render_pass::{
+        AttachmentDescription, AttachmentReference, Framebuffer, FramebufferCreateInfo, LoadOp,
+        RenderPass, RenderPassCreateInfo, StoreOp, Subpass, SubpassDescription,
+    },
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    DeviceSize,
+};
+
+/// Left and right.
+const VIEWS: u32 = 2;
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+        #extension GL_EXT_multiview : require
+
+        layout(location = 0) in vec2 position;
+        layout(location = 1) in vec4 color;
+        layout(location = 2) in vec2 basis_x;
+        layout(location = 3) in vec2 basis_y;
+        layout(location = 4) in vec2 translation;
+        layout(location = 5) in vec2 phase;
+
+        layout(location = 0) out vec4 out_color;
+
+        layout(set = 2, binding = 0) readonly buffer InstanceColors {
+            vec4 colors[];
+        };
+
+        layout(push_constant) uniform StereoParams {
+            // Each view's transform: its scale in xy, then its offset in zw.
+            vec4 views[2];
+            // The rest are the scene's.
+            vec4 bands[2];
+            float x;
+            float y;
+            float zoom;
+        } params;
+
+        void main() {
+            out_color = color*colors[gl_InstanceIndex];
+            vec2 pos = position*vec2(params.x, params.y)*params.zoom;
+            uint band = uint(gl_InstanceIndex)%8u;
+            float amplitude = 0.5*(1.0+2.0*params.bands[band/4u][band%4u]);
+            vec2 wobble = vec2(sin(phase.x+position.x+position.y), sin(phase.y+position.x+position.y))*amplitude;
+            vec2 world = translation+mat2(basis_x, basis_y)*(pos+wobble);
+            vec4 view = params.views[gl_ViewIndex];
+            gl_Position = vec4(world*view.xy+view.zw, 0.0, 1.0);
+            gl_PointSize = 1.0;
+        }
+        "
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec4 in_color;
+
+        layout(location = 0) out vec4 f_color;
+
+        void main() {
+            f_color = in_color;
+        }
+        "
+    }
+}
+
+mod composite_vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) out vec2 out_uv;
+
+        void main() {
+            vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+            out_uv = uv;
+            gl_Position = vec4(uv*2.0-1.0, 0.0, 1.0);
+        }
+        "
+    }
+}
+
+mod composite_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 in_uv;
+
+        layout(location = 0) out vec4 f_color;
+
+        layout(set = 0, binding = 0) uniform sampler2DArray views;
+
+        void main() {
+            // The left view on the left half, the right one on the right half.
+            float layer = in_uv.x < 0.5 ? 0.0 : 1.0;
+            f_color = texture(views, vec3(in_uv.x*2.0-layer, in_uv.y, layer));
+        }
+        "
+    }
+}
+
+/// Draws the instances once for each eye in a single multiview pass, into the two
+/// layers of one image, each eye with its own view transform; then composites the
+/// layers side by side in the scene pass. Instances are drawn with their vertex
+/// colors whatever their material, and the views are cleared to transparent so
+/// what's drawn before the composite shows through.
+pub struct Stereo {
+    render_pass: Arc<RenderPass>,
+    pipelines: PipelineVariants,
+    separation: f32,
+    /// Half the window wide, as each view fills half of it.
+    framebuffer: Arc<Framebuffer>,
+    image: Arc<ImageView<AttachmentImage>>,
+    viewport: Viewport,
+    composite_pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    /// Samples the views; rebuilt with the image and the composite pipeline.
+    descriptor_set: Arc<PersistentDescriptorSet>,
+}
+
+impl Stereo {
+    /// `device` must have the `multiview` feature enabled; `dimensions` are the
+    /// window's.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Arc<Device>,
+        target: &RenderTarget,
+        vertex_format: VertexFormat,
+        topologies: &[PrimitiveTopology],
+        rasterizer: &RasterizerSettings,
+        separation: f32,
+        dimensions: [f32; 2],
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        let render_pass = RenderPass::new(
+            device.clone(),
+            RenderPassCreateInfo {
+                attachments: vec![AttachmentDescription {
+                    format: Some(SCENE_FORMAT),
+                    samples: SampleCount::Sample1,
+                    load_op: LoadOp::Clear,
+                    store_op: StoreOp::Store,
+                    initial_layout: ImageLayout::ColorAttachmentOptimal,
+                    final_layout: ImageLayout::ColorAttachmentOptimal,
+                    ..Default::default()
+                }],
+                subpasses: vec![SubpassDescription {
+                    view_mask: (1 << VIEWS) - 1,
+                    color_attachments: vec![Some(AttachmentReference {
+                        attachment: 0,
+                        layout: ImageLayout::ColorAttachmentOptimal,
+                        ..Default::default()
+                    })],
+                    ..Default::default()
+                }],
+                // The views overlap almost entirely.
+                correlated_view_masks: vec![(1 << VIEWS) - 1],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let pipelines =
+            create_pipelines(device, &render_pass, vertex_format, topologies, rasterizer);
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let (image, framebuffer, viewport) = create_views(device, &render_pass, dimensions);
+        memory_stats.track(AllocationPurpose::Texture, views_size(&viewport));
+        let composite_pipeline = create_composite_pipeline(device, target);
+        let descriptor_set = sampling_descriptor_set(&composite_pipeline, &image, &sampler);
+        Stereo {
+            render_pass,
+            pipelines,
+            separation,
+            framebuffer,
+            image,
+            viewport,
+            composite_pipeline,
+            sampler,
+            descriptor_set,
+        }
+    }
+
+    pub fn set_composite_pipeline(&mut self, pipeline: Arc<GraphicsPipeline>) {
+        self.descriptor_set = sampling_descriptor_set(&pipeline, &self.image, &self.sampler);
+        self.composite_pipeline = pipeline;
+    }
+
+    /// Recreates the pipelines drawing the views, e.g. for new topologies.
+    pub fn recreate_pipelines(
+        &mut self,
+        device: &Arc<Device>,
+        vertex_format: VertexFormat,
+        topologies: &[PrimitiveTopology],
+        rasterizer: &RasterizerSettings,
+    ) {
+        self.pipelines = create_pipelines(
+            device,
+            &self.render_pass,
+            vertex_format,
+            topologies,
+            rasterizer,
+        );
+    }
+
+    /// Recreates the views for a window of `dimensions`, if they changed.
+    pub fn resize(
+        &mut self,
+        device: &Arc<Device>,
+        dimensions: [f32; 2],
+        memory_stats: &mut MemoryStats,
+    ) {
+        if view_dimensions(dimensions) == self.viewport.dimensions {
+            return;
+        }
+        memory_stats.untrack(AllocationPurpose::Texture, views_size(&self.viewport));
+        let (image, framebuffer, viewport) = create_views(device, &self.render_pass, dimensions);
+        memory_stats.track(AllocationPurpose::Texture, views_size(&viewport));
+        self.descriptor_set =
+            sampling_descriptor_set(&self.composite_pipeline, &image, &self.sampler);
+        self.image = image;
+        self.framebuffer = framebuffer;
+        self.viewport = viewport;
+    }
+
+    /// Records the multiview pass drawing `instances` of `inputs` into both views.
+    /// Call outside the render pass, before [`Self::draw`].
+    pub fn render(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        inputs: &DrawInputs,
+        instances: Range<u32>,
+    ) {
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![Some([0.0; 4].into())],
+                    ..RenderPassBeginInfo::framebuffer(self.framebuffer.clone())
+                },
+                SubpassContents::Inline,
+            )
+            .unwrap();
+        let params = vertex_shader::ty::StereoParams {
+            views: self.views(),
+            bands: inputs.push_constants.bands,
+            x: inputs.push_constants.x,
+            y: inputs.push_constants.y,
+            zoom: inputs.push_constants.zoom,
+        };
+        builder.set_viewport(0, [self.viewport.clone()]);
+        // Every material is drawn with the one pipeline, binding it for each batch
+        // only as the topology may change.
+        for batch in inputs.batches {
+            let start = max(batch.instances.start, instances.start);
+            let end = min(batch.instances.end, instances.end);
+            if start >= end {
+                continue;
+            }
+            let mesh = inputs.meshes.get(batch.mesh.0).unwrap_or(&inputs.meshes[0]);
+            let pipeline = self.pipelines.get(mesh.topology());
+            builder
+                .bind_pipeline_graphics(pipeline.clone())
+                .push_constants(pipeline.layout().clone(), 0, params)
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    pipeline.layout().clone(),
+                    COLOR_SET,
+                    inputs.colors.clone(),
+                );
+            mesh.bind(builder, inputs.instance_buffer);
+            let lod = mesh.lod(batch.lod);
+            builder
+                .draw_indexed(lod.end - lod.start, end - start, lod.start, 0, start)
+                .unwrap();
+        }
+        builder.end_render_pass().unwrap();
+    }
+
+    /// The left and right eye's view transforms: the left eye sees the scene moved
+    /// right by half the separation, the right eye left.
+    fn views(&self) -> [[f32; 4]; 2] {
+        let offset = 0.5 * self.separation;
+        [[1.0, 1.0, offset, 0.0], [1.0, 1.0, -offset, 0.0]]
+    }
+
+    /// Draws the views written by the last [`Self::render`] side by side over the
+    /// whole viewport, blended over what's drawn before.
+    pub fn draw<L, P>(&self, builder: &mut AutoCommandBufferBuilder<L, P>, viewport: &Viewport) {
+        builder
+            .set_viewport(0, [viewport.clone()])
+            .bind_pipeline_graphics(self.composite_pipeline.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.composite_pipeline.layout().clone(),
+                0,
+                self.descriptor_set.clone(),
+            )
+            .draw(3, 1, 0, 0)
+            .unwrap();
+    }
+}
+
+/// Each view's size for a window of `dimensions`: half as wide.
+fn view_dimensions(dimensions: [f32; 2]) -> [f32; 2] {
+    [
+        (dimensions[0] / 2.0).max(1.0).floor(),
+        dimensions[1].max(1.0),
+    ]
+}
+
+fn create_views(
+    device: &Arc<Device>,
+    render_pass: &Arc<RenderPass>,
+    dimensions: [f32; 2],
+) -> (Arc<ImageView<AttachmentImage>>, Arc<Framebuffer>, Viewport) {
+    let view_dimensions = view_dimensions(dimensions);
+    let extent = view_dimensions.map(|x| x as u32);
+    let image = AttachmentImage::multisampled_with_usage_with_layers(
+        device.clone(),
+        extent,
+        VIEWS,
+        SampleCount::Sample1,
+        SCENE_FORMAT,
+        ImageUsage {
+            color_attachment: true,
+            sampled: true,
+            ..ImageUsage::none()
+        },
+    )
+    .unwrap();
+    let image = ImageView::new_default(image).unwrap();
+    let framebuffer = Framebuffer::new(
+        render_pass.clone(),
+        FramebufferCreateInfo {
+            attachments: vec![image.clone()],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let viewport = Viewport {
+        origin: [0.0, 0.0],
+        dimensions: view_dimensions,
+        depth_range: 0.0..1.0,
+    };
+    (image, framebuffer, viewport)
+}
+
+/// Bytes of both views at `viewport`, 8 a texel.
+fn views_size(viewport: &Viewport) -> DeviceSize {
+    let [width, height] = viewport.dimensions;
+    (width * height) as DeviceSize * 8 * VIEWS as DeviceSize
+}
+
+fn sampling_descriptor_set(
+    pipeline: &Arc<GraphicsPipeline>,
+    image: &Arc<ImageView<AttachmentImage>>,
+    sampler: &Arc<Sampler>,
+) -> Arc<PersistentDescriptorSet> {
+    PersistentDescriptorSet::new(
+        pipeline.layout().set_layouts()[0].clone(),
+        [WriteDescriptorSet::image_view_sampler(
+            0,
+            image.clone(),
+            sampler.clone(),
+        )],
+    )
+    .unwrap()
+}
+
+fn create_pipelines(
+    device: &Arc<Device>,
+    render_pass: &Arc<RenderPass>,
+    vertex_format: VertexFormat,
+    topologies: &[PrimitiveTopology],
+    rasterizer: &RasterizerSettings,
+) -> PipelineVariants {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = fragment_shader::load(device.clone()).unwrap();
+    PipelineVariants::new(topologies, |topology| {
+        GraphicsPipeline::start()
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .vertex_input_state(MeshVertices::<InstanceData>::new(vertex_format))
+            .input_assembly_state(InputAssemblyState::new().topology(topology))
+            .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+            .rasterization_state(rasterizer.mesh_state(device))
+            .build(device.clone())
+    })
+    .unwrap()
+}
+
+pub fn create_composite_pipeline(
+    device: &Arc<Device>,
+    target: &RenderTarget,
+) -> Arc<GraphicsPipeline> {
+    let loaded_vertex_shader = composite_vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = composite_fragment_shader::load(device.clone()).unwrap();
+
+    GraphicsPipeline::start()
+        .render_pass(target.scene())
+        .vertex_input_state(BuffersDefinition::new())
+        .input_assembly_state(InputAssemblyState::new())
+        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+        .color_blend_state(ColorBlendState::new(1).blend(AttachmentBlend::alpha()))
+        .build(device.clone())
+        .unwrap()
+}
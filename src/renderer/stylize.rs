@@ -0,0 +1,282 @@
+use super::post;
+use crate::memory::{AllocationPurpose, MemoryStats};
+use std::sync::Arc;
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::WriteDescriptorSet,
+    device::Device,
+    image::{view::ImageView, AttachmentImage},
+    pipeline::{graphics::viewport::Viewport, GraphicsPipeline},
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+};
+
+mod chromatic_aberration_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(set = 0, binding = 0) uniform sampler2D scene;
+
+        layout(location = 0) out vec4 f_color;
+
+        layout(push_constant) uniform StyleParams {
+            float intensity;
+            float time;
+        } params;
+
+        void main() {
+            vec2 uv = gl_FragCoord.xy/vec2(textureSize(scene, 0));
+            // Red and blue are split apart from the center out, two hundredths of
+            // the screen in the corners at full intensity.
+            vec2 split = (uv-0.5)*0.04*params.intensity;
+            vec4 center = texture(scene, uv);
+            f_color = vec4(texture(scene, uv+split).r, center.g, texture(scene, uv-split).b, center.a);
+        }
+        "
+    }
+}
+
+mod vignette_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(set = 0, binding = 0) uniform sampler2D scene;
+
+        layout(location = 0) out vec4 f_color;
+
+        layout(push_constant) uniform StyleParams {
+            float intensity;
+            float time;
+        } params;
+
+        void main() {
+            vec2 uv = gl_FragCoord.xy/vec2(textureSize(scene, 0));
+            // 0 in the center to 1 in the corners.
+            vec2 offset = (uv-0.5)*2.0;
+            float falloff = dot(offset, offset)*0.5;
+            vec4 color = texture(scene, uv);
+            f_color = vec4(color.rgb*(1.0-params.intensity*falloff*falloff), color.a);
+        }
+        "
+    }
+}
+
+mod film_grain_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        // Times a second the grain changes, as film's frames would.
+        #define GRAIN_RATE 24.0
+
+        layout(set = 0, binding = 0) uniform sampler2D scene;
+
+        layout(location = 0) out vec4 f_color;
+
+        layout(push_constant) uniform StyleParams {
+            float intensity;
+            float time;
+        } params;
+
+        uint hash(uint x) {
+            x ^= x >> 16;
+            x *= 0x7feb352du;
+            x ^= x >> 15;
+            x *= 0x846ca68bu;
+            x ^= x >> 16;
+            return x;
+        }
+
+        void main() {
+            uvec2 pixel = uvec2(gl_FragCoord.xy);
+            uint frame = uint(params.time*GRAIN_RATE);
+            float grain = float(hash(pixel.x+hash(pixel.y+hash(frame))))/4294967295.0-0.5;
+            vec4 color = texelFetch(scene, ivec2(pixel), 0);
+            // Strongest in the midtones, as film's is.
+            float luma = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+            float amount = params.intensity*0.3*clamp(luma*(2.0-luma), 0.1, 1.0);
+            f_color = vec4(max(color.rgb+grain*amount, 0.0), color.a);
+        }
+        "
+    }
+}
+
+/// Intensities of the stylization passes, from 0 to 1, each off while `None`.
+/// They're drawn in the order of the fields, after the other post effects.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stylization {
+    /// Splits red and blue apart toward the edges, as a cheap lens would.
+    pub chromatic_aberration: Option<f32>,
+    /// Darkens the corners.
+    pub vignette: Option<f32>,
+    /// Adds noise that changes every film frame.
+    pub film_grain: Option<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    ChromaticAberration,
+    Vignette,
+    FilmGrain,
+}
+
+impl Style {
+    /// In the order they're drawn.
+    pub const ALL: [Style; 3] = [
+        Style::ChromaticAberration,
+        Style::Vignette,
+        Style::FilmGrain,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Style::ChromaticAberration => "chromatic aberration",
+            Style::Vignette => "vignette",
+            Style::FilmGrain => "film grain",
+        }
+    }
+
+    /// Its intensity in `stylization`, `None` while it's off.
+    pub fn intensity_mut(self, stylization: &mut Stylization) -> &mut Option<f32> {
+        match self {
+            Style::ChromaticAberration => &mut stylization.chromatic_aberration,
+            Style::Vignette => &mut stylization.vignette,
+            Style::FilmGrain => &mut stylization.film_grain,
+        }
+    }
+}
+
+/// One of the stylization passes, drawing the frame with its [`Style`] into an
+/// image of its own for the next pass or the output pass. Needs dynamic rendering,
+/// as the chromatic aberration reads the frame's neighboring pixels, which an input
+/// attachment can't, and the passes are chained like the other post effects.
+pub struct StylePass {
+    style: Style,
+    intensity: f32,
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    image: Arc<ImageView<AttachmentImage>>,
+    dimensions: [u32; 2],
+}
+
+impl StylePass {
+    pub fn new(
+        device: &Arc<Device>,
+        style: Style,
+        dimensions: [u32; 2],
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        let fragment_shader = match style {
+            Style::ChromaticAberration => chromatic_aberration_shader::load(device.clone()),
+            Style::Vignette => vignette_shader::load(device.clone()),
+            Style::FilmGrain => film_grain_shader::load(device.clone()),
+        }
+        .unwrap();
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        memory_stats.track(AllocationPurpose::Texture, post::target_size(dimensions));
+        StylePass {
+            style,
+            intensity: 0.0,
+            pipeline: post::create_pipeline(device, &fragment_shader),
+            sampler,
+            image: post::create_target(device, dimensions),
+            dimensions,
+        }
+    }
+
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity;
+    }
+
+    /// Recreates the image for a viewport of `dimensions`, if they changed.
+    pub fn resize(
+        &mut self,
+        device: &Arc<Device>,
+        dimensions: [u32; 2],
+        memory_stats: &mut MemoryStats,
+    ) {
+        if dimensions == self.dimensions {
+            return;
+        }
+        memory_stats.untrack(
+            AllocationPurpose::Texture,
+            post::target_size(self.dimensions),
+        );
+        memory_stats.track(AllocationPurpose::Texture, post::target_size(dimensions));
+        self.image = post::create_target(device, dimensions);
+        self.dimensions = dimensions;
+    }
+
+    pub fn untrack_memory(&self, memory_stats: &mut MemoryStats) {
+        memory_stats.untrack(
+            AllocationPurpose::Texture,
+            post::target_size(self.dimensions),
+        );
+    }
+
+    /// What the last [`Self::draw`] wrote, for the next pass to read.
+    pub fn image(&self) -> &Arc<ImageView<AttachmentImage>> {
+        &self.image
+    }
+
+    /// Draws `scene` with the style, `time` seconds in, in a rendering scope of its
+    /// own. Call between the scene's and the output pass's.
+    pub fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        scene: &Arc<ImageView<AttachmentImage>>,
+        time: f32,
+        viewport: &Viewport,
+    ) {
+        let writes = [WriteDescriptorSet::image_view_sampler(
+            0,
+            scene.clone(),
+            self.sampler.clone(),
+        )];
+        let intensity = self.intensity;
+        // The shaders share a push constant block, but each has its own type for it.
+        match self.style {
+            Style::ChromaticAberration => post::draw(
+                builder,
+                &self.pipeline,
+                &self.image,
+                writes,
+                chromatic_aberration_shader::ty::StyleParams { intensity, time },
+                viewport,
+            ),
+            Style::Vignette => post::draw(
+                builder,
+                &self.pipeline,
+                &self.image,
+                writes,
+                vignette_shader::ty::StyleParams { intensity, time },
+                viewport,
+            ),
+            Style::FilmGrain => post::draw(
+                builder,
+                &self.pipeline,
+                &self.image,
+                writes,
+                film_grain_shader::ty::StyleParams { intensity, time },
+                viewport,
+            ),
+        }
+    }
+}
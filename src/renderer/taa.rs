@@ -0,0 +1,204 @@
+use super::post;
+use crate::memory::{AllocationPurpose, MemoryStats};
+use std::sync::Arc;
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::WriteDescriptorSet,
+    device::Device,
+    image::{view::ImageView, AttachmentImage},
+    pipeline::{graphics::viewport::Viewport, GraphicsPipeline},
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    DeviceSize,
+};
+
+/// Frames the jitter pattern repeats after.
+const JITTER_PHASES: u32 = 8;
+
+/// How much of each frame goes into the history once it's built up; the rest is
+/// the history, so about the last `1 / BLEND` frames are averaged.
+const BLEND: f32 = 0.1;
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(set = 0, binding = 0) uniform sampler2D scene;
+        layout(set = 0, binding = 1) uniform sampler2D history;
+        layout(set = 0, binding = 2) uniform sampler2D motion;
+
+        layout(location = 0) out vec4 f_color;
+
+        layout(push_constant) uniform TaaParams {
+            // How much of the frame to take, 1 while there is no history.
+            float blend;
+        } params;
+
+        void main() {
+            ivec2 pixel = ivec2(gl_FragCoord.xy);
+            ivec2 last = textureSize(scene, 0)-1;
+            vec4 current = texelFetch(scene, pixel, 0);
+            // The history is kept within the colors around the pixel this frame,
+            // so what moved or changed doesn't leave a trail.
+            vec4 lo = current;
+            vec4 hi = current;
+            for (int y = -1; y <= 1; y++) {
+                for (int x = -1; x <= 1; x++) {
+                    vec4 neighbor = texelFetch(scene, clamp(pixel+ivec2(x, y), ivec2(0), last), 0);
+                    lo = min(lo, neighbor);
+                    hi = max(hi, neighbor);
+                }
+            }
+            // Where what's at the pixel was the last frame.
+            vec2 uv = gl_FragCoord.xy/vec2(textureSize(history, 0))-texelFetch(motion, pixel, 0).xy;
+            vec4 previous = clamp(texture(history, uv), lo, hi);
+            // What came from off screen has no history.
+            bool outside = any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)));
+            f_color = mix(previous, current, outside ? 1.0 : params.blend);
+        }
+        "
+    }
+}
+
+/// Temporal anti-aliasing: the instances are drawn a fraction of a pixel off each
+/// frame, by [`Self::jitter`], and each frame is blended into a history of the ones
+/// before, which the output pass then shows instead of the frame. Edges thus
+/// average over several sample positions. The history is read where the motion
+/// vectors say the pixel was, and kept from smearing where they're wrong, e.g. for
+/// what was hidden, by clamping it to the frame's colors around the pixel. Needs
+/// dynamic rendering, as it reads the frame's neighboring pixels, which an input
+/// attachment can't.
+pub struct Taa {
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    /// Written on alternate frames, each reading the other.
+    history: [Arc<ImageView<AttachmentImage>>; 2],
+    dimensions: [u32; 2],
+    /// Index of the history the last resolve wrote.
+    current: usize,
+    /// Whether `history[current]` holds a frame; not after a resize.
+    valid: bool,
+    frame: u32,
+}
+
+impl Taa {
+    pub fn new(device: &Arc<Device>, dimensions: [u32; 2], memory_stats: &mut MemoryStats) -> Self {
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        memory_stats.track(AllocationPurpose::Texture, history_size(dimensions));
+        Taa {
+            pipeline: post::create_pipeline(
+                device,
+                &fragment_shader::load(device.clone()).unwrap(),
+            ),
+            sampler,
+            history: create_history(device, dimensions),
+            dimensions,
+            current: 0,
+            valid: false,
+            frame: 0,
+        }
+    }
+
+    /// Recreates the history for a viewport of `dimensions`, if they changed.
+    pub fn resize(
+        &mut self,
+        device: &Arc<Device>,
+        dimensions: [u32; 2],
+        memory_stats: &mut MemoryStats,
+    ) {
+        if dimensions == self.dimensions {
+            return;
+        }
+        memory_stats.untrack(AllocationPurpose::Texture, history_size(self.dimensions));
+        memory_stats.track(AllocationPurpose::Texture, history_size(dimensions));
+        self.history = create_history(device, dimensions);
+        self.dimensions = dimensions;
+        self.valid = false;
+    }
+
+    pub fn untrack_memory(&self, memory_stats: &mut MemoryStats) {
+        memory_stats.untrack(AllocationPurpose::Texture, history_size(self.dimensions));
+    }
+
+    /// Offset of this frame's sample position from the pixel centers in clip space,
+    /// from the Halton sequence in bases 2 and 3.
+    pub fn jitter(&self) -> [f32; 2] {
+        let index = self.frame % JITTER_PHASES + 1;
+        let [width, height] = self.dimensions;
+        [
+            (halton(index, 2) - 0.5) * 2.0 / width as f32,
+            (halton(index, 3) - 0.5) * 2.0 / height as f32,
+        ]
+    }
+
+    /// The history the last [`Self::resolve`] wrote, for the output pass to show.
+    pub fn resolved(&self) -> &Arc<ImageView<AttachmentImage>> {
+        &self.history[self.current]
+    }
+
+    /// Blends `scene` into the history, reprojected by `motion`, in a rendering
+    /// scope of its own. Call between the scene's and the output pass's.
+    pub fn resolve(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        scene: &Arc<ImageView<AttachmentImage>>,
+        motion: &Arc<ImageView<AttachmentImage>>,
+        viewport: &Viewport,
+    ) {
+        let previous = self.current;
+        self.current = 1 - self.current;
+        post::draw(
+            builder,
+            &self.pipeline,
+            &self.history[self.current],
+            [
+                WriteDescriptorSet::image_view_sampler(0, scene.clone(), self.sampler.clone()),
+                WriteDescriptorSet::image_view_sampler(
+                    1,
+                    self.history[previous].clone(),
+                    self.sampler.clone(),
+                ),
+                WriteDescriptorSet::image_view_sampler(2, motion.clone(), self.sampler.clone()),
+            ],
+            fragment_shader::ty::TaaParams {
+                blend: if self.valid { BLEND } else { 1.0 },
+            },
+            viewport,
+        );
+        self.valid = true;
+        self.frame = self.frame.wrapping_add(1);
+    }
+}
+
+/// Element `index` of the Halton sequence in `base`, in [0, 1).
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+fn history_size(dimensions: [u32; 2]) -> DeviceSize {
+    2 * post::target_size(dimensions)
+}
+
+fn create_history(
+    device: &Arc<Device>,
+    dimensions: [u32; 2],
+) -> [Arc<ImageView<AttachmentImage>>; 2] {
+    [(); 2].map(|()| post::create_target(device, dimensions))
+}
@@ -0,0 +1,558 @@
+use super::{
+    demo::{Demo, DemoFrame, DemoInput},
+    nbody::hash,
+    RenderTarget,
+};
+use crate::{
+    memory::{AllocationPurpose, MemoryStats},
+    texture::TextureImage,
+};
+use bytemuck::{Pod, Zeroable};
+use std::{
+    f32::consts::{FRAC_PI_3, TAU},
+    mem::size_of,
+    ops::Range,
+    sync::Arc,
+};
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, SecondaryAutoCommandBuffer,
+    },
+    device::Device,
+    impl_vertex,
+    pipeline::{
+        graphics::{
+            depth_stencil::DepthStencilState, input_assembly::InputAssemblyState,
+            vertex_input::BuffersDefinition, viewport::ViewportState,
+        },
+        GraphicsPipeline, Pipeline,
+    },
+    DeviceSize,
+};
+
+/// Quads along each side of the grid, whatever the heightmap's size.
+const GRID_QUADS: u32 = 256;
+
+/// Quads along each side of a chunk, the unit the grid is culled in.
+const CHUNK_QUADS: u32 = 32;
+
+/// Side of the terrain and height of its highest point, in the units the camera
+/// moves in.
+const SIZE: f32 = 64.0;
+const HEIGHT: f32 = 10.0;
+
+/// Sides of the generated heightmap's grid, and the octaves of noise it's summed
+/// from.
+const GENERATED_SIZE: u32 = 257;
+const GENERATED_OCTAVES: u32 = 6;
+
+/// How far the camera orbits the terrain's center, closest and farthest.
+const MIN_DISTANCE: f32 = 4.0;
+const MAX_DISTANCE: f32 = 90.0;
+
+/// Elevation of the camera above the horizon, lowest and highest, in radians.
+const MIN_PITCH: f32 = 0.1;
+const MAX_PITCH: f32 = 1.45;
+
+/// Distance factor of one scroll wheel line.
+const ZOOM_PER_LINE: f32 = 1.15;
+
+/// Vertical field of view, and the near and far planes' distances.
+const FOV: f32 = FRAC_PI_3;
+const NEAR: f32 = 0.1;
+const FAR: f32 = 200.0;
+
+/// Heights in `[0, 1]` on a grid, which [`DemoKind::Terrain`](super::DemoKind::Terrain)
+/// builds its terrain from.
+pub struct Heightmap {
+    width: u32,
+    height: u32,
+    heights: Vec<f32>,
+}
+
+impl Heightmap {
+    /// The brightness of `image`'s red channel, which is all of it for grayscale
+    /// images. Heightmaps store heights linearly, so it isn't decoded from sRGB.
+    pub fn from_image(image: &TextureImage) -> Self {
+        Heightmap {
+            width: image.width,
+            height: image.height,
+            heights: image
+                .pixels
+                .chunks(4)
+                .map(|pixel| pixel[0] as f32 / 255.0)
+                .collect(),
+        }
+    }
+
+    /// Rolling hills: octaves of value noise, each twice as fine and half as high
+    /// as the last, for when no heightmap is given.
+    pub fn generated() -> Self {
+        let size = GENERATED_SIZE;
+        let mut heights = Vec::with_capacity((size * size) as usize);
+        for y in 0..size {
+            for x in 0..size {
+                let (u, v) = (x as f32 / (size - 1) as f32, y as f32 / (size - 1) as f32);
+                let mut height = 0.0;
+                let mut amplitude = 0.5;
+                for octave in 0..GENERATED_OCTAVES {
+                    let frequency = (4 << octave) as f32;
+                    height += amplitude * value_noise(u * frequency, v * frequency, octave);
+                    amplitude *= 0.5;
+                }
+                heights.push(height);
+            }
+        }
+        // Stretched to the whole range, so the highest peaks get snow.
+        let (min, max) = heights.iter().fold((f32::MAX, f32::MIN), |(min, max), &h| {
+            (min.min(h), max.max(h))
+        });
+        let range = (max - min).max(f32::EPSILON);
+        for height in &mut heights {
+            *height = (*height - min) / range;
+        }
+        Heightmap {
+            width: size,
+            height: size,
+            heights,
+        }
+    }
+
+    /// The height at `[u, v]` in `[0, 1]` across the grid, interpolated between the
+    /// four nearest.
+    fn sample(&self, u: f32, v: f32) -> f32 {
+        let x = u.clamp(0.0, 1.0) * (self.width - 1) as f32;
+        let y = v.clamp(0.0, 1.0) * (self.height - 1) as f32;
+        let (x0, y0) = (x as u32, y as u32);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x.fract(), y.fract());
+        let at = |x: u32, y: u32| self.heights[(y * self.width + x) as usize];
+        let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * fx;
+        let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * fx;
+        top + (bottom - top) * fy
+    }
+}
+
+/// Noise in `[0, 1]` smoothly interpolated between random values at the integer
+/// points, a different set for each `octave`.
+fn value_noise(x: f32, y: f32, octave: u32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let lattice = |dx: u32, dy: u32| {
+        let (ix, iy) = (x0 as u32 + dx, y0 as u32 + dy);
+        hash(ix.wrapping_mul(73_856_093) ^ iy.wrapping_mul(19_349_663) ^ octave << 24)
+    };
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (fx, fy) = (smooth(x - x0), smooth(y - y0));
+    let top = lattice(0, 0) + (lattice(1, 0) - lattice(0, 0)) * fx;
+    let bottom = lattice(0, 1) + (lattice(1, 1) - lattice(0, 1)) * fx;
+    top + (bottom - top) * fy
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+struct TerrainVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+}
+impl_vertex!(TerrainVertex, position, normal);
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec3 position;
+        layout(location = 1) in vec3 normal;
+
+        layout(location = 0) out vec3 out_normal;
+        layout(location = 1) out float out_height;
+
+        layout(push_constant) uniform TerrainParams {
+            mat4 view_projection;
+            // Of the highest point.
+            float height;
+        } params;
+
+        void main() {
+            out_normal = normal;
+            out_height = position.y/params.height;
+            gl_Position = params.view_projection*vec4(position, 1.0);
+        }
+        "
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec3 in_normal;
+        layout(location = 1) in float in_height;
+
+        layout(location = 0) out vec4 f_color;
+
+        const vec3 SUN = normalize(vec3(0.4, 0.8, 0.3));
+        const float AMBIENT = 0.3;
+
+        const vec3 SAND = vec3(0.76, 0.7, 0.5);
+        const vec3 GRASS = vec3(0.25, 0.5, 0.15);
+        const vec3 ROCK = vec3(0.42, 0.38, 0.35);
+        const vec3 SNOW = vec3(0.95, 0.95, 1.0);
+
+        void main() {
+            vec3 normal = normalize(in_normal);
+            // 0 on flat ground, 1 on a cliff.
+            float slope = 1.0-normal.y;
+            vec3 color = mix(SAND, GRASS, smoothstep(0.08, 0.14, in_height));
+            // Snow settles high up, but slides off steep faces.
+            float snow = smoothstep(0.65, 0.75, in_height)*(1.0-smoothstep(0.3, 0.45, slope));
+            color = mix(color, SNOW, snow);
+            color = mix(color, ROCK, smoothstep(0.25, 0.4, slope)*(1.0-snow));
+            float light = AMBIENT+(1.0-AMBIENT)*max(dot(normal, SUN), 0.0);
+            f_color = vec4(color*light, 1.0);
+        }
+        "
+    }
+}
+
+/// A square of the grid drawn with one call: a range of the index buffer, and the
+/// box around its vertices it's culled by.
+struct Chunk {
+    indices: Range<u32>,
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+impl Chunk {
+    /// Whether any of the box is inside all of `planes`.
+    fn visible(&self, planes: &[[f32; 4]; 6]) -> bool {
+        planes.iter().all(|plane| {
+            // The corner farthest along the plane's normal.
+            let corner: [f32; 3] = std::array::from_fn(|i| {
+                if plane[i] >= 0.0 {
+                    self.max[i]
+                } else {
+                    self.min[i]
+                }
+            });
+            plane[0] * corner[0] + plane[1] * corner[1] + plane[2] * corner[2] + plane[3] >= 0.0
+        })
+    }
+}
+
+/// A camera circling the terrain's center, looking at it.
+struct OrbitCamera {
+    /// Around the vertical axis, in radians.
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+}
+
+impl OrbitCamera {
+    fn position(&self) -> [f32; 3] {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        [
+            self.distance * cos_pitch * sin_yaw,
+            self.distance * sin_pitch + 0.3 * HEIGHT,
+            self.distance * cos_pitch * cos_yaw,
+        ]
+    }
+
+    /// From the terrain's space to clip space, for a viewport `aspect` times wider
+    /// than tall.
+    fn view_projection(&self, aspect: f32) -> [[f32; 4]; 4] {
+        let view = look_at(self.position(), [0.0, 0.3 * HEIGHT, 0.0]);
+        multiply(&perspective(FOV, aspect, NEAR, FAR), &view)
+    }
+}
+
+/// A landscape for [`DemoKind::Terrain`](super::DemoKind::Terrain): a grid mesh
+/// lifted by a heightmap, in chunks that are each left out when they're outside
+/// the view. It's colored by height and slope: sand at the bottom, grass, rock on
+/// steep faces and snow on the peaks. Dragging orbits the camera and scrolling
+/// moves it closer.
+pub struct TerrainDemo {
+    pipeline: Arc<GraphicsPipeline>,
+    vertices: Arc<CpuAccessibleBuffer<[TerrainVertex]>>,
+    indices: Arc<CpuAccessibleBuffer<[u32]>>,
+    chunks: Vec<Chunk>,
+    camera: OrbitCamera,
+    dragged_from: Option<[f32; 2]>,
+}
+
+impl TerrainDemo {
+    /// Builds the terrain from `heightmap`, or from generated hills without one.
+    pub fn new(
+        device: &Arc<Device>,
+        target: &RenderTarget,
+        heightmap: Option<&Heightmap>,
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        let generated;
+        let heightmap = match heightmap {
+            Some(heightmap) => heightmap,
+            None => {
+                generated = Heightmap::generated();
+                &generated
+            }
+        };
+        let vertices = grid_vertices(heightmap);
+        let (indices, chunks) = chunk_indices(&vertices);
+        let vertices = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::vertex_buffer(),
+            false,
+            vertices,
+        )
+        .unwrap();
+        let indices = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::index_buffer(),
+            false,
+            indices,
+        )
+        .unwrap();
+        memory_stats.track(AllocationPurpose::Vertex, buffers_size());
+        TerrainDemo {
+            pipeline: create_pipeline(device, target),
+            vertices,
+            indices,
+            chunks,
+            camera: OrbitCamera {
+                yaw: 0.0,
+                pitch: 0.5,
+                distance: 40.0,
+            },
+            dragged_from: None,
+        }
+    }
+
+    fn draw<L, P>(&self, builder: &mut AutoCommandBufferBuilder<L, P>, frame: &DemoFrame) {
+        let [width, height] = frame.viewport.dimensions;
+        let view_projection = self.camera.view_projection(width / height.max(1.0));
+        let planes = frustum_planes(&view_projection);
+        builder
+            .set_viewport(0, [frame.viewport.clone()])
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .bind_vertex_buffers(0, self.vertices.clone())
+            .bind_index_buffer(self.indices.clone())
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                vertex_shader::ty::TerrainParams {
+                    view_projection,
+                    height: HEIGHT,
+                },
+            );
+        for chunk in self.chunks.iter().filter(|chunk| chunk.visible(&planes)) {
+            builder
+                .draw_indexed(
+                    chunk.indices.end - chunk.indices.start,
+                    1,
+                    chunk.indices.start,
+                    0,
+                    0,
+                )
+                .unwrap();
+        }
+    }
+}
+
+impl Demo for TerrainDemo {
+    fn recreate_pipelines(&mut self, device: &Arc<Device>, target: &RenderTarget) {
+        self.pipeline = create_pipeline(device, target);
+    }
+
+    /// Dragging across the window turns the camera around the terrain once, and
+    /// down it raises the camera toward overhead.
+    fn handle_input(&mut self, input: &DemoInput, _dimensions: [f32; 2]) {
+        if let Some(from) = self.dragged_from {
+            self.camera.yaw -= (input.cursor[0] - from[0]) * TAU;
+            self.camera.pitch = (self.camera.pitch + (input.cursor[1] - from[1]) * MAX_PITCH)
+                .clamp(MIN_PITCH, MAX_PITCH);
+        }
+        self.dragged_from = input.pressed.then_some(input.cursor);
+        self.camera.distance = (self.camera.distance / ZOOM_PER_LINE.powf(input.scroll))
+            .clamp(MIN_DISTANCE, MAX_DISTANCE);
+    }
+
+    fn draw_inline(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &DemoFrame,
+    ) {
+        self.draw(builder, frame);
+    }
+
+    fn draw_secondary(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &DemoFrame,
+    ) {
+        self.draw(builder, frame);
+    }
+
+    fn untrack_memory(&self, memory_stats: &mut MemoryStats) {
+        memory_stats.untrack(AllocationPurpose::Vertex, buffers_size());
+    }
+}
+
+fn buffers_size() -> DeviceSize {
+    let vertices = (GRID_QUADS + 1) * (GRID_QUADS + 1);
+    let indices = GRID_QUADS * GRID_QUADS * 6;
+    vertices as DeviceSize * size_of::<TerrainVertex>() as DeviceSize
+        + indices as DeviceSize * size_of::<u32>() as DeviceSize
+}
+
+/// The grid's vertices row by row, centered on the origin, with normals from the
+/// slope between their neighbors.
+fn grid_vertices(heightmap: &Heightmap) -> Vec<TerrainVertex> {
+    let side = GRID_QUADS + 1;
+    let spacing = SIZE / GRID_QUADS as f32;
+    let heights: Vec<f32> = (0..side * side)
+        .map(|i| {
+            let (x, z) = (i % side, i / side);
+            HEIGHT * heightmap.sample(x as f32 / GRID_QUADS as f32, z as f32 / GRID_QUADS as f32)
+        })
+        .collect();
+    let at = |x: u32, z: u32| heights[(z.min(GRID_QUADS) * side + x.min(GRID_QUADS)) as usize];
+    (0..side * side)
+        .map(|i| {
+            let (x, z) = (i % side, i / side);
+            let dx = at(x + 1, z) - at(x.saturating_sub(1), z);
+            let dz = at(x, z + 1) - at(x, z.saturating_sub(1));
+            let normal = [-dx, 2.0 * spacing, -dz];
+            let length =
+                (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+            TerrainVertex {
+                position: [
+                    x as f32 * spacing - 0.5 * SIZE,
+                    at(x, z),
+                    z as f32 * spacing - 0.5 * SIZE,
+                ],
+                normal: normal.map(|n| n / length),
+            }
+        })
+        .collect()
+}
+
+/// The triangles of the grid, chunk after chunk, and the chunks.
+fn chunk_indices(vertices: &[TerrainVertex]) -> (Vec<u32>, Vec<Chunk>) {
+    let side = GRID_QUADS + 1;
+    let chunks_per_side = GRID_QUADS / CHUNK_QUADS;
+    let mut indices = Vec::with_capacity((GRID_QUADS * GRID_QUADS * 6) as usize);
+    let mut chunks = Vec::with_capacity((chunks_per_side * chunks_per_side) as usize);
+    for chunk_z in 0..chunks_per_side {
+        for chunk_x in 0..chunks_per_side {
+            let start = indices.len() as u32;
+            let mut min = [f32::MAX; 3];
+            let mut max = [f32::MIN; 3];
+            for z in chunk_z * CHUNK_QUADS..(chunk_z + 1) * CHUNK_QUADS {
+                for x in chunk_x * CHUNK_QUADS..(chunk_x + 1) * CHUNK_QUADS {
+                    let corner = z * side + x;
+                    indices.extend([
+                        corner,
+                        corner + side,
+                        corner + 1,
+                        corner + 1,
+                        corner + side,
+                        corner + side + 1,
+                    ]);
+                }
+            }
+            for &index in &indices[start as usize..] {
+                let position = vertices[index as usize].position;
+                for i in 0..3 {
+                    min[i] = min[i].min(position[i]);
+                    max[i] = max[i].max(position[i]);
+                }
+            }
+            chunks.push(Chunk {
+                indices: start..indices.len() as u32,
+                min,
+                max,
+            });
+        }
+    }
+    (indices, chunks)
+}
+
+/// A right-handed view from `eye` toward `target`, y up. Matrices are column-major,
+/// as GLSL reads them.
+fn look_at(eye: [f32; 3], target: [f32; 3]) -> [[f32; 4]; 4] {
+    let forward = normalize(std::array::from_fn(|i| target[i] - eye[i]));
+    let side = normalize(cross(forward, [0.0, 1.0, 0.0]));
+    let up = cross(side, forward);
+    [
+        [side[0], up[0], -forward[0], 0.0],
+        [side[1], up[1], -forward[1], 0.0],
+        [side[2], up[2], -forward[2], 0.0],
+        [-dot(side, eye), -dot(up, eye), dot(forward, eye), 1.0],
+    ]
+}
+
+/// A perspective projection to Vulkan's clip space, y down and depth from 0 at
+/// `near` to 1 at `far`.
+fn perspective(fov: f32, aspect: f32, near: f32, far: f32) -> [[f32; 4]; 4] {
+    let focal = 1.0 / (0.5 * fov).tan();
+    [
+        [focal / aspect, 0.0, 0.0, 0.0],
+        [0.0, -focal, 0.0, 0.0],
+        [0.0, 0.0, far / (near - far), -1.0],
+        [0.0, 0.0, near * far / (near - far), 0.0],
+    ]
+}
+
+fn multiply(a: &[[f32; 4]; 4], b: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    std::array::from_fn(|column| {
+        std::array::from_fn(|row| (0..4).map(|k| a[k][row] * b[column][k]).sum())
+    })
+}
+
+/// The planes bounding what `view_projection` shows, as `[a, b, c, d]` with the
+/// inside where `a*x + b*y + c*z + d >= 0`: left, right, bottom, top, near, far.
+fn frustum_planes(view_projection: &[[f32; 4]; 4]) -> [[f32; 4]; 6] {
+    let row = |i: usize| -> [f32; 4] { std::array::from_fn(|column| view_projection[column][i]) };
+    let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+    let add = |a: [f32; 4], b: [f32; 4]| -> [f32; 4] { std::array::from_fn(|i| a[i] + b[i]) };
+    let sub = |a: [f32; 4], b: [f32; 4]| -> [f32; 4] { std::array::from_fn(|i| a[i] - b[i]) };
+    [add(w, x), sub(w, x), add(w, y), sub(w, y), z, sub(w, z)]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = dot(v, v).sqrt();
+    v.map(|x| x / length)
+}
+
+fn create_pipeline(device: &Arc<Device>, target: &RenderTarget) -> Arc<GraphicsPipeline> {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = fragment_shader::load(device.clone()).unwrap();
+
+    GraphicsPipeline::start()
+        .render_pass(target.scene())
+        .vertex_input_state(BuffersDefinition::new().vertex::<TerrainVertex>())
+        .input_assembly_state(InputAssemblyState::new())
+        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+        .depth_stencil_state(DepthStencilState::simple_depth_test())
+        .build(device.clone())
+        .unwrap()
+}
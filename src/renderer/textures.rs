@@ -0,0 +1,372 @@
+use crate::{
+    memory::{AllocationPurpose, MemoryStats},
+    texture::TextureImage,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, iter, sync::Arc};
+use tracing::warn;
+use vulkano::{
+    descriptor_set::{
+        layout::{
+            DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo,
+            DescriptorType,
+        },
+        PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::{physical::PhysicalDevice, Device, DeviceExtensions, Queue},
+    format::Format,
+    image::{
+        view::{ImageView, ImageViewAbstract},
+        ImageDimensions, ImmutableImage, MipmapsCount,
+    },
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    shader::{ShaderModule, ShaderStages},
+    sync::{self, GpuFuture},
+    DeviceSize, Version,
+};
+
+/// The lit shaders' light, which [`Lights`](super::lights::Lights) lays out its own
+/// like.
+pub type ShaderLight = bindless_lit_fragment_shader::ty::Light;
+
+/// Length of the bindless texture array; devices that can't bind this many fall back
+/// to the texture array image.
+pub const MAX_TEXTURES: u32 = 1024;
+
+/// Side of each layer of the fallback array image, which every texture is scaled to.
+const LAYER_SIZE: u32 = 256;
+
+/// A texture given in [`RendererSettings::textures`](super::RendererSettings::textures),
+/// from 1 on in the order given. The default, 0, is plain white.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct TextureId(pub u32);
+
+/// Material that multiplies the vertex colors with the instance's texture, indexing
+/// a variable-count array of textures.
+mod bindless_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+        #extension GL_EXT_nonuniform_qualifier : require
+
+        layout(location = 0) in vec4 in_color;
+        layout(location = 1) in vec2 in_uv;
+        layout(location = 2) flat in uint in_texture;
+
+        layout(location = 0) out vec4 f_color;
+
+        layout(set = 0, binding = 0) uniform sampler2D textures[];
+
+        void main() {
+            f_color = in_color*texture(textures[nonuniformEXT(in_texture)], in_uv);
+        }
+        "
+    }
+}
+
+/// [`bindless_fragment_shader`] for devices without descriptor indexing: the
+/// textures are layers of one array image.
+mod layered_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec4 in_color;
+        layout(location = 1) in vec2 in_uv;
+        layout(location = 2) flat in uint in_texture;
+
+        layout(location = 0) out vec4 f_color;
+
+        layout(set = 0, binding = 0) uniform sampler2DArray textures;
+
+        void main() {
+            f_color = in_color*texture(textures, vec3(in_uv, float(in_texture)));
+        }
+        "
+    }
+}
+
+/// Material that lights the textured vertex colors, with the normals of the
+/// instance's normal map if it has one: by a fixed light, and by the
+/// [`Lights`](super::lights::Lights) bound as set 1.
+mod bindless_lit_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/lit.frag",
+        define: [("BINDLESS", "1")]
+    }
+}
+
+/// [`bindless_lit_fragment_shader`] for devices without descriptor indexing.
+mod layered_lit_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/lit.frag"
+    }
+}
+
+/// Whether `physical_device` can bind [`MAX_TEXTURES`] textures in a variable-count
+/// array and index it with a different texture per instance.
+pub fn bindless_supported(physical_device: PhysicalDevice) -> bool {
+    let features = physical_device.supported_features();
+    let properties = physical_device.properties();
+    physical_device.api_version() >= Version::V1_1
+        && (physical_device.api_version() >= Version::V1_2
+            || physical_device
+                .supported_extensions()
+                .ext_descriptor_indexing)
+        && features.runtime_descriptor_array
+        && features.shader_sampled_image_array_non_uniform_indexing
+        && features.descriptor_binding_variable_descriptor_count
+        && properties.max_per_stage_descriptor_samplers >= MAX_TEXTURES
+        && properties.max_per_stage_descriptor_sampled_images >= MAX_TEXTURES
+        && properties.max_descriptor_set_samplers >= MAX_TEXTURES
+        && properties.max_descriptor_set_sampled_images >= MAX_TEXTURES
+}
+
+/// Core in Vulkan 1.2, an extension before.
+pub fn bindless_extensions(physical_device: PhysicalDevice) -> DeviceExtensions {
+    DeviceExtensions {
+        ext_descriptor_indexing: physical_device.api_version() < Version::V1_2,
+        ..DeviceExtensions::none()
+    }
+}
+
+/// Makes the runtime-sized arrays a shader declares bindless texture arrays of up to
+/// [`MAX_TEXTURES`], matching [`Textures`]' layout.
+pub fn adjust_layout(set_layouts: &mut [DescriptorSetLayoutCreateInfo]) {
+    for binding in set_layouts
+        .iter_mut()
+        .flat_map(|set_layout| set_layout.bindings.values_mut())
+    {
+        if binding.descriptor_count == 0 {
+            binding.descriptor_count = MAX_TEXTURES;
+            binding.variable_descriptor_count = true;
+        }
+    }
+}
+
+/// The textures instances sample by [`TextureId`], bound as set 0 of the materials
+/// whose pipeline layout has one. With descriptor indexing they are a variable-count
+/// array of separate images, each sampled at its own size; without it they are the
+/// layers of one array image, scaled to [`LAYER_SIZE`].
+pub struct Textures {
+    bindless: bool,
+    /// Uploaded after white, kept so that one can be replaced.
+    images: Vec<TextureImage>,
+    anisotropy: Option<f32>,
+    /// Bytes of the uploaded images in the memory stats.
+    size: DeviceSize,
+    descriptor_set: Arc<PersistentDescriptorSet>,
+}
+
+impl Textures {
+    /// Uploads white, then `images`. Bindless only if the device was created with
+    /// the features [`bindless_supported`] checks for. Sampled with `anisotropy` if
+    /// given, which needs the `sampler_anisotropy` feature.
+    pub fn new(
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        images: &[TextureImage],
+        anisotropy: Option<f32>,
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        let enabled_features = device.enabled_features();
+        let bindless = enabled_features.runtime_descriptor_array
+            && enabled_features.shader_sampled_image_array_non_uniform_indexing
+            && enabled_features.descriptor_binding_variable_descriptor_count;
+        let max_textures = if bindless {
+            MAX_TEXTURES
+        } else {
+            device.physical_device().properties().max_image_array_layers
+        } as usize;
+        if images.len() >= max_textures {
+            warn!(
+                textures = images.len(),
+                max_textures, "too many textures, dropping the last ones"
+            );
+        }
+        let images: Vec<TextureImage> = images.iter().take(max_textures - 1).cloned().collect();
+        let (descriptor_set, size) = upload(device, queue, bindless, &images, anisotropy);
+        memory_stats.track(AllocationPurpose::Texture, size);
+        Textures {
+            bindless,
+            images,
+            anisotropy,
+            size,
+            descriptor_set,
+        }
+    }
+
+    /// Replaces the image of `id` with `image` from the next frame on, uploading them
+    /// all again. White can't be replaced.
+    pub fn replace(
+        &mut self,
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        id: TextureId,
+        image: TextureImage,
+        memory_stats: &mut MemoryStats,
+    ) -> Result<(), String> {
+        let slot = (id.0 as usize)
+            .checked_sub(1)
+            .and_then(|index| self.images.get_mut(index))
+            .ok_or_else(|| format!("there's no texture {}", id.0))?;
+        *slot = image;
+        let (descriptor_set, size) =
+            upload(device, queue, self.bindless, &self.images, self.anisotropy);
+        memory_stats.untrack(AllocationPurpose::Texture, self.size);
+        memory_stats.track(AllocationPurpose::Texture, size);
+        self.descriptor_set = descriptor_set;
+        self.size = size;
+        Ok(())
+    }
+
+    /// The fragment shader of a material sampling the instance's texture.
+    pub fn shader(&self, device: &Arc<Device>) -> Arc<ShaderModule> {
+        if self.bindless {
+            bindless_fragment_shader::load(device.clone()).unwrap()
+        } else {
+            layered_fragment_shader::load(device.clone()).unwrap()
+        }
+    }
+
+    /// The fragment shader of a material lighting the instance's texture, with its
+    /// normal map if it has one.
+    pub fn lit_shader(&self, device: &Arc<Device>) -> Arc<ShaderModule> {
+        if self.bindless {
+            bindless_lit_fragment_shader::load(device.clone()).unwrap()
+        } else {
+            layered_lit_fragment_shader::load(device.clone()).unwrap()
+        }
+    }
+
+    pub fn descriptor_set(&self) -> &Arc<PersistentDescriptorSet> {
+        &self.descriptor_set
+    }
+}
+
+/// Uploads white, then `images`, returning the descriptor set binding them and their
+/// size in bytes.
+fn upload(
+    device: &Arc<Device>,
+    queue: &Arc<Queue>,
+    bindless: bool,
+    images: &[TextureImage],
+    anisotropy: Option<f32>,
+) -> (Arc<PersistentDescriptorSet>, DeviceSize) {
+    let white = TextureImage::white();
+    let images: Vec<&TextureImage> = iter::once(&white).chain(images).collect();
+
+    let sampler = Sampler::new(
+        device.clone(),
+        SamplerCreateInfo {
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            address_mode: [SamplerAddressMode::Repeat; 3],
+            anisotropy,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let layout = create_layout(device, bindless);
+    // UNORM rather than SRGB, like the tile atlas: the scene target holds
+    // sRGB-encoded values.
+    if bindless {
+        let mut upload = sync::now(device.clone()).boxed();
+        let mut views: Vec<Arc<dyn ImageViewAbstract>> = Vec::with_capacity(images.len());
+        let mut size = 0;
+        for image in &images {
+            let (texture, texture_upload) = ImmutableImage::from_iter(
+                image.pixels.iter().copied(),
+                ImageDimensions::Dim2d {
+                    width: image.width,
+                    height: image.height,
+                    array_layers: 1,
+                },
+                MipmapsCount::One,
+                Format::R8G8B8A8_UNORM,
+                queue.clone(),
+            )
+            .unwrap();
+            upload = upload.join(texture_upload).boxed();
+            size += image.pixels.len() as DeviceSize;
+            views.push(ImageView::new_default(texture).unwrap());
+        }
+        upload
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+        let descriptor_set = PersistentDescriptorSet::new_variable(
+            layout,
+            views.len() as u32,
+            [WriteDescriptorSet::image_view_sampler_array(
+                0,
+                0,
+                views.into_iter().map(|view| (view, sampler.clone())),
+            )],
+        )
+        .unwrap();
+        (descriptor_set, size)
+    } else {
+        let layers: Vec<u8> = images
+            .iter()
+            .flat_map(|image| image.resampled(LAYER_SIZE))
+            .collect();
+        let size = layers.len() as DeviceSize;
+        let (texture, upload) = ImmutableImage::from_iter(
+            layers,
+            ImageDimensions::Dim2d {
+                width: LAYER_SIZE,
+                height: LAYER_SIZE,
+                array_layers: images.len() as u32,
+            },
+            MipmapsCount::One,
+            Format::R8G8B8A8_UNORM,
+            queue.clone(),
+        )
+        .unwrap();
+        upload
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+        let descriptor_set = PersistentDescriptorSet::new(
+            layout,
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                ImageView::new_default(texture).unwrap(),
+                sampler,
+            )],
+        )
+        .unwrap();
+        (descriptor_set, size)
+    }
+}
+
+/// The layout the pipelines of [`Textures::shader`] get, so the one descriptor set
+/// binds to all of them.
+fn create_layout(device: &Arc<Device>, bindless: bool) -> Arc<DescriptorSetLayout> {
+    let binding = DescriptorSetLayoutBinding {
+        descriptor_count: if bindless { MAX_TEXTURES } else { 1 },
+        variable_descriptor_count: bindless,
+        stages: ShaderStages {
+            fragment: true,
+            ..ShaderStages::none()
+        },
+        ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::CombinedImageSampler)
+    };
+    DescriptorSetLayout::new(
+        device.clone(),
+        DescriptorSetLayoutCreateInfo {
+            bindings: BTreeMap::from([(0, binding)]),
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
@@ -0,0 +1,305 @@
+use super::{push_descriptor_layout, RenderTarget};
+use crate::{
+    allocator::{FrameChunk, FrameRing, StagingRing},
+    memory::{AllocationPurpose, MemoryStats},
+    tilemap::{TileAtlas, Tilemap, CHUNK_SIZE},
+};
+use bytemuck::{Pod, Zeroable};
+use std::{mem::size_of_val, sync::Arc};
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer, TypedBufferAccess},
+    command_buffer::AutoCommandBufferBuilder,
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    device::{Device, Queue},
+    format::Format,
+    image::{view::ImageView, ImageDimensions, ImmutableImage, MipmapsCount},
+    impl_vertex,
+    pipeline::{
+        graphics::{
+            input_assembly::InputAssemblyState,
+            vertex_input::BuffersDefinition,
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    sync::GpuFuture,
+    DeviceSize,
+};
+
+/// Chunks per frame the ring starts out sized for; it grows past that.
+const VISIBLE_CHUNKS: usize = 256;
+
+/// One visible chunk, drawn as an instance of a quad.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct TileChunk {
+    chunk: [u32; 2],
+}
+impl_vertex!(TileChunk, chunk);
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        layout(location = 0) in uvec2 chunk;
+
+        layout(location = 0) out vec2 tile_coord;
+        layout(location = 1) flat out uvec4 grid;
+
+        layout(push_constant) uniform TileView {
+            vec2 origin;
+            vec2 tile_extent;
+            uvec2 map_size;
+            uvec2 atlas_grid;
+            uint chunk_size;
+        } view;
+
+        const vec2 corners[6] = vec2[](
+            vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0),
+            vec2(0.0, 1.0), vec2(1.0, 0.0), vec2(1.0, 1.0)
+        );
+
+        void main() {
+            // Chunks on the map's right and bottom edges stop at the last tile.
+            tile_coord = min((vec2(chunk)+corners[gl_VertexIndex])*float(view.chunk_size), vec2(view.map_size));
+            grid = uvec4(view.map_size, view.atlas_grid);
+            gl_Position = vec4(view.origin+tile_coord*view.tile_extent, 0.0, 1.0);
+        }
+        "
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec2 tile_coord;
+        layout(location = 1) flat in uvec4 grid;
+
+        layout(location = 0) out vec4 f_color;
+
+        layout(set = 0, binding = 0) readonly buffer Tiles {
+            uint tiles[];
+        } map;
+
+        layout(set = 0, binding = 1) uniform sampler2D atlas;
+
+        void main() {
+            uvec2 tile = min(uvec2(tile_coord), grid.xy-1u);
+            uint index = map.tiles[tile.y*grid.x+tile.x];
+            if (index == 0xffffffffu) {
+                discard;
+            }
+            vec2 cell = vec2(index%grid.z, index/grid.z);
+            f_color = texture(atlas, (cell+fract(tile_coord))/vec2(grid.zw));
+        }
+        "
+    }
+}
+
+/// Draws a [`Tilemap`] behind the scene: one quad per visible chunk, whose fragments
+/// look their tile up in a storage buffer and sample it from the atlas.
+pub struct TileLayer {
+    pipeline: Arc<GraphicsPipeline>,
+    atlas: Arc<ImageView<ImmutableImage>>,
+    atlas_grid: [u32; 2],
+    sampler: Arc<Sampler>,
+    tiles: Option<Arc<CpuAccessibleBuffer<[u32]>>>,
+    /// Binds the uploaded tiles and the atlas; `None` when they are pushed instead.
+    descriptor_set: Option<Arc<PersistentDescriptorSet>>,
+    tiles_size: DeviceSize,
+    /// [`Tilemap::revision`] of the uploaded tiles.
+    revision: Option<u64>,
+    view: vertex_shader::ty::TileView,
+    visible: Vec<TileChunk>,
+    chunk_ring: FrameRing<TileChunk>,
+}
+
+impl TileLayer {
+    pub fn new(
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        target: &RenderTarget,
+        atlas: &TileAtlas,
+        frames_in_flight: usize,
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        // UNORM rather than SRGB: the scene target holds sRGB-encoded values, which
+        // the output pass decodes.
+        let (image, upload) = ImmutableImage::from_iter(
+            atlas.pixels.iter().copied(),
+            ImageDimensions::Dim2d {
+                width: atlas.width,
+                height: atlas.height,
+                array_layers: 1,
+            },
+            MipmapsCount::One,
+            Format::R8G8B8A8_UNORM,
+            queue.clone(),
+        )
+        .unwrap();
+        upload
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+        memory_stats.track(
+            AllocationPurpose::Texture,
+            size_of_val(atlas.pixels.as_slice()) as DeviceSize,
+        );
+
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Nearest,
+                min_filter: Filter::Nearest,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        TileLayer {
+            pipeline: create_pipeline(device, target),
+            atlas: ImageView::new_default(image).unwrap(),
+            atlas_grid: atlas.grid(),
+            sampler,
+            tiles: None,
+            descriptor_set: None,
+            tiles_size: 0,
+            revision: None,
+            view: vertex_shader::ty::TileView {
+                origin: [0.0; 2],
+                tile_extent: [0.0; 2],
+                map_size: [0; 2],
+                atlas_grid: atlas.grid(),
+                chunk_size: CHUNK_SIZE,
+            },
+            visible: Vec::new(),
+            chunk_ring: FrameRing::new(
+                device.clone(),
+                BufferUsage::vertex_buffer(),
+                AllocationPurpose::Instance,
+                frames_in_flight,
+                VISIBLE_CHUNKS,
+                memory_stats,
+            )
+            .unwrap(),
+        }
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: Arc<GraphicsPipeline>) {
+        self.pipeline = pipeline;
+    }
+
+    /// Takes over `tilemap`'s view and visible chunks, uploading its tiles if they
+    /// changed since the last call.
+    pub fn sync(
+        &mut self,
+        device: &Arc<Device>,
+        tilemap: &Tilemap,
+        memory_stats: &mut MemoryStats,
+    ) {
+        if self.revision != Some(tilemap.revision()) {
+            self.revision = Some(tilemap.revision());
+            memory_stats.untrack(AllocationPurpose::Storage, self.tiles_size);
+            self.tiles_size = size_of_val(tilemap.tiles()) as DeviceSize;
+            memory_stats.track(AllocationPurpose::Storage, self.tiles_size);
+            // A fresh buffer, as frames in flight may still read the old one.
+            let tiles = CpuAccessibleBuffer::from_iter(
+                device.clone(),
+                BufferUsage::storage_buffer(),
+                false,
+                tilemap.tiles().iter().copied(),
+            )
+            .unwrap();
+            let layout = self.pipeline.layout().set_layouts()[0].clone();
+            self.descriptor_set = (!layout.push_descriptor())
+                .then(|| PersistentDescriptorSet::new(layout, self.writes(&tiles)).unwrap());
+            self.tiles = Some(tiles);
+        }
+
+        self.view = vertex_shader::ty::TileView {
+            origin: tilemap.origin,
+            tile_extent: tilemap.tile_extent,
+            map_size: tilemap.size(),
+            atlas_grid: self.atlas_grid,
+            chunk_size: CHUNK_SIZE,
+        };
+        self.visible.clear();
+        self.visible
+            .extend(tilemap.visible_chunks().map(|chunk| TileChunk { chunk }));
+    }
+
+    /// Uploads this frame's visible chunks; `None` when there is nothing to draw.
+    pub fn upload<L, P>(
+        &mut self,
+        staging: &mut StagingRing,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        memory_stats: &mut MemoryStats,
+    ) -> Option<FrameChunk<TileChunk>> {
+        (self.tiles.is_some() && !self.visible.is_empty()).then(|| {
+            self.chunk_ring
+                .upload(&self.visible, staging, builder, memory_stats)
+        })
+    }
+
+    /// Draws the `chunks` returned by [`Self::upload`].
+    pub fn draw<L, P>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        viewport: &Viewport,
+        chunks: &FrameChunk<TileChunk>,
+    ) {
+        let layout = self.pipeline.layout();
+        builder
+            .set_viewport(0, [viewport.clone()])
+            .bind_pipeline_graphics(self.pipeline.clone());
+        match &self.descriptor_set {
+            Some(descriptor_set) => builder.bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                layout.clone(),
+                0,
+                descriptor_set.clone(),
+            ),
+            None => builder.push_descriptor_set(
+                PipelineBindPoint::Graphics,
+                layout.clone(),
+                0,
+                self.writes(self.tiles.as_ref().unwrap()),
+            ),
+        };
+        builder
+            .bind_vertex_buffers(0, chunks.clone())
+            .push_constants(self.pipeline.layout().clone(), 0, self.view)
+            .draw(6, chunks.len() as u32, 0, 0)
+            .unwrap();
+    }
+
+    fn writes(&self, tiles: &Arc<CpuAccessibleBuffer<[u32]>>) -> [WriteDescriptorSet; 2] {
+        [
+            WriteDescriptorSet::buffer(0, tiles.clone()),
+            WriteDescriptorSet::image_view_sampler(1, self.atlas.clone(), self.sampler.clone()),
+        ]
+    }
+}
+
+pub fn create_pipeline(device: &Arc<Device>, target: &RenderTarget) -> Arc<GraphicsPipeline> {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = fragment_shader::load(device.clone()).unwrap();
+
+    GraphicsPipeline::start()
+        .render_pass(target.scene())
+        .vertex_input_state(BuffersDefinition::new().instance::<TileChunk>())
+        .input_assembly_state(InputAssemblyState::new())
+        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+        .with_auto_layout(device.clone(), push_descriptor_layout(device))
+        .unwrap()
+}
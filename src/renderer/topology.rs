@@ -0,0 +1,79 @@
+use std::sync::Arc;
+use vulkano::pipeline::{graphics::input_assembly::PrimitiveTopology, GraphicsPipeline};
+
+/// How a [`Mesh`](super::Mesh)'s indices are assembled into primitives.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Topology {
+    #[default]
+    TriangleList,
+    TriangleStrip,
+    /// Drawn as a triangle list, as portability implementations such as MoltenVK may
+    /// not have fans; the indices are rearranged when the mesh is uploaded.
+    TriangleFan,
+    LineList,
+    LineStrip,
+    PointList,
+}
+
+impl Topology {
+    /// Parses `triangle-list`, `triangle-strip`, `triangle-fan`, `line-list`,
+    /// `line-strip` or `point-list`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "triangle-list" => Some(Topology::TriangleList),
+            "triangle-strip" => Some(Topology::TriangleStrip),
+            "triangle-fan" => Some(Topology::TriangleFan),
+            "line-list" => Some(Topology::LineList),
+            "line-strip" => Some(Topology::LineStrip),
+            "point-list" => Some(Topology::PointList),
+            _ => None,
+        }
+    }
+
+    /// What the input assembler is set to for meshes of this topology.
+    pub fn primitive_topology(self) -> PrimitiveTopology {
+        match self {
+            Topology::TriangleList | Topology::TriangleFan => PrimitiveTopology::TriangleList,
+            Topology::TriangleStrip => PrimitiveTopology::TriangleStrip,
+            Topology::LineList => PrimitiveTopology::LineList,
+            Topology::LineStrip => PrimitiveTopology::LineStrip,
+            Topology::PointList => PrimitiveTopology::PointList,
+        }
+    }
+}
+
+/// A pipeline per primitive topology the meshes are drawn with, alike otherwise.
+#[derive(Clone)]
+pub struct PipelineVariants {
+    /// The first is used for topologies without a variant.
+    variants: Vec<(PrimitiveTopology, Arc<GraphicsPipeline>)>,
+}
+
+impl PipelineVariants {
+    /// Creates a variant for each of `topologies`, which mustn't be empty.
+    pub fn new<E>(
+        topologies: &[PrimitiveTopology],
+        mut create: impl FnMut(PrimitiveTopology) -> Result<Arc<GraphicsPipeline>, E>,
+    ) -> Result<Self, E> {
+        let variants = topologies
+            .iter()
+            .map(|&topology| Ok((topology, create(topology)?)))
+            .collect::<Result<Vec<_>, E>>()?;
+        Ok(PipelineVariants { variants })
+    }
+
+    /// Draws every mesh with `pipeline`, a triangle list one, whatever its topology.
+    pub fn single(pipeline: Arc<GraphicsPipeline>) -> Self {
+        PipelineVariants {
+            variants: vec![(PrimitiveTopology::TriangleList, pipeline)],
+        }
+    }
+
+    /// The variant for `topology`, or the first if it has none.
+    pub fn get(&self, topology: PrimitiveTopology) -> &Arc<GraphicsPipeline> {
+        self.variants
+            .iter()
+            .find(|(variant, _)| *variant == topology)
+            .map_or(&self.variants[0].1, |(_, pipeline)| pipeline)
+    }
+}
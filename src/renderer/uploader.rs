@@ -0,0 +1,292 @@
+use super::{
+    mesh::{IndexBuffer, Indices, Mesh, MeshBuffer},
+    occlusion::mesh_extent,
+    FrameFence, VertexFormat,
+};
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+use tracing::info_span;
+use vulkano::{
+    buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer, DeviceLocalBuffer},
+    command_buffer::{AutoCommandBufferBuilder, BufferCopy, CommandBufferUsage, CopyBufferInfo},
+    device::{
+        physical::{PhysicalDevice, QueueFamily},
+        Device, Queue,
+    },
+    sync::{self, GpuFuture},
+    DeviceSize,
+};
+
+/// Bytes copied per command buffer; two chunks of staging memory are in flight.
+const CHUNK_BYTES: usize = 1 << 20;
+
+type LoadMesh = Box<dyn FnOnce() -> Result<Mesh, String> + Send>;
+
+struct Job {
+    name: String,
+    load: LoadMesh,
+}
+
+/// A mesh in device memory, which the GPU may still be copying into.
+pub struct UploadedMesh {
+    pub buffer: MeshBuffer,
+    pub extent: [f32; 2],
+    pub size: DeviceSize,
+    /// Signals a semaphore once the copies are done; the first submission drawing
+    /// the mesh has to wait for it.
+    pub ready: Box<dyn GpuFuture + Send + Sync>,
+}
+
+pub struct FinishedUpload {
+    pub name: String,
+    pub result: Result<UploadedMesh, String>,
+}
+
+/// The queue family the upload thread gets a queue of: a transfer-only one if the
+/// device has it, so copies run alongside rendering.
+pub fn transfer_family(physical_device: PhysicalDevice) -> Option<QueueFamily> {
+    physical_device.queue_families().find(|family| {
+        family.explicitly_supports_transfers()
+            && !family.supports_graphics()
+            && !family.supports_compute()
+    })
+}
+
+/// Loads meshes on a worker thread: it runs the load, then streams the vertices and
+/// indices through staging buffers into device-local buffers with command buffers of its
+/// own, a chunk at a time. The event loop only picks up finished meshes.
+pub struct Uploader {
+    jobs: Option<Sender<Job>>,
+    finished: Receiver<FinishedUpload>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Uploader {
+    /// Copies on `queue`, into buffers that `graphics_family` reads as well, with the
+    /// vertices converted to `vertex_format`.
+    pub fn new(
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        graphics_family: u32,
+        vertex_format: VertexFormat,
+    ) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (finished_sender, finished) = mpsc::channel();
+        let device = device.clone();
+        let queue = queue.clone();
+        let thread = thread::Builder::new()
+            .name("uploader".to_owned())
+            .spawn(move || {
+                for job in job_receiver {
+                    let _span = info_span!("upload_mesh", name = %job.name).entered();
+                    let result = (job.load)().and_then(|mesh| {
+                        upload(&device, &queue, graphics_family, &mesh, vertex_format)
+                    });
+                    let finished = FinishedUpload {
+                        name: job.name,
+                        result,
+                    };
+                    if finished_sender.send(finished).is_err() {
+                        break;
+                    }
+                }
+            })
+            .unwrap();
+        Uploader {
+            jobs: Some(jobs),
+            finished,
+            thread: Some(thread),
+        }
+    }
+
+    /// Queues `load` to run on the upload thread; `name` identifies it in
+    /// [`Self::finished`].
+    pub fn load(&self, name: String, load: impl FnOnce() -> Result<Mesh, String> + Send + 'static) {
+        let job = Job {
+            name,
+            load: Box::new(load),
+        };
+        // Fails only once the thread panicked.
+        let _ = self.jobs.as_ref().unwrap().send(job);
+    }
+
+    /// Meshes finished since the last call, in the order they were queued.
+    pub fn finished(&self) -> impl Iterator<Item = FinishedUpload> + '_ {
+        self.finished.try_iter()
+    }
+}
+
+impl Drop for Uploader {
+    fn drop(&mut self) {
+        // Ends the thread once it's done with the queued loads.
+        self.jobs = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Copies each stream of `mesh` in `vertex_format` and its indices into
+/// device-local buffers.
+fn upload(
+    device: &Arc<Device>,
+    queue: &Arc<Queue>,
+    graphics_family: u32,
+    mesh: &Mesh,
+    vertex_format: VertexFormat,
+) -> Result<UploadedMesh, String> {
+    mesh.validate()?;
+    let physical_device = device.physical_device();
+    let mut families = vec![queue.family()];
+    if graphics_family != queue.family().id() {
+        families.extend(physical_device.queue_family_by_id(graphics_family));
+    }
+    let streams = vertex_format.streams(&mesh.vertices);
+    let vertex_buffers = streams
+        .iter()
+        .map(|stream| {
+            DeviceLocalBuffer::<[u8]>::array(
+                device.clone(),
+                stream.len() as DeviceSize,
+                BufferUsage {
+                    transfer_dst: true,
+                    vertex_buffer: true,
+                    ..BufferUsage::none()
+                },
+                families.iter().copied(),
+            )
+            .map_err(|e| e.to_string())
+            .map(|buffer| buffer as Arc<dyn BufferAccess>)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let index_usage = BufferUsage {
+        transfer_dst: true,
+        index_buffer: true,
+        ..BufferUsage::none()
+    };
+    let indices = mesh.assembled_indices();
+    let (index_buffer, index_destination): (_, Arc<dyn BufferAccess>) = match indices.as_ref() {
+        Indices::U16(indices) => {
+            let buffer = DeviceLocalBuffer::<[u16]>::array(
+                device.clone(),
+                indices.len() as DeviceSize,
+                index_usage,
+                families.iter().copied(),
+            )
+            .map_err(|e| e.to_string())?;
+            (IndexBuffer::U16(buffer.clone()), buffer)
+        }
+        Indices::U32(indices) => {
+            let buffer = DeviceLocalBuffer::<[u32]>::array(
+                device.clone(),
+                indices.len() as DeviceSize,
+                index_usage,
+                families.iter().copied(),
+            )
+            .map_err(|e| e.to_string())?;
+            (IndexBuffer::U32(buffer.clone()), buffer)
+        }
+    };
+    // Each stream into its vertex buffer, then the indices.
+    let copies: Vec<(&[u8], &Arc<dyn BufferAccess>)> = streams
+        .iter()
+        .map(Vec::as_slice)
+        .zip(&vertex_buffers)
+        .chain([(indices.bytes(), &index_destination)])
+        .collect();
+
+    let staging_len = copies
+        .iter()
+        .map(|(bytes, _)| bytes.len())
+        .max()
+        .unwrap()
+        .min(CHUNK_BYTES);
+    let staging = [(); 2].map(|_| {
+        // Safe: each chunk is written before it's copied, and only its part is.
+        unsafe {
+            CpuAccessibleBuffer::<[u8]>::uninitialized_array(
+                device.clone(),
+                staging_len as DeviceSize,
+                BufferUsage::transfer_src(),
+                false,
+            )
+        }
+    });
+    let staging = match staging {
+        [Ok(first), Ok(second)] => [first, second],
+        [Err(e), _] | [_, Err(e)] => return Err(e.to_string()),
+    };
+
+    // Each chunk chains onto the previous one, so the copies run in order and the
+    // final future carries the access to every buffer.
+    let mut previous = sync::now(device.clone()).boxed_send_sync();
+    let mut in_flight: [Option<Arc<FrameFence>>; 2] = [None, None];
+    let chunks = copies.iter().flat_map(|&(bytes, buffer)| {
+        bytes
+            .chunks(CHUNK_BYTES)
+            .enumerate()
+            .map(move |(index, chunk)| (buffer, index * CHUNK_BYTES, chunk))
+    });
+    for (index, (buffer, offset, chunk)) in chunks.enumerate() {
+        let slot = index % 2;
+        if let Some(fence) = in_flight[slot].take() {
+            fence.wait(None).map_err(|e| e.to_string())?;
+        }
+        staging[slot].write().map_err(|e| e.to_string())?[..chunk.len()].copy_from_slice(chunk);
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            device.clone(),
+            queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .map_err(|e| e.to_string())?;
+        builder
+            .copy_buffer(CopyBufferInfo {
+                regions: [BufferCopy {
+                    src_offset: 0,
+                    dst_offset: offset as DeviceSize,
+                    size: chunk.len() as DeviceSize,
+                    ..Default::default()
+                }]
+                .into(),
+                ..CopyBufferInfo::buffers(staging[slot].clone(), buffer.clone())
+            })
+            .map_err(|e| e.to_string())?;
+        let command_buffer = builder.build().map_err(|e| e.to_string())?;
+
+        let fence = Arc::new(
+            previous
+                .then_execute(queue.clone(), command_buffer)
+                .map_err(|e| e.to_string())?
+                .boxed_send_sync()
+                .then_signal_fence_and_flush()
+                .map_err(|e| e.to_string())?,
+        );
+        previous = fence.clone().boxed_send_sync();
+        in_flight[slot] = Some(fence);
+    }
+
+    // Covers every copy submitted to the queue before it.
+    let ready = previous
+        .then_signal_semaphore_and_flush()
+        .map_err(|e| e.to_string())?
+        .boxed_send_sync();
+    let buffer = MeshBuffer::new(
+        vertex_buffers,
+        index_buffer,
+        mesh.vertices.len() as u32,
+        mesh.topology,
+        mesh.assembled_lods(),
+    );
+    Ok(UploadedMesh {
+        extent: mesh_extent(&mesh.vertices),
+        size: buffer.size(),
+        buffer,
+        ready,
+    })
+}
@@ -0,0 +1,259 @@
+use super::{
+    demo::{Demo, DemoFrame, DemoInput},
+    nbody::hash,
+    RenderTarget,
+};
+use crate::memory::{AllocationPurpose, MemoryStats};
+use bytemuck::{Pod, Zeroable};
+use std::{mem::size_of, sync::Arc};
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, SecondaryAutoCommandBuffer,
+    },
+    device::Device,
+    impl_vertex,
+    pipeline::{
+        graphics::{
+            color_blend::{AttachmentBlend, ColorBlendState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            vertex_input::BuffersDefinition,
+            viewport::ViewportState,
+        },
+        GraphicsPipeline, Pipeline,
+    },
+    DeviceSize,
+};
+
+/// Blades scattered over the ground.
+const BLADE_COUNT: u32 = 65_536;
+
+/// Segments each blade bends in; a strip of two vertices per segment and the tip.
+/// Matches `SEGMENTS` in the shader.
+const SEGMENTS: u32 = 4;
+
+/// Distance from the camera of the nearest and the farthest blades, in the units
+/// the ground is laid out in; the camera looks along the ground from a height of
+/// 0.3, so the nearest row is at the bottom of the window. `FAR` matches the
+/// shader's.
+const NEAR: f32 = 0.3;
+const FAR: f32 = 8.0;
+
+/// How many times wider than tall the windows are that the field still fills
+/// sideways; it's scattered wider the farther away it is, as the view widens.
+const MAX_ASPECT: f32 = 2.5;
+
+/// How far the wind bends the tips at most, as a fraction of the blade's height,
+/// with the cursor at either edge of the window.
+const MAX_WIND: f32 = 0.6;
+
+/// One blade's instance data: where it grows on the ground and the seed its height,
+/// color and flutter are drawn from.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+struct Blade {
+    /// Across, then away from the camera.
+    blade_root: [f32; 2],
+    seed: u32,
+}
+impl_vertex!(Blade, blade_root, seed);
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 460
+
+        #define SEGMENTS 4
+        #define CAMERA_HEIGHT 0.3
+        #define FADE_START 4.0
+        #define FAR 8.0
+
+        layout(location = 0) in vec2 blade_root;
+        layout(location = 1) in uint seed;
+
+        layout(location = 0) out vec4 out_color;
+
+        layout(push_constant) uniform VegetationParams {
+            float time;
+            // The viewport's width over its height.
+            float aspect;
+            // Bends the tips sideways, right for positive values.
+            float wind;
+        } params;
+
+        // The same hash as the renderer's, in [0, 1].
+        float random(uint x) {
+            x ^= x >> 16;
+            x *= 0x7feb352du;
+            x ^= x >> 15;
+            x *= 0x846ca68bu;
+            x ^= x >> 16;
+            return float(x)/4294967295.0;
+        }
+
+        void main() {
+            float height = mix(0.05, 0.12, random(seed));
+            float width = mix(0.006, 0.01, random(seed+1u));
+            float phase = 6.2831853*random(seed+2u);
+            float shade = random(seed+3u);
+
+            // Two vertices a segment up the blade, narrowing to the tip.
+            float t = float(gl_VertexIndex/2)/float(SEGMENTS);
+            float side = float(gl_VertexIndex%2)*2.0-1.0;
+            float x = blade_root.x+side*width*(1.0-t);
+            float z = blade_root.y;
+
+            // The wind comes in slow gusts rolling across the field, and each blade
+            // flutters in it at its own phase. The tip bends the most.
+            float gust = 0.6+0.4*sin(params.time*0.8-z*0.9+x*0.3);
+            float flutter = 0.15*sin(params.time*3.0+phase);
+            float bend = (params.wind*gust+flutter)*height*t*t;
+            // Bending keeps the blade about as long, so it sinks as it leans.
+            float y = height*t-0.5*bend*bend/height;
+            x += bend;
+
+            out_color = mix(vec4(0.04, 0.16, 0.03, 1.0), vec4(0.4+0.2*shade, 0.7, 0.15+0.1*shade, 1.0), t);
+            // Fades into the background towards the far edge.
+            out_color.a = 1.0-smoothstep(FADE_START, FAR, z);
+            // A pinhole camera at CAMERA_HEIGHT looking along the ground, y down.
+            gl_Position = vec4(x/(z*params.aspect), (CAMERA_HEIGHT-y)/z, 0.0, 1.0);
+        }
+        "
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 460
+
+        layout(location = 0) in vec4 in_color;
+
+        layout(location = 0) out vec4 f_color;
+
+        void main() {
+            f_color = in_color;
+        }
+        "
+    }
+}
+
+/// A field of grass for [`DemoKind::Vegetation`](super::DemoKind::Vegetation): tens of
+/// thousands of blades, each an instance with nothing but its root and a random
+/// seed, built into a bending strip by the vertex shader, which also sways it in the
+/// wind. Moving the cursor across the window turns the wind. The blades are sorted
+/// far to near once, so they blend in order as they fade out with distance.
+pub struct VegetationDemo {
+    pipeline: Arc<GraphicsPipeline>,
+    blades: Arc<CpuAccessibleBuffer<[Blade]>>,
+    wind: f32,
+}
+
+impl VegetationDemo {
+    pub fn new(
+        device: &Arc<Device>,
+        target: &RenderTarget,
+        memory_stats: &mut MemoryStats,
+    ) -> Self {
+        let mut blades: Vec<Blade> = (0..BLADE_COUNT).map(scattered_blade).collect();
+        blades.sort_by(|a, b| b.blade_root[1].total_cmp(&a.blade_root[1]));
+        let blades = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::vertex_buffer(),
+            false,
+            blades,
+        )
+        .unwrap();
+        memory_stats.track(AllocationPurpose::Instance, blades_size());
+        VegetationDemo {
+            pipeline: create_pipeline(device, target),
+            blades,
+            wind: 0.0,
+        }
+    }
+
+    fn draw<L, P>(&self, builder: &mut AutoCommandBufferBuilder<L, P>, frame: &DemoFrame) {
+        let [width, height] = frame.viewport.dimensions;
+        builder
+            .set_viewport(0, [frame.viewport.clone()])
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .bind_vertex_buffers(0, self.blades.clone())
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                vertex_shader::ty::VegetationParams {
+                    time: frame.time,
+                    aspect: width / height.max(1.0),
+                    wind: self.wind,
+                },
+            )
+            .draw(2 * SEGMENTS + 1, BLADE_COUNT, 0, 0)
+            .unwrap();
+    }
+}
+
+impl Demo for VegetationDemo {
+    fn recreate_pipelines(&mut self, device: &Arc<Device>, target: &RenderTarget) {
+        self.pipeline = create_pipeline(device, target);
+    }
+
+    /// The wind blows towards the side of the window the cursor is on, harder the
+    /// nearer it is to the edge.
+    fn handle_input(&mut self, input: &DemoInput, _dimensions: [f32; 2]) {
+        self.wind = (2.0 * input.cursor[0] - 1.0) * MAX_WIND;
+    }
+
+    fn draw_inline(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &DemoFrame,
+    ) {
+        self.draw(builder, frame);
+    }
+
+    fn draw_secondary(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &DemoFrame,
+    ) {
+        self.draw(builder, frame);
+    }
+
+    fn untrack_memory(&self, memory_stats: &mut MemoryStats) {
+        memory_stats.untrack(AllocationPurpose::Instance, blades_size());
+    }
+}
+
+fn blades_size() -> DeviceSize {
+    BLADE_COUNT as DeviceSize * size_of::<Blade>() as DeviceSize
+}
+
+/// Blade `i`, evenly spread over the ground in view: the view widens with distance,
+/// so distances are drawn by area.
+fn scattered_blade(i: u32) -> Blade {
+    let z = (NEAR * NEAR + hash(2 * i) * (FAR * FAR - NEAR * NEAR)).sqrt();
+    let x = (2.0 * hash(2 * i + 1) - 1.0) * z * MAX_ASPECT;
+    Blade {
+        blade_root: [x, z],
+        // Four apart, as the shader hashes `seed` to `seed + 3`.
+        seed: 4 * i,
+    }
+}
+
+fn create_pipeline(device: &Arc<Device>, target: &RenderTarget) -> Arc<GraphicsPipeline> {
+    let loaded_vertex_shader = vertex_shader::load(device.clone()).unwrap();
+    let loaded_fragment_shader = fragment_shader::load(device.clone()).unwrap();
+
+    GraphicsPipeline::start()
+        .render_pass(target.scene())
+        .vertex_input_state(BuffersDefinition::new().instance::<Blade>())
+        .input_assembly_state(InputAssemblyState::new().topology(PrimitiveTopology::TriangleStrip))
+        .vertex_shader(loaded_vertex_shader.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(loaded_fragment_shader.entry_point("main").unwrap(), ())
+        .color_blend_state(ColorBlendState::new(1).blend(AttachmentBlend::alpha()))
+        .build(device.clone())
+        .unwrap()
+}
@@ -0,0 +1,250 @@
+use super::{
+    mesh::{IndexBuffer, Indices, Mesh, MeshBuffer},
+    Vertex,
+};
+use bytemuck::{Pod, Zeroable};
+use half::f16;
+use std::{marker::PhantomData, sync::Arc};
+use vulkano::{
+    buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer},
+    device::Device,
+    format::Format,
+    memory::DeviceMemoryAllocationError,
+    pipeline::graphics::vertex_input::{
+        BuffersDefinition, IncompatibleVertexDefinitionError, Vertex as VertexType,
+        VertexDefinition, VertexInputAttributeDescription, VertexInputBindingDescription,
+        VertexInputRate, VertexInputState,
+    },
+    shader::ShaderInterface,
+};
+
+/// The members of [`Vertex`], in order.
+const MEMBERS: [&str; 2] = ["position", "color"];
+
+/// How the vertices of every mesh of a renderer are stored. Meshes are built as
+/// [`Vertex`]es either way and converted as they're uploaded; the shaders read the
+/// same `vec2` position and `vec4` color from all of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VertexFormat {
+    pub encoding: VertexEncoding,
+    pub layout: VertexLayout,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VertexEncoding {
+    /// [`Vertex`] as it is, in 32-bit floats.
+    #[default]
+    Full,
+    /// [`PackedVertex`], half the size, for large meshes where vertex fetch
+    /// bandwidth matters more than precision.
+    Quantized,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VertexLayout {
+    /// Each vertex's position and color next to each other, in one buffer.
+    #[default]
+    Interleaved,
+    /// Every position in one buffer and every color in another, bound at bindings 0
+    /// and 1.
+    Planar,
+}
+
+impl VertexEncoding {
+    /// Format and size of each of [`MEMBERS`].
+    fn members(self) -> [(Format, u32); 2] {
+        match self {
+            VertexEncoding::Full => [
+                (Format::R32G32_SFLOAT, 8),
+                (Format::R32G32B32A32_SFLOAT, 16),
+            ],
+            VertexEncoding::Quantized => [(Format::R16G16_SFLOAT, 4), (Format::R8G8B8A8_UNORM, 4)],
+        }
+    }
+}
+
+impl VertexFormat {
+    /// Host-visible buffers of `mesh`, with a vertex buffer per stream in this format.
+    pub fn create_buffer(
+        self,
+        device: &Arc<Device>,
+        mesh: &Mesh,
+    ) -> Result<MeshBuffer, DeviceMemoryAllocationError> {
+        let usage = BufferUsage::all();
+        let streams = self
+            .streams(&mesh.vertices)
+            .into_iter()
+            .map(|bytes| {
+                CpuAccessibleBuffer::from_iter(device.clone(), usage, false, bytes)
+                    .map(|buffer| buffer as Arc<dyn BufferAccess>)
+            })
+            .collect::<Result<_, _>>()?;
+        let indices = match mesh.assembled_indices().as_ref() {
+            Indices::U16(indices) => IndexBuffer::U16(CpuAccessibleBuffer::from_iter(
+                device.clone(),
+                usage,
+                false,
+                indices.clone(),
+            )?),
+            Indices::U32(indices) => IndexBuffer::U32(CpuAccessibleBuffer::from_iter(
+                device.clone(),
+                usage,
+                false,
+                indices.clone(),
+            )?),
+        };
+        Ok(MeshBuffer::new(
+            streams,
+            indices,
+            mesh.vertices.len() as u32,
+            mesh.topology,
+            mesh.assembled_lods(),
+        ))
+    }
+
+    /// The contents of each buffer `vertices` are stored in, in binding order.
+    pub fn streams(self, vertices: &[Vertex]) -> Vec<Vec<u8>> {
+        match self.encoding {
+            VertexEncoding::Full => {
+                self.split(vertices, |vertex| vertex.position, |vertex| vertex.color)
+            }
+            VertexEncoding::Quantized => {
+                let packed: Vec<_> = vertices.iter().map(PackedVertex::pack).collect();
+                self.split(&packed, |vertex| vertex.position, |vertex| vertex.color)
+            }
+        }
+    }
+
+    fn split<T: Pod, P: Pod, C: Pod>(
+        self,
+        vertices: &[T],
+        position: impl Fn(&T) -> P,
+        color: impl Fn(&T) -> C,
+    ) -> Vec<Vec<u8>> {
+        match self.layout {
+            VertexLayout::Interleaved => vec![bytemuck::cast_slice(vertices).to_vec()],
+            VertexLayout::Planar => {
+                let positions: Vec<_> = vertices.iter().map(position).collect();
+                let colors: Vec<_> = vertices.iter().map(color).collect();
+                vec![
+                    bytemuck::cast_slice(&positions).to_vec(),
+                    bytemuck::cast_slice(&colors).to_vec(),
+                ]
+            }
+        }
+    }
+
+    /// Stride of each buffer a mesh is stored in, in binding order.
+    fn strides(self) -> Vec<u32> {
+        let sizes = self.encoding.members().map(|(_, size)| size);
+        match self.layout {
+            VertexLayout::Interleaved => vec![sizes.iter().sum()],
+            VertexLayout::Planar => sizes.to_vec(),
+        }
+    }
+
+    /// Where the shader reads `MEMBERS[index]` from.
+    fn attribute(self, index: usize) -> VertexInputAttributeDescription {
+        let members = self.encoding.members();
+        let (format, _) = members[index];
+        match self.layout {
+            VertexLayout::Interleaved => VertexInputAttributeDescription {
+                binding: 0,
+                format,
+                offset: members[..index].iter().map(|(_, size)| size).sum(),
+            },
+            VertexLayout::Planar => VertexInputAttributeDescription {
+                binding: index as u32,
+                format,
+                offset: 0,
+            },
+        }
+    }
+}
+
+/// A [`Vertex`] in 12 bytes instead of 24: the position as half floats
+/// (`R16G16_SFLOAT`) and the color as normalized bytes (`R8G8B8A8_UNORM`).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct PackedVertex {
+    /// Bits of two `f16`s.
+    pub position: [u16; 2],
+    pub color: [u8; 4],
+}
+
+impl PackedVertex {
+    /// Rounds the position to the nearest half float and clamps the color to
+    /// `[0, 1]` in steps of 1/255.
+    pub fn pack(vertex: &Vertex) -> Self {
+        PackedVertex {
+            position: vertex.position.map(|x| f16::from_f32(x).to_bits()),
+            color: vertex
+                .color
+                .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8),
+        }
+    }
+}
+
+/// The vertex buffers of a pipeline drawing meshes: the mesh's streams in
+/// `format` from binding 0, and instances of `I` after them.
+pub struct MeshVertices<I> {
+    format: VertexFormat,
+    instance: PhantomData<fn() -> I>,
+}
+
+impl<I> MeshVertices<I> {
+    pub fn new(format: VertexFormat) -> Self {
+        MeshVertices {
+            format,
+            instance: PhantomData,
+        }
+    }
+}
+
+unsafe impl<I: VertexType> VertexDefinition for MeshVertices<I> {
+    fn definition(
+        &self,
+        interface: &ShaderInterface,
+    ) -> Result<VertexInputState, IncompatibleVertexDefinitionError> {
+        let mut state = BuffersDefinition::new()
+            .vertex::<Vertex>()
+            .instance::<I>()
+            .definition(interface)?;
+        if self.format == VertexFormat::default() {
+            return Ok(state);
+        }
+        // Checked against `Vertex` above; only where the mesh's members are read from
+        // differs. `impl_vertex!` can't describe that, as it reads every member in the
+        // format the shader declares, from one buffer.
+        let strides = self.format.strides();
+        let instance_binding = strides.len() as u32;
+        let instance = state.bindings.remove(&1).unwrap();
+        for attribute in state.attributes.values_mut() {
+            if attribute.binding == 1 {
+                attribute.binding = instance_binding;
+            }
+        }
+        state.bindings.insert(instance_binding, instance);
+        for (binding, stride) in strides.into_iter().enumerate() {
+            state.bindings.insert(
+                binding as u32,
+                VertexInputBindingDescription {
+                    stride,
+                    input_rate: VertexInputRate::Vertex,
+                },
+            );
+        }
+        for element in interface.elements() {
+            let member = element
+                .name
+                .as_deref()
+                .and_then(|name| MEMBERS.iter().position(|&member| member == name));
+            if let Some(index) = member {
+                state
+                    .attributes
+                    .insert(element.location, self.format.attribute(index));
+            }
+        }
+        Ok(state)
+    }
+}
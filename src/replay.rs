@@ -0,0 +1,114 @@
+use crate::simulation::Input;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+use tracing::warn;
+use winit::event::{ElementState, MouseButton, VirtualKeyCode};
+
+/// Something that happened to the window, stamped with the simulation tick it
+/// takes effect on.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    pub tick: u64,
+    pub kind: EventKind,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum EventKind {
+    /// The sampled input changed: cursor, scroll or pinch.
+    Input(Input),
+    Key {
+        key: VirtualKeyCode,
+        state: ElementState,
+    },
+    /// A mouse button was pressed at `cursor`, normalized to `[0, 1]` across the
+    /// window.
+    Click {
+        button: MouseButton,
+        cursor: [f32; 2],
+    },
+    /// A Ctrl+click at `cursor`, selecting or deselecting an instance.
+    Pick { cursor: [f32; 2] },
+    /// Physical size of the window.
+    Resized { width: u32, height: u32 },
+    /// The window was closed, which ends the replay.
+    Exit,
+}
+
+/// Writes events as JSON lines for `--record-input`.
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+    last_input: Option<Input>,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(InputRecorder {
+            writer: BufWriter::new(file),
+            last_input: None,
+        })
+    }
+
+    /// Records `input` if it differs from the last one recorded. Call right before the
+    /// simulation consumes it.
+    pub fn record_input(&mut self, tick: u64, input: &Input) {
+        if self.last_input.as_ref() != Some(input) {
+            self.last_input = Some(*input);
+            self.record(tick, EventKind::Input(*input));
+        }
+    }
+
+    pub fn record(&mut self, tick: u64, kind: EventKind) {
+        let event = RecordedEvent { tick, kind };
+        let result = serde_json::to_writer(&mut self.writer, &event)
+            .map_err(|e| e.to_string())
+            .and_then(|()| writeln!(self.writer).map_err(|e| e.to_string()));
+        if let Err(message) = result {
+            warn!(%message, "failed to record input event");
+        }
+    }
+
+    /// Records the end of the session and flushes the file.
+    pub fn finish(&mut self, tick: u64) {
+        self.record(tick, EventKind::Exit);
+        if let Err(e) = self.writer.flush() {
+            warn!(error = %e, "failed to write the input recording");
+        }
+    }
+}
+
+/// Hands out the events of a `--record-input` file as the replay reaches their tick.
+pub struct InputReplay {
+    events: VecDeque<RecordedEvent>,
+}
+
+impl InputReplay {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let events = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line)
+                    .map_err(|e| format!("{}:{}: {}", path.display(), index + 1, e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(InputReplay { events })
+    }
+
+    /// The next event due at or before `tick`, in recording order.
+    pub fn next_due(&mut self, tick: u64) -> Option<EventKind> {
+        if self.events.front()?.tick <= tick {
+            self.events.pop_front().map(|event| event.kind)
+        } else {
+            None
+        }
+    }
+}
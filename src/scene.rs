@@ -0,0 +1,784 @@
+use crate::{
+    boids::{Boid, Flock},
+    particles::Emitter,
+    renderer::{DrawList, InstanceData, Instances, MaterialId, MeshId, TextureId},
+};
+#[cfg(feature = "physics")]
+use crate::{
+    physics::{Body, Physics, BODY_HALF_EXTENT},
+    simulation::TICK_RATE,
+};
+use hecs::{Entity, World};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    f32::consts::{PI, TAU},
+};
+
+mod file;
+
+pub use file::{Camera, SceneFile};
+
+/// Wobbling copies of the mesh in the default scene.
+pub const DEFAULT_INSTANCE_COUNT: u32 = 1000;
+
+/// [`DEFAULT_INSTANCE_COUNT`] on CPU rasterizers, which would run it at a crawl.
+pub const SOFTWARE_INSTANCE_COUNT: u32 = 200;
+
+/// Children spawned by [`Scene::spawn_cluster`].
+pub const CLUSTER_SIZE: u32 = 6;
+
+/// Deeper hierarchies are assumed to be parent cycles and cut off there.
+const MAX_DEPTH: usize = 64;
+
+/// How far an instance reaches from its origin on screen, in clip space units, down
+/// to which it's drawn in its mesh's full detail; every halving drops a level.
+const LOD0_EXTENT: f32 = 0.5;
+
+/// The least detailed level instances are packed with; meshes with fewer levels
+/// draw their last.
+const MAX_LOD: u32 = 15;
+
+/// Placement relative to the [`Parent`], or to clip space for root entities.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Transform {
+    pub translation: [f32; 2],
+    /// Counter-clockwise, in radians.
+    pub rotation: f32,
+    pub scale: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: [0.0, 0.0],
+            rotation: 0.0,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Transform {
+    /// Blends from `self` to `other`, turning the shorter way round.
+    fn lerp(&self, other: &Transform, alpha: f32) -> Transform {
+        let mix = |a: f32, b: f32| a + (b - a) * alpha;
+        let mut turn = (other.rotation - self.rotation).rem_euclid(TAU);
+        if turn > PI {
+            turn -= TAU;
+        }
+        Transform {
+            translation: [
+                mix(self.translation[0], other.translation[0]),
+                mix(self.translation[1], other.translation[1]),
+            ],
+            rotation: self.rotation + turn * alpha,
+            scale: mix(self.scale, other.scale),
+        }
+    }
+}
+
+/// The [`Transform`] as of the tick before the latest, which
+/// [`Scene::pack_instances`] blends from. Entities get it on their first tick.
+#[derive(Clone, Copy, Debug)]
+struct PreviousTransform(Transform);
+
+/// Multiplies the mesh's vertex colors. Only entities with a color are drawn; the
+/// others are group nodes. Drawables use the main mesh and material unless they have
+/// a [`MeshId`] or [`MaterialId`]; materials sampling textures read their
+/// [`TextureId`], white without one.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Color(pub [f32; 4]);
+
+impl Color {
+    pub const WHITE: Color = Color([1.0, 1.0, 1.0, 1.0]);
+}
+
+/// Clip space units per second.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Velocity(pub [f32; 2]);
+
+/// Radians per second, counter-clockwise.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct AngularVelocity(pub f32);
+
+/// Offsets of the wobble animation, which advances with the frame time.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Wobble {
+    pub phase: [f32; 2],
+}
+
+/// Cycles the entity's [`Color`] around the color wheel.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Rainbow {
+    /// Position on the color wheel, in `[0, 1)`.
+    pub hue: f32,
+    /// Turns around the color wheel per second.
+    pub speed: f32,
+}
+
+/// Attaches an entity to another, so it moves, turns, scales and hides with it.
+#[derive(Clone, Copy, Debug)]
+pub struct Parent(pub Entity);
+
+/// Hides an entity and everything attached to it.
+#[derive(Clone, Copy, Debug)]
+pub struct Hidden;
+
+/// Marks an entity picked with [`Scene::toggle_selection`], which the renderer
+/// outlines.
+#[derive(Clone, Copy, Debug)]
+pub struct Selected;
+
+/// The normal map an entity's material bends its shading with, if the material
+/// is lit; see [`Renderer::lit_shader`](crate::renderer::Renderer::lit_shader).
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct NormalMap(pub TextureId);
+
+/// A 2D affine transform: `basis` columns, then `translation`.
+#[derive(Clone, Copy, Debug)]
+struct Affine {
+    basis: [[f32; 2]; 2],
+    translation: [f32; 2],
+}
+
+impl Affine {
+    fn from_transform(transform: &Transform) -> Self {
+        let (sin, cos) = transform.rotation.sin_cos();
+        let scale = transform.scale;
+        Affine {
+            basis: [[cos * scale, sin * scale], [-sin * scale, cos * scale]],
+            translation: transform.translation,
+        }
+    }
+
+    fn apply(&self, point: [f32; 2]) -> [f32; 2] {
+        [
+            self.basis[0][0] * point[0] + self.basis[1][0] * point[1] + self.translation[0],
+            self.basis[0][1] * point[0] + self.basis[1][1] * point[1] + self.translation[1],
+        ]
+    }
+
+    /// `self` applied after `local`.
+    fn then(&self, local: &Affine) -> Affine {
+        let rotate = |v: [f32; 2]| {
+            [
+                self.basis[0][0] * v[0] + self.basis[1][0] * v[1],
+                self.basis[0][1] * v[0] + self.basis[1][1] * v[1],
+            ]
+        };
+        Affine {
+            basis: [rotate(local.basis[0]), rotate(local.basis[1])],
+            translation: self.apply(local.translation),
+        }
+    }
+}
+
+/// A node's place in the hierarchy, gathered once per frame.
+struct Node {
+    local: Affine,
+    parent: Option<Entity>,
+    hidden: bool,
+}
+
+/// Instance data packed by [`Scene::pack_instances`].
+pub struct PackedInstances<'a> {
+    pub meshes: Instances<'a>,
+    /// The batches `meshes` are drawn in.
+    pub draw_list: &'a DrawList,
+    /// Drawn after the meshes, with additive blending.
+    pub particles: Instances<'a>,
+    /// Indices into `meshes` of the [`Selected`] entities.
+    pub selected: &'a [u32],
+}
+
+/// The entities drawn as instances of the current mesh, as a tree of [`Parent`]
+/// links. Anything with a [`Transform`] and a [`Color`] is drawn; demos add their
+/// own components and systems for per-object logic.
+pub struct Scene {
+    pub world: World,
+    instances: Vec<InstanceData>,
+    /// The [`Color`] of each of `instances`.
+    instance_colors: Vec<[f32; 4]>,
+    draw_list: DrawList,
+    particle_instances: Vec<InstanceData>,
+    particle_colors: Vec<[f32; 4]>,
+    selected_instances: Vec<u32>,
+    /// Packs every instance without its [`NormalMap`], to compare.
+    flat_shading: bool,
+    /// Set by [`Self::set_view_extent`].
+    view_extent: f32,
+    /// Set by [`Self::enable_physics`].
+    #[cfg(feature = "physics")]
+    physics: Option<Physics>,
+    /// Set by [`Self::enable_boids`].
+    flock: Option<Flock>,
+}
+
+impl Scene {
+    /// The default scene: `instance_count` wobbling copies of the mesh.
+    pub fn new(instance_count: u32) -> Self {
+        let mut world = World::new();
+        for i in 0..instance_count {
+            world.spawn((
+                Transform::default(),
+                Color::WHITE,
+                Velocity::default(),
+                Wobble {
+                    phase: [i as f32, 2.0 * i as f32],
+                },
+            ));
+        }
+        Scene {
+            world,
+            instances: Vec::with_capacity(instance_count as usize),
+            instance_colors: Vec::with_capacity(instance_count as usize),
+            draw_list: DrawList::default(),
+            particle_instances: Vec::new(),
+            particle_colors: Vec::new(),
+            selected_instances: Vec::new(),
+            flat_shading: false,
+            view_extent: 1.0,
+            #[cfg(feature = "physics")]
+            physics: None,
+            flock: None,
+        }
+    }
+
+    /// Spawns a group node at `center` turning at `speed` with a ring of
+    /// [`CLUSTER_SIZE`] differently colored children, and returns the group node.
+    pub fn spawn_cluster(&mut self, center: [f32; 2], speed: f32) -> Entity {
+        let root = self.world.spawn((
+            Transform {
+                translation: center,
+                ..Transform::default()
+            },
+            AngularVelocity(speed),
+        ));
+        for i in 0..CLUSTER_SIZE {
+            let angle = i as f32 / CLUSTER_SIZE as f32 * TAU;
+            self.world.spawn((
+                Transform {
+                    translation: [0.15 * angle.cos(), 0.15 * angle.sin()],
+                    rotation: angle,
+                    scale: 0.2,
+                },
+                Color(hue(i as f32 / CLUSTER_SIZE as f32)),
+                Parent(root),
+            ));
+        }
+        root
+    }
+
+    /// Spawns a wobbling instance at `position`, in clip space, each in the next
+    /// color around the color wheel.
+    pub fn spawn_instance(&mut self, position: [f32; 2]) -> Entity {
+        let index = self.drawable_count() as f32;
+        let entity = self.world.spawn((
+            Transform {
+                translation: position,
+                scale: 0.5,
+                ..Transform::default()
+            },
+            // Steps by the golden ratio, so consecutive colors stay far apart.
+            Color(hue((index * 0.618_034).fract())),
+            Velocity::default(),
+            Wobble {
+                phase: [index, 2.0 * index],
+            },
+        ));
+        #[cfg(feature = "physics")]
+        if let Some(physics) = self.physics.as_mut() {
+            let body = physics.add_body(position, 0.0, 0.5 * BODY_HALF_EXTENT);
+            self.world.insert_one(entity, body).unwrap();
+        }
+        if self.flock.is_some() {
+            self.world.insert_one(entity, Boid).unwrap();
+        }
+        entity
+    }
+
+    /// Despawns the visible drawable root entity whose translation is nearest to
+    /// `position`, in clip space, and returns it. Children of a group node are left
+    /// alone, as they go with their group.
+    pub fn despawn_nearest(&mut self, position: [f32; 2]) -> Option<Entity> {
+        let nearest = self.nearest_root(position)?;
+        #[cfg(feature = "physics")]
+        if let (Some(physics), Ok(body)) = (self.physics.as_mut(), self.world.get::<Body>(nearest))
+        {
+            physics.remove_body(*body);
+        }
+        self.world.despawn(nearest).unwrap();
+        Some(nearest)
+    }
+
+    /// Selects the visible drawable root entity nearest to `position`, as
+    /// [`Self::despawn_nearest`] finds it, or deselects it if it was selected.
+    /// Returns it and whether it's selected now.
+    pub fn toggle_selection(&mut self, position: [f32; 2]) -> Option<(Entity, bool)> {
+        let nearest = self.nearest_root(position)?;
+        if self.world.remove_one::<Selected>(nearest).is_ok() {
+            return Some((nearest, false));
+        }
+        self.world.insert_one(nearest, Selected).unwrap();
+        Some((nearest, true))
+    }
+
+    /// The visible drawable root entity whose translation is nearest to `position`.
+    fn nearest_root(&self, position: [f32; 2]) -> Option<Entity> {
+        let distance = |translation: [f32; 2]| {
+            (translation[0] - position[0]).powi(2) + (translation[1] - position[1]).powi(2)
+        };
+        self.world
+            .query::<(&Transform, &Color, Option<&Parent>, Option<&Hidden>)>()
+            .iter()
+            .filter(|(_, (_, _, parent, hidden))| parent.is_none() && hidden.is_none())
+            .map(|(entity, (transform, ..))| (entity, distance(transform.translation)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(entity, _)| entity)
+    }
+
+    /// Gives every drawable root entity a rigid body, replacing the physics world
+    /// if there was one. From then on the entities' transforms follow their bodies.
+    /// Entities sharing a spot with an earlier one, like all of the default scene's,
+    /// are first shrunk and lined up in rows across the top of the view, as
+    /// overlapping bodies would fly apart.
+    #[cfg(feature = "physics")]
+    pub fn enable_physics(&mut self) {
+        /// Spacing of the rows, and of the entities in them.
+        const SLOT: f32 = 0.06;
+        let per_row = (2.0 / SLOT) as usize - 1;
+
+        let mut physics = Physics::new((1.0 / TICK_RATE) as f32);
+        let mut taken = Vec::new();
+        let mut slots = 0;
+        let roots: Vec<Entity> = self
+            .world
+            .query::<(&Transform, &Color, Option<&Parent>)>()
+            .iter()
+            .filter(|(_, (_, _, parent))| parent.is_none())
+            .map(|(entity, _)| entity)
+            .collect();
+        for entity in roots {
+            let mut transform = self.world.get_mut::<Transform>(entity).unwrap();
+            if taken.contains(&transform.translation) {
+                transform.translation = [
+                    -1.0 + SLOT * (1 + slots % per_row) as f32,
+                    -1.0 + SLOT * (1 + slots / per_row) as f32,
+                ];
+                transform.scale = transform.scale.min(0.1);
+                slots += 1;
+            }
+            taken.push(transform.translation);
+            let body = physics.add_body(
+                transform.translation,
+                transform.rotation,
+                BODY_HALF_EXTENT * transform.scale,
+            );
+            drop(transform);
+            self.world.insert_one(entity, body).unwrap();
+        }
+        self.physics = Some(physics);
+    }
+
+    /// Pushes the bodies near `position`, in clip space, away from it.
+    #[cfg(feature = "physics")]
+    pub fn blast(&mut self, position: [f32; 2]) {
+        if let Some(physics) = self.physics.as_mut() {
+            physics.blast(position);
+        }
+    }
+
+    /// Turns every drawable root entity with a [`Velocity`] into a [`Boid`], keeping
+    /// the flock's parameters if it already had one. Entities sharing a spot with an
+    /// earlier one, like all of the default scene's, are first shrunk and scattered
+    /// over the view, each heading another way, as boids on top of each other would
+    /// have nothing to steer apart by.
+    pub fn enable_boids(&mut self) {
+        /// The scattered boids' scale, and their speed as a fraction of the maximum.
+        const SCALE: f32 = 0.08;
+        const SPEED: f32 = 0.5;
+        /// Steps of a low-discrepancy sequence, which spreads points evenly.
+        const STEP: [f32; 2] = [0.754_877_7, 0.569_840_3];
+        const GOLDEN_ANGLE: f32 = 2.399_963;
+
+        let flock = self.flock.get_or_insert_with(Flock::default);
+        let speed = SPEED * flock.params.max_speed;
+        let mut taken = Vec::new();
+        let mut scattered = 0;
+        let roots: Vec<Entity> = self
+            .world
+            .query::<(&Transform, &Color, &Velocity, Option<&Parent>)>()
+            .iter()
+            .filter(|(_, (.., parent))| parent.is_none())
+            .map(|(entity, _)| entity)
+            .collect();
+        for entity in roots {
+            let (transform, velocity) = self
+                .world
+                .query_one_mut::<(&mut Transform, &mut Velocity)>(entity)
+                .unwrap();
+            if taken.contains(&transform.translation) {
+                scattered += 1;
+                let n = scattered as f32;
+                transform.translation = [
+                    1.8 * (0.5 + n * STEP[0]).fract() - 0.9,
+                    1.8 * (0.5 + n * STEP[1]).fract() - 0.9,
+                ];
+                transform.scale = transform.scale.min(SCALE);
+                let (sin, cos) = (n * GOLDEN_ANGLE).sin_cos();
+                velocity.0 = [speed * cos, speed * sin];
+            }
+            taken.push(transform.translation);
+            self.world.insert_one(entity, Boid).unwrap();
+        }
+    }
+
+    /// Points the boids toward `target`, in clip space, from the next tick on.
+    pub fn steer_flock(&mut self, target: [f32; 2]) {
+        if let Some(flock) = self.flock.as_mut() {
+            flock.target = target;
+        }
+    }
+
+    /// Set by [`Self::enable_boids`].
+    pub fn flock(&self) -> Option<&Flock> {
+        self.flock.as_ref()
+    }
+
+    pub fn flock_mut(&mut self) -> Option<&mut Flock> {
+        self.flock.as_mut()
+    }
+
+    pub fn set_visible(&mut self, entity: Entity, visible: bool) {
+        // Failing only means the entity is gone or already in the requested state.
+        if visible {
+            let _ = self.world.remove_one::<Hidden>(entity);
+        } else {
+            let _ = self.world.insert_one(entity, Hidden);
+        }
+    }
+
+    /// Draws the children of every cluster with `mesh` and `material`.
+    pub fn style_clusters(&mut self, mesh: MeshId, material: MaterialId) {
+        let children: Vec<Entity> = self
+            .world
+            .query::<(&Color, &Parent)>()
+            .iter()
+            .map(|(entity, _)| entity)
+            .collect();
+        for child in children {
+            self.world.insert(child, (mesh, material)).unwrap();
+        }
+    }
+
+    /// Entities with a [`Transform`] that aren't drawn themselves, such as the group
+    /// nodes of clusters.
+    pub fn group_nodes(&self) -> Vec<Entity> {
+        self.world
+            .query::<(&Transform, Option<&Color>)>()
+            .iter()
+            .filter(|(_, (_, color))| color.is_none())
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+
+    /// Tints the default scene's instances in a rainbow that scrolls through them.
+    pub fn add_rainbow(&mut self) {
+        let count = self.drawable_count().max(1) as f32;
+        let entities: Vec<Entity> = self
+            .world
+            .query::<(&Color, &Wobble)>()
+            .iter()
+            .map(|(entity, _)| entity)
+            .collect();
+        for (i, entity) in entities.into_iter().enumerate() {
+            let rainbow = Rainbow {
+                hue: i as f32 / count,
+                speed: 0.25,
+            };
+            self.world.insert_one(entity, rainbow).unwrap();
+        }
+    }
+
+    /// Draws the default scene's instances with `material`, each sampling the next of
+    /// `textures` in turn.
+    pub fn texture_instances(&mut self, material: MaterialId, textures: &[TextureId]) {
+        let entities: Vec<Entity> = self
+            .world
+            .query::<(&Color, &Wobble)>()
+            .iter()
+            .map(|(entity, _)| entity)
+            .collect();
+        for (entity, texture) in entities.into_iter().zip(textures.iter().cycle()) {
+            self.world.insert(entity, (material, *texture)).unwrap();
+        }
+    }
+
+    /// Bends the shading of the default scene's instances with `normal_map`, where
+    /// their material is lit.
+    pub fn normal_map_instances(&mut self, normal_map: TextureId) {
+        let entities: Vec<Entity> = self
+            .world
+            .query::<(&Color, &Wobble)>()
+            .iter()
+            .map(|(entity, _)| entity)
+            .collect();
+        for entity in entities {
+            self.world
+                .insert_one(entity, NormalMap(normal_map))
+                .unwrap();
+        }
+    }
+
+    /// Draws the default scene's instances with `mesh`.
+    pub fn mesh_instances(&mut self, mesh: MeshId) {
+        let entities: Vec<Entity> = self
+            .world
+            .query::<(&Color, &Wobble)>()
+            .iter()
+            .map(|(entity, _)| entity)
+            .collect();
+        for entity in entities {
+            self.world.insert_one(entity, mesh).unwrap();
+        }
+    }
+
+    /// Sets how far meshes reach from an instance's origin in the instance's own
+    /// space, as the vertex shader scales and wobbles them, which the level of
+    /// detail each instance is packed with follows. 1 until set.
+    pub fn set_view_extent(&mut self, extent: f32) {
+        self.view_extent = extent;
+    }
+
+    /// Switches between shading with and without normal maps, returning whether
+    /// they're used now.
+    pub fn toggle_normal_maps(&mut self) -> bool {
+        self.flat_shading = !self.flat_shading;
+        !self.flat_shading
+    }
+
+    /// Entities that are drawn, whether currently visible or not.
+    pub fn drawable_count(&self) -> usize {
+        self.world.query::<(&Transform, &Color)>().iter().count()
+    }
+
+    /// Runs the scene's systems for one simulation tick of `dt` seconds.
+    pub fn update(&mut self, dt: f32) {
+        let mut new_entities = Vec::new();
+        for (entity, (transform, previous)) in self
+            .world
+            .query_mut::<(&Transform, Option<&mut PreviousTransform>)>()
+        {
+            match previous {
+                Some(previous) => previous.0 = *transform,
+                None => new_entities.push((entity, PreviousTransform(*transform))),
+            }
+        }
+        for (entity, previous) in new_entities {
+            self.world.insert_one(entity, previous).unwrap();
+        }
+        if let Some(flock) = self.flock.as_mut() {
+            flock.steer(&mut self.world, dt);
+        }
+        for (_, (transform, velocity)) in self.world.query_mut::<(&mut Transform, &Velocity)>() {
+            transform.translation[0] += velocity.0[0] * dt;
+            transform.translation[1] += velocity.0[1] * dt;
+        }
+        for (_, (transform, angular_velocity)) in
+            self.world.query_mut::<(&mut Transform, &AngularVelocity)>()
+        {
+            transform.rotation += angular_velocity.0 * dt;
+        }
+        for (_, (color, rainbow)) in self.world.query_mut::<(&mut Color, &mut Rainbow)>() {
+            rainbow.hue = (rainbow.hue + rainbow.speed * dt).rem_euclid(1.0);
+            color.0 = hue(rainbow.hue);
+        }
+        #[cfg(feature = "physics")]
+        if let Some(physics) = self.physics.as_mut() {
+            // Ticks run at the fixed rate the physics world was created with.
+            physics.step();
+            for (_, (transform, body)) in self.world.query_mut::<(&mut Transform, &Body)>() {
+                if let Some((translation, rotation)) = physics.pose(*body) {
+                    transform.translation = translation;
+                    transform.rotation = rotation;
+                }
+            }
+        }
+        for (_, (transform, emitter)) in self.world.query_mut::<(&Transform, &mut Emitter)>() {
+            emitter.update(dt, transform.translation);
+        }
+    }
+
+    /// Flattens the hierarchy to world transforms and packs every visible drawable
+    /// entity and the particles of visible emitters into the instance data the
+    /// renderer uploads. Transforms are blended `alpha` of the way from the tick
+    /// before the latest to the latest, so motion is as smooth as the frame rate.
+    pub fn pack_instances(&mut self, time: f32, alpha: f32) -> PackedInstances<'_> {
+        let nodes: HashMap<Entity, Node> = self
+            .world
+            .query_mut::<(
+                &Transform,
+                Option<&PreviousTransform>,
+                Option<&Parent>,
+                Option<&Hidden>,
+            )>()
+            .into_iter()
+            .map(|(entity, (transform, previous, parent, hidden))| {
+                let transform = match previous {
+                    Some(previous) => previous.0.lerp(transform, alpha),
+                    None => *transform,
+                };
+                let node = Node {
+                    local: Affine::from_transform(&transform),
+                    parent: parent.map(|parent| parent.0),
+                    hidden: hidden.is_some(),
+                };
+                (entity, node)
+            })
+            .collect();
+        let mut world_transforms = HashMap::with_capacity(nodes.len());
+
+        self.instances.clear();
+        self.instance_colors.clear();
+        self.draw_list.clear();
+        self.selected_instances.clear();
+        let flat_shading = self.flat_shading;
+        let view_extent = self.view_extent;
+        for (entity, (color, wobble, mesh, material, (texture, normal_map), selected)) in
+            self.world.query_mut::<(
+                &Color,
+                Option<&Wobble>,
+                Option<&MeshId>,
+                Option<&MaterialId>,
+                (Option<&TextureId>, Option<&NormalMap>),
+                Option<&Selected>,
+            )>()
+        {
+            let world = match world_transform(entity, &nodes, &mut world_transforms, 0) {
+                Some(world) => world,
+                None => continue,
+            };
+            self.draw_list.push(
+                material.copied().unwrap_or_default(),
+                mesh.copied().unwrap_or_default(),
+                level_of_detail(&world, view_extent),
+                self.instances.len() as u32,
+            );
+            if selected.is_some() {
+                self.selected_instances.push(self.instances.len() as u32);
+            }
+            let phase = wobble.map_or([0.0, 0.0], |wobble| wobble.phase);
+            self.instances.push(InstanceData {
+                basis_x: world.basis[0],
+                basis_y: world.basis[1],
+                translation: world.translation,
+                phase: [phase[0] + time, phase[1] + time],
+                texture: texture.copied().unwrap_or_default().0,
+                normal_map: normal_map
+                    .filter(|_| !flat_shading)
+                    .map_or(0, |normal_map| normal_map.0 .0),
+            });
+            self.instance_colors.push(color.0);
+        }
+
+        self.particle_instances.clear();
+        self.particle_colors.clear();
+        for (entity, emitter) in self.world.query_mut::<&Emitter>() {
+            if world_transform(entity, &nodes, &mut world_transforms, 0).is_some() {
+                emitter.pack_instances(&mut self.particle_instances, &mut self.particle_colors);
+            }
+        }
+
+        // Entities with the same components are stored together, so the batches are
+        // already few; sorting them keeps the binds down.
+        self.draw_list.sort();
+        PackedInstances {
+            meshes: Instances {
+                data: &self.instances,
+                colors: &self.instance_colors,
+            },
+            draw_list: &self.draw_list,
+            particles: Instances {
+                data: &self.particle_instances,
+                colors: &self.particle_colors,
+            },
+            selected: &self.selected_instances,
+        }
+    }
+}
+
+/// The level of detail an instance placed by `world` is drawn in, from how far its
+/// mesh reaches on screen, `view_extent` in its own space.
+fn level_of_detail(world: &Affine, view_extent: f32) -> u32 {
+    let [x, y] = world.basis;
+    let scale = x[0].hypot(x[1]).max(y[0].hypot(y[1]));
+    let extent = scale * view_extent;
+    // Also the most detailed for a NaN extent, and the least for a collapsed one.
+    let lod = (LOD0_EXTENT / extent).log2().floor().max(0.0);
+    (lod as u32).min(MAX_LOD)
+}
+
+/// The transform from `entity`'s space to clip space, or `None` when it or an
+/// ancestor is hidden or has no [`Transform`]. Results are memoized in `cache`.
+fn world_transform(
+    entity: Entity,
+    nodes: &HashMap<Entity, Node>,
+    cache: &mut HashMap<Entity, Option<Affine>>,
+    depth: usize,
+) -> Option<Affine> {
+    if let Some(world) = cache.get(&entity) {
+        return *world;
+    }
+    let node = nodes.get(&entity)?;
+    let world = if node.hidden || depth > MAX_DEPTH {
+        None
+    } else {
+        match node.parent {
+            Some(parent) => world_transform(parent, nodes, cache, depth + 1)
+                .map(|parent| parent.then(&node.local)),
+            None => Some(node.local),
+        }
+    };
+    cache.insert(entity, world);
+    world
+}
+
+/// A fully saturated color around the color wheel, `t` in `[0, 1)`.
+fn hue(t: f32) -> [f32; 4] {
+    let channel = |offset: f32| {
+        let x = ((t + offset).fract() * 6.0 - 3.0).abs() - 1.0;
+        x.clamp(0.0, 1.0)
+    };
+    [channel(0.0), channel(2.0 / 3.0), channel(1.0 / 3.0), 1.0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transforms_blend_linearly() {
+        let from = Transform::default();
+        let to = Transform {
+            translation: [1.0, -2.0],
+            rotation: 1.0,
+            scale: 3.0,
+        };
+        let halfway = from.lerp(&to, 0.5);
+        assert_eq!(halfway.translation, [0.5, -1.0]);
+        assert_eq!(halfway.rotation, 0.5);
+        assert_eq!(halfway.scale, 2.0);
+        assert_eq!(from.lerp(&to, 1.0).translation, to.translation);
+    }
+
+    #[test]
+    fn rotation_blends_the_shorter_way_round() {
+        let at = |rotation| Transform {
+            rotation,
+            ..Transform::default()
+        };
+        // From just below half a turn to just above minus half a turn is a small step.
+        let blended = at(PI - 0.1).lerp(&at(-PI + 0.1), 0.5);
+        assert!((blended.rotation - PI).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,178 @@
+use super::{
+    AngularVelocity, Color, Hidden, NormalMap, Parent, Rainbow, Scene, Transform, Velocity, Wobble,
+};
+use crate::renderer::{MaterialId, MeshId, TextureId};
+use hecs::{Entity, World};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path};
+
+/// What the view was looking at when the scene was saved.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Camera {
+    pub mouse: [f32; 2],
+    pub zoom: f32,
+}
+
+/// A scene as written to a `.ron` file: the entities with their components, the
+/// camera and the clear color. An entity's [`Color`] is its material.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub clear_color: [f32; 4],
+    pub camera: Camera,
+    entities: Vec<SavedEntity>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedEntity {
+    transform: Transform,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<Color>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    velocity: Option<Velocity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    angular_velocity: Option<AngularVelocity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    wobble: Option<Wobble>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rainbow: Option<Rainbow>,
+    /// Meshes and materials are saved as the ids the renderer handed out, which are
+    /// only meaningful if the same ones are added again in the same order. Textures
+    /// likewise, if the same ones are given in the same order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mesh: Option<MeshId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    material: Option<MaterialId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    texture: Option<TextureId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    normal_map: Option<TextureId>,
+    /// Index of the parent in the file's entity list.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent: Option<usize>,
+    #[serde(default)]
+    hidden: bool,
+}
+
+impl SceneFile {
+    /// Snapshots every entity with a [`Transform`]; anything else is left out.
+    pub fn new(scene: &Scene, clear_color: [f32; 4], camera: Camera) -> Self {
+        let world = &scene.world;
+        let entities: Vec<Entity> = world
+            .query::<&Transform>()
+            .iter()
+            .map(|(entity, _)| entity)
+            .collect();
+        let indices: HashMap<Entity, usize> = entities
+            .iter()
+            .enumerate()
+            .map(|(index, &entity)| (entity, index))
+            .collect();
+
+        let entities = entities
+            .into_iter()
+            .map(|entity| SavedEntity {
+                transform: *world.get::<Transform>(entity).unwrap(),
+                color: world.get::<Color>(entity).ok().map(|color| *color),
+                velocity: world.get::<Velocity>(entity).ok().map(|velocity| *velocity),
+                angular_velocity: world
+                    .get::<AngularVelocity>(entity)
+                    .ok()
+                    .map(|angular_velocity| *angular_velocity),
+                wobble: world.get::<Wobble>(entity).ok().map(|wobble| *wobble),
+                rainbow: world.get::<Rainbow>(entity).ok().map(|rainbow| *rainbow),
+                mesh: world.get::<MeshId>(entity).ok().map(|mesh| *mesh),
+                material: world
+                    .get::<MaterialId>(entity)
+                    .ok()
+                    .map(|material| *material),
+                texture: world.get::<TextureId>(entity).ok().map(|texture| *texture),
+                normal_map: world
+                    .get::<NormalMap>(entity)
+                    .ok()
+                    .map(|normal_map| normal_map.0),
+                parent: world
+                    .get::<Parent>(entity)
+                    .ok()
+                    .and_then(|parent| indices.get(&parent.0).copied()),
+                hidden: world.get::<Hidden>(entity).is_ok(),
+            })
+            .collect();
+        SceneFile {
+            clear_color,
+            camera,
+            entities,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let file: SceneFile =
+            ron::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if let Some(parent) = file
+            .entities
+            .iter()
+            .filter_map(|entity| entity.parent)
+            .find(|&parent| parent >= file.entities.len())
+        {
+            return Err(format!(
+                "{}: parent {} is out of range, the file has {} entities",
+                path.display(),
+                parent,
+                file.entities.len()
+            ));
+        }
+        Ok(file)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents =
+            ron::ser::to_string_pretty(self, Default::default()).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Replaces everything in `scene` with the saved entities.
+    pub fn restore(&self, scene: &mut Scene) {
+        let world = &mut scene.world;
+        world.clear();
+        let entities: Vec<Entity> = self
+            .entities
+            .iter()
+            .map(|saved| world.spawn((saved.transform,)))
+            .collect();
+
+        for (saved, &entity) in self.entities.iter().zip(&entities) {
+            insert_some(world, entity, saved.color);
+            insert_some(world, entity, saved.velocity);
+            insert_some(world, entity, saved.angular_velocity);
+            insert_some(world, entity, saved.wobble);
+            insert_some(world, entity, saved.rainbow);
+            insert_some(world, entity, saved.mesh);
+            insert_some(world, entity, saved.material);
+            insert_some(world, entity, saved.texture);
+            insert_some(world, entity, saved.normal_map.map(NormalMap));
+            insert_some(
+                world,
+                entity,
+                saved.parent.map(|index| Parent(entities[index])),
+            );
+            if saved.hidden {
+                world.insert_one(entity, Hidden).unwrap();
+            }
+        }
+        // The bodies and boid markers went with the old entities.
+        #[cfg(feature = "physics")]
+        if scene.physics.is_some() {
+            scene.enable_physics();
+        }
+        if scene.flock.is_some() {
+            scene.enable_boids();
+        }
+    }
+}
+
+fn insert_some<T: hecs::Component>(world: &mut World, entity: Entity, component: Option<T>) {
+    if let Some(component) = component {
+        world.insert_one(entity, component).unwrap();
+    }
+}
@@ -0,0 +1,274 @@
+use crate::{
+    input::ZOOM_RANGE,
+    scene::{Camera, Scene},
+};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+pub const TICK_RATE: f64 = 60.0;
+
+/// How quickly the zoom follows its target, per second. Higher is snappier.
+const ZOOM_SMOOTHING: f32 = 12.0;
+
+/// Zoom factor of one notch of the scroll wheel.
+const ZOOM_PER_LINE: f32 = 1.1;
+
+/// Upper bound on catch-up ticks per frame, so a long stall (debugger, power-save
+/// sleep, window drag) can't snowball into seconds of simulation work.
+const MAX_TICKS_PER_FRAME: u32 = 8;
+
+/// Seconds [`Simulation::fly_to`] takes to reach its camera.
+const FLIGHT_DURATION: f32 = 0.5;
+
+/// Everything the simulation owns and the renderer interpolates between ticks.
+#[derive(Clone, Copy, Debug)]
+pub struct State {
+    pub time: f32,
+    pub mouse: [f32; 2],
+    pub zoom: f32,
+    /// How far past the previous tick towards the latest this is, from 0 to 1, for
+    /// [`Scene::pack_instances`] to blend the entities' transforms by.
+    pub alpha: f32,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            time: 0.0,
+            mouse: [0.0, 0.0],
+            zoom: 1.0,
+            alpha: 1.0,
+        }
+    }
+}
+
+impl State {
+    fn lerp(&self, other: &State, alpha: f32) -> State {
+        let mix = |a: f32, b: f32| a + (b - a) * alpha;
+        State {
+            time: mix(self.time, other.time),
+            mouse: [
+                mix(self.mouse[0], other.mouse[0]),
+                mix(self.mouse[1], other.mouse[1]),
+            ],
+            zoom: mix(self.zoom, other.zoom),
+            alpha,
+        }
+    }
+}
+
+/// Input sampled by the event loop and consumed on the next tick.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Input {
+    pub mouse: [f32; 2],
+    /// Scroll wheel lines accumulated since startup, up is positive.
+    pub scroll: f32,
+    /// Zoom factor from pinch gestures.
+    pub pinch: f32,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Input {
+            mouse: [0.0, 0.0],
+            scroll: 0.0,
+            pinch: 1.0,
+        }
+    }
+}
+
+impl Input {
+    /// The zoom the simulation eases towards.
+    pub fn zoom(&self) -> f32 {
+        (self.pinch * ZOOM_PER_LINE.powf(self.scroll)).clamp(ZOOM_RANGE.0, ZOOM_RANGE.1)
+    }
+
+    /// Accumulates scroll, stopping once it alone would leave the zoom range so
+    /// scrolling back responds immediately.
+    pub fn add_scroll(&mut self, lines: f32) {
+        let limit = ZOOM_RANGE.1.ln() / ZOOM_PER_LINE.ln();
+        self.scroll = (self.scroll + lines).clamp(-limit, limit);
+    }
+}
+
+/// The view moving to a camera, which overrides the input until the input changes.
+struct Flight {
+    from: Camera,
+    to: Camera,
+    elapsed: f32,
+    input: Input,
+}
+
+/// Fixed-rate simulation driven by an accumulator, decoupled from the frame rate.
+pub struct Simulation {
+    step: Duration,
+    accumulator: Duration,
+    last_advance: Instant,
+    previous: State,
+    current: State,
+    ticks: u64,
+    scene: Scene,
+    flight: Option<Flight>,
+}
+
+impl Simulation {
+    pub fn new(scene: Scene) -> Self {
+        Simulation {
+            step: Duration::from_secs_f64(1.0 / TICK_RATE),
+            accumulator: Duration::ZERO,
+            last_advance: Instant::now(),
+            previous: State::default(),
+            current: State::default(),
+            ticks: 0,
+            scene,
+            flight: None,
+        }
+    }
+
+    /// Runs the ticks due since the last call and returns the state to render,
+    /// interpolated between the two most recent ticks.
+    pub fn advance(&mut self, input: &Input) -> State {
+        let now = Instant::now();
+        self.accumulator += now - self.last_advance;
+        self.last_advance = now;
+
+        for _ in 0..take_ticks(&mut self.accumulator, self.step) {
+            self.previous = self.current;
+            self.tick(input);
+        }
+
+        let alpha = self.accumulator.as_secs_f32() / self.step.as_secs_f32();
+        self.previous.lerp(&self.current, alpha)
+    }
+
+    /// Runs exactly one tick however much time passed, so every run produces the same
+    /// frames. Used by `--bench`.
+    pub fn step(&mut self, input: &Input) -> State {
+        self.previous = self.current;
+        self.tick(input);
+        self.last_advance = Instant::now();
+        self.current
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    pub fn scene_mut(&mut self) -> &mut Scene {
+        &mut self.scene
+    }
+
+    /// Where the view is as of the latest tick.
+    pub fn camera(&self) -> Camera {
+        Camera {
+            mouse: self.current.mouse,
+            zoom: self.current.zoom,
+        }
+    }
+
+    /// Jumps the view to `camera` without easing, e.g. after loading a scene.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        for state in [&mut self.previous, &mut self.current] {
+            state.mouse = camera.mouse;
+            state.zoom = camera.zoom;
+        }
+    }
+
+    /// Moves the view smoothly to `camera`, then holds it there until `input`, the
+    /// input as of now, changes.
+    pub fn fly_to(&mut self, camera: Camera, input: &Input) {
+        self.flight = Some(Flight {
+            from: self.camera(),
+            to: camera,
+            elapsed: 0.0,
+            input: *input,
+        });
+    }
+
+    /// Ticks run so far; input recorded now takes effect on this tick.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    fn tick(&mut self, input: &Input) {
+        self.ticks += 1;
+        self.current.time += self.step.as_secs_f32();
+        if self
+            .flight
+            .as_ref()
+            .is_some_and(|flight| flight.input != *input)
+        {
+            self.flight = None;
+        }
+        if let Some(flight) = &mut self.flight {
+            flight.elapsed += self.step.as_secs_f32();
+            let t = (flight.elapsed / FLIGHT_DURATION).min(1.0);
+            let eased = t * t * (3.0 - 2.0 * t);
+            let mix = |a: f32, b: f32| a + (b - a) * eased;
+            self.current.mouse = [
+                mix(flight.from.mouse[0], flight.to.mouse[0]),
+                mix(flight.from.mouse[1], flight.to.mouse[1]),
+            ];
+            self.current.zoom = mix(flight.from.zoom, flight.to.zoom);
+        } else {
+            self.current.mouse = input.mouse;
+            // Frame-rate independent exponential approach towards the target.
+            let blend = 1.0 - (-ZOOM_SMOOTHING * self.step.as_secs_f32()).exp();
+            self.current.zoom += (input.zoom() - self.current.zoom) * blend;
+        }
+        self.scene
+            .steer_flock([2.0 * input.mouse[0] - 1.0, 2.0 * input.mouse[1] - 1.0]);
+        self.scene.update(self.step.as_secs_f32());
+    }
+}
+
+/// Takes the ticks of `step` that fit in `accumulator` out of it, up to
+/// [`MAX_TICKS_PER_FRAME`]; past that, the time left over is dropped with them.
+fn take_ticks(accumulator: &mut Duration, step: Duration) -> u32 {
+    let due = accumulator.as_nanos() / step.as_nanos();
+    if due > MAX_TICKS_PER_FRAME as u128 {
+        *accumulator = Duration::ZERO;
+        MAX_TICKS_PER_FRAME
+    } else {
+        *accumulator -= step * due as u32;
+        due as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_leave_the_remainder_for_the_next_frame() {
+        let step = Duration::from_millis(10);
+        let mut accumulator = Duration::from_millis(25);
+        assert_eq!(take_ticks(&mut accumulator, step), 2);
+        assert_eq!(accumulator, Duration::from_millis(5));
+    }
+
+    #[test]
+    fn catch_up_stops_at_the_tick_limit() {
+        let step = Duration::from_millis(10);
+        let mut accumulator = step * MAX_TICKS_PER_FRAME + Duration::from_millis(5);
+        assert_eq!(take_ticks(&mut accumulator, step), MAX_TICKS_PER_FRAME);
+        assert_eq!(accumulator, Duration::from_millis(5));
+
+        // A stall longer than that drops the time it couldn't catch up on.
+        let mut accumulator = Duration::from_secs(5);
+        assert_eq!(take_ticks(&mut accumulator, step), MAX_TICKS_PER_FRAME);
+        assert_eq!(accumulator, Duration::ZERO);
+    }
+
+    #[test]
+    fn states_blend_by_alpha() {
+        let previous = State::default();
+        let mut current = previous;
+        current.time = 1.0;
+        current.mouse = [0.5, 1.0];
+        let blended = previous.lerp(&current, 0.25);
+        assert_eq!(blended.time, 0.25);
+        assert_eq!(blended.mouse, [0.125, 0.25]);
+        assert_eq!(previous.lerp(&current, 1.0).mouse, current.mouse);
+    }
+}
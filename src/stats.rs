@@ -0,0 +1,95 @@
+use crate::renderer::PipelineStatistics;
+use std::time::{Duration, Instant};
+use tracing::{debug, trace};
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Accumulates frame times and logs a summary once per second.
+pub struct FrameStats {
+    last_frame: Instant,
+    window_start: Instant,
+    frames: u32,
+    slowest: Duration,
+    /// Sum over the frames that reported occlusion culling, and their count.
+    culled_clusters: u64,
+    culling_frames: u32,
+    /// Sum over the frames whose pipeline statistics were read back, and their count.
+    statistics: PipelineStatistics,
+    counted_frames: u32,
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        FrameStats {
+            last_frame: now,
+            window_start: now,
+            frames: 0,
+            slowest: Duration::ZERO,
+            culled_clusters: 0,
+            culling_frames: 0,
+            statistics: PipelineStatistics::default(),
+            counted_frames: 0,
+        }
+    }
+
+    /// Adds a frame's counts from [`Renderer::take_pipeline_statistics`](crate::renderer::Renderer::take_pipeline_statistics).
+    pub fn pipeline_statistics_read(&mut self, statistics: PipelineStatistics) {
+        self.statistics.vertex_shader_invocations += statistics.vertex_shader_invocations;
+        self.statistics.clipping_primitives += statistics.clipping_primitives;
+        self.statistics.fragment_shader_invocations += statistics.fragment_shader_invocations;
+        self.counted_frames += 1;
+    }
+
+    /// `culled_clusters` is from [`Renderer::culled_clusters`](crate::renderer::Renderer::culled_clusters).
+    pub fn frame_presented(&mut self, culled_clusters: Option<u32>) {
+        let now = Instant::now();
+        let frame_time = now - self.last_frame;
+        self.last_frame = now;
+        self.frames += 1;
+        self.slowest = self.slowest.max(frame_time);
+        if let Some(culled_clusters) = culled_clusters {
+            self.culled_clusters += culled_clusters as u64;
+            self.culling_frames += 1;
+        }
+        trace!(
+            frame_ms = frame_time.as_secs_f64() * 1000.0,
+            "frame presented"
+        );
+
+        let elapsed = now - self.window_start;
+        if elapsed >= REPORT_INTERVAL {
+            let average_ms = elapsed.as_secs_f64() * 1000.0 / self.frames as f64;
+            debug!(
+                fps = self.frames as f64 / elapsed.as_secs_f64(),
+                average_ms,
+                slowest_ms = self.slowest.as_secs_f64() * 1000.0,
+                "frame stats"
+            );
+            if self.culling_frames > 0 {
+                debug!(
+                    culled_clusters = self.culled_clusters as f64 / self.culling_frames as f64,
+                    "occlusion culling"
+                );
+            }
+            self.window_start = now;
+            self.frames = 0;
+            self.slowest = Duration::ZERO;
+            if self.counted_frames > 0 {
+                let per_frame = |count: u64| count / self.counted_frames as u64;
+                debug!(
+                    vertex_shader_invocations =
+                        per_frame(self.statistics.vertex_shader_invocations),
+                    clipping_primitives = per_frame(self.statistics.clipping_primitives),
+                    fragment_shader_invocations =
+                        per_frame(self.statistics.fragment_shader_invocations),
+                    "pipeline statistics per frame"
+                );
+            }
+            self.culled_clusters = 0;
+            self.culling_frames = 0;
+            self.statistics = PipelineStatistics::default();
+            self.counted_frames = 0;
+        }
+    }
+}
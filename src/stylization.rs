@@ -0,0 +1,118 @@
+use crate::{
+    debug_draw::DebugDraw,
+    renderer::{Style, Stylization},
+};
+use tracing::info;
+use winit::event::VirtualKeyCode;
+
+/// Intensity a style is turned on at when the command line didn't give one.
+const DEFAULT_INTENSITY: f32 = 0.5;
+
+/// Change of intensity per key press.
+const STEP: f32 = 0.1;
+
+/// Toggles each of [`Style::ALL`].
+const KEYS: [VirtualKeyCode; 3] = [VirtualKeyCode::F1, VirtualKeyCode::F2, VirtualKeyCode::F3];
+
+/// Of each of [`Style::ALL`]'s bar in the overlay.
+const COLORS: [[f32; 4]; 3] = [
+    [1.0, 0.3, 1.0, 1.0],
+    [0.5, 0.5, 1.0, 1.0],
+    [1.0, 0.8, 0.4, 1.0],
+];
+
+/// Where the bars of [`StyleRig::draw_overlay`] start, and their size.
+const BARS_ORIGIN: [f32; 2] = [0.55, -0.95];
+const BAR_SIZE: [f32; 2] = [0.4, 0.03];
+const BAR_SPACING: f32 = 0.05;
+
+const SELECTED_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const UNSELECTED_COLOR: [f32; 4] = [0.4, 0.4, 0.4, 1.0];
+
+/// The stylization passes, edited with the keyboard: F1, F2 and F3 toggle the
+/// chromatic aberration, the vignette and the film grain and pick it, and [ and ]
+/// lower and raise the picked one's intensity. Changes are logged, and while any is
+/// on their intensities are shown in the overlay.
+pub struct StyleRig {
+    /// Of each of [`Style::ALL`], kept while it's off.
+    intensities: [f32; 3],
+    enabled: [bool; 3],
+    /// Index into [`Style::ALL`] of the style [ and ] change.
+    selected: usize,
+}
+
+impl StyleRig {
+    /// With the styles `stylization` turns on, the others at
+    /// [`DEFAULT_INTENSITY`] once turned on.
+    pub fn new(mut stylization: Stylization) -> Self {
+        let intensities = Style::ALL.map(|style| *style.intensity_mut(&mut stylization));
+        StyleRig {
+            intensities: intensities.map(|intensity| intensity.unwrap_or(DEFAULT_INTENSITY)),
+            enabled: intensities.map(|intensity| intensity.is_some()),
+            selected: 0,
+        }
+    }
+
+    /// For [`crate::renderer::Renderer::set_stylization`].
+    pub fn stylization(&self) -> Stylization {
+        let mut stylization = Stylization::default();
+        for (i, style) in Style::ALL.into_iter().enumerate() {
+            *style.intensity_mut(&mut stylization) = self.enabled[i].then_some(self.intensities[i]);
+        }
+        stylization
+    }
+
+    /// Edits the styles with `key`, returning whether they changed. Other keys are
+    /// ignored.
+    pub fn handle_key(&mut self, key: VirtualKeyCode) -> bool {
+        if let Some(i) = KEYS.iter().position(|&k| k == key) {
+            self.selected = i;
+            self.enabled[i] = !self.enabled[i];
+        } else if key == VirtualKeyCode::LBracket || key == VirtualKeyCode::RBracket {
+            let step = if key == VirtualKeyCode::LBracket {
+                -STEP
+            } else {
+                STEP
+            };
+            let intensity = &mut self.intensities[self.selected];
+            *intensity = (*intensity + step).clamp(0.0, 1.0);
+        } else {
+            return false;
+        }
+        info!(
+            style = Style::ALL[self.selected].name(),
+            enabled = self.enabled[self.selected],
+            intensity = self.intensities[self.selected],
+            "stylization"
+        );
+        true
+    }
+
+    /// Draws a bar per style in the top right corner, filled as far as its
+    /// intensity if it's on, the picked one outlined in white. There's no text; the
+    /// styles are logged as they're edited.
+    pub fn draw_overlay(&self, debug_draw: &mut DebugDraw) {
+        if !self.enabled.contains(&true) {
+            return;
+        }
+        for (i, color) in COLORS.into_iter().enumerate() {
+            let min = [BARS_ORIGIN[0], BARS_ORIGIN[1] + i as f32 * BAR_SPACING];
+            let max = [min[0] + BAR_SIZE[0], min[1] + BAR_SIZE[1]];
+            let outline = if i == self.selected {
+                SELECTED_COLOR
+            } else {
+                UNSELECTED_COLOR
+            };
+            debug_draw.rect(min, max, outline);
+            if !self.enabled[i] {
+                continue;
+            }
+            // Hatched, as the overlay only draws lines.
+            for line in 1..4 {
+                let y = min[1] + BAR_SIZE[1] * line as f32 / 4.0;
+                let end = min[0] + BAR_SIZE[0] * self.intensities[i];
+                debug_draw.line([min[0], y], [end, y], color);
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+/// An image decoded to 8-bit RGBA, sRGB encoded like the rest of the scene's colors.
+#[derive(Clone)]
+pub struct TextureImage {
+    pub pixels: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TextureImage {
+    /// Decodes a PNG of any color type, or a JPEG if the extension says so.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let jpeg = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                extension.eq_ignore_ascii_case("jpg") || extension.eq_ignore_ascii_case("jpeg")
+            });
+        if jpeg {
+            Self::decode_jpeg(BufReader::new(file))
+        } else {
+            Self::decode_png(file)
+        }
+        .map_err(|message| format!("{}: {}", path.display(), message))
+    }
+
+    fn decode_png(file: impl Read) -> Result<Self, String> {
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+        buffer.truncate(info.buffer_size());
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buffer,
+            png::ColorType::Rgb => buffer
+                .chunks(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buffer
+                .chunks(2)
+                .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+                .collect(),
+            png::ColorType::Grayscale => buffer.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+            png::ColorType::Indexed => unreachable!("indexed images are expanded"),
+        };
+        Ok(TextureImage {
+            pixels,
+            width: info.width,
+            height: info.height,
+        })
+    }
+
+    /// JPEGs have no alpha, so the image is opaque.
+    fn decode_jpeg(file: impl Read) -> Result<Self, String> {
+        let mut decoder = jpeg_decoder::Decoder::new(file);
+        let buffer = decoder.decode().map_err(|e| e.to_string())?;
+        let info = decoder.info().ok_or("no image information")?;
+        let pixels = match info.pixel_format {
+            jpeg_decoder::PixelFormat::RGB24 => buffer
+                .chunks(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+                .collect(),
+            jpeg_decoder::PixelFormat::L8 => buffer.iter().flat_map(|&l| [l, l, l, 255]).collect(),
+            // Big endian; the high byte is enough for 8 bits.
+            jpeg_decoder::PixelFormat::L16 => buffer
+                .chunks(2)
+                .flat_map(|l| [l[0], l[0], l[0], 255])
+                .collect(),
+            jpeg_decoder::PixelFormat::CMYK32 => return Err("CMYK JPEGs aren't supported".into()),
+        };
+        Ok(TextureImage {
+            pixels,
+            width: info.width as u32,
+            height: info.height as u32,
+        })
+    }
+
+    /// A single opaque white pixel.
+    pub fn white() -> Self {
+        TextureImage {
+            pixels: vec![255; 4],
+            width: 1,
+            height: 1,
+        }
+    }
+
+    /// The image scaled to `size` by `size` pixels, picking the nearest pixel.
+    pub fn resampled(&self, size: u32) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+        for y in 0..size {
+            let source_y = (y as u64 * self.height as u64 / size as u64) as u32;
+            for x in 0..size {
+                let source_x = (x as u64 * self.width as u64 / size as u64) as u32;
+                let offset = ((source_y * self.width + source_x) * 4) as usize;
+                pixels.extend_from_slice(&self.pixels[offset..offset + 4]);
+            }
+        }
+        pixels
+    }
+}
@@ -0,0 +1,121 @@
+use crate::texture::TextureImage;
+use std::path::Path;
+
+/// Tiles per side of a chunk, the unit the renderer culls and draws.
+pub const CHUNK_SIZE: u32 = 16;
+
+/// Tile index of a cell that isn't drawn.
+pub const EMPTY: u32 = u32::MAX;
+
+/// A grid of tile indices into a [`TileAtlas`], drawn behind the scene by
+/// [`Renderer::sync_tilemap`](crate::renderer::Renderer::sync_tilemap). Tile `i` is
+/// the atlas cell `i` in row-major order.
+pub struct Tilemap {
+    width: u32,
+    height: u32,
+    tiles: Vec<u32>,
+    /// Clip space position of the map's top-left corner.
+    pub origin: [f32; 2],
+    /// Clip space size of one tile.
+    pub tile_extent: [f32; 2],
+    /// Bumped on every change to the tiles, so the renderer knows when to upload them.
+    revision: u64,
+}
+
+impl Tilemap {
+    /// An empty map of `width` by `height` tiles, centered on the window.
+    pub fn new(width: u32, height: u32, tile_extent: [f32; 2]) -> Self {
+        Tilemap {
+            width,
+            height,
+            tiles: vec![EMPTY; (width * height) as usize],
+            origin: [
+                -0.5 * width as f32 * tile_extent[0],
+                -0.5 * height as f32 * tile_extent[1],
+            ],
+            tile_extent,
+            revision: 0,
+        }
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        [self.width, self.height]
+    }
+
+    /// Sets the tile at column `x`, row `y`; coordinates outside the map are ignored.
+    pub fn set(&mut self, x: u32, y: u32, tile: u32) {
+        if x < self.width && y < self.height {
+            self.tiles[(y * self.width + x) as usize] = tile;
+            self.revision += 1;
+        }
+    }
+
+    /// Row-major tile indices.
+    pub fn tiles(&self) -> &[u32] {
+        &self.tiles
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Chunks overlapping the `[-1, 1]` clip space square, as chunk column and row.
+    pub fn visible_chunks(&self) -> impl Iterator<Item = [u32; 2]> {
+        let chunk_extent = self.tile_extent.map(|extent| extent * CHUNK_SIZE as f32);
+        let chunks = [self.width, self.height].map(|size| size.div_ceil(CHUNK_SIZE));
+        let range = |axis: usize| {
+            let first = (-1.0 - self.origin[axis]) / chunk_extent[axis];
+            let last = (1.0 - self.origin[axis]) / chunk_extent[axis];
+            let first = first.floor().max(0.0) as u32;
+            let last = (last.ceil().max(0.0) as u32).min(chunks[axis]);
+            first..last
+        };
+        let (columns, rows) = (range(0), range(1));
+        rows.flat_map(move |row| columns.clone().map(move |column| [column, row]))
+    }
+}
+
+/// A PNG of equally sized square tiles, laid out in rows.
+pub struct TileAtlas {
+    /// 8-bit RGBA, sRGB encoded like the rest of the scene's colors.
+    pub pixels: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// Side of one tile in pixels.
+    pub tile_size: u32,
+}
+
+impl TileAtlas {
+    pub fn load(path: &Path, tile_size: u32) -> Result<Self, String> {
+        let TextureImage {
+            pixels,
+            width,
+            height,
+        } = TextureImage::load(path)?;
+        if tile_size == 0 || width < tile_size || height < tile_size {
+            return Err(format!(
+                "{}: a {}x{} image doesn't fit a {} pixel tile",
+                path.display(),
+                width,
+                height,
+                tile_size
+            ));
+        }
+        Ok(TileAtlas {
+            pixels,
+            width,
+            height,
+            tile_size,
+        })
+    }
+
+    /// Tiles per row and per column.
+    pub fn grid(&self) -> [u32; 2] {
+        [self.width / self.tile_size, self.height / self.tile_size]
+    }
+
+    pub fn tile_count(&self) -> u32 {
+        let [columns, rows] = self.grid();
+        columns * rows
+    }
+}
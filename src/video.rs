@@ -0,0 +1,150 @@
+use crate::renderer::CapturedFrame;
+use std::{
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver, SyncSender, TrySendError},
+    thread::{self, JoinHandle},
+};
+use tracing::{error, info, warn};
+
+/// Frames waiting for the worker, at most. Beyond that they're dropped rather than
+/// stalling the render loop while ffmpeg catches up.
+const QUEUED_FRAMES: usize = 8;
+
+/// How F9 recordings are encoded.
+#[derive(Clone, Debug)]
+pub struct VideoSettings {
+    /// The ffmpeg executable.
+    pub ffmpeg: PathBuf,
+    /// An ffmpeg encoder, e.g. `libx264` or `h264_nvenc`.
+    pub codec: String,
+    /// In ffmpeg's notation, e.g. `8M`.
+    pub bitrate: String,
+    /// Where the first recording goes; later ones get a number appended instead of
+    /// overwriting it.
+    pub output: PathBuf,
+}
+
+/// A recording in progress. Captured frames go to a worker thread, which converts
+/// them and writes them as raw video to the standard input of an ffmpeg process, so
+/// nothing touches the disk but the encoded video.
+pub struct VideoRecorder {
+    path: PathBuf,
+    sender: SyncSender<CapturedFrame>,
+    worker: JoinHandle<()>,
+    frames: u64,
+    dropped: u64,
+}
+
+impl VideoRecorder {
+    /// Starts ffmpeg encoding frames of `extent` pixels into the next free output
+    /// path. Frames of another size, from a resize midway, are cropped or padded.
+    pub fn start(settings: &VideoSettings, extent: [u32; 2]) -> Result<Self, String> {
+        // Most encoders want the chroma planes of yuv420p to split evenly.
+        let extent = extent.map(|side| (side & !1).max(2));
+        let path = free_path(&settings.output);
+        let mut ffmpeg = Command::new(&settings.ffmpeg)
+            .args(["-loglevel", "error", "-y"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+            .args(["-video_size", &format!("{}x{}", extent[0], extent[1])])
+            // Frames come as fast as they're drawn, so they're timed as they arrive.
+            .args(["-use_wallclock_as_timestamps", "1", "-i", "-"])
+            .args(["-c:v", &settings.codec, "-b:v", &settings.bitrate])
+            .args(["-pix_fmt", "yuv420p", "-vsync", "vfr"])
+            .arg(&path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("can't run {}: {}", settings.ffmpeg.display(), e))?;
+        let stdin = ffmpeg.stdin.take().unwrap();
+
+        let (sender, receiver) = mpsc::sync_channel(QUEUED_FRAMES);
+        let worker = thread::Builder::new()
+            .name("video encoder".to_owned())
+            .spawn(move || write_frames(receiver, stdin, ffmpeg, extent))
+            .unwrap();
+        info!(path = %path.display(), width = extent[0], height = extent[1], "recording video");
+        Ok(VideoRecorder {
+            path,
+            sender,
+            worker,
+            frames: 0,
+            dropped: 0,
+        })
+    }
+
+    /// Queues `frame` for the worker, or drops it if the queue is full. Fails once
+    /// ffmpeg stopped taking frames.
+    pub fn push(&mut self, frame: CapturedFrame) -> Result<(), String> {
+        match self.sender.try_send(frame) {
+            Ok(()) => self.frames += 1,
+            Err(TrySendError::Full(_)) => self.dropped += 1,
+            Err(TrySendError::Disconnected(_)) => {
+                return Err(format!("ffmpeg stopped encoding {}", self.path.display()))
+            }
+        }
+        Ok(())
+    }
+
+    /// Ends the recording, waiting for ffmpeg to encode the queued frames.
+    pub fn stop(self) {
+        drop(self.sender);
+        if self.worker.join().is_err() {
+            error!("video encoder thread panicked");
+        }
+        if self.dropped > 0 {
+            warn!(
+                dropped = self.dropped,
+                "ffmpeg couldn't keep up, frames were left out of the video"
+            );
+        }
+        info!(path = %self.path.display(), frames = self.frames, "saved video");
+    }
+}
+
+/// Runs on the worker thread until the recorder is dropped or ffmpeg fails.
+fn write_frames(
+    receiver: Receiver<CapturedFrame>,
+    stdin: ChildStdin,
+    mut ffmpeg: Child,
+    extent: [u32; 2],
+) {
+    let mut stdin = BufWriter::new(stdin);
+    for frame in receiver {
+        if let Err(e) = stdin.write_all(&frame.to_rgba(extent)) {
+            error!(error = %e, "failed to write a frame to ffmpeg");
+            break;
+        }
+    }
+    // Closing its input lets ffmpeg finish the file.
+    if let Err(e) = stdin.flush() {
+        error!(error = %e, "failed to flush the last frames to ffmpeg");
+    }
+    drop(stdin);
+    match ffmpeg.wait() {
+        Ok(status) if status.success() => {}
+        Ok(status) => error!(%status, "ffmpeg failed"),
+        Err(e) => error!(error = %e, "failed to wait for ffmpeg"),
+    }
+}
+
+/// `path` if nothing is there yet, else the first free `<stem>-<N>.<extension>`.
+pub fn free_path(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_owned();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy());
+    (2..)
+        .map(|n| {
+            let name = match &extension {
+                Some(extension) => format!("{}-{}.{}", stem, n, extension),
+                None => format!("{}-{}", stem, n),
+            };
+            path.with_file_name(name)
+        })
+        .find(|candidate| !candidate.exists())
+        .unwrap()
+}
@@ -0,0 +1,249 @@
+use crate::{
+    config,
+    monitor::{self, FullscreenMode, MonitorSelector},
+};
+use std::{fs, path::PathBuf};
+use tracing::{debug, warn};
+use winit::{
+    dpi::{LogicalSize, PhysicalPosition, PhysicalSize, Size},
+    event_loop::EventLoopWindowTarget,
+    window::{Icon, Window, WindowBuilder},
+};
+
+const ICON_PNG: &[u8] = include_bytes!("../assets/icon.png");
+
+/// How the window is created. Sizes are in logical units.
+#[derive(Clone, Debug)]
+pub struct WindowSettings {
+    pub title: String,
+    pub size: Option<LogicalSize<f64>>,
+    pub min_size: Option<LogicalSize<f64>>,
+    pub max_size: Option<LogicalSize<f64>>,
+    pub resizable: bool,
+    pub decorations: bool,
+    pub always_on_top: bool,
+    /// Let the desktop show through where the window's content isn't opaque.
+    pub transparent: bool,
+    /// Use the icon embedded from `assets/icon.png`.
+    pub icon: bool,
+    /// Monitor to open on, centered; otherwise the window system decides.
+    pub monitor: Option<MonitorSelector>,
+    pub fullscreen: Option<FullscreenMode>,
+    /// Reopen where the window was when the last run exited.
+    pub restore_placement: bool,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            title: "Cool Vulkano Example".to_owned(),
+            size: None,
+            min_size: None,
+            max_size: None,
+            resizable: true,
+            decorations: true,
+            always_on_top: false,
+            transparent: false,
+            icon: true,
+            monitor: None,
+            fullscreen: None,
+            restore_placement: true,
+        }
+    }
+}
+
+impl WindowSettings {
+    pub fn builder<T>(&self, window_target: &EventLoopWindowTarget<T>) -> WindowBuilder {
+        let mut builder = WindowBuilder::new()
+            .with_title(&self.title)
+            .with_resizable(self.resizable)
+            .with_decorations(self.decorations)
+            .with_always_on_top(self.always_on_top)
+            .with_transparent(self.transparent);
+
+        let placement = if self.restore_placement {
+            WindowPlacement::load(window_target)
+        } else {
+            None
+        };
+        let size: Option<Size> = match (self.size, &placement) {
+            (Some(size), _) => Some(size.into()),
+            (None, Some(placement)) => Some(placement.size.into()),
+            (None, None) => None,
+        };
+        if let Some(size) = size {
+            builder = builder.with_inner_size(size);
+        }
+
+        let monitor = monitor::select(window_target, self.monitor.as_ref());
+        if let Some(fullscreen) = self.fullscreen {
+            builder = builder.with_fullscreen(Some(monitor::fullscreen(
+                window_target,
+                fullscreen,
+                monitor,
+            )));
+        } else if let Some(monitor) = monitor {
+            // winit opens windows at 800x600 when no size is given.
+            let size = size
+                .unwrap_or_else(|| LogicalSize::new(800.0, 600.0).into())
+                .to_physical::<i32>(monitor.scale_factor());
+            let origin = monitor.position();
+            let area = monitor.size();
+            builder = builder.with_position(PhysicalPosition::new(
+                origin.x + (area.width as i32 - size.width) / 2,
+                origin.y + (area.height as i32 - size.height) / 2,
+            ));
+        } else if let Some(placement) = placement {
+            builder = builder.with_position(placement.position);
+        }
+
+        if let Some(min_size) = self.min_size {
+            builder = builder.with_min_inner_size(min_size);
+        }
+        if let Some(max_size) = self.max_size {
+            builder = builder.with_max_inner_size(max_size);
+        }
+        if self.icon {
+            match load_icon(ICON_PNG) {
+                Ok(icon) => builder = builder.with_window_icon(Some(icon)),
+                Err(message) => warn!(%message, "failed to load the window icon"),
+            }
+        }
+        builder
+    }
+}
+
+/// Where the window was and how large, saved on exit and restored on the next run.
+/// Both are in physical pixels, as monitor positions are.
+#[derive(Clone, Copy, Debug)]
+pub struct WindowPlacement {
+    pub position: PhysicalPosition<i32>,
+    pub size: PhysicalSize<u32>,
+}
+
+impl WindowPlacement {
+    /// Reads the saved placement, ignoring it when it's missing, unreadable or no
+    /// longer on any monitor.
+    fn load<T>(window_target: &EventLoopWindowTarget<T>) -> Option<Self> {
+        let contents = fs::read_to_string(placement_path()?).ok()?;
+        let values = contents
+            .split_whitespace()
+            .map(|value| value.parse::<i64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        let placement = match values[..] {
+            [x, y, width, height] if width > 0 && height > 0 => WindowPlacement {
+                position: PhysicalPosition::new(x as i32, y as i32),
+                size: PhysicalSize::new(width as u32, height as u32),
+            },
+            _ => return None,
+        };
+
+        let on_screen = window_target.available_monitors().any(|monitor| {
+            let origin = monitor.position();
+            let area = monitor.size();
+            (origin.x..origin.x + area.width as i32).contains(&placement.position.x)
+                && (origin.y..origin.y + area.height as i32).contains(&placement.position.y)
+        });
+        if !on_screen {
+            debug!(
+                ?placement,
+                "saved window placement is off screen, ignoring it"
+            );
+            return None;
+        }
+        Some(placement)
+    }
+
+    /// Saves the placement of a windowed, non-minimized window. Failures are logged.
+    pub fn save(window: &Window) {
+        if window.fullscreen().is_some() {
+            return;
+        }
+        let (position, size) = match window.outer_position() {
+            Ok(position) => (position, window.inner_size()),
+            Err(_) => return,
+        };
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+
+        let path = match placement_path() {
+            Some(path) => path,
+            None => return,
+        };
+        let contents = format!(
+            "{} {} {} {}\n",
+            position.x, position.y, size.width, size.height
+        );
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&path, contents));
+        if let Err(e) = result {
+            warn!(path = %path.display(), error = %e, "failed to save the window placement");
+        }
+    }
+}
+
+fn placement_path() -> Option<PathBuf> {
+    config::config_file("window_placement")
+}
+
+/// Parses `WIDTHxHEIGHT`.
+pub fn parse_size(value: &str) -> Option<LogicalSize<f64>> {
+    let (width, height) = value.split_once('x')?;
+    let size = LogicalSize::new(width.trim().parse().ok()?, height.trim().parse().ok()?);
+    (size.width > 0.0 && size.height > 0.0).then_some(size)
+}
+
+fn load_icon(png_bytes: &[u8]) -> Result<Icon, String> {
+    let mut decoder = png::Decoder::new(png_bytes);
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+    if (info.color_type, info.bit_depth) != (png::ColorType::Rgba, png::BitDepth::Eight) {
+        return Err(format!(
+            "expected an 8-bit RGBA image, got {:?} {:?}",
+            info.bit_depth, info.color_type
+        ));
+    }
+    buffer.truncate(info.buffer_size());
+    Icon::from_rgba(buffer, info.width, info.height).map_err(|e| e.to_string())
+}
+
+/// The window's size in physical pixels plus the scale factor to get logical,
+/// DPI-independent units. Rendering works in physical pixels; input and anything
+/// meant to look the same size on every display works in logical units.
+#[derive(Clone, Copy, Debug)]
+pub struct WindowMetrics {
+    pub physical_size: PhysicalSize<u32>,
+    pub scale_factor: f64,
+}
+
+impl WindowMetrics {
+    pub fn new(window: &Window) -> Self {
+        WindowMetrics {
+            physical_size: window.inner_size(),
+            scale_factor: window.scale_factor(),
+        }
+    }
+
+    pub fn logical_size(&self) -> LogicalSize<f64> {
+        self.physical_size.to_logical(self.scale_factor)
+    }
+
+    /// Maps a cursor position to `[0, 1]` across the window, in logical units so it
+    /// behaves the same at 100% and 200% scaling.
+    pub fn normalize_cursor(&self, position: PhysicalPosition<f64>) -> [f32; 2] {
+        let position = position.to_logical::<f64>(self.scale_factor);
+        let size = self.logical_size();
+        if size.width <= 0.0 || size.height <= 0.0 {
+            return [0.0, 0.0];
+        }
+        [
+            (position.x / size.width) as f32,
+            (position.y / size.height) as f32,
+        ]
+    }
+}
@@ -0,0 +1,116 @@
+//! Renders the `--capture` frames on lavapipe and compares them with the reference
+//! images in `tests/golden`. After an intended change to the output, rerun with
+//! `UPDATE_GOLDEN=1` to rewrite the references and commit them. A frame without a
+//! reference fails the test.
+
+use std::{
+    env,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Largest per-channel difference that still counts as the same pixel.
+const TOLERANCE: u8 = 2;
+
+/// Share of pixels allowed to differ by more than `TOLERANCE`, for rasterization
+/// differences between lavapipe versions along triangle edges.
+const MAX_MISMATCHED_FRACTION: f64 = 0.001;
+
+/// Mirrors the binary's exit code for "no usable GPU".
+const NO_DEVICE_EXIT_CODE: i32 = 3;
+
+#[test]
+fn captured_frames_match_golden_images() {
+    let golden_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+
+    let capture_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    let _ = fs::remove_dir_all(&capture_dir);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_vulkano_triangle_tutorial"))
+        .args(["--gpu", "llvmpipe", "--capture"])
+        .arg(&capture_dir)
+        .output()
+        .unwrap();
+    if output.status.code() == Some(NO_DEVICE_EXIT_CODE) && env::var_os("CI").is_none() {
+        eprintln!("skipping golden-image test: lavapipe is not installed");
+        return;
+    }
+    assert!(
+        output.status.success(),
+        "capture failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let mut captured: Vec<_> = fs::read_dir(&capture_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    captured.sort();
+    assert!(!captured.is_empty(), "no frames were captured");
+
+    let mut failures = Vec::new();
+    for path in captured {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let reference_path = golden_dir.join(&name);
+        if update {
+            fs::create_dir_all(&golden_dir).unwrap();
+            fs::copy(&path, &reference_path).unwrap();
+            continue;
+        }
+        if !reference_path.exists() {
+            failures.push(format!(
+                "{}: no reference image, run with UPDATE_GOLDEN=1",
+                name
+            ));
+            continue;
+        }
+
+        let (size, pixels) = read_rgba(&path);
+        let (reference_size, reference) = read_rgba(&reference_path);
+        if size != reference_size {
+            failures.push(format!(
+                "{}: size {:?} differs from the reference's {:?}",
+                name, size, reference_size
+            ));
+            continue;
+        }
+
+        let mismatched = pixels
+            .chunks(4)
+            .zip(reference.chunks(4))
+            .filter(|(pixel, expected)| {
+                pixel
+                    .iter()
+                    .zip(expected.iter())
+                    .any(|(a, b)| a.abs_diff(*b) > TOLERANCE)
+            })
+            .count();
+        let fraction = mismatched as f64 / (size.0 * size.1) as f64;
+        if fraction > MAX_MISMATCHED_FRACTION {
+            failures.push(format!(
+                "{}: {:.2}% of pixels differ, see {}",
+                name,
+                fraction * 100.0,
+                path.display()
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+fn read_rgba(path: &Path) -> ((u32, u32), Vec<u8>) {
+    let decoder = png::Decoder::new(File::open(path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!(
+        (info.color_type, info.bit_depth),
+        (png::ColorType::Rgba, png::BitDepth::Eight),
+        "{} is not 8-bit RGBA",
+        path.display()
+    );
+    pixels.truncate(info.buffer_size());
+    ((info.width, info.height), pixels)
+}